@@ -0,0 +1,65 @@
+//! Example demonstrating batch filesystem operations, rolled back as a unit
+
+use gamecode_tools::{create_default_dispatcher, jsonrpc};
+use serde_json::{json, Value};
+use tokio::fs;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dispatcher = create_default_dispatcher();
+
+    let dir = std::env::temp_dir().join("gamecode_example_batch");
+    fs::create_dir_all(&dir).await?;
+
+    // Create a directory, write a file into it, then rename the file
+    println!("Running a batch...");
+    let batch_request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "batch",
+        "params": {
+            "operations": [
+                {"op": "mkdir", "path": dir.join("src")},
+                {"op": "write", "path": dir.join("src/draft.rs"), "content": "fn main() {}\n"},
+                {"op": "move", "source": dir.join("src/draft.rs"), "destination": dir.join("src/main.rs")}
+            ]
+        }
+    });
+    dispatch_and_print_result(&dispatcher, batch_request).await?;
+
+    // The move fails because its destination exists, so the write before it
+    // is undone too
+    println!("\nRunning a batch that fails...");
+    let failing_request = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "batch",
+        "params": {
+            "operations": [
+                {"op": "write", "path": dir.join("src/lib.rs"), "content": "pub fn lib() {}\n"},
+                {"op": "move", "source": dir.join("src/lib.rs"), "destination": dir.join("src/main.rs")}
+            ]
+        }
+    });
+    dispatch_and_print_result(&dispatcher, failing_request).await?;
+    println!("src/lib.rs exists after rollback: {}", dir.join("src/lib.rs").exists());
+
+    fs::remove_dir_all(&dir).await?;
+
+    Ok(())
+}
+
+/// Helper function to dispatch a request and print the result
+async fn dispatch_and_print_result(
+    dispatcher: &jsonrpc::Dispatcher,
+    request: Value,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("Request: {}", request);
+
+    let result = dispatcher.dispatch(&request.to_string()).await?;
+    let result_value: Value = serde_json::from_str(&result)?;
+
+    println!("Response: {}", result_value);
+
+    Ok(result_value)
+}
@@ -0,0 +1,64 @@
+//! Example demonstrating exact-match edits with file_edit
+
+use gamecode_tools::{create_default_dispatcher, jsonrpc};
+use serde_json::{json, Value};
+use tokio::fs;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dispatcher = create_default_dispatcher();
+
+    let dir = std::env::temp_dir().join("gamecode_example_file_edit");
+    fs::create_dir_all(&dir).await?;
+    let path = dir.join("config.toml");
+    fs::write(&path, "name = \"demo\"\nlevel = 1\ndebug = false\n").await?;
+
+    // Replace two anchors; each must occur exactly once or nothing is written
+    println!("Editing file...");
+    let edit_request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "file_edit",
+        "params": {
+            "path": path,
+            "edits": [
+                {"old_string": "level = 1", "new_string": "level = 2"},
+                {"old_string": "debug = false", "new_string": "debug = true"}
+            ]
+        }
+    });
+    dispatch_and_print_result(&dispatcher, edit_request).await?;
+    println!("Edited content:\n{}", fs::read_to_string(&path).await?);
+
+    // An anchor that no longer matches is rejected, leaving the file as it was
+    println!("\nEditing with a stale anchor...");
+    let stale_request = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "file_edit",
+        "params": {
+            "path": path,
+            "edits": [{"old_string": "level = 1", "new_string": "level = 3"}]
+        }
+    });
+    dispatch_and_print_result(&dispatcher, stale_request).await?;
+
+    fs::remove_dir_all(&dir).await?;
+
+    Ok(())
+}
+
+/// Helper function to dispatch a request and print the result
+async fn dispatch_and_print_result(
+    dispatcher: &jsonrpc::Dispatcher,
+    request: Value,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("Request: {}", request);
+
+    let result = dispatcher.dispatch(&request.to_string()).await?;
+    let result_value: Value = serde_json::from_str(&result)?;
+
+    println!("Response: {}", result_value);
+
+    Ok(result_value)
+}
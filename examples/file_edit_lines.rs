@@ -0,0 +1,81 @@
+//! Example demonstrating line-range edits with file_edit_lines
+
+use gamecode_tools::{create_default_dispatcher, jsonrpc};
+use serde_json::{json, Value};
+use tokio::fs;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dispatcher = create_default_dispatcher();
+
+    let dir = std::env::temp_dir().join("gamecode_example_file_edit_lines");
+    fs::create_dir_all(&dir).await?;
+    let path = dir.join("lines.txt");
+    fs::write(&path, "Line 1\nLine 2\nLine 3\nLine 4\nLine 5\n").await?;
+
+    // Replace lines 2-3 with a single line
+    println!("Replacing lines 2-3...");
+    let replace_request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "file_edit_lines",
+        "params": {
+            "path": path,
+            "start_line": 2,
+            "end_line": 3,
+            "operation": "replace",
+            "content": "Lines 2 and 3"
+        }
+    });
+    dispatch_and_print_result(&dispatcher, replace_request).await?;
+
+    // Insert a line before line 1
+    println!("\nInserting a header...");
+    let insert_request = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "file_edit_lines",
+        "params": {
+            "path": path,
+            "start_line": 1,
+            "operation": "insert",
+            "content": "# Header"
+        }
+    });
+    dispatch_and_print_result(&dispatcher, insert_request).await?;
+
+    // Delete the last line
+    println!("\nDeleting the last line...");
+    let delete_request = json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "file_edit_lines",
+        "params": {
+            "path": path,
+            "start_line": 5,
+            "operation": "delete"
+        }
+    });
+    dispatch_and_print_result(&dispatcher, delete_request).await?;
+
+    println!("\nEdited content:\n{}", fs::read_to_string(&path).await?);
+
+    fs::remove_dir_all(&dir).await?;
+
+    Ok(())
+}
+
+/// Helper function to dispatch a request and print the result
+async fn dispatch_and_print_result(
+    dispatcher: &jsonrpc::Dispatcher,
+    request: Value,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("Request: {}", request);
+
+    let result = dispatcher.dispatch(&request.to_string()).await?;
+    let result_value: Value = serde_json::from_str(&result)?;
+
+    println!("Response: {}", result_value);
+
+    Ok(result_value)
+}
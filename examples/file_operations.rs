@@ -1,5 +1,6 @@
 //! Example demonstrating file read and write operations
 
+use gamecode_tools::tools::{file_read, file_write};
 use gamecode_tools::{create_default_dispatcher, jsonrpc};
 use serde_json::{json, Value};
 use tokio::fs;
@@ -34,11 +35,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
     
     let text_result = dispatch_and_print_result(&dispatcher, write_text_request).await?;
-    if text_result.get("result").is_some() {
-        println!("Text file size: {} bytes\n", text_result["result"]["size"]);
-    } else {
-        println!("Failed to write text file: {}\n", text_result["error"]["message"]);
-        return Ok(());
+    match jsonrpc::extract_result::<file_write::Output>(&text_result) {
+        Ok(output) => println!("Text file size: {} bytes\n", output.size),
+        Err(err) => {
+            println!("Failed to write text file: {}\n", err);
+            return Ok(());
+        }
     }
     
     // 2. Read back the text file
@@ -55,11 +57,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
     
     let read_text_result = dispatch_and_print_result(&dispatcher, read_text_request).await?;
-    if read_text_result.get("result").is_some() {
-        println!("Text file content:\n{}\n", read_text_result["result"]["content"]);
-    } else {
-        println!("Failed to read text file: {}\n", read_text_result["error"]["message"]);
-        return Ok(());
+    match jsonrpc::extract_result::<file_read::Output>(&read_text_result) {
+        Ok(output) => println!("Text file content:\n{}\n", output.content),
+        Err(err) => {
+            println!("Failed to read text file: {}\n", err);
+            return Ok(());
+        }
     }
     
     // 3. Write a binary file
@@ -80,11 +83,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
     
     let binary_result = dispatch_and_print_result(&dispatcher, write_binary_request).await?;
-    if binary_result.get("result").is_none() {
-        println!("Failed to write binary file: {}\n", binary_result["error"]["message"]);
-        return Ok(());
-    }
-    println!("Binary file size: {} bytes\n", binary_result["result"]["size"]);
+    let binary_output = match jsonrpc::extract_result::<file_write::Output>(&binary_result) {
+        Ok(output) => output,
+        Err(err) => {
+            println!("Failed to write binary file: {}\n", err);
+            return Ok(());
+        }
+    };
+    println!("Binary file size: {} bytes\n", binary_output.size);
     
     // 4. Read back the binary file
     println!("Reading binary file...");
@@ -99,12 +105,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
     
     let read_binary_result = dispatch_and_print_result(&dispatcher, read_binary_request).await?;
-    if read_binary_result.get("result").is_none() {
-        println!("Failed to read binary file: {}\n", read_binary_result["error"]["message"]);
-        return Ok(());
-    }
+    let read_binary_output = match jsonrpc::extract_result::<file_read::Output>(&read_binary_result) {
+        Ok(output) => output,
+        Err(err) => {
+            println!("Failed to read binary file: {}\n", err);
+            return Ok(());
+        }
+    };
     
-    let read_base64 = read_binary_result["result"]["content"].as_str().unwrap();
+    let read_base64 = read_binary_output.content.as_str();
     println!("Binary file content (base64): {}", read_base64);
     
     // Decode and print the binary data
@@ -120,18 +129,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-/// Helper function to dispatch a request and print the result
+/// Helper function to dispatch a request and print the response
 async fn dispatch_and_print_result(
     dispatcher: &jsonrpc::Dispatcher,
     request: Value,
-) -> Result<Value, Box<dyn std::error::Error>> {
+) -> Result<String, Box<dyn std::error::Error>> {
     println!("Request: {}", request);
     
     let request_str = request.to_string();
-    let result = dispatcher.dispatch(&request_str).await?;
-    let result_value: Value = serde_json::from_str(&result)?;
+    let response = dispatcher.dispatch(&request_str).await?;
     
-    println!("Response: {}", result_value);
+    println!("Response: {}", response);
     
-    Ok(result_value)
+    Ok(response)
 }
\ No newline at end of file
@@ -0,0 +1,74 @@
+//! Example demonstrating fetching a large binary file in chunks with
+//! file_read_chunk
+
+use base64::{engine::general_purpose, Engine as _};
+use gamecode_tools::{create_default_dispatcher, jsonrpc};
+use serde_json::{json, Value};
+use tokio::fs;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dispatcher = create_default_dispatcher();
+
+    let dir = std::env::temp_dir().join("gamecode_example_file_read_chunk");
+    fs::create_dir_all(&dir).await?;
+    let path = dir.join("data.bin");
+    let data: Vec<u8> = (0..=255).cycle().take(2500).collect();
+    fs::write(&path, &data).await?;
+
+    // Binary files over chunk_threshold come back as a chunked handle
+    println!("Reading file...");
+    let read_request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "file_read",
+        "params": {
+            "path": path,
+            "chunk_threshold": 1024,
+            "chunk_size": 1000
+        }
+    });
+    let read_result = dispatch_and_print_result(&dispatcher, read_request).await?;
+    let chunked = &read_result["result"]["chunked"];
+    let handle = chunked["handle"].as_str().ok_or("file was not chunked")?;
+    let chunks = chunked["chunks"].as_u64().unwrap_or(0);
+
+    // Fetch the chunks one at a time
+    let mut fetched = Vec::new();
+    for index in 0..chunks {
+        println!("\nFetching chunk {}...", index);
+        let chunk_request = json!({
+            "jsonrpc": "2.0",
+            "id": 2 + index,
+            "method": "file_read_chunk",
+            "params": {
+                "handle": handle,
+                "index": index
+            }
+        });
+        let chunk_result = dispatch_and_print_result(&dispatcher, chunk_request).await?;
+        let content = chunk_result["result"]["content"].as_str().unwrap_or_default();
+        fetched.extend(general_purpose::STANDARD.decode(content)?);
+    }
+
+    println!("\nFetched {} bytes, matching the file: {}", fetched.len(), fetched == data);
+
+    fs::remove_dir_all(&dir).await?;
+
+    Ok(())
+}
+
+/// Helper function to dispatch a request and print the result
+async fn dispatch_and_print_result(
+    dispatcher: &jsonrpc::Dispatcher,
+    request: Value,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("Request: {}", request);
+
+    let result = dispatcher.dispatch(&request.to_string()).await?;
+    let result_value: Value = serde_json::from_str(&result)?;
+
+    println!("Response: {}", result_value);
+
+    Ok(result_value)
+}
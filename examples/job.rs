@@ -0,0 +1,84 @@
+//! Example demonstrating background shell jobs
+
+use gamecode_tools::{create_default_dispatcher, jsonrpc};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dispatcher = create_default_dispatcher();
+
+    // Start a command; the job id comes back right away
+    println!("Starting a job...");
+    let spawn_request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "shell_spawn",
+        "params": {"command": "echo", "args": ["hello from a job"]}
+    });
+    let spawn_result = dispatch_and_print_result(&dispatcher, spawn_request).await?;
+    let job_id = spawn_result["result"]["job_id"].as_u64().ok_or("no job id")?;
+
+    // Poll until the job has finished
+    loop {
+        println!("\nChecking job status...");
+        let status_request = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "job_status",
+            "params": {"job_id": job_id}
+        });
+        let status = dispatch_and_print_result(&dispatcher, status_request).await?;
+        if status["result"]["state"] != "running" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // Read the output from the start; later calls pass the returned offsets
+    println!("\nReading job output...");
+    let output_request = json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "job_output",
+        "params": {"job_id": job_id, "stdout_offset": 0}
+    });
+    dispatch_and_print_result(&dispatcher, output_request).await?;
+
+    // Start a long-running job and stop it
+    println!("\nStarting a long job...");
+    let long_request = json!({
+        "jsonrpc": "2.0",
+        "id": 4,
+        "method": "shell_spawn",
+        "params": {"command": "sleep", "args": ["30"]}
+    });
+    let long_result = dispatch_and_print_result(&dispatcher, long_request).await?;
+    let long_id = long_result["result"]["job_id"].as_u64().ok_or("no job id")?;
+
+    println!("\nKilling the long job...");
+    let kill_request = json!({
+        "jsonrpc": "2.0",
+        "id": 5,
+        "method": "job_kill",
+        "params": {"job_id": long_id}
+    });
+    dispatch_and_print_result(&dispatcher, kill_request).await?;
+
+    Ok(())
+}
+
+/// Helper function to dispatch a request and print the result
+async fn dispatch_and_print_result(
+    dispatcher: &jsonrpc::Dispatcher,
+    request: Value,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("Request: {}", request);
+
+    let result = dispatcher.dispatch(&request.to_string()).await?;
+    let result_value: Value = serde_json::from_str(&result)?;
+
+    println!("Response: {}", result_value);
+
+    Ok(result_value)
+}
@@ -0,0 +1,66 @@
+//! Example demonstrating inserting lines next to an anchor with text_insert
+
+use gamecode_tools::{create_default_dispatcher, jsonrpc};
+use serde_json::{json, Value};
+use tokio::fs;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dispatcher = create_default_dispatcher();
+
+    let dir = std::env::temp_dir().join("gamecode_example_text_insert");
+    fs::create_dir_all(&dir).await?;
+    let path = dir.join("lib.rs");
+    fs::write(&path, "use std::fmt;\n\npub fn run() {\n    println!(\"run\");\n}\n").await?;
+
+    // Insert after the one line matching the anchor regex
+    println!("Inserting after an anchor...");
+    let anchor_request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "text_insert",
+        "params": {
+            "path": path,
+            "anchor": "^use std::fmt;$",
+            "position": "after",
+            "content": "use std::io;"
+        }
+    });
+    dispatch_and_print_result(&dispatcher, anchor_request).await?;
+
+    // Insert before a line picked by number
+    println!("\nInserting before line 1...");
+    let line_request = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "text_insert",
+        "params": {
+            "path": path,
+            "line": 1,
+            "position": "before",
+            "content": "//! Example library"
+        }
+    });
+    dispatch_and_print_result(&dispatcher, line_request).await?;
+
+    println!("\nEdited content:\n{}", fs::read_to_string(&path).await?);
+
+    fs::remove_dir_all(&dir).await?;
+
+    Ok(())
+}
+
+/// Helper function to dispatch a request and print the result
+async fn dispatch_and_print_result(
+    dispatcher: &jsonrpc::Dispatcher,
+    request: Value,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("Request: {}", request);
+
+    let result = dispatcher.dispatch(&request.to_string()).await?;
+    let result_value: Value = serde_json::from_str(&result)?;
+
+    println!("Response: {}", result_value);
+
+    Ok(result_value)
+}
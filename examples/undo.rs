@@ -0,0 +1,78 @@
+//! Example demonstrating rolling back changes with the undo journal
+
+use gamecode_tools::tools::undo::UndoJournal;
+use gamecode_tools::{create_dispatcher_with_undo_journal, jsonrpc, transform};
+use serde_json::{json, Value};
+use tokio::fs;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The mutating tools record what they change in the journal
+    let (dispatcher, _registry) =
+        create_dispatcher_with_undo_journal(transform::standard_transformer(), UndoJournal::new());
+
+    let dir = std::env::temp_dir().join("gamecode_example_undo");
+    fs::create_dir_all(&dir).await?;
+    let path = dir.join("notes.txt");
+
+    println!("Writing file...");
+    let write_request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "file_write",
+        "params": {"path": path, "content": "first draft\n"}
+    });
+    dispatch_and_print_result(&dispatcher, write_request).await?;
+
+    println!("\nEditing file...");
+    let edit_request = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "file_edit",
+        "params": {
+            "path": path,
+            "edits": [{"old_string": "first", "new_string": "second"}]
+        }
+    });
+    dispatch_and_print_result(&dispatcher, edit_request).await?;
+
+    println!("\nListing recorded operations...");
+    let list_request = json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "undo_list",
+        "params": {}
+    });
+    dispatch_and_print_result(&dispatcher, list_request).await?;
+
+    // Roll back the edit, newest first
+    println!("\nUndoing the last operation...");
+    let apply_request = json!({
+        "jsonrpc": "2.0",
+        "id": 4,
+        "method": "undo_apply",
+        "params": {"count": 1}
+    });
+    dispatch_and_print_result(&dispatcher, apply_request).await?;
+
+    println!("\nContent after undo: {}", fs::read_to_string(&path).await?);
+
+    fs::remove_dir_all(&dir).await?;
+
+    Ok(())
+}
+
+/// Helper function to dispatch a request and print the result
+async fn dispatch_and_print_result(
+    dispatcher: &jsonrpc::Dispatcher,
+    request: Value,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("Request: {}", request);
+
+    let result = dispatcher.dispatch(&request.to_string()).await?;
+    let result_value: Value = serde_json::from_str(&result)?;
+
+    println!("Response: {}", result_value);
+
+    Ok(result_value)
+}
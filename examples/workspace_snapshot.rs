@@ -0,0 +1,84 @@
+//! Example demonstrating workspace snapshots and restoring from them
+
+use gamecode_tools::tools::workspace_snapshot::SnapshotStore;
+use gamecode_tools::{create_dispatcher_with_schema_registry, jsonrpc, transform};
+use serde_json::{json, Value};
+use tokio::fs;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join("gamecode_example_workspace_snapshot");
+    let workspace = dir.join("workspace");
+    fs::create_dir_all(&workspace).await?;
+    fs::write(workspace.join("main.rs"), "fn main() {}\n").await?;
+
+    // Add the snapshot tools, covering the workspace
+    let (mut dispatcher, mut registry) =
+        create_dispatcher_with_schema_registry(transform::standard_transformer());
+    SnapshotStore::new(dir.join("snapshots"))
+        .with_root(&workspace)
+        .register(&mut dispatcher, &mut registry);
+
+    println!("Taking a snapshot...");
+    let snapshot_request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "workspace_snapshot",
+        "params": {"label": "before refactor"}
+    });
+    dispatch_and_print_result(&dispatcher, snapshot_request).await?;
+
+    // Change a file and add another
+    fs::write(workspace.join("main.rs"), "fn main() { broken }\n").await?;
+    fs::write(workspace.join("extra.rs"), "// added\n").await?;
+
+    println!("\nListing snapshots...");
+    let list_request = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "workspace_snapshot_list",
+        "params": {}
+    });
+    dispatch_and_print_result(&dispatcher, list_request).await?;
+
+    // See what a restore of the latest snapshot would change, then restore it
+    println!("\nRestoring (dry run)...");
+    let dry_run_request = json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "workspace_restore",
+        "params": {"dry_run": true}
+    });
+    dispatch_and_print_result(&dispatcher, dry_run_request).await?;
+
+    println!("\nRestoring...");
+    let restore_request = json!({
+        "jsonrpc": "2.0",
+        "id": 4,
+        "method": "workspace_restore",
+        "params": {}
+    });
+    dispatch_and_print_result(&dispatcher, restore_request).await?;
+
+    println!("\nmain.rs: {}", fs::read_to_string(workspace.join("main.rs")).await?);
+    println!("extra.rs exists: {}", workspace.join("extra.rs").exists());
+
+    fs::remove_dir_all(&dir).await?;
+
+    Ok(())
+}
+
+/// Helper function to dispatch a request and print the result
+async fn dispatch_and_print_result(
+    dispatcher: &jsonrpc::Dispatcher,
+    request: Value,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("Request: {}", request);
+
+    let result = dispatcher.dispatch(&request.to_string()).await?;
+    let result_value: Value = serde_json::from_str(&result)?;
+
+    println!("Response: {}", result_value);
+
+    Ok(result_value)
+}
@@ -0,0 +1,293 @@
+//! Attribute macro for defining gamecode-tools tools.
+//!
+//! Use it through the `derive` feature of `gamecode-tools`, which re-exports
+//! [`macro@tool`] as `gamecode_tools::tool`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{
+    Attribute, Expr, ExprLit, FnArg, GenericArgument, Ident, ItemFn, Lit, Meta, Pat, PathArguments,
+    ReturnType, Token, Type, parse_macro_input,
+};
+
+/// Annotation hints that can be listed in the attribute
+const HINTS: [&str; 4] = ["read_only", "destructive", "idempotent", "open_world"];
+
+/// Turn an async function into a tool.
+///
+/// ```ignore
+/// use gamecode_tools::{Result, tool};
+///
+/// /// Count the words in a text
+/// #[tool(name = "word_count", read_only)]
+/// pub async fn word_count(
+///     /// Text to count the words of
+///     text: String,
+///     /// Count only words at least this long
+///     #[serde(default)]
+///     min_length: usize,
+/// ) -> Result<usize> {
+///     Ok(text.split_whitespace().filter(|w| w.len() >= min_length).count())
+/// }
+/// ```
+///
+/// Besides the function itself, this generates:
+///
+/// - `WordCountParams`, a struct with a field for each argument that derives
+///   `Deserialize` and `JsonSchema`. Doc comments and `serde` or `schemars`
+///   attributes on the arguments are moved to the fields.
+/// - `WordCount`, a unit struct implementing `Tool`.
+/// - `register_word_count(&mut Dispatcher, &mut ToolSchemaRegistry)`, which
+///   registers the tool, its schema, and lists it in `tools/list`.
+///
+/// `name` defaults to the function's name and `description` to its doc
+/// comment. Listing any of the hints `read_only`, `destructive`, `idempotent`
+/// and `open_world` sets the annotations to exactly those; without hints the
+/// tool gets the default, most cautious annotations.
+///
+/// The function must return a `Result` whose error converts into
+/// `gamecode_tools::Error`, and its future must be `Send`.
+#[proc_macro_attribute]
+pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr with Punctuated::<Meta, Token![,]>::parse_terminated);
+    let function = parse_macro_input!(item as ItemFn);
+    expand(args, function)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Settings given in the attribute
+#[derive(Default)]
+struct Settings {
+    name: Option<String>,
+    description: Option<String>,
+    hints: Option<Vec<String>>,
+}
+
+impl Settings {
+    fn parse(args: Punctuated<Meta, Token![,]>) -> syn::Result<Self> {
+        let mut settings = Settings::default();
+        for meta in args {
+            match &meta {
+                Meta::NameValue(pair) if pair.path.is_ident("name") => {
+                    settings.name = Some(string_value(&pair.value)?);
+                }
+                Meta::NameValue(pair) if pair.path.is_ident("description") => {
+                    settings.description = Some(string_value(&pair.value)?);
+                }
+                Meta::Path(path) => {
+                    let hint = path
+                        .get_ident()
+                        .map(Ident::to_string)
+                        .filter(|hint| HINTS.contains(&hint.as_str()))
+                        .ok_or_else(|| {
+                            syn::Error::new(path.span(), "unknown tool annotation hint")
+                        })?;
+                    settings.hints.get_or_insert_with(Vec::new).push(hint);
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        meta.span(),
+                        "expected `name = \"...\"`, `description = \"...\"` or an annotation hint",
+                    ));
+                }
+            }
+        }
+        Ok(settings)
+    }
+}
+
+fn string_value(expr: &Expr) -> syn::Result<String> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(value),
+            ..
+        }) => Ok(value.value()),
+        _ => Err(syn::Error::new(expr.span(), "expected a string literal")),
+    }
+}
+
+/// The text of the doc comments among `attrs`, one line each
+fn doc_comment(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(pair) => string_value(&pair.value).ok(),
+            _ => None,
+        })
+        .map(|line| line.trim().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// `T` of a `Result<T>` or `Result<T, E>` return type
+fn output_type(output: &ReturnType) -> Option<&Type> {
+    let ReturnType::Type(_, ty) = output else {
+        return None;
+    };
+    let Type::Path(path) = ty.as_ref() else {
+        return None;
+    };
+    let last = path.path.segments.last()?;
+    if last.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &last.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
+}
+
+/// `word_count` as `WordCount`
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn expand(args: Punctuated<Meta, Token![,]>, mut function: ItemFn) -> syn::Result<TokenStream2> {
+    let settings = Settings::parse(args)?;
+    let sig = &function.sig;
+
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new(
+            sig.fn_token.span(),
+            "a tool must be an async fn",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "a tool can't be generic",
+        ));
+    }
+    let output = output_type(&sig.output)
+        .cloned()
+        .ok_or_else(|| syn::Error::new(sig.output.span(), "a tool must return a `Result`"))?;
+
+    let fn_name = sig.ident.clone();
+    let name = settings.name.unwrap_or_else(|| fn_name.to_string());
+    let description = settings
+        .description
+        .unwrap_or_else(|| doc_comment(&function.attrs));
+    let vis = function.vis.clone();
+    let tool = format_ident!("{}", camel_case(&fn_name.to_string()));
+    let params = format_ident!("{}Params", tool);
+    let register = format_ident!("register_{}", fn_name);
+
+    // Each argument becomes a field, taking its docs and serde attributes along
+    let mut fields = Vec::new();
+    let mut field_names = Vec::new();
+    for input in function.sig.inputs.iter_mut() {
+        let FnArg::Typed(arg) = input else {
+            return Err(syn::Error::new(input.span(), "a tool can't take `self`"));
+        };
+        let Pat::Ident(pat) = arg.pat.as_ref() else {
+            return Err(syn::Error::new(
+                arg.pat.span(),
+                "tool arguments must be plain identifiers",
+            ));
+        };
+        let field = pat.ident.clone();
+        let ty = &arg.ty;
+        let attrs = std::mem::take(&mut arg.attrs);
+        fields.push(quote! {
+            #(#attrs)*
+            pub #field: #ty
+        });
+        field_names.push(field);
+    }
+
+    let annotations = match settings.hints {
+        None => quote! { ::gamecode_tools::tools::Annotations::default() },
+        Some(hints) => {
+            let [read_only, destructive, idempotent, open_world] =
+                HINTS.map(|hint| hints.iter().any(|h| h == hint));
+            quote! {
+                ::gamecode_tools::tools::Annotations {
+                    read_only: #read_only,
+                    destructive: #destructive,
+                    idempotent: #idempotent,
+                    open_world: #open_world,
+                }
+            }
+        }
+    };
+
+    let params_doc = format!("Parameters of the `{}` tool", name);
+    let tool_doc = format!("The `{}` tool", name);
+    let register_doc = format!(
+        "Register the `{}` tool with a dispatcher and its schema with a registry",
+        name
+    );
+
+    Ok(quote! {
+        #function
+
+        #[doc = #params_doc]
+        #[derive(Debug, ::gamecode_tools::__private::serde::Deserialize, ::gamecode_tools::__private::schemars::JsonSchema)]
+        #[serde(crate = "::gamecode_tools::__private::serde")]
+        #[schemars(crate = "::gamecode_tools::__private::schemars")]
+        #vis struct #params {
+            #(#fields,)*
+        }
+
+        #[doc = #tool_doc]
+        #[derive(Debug, Clone, Copy, Default)]
+        #vis struct #tool;
+
+        #[::gamecode_tools::__private::async_trait]
+        impl ::gamecode_tools::tools::Tool for #tool {
+            type Params = #params;
+            type Output = #output;
+
+            fn name(&self) -> &str {
+                #name
+            }
+
+            fn description(&self) -> &str {
+                #description
+            }
+
+            fn annotations(&self) -> ::gamecode_tools::tools::Annotations {
+                #annotations
+            }
+
+            async fn execute(&self, params: Self::Params) -> ::gamecode_tools::Result<Self::Output> {
+                #fn_name(#(params.#field_names),*)
+                    .await
+                    .map_err(::core::convert::Into::into)
+            }
+        }
+
+        #[doc = #register_doc]
+        #vis fn #register(
+            dispatcher: &mut ::gamecode_tools::jsonrpc::Dispatcher,
+            registry: &mut ::gamecode_tools::ToolSchemaRegistry,
+        ) {
+            registry.register_tool(&#tool);
+            if let Some(schema) = registry.get(#name) {
+                dispatcher.list_tool(schema);
+            }
+            dispatcher.register(#name, move |params: #params| async move {
+                ::gamecode_tools::tools::Tool::execute(&#tool, params).await
+            });
+        }
+    })
+}
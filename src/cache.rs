@@ -0,0 +1,366 @@
+//! Result cache for idempotent tools.
+//!
+//! Agents repeat the same reads and searches many times in a session. A
+//! dispatcher built with [`Dispatcher::with_cache`] answers a repeated call to a
+//! cacheable tool from memory, as long as the parameters are the same and none
+//! of the files named in them has a new modification time.
+//!
+//! Only the paths in the parameters are checked, so a search over a directory
+//! notices files added or removed directly in it but not changes deeper down;
+//! those show up once the entry expires. Any call to a tool that isn't
+//! cacheable, and so may change files, clears the cache.
+//!
+//! [`Dispatcher::with_cache`]: crate::jsonrpc::Dispatcher::with_cache
+
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::session::{self, Session};
+use crate::{roots, vfs};
+
+/// Tools whose results are cached by default
+const DEFAULT_TOOLS: &[&str] = &[
+    "file_read",
+    "files_read",
+    "file_find",
+    "file_grep",
+    "file_diff",
+    "directory_list",
+];
+
+/// Bounds and tools of a result cache
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+    /// How long a result stays valid
+    pub ttl: Duration,
+    /// Maximum number of cached results
+    pub max_entries: usize,
+    /// Maximum total size of the cached results, as serialized JSON
+    pub max_bytes: usize,
+    /// Tools whose results are cached; they must not change anything
+    pub tools: HashSet<String>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(60),
+            max_entries: 256,
+            max_bytes: 16 * 1024 * 1024,
+            tools: DEFAULT_TOOLS.iter().map(|tool| tool.to_string()).collect(),
+        }
+    }
+}
+
+/// A cached result
+struct Entry {
+    value: Value,
+    size: usize,
+    inserted: Instant,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<String, Entry>,
+    bytes: usize,
+    /// Incremented on every access, to find the least recently used entry
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl State {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.bytes -= entry.size;
+        }
+    }
+}
+
+/// Counters of a result cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Calls answered from the cache
+    pub hits: u64,
+    /// Cacheable calls that had to run the tool
+    pub misses: u64,
+    /// Results currently cached
+    pub entries: usize,
+    /// Total size of the cached results
+    pub bytes: usize,
+}
+
+/// Results of idempotent tool calls, bounded in age, count and size
+pub struct ResultCache {
+    config: CacheConfig,
+    state: Mutex<State>,
+}
+
+impl ResultCache {
+    /// Create an empty cache
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Bounds and tools of the cache
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    /// Whether results of a tool are cached
+    pub fn is_cacheable(&self, method: &str) -> bool {
+        self.config.tools.contains(method)
+    }
+
+    /// Drop every cached result
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.bytes = 0;
+    }
+
+    /// Current counters
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+        CacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            entries: state.entries.len(),
+            bytes: state.bytes,
+        }
+    }
+
+    /// Key of a call: the method, the parameters (already transformed and
+    /// resolved), the session's working directory and the modification times
+    /// of the paths in the parameters, with root references resolved
+    pub(crate) async fn key(method: &str, params: &Value, session: Option<&Session>) -> String {
+        let mut key = format!("{}\n{}\n", method, params);
+        if let Some(session) = session {
+            key.push_str(&session.cwd().to_string_lossy());
+        }
+
+        for path in session::param_paths(params) {
+            let path = match path.to_str().map(roots::resolve) {
+                Some(Ok(resolved)) => PathBuf::from(resolved),
+                _ => path,
+            };
+            let modified = match vfs::for_path(&path) {
+                Ok(fs) => fs.metadata(&path).await.ok().and_then(|m| m.modified),
+                Err(_) => None,
+            };
+            let stamp = modified
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_nanos().to_string())
+                .unwrap_or_else(|| "-".to_string());
+            key.push_str(&format!("\n{}@{}", path.display(), stamp));
+        }
+
+        key
+    }
+
+    /// A cached result that hasn't expired
+    pub(crate) fn get(&self, key: &str) -> Option<Value> {
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let clock = state.clock;
+
+        let fresh = match state.entries.get_mut(key) {
+            Some(entry) if entry.inserted.elapsed() < self.config.ttl => {
+                entry.last_used = clock;
+                Some(entry.value.clone())
+            }
+            Some(_) => {
+                state.remove(key);
+                None
+            }
+            None => None,
+        };
+
+        if fresh.is_some() {
+            state.hits += 1;
+        } else {
+            state.misses += 1;
+        }
+        fresh
+    }
+
+    /// Cache a result, evicting expired and then least recently used entries
+    /// to stay within bounds
+    pub(crate) fn insert(&self, key: String, value: Value) {
+        let size = key.len() + value.to_string().len();
+        if size > self.config.max_bytes || self.config.max_entries == 0 {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.remove(&key);
+
+        let ttl = self.config.ttl;
+        let expired: Vec<String> = state
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.inserted.elapsed() >= ttl)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            state.remove(&key);
+        }
+
+        while state.entries.len() >= self.config.max_entries
+            || state.bytes + size > self.config.max_bytes
+        {
+            let Some(oldest) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            state.remove(&oldest);
+        }
+
+        state.clock += 1;
+        let last_used = state.clock;
+        state.bytes += size;
+        state.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                inserted: Instant::now(),
+                last_used,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cache(max_entries: usize, ttl: Duration) -> ResultCache {
+        ResultCache::new(CacheConfig {
+            ttl,
+            max_entries,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_eviction() {
+        let cache = cache(2, Duration::from_secs(60));
+
+        cache.insert("a".to_string(), json!(1));
+        cache.insert("b".to_string(), json!(2));
+        assert_eq!(cache.get("a"), Some(json!(1)));
+
+        // "b" is the least recently used
+        cache.insert("c".to_string(), json!(3));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(json!(1)));
+        assert_eq!(cache.get("c"), Some(json!(3)));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (3, 1, 2));
+
+        cache.clear();
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(cache.stats().bytes, 0);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let cache = cache(10, Duration::ZERO);
+        cache.insert("a".to_string(), json!(1));
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_dispatcher_cache() -> crate::Result<()> {
+        let test_file = std::env::temp_dir().join(format!(
+            "cache_test_{}_{}.txt",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        std::fs::write(&test_file, "one\n")?;
+
+        let dispatcher = crate::create_default_dispatcher().with_cache(CacheConfig::default());
+        let read = json!({
+            "jsonrpc": "2.0",
+            "method": "file_read",
+            "params": {"path": test_file},
+            "id": 1,
+        })
+        .to_string();
+
+        dispatcher.dispatch(&read).await?;
+        dispatcher.dispatch(&read).await?;
+        let stats = dispatcher.cache().unwrap().stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+
+        // Writing through the dispatcher clears the cache
+        let write = json!({
+            "jsonrpc": "2.0",
+            "method": "file_write",
+            "params": {"path": test_file, "content": "two\n"},
+            "id": 2,
+        })
+        .to_string();
+        dispatcher.dispatch(&write).await?;
+        assert_eq!(dispatcher.cache().unwrap().stats().entries, 0);
+
+        let response: Value = serde_json::from_str(&dispatcher.dispatch(&read).await?)?;
+        assert_eq!(response["result"]["content"], "two\n");
+
+        std::fs::remove_file(&test_file)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dispatcher_cache_with_roots() -> crate::Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "cache_roots_test_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        std::fs::create_dir_all(&dir)?;
+        let test_file = dir.join("notes.txt");
+        std::fs::write(&test_file, "one\n")?;
+
+        let dispatcher = crate::create_default_dispatcher()
+            .with_root("work", &dir)
+            .with_cache(CacheConfig::default());
+        let read = json!({
+            "jsonrpc": "2.0",
+            "method": "file_read",
+            "params": {"path": "work:notes.txt"},
+            "id": 1,
+        })
+        .to_string();
+        dispatcher.dispatch(&read).await?;
+
+        // An edit behind the dispatcher's back changes the key of the call
+        std::fs::write(&test_file, "two\n")?;
+        std::fs::File::options()
+            .write(true)
+            .open(&test_file)?
+            .set_modified(std::time::SystemTime::now() + Duration::from_secs(10))?;
+        let response: Value = serde_json::from_str(&dispatcher.dispatch(&read).await?)?;
+        assert_eq!(response["result"]["content"], "two\n");
+        let stats = dispatcher.cache().unwrap().stats();
+        assert_eq!((stats.hits, stats.misses), (0, 2));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+//! Cancellation of running tool calls
+//!
+//! Every call dispatched runs with a [`CancellationToken`]. Cancelling it stops
+//! the call at its next await point, and the long-running tools (`file_grep`,
+//! `file_find`, `file_diff`, `shell`) also check it inside their loops and
+//! blocking sections, so a cancelled search stops walking the tree and a
+//! cancelled command is killed. The call then fails with [`Error::Cancelled`].
+//!
+//! Tools look the token up with [`current`] or [`check`] rather than taking it
+//! as a parameter, the same way they find the [`vfs`](crate::vfs) to use.
+//!
+//! A call can also have a deadline, such as the timeout of the HTTP request
+//! that carried it, after which the client has given up on it. Once the
+//! deadline passes the call's token is cancelled, so the tools stop as they
+//! would when cancelled, and the call fails with [`Error::Timeout`] instead.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use tokio::time::Instant;
+
+pub use tokio_util::sync::CancellationToken;
+
+use crate::{Error, Result};
+
+/// Name of the built-in method that cancels a running request
+pub const METHOD: &str = "cancel";
+
+/// Parameters for the cancel method
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Params {
+    /// JSONRPC id of the request to cancel
+    pub id: serde_json::Value,
+}
+
+/// Output of the cancel method
+#[derive(Debug, Serialize)]
+pub struct Output {
+    /// Whether a running request with that id was found
+    pub cancelled: bool,
+}
+
+tokio::task_local! {
+    static CURRENT: CancellationToken;
+    static DEADLINE: Instant;
+}
+
+/// Token of the call running on this task, or one that is never cancelled
+/// outside of a call
+pub fn current() -> CancellationToken {
+    CURRENT.try_with(|token| token.clone()).unwrap_or_default()
+}
+
+/// Whether the call running on this task has been cancelled
+pub fn is_cancelled() -> bool {
+    CURRENT
+        .try_with(|token| token.is_cancelled())
+        .unwrap_or(false)
+}
+
+/// Deadline of the call running on this task, if it has one
+pub fn deadline() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Whether the deadline of the call running on this task has passed
+pub fn is_expired() -> bool {
+    deadline().is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// Fail if the deadline of the call running on this task has passed or the
+/// call has been cancelled
+pub fn check() -> Result<()> {
+    if is_expired() {
+        return Err(expired());
+    }
+    if is_cancelled() {
+        return Err(Error::Cancelled);
+    }
+    Ok(())
+}
+
+fn expired() -> Error {
+    Error::Timeout("The deadline passed before the call finished".to_string())
+}
+
+/// Run a future with `token` as the current token, dropping it as soon as the
+/// token is cancelled
+pub async fn scope<T, F>(token: CancellationToken, future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let cancelled = token.clone();
+    CURRENT
+        .scope(token, async move {
+            tokio::select! {
+                biased;
+                _ = cancelled.cancelled() => Err(Error::Cancelled),
+                result = future => result,
+            }
+        })
+        .await
+}
+
+/// Run a future like [`scope`] that also fails with [`Error::Timeout`] once
+/// `deadline` passes, cancelling `token` so blocking work stops too
+pub async fn scope_until<T, F>(
+    token: CancellationToken,
+    deadline: Option<Instant>,
+    future: F,
+) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let Some(deadline) = deadline else {
+        return scope(token, future).await;
+    };
+    let expire = token.clone();
+    DEADLINE
+        .scope(deadline, async move {
+            tokio::select! {
+                biased;
+                _ = tokio::time::sleep_until(deadline) => {
+                    expire.cancel();
+                    Err(expired())
+                }
+                result = scope(token, future) => result,
+            }
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_scope() {
+        assert!(!is_cancelled());
+        assert!(check().is_ok());
+
+        let token = CancellationToken::new();
+        let result = scope(token.clone(), async {
+            assert!(check().is_ok());
+            current().cancel();
+            check()?;
+            Ok(())
+        })
+        .await;
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(token.is_cancelled());
+
+        // A future that never finishes on its own is dropped
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            canceller.cancel();
+        });
+        let result: Result<()> = scope(token, std::future::pending()).await;
+        assert!(matches!(result, Err(Error::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_scope_until() {
+        assert!(deadline().is_none());
+        assert!(!is_expired());
+
+        // A future that never finishes on its own times out
+        let token = CancellationToken::new();
+        let result: Result<()> = scope_until(
+            token.clone(),
+            Some(Instant::now() + Duration::from_millis(20)),
+            std::future::pending(),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::Timeout(_))));
+        assert!(token.is_cancelled());
+
+        // Tools checking in their loops see the deadline pass
+        let soon = Instant::now() + Duration::from_millis(50);
+        let result = scope_until(CancellationToken::new(), Some(soon), async {
+            assert_eq!(deadline(), Some(soon));
+            check()?;
+            std::thread::sleep(Duration::from_millis(60));
+            check()
+        })
+        .await;
+        assert!(matches!(result, Err(Error::Timeout(_))));
+
+        // Calls that finish in time are unaffected
+        let later = Instant::now() + Duration::from_secs(60);
+        let result = scope_until(CancellationToken::new(), Some(later), async { Ok(1) }).await;
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_request_timeout() -> Result<()> {
+        let dispatcher =
+            crate::create_default_dispatcher().with_request_timeout(Duration::from_millis(100));
+        let slow = r#"{"jsonrpc": "2.0", "method": "shell", "params": {"command": "sleep", "args": ["5"]}, "id": 1}"#;
+
+        let started = std::time::Instant::now();
+        let response: serde_json::Value = serde_json::from_str(&dispatcher.dispatch(slow).await?)?;
+        assert_eq!(response["error"]["data"]["code"], "timeout");
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // A transport's own deadline applies when it is sooner
+        let dispatcher = crate::create_default_dispatcher();
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let response = dispatcher
+            .dispatch_until(slow, None, CancellationToken::new(), deadline)
+            .await?;
+        let response: serde_json::Value = serde_json::from_str(&response)?;
+        assert_eq!(response["error"]["data"]["code"], "timeout");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cancel_request() -> Result<()> {
+        let dispatcher = crate::create_default_dispatcher();
+
+        let slow = r#"{"jsonrpc": "2.0", "method": "shell", "params": {"command": "sleep", "args": ["5"]}, "id": "slow"}"#;
+        let cancel = r#"{"jsonrpc": "2.0", "method": "cancel", "params": {"id": "slow"}, "id": 2}"#;
+
+        let (slow, cancel) = tokio::join!(dispatcher.dispatch(slow), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            dispatcher.dispatch(cancel).await
+        });
+        let slow: serde_json::Value = serde_json::from_str(&slow?)?;
+        let cancel: serde_json::Value = serde_json::from_str(&cancel?)?;
+        assert_eq!(cancel["result"]["cancelled"], true);
+        assert_eq!(slow["error"]["code"], -32800);
+        assert_eq!(slow["error"]["data"]["code"], "cancelled");
+
+        // Nothing is running under that id any more
+        let again = r#"{"jsonrpc": "2.0", "method": "cancel", "params": {"id": "slow"}, "id": 3}"#;
+        let again: serde_json::Value = serde_json::from_str(&dispatcher.dispatch(again).await?)?;
+        assert_eq!(again["result"]["cancelled"], false);
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shutdown() -> Result<()> {
+        let dispatcher = crate::create_default_dispatcher();
+
+        // A request that outlives the deadline is cancelled
+        let slow = r#"{"jsonrpc": "2.0", "method": "shell", "params": {"command": "sleep", "args": ["5"]}, "id": 2}"#;
+        let (slow, cancelled) = tokio::join!(dispatcher.dispatch(slow), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            dispatcher.shutdown(Duration::from_millis(100)).await
+        });
+        let slow: serde_json::Value = serde_json::from_str(&slow?)?;
+        assert_eq!(cancelled, 1);
+        assert_eq!(slow["error"]["data"]["code"], "cancelled");
+        assert!(dispatcher.is_shutting_down());
+
+        // New requests are refused
+        let ping = r#"{"jsonrpc": "2.0", "method": "ping", "params": {}, "id": 3}"#;
+        let refused: serde_json::Value = serde_json::from_str(&dispatcher.dispatch(ping).await?)?;
+        assert_eq!(refused["error"]["data"]["code"], "shutting_down");
+
+        Ok(())
+    }
+}
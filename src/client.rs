@@ -0,0 +1,539 @@
+//! Typed client for a remote dispatcher
+//!
+//! A [`Client`] sends JSONRPC requests through a [`Transport`] and turns the
+//! responses back into typed results, with error responses mapped to the
+//! [`Error`] kind named by their `data.code`. Besides the generic
+//! [`Client::call`], it has a method for each built-in tool taking that tool's
+//! `Params` and returning its `Output`.
+//!
+//! Two transports come with the crate: a [`Dispatcher`] answers requests in
+//! process, and [`StdioTransport`] talks newline-delimited JSON to a server
+//! started as a child process. Other transports, such as HTTP or WebSocket
+//! clients, implement [`Transport`] on top of the library of their choice:
+//!
+//! ```ignore
+//! struct HttpTransport { client: reqwest::Client, url: String }
+//!
+//! #[async_trait]
+//! impl Transport for HttpTransport {
+//!     async fn request(&self, request: String) -> Result<String> {
+//!         let response = self.client.post(&self.url).body(request).send().await
+//!             .map_err(|e| Error::Other(e.to_string()))?;
+//!         response.text().await.map_err(|e| Error::Other(e.to_string()))
+//!     }
+//! }
+//! ```
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::ffi::OsStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(not(target_family = "wasm"))]
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+#[cfg(not(target_family = "wasm"))]
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+#[cfg(not(target_family = "wasm"))]
+use tokio::sync::Mutex;
+
+use crate::cancel;
+use crate::jsonrpc::{self, Dispatcher, Request};
+#[cfg(feature = "batch")]
+use crate::tools::batch;
+#[cfg(feature = "code-outline")]
+use crate::tools::code_outline;
+#[cfg(feature = "directory-list")]
+use crate::tools::directory_list;
+#[cfg(feature = "directory-make")]
+use crate::tools::directory_make;
+#[cfg(feature = "directory-sync")]
+use crate::tools::directory_sync;
+#[cfg(feature = "file-compare")]
+use crate::tools::file_compare;
+#[cfg(feature = "file-diff")]
+use crate::tools::file_diff;
+#[cfg(feature = "file-edit")]
+use crate::tools::file_edit;
+#[cfg(feature = "file-edit-lines")]
+use crate::tools::file_edit_lines;
+#[cfg(feature = "file-find")]
+use crate::tools::file_find;
+#[cfg(feature = "file-grep")]
+use crate::tools::file_grep;
+#[cfg(feature = "file-metadata-set")]
+use crate::tools::file_metadata_set;
+#[cfg(feature = "file-move")]
+use crate::tools::file_move;
+#[cfg(feature = "file-patch")]
+use crate::tools::file_patch;
+#[cfg(feature = "file-read")]
+use crate::tools::file_read;
+#[cfg(feature = "file-read-chunk")]
+use crate::tools::file_read_chunk;
+#[cfg(feature = "file-write")]
+use crate::tools::file_write;
+#[cfg(feature = "files-read")]
+use crate::tools::files_read;
+#[cfg(feature = "fuzzy-find")]
+use crate::tools::fuzzy_find;
+#[cfg(feature = "generate-id")]
+use crate::tools::generate_id;
+#[cfg(feature = "job")]
+use crate::tools::job;
+#[cfg(feature = "network-check")]
+use crate::tools::network_check;
+#[cfg(feature = "patch-create")]
+use crate::tools::patch_create;
+#[cfg(feature = "path-info")]
+use crate::tools::path_info;
+#[cfg(feature = "recent-files")]
+use crate::tools::recent_files;
+#[cfg(feature = "shell")]
+use crate::tools::shell;
+#[cfg(feature = "text-insert")]
+use crate::tools::text_insert;
+#[cfg(feature = "undo")]
+use crate::tools::undo;
+#[cfg(feature = "workspace-snapshot")]
+use crate::tools::workspace_snapshot;
+use crate::{Error, Result};
+
+/// Carries a JSONRPC request to a dispatcher and its response back
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Send one request and wait for its response
+    async fn request(&self, request: String) -> Result<String>;
+}
+
+#[async_trait]
+impl Transport for Dispatcher {
+    async fn request(&self, request: String) -> Result<String> {
+        self.dispatch(&request).await
+    }
+}
+
+#[async_trait]
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    async fn request(&self, request: String) -> Result<String> {
+        (**self).request(request).await
+    }
+}
+
+/// Transport to a server running as a child process that reads one request
+/// per line on stdin and writes one response per line on stdout. Requests are
+/// sent one at a time; the child is killed when the transport is dropped.
+/// Not available on WASM.
+#[cfg(not(target_family = "wasm"))]
+#[derive(Debug)]
+pub struct StdioTransport {
+    _child: Child,
+    pipes: Mutex<(ChildStdin, BufReader<ChildStdout>)>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl StdioTransport {
+    /// Start `program` with `args` as the server
+    pub fn spawn<I, S>(program: impl AsRef<OsStr>, args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut command = Command::new(program);
+        command.args(args);
+        Self::from_command(command)
+    }
+
+    /// Start the server with a prepared command; its stdin and stdout are
+    /// replaced by pipes
+    pub fn from_command(mut command: Command) -> Result<Self> {
+        command
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        let mut child = command.spawn()?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| Error::Other("Server process has no stdin".to_string()))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| Error::Other("Server process has no stdout".to_string()))?;
+        Ok(Self {
+            _child: child,
+            pipes: Mutex::new((stdin, BufReader::new(stdout))),
+        })
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+#[async_trait]
+impl Transport for StdioTransport {
+    async fn request(&self, request: String) -> Result<String> {
+        let mut pipes = self.pipes.lock().await;
+        let (stdin, stdout) = &mut *pipes;
+        stdin.write_all(request.trim_end().as_bytes()).await?;
+        stdin.write_all(b"\n").await?;
+        stdin.flush().await?;
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if stdout.read_line(&mut line).await? == 0 {
+                return Err(Error::Other("Server process closed its output".to_string()));
+            }
+            if !line.trim().is_empty() {
+                return Ok(line);
+            }
+        }
+    }
+}
+
+/// Client calling the methods of a dispatcher through a transport
+#[derive(Debug)]
+pub struct Client<T> {
+    transport: T,
+    next_id: AtomicU64,
+}
+
+impl<T: Transport> Client<T> {
+    /// Create a client sending its requests through `transport`
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// The transport requests go through
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Id the next request will be sent with, so it can be cancelled from
+    /// another task
+    pub fn next_id(&self) -> u64 {
+        self.next_id.load(Ordering::Relaxed)
+    }
+
+    /// Call a method and parse its result
+    pub async fn call<P, R>(&self, method: &str, params: &P) -> Result<R>
+    where
+        P: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = Request {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+            id: id.into(),
+        };
+        let response = self
+            .transport
+            .request(serde_json::to_string(&request)?)
+            .await?;
+        jsonrpc::extract_result(&response)
+    }
+
+    /// Cancel the request sent with `id`; returns whether it was still running
+    pub async fn cancel(&self, id: u64) -> Result<bool> {
+        let output: serde_json::Value = self
+            .call(cancel::METHOD, &serde_json::json!({ "id": id }))
+            .await?;
+        Ok(output["cancelled"].as_bool().unwrap_or(false))
+    }
+
+    /// Call the `batch` tool
+    #[cfg(feature = "batch")]
+    pub async fn batch(&self, params: &batch::Params) -> Result<batch::Output> {
+        self.call("batch", params).await
+    }
+
+    /// Call the `code_outline` tool
+    #[cfg(feature = "code-outline")]
+    pub async fn code_outline(
+        &self,
+        params: &code_outline::Params,
+    ) -> Result<code_outline::Output> {
+        self.call("code_outline", params).await
+    }
+
+    /// Call the `directory_list` tool
+    #[cfg(feature = "directory-list")]
+    pub async fn directory_list(
+        &self,
+        params: &directory_list::Params,
+    ) -> Result<directory_list::Output> {
+        self.call("directory_list", params).await
+    }
+
+    /// Call the `directory_make` tool
+    #[cfg(feature = "directory-make")]
+    pub async fn directory_make(
+        &self,
+        params: &directory_make::Params,
+    ) -> Result<directory_make::Output> {
+        self.call("directory_make", params).await
+    }
+
+    /// Call the `directory_sync` tool
+    #[cfg(feature = "directory-sync")]
+    pub async fn directory_sync(
+        &self,
+        params: &directory_sync::Params,
+    ) -> Result<directory_sync::Output> {
+        self.call("directory_sync", params).await
+    }
+
+    /// Call the `file_compare` tool
+    #[cfg(feature = "file-compare")]
+    pub async fn file_compare(
+        &self,
+        params: &file_compare::Params,
+    ) -> Result<file_compare::Output> {
+        self.call("file_compare", params).await
+    }
+
+    /// Call the `file_diff` tool
+    #[cfg(feature = "file-diff")]
+    pub async fn file_diff(&self, params: &file_diff::Params) -> Result<file_diff::Output> {
+        self.call("file_diff", params).await
+    }
+
+    /// Call the `file_edit` tool
+    #[cfg(feature = "file-edit")]
+    pub async fn file_edit(&self, params: &file_edit::Params) -> Result<file_edit::Output> {
+        self.call("file_edit", params).await
+    }
+
+    /// Call the `file_edit_lines` tool
+    #[cfg(feature = "file-edit-lines")]
+    pub async fn file_edit_lines(
+        &self,
+        params: &file_edit_lines::Params,
+    ) -> Result<file_edit_lines::Output> {
+        self.call("file_edit_lines", params).await
+    }
+
+    /// Call the `file_find` tool
+    #[cfg(feature = "file-find")]
+    pub async fn file_find(&self, params: &file_find::Params) -> Result<file_find::Output> {
+        self.call("file_find", params).await
+    }
+
+    /// Call the `fuzzy_find` tool
+    #[cfg(feature = "fuzzy-find")]
+    pub async fn fuzzy_find(&self, params: &fuzzy_find::Params) -> Result<fuzzy_find::Output> {
+        self.call("fuzzy_find", params).await
+    }
+
+    /// Call the `generate_id` tool
+    #[cfg(feature = "generate-id")]
+    pub async fn generate_id(&self, params: &generate_id::Params) -> Result<generate_id::Output> {
+        self.call("generate_id", params).await
+    }
+
+    /// Call the `file_grep` tool
+    #[cfg(feature = "file-grep")]
+    pub async fn file_grep(&self, params: &file_grep::Params) -> Result<file_grep::Output> {
+        self.call("file_grep", params).await
+    }
+
+    /// Call the `file_metadata_set` tool
+    #[cfg(feature = "file-metadata-set")]
+    pub async fn file_metadata_set(
+        &self,
+        params: &file_metadata_set::Params,
+    ) -> Result<file_metadata_set::Output> {
+        self.call("file_metadata_set", params).await
+    }
+
+    /// Call the `file_move` tool
+    #[cfg(feature = "file-move")]
+    pub async fn file_move(&self, params: &file_move::Params) -> Result<file_move::Output> {
+        self.call("file_move", params).await
+    }
+
+    /// Call the `file_patch` tool
+    #[cfg(feature = "file-patch")]
+    pub async fn file_patch(&self, params: &file_patch::Params) -> Result<file_patch::Output> {
+        self.call("file_patch", params).await
+    }
+
+    /// Call the `file_read` tool
+    #[cfg(feature = "file-read")]
+    pub async fn file_read(&self, params: &file_read::Params) -> Result<file_read::Output> {
+        self.call("file_read", params).await
+    }
+
+    /// Call the `file_read_chunk` tool
+    #[cfg(feature = "file-read-chunk")]
+    pub async fn file_read_chunk(
+        &self,
+        params: &file_read_chunk::Params,
+    ) -> Result<file_read_chunk::Output> {
+        self.call("file_read_chunk", params).await
+    }
+
+    /// Call the `file_write` tool
+    #[cfg(feature = "file-write")]
+    pub async fn file_write(&self, params: &file_write::Params) -> Result<file_write::Output> {
+        self.call("file_write", params).await
+    }
+
+    /// Call the `files_read` tool
+    #[cfg(feature = "files-read")]
+    pub async fn files_read(&self, params: &files_read::Params) -> Result<files_read::Output> {
+        self.call("files_read", params).await
+    }
+
+    /// Call the `network_check` tool
+    #[cfg(feature = "network-check")]
+    pub async fn network_check(
+        &self,
+        params: &network_check::Params,
+    ) -> Result<network_check::Output> {
+        self.call("network_check", params).await
+    }
+
+    /// Call the `patch_create` tool
+    #[cfg(feature = "patch-create")]
+    pub async fn patch_create(
+        &self,
+        params: &patch_create::Params,
+    ) -> Result<patch_create::Output> {
+        self.call("patch_create", params).await
+    }
+
+    /// Call the `path_info` tool
+    #[cfg(feature = "path-info")]
+    pub async fn path_info(&self, params: &path_info::Params) -> Result<path_info::Output> {
+        self.call("path_info", params).await
+    }
+
+    /// Call the `recent_files` tool
+    #[cfg(feature = "recent-files")]
+    pub async fn recent_files(
+        &self,
+        params: &recent_files::Params,
+    ) -> Result<recent_files::Output> {
+        self.call("recent_files", params).await
+    }
+
+    /// Call the `shell` tool
+    #[cfg(feature = "shell")]
+    pub async fn shell(&self, params: &shell::Params) -> Result<shell::Output> {
+        self.call("shell", params).await
+    }
+
+    /// Call the `text_insert` tool
+    #[cfg(feature = "text-insert")]
+    pub async fn text_insert(&self, params: &text_insert::Params) -> Result<text_insert::Output> {
+        self.call("text_insert", params).await
+    }
+
+    /// Call the `shell_spawn` tool
+    #[cfg(feature = "job")]
+    pub async fn shell_spawn(&self, params: &shell::Params) -> Result<job::SpawnOutput> {
+        self.call("shell_spawn", params).await
+    }
+
+    /// Call the `job_status` tool
+    #[cfg(feature = "job")]
+    pub async fn job_status(&self, params: &job::StatusParams) -> Result<job::StatusOutput> {
+        self.call("job_status", params).await
+    }
+
+    /// Call the `job_output` tool
+    #[cfg(feature = "job")]
+    pub async fn job_output(&self, params: &job::OutputParams) -> Result<job::OutputChunk> {
+        self.call("job_output", params).await
+    }
+
+    /// Call the `job_kill` tool
+    #[cfg(feature = "job")]
+    pub async fn job_kill(&self, params: &job::KillParams) -> Result<job::KillOutput> {
+        self.call("job_kill", params).await
+    }
+
+    /// Call the `undo_list` tool
+    #[cfg(feature = "undo")]
+    pub async fn undo_list(&self, params: &undo::ListParams) -> Result<undo::ListOutput> {
+        self.call("undo_list", params).await
+    }
+
+    /// Call the `undo_apply` tool
+    #[cfg(feature = "undo")]
+    pub async fn undo_apply(&self, params: &undo::ApplyParams) -> Result<undo::ApplyOutput> {
+        self.call("undo_apply", params).await
+    }
+
+    /// Call the `workspace_snapshot` tool
+    #[cfg(feature = "workspace-snapshot")]
+    pub async fn workspace_snapshot(
+        &self,
+        params: &workspace_snapshot::SnapshotParams,
+    ) -> Result<workspace_snapshot::SnapshotOutput> {
+        self.call("workspace_snapshot", params).await
+    }
+
+    /// Call the `workspace_restore` tool
+    #[cfg(feature = "workspace-snapshot")]
+    pub async fn workspace_restore(
+        &self,
+        params: &workspace_snapshot::RestoreParams,
+    ) -> Result<workspace_snapshot::RestoreOutput> {
+        self.call("workspace_restore", params).await
+    }
+
+    /// Call the `workspace_snapshot_list` tool
+    #[cfg(feature = "workspace-snapshot")]
+    pub async fn workspace_snapshot_list(
+        &self,
+        params: &workspace_snapshot::ListParams,
+    ) -> Result<workspace_snapshot::ListOutput> {
+        self.call("workspace_snapshot_list", params).await
+    }
+}
+
+#[cfg(all(test, feature = "file-read"))]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[tokio::test]
+    async fn test_client_with_dispatcher() -> Result<()> {
+        let dispatcher = crate::create_default_dispatcher()
+            .with_filesystem(Arc::new(MemoryFs::new().with_file("/a.txt", "one\ntwo\n")));
+        let client = Client::new(dispatcher);
+
+        let params: file_read::Params = serde_json::from_value(serde_json::json!({
+            "path": "/a.txt"
+        }))?;
+        let output = client.file_read(&params).await?;
+        assert_eq!(output.content, "one\ntwo\n");
+        assert_eq!(output.size, 8);
+
+        // Error responses come back as the same kind of error
+        let params: file_read::Params = serde_json::from_value(serde_json::json!({
+            "path": "/missing.txt"
+        }))?;
+        let err = client.file_read(&params).await.unwrap_err();
+        assert_eq!(err.code(), "not_found");
+
+        let err = client
+            .call::<_, serde_json::Value>("no_such_method", &serde_json::json!({}))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Other(_)));
+
+        let pong: serde_json::Value = client
+            .call(crate::info::PING, &serde_json::json!({}))
+            .await?;
+        assert_eq!(pong["status"], "ok");
+
+        Ok(())
+    }
+}
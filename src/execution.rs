@@ -0,0 +1,150 @@
+//! Execution metadata
+//!
+//! A dispatcher built with [`Dispatcher::with_execution_metadata`] adds a `meta`
+//! object to every response to a tool call, so hosts can show what a call
+//! cost: how long it took, how many bytes it read and wrote through the
+//! [`vfs`](crate::vfs) layer, whether the result came from the cache and
+//! whether the result was cut short (any `truncated: true` in it).
+//!
+//! ```json
+//! {"jsonrpc": "2.0", "result": {...}, "id": 1,
+//!  "meta": {"duration_ms": 12, "bytes_read": 5120, "bytes_written": 0, "cached": false, "truncated": false}}
+//! ```
+//!
+//! The `shell` tool goes to the real filesystem directly, so doesn't count its
+//! bytes.
+//!
+//! [`Dispatcher::with_execution_metadata`]: crate::jsonrpc::Dispatcher::with_execution_metadata
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// What a call cost, as added to its response
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionMetadata {
+    /// Time from dispatch to result in milliseconds
+    pub duration_ms: u64,
+
+    /// Bytes read from files
+    pub bytes_read: u64,
+
+    /// Bytes written to files
+    pub bytes_written: u64,
+
+    /// Whether the result was answered from the result cache
+    pub cached: bool,
+
+    /// Whether the result says it was truncated
+    pub truncated: bool,
+}
+
+/// Counts what one call does
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    cached: AtomicBool,
+}
+
+impl Recorder {
+    /// What was recorded, for a call that took `elapsed` and gave `result`
+    pub(crate) fn metadata(&self, elapsed: Duration, result: Option<&Value>) -> ExecutionMetadata {
+        ExecutionMetadata {
+            duration_ms: elapsed.as_millis() as u64,
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            cached: self.cached.load(Ordering::Relaxed),
+            truncated: result.is_some_and(is_truncated),
+        }
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: Arc<Recorder>;
+}
+
+/// Run `future` with `recorder` counting what it does
+pub(crate) fn scope<F: Future>(recorder: Arc<Recorder>, future: F) -> impl Future<Output = F::Output> {
+    CURRENT.scope(recorder, future)
+}
+
+/// Count bytes read by the current call
+pub(crate) fn record_read(bytes: usize) {
+    let _ = CURRENT.try_with(|recorder| {
+        recorder
+            .bytes_read
+            .fetch_add(bytes as u64, Ordering::Relaxed)
+    });
+}
+
+/// Count bytes written by the current call
+pub(crate) fn record_written(bytes: usize) {
+    let _ = CURRENT.try_with(|recorder| {
+        recorder
+            .bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed)
+    });
+}
+
+/// Note that the current call was answered from the cache
+pub(crate) fn record_cached() {
+    let _ = CURRENT.try_with(|recorder| recorder.cached.store(true, Ordering::Relaxed));
+}
+
+/// Whether a result has a `truncated: true` anywhere in it
+fn is_truncated(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => map.iter().any(|(key, value)| {
+            (key == "truncated" && value == &Value::Bool(true)) || is_truncated(value)
+        }),
+        Value::Array(items) => items.iter().any(is_truncated),
+        _ => false,
+    }
+}
+
+#[cfg(all(test, feature = "file-read"))]
+mod tests {
+    use super::*;
+    use crate::cache::CacheConfig;
+    use crate::vfs::MemoryFs;
+
+    #[test]
+    fn test_is_truncated() {
+        assert!(is_truncated(&serde_json::json!({"truncated": true})));
+        assert!(is_truncated(
+            &serde_json::json!({"files": [{"truncated": false}, {"truncated": true}]})
+        ));
+        assert!(!is_truncated(
+            &serde_json::json!({"truncated": false, "content": "truncated"})
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_execution_metadata() -> crate::Result<()> {
+        let dispatcher = crate::create_default_dispatcher()
+            .with_filesystem(Arc::new(MemoryFs::new().with_file("/a.txt", "hello")))
+            .with_cache(CacheConfig::default())
+            .with_execution_metadata();
+        let read =
+            r#"{"jsonrpc": "2.0", "method": "file_read", "params": {"path": "/a.txt"}, "id": 1}"#;
+
+        let response: Value = serde_json::from_str(&dispatcher.dispatch(read).await?)?;
+        let meta: ExecutionMetadata = serde_json::from_value(response["meta"].clone())?;
+        assert_eq!(meta.bytes_read, 5);
+        assert!(!meta.cached && !meta.truncated);
+
+        let response: Value = serde_json::from_str(&dispatcher.dispatch(read).await?)?;
+        assert_eq!(response["meta"]["cached"], true);
+        assert_eq!(response["meta"]["bytes_read"], 0);
+
+        // Off by default
+        let response: Value =
+            serde_json::from_str(&crate::create_default_dispatcher().dispatch(read).await?)?;
+        assert!(response.get("meta").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+//! The `ping` and `server/info` methods, for health checks
+//!
+//! Both are built into the dispatcher. `ping` answers as long as the
+//! dispatcher is serving requests; `server/info` describes the server: crate
+//! version, available methods, enabled features, configured limits and uptime.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Name of the health check method
+pub const PING: &str = "ping";
+
+/// Name of the server description method
+pub const INFO: &str = "server/info";
+
+/// Output of the ping method
+#[derive(Debug, Serialize)]
+pub struct Pong {
+    /// Always "ok"
+    pub status: &'static str,
+
+    /// Seconds since the dispatcher was created
+    pub uptime_secs: u64,
+}
+
+/// Output of the server/info method
+#[derive(Debug, Serialize)]
+pub struct ServerInfo {
+    /// Crate name
+    pub name: &'static str,
+
+    /// Crate version
+    pub version: &'static str,
+
+    /// Seconds since the dispatcher was created
+    pub uptime_secs: u64,
+
+    /// Methods that can be called, sorted
+    pub tools: Vec<String>,
+
+    /// Cargo features the crate was built with
+    pub features: Vec<&'static str>,
+
+    /// Configured limits
+    pub limits: Limits,
+
+    /// Requests being dispatched right now
+    pub in_flight: usize,
+
+    /// Named roots that `name:path` references resolve against, by name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub roots: BTreeMap<String, String>,
+}
+
+/// Limits that apply to calls
+#[derive(Debug, Serialize)]
+pub struct Limits {
+    /// Maximum number of steps in a pipeline
+    pub pipeline_max_steps: usize,
+
+    /// Bounds of the result cache, if enabled
+    pub cache: Option<CacheLimits>,
+
+    /// Bounds of the filesystem work of tools
+    pub filesystem: FilesystemLimits,
+}
+
+/// Bounds of the filesystem work of tools
+#[derive(Debug, Serialize)]
+pub struct FilesystemLimits {
+    /// Maximum number of files open at once
+    pub max_open_files: usize,
+
+    /// Maximum number of entries one directory walk visits, if bounded
+    pub max_walk_entries: Option<usize>,
+
+    /// Maximum time one directory walk takes in milliseconds, if bounded
+    pub max_walk_duration_ms: Option<u64>,
+}
+
+/// Bounds of the result cache
+#[derive(Debug, Serialize)]
+pub struct CacheLimits {
+    /// How long a result stays valid, in seconds
+    pub ttl_secs: u64,
+
+    /// Maximum number of cached results
+    pub max_entries: usize,
+
+    /// Maximum total size of the cached results in bytes
+    pub max_bytes: usize,
+}
+
+impl Pong {
+    pub(crate) fn new(uptime: Duration) -> Self {
+        Self {
+            status: "ok",
+            uptime_secs: uptime.as_secs(),
+        }
+    }
+}
+
+/// Cargo features enabled in this build
+pub(crate) fn features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "image-resize") {
+        features.push("image-resize");
+    }
+    if cfg!(feature = "object-store") {
+        features.push("object-store");
+    }
+    if cfg!(feature = "telemetry") {
+        features.push("telemetry");
+    }
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::Result;
+    use crate::cache::CacheConfig;
+
+    #[tokio::test]
+    async fn test_ping_and_info() -> Result<()> {
+        let dispatcher = crate::create_default_dispatcher().with_cache(CacheConfig::default());
+
+        let ping = r#"{"jsonrpc": "2.0", "method": "ping", "id": 1, "params": null}"#;
+        let response: Value = serde_json::from_str(&dispatcher.dispatch(ping).await?)?;
+        assert_eq!(response["result"]["status"], "ok");
+
+        let info = r#"{"jsonrpc": "2.0", "method": "server/info", "id": 2, "params": {}}"#;
+        let response: Value = serde_json::from_str(&dispatcher.dispatch(info).await?)?;
+        let result = &response["result"];
+        assert_eq!(result["name"], "gamecode-tools");
+        assert_eq!(result["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(result["limits"]["cache"]["max_entries"], 256);
+        assert_eq!(result["in_flight"], 1);
+
+        let tools: Vec<&str> = result["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(Value::as_str)
+            .collect();
+        for tool in [
+            "file_read",
+            "shell",
+            "pipeline",
+            "cancel",
+            "ping",
+            "server/info",
+        ] {
+            assert!(tools.contains(&tool), "{} missing from {:?}", tool, tools);
+        }
+
+        Ok(())
+    }
+}
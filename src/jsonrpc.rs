@@ -1,12 +1,35 @@
 //! JSONRPC protocol structures and handling.
 
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, hash_map::Entry};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWrite;
+use tokio::sync::Notify;
+use tokio::time;
 
 use crate::Error;
+use crate::cache::{CacheConfig, ResultCache};
+use crate::cancel::{self, CancellationToken};
+use crate::execution::{self, ExecutionMetadata};
+use crate::info;
+use crate::limits::{self, FsLimits, Limiter};
+use crate::logging::{self, RequestLogger};
+use crate::paths;
+use crate::pipeline;
+use crate::quota::{self, QuotaConfig, Quotas};
 use crate::Result;
+use crate::roots::{self, Roots};
+use crate::schema::{self, ToolSchema, ToolSchemaRegistry};
+use crate::session::Session;
+use crate::shaping::TokenBudget;
+use crate::telemetry;
 use crate::transform::{FormatTransformer, deserialize, serialize};
+use crate::trash;
+use crate::vfs::{self, Filesystem};
+use crate::warnings;
 
 /// JSONRPC request structure
 #[derive(Debug, Deserialize, Serialize)]
@@ -43,6 +66,12 @@ pub struct SuccessResponse<T> {
     pub result: T,
     /// Request ID (same as in the request)
     pub id: serde_json::Value,
+    /// Correlation id of the call in the server logs (optional extension)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// What the call cost (optional extension)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ExecutionMetadata>,
 }
 
 /// JSONRPC error response structure
@@ -54,6 +83,12 @@ pub struct ErrorResponse {
     pub error: RpcError,
     /// Request ID (same as in the request)
     pub id: serde_json::Value,
+    /// Correlation id of the call in the server logs (optional extension)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// What the call cost (optional extension)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ExecutionMetadata>,
 }
 
 /// JSONRPC error object
@@ -68,6 +103,35 @@ pub struct RpcError {
     pub data: Option<serde_json::Value>,
 }
 
+impl RpcError {
+    /// The [`Error`] named by `data.code`, carrying the message
+    pub fn into_error(self) -> Error {
+        let code = self
+            .data
+            .as_ref()
+            .and_then(|data| data.get("code"))
+            .and_then(|code| code.as_str());
+        let message = self.message;
+        match code {
+            Some("not_found") => Error::NotFound(message),
+            Some("already_exists") => Error::AlreadyExists(message),
+            Some("permission_denied") => Error::PermissionDenied(message),
+            Some("outside_sandbox") => Error::OutsideSandbox(message),
+            Some("too_large") => Error::TooLarge(message),
+            Some("timeout") => Error::Timeout(message),
+            Some("conflict") => Error::Conflict(message),
+            Some("cancelled") => Error::Cancelled,
+            Some("shutting_down") => Error::ShuttingDown,
+            Some("quota_exceeded") => Error::QuotaExceeded(message),
+            Some("invalid_param") => Error::InvalidParam(message),
+            Some("json") => Error::Json(serde::de::Error::custom(message)),
+            Some("io") => Error::Io(std::io::Error::other(message)),
+            _ if self.code == -32602 => Error::InvalidParam(message),
+            _ => Error::Other(message),
+        }
+    }
+}
+
 /// JSONRPC response (either success or error)
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
@@ -78,22 +142,103 @@ pub enum Response<T> {
     Error(ErrorResponse),
 }
 
+impl<T> Response<T> {
+    /// Attach the correlation id of the call
+    pub fn with_correlation_id(mut self, correlation_id: String) -> Self {
+        match &mut self {
+            Response::Success(resp) => resp.correlation_id = Some(correlation_id),
+            Response::Error(resp) => resp.correlation_id = Some(correlation_id),
+        }
+        self
+    }
+
+    /// Attach what the call cost
+    pub fn with_metadata(mut self, meta: ExecutionMetadata) -> Self {
+        match &mut self {
+            Response::Success(resp) => resp.meta = Some(meta),
+            Response::Error(resp) => resp.meta = Some(meta),
+        }
+        self
+    }
+
+    /// The result, or the error the server reported as an [`Error`]
+    pub fn into_result(self) -> Result<T> {
+        match self {
+            Response::Success(resp) => Ok(resp.result),
+            Response::Error(resp) => Err(resp.error.into_error()),
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Response<T> {
+    /// Parse a response, checking that it is a JSONRPC 2.0 response with
+    /// either a `result` or an `error`
+    pub fn parse(response: &str) -> Result<Self> {
+        let mut value: serde_json::Value = serde_json::from_str(response)?;
+        if value.get("jsonrpc").and_then(|v| v.as_str()) != Some("2.0") {
+            return Err(Error::InvalidParam(
+                "Not a JSONRPC 2.0 response".to_string(),
+            ));
+        }
+        // Check for an error first: a missing `result` would parse as `null`
+        // for result types that accept it
+        if value.get("error").is_some() {
+            return Ok(Response::Error(serde_json::from_value(value)?));
+        }
+        let Some(result) = value.get_mut("result").map(serde_json::Value::take) else {
+            return Err(Error::InvalidParam(
+                "Response has neither a result nor an error".to_string(),
+            ));
+        };
+        Ok(Response::Success(SuccessResponse {
+            jsonrpc: "2.0".to_string(),
+            result: serde_json::from_value(result)?,
+            id: value["id"].take(),
+            correlation_id: value
+                .get("correlation_id")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            meta: value
+                .get_mut("meta")
+                .map(serde_json::Value::take)
+                .and_then(|meta| serde_json::from_value(meta).ok()),
+        }))
+    }
+}
+
+/// Parse a response and return its result, or the error the server reported
+pub fn extract_result<T: DeserializeOwned>(response: &str) -> Result<T> {
+    Response::<T>::parse(response)?.into_result()
+}
+
 /// Create a JSONRPC success response
 pub fn success<T>(result: T, id: serde_json::Value) -> Response<T> {
     Response::Success(SuccessResponse {
         jsonrpc: "2.0".to_string(),
         result,
         id,
+        correlation_id: None,
+        meta: None,
     })
 }
 
-/// Create a JSONRPC error response
+/// Create a JSONRPC error response. Besides the numeric code, `data.code`
+/// holds the stable [`Error::code`] of the error.
 pub fn error<T>(error: Error, id: serde_json::Value) -> Response<T> {
     let (code, message) = match &error {
         Error::Io(err) => (-32000, format!("I/O error: {}", err)),
         Error::Json(err) => (-32700, format!("Parse error: {}", err)),
         Error::InvalidParam(msg) => (-32602, format!("Invalid params: {}", msg)),
         Error::PermissionDenied(msg) => (-32001, format!("Permission denied: {}", msg)),
+        Error::NotFound(msg) => (-32004, msg.clone()),
+        Error::AlreadyExists(msg) => (-32005, msg.clone()),
+        Error::TooLarge(msg) => (-32006, msg.clone()),
+        Error::OutsideSandbox(msg) => (-32001, format!("Permission denied: {}", msg)),
+        Error::Timeout(msg) => (-32000, msg.clone()),
+        Error::Conflict(msg) => (-32002, format!("Conflict: {}", msg)),
+        Error::Cancelled => (-32800, "Request cancelled".to_string()),
+        Error::ShuttingDown => (-32000, "Server is shutting down".to_string()),
+        Error::QuotaExceeded(msg) => (-32003, format!("Quota exceeded: {}", msg)),
         Error::Other(msg) => (-32603, msg.clone()),
     };
 
@@ -102,9 +247,11 @@ pub fn error<T>(error: Error, id: serde_json::Value) -> Response<T> {
         error: RpcError {
             code,
             message,
-            data: None,
+            data: Some(serde_json::json!({ "code": error.code() })),
         },
         id,
+        correlation_id: None,
+        meta: None,
     })
 }
 
@@ -118,6 +265,8 @@ pub fn method_not_found<T>(id: serde_json::Value) -> Response<T> {
             data: None,
         },
         id,
+        correlation_id: None,
+        meta: None,
     })
 }
 
@@ -131,25 +280,99 @@ pub fn invalid_request<T>(message: &str, id: serde_json::Value) -> Response<T> {
             data: None,
         },
         id,
+        correlation_id: None,
+        meta: None,
     })
 }
 
-/// Tool handler function signature
+/// Tool handler function signature, taking the raw parameters and the
+/// session (if any) whose working directory relative paths are resolved against
 pub type HandlerFn = Box<
     dyn Fn(
             serde_json::Value,
+            Option<Arc<Session>>,
         )
             -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<serde_json::Value>> + Send>>
         + Send
         + Sync,
 >;
 
+/// How long cancelled requests get to stop during a shutdown
+const CANCEL_GRACE: Duration = Duration::from_secs(5);
+
+/// Methods the dispatcher provides itself
+const BUILTIN_METHODS: &[&str] = &[pipeline::METHOD, cancel::METHOD, info::PING, info::INFO];
+
 /// JSONRPC request dispatcher
 pub struct Dispatcher {
     /// Method handlers
     handlers: HashMap<String, HandlerFn>,
     /// Format transformer
     transformer: Arc<FormatTransformer>,
+    /// Filesystem the tools run against, the real one if not set
+    filesystem: Option<Arc<dyn Filesystem>>,
+    /// Directory `use_trash` moves deleted paths to, if set
+    trash_dir: Option<PathBuf>,
+    /// Named roots that `name:path` references resolve against
+    roots: Arc<Roots>,
+    /// Cache of idempotent tool results, if enabled
+    cache: Option<ResultCache>,
+    /// Usage of each session against its quotas, if enabled
+    quotas: Option<Quotas>,
+    /// MCP definitions of the tools for `tools/list`, if provided
+    tool_list: Option<Vec<serde_json::Value>>,
+    /// Schemas of the tools registered with `register_with_schema`
+    custom_tools: Vec<ToolSchema>,
+    /// Tokens of the requests being dispatched, by session and JSONRPC id (or
+    /// correlation id for notifications)
+    in_flight: Mutex<HashMap<RequestKey, CancellationToken>>,
+    /// Notified when the last request in flight finishes
+    drained: Notify,
+    /// Set once a shutdown has started
+    closing: AtomicBool,
+    /// Run at the end of a shutdown
+    shutdown_hooks: Vec<Box<dyn Fn() + Send + Sync>>,
+    /// Whether responses carry the correlation id of their call
+    correlation_ids: bool,
+    /// Whether responses are indented for reading
+    pretty: bool,
+    /// Whether responses end with a newline
+    trailing_newline: bool,
+    /// Whether responses to calls carry their execution metadata
+    execution_metadata: bool,
+    /// Logs requests and responses, if set
+    request_logger: Option<RequestLogger>,
+    /// Budget tool results are trimmed to, if set
+    token_budget: Option<TokenBudget>,
+    /// Time each call gets to finish, if limited
+    request_timeout: Option<Duration>,
+    /// Parameters used for those a call leaves out, by tool
+    tool_defaults: HashMap<String, serde_json::Map<String, serde_json::Value>>,
+    /// Limits on the filesystem work of tools
+    fs_limits: Arc<Limiter>,
+    /// When the dispatcher was created
+    started: std::time::Instant,
+}
+
+/// A request in flight: the session it was sent in, if any, and its id.
+/// Sessions choose their ids independently, so the same id can be in flight
+/// in several of them.
+type RequestKey = (Option<String>, String);
+
+/// Forgets a request's token once it has been dispatched
+struct InFlight<'a> {
+    dispatcher: &'a Dispatcher,
+    key: RequestKey,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let mut requests = self.dispatcher.in_flight.lock().unwrap();
+        requests.remove(&self.key);
+        if requests.is_empty() {
+            self.dispatcher.drained.notify_waiters();
+        }
+    }
 }
 
 impl Default for Dispatcher {
@@ -169,7 +392,195 @@ impl Dispatcher {
         Self {
             handlers: HashMap::new(),
             transformer,
+            filesystem: None,
+            trash_dir: None,
+            roots: Arc::new(Roots::new()),
+            cache: None,
+            quotas: None,
+            tool_list: None,
+            custom_tools: Vec::new(),
+            in_flight: Mutex::new(HashMap::new()),
+            drained: Notify::new(),
+            closing: AtomicBool::new(false),
+            shutdown_hooks: Vec::new(),
+            correlation_ids: false,
+            pretty: false,
+            trailing_newline: false,
+            execution_metadata: false,
+            request_logger: None,
+            token_budget: None,
+            request_timeout: None,
+            tool_defaults: HashMap::new(),
+            fs_limits: Arc::new(Limiter::new(FsLimits::default())),
+            started: std::time::Instant::now(),
+        }
+    }
+
+    /// Run the tools against the given filesystem instead of the real one
+    pub fn with_filesystem(mut self, filesystem: Arc<dyn Filesystem>) -> Self {
+        self.filesystem = Some(filesystem);
+        self
+    }
+
+    /// Move what tools delete with `use_trash` to `directory`, instead of the
+    /// system trash or `.trash` in the working directory
+    pub fn with_trash_dir(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.trash_dir = Some(directory.into());
+        self
+    }
+
+    /// Name `path` as a root, so tools accept `name:relative/path` references
+    /// to files below it
+    pub fn with_root(mut self, name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Arc::make_mut(&mut self.roots).insert(name.into(), path.into());
+        self
+    }
+
+    /// Cache the results of idempotent tools
+    pub fn with_cache(mut self, config: CacheConfig) -> Self {
+        self.cache = Some(ResultCache::new(config));
+        self
+    }
+
+    /// Limit what each session may write and run, and provide the
+    /// `quota/status` method
+    pub fn with_quotas(mut self, config: QuotaConfig) -> Self {
+        self.quotas = Some(Quotas::new(config));
+        self
+    }
+
+    /// Answer the `tools/list` method with the tools in `registry`, in MCP
+    /// format with their descriptions and annotations
+    pub fn with_tool_list(mut self, registry: &ToolSchemaRegistry) -> Self {
+        self.tool_list = Some(registry.to_mcp_tools());
+        for tool in std::mem::take(&mut self.custom_tools) {
+            self.list_tool(&tool);
+            self.custom_tools.push(tool);
         }
+        self
+    }
+
+    /// Add a tool to the `tools/list` answer, if the dispatcher has one
+    pub fn list_tool(&mut self, tool: &ToolSchema) {
+        if let Some(tools) = &mut self.tool_list {
+            tools.retain(|listed| listed["name"] != tool.name.as_str());
+            tools.push(schema::to_mcp_tool(tool));
+            tools.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+        }
+    }
+
+    /// Add the correlation id of each call to its response as `correlation_id`,
+    /// to match responses with the server logs
+    pub fn with_correlation_ids(mut self) -> Self {
+        self.correlation_ids = true;
+        self
+    }
+
+    /// Indent responses for reading instead of writing them on one line, for
+    /// debugging
+    pub fn with_pretty_output(mut self) -> Self {
+        self.pretty = true;
+        self
+    }
+
+    /// End every response with a newline, for line-delimited transports
+    pub fn with_trailing_newline(mut self) -> Self {
+        self.trailing_newline = true;
+        self
+    }
+
+    /// Add what each call cost to its response as `meta`: duration, bytes
+    /// read and written, and whether the result was cached or truncated
+    pub fn with_execution_metadata(mut self) -> Self {
+        self.execution_metadata = true;
+        self
+    }
+
+    /// Log each request and response at debug level under the
+    /// `gamecode_tools::traffic` target, redacted by `logger`
+    pub fn with_request_logging(mut self, logger: RequestLogger) -> Self {
+        self.request_logger = Some(logger);
+        self
+    }
+
+    /// Trim tool results that are over `budget`, dropping their least
+    /// important parts and noting what was dropped under `shaped`
+    pub fn with_token_budget(mut self, budget: TokenBudget) -> Self {
+        self.token_budget = Some(budget);
+        self
+    }
+
+    /// Fail calls that run longer than `timeout` with a `timeout` error,
+    /// stopping their work. Transports with a deadline of their own pass it
+    /// to [`dispatch_until`](Self::dispatch_until); the sooner one applies.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the open files and directory walks of tools to `limits` instead
+    /// of [`FsLimits::default`]
+    pub fn with_fs_limits(mut self, limits: FsLimits) -> Self {
+        self.fs_limits = Arc::new(Limiter::new(limits));
+        self
+    }
+
+    /// Run `hook` at the end of [`shutdown`](Self::shutdown), for example to
+    /// stop background work the tools started
+    pub fn on_shutdown(&mut self, hook: impl Fn() + Send + Sync + 'static) {
+        self.shutdown_hooks.push(Box::new(hook));
+    }
+
+    /// Shut down gracefully: stop taking requests, wait up to `deadline` for
+    /// the running ones to finish, cancel those still running (killing their
+    /// shell commands) and run the shutdown hooks. Returns the number of
+    /// requests that had to be cancelled.
+    pub async fn shutdown(&self, deadline: Duration) -> usize {
+        self.closing.store(true, Ordering::SeqCst);
+
+        let cancelled = if time::timeout(deadline, self.drain()).await.is_ok() {
+            0
+        } else {
+            let tokens: Vec<CancellationToken> =
+                self.in_flight.lock().unwrap().values().cloned().collect();
+            for token in &tokens {
+                token.cancel();
+            }
+            // Cancelled requests stop at their next await point
+            let _ = time::timeout(CANCEL_GRACE, self.drain()).await;
+            tokens.len()
+        };
+
+        for hook in &self.shutdown_hooks {
+            hook();
+        }
+        cancelled
+    }
+
+    /// Whether a shutdown has started
+    pub fn is_shutting_down(&self) -> bool {
+        self.closing.load(Ordering::SeqCst)
+    }
+
+    /// Wait until no request is in flight
+    async fn drain(&self) {
+        loop {
+            let drained = self.drained.notified();
+            if self.in_flight.lock().unwrap().is_empty() {
+                return;
+            }
+            drained.await;
+        }
+    }
+
+    /// The result cache, if enabled
+    pub fn cache(&self) -> Option<&ResultCache> {
+        self.cache.as_ref()
+    }
+
+    /// The quota meters, if enabled
+    pub fn quotas(&self) -> Option<&Quotas> {
+        self.quotas.as_ref()
     }
 
     /// Get the current transformer
@@ -188,13 +599,18 @@ impl Dispatcher {
         let method_name = method.to_string();
         let transformer = self.transformer.clone();
 
-        let handler_fn: HandlerFn = Box::new(move |params: serde_json::Value| {
+        let handler_fn: HandlerFn = Box::new(move |params: serde_json::Value, session| {
             let handler_clone = handler.clone();
             let transformer_clone = transformer.clone();
 
             Box::pin(async move {
                 // Transform parameters using the transformer
-                let transformed_params = transformer_clone.transform_params(params)?;
+                let mut transformed_params = transformer_clone.transform_params(params)?;
+
+                // Resolve relative paths against the session's working directory
+                if let Some(session) = &session {
+                    session.resolve_params(&mut transformed_params);
+                }
 
                 // Deserialize to the specific parameter type
                 let typed_params: P = deserialize(transformed_params)?;
@@ -213,33 +629,1028 @@ impl Dispatcher {
         self.handlers.insert(method_name, handler_fn);
     }
 
+    /// Register a tool whose parameters have no Rust type to derive a schema
+    /// from, such as one loaded from a plugin. The handler gets the parameters
+    /// as JSON, and `schema` (a JSON schema of type `object`) is what
+    /// `tools/list` shows for it, now or once a tool list is set.
+    pub fn register_with_schema<F, Fut>(
+        &mut self,
+        name: &str,
+        description: &str,
+        schema: serde_json::Value,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + Clone + 'static,
+        Fut: std::future::Future<Output = Result<serde_json::Value>> + Send + 'static,
+    {
+        let Some(object) = schema.as_object() else {
+            return Err(Error::InvalidParam(format!(
+                "Schema of {} is not a JSON object",
+                name
+            )));
+        };
+        if object.get("type").is_some_and(|t| t != "object") {
+            return Err(Error::InvalidParam(format!(
+                "Schema of {} must have type \"object\"",
+                name
+            )));
+        }
+
+        self.register(name, handler);
+        let tool = ToolSchema {
+            name: name.to_string(),
+            description: description.to_string(),
+            parameters_schema: schema,
+            annotations: Default::default(),
+            result_schema: None,
+        };
+        self.list_tool(&tool);
+        self.custom_tools.retain(|listed| listed.name != tool.name);
+        self.custom_tools.push(tool);
+        Ok(())
+    }
+
+    /// Use `defaults` (a JSON object) for the parameters that calls of `tool`
+    /// leave out, such as a site-wide `exclude` for `file_grep` or a longer
+    /// `timeout_ms` for `shell`. Parameters a call gives, even as `null`, win.
+    /// Setting defaults for a tool again replaces them.
+    pub fn set_tool_defaults(&mut self, tool: &str, defaults: serde_json::Value) -> Result<()> {
+        if !self.has_method(tool) {
+            return Err(Error::InvalidParam(format!("Unknown tool: {}", tool)));
+        }
+        let serde_json::Value::Object(defaults) = defaults else {
+            return Err(Error::InvalidParam(format!(
+                "Defaults of {} are not a JSON object",
+                tool
+            )));
+        };
+        self.tool_defaults.insert(tool.to_string(), defaults);
+        Ok(())
+    }
+
+    /// `params` with the defaults of `method` added for what they leave out
+    fn with_defaults(&self, method: &str, params: serde_json::Value) -> serde_json::Value {
+        let Some(defaults) = self.tool_defaults.get(method) else {
+            return params;
+        };
+        match params {
+            serde_json::Value::Null => serde_json::Value::Object(defaults.clone()),
+            serde_json::Value::Object(mut params) => {
+                for (name, value) in defaults {
+                    params.entry(name).or_insert_with(|| value.clone());
+                }
+                serde_json::Value::Object(params)
+            }
+            params => params,
+        }
+    }
+
+    /// Whether a method has a handler
+    pub fn has_method(&self, method: &str) -> bool {
+        self.handlers.contains_key(method)
+    }
+
     /// Dispatch a JSONRPC request
     pub async fn dispatch(&self, request_str: &str) -> Result<String> {
-        let raw_request: RawRequest = serde_json::from_str(request_str)?;
+        self.dispatch_cancellable(request_str, None, CancellationToken::new())
+            .await
+    }
 
-        let response = if raw_request.jsonrpc != "2.0" {
-            let resp =
-                invalid_request::<serde_json::Value>("Invalid JSONRPC version", raw_request.id);
-            serde_json::to_string(&resp)?
-        } else {
-            match self.handlers.get(&raw_request.method) {
-                Some(handler) => match handler(raw_request.params.clone()).await {
-                    Ok(result) => {
-                        let resp = success(result, raw_request.id);
-                        serde_json::to_string(&resp)?
-                    }
-                    Err(e) => {
-                        let resp = error::<serde_json::Value>(e, raw_request.id);
-                        serde_json::to_string(&resp)?
-                    }
+    /// Dispatch a JSONRPC request and write the response to `writer`,
+    /// serialized straight into it rather than through a `String`, so a large
+    /// result is never held twice. The writer is not flushed.
+    pub async fn dispatch_to_writer<W>(&self, request_str: &str, writer: &mut W) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let response = self
+            .respond(request_str, None, CancellationToken::new(), None)
+            .await;
+
+        // WASM has no thread to serialize on
+        #[cfg(target_family = "wasm")]
+        tokio::io::AsyncWriteExt::write_all(writer, &self.encode(&response)?).await?;
+
+        // The serializer only writes synchronously, so it runs on a blocking
+        // thread and feeds the writer through a pipe
+        #[cfg(not(target_family = "wasm"))]
+        {
+            let (mut reader, pipe) = tokio::io::duplex(64 * 1024);
+            let (pretty, trailing_newline) = (self.pretty, self.trailing_newline);
+            let serializer = tokio::task::spawn_blocking(move || {
+                let mut out = std::io::BufWriter::new(tokio_util::io::SyncIoBridge::new(pipe));
+                write_response(&mut out, &response, pretty, trailing_newline)?;
+                std::io::Write::flush(&mut out)?;
+                Ok::<_, Error>(())
+            });
+            let copied = tokio::io::copy(&mut reader, writer).await;
+            // Ending the pipe stops a serializer the writer failed on
+            drop(reader);
+            let serialized = serializer.await.map_err(|e| Error::Other(e.to_string()))?;
+            copied?;
+            serialized?;
+        }
+        Ok(())
+    }
+
+    /// Dispatch a JSONRPC request on behalf of a session. Relative paths are
+    /// resolved against the session's working directory, and the `session_cd`
+    /// and `session_info` methods are available.
+    pub async fn dispatch_in_session(
+        &self,
+        request_str: &str,
+        session: Arc<Session>,
+    ) -> Result<String> {
+        self.dispatch_cancellable(request_str, Some(session), CancellationToken::new())
+            .await
+    }
+
+    /// Run a session method, with the parameters and result transformed
+    /// like those of the tools
+    fn handle_session_method(
+        &self,
+        session: &Session,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Option<Result<serde_json::Value>> {
+        let params = match self.transformer.transform_params(params) {
+            Ok(params) => params,
+            Err(e) => return Some(Err(e)),
+        };
+        session
+            .handle(method, params)
+            .map(|result| result.and_then(|value| self.transformer.transform_result(value)))
+    }
+
+    /// Run a tool handler, answering from the cache when possible
+    async fn call_handler(
+        &self,
+        handler: &HandlerFn,
+        method: &str,
+        params: serde_json::Value,
+        session: Option<Arc<Session>>,
+    ) -> Result<serde_json::Value> {
+        // The result carries the warnings noted on the way, cached or not
+        let handler = |params: serde_json::Value, session: Option<Arc<Session>>| async move {
+            let (result, noted) = warnings::capture(handler(params, session)).await;
+            result.map(|result| warnings::attach(result, noted))
+        };
+
+        let params = self.with_defaults(method, params);
+        let Some(cache) = &self.cache else {
+            return handler(params, session).await;
+        };
+
+        if !cache.is_cacheable(method) {
+            // The tool may have changed files that cached results depend on
+            let result = handler(params, session).await;
+            cache.clear();
+            return result;
+        }
+
+        // Key on the parameters as the tool sees them; bad ones aren't cached
+        let Ok(mut resolved) = self.transformer.transform_params(params.clone()) else {
+            return handler(params, session).await;
+        };
+        if let Some(session) = &session {
+            session.resolve_params(&mut resolved);
+        }
+        let key = ResultCache::key(method, &resolved, session.as_deref()).await;
+
+        if let Some(result) = cache.get(&key) {
+            execution::record_cached();
+            return Ok(result);
+        }
+        let result = handler(params, session).await?;
+        cache.insert(key, result.clone());
+        Ok(result)
+    }
+
+    /// Whether a method is registered or built in
+    fn handles(&self, method: &str) -> bool {
+        self.has_method(method)
+            || BUILTIN_METHODS.contains(&method)
+            || (method == quota::METHOD && self.quotas.is_some())
+            || (method == schema::LIST_METHOD && self.tool_list.is_some())
+    }
+
+    /// Run a registered tool or one of the built-in methods
+    async fn run_method(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+        session: Option<Arc<Session>>,
+    ) -> Result<serde_json::Value> {
+        if let Some(handler) = self.handlers.get(method) {
+            let future = self.call_handler(handler, method, params, session);
+            return telemetry::instrument(method, future).await;
+        }
+        match method {
+            cancel::METHOD => self.cancel_request(params, session.as_deref()),
+            info::PING => self
+                .transformer
+                .transform_result(serialize(info::Pong::new(self.started.elapsed()))?),
+            info::INFO => self
+                .transformer
+                .transform_result(serialize(self.server_info())?),
+            quota::METHOD => {
+                let quotas = self
+                    .quotas
+                    .as_ref()
+                    .ok_or_else(|| Error::Other("Quotas are not enabled".to_string()))?;
+                let status = quotas.status(session.as_deref().map(Session::id));
+                self.transformer.transform_result(serialize(status)?)
+            }
+            schema::LIST_METHOD => {
+                let tools = self.tool_list.clone().unwrap_or_default();
+                if self.roots.is_empty() {
+                    Ok(serde_json::json!({ "tools": tools }))
+                } else {
+                    Ok(serde_json::json!({ "tools": tools, "roots": self.root_paths() }))
+                }
+            }
+            pipeline::METHOD => self.run_pipeline(params, session).await,
+            _ => Err(Error::NotFound(format!("Method not found: {}", method))),
+        }
+    }
+
+    /// Describe the server for the server/info method
+    fn server_info(&self) -> info::ServerInfo {
+        let mut tools: Vec<String> = self
+            .handlers
+            .keys()
+            .cloned()
+            .chain(BUILTIN_METHODS.iter().map(|method| method.to_string()))
+            .chain(self.quotas.is_some().then(|| quota::METHOD.to_string()))
+            .chain(
+                self.tool_list
+                    .is_some()
+                    .then(|| schema::LIST_METHOD.to_string()),
+            )
+            .collect();
+        tools.sort();
+
+        let cache = self.cache.as_ref().map(|cache| {
+            let config = cache.config();
+            info::CacheLimits {
+                ttl_secs: config.ttl.as_secs(),
+                max_entries: config.max_entries,
+                max_bytes: config.max_bytes,
+            }
+        });
+
+        let fs_limits = self.fs_limits.limits();
+
+        info::ServerInfo {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_secs: self.started.elapsed().as_secs(),
+            tools,
+            features: info::features(),
+            limits: info::Limits {
+                pipeline_max_steps: pipeline::MAX_STEPS,
+                cache,
+                filesystem: info::FilesystemLimits {
+                    max_open_files: fs_limits.max_open_files,
+                    max_walk_entries: fs_limits.max_walk_entries,
+                    max_walk_duration_ms: fs_limits
+                        .max_walk_duration
+                        .map(|duration| duration.as_millis() as u64),
                 },
-                None => {
-                    let resp = method_not_found::<serde_json::Value>(raw_request.id);
-                    serde_json::to_string(&resp)?
+            },
+            in_flight: self.in_flight.lock().unwrap().len(),
+            roots: self.root_paths(),
+        }
+    }
+
+    /// The named roots, with their paths as reported to callers
+    fn root_paths(&self) -> BTreeMap<String, String> {
+        self.roots
+            .iter()
+            .map(|(name, path)| (name.clone(), paths::to_string(path)))
+            .collect()
+    }
+
+    /// Remember the token of a request so the cancel method and a shutdown
+    /// can find it. Notifications, which have no id, are kept under their
+    /// correlation id; a `#` keeps those apart from JSON ids. `None` if a
+    /// request with the same id is still in flight in the same session.
+    fn track(
+        &self,
+        session: Option<&Session>,
+        id: &serde_json::Value,
+        correlation_id: &str,
+        token: &CancellationToken,
+    ) -> Option<InFlight<'_>> {
+        let id = if id.is_null() {
+            format!("#{}", correlation_id)
+        } else {
+            id.to_string()
+        };
+        let key = (session.map(|session| session.id().to_string()), id);
+        match self.in_flight.lock().unwrap().entry(key.clone()) {
+            Entry::Occupied(_) => return None,
+            Entry::Vacant(entry) => entry.insert(token.clone()),
+        };
+        Some(InFlight {
+            dispatcher: self,
+            key,
+        })
+    }
+
+    /// Cancel the running request with the given id, if it was sent in the
+    /// same session as the cancel request
+    fn cancel_request(
+        &self,
+        params: serde_json::Value,
+        session: Option<&Session>,
+    ) -> Result<serde_json::Value> {
+        let params: cancel::Params = deserialize(self.transformer.transform_params(params)?)?;
+        let key = (
+            session.map(|session| session.id().to_string()),
+            params.id.to_string(),
+        );
+        let token = self.in_flight.lock().unwrap().get(&key).cloned();
+        if let Some(token) = &token {
+            token.cancel();
+        }
+        let output = cancel::Output {
+            cancelled: token.is_some(),
+        };
+        self.transformer.transform_result(serialize(output)?)
+    }
+
+    /// Run the steps of a pipeline in order, stopping at the first failure
+    async fn run_pipeline(
+        &self,
+        params: serde_json::Value,
+        session: Option<Arc<Session>>,
+    ) -> Result<serde_json::Value> {
+        let params: pipeline::Params =
+            serde_json::from_value(self.transformer.transform_params(params)?)
+                .map_err(|e| Error::InvalidParam(format!("Invalid pipeline: {}", e)))?;
+        pipeline::Run::validate(&params)?;
+
+        // Check every method up front so a typo doesn't leave half a pipeline applied
+        if let Some(step) = params
+            .steps
+            .iter()
+            .find(|step| !self.handlers.contains_key(&step.method))
+        {
+            return Err(Error::InvalidParam(format!(
+                "Unknown method in pipeline: {}",
+                step.method
+            )));
+        }
+
+        let mut run = pipeline::Run::default();
+        for (index, step) in params.steps.iter().enumerate() {
+            let handler = &self.handlers[&step.method];
+            let step_params = run
+                .render(step)
+                .map_err(|e| pipeline::step_error(step, index, e))?;
+            let future = self.call_handler(handler, &step.method, step_params, session.clone());
+            let result = telemetry::instrument(&step.method, future)
+                .await
+                .map_err(|e| pipeline::step_error(step, index, e))?;
+            run.record(step, index, result);
+        }
+
+        Ok(serde_json::to_value(run.finish())?)
+    }
+
+    /// Dispatch a JSONRPC request, on behalf of a session if there is one,
+    /// that stops with a "Request cancelled" error once `token` is cancelled
+    /// (for example when the client disconnects) or a `cancel` request names
+    /// its id
+    pub async fn dispatch_cancellable(
+        &self,
+        request_str: &str,
+        session: Option<Arc<Session>>,
+        token: CancellationToken,
+    ) -> Result<String> {
+        let response = self.respond(request_str, session, token, None).await;
+        let bytes = self.encode(&response)?;
+        // serde_json only writes valid UTF-8
+        Ok(String::from_utf8(bytes).expect("JSON is UTF-8"))
+    }
+
+    /// Dispatch a JSONRPC request like [`dispatch_cancellable`](Self::dispatch_cancellable)
+    /// that fails with a `timeout` error once `deadline` passes, for transports
+    /// that give up on requests: the timeout of an HTTP request, or the
+    /// progress timeout of an MCP client
+    pub async fn dispatch_until(
+        &self,
+        request_str: &str,
+        session: Option<Arc<Session>>,
+        token: CancellationToken,
+        deadline: time::Instant,
+    ) -> Result<String> {
+        let response = self
+            .respond(request_str, session, token, Some(deadline))
+            .await;
+        let bytes = self.encode(&response)?;
+        Ok(String::from_utf8(bytes).expect("JSON is UTF-8"))
+    }
+
+    /// Handle a request, answering it with a response
+    async fn respond(
+        &self,
+        request_str: &str,
+        session: Option<Arc<Session>>,
+        token: CancellationToken,
+        deadline: Option<time::Instant>,
+    ) -> Response<serde_json::Value> {
+        let correlation_id = logging::new_correlation_id();
+        if let Some(logger) = &self.request_logger {
+            logger.log_request(&correlation_id, request_str);
+        }
+        let raw_request = match parse_request(request_str) {
+            Ok(raw_request) => raw_request,
+            Err(response) => return self.finish(response, correlation_id),
+        };
+
+        let session_result = match &session {
+            Some(session) if !self.is_shutting_down() => {
+                self.handle_session_method(session, &raw_request.method, raw_request.params.clone())
+            }
+            _ => None,
+        };
+
+        let response = if self.is_shutting_down() {
+            error::<serde_json::Value>(Error::ShuttingDown, raw_request.id)
+        } else if let Some(result) = session_result {
+            match result {
+                Ok(result) => success(result, raw_request.id),
+                Err(e) => error::<serde_json::Value>(e, raw_request.id),
+            }
+        } else if !self.handles(&raw_request.method) {
+            method_not_found::<serde_json::Value>(raw_request.id)
+        } else {
+            // A second request under the same id in a session would make cancelling
+            // it ambiguous
+            let Some(_in_flight) = self.track(
+                session.as_deref(),
+                &raw_request.id,
+                &correlation_id,
+                &token,
+            ) else {
+                let response = invalid_request::<serde_json::Value>(
+                    &format!("id {} is already in flight", raw_request.id),
+                    raw_request.id,
+                );
+                return self.finish(response, correlation_id);
+            };
+            let start = std::time::Instant::now();
+            let meter = self
+                .quotas
+                .as_ref()
+                .map(|quotas| quotas.meter(session.as_deref().map(Session::id)));
+            let call = async {
+                let run = self.run_method(&raw_request.method, raw_request.params.clone(), session);
+                match meter {
+                    Some(meter) => quota::scope(meter, run).await,
+                    None => run.await,
                 }
+            };
+            let call = async {
+                match &self.trash_dir {
+                    Some(directory) => trash::scope(directory.clone(), call).await,
+                    None => call.await,
+                }
+            };
+            let call = roots::scope(self.roots.clone(), call);
+            let call = limits::scope(self.fs_limits.clone(), call);
+            let recorder = Arc::new(execution::Recorder::default());
+            let call = execution::scope(recorder.clone(), call);
+            let timeout = self
+                .request_timeout
+                .map(|timeout| time::Instant::now() + timeout);
+            let deadline = deadline.into_iter().chain(timeout).min();
+            let future = logging::scope(
+                correlation_id.clone(),
+                cancel::scope_until(token, deadline, call),
+            );
+            let result = match &self.filesystem {
+                Some(filesystem) => vfs::scope(filesystem.clone(), future).await,
+                None => future.await,
+            };
+            logging::log_call(
+                &correlation_id,
+                &raw_request.method,
+                start.elapsed(),
+                &result,
+            );
+            let result = match &self.token_budget {
+                Some(budget) if self.has_method(&raw_request.method) => {
+                    result.map(|result| budget.shape(result))
+                }
+                _ => result,
+            };
+            let meta = recorder.metadata(start.elapsed(), result.as_ref().ok());
+            let response = match result {
+                Ok(result) => success(result, raw_request.id),
+                Err(e) => error::<serde_json::Value>(e, raw_request.id),
+            };
+            if self.execution_metadata {
+                response.with_metadata(meta)
+            } else {
+                response
             }
         };
+        self.finish(response, correlation_id)
+    }
+
+    /// Log a response and add the correlation id of the call, if enabled
+    fn finish(
+        &self,
+        response: Response<serde_json::Value>,
+        correlation_id: String,
+    ) -> Response<serde_json::Value> {
+        if let Some(logger) = &self.request_logger {
+            logger.log_response(&correlation_id, &response);
+        }
+        if self.correlation_ids {
+            response.with_correlation_id(correlation_id)
+        } else {
+            response
+        }
+    }
+
+    /// Serialize a response in the configured style
+    fn encode(&self, response: &Response<serde_json::Value>) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        write_response(&mut bytes, response, self.pretty, self.trailing_newline)?;
+        Ok(bytes)
+    }
+}
+
+/// Serialize a response into `out`, indented if `pretty`
+fn write_response(
+    out: &mut impl std::io::Write,
+    response: &Response<serde_json::Value>,
+    pretty: bool,
+    trailing_newline: bool,
+) -> Result<()> {
+    if pretty {
+        serde_json::to_writer_pretty(&mut *out, response)?;
+    } else {
+        serde_json::to_writer(&mut *out, response)?;
+    }
+    if trailing_newline {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Check the envelope of a request. Text that isn't JSON gets a parse error,
+/// and anything that isn't a JSONRPC 2.0 request an invalid request error,
+/// with a null id unless the request has a valid one. Parameters that are
+/// neither an object nor an array get an invalid params error.
+#[allow(clippy::result_large_err)]
+fn parse_request(
+    request_str: &str,
+) -> std::result::Result<RawRequest, Response<serde_json::Value>> {
+    use serde_json::Value;
+
+    let value: Value =
+        serde_json::from_str(request_str).map_err(|e| error(Error::Json(e), Value::Null))?;
+    let Value::Object(mut request) = value else {
+        return Err(invalid_request(
+            "Request must be a JSON object",
+            Value::Null,
+        ));
+    };
+    let id = match request.remove("id") {
+        None | Some(Value::Null) => Value::Null,
+        Some(id @ (Value::String(_) | Value::Number(_))) => id,
+        Some(_) => {
+            return Err(invalid_request(
+                "id must be a string, a number or null",
+                Value::Null,
+            ));
+        }
+    };
+    if request.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(invalid_request("Invalid JSONRPC version", id));
+    }
+    let method = match request.remove("method") {
+        Some(Value::String(method)) => method,
+        Some(_) => return Err(invalid_request("method must be a string", id)),
+        None => return Err(invalid_request("Missing method", id)),
+    };
+    let params = match request.remove("params") {
+        None | Some(Value::Null) => Value::Null,
+        Some(params @ (Value::Object(_) | Value::Array(_))) => params,
+        Some(_) => {
+            return Err(error(
+                Error::InvalidParam("params must be an object or an array".to_string()),
+                id,
+            ));
+        }
+    };
+    Ok(RawRequest {
+        jsonrpc: "2.0".to_string(),
+        method,
+        params,
+        id,
+    })
+}
+
+#[cfg(all(test, feature = "file-read"))]
+mod tests {
+    use super::*;
+    use crate::tools::file_read;
+
+    #[tokio::test]
+    async fn test_parse_response() -> Result<()> {
+        let dispatcher = crate::create_default_dispatcher().with_filesystem(Arc::new(
+            crate::vfs::MemoryFs::new().with_file("/a.txt", "hello"),
+        ));
+        let call = async |path: &str| -> Result<String> {
+            let request = serde_json::json!({
+                "jsonrpc": "2.0", "method": "file_read", "params": {"path": path}, "id": 7
+            });
+            dispatcher.dispatch(&request.to_string()).await
+        };
+
+        let response = Response::<file_read::Output>::parse(&call("/a.txt").await?)?;
+        let Response::Success(success) = &response else {
+            panic!("expected a success response");
+        };
+        assert_eq!(success.id, 7);
+        assert_eq!(success.result.content, "hello");
+
+        let output: file_read::Output = extract_result(&call("/a.txt").await?)?;
+        assert_eq!(output.size, 5);
+
+        // Errors come back as the kind the server reported, even when the
+        // result type would accept a missing result
+        let err = extract_result::<Option<file_read::Output>>(&call("/b.txt").await?).unwrap_err();
+        assert!(matches!(err, Error::NotFound(_)));
+
+        assert!(extract_result::<serde_json::Value>(r#"{"result": 1, "id": 1}"#).is_err());
+        assert!(extract_result::<serde_json::Value>(r#"{"jsonrpc": "2.0", "id": 1}"#).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_requests() -> Result<()> {
+        let dispatcher = crate::create_default_dispatcher();
+        let call = async |request: &str| -> Result<(i64, serde_json::Value)> {
+            let response: serde_json::Value =
+                serde_json::from_str(&dispatcher.dispatch(request).await?)?;
+            Ok((
+                response["error"]["code"].as_i64().unwrap(),
+                response["id"].clone(),
+            ))
+        };
+        let null = serde_json::Value::Null;
+
+        assert_eq!(call("not json").await?, (-32700, null.clone()));
+        assert_eq!(call("[1, 2]").await?, (-32600, null.clone()));
+        // An id of the wrong type can't be echoed back
+        assert_eq!(
+            call(r#"{"jsonrpc": "2.0", "method": "ping", "id": {"a": 1}}"#).await?,
+            (-32600, null.clone())
+        );
+        assert_eq!(
+            call(r#"{"jsonrpc": "2.0", "id": 3}"#).await?,
+            (-32600, 3.into())
+        );
+        assert_eq!(
+            call(r#"{"jsonrpc": "2.0", "method": 7, "id": "a"}"#).await?,
+            (-32600, "a".into())
+        );
+        assert_eq!(
+            call(r#"{"jsonrpc": "1.0", "method": "ping", "id": 4}"#).await?,
+            (-32600, 4.into())
+        );
+        assert_eq!(
+            call(r#"{"jsonrpc": "2.0", "method": "ping", "params": "x", "id": 5}"#).await?,
+            (-32602, 5.into())
+        );
+        // Parameters of the wrong shape for the tool
+        assert_eq!(
+            call(r#"{"jsonrpc": "2.0", "method": "file_read", "params": {"path": 1}, "id": 6}"#)
+                .await?,
+            (-32602, 6.into())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_output_style() -> Result<()> {
+        let ping = r#"{"jsonrpc": "2.0", "method": "ping", "id": 1}"#;
+
+        let compact = crate::create_default_dispatcher().dispatch(ping).await?;
+        assert!(!compact.contains('\n'));
+
+        let dispatcher = crate::create_default_dispatcher()
+            .with_pretty_output()
+            .with_trailing_newline();
+        let pretty = dispatcher.dispatch(ping).await?;
+        assert!(pretty.starts_with("{\n  "));
+        assert!(pretty.ends_with("}\n"));
+
+        let mut written = Vec::new();
+        dispatcher.dispatch_to_writer(ping, &mut written).await?;
+        let written: serde_json::Value = serde_json::from_slice(&written)?;
+        assert_eq!(written["result"]["status"], "ok");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_to_writer_large() -> Result<()> {
+        let mut dispatcher = crate::create_default_dispatcher().with_trailing_newline();
+        dispatcher.register("big", |params: serde_json::Value| async move {
+            let len = params["len"].as_u64().unwrap() as usize;
+            Ok(serde_json::json!({ "text": "x".repeat(len) }))
+        });
+
+        // Far more than the pipe holds, read through a small buffer
+        let request = r#"{"jsonrpc": "2.0", "method": "big", "params": {"len": 1000000}, "id": 1}"#;
+        let (mut writer, mut reader) = tokio::io::duplex(1024);
+        let read = tokio::spawn(async move {
+            let mut bytes = Vec::new();
+            tokio::io::AsyncReadExt::read_to_end(&mut reader, &mut bytes).await?;
+            Ok::<_, std::io::Error>(bytes)
+        });
+        dispatcher.dispatch_to_writer(request, &mut writer).await?;
+        drop(writer);
+
+        let bytes = read.await.unwrap()?;
+        assert_eq!(bytes.last(), Some(&b'\n'));
+        let response: serde_json::Value = serde_json::from_slice(&bytes)?;
+        assert_eq!(response["result"]["text"].as_str().unwrap().len(), 1_000_000);
+        assert_eq!(response["id"], 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_register_with_schema() -> Result<()> {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"name": {"type": "string"}},
+            "required": ["name"]
+        });
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register_with_schema("greet", "Greet someone", schema.clone(), |params: serde_json::Value| async move {
+            Ok(serde_json::json!({"greeting": format!("hello {}", params["name"].as_str().unwrap_or("?"))}))
+        })?;
+        // Listed even though the tool list is set afterwards
+        let dispatcher = dispatcher.with_tool_list(&ToolSchemaRegistry::new());
+
+        let call = r#"{"jsonrpc": "2.0", "method": "greet", "params": {"name": "ada"}, "id": 1}"#;
+        let result: serde_json::Value = extract_result(&dispatcher.dispatch(call).await?)?;
+        assert_eq!(result["greeting"], "hello ada");
+
+        let list = r#"{"jsonrpc": "2.0", "method": "tools/list", "id": 2}"#;
+        let result: serde_json::Value = extract_result(&dispatcher.dispatch(list).await?)?;
+        let tools = result["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0]["name"], "greet");
+        assert_eq!(tools[0]["inputSchema"], schema);
+
+        let mut dispatcher = Dispatcher::new();
+        let handler = |params: serde_json::Value| async move { Ok::<_, Error>(params) };
+        assert!(
+            dispatcher
+                .register_with_schema("bad", "", serde_json::json!([]), handler)
+                .is_err()
+        );
+        assert!(
+            dispatcher
+                .register_with_schema("bad", "", serde_json::json!({"type": "string"}), handler)
+                .is_err()
+        );
+        assert!(!dispatcher.has_method("bad"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tool_defaults() -> Result<()> {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register(
+            "echo",
+            |params: serde_json::Value| async move { Ok(params) },
+        );
+        dispatcher.set_tool_defaults(
+            "echo",
+            serde_json::json!({"limit": 10, "exclude": [".git", "target"]}),
+        )?;
+
+        let call = r#"{"jsonrpc": "2.0", "method": "echo", "params": {"limit": 5}, "id": 1}"#;
+        let result: serde_json::Value = extract_result(&dispatcher.dispatch(call).await?)?;
+        assert_eq!(
+            result,
+            serde_json::json!({"limit": 5, "exclude": [".git", "target"]})
+        );
+
+        // Calls without params get all the defaults, and pipeline steps get them too
+        let call = r#"{"jsonrpc": "2.0", "method": "echo", "id": 2}"#;
+        let result: serde_json::Value = extract_result(&dispatcher.dispatch(call).await?)?;
+        assert_eq!(result["limit"], 10);
+        let pipeline = r#"{"jsonrpc": "2.0", "method": "pipeline", "params": {"steps": [{"method": "echo", "params": {"exclude": null}}]}, "id": 3}"#;
+        let result: serde_json::Value = extract_result(&dispatcher.dispatch(pipeline).await?)?;
+        assert_eq!(
+            result["steps"][0]["result"],
+            serde_json::json!({"limit": 10, "exclude": null})
+        );
+
+        assert!(
+            dispatcher
+                .set_tool_defaults("missing", serde_json::json!({}))
+                .is_err()
+        );
+        assert!(
+            dispatcher
+                .set_tool_defaults("echo", serde_json::json!([1]))
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_warnings() -> Result<()> {
+        let mut dispatcher = Dispatcher::new().with_cache(CacheConfig {
+            tools: ["scan".to_string()].into(),
+            ..CacheConfig::default()
+        });
+        dispatcher.register("scan", |params: serde_json::Value| async move {
+            if params["skip"] == true {
+                warnings::warn("Skipped /private: Permission denied");
+            }
+            Ok(serde_json::json!({"entries": []}))
+        });
+
+        let call = r#"{"jsonrpc": "2.0", "method": "scan", "params": {"skip": false}, "id": 1}"#;
+        let result: serde_json::Value = extract_result(&dispatcher.dispatch(call).await?)?;
+        assert_eq!(result, serde_json::json!({"entries": []}));
+
+        // Cached results keep their warnings
+        let call = r#"{"jsonrpc": "2.0", "method": "scan", "params": {"skip": true}, "id": 2}"#;
+        for _ in 0..2 {
+            let result: serde_json::Value = extract_result(&dispatcher.dispatch(call).await?)?;
+            assert_eq!(
+                result["warnings"],
+                serde_json::json!(["Skipped /private: Permission denied"])
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_method_names() -> Result<()> {
+        let dispatcher = crate::create_default_dispatcher();
+        let call = r#"{"jsonrpc": "2.0", "method": "pipelines", "params": {"steps": []}, "id": 1}"#;
+        let response: serde_json::Value = serde_json::from_str(&dispatcher.dispatch(call).await?)?;
+        assert_eq!(response["error"]["code"], -32601);
+
+        // Only the pipeline method runs a pipeline
+        let result = dispatcher
+            .run_method("pipelines", serde_json::json!({"steps": []}), None)
+            .await;
+        assert!(matches!(result, Err(Error::NotFound(msg)) if msg == "Method not found: pipelines"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_ids() -> Result<()> {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register("wait", |_: serde_json::Value| async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok(serde_json::json!("done"))
+        });
+        let call = |id: u32| format!(r#"{{"jsonrpc": "2.0", "method": "wait", "id": {}}}"#, id);
+
+        let (one, two) = (call(1), call(2));
+        let (first, second, other) = tokio::join!(
+            dispatcher.dispatch(&one),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                dispatcher.dispatch(&one).await
+            },
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                dispatcher.dispatch(&two).await
+            }
+        );
+        assert_eq!(extract_result::<String>(&first?)?, "done");
+        assert_eq!(extract_result::<String>(&other?)?, "done");
+        let second: serde_json::Value = serde_json::from_str(&second?)?;
+        assert_eq!(second["error"]["code"], -32600);
+        assert_eq!(second["id"], 1);
+
+        // The id is free again once its request has finished
+        assert_eq!(extract_result::<String>(&dispatcher.dispatch(&one).await?)?, "done");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ids_per_session() -> Result<()> {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register("wait", |_: serde_json::Value| async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok(serde_json::json!("done"))
+        });
+        let first = Arc::new(Session::new(std::env::temp_dir())?);
+        let second = Arc::new(Session::new(std::env::temp_dir())?);
+        let wait = r#"{"jsonrpc": "2.0", "method": "wait", "id": 1}"#;
+        let cancel = r#"{"jsonrpc": "2.0", "method": "cancel", "params": {"id": 1}, "id": 2}"#;
+
+        // Both sessions run a request with the same id, and a cancel only
+        // reaches the one in its own session
+        let (first_result, second_result, cancels) = tokio::join!(
+            dispatcher.dispatch_in_session(wait, first.clone()),
+            dispatcher.dispatch_in_session(wait, second.clone()),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let outside: serde_json::Value = extract_result(&dispatcher.dispatch(cancel).await?)?;
+                let inside: serde_json::Value =
+                    extract_result(&dispatcher.dispatch_in_session(cancel, second.clone()).await?)?;
+                Ok::<_, Error>((outside, inside))
+            }
+        );
+        let (outside, inside) = cancels?;
+        assert_eq!(outside["cancelled"], false);
+        assert_eq!(inside["cancelled"], true);
+        assert_eq!(extract_result::<String>(&first_result?)?, "done");
+        let second_result: serde_json::Value = serde_json::from_str(&second_result?)?;
+        assert_eq!(second_result["error"]["code"], -32800);
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let io = |kind| Error::Io(std::io::Error::new(kind, "disk"));
+        let json = || Error::Json(serde_json::from_str::<u8>("x").unwrap_err());
+        let errors = [
+            (io(std::io::ErrorKind::Other), -32000, "io"),
+            (io(std::io::ErrorKind::NotFound), -32000, "not_found"),
+            (io(std::io::ErrorKind::AlreadyExists), -32000, "already_exists"),
+            (io(std::io::ErrorKind::PermissionDenied), -32000, "permission_denied"),
+            (io(std::io::ErrorKind::TimedOut), -32000, "timeout"),
+            (io(std::io::ErrorKind::FileTooLarge), -32000, "too_large"),
+            (json(), -32700, "json"),
+            (Error::InvalidParam("a".into()), -32602, "invalid_param"),
+            (Error::PermissionDenied("a".into()), -32001, "permission_denied"),
+            (Error::NotFound("a".into()), -32004, "not_found"),
+            (Error::AlreadyExists("a".into()), -32005, "already_exists"),
+            (Error::OutsideSandbox("a".into()), -32001, "outside_sandbox"),
+            (Error::TooLarge("a".into()), -32006, "too_large"),
+            (Error::Timeout("a".into()), -32000, "timeout"),
+            (Error::Conflict("a".into()), -32002, "conflict"),
+            (Error::Cancelled, -32800, "cancelled"),
+            (Error::ShuttingDown, -32000, "shutting_down"),
+            (Error::QuotaExceeded("a".into()), -32003, "quota_exceeded"),
+            (Error::Other("a".into()), -32603, "other"),
+        ];
+
+        for (err, number, code) in errors {
+            assert_eq!(err.code(), code);
+            let Response::<()>::Error(response) = error(err, 1.into()) else {
+                panic!("{} gave a success response", code);
+            };
+            assert_eq!(response.error.code, number, "{}", code);
+            assert_eq!(response.error.data.as_ref().unwrap()["code"], code);
+
+            // The client gets back an error of the same kind
+            let text = serde_json::to_string(&response.error).unwrap();
+            let rpc: RpcError = serde_json::from_str(&text).unwrap();
+            assert_eq!(rpc.into_error().code(), code);
+        }
+    }
+
+    #[test]
+    fn test_error_with_context() {
+        use std::error::Error as _;
+
+        let err = Error::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "gone"))
+            .with_context("Step 2");
+        let Error::Io(io) = &err else {
+            panic!("not an I/O error: {:?}", err);
+        };
+        assert_eq!(io.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "I/O error: Step 2: gone");
+        // The original error is still in the chain
+        let original = err.source().and_then(|e| e.source()).unwrap();
+        assert_eq!(original.to_string(), "gone");
+        assert!(original.downcast_ref::<std::io::Error>().is_some());
+
+        let err = Error::Json(serde_json::from_str::<u8>("x").unwrap_err()).with_context("Step 3");
+        assert_eq!(err.code(), "json");
+        assert!(err.to_string().starts_with("JSON error: Step 3: expected value"));
+        let original = err.source().and_then(|e| e.source()).unwrap();
+        assert!(original.downcast_ref::<serde_json::Error>().is_some());
 
-        Ok(response)
+        let err = Error::NotFound("a.txt".into()).with_context("Step 4");
+        assert!(matches!(&err, Error::NotFound(msg) if msg == "Step 4: a.txt"));
     }
 }
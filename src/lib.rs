@@ -7,15 +7,52 @@ use std::error::Error as StdError;
 use std::fmt;
 use std::sync::Arc;
 
+pub mod cache;
+pub mod cancel;
+pub mod client;
+pub mod execution;
+pub mod info;
 pub mod jsonrpc;
+pub mod limits;
 pub mod logging;
+pub mod paths;
+pub mod pipeline;
+pub mod quota;
+pub mod roots;
 pub mod schema;
+pub mod session;
+pub mod shaping;
+mod sys;
+pub mod telemetry;
 pub mod tools;
 pub mod transform;
+pub mod trash;
+pub mod vfs;
+pub mod warnings;
+
+#[cfg(all(target_family = "wasm", any(feature = "shell", feature = "job")))]
+compile_error!("the `shell` and `job` features need processes, which WASM doesn't have; build with `default-features = false`");
+#[cfg(all(target_family = "wasm", feature = "network-check"))]
+compile_error!("the `network-check` feature needs sockets, which WASM doesn't have; build with `default-features = false`");
 
 // Re-export key types
 pub use transform::{FormatConfig, FormatTransformer, InputFormat, OutputFormat};
+pub use session::Session;
+pub use cache::CacheConfig;
+pub use quota::QuotaConfig;
+pub use limits::FsLimits;
 pub use schema::{ToolSchema, ToolSchemaRegistry, BedrockToolSpec, generate_tool_schema, to_bedrock_tool_spec};
+#[cfg(feature = "derive")]
+pub use gamecode_tools_derive::tool;
+
+/// Paths used by the code `#[tool]` generates
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __private {
+    pub use async_trait::async_trait;
+    pub use schemars;
+    pub use serde;
+}
 
 /// Custom error type for the library
 #[derive(Debug)]
@@ -28,10 +65,108 @@ pub enum Error {
     InvalidParam(String),
     /// Operation not permitted error
     PermissionDenied(String),
+    /// A file or directory the call needs does not exist
+    NotFound(String),
+    /// A file or directory the call would create already exists
+    AlreadyExists(String),
+    /// A path is outside the directory the call is confined to
+    OutsideSandbox(String),
+    /// An input or result is over a size limit
+    TooLarge(String),
+    /// The call did not finish in time
+    Timeout(String),
+    /// The target changed since the caller last saw it
+    Conflict(String),
+    /// The call was cancelled before it finished
+    Cancelled,
+    /// The server is shutting down and takes no new requests
+    ShuttingDown,
+    /// The call would go over the session's quota
+    QuotaExceeded(String),
     /// General error
     Other(String),
 }
 
+impl Error {
+    /// Stable code of the kind of error, sent to clients in the `code` member
+    /// of the JSONRPC error's `data`. I/O errors are classified by their kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Io(err) => match err.kind() {
+                std::io::ErrorKind::NotFound => "not_found",
+                std::io::ErrorKind::AlreadyExists => "already_exists",
+                std::io::ErrorKind::PermissionDenied => "permission_denied",
+                std::io::ErrorKind::TimedOut => "timeout",
+                std::io::ErrorKind::FileTooLarge => "too_large",
+                std::io::ErrorKind::QuotaExceeded => "quota_exceeded",
+                _ => "io",
+            },
+            Error::Json(_) => "json",
+            Error::InvalidParam(_) => "invalid_param",
+            Error::PermissionDenied(_) => "permission_denied",
+            Error::NotFound(_) => "not_found",
+            Error::AlreadyExists(_) => "already_exists",
+            Error::OutsideSandbox(_) => "outside_sandbox",
+            Error::TooLarge(_) => "too_large",
+            Error::Timeout(_) => "timeout",
+            Error::Conflict(_) => "conflict",
+            Error::Cancelled => "cancelled",
+            Error::ShuttingDown => "shutting_down",
+            Error::QuotaExceeded(_) => "quota_exceeded",
+            Error::Other(_) => "other",
+        }
+    }
+
+    /// Prefix the message with where the error came from, keeping its kind.
+    /// I/O and JSON errors keep the original error as their source.
+    pub fn with_context(self, context: &str) -> Self {
+        let message = |msg: String| format!("{}: {}", context, msg);
+        let contextual = |source| Contextual {
+            context: context.to_string(),
+            source,
+        };
+        match self {
+            Error::Io(err) => Error::Io(std::io::Error::new(err.kind(), contextual(err.into()))),
+            // serde_json errors can only be built around an I/O error
+            Error::Json(err) => Error::Json(serde_json::Error::io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                contextual(err.into()),
+            ))),
+            Error::InvalidParam(msg) => Error::InvalidParam(message(msg)),
+            Error::PermissionDenied(msg) => Error::PermissionDenied(message(msg)),
+            Error::NotFound(msg) => Error::NotFound(message(msg)),
+            Error::AlreadyExists(msg) => Error::AlreadyExists(message(msg)),
+            Error::OutsideSandbox(msg) => Error::OutsideSandbox(message(msg)),
+            Error::TooLarge(msg) => Error::TooLarge(message(msg)),
+            Error::Timeout(msg) => Error::Timeout(message(msg)),
+            Error::Conflict(msg) => Error::Conflict(message(msg)),
+            Error::Cancelled => Error::Cancelled,
+            Error::ShuttingDown => Error::ShuttingDown,
+            Error::QuotaExceeded(msg) => Error::QuotaExceeded(message(msg)),
+            Error::Other(msg) => Error::Other(message(msg)),
+        }
+    }
+}
+
+/// An error with a note on where it came from, shown before its message
+#[derive(Debug)]
+struct Contextual {
+    context: String,
+    source: Box<dyn StdError + Send + Sync>,
+}
+
+impl fmt::Display for Contextual {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.context, self.source)
+    }
+}
+
+impl StdError for Contextual {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&*self.source)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -39,6 +174,15 @@ impl fmt::Display for Error {
             Error::Json(err) => write!(f, "JSON error: {}", err),
             Error::InvalidParam(msg) => write!(f, "Invalid parameter: {}", msg),
             Error::PermissionDenied(msg) => write!(f, "Permission denied: {}", msg),
+            Error::NotFound(msg) => write!(f, "{}", msg),
+            Error::AlreadyExists(msg) => write!(f, "{}", msg),
+            Error::OutsideSandbox(msg) => write!(f, "{}", msg),
+            Error::TooLarge(msg) => write!(f, "{}", msg),
+            Error::Timeout(msg) => write!(f, "{}", msg),
+            Error::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            Error::Cancelled => write!(f, "Cancelled"),
+            Error::ShuttingDown => write!(f, "Server is shutting down"),
+            Error::QuotaExceeded(msg) => write!(f, "Quota exceeded: {}", msg),
             Error::Other(msg) => write!(f, "{}", msg),
         }
     }
@@ -49,16 +193,18 @@ impl StdError for Error {
         match self {
             Error::Io(err) => Some(err),
             Error::Json(err) => Some(err),
-            Error::InvalidParam(_) => None,
-            Error::PermissionDenied(_) => None,
-            Error::Other(_) => None,
+            _ => None,
         }
     }
 }
 
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
-        Error::Io(err)
+        // Quota errors from the filesystem layer keep their own kind
+        match err.kind() {
+            std::io::ErrorKind::QuotaExceeded => Error::QuotaExceeded(err.to_string()),
+            _ => Error::Io(err),
+        }
     }
 }
 
@@ -96,7 +242,36 @@ pub fn create_dispatcher_with_schema_registry(
     transformer: transform::FormatTransformer,
 ) -> (jsonrpc::Dispatcher, schema::ToolSchemaRegistry) {
     let mut registry = schema::ToolSchemaRegistry::new();
-    let dispatcher = create_dispatcher_with_transformer_and_registry(transformer, &mut registry);
+    let dispatcher =
+        create_dispatcher_with_transformer_and_registry(transformer, &mut registry, None, None);
+    (dispatcher, registry)
+}
+
+/// Factory function to create a dispatcher with schema registry whose mutating tools
+/// record their changes in `journal`, adding the `undo_list` and `undo_apply` tools
+#[cfg(feature = "undo")]
+pub fn create_dispatcher_with_undo_journal(
+    transformer: transform::FormatTransformer,
+    journal: tools::undo::UndoJournal,
+) -> (jsonrpc::Dispatcher, schema::ToolSchemaRegistry) {
+    let mut registry = schema::ToolSchemaRegistry::new();
+    let dispatcher =
+        create_dispatcher_with_transformer_and_registry(transformer, &mut registry, Some(journal), None);
+    (dispatcher, registry)
+}
+
+/// Factory function to create a dispatcher with schema registry whose `shell` and
+/// `shell_spawn` tools run with `shell`'s settings, such as shell mode
+/// ([`Shell::with_shell_mode`](tools::shell::Shell::with_shell_mode)) or a
+/// workspace root ([`Shell::with_root`](tools::shell::Shell::with_root))
+#[cfg(feature = "shell")]
+pub fn create_dispatcher_with_shell(
+    transformer: transform::FormatTransformer,
+    shell: tools::shell::Shell,
+) -> (jsonrpc::Dispatcher, schema::ToolSchemaRegistry) {
+    let mut registry = schema::ToolSchemaRegistry::new();
+    let dispatcher =
+        create_dispatcher_with_transformer_and_registry(transformer, &mut registry, None, Some(shell));
     (dispatcher, registry)
 }
 
@@ -105,189 +280,430 @@ pub fn create_bedrock_dispatcher_with_schemas() -> (jsonrpc::Dispatcher, schema:
     create_dispatcher_with_schema_registry(transform::bedrock_to_standard_transformer())
 }
 
-/// Factory function to create a dispatcher with a custom transformer and schema registry
+/// Undo journal passed to the mutating tools, if any
+#[cfg(feature = "undo")]
+type Journal = Option<tools::undo::UndoJournal>;
+#[cfg(not(feature = "undo"))]
+type Journal = Option<std::convert::Infallible>;
+
+/// Shell tool to register instead of the default one, if any
+#[cfg(feature = "shell")]
+type ShellTool = Option<tools::shell::Shell>;
+#[cfg(not(feature = "shell"))]
+type ShellTool = Option<std::convert::Infallible>;
+
+/// Wrap a mutating tool so it records its changes in the journal, if any
+#[cfg(feature = "undo")]
+#[allow(dead_code)]
+fn journaled<T>(tool: T, journal: &Journal) -> tools::undo::Journaled<T> {
+    tools::undo::Journaled::new(tool, journal.clone())
+}
+#[cfg(not(feature = "undo"))]
+#[allow(dead_code)]
+fn journaled<T>(tool: T, _journal: &Journal) -> T {
+    tool
+}
+
+/// Factory function to create a dispatcher with a custom transformer and schema registry.
+/// Only the tools whose features are enabled are registered.
+// Without undo, the journaled tools are the plain ones, some of them Copy
+#[cfg_attr(not(feature = "undo"), allow(clippy::clone_on_copy))]
 fn create_dispatcher_with_transformer_and_registry(
     transformer: transform::FormatTransformer,
     registry: &mut schema::ToolSchemaRegistry,
+    journal: Journal,
+    shell: ShellTool,
 ) -> jsonrpc::Dispatcher {
+    #[allow(unused_imports)]
     use tools::Tool;
 
+    // Nothing is registered when no tool features are enabled
+    #[allow(unused_mut)]
     let mut dispatcher = jsonrpc::Dispatcher::with_transformer(Arc::new(transformer));
 
     // Register directory_list tool
-    let dir_list_tool = tools::directory_list::DirectoryList;
-    registry.register::<tools::directory_list::Params>("directory_list", "List contents of a directory");
-    dispatcher.register(
-        "directory_list",
-        move |params: tools::directory_list::Params| async move {
-            dir_list_tool.execute(params).await
-        },
-    );
+    #[cfg(feature = "directory-list")]
+    {
+        let dir_list_tool = tools::directory_list::DirectoryList;
+        registry.register_tool_with_result(&dir_list_tool);
+        dispatcher.register(
+            "directory_list",
+            move |params: tools::directory_list::Params| async move {
+                dir_list_tool.execute(params).await
+            },
+        );
+    }
 
     // Register file_read tool
-    let file_read_tool = tools::file_read::FileRead;
-    registry.register::<tools::file_read::Params>("file_read", "Read a file from the filesystem");
-    dispatcher.register(
-        "file_read",
-        move |params: tools::file_read::Params| async move { file_read_tool.execute(params).await },
-    );
+    #[cfg(feature = "file-read")]
+    {
+        let file_read_tool = tools::file_read::FileRead;
+        registry.register_tool_with_result(&file_read_tool);
+        dispatcher.register(
+            "file_read",
+            move |params: tools::file_read::Params| async move { file_read_tool.execute(params).await },
+        );
+    }
 
-    // Register file_write tool
-    let file_write_tool = tools::file_write::FileWrite;
-    registry.register::<tools::file_write::Params>("file_write", "Write content to a file");
-    dispatcher.register(
-        "file_write",
-        move |params: tools::file_write::Params| async move {
-            file_write_tool.execute(params).await
-        },
-    );
+    // Register file_read_chunk tool
+    #[cfg(feature = "file-read-chunk")]
+    {
+        let file_read_chunk_tool = tools::file_read_chunk::FileReadChunk;
+        registry.register_tool_with_result(&file_read_chunk_tool);
+        dispatcher.register(
+            "file_read_chunk",
+            move |params: tools::file_read_chunk::Params| async move {
+                file_read_chunk_tool.execute(params).await
+            },
+        );
+    }
 
-    // Register file_patch tool
-    let file_patch_tool = tools::file_patch::FilePatch;
-    registry.register::<tools::file_patch::Params>("file_patch", "Apply a patch to a file");
-    dispatcher.register(
-        "file_patch",
-        move |params: tools::file_patch::Params| async move {
-            file_patch_tool.execute(params).await
-        },
-    );
+    // Register files_read tool
+    #[cfg(feature = "files-read")]
+    {
+        let files_read_tool = tools::files_read::FilesRead;
+        registry.register_tool_with_result(&files_read_tool);
+        dispatcher.register(
+            "files_read",
+            move |params: tools::files_read::Params| async move { files_read_tool.execute(params).await },
+        );
+    }
 
-    // Register directory_make tool
-    let dir_make_tool = tools::directory_make::DirectoryMake;
-    registry.register::<tools::directory_make::Params>("directory_make", "Create a directory");
-    dispatcher.register(
-        "directory_make",
-        move |params: tools::directory_make::Params| async move {
-            dir_make_tool.execute(params).await
-        },
-    );
+    // Register batch tool (mutating tools record their changes in the undo journal, if any)
+    #[cfg(feature = "batch")]
+    {
+        let batch_tool = journaled(tools::batch::Batch, &journal);
+        registry.register_tool_with_result(&batch_tool);
+        dispatcher.register("batch", move |params: tools::batch::Params| {
+            let batch_tool = batch_tool.clone();
+            async move { batch_tool.execute(params).await }
+        });
+    }
 
-    // Register file_move tool
-    let file_move_tool = tools::file_move::FileMove;
-    registry.register::<tools::file_move::Params>("file_move", "Move or rename a file");
-    dispatcher.register(
-        "file_move",
-        move |params: tools::file_move::Params| async move { file_move_tool.execute(params).await },
-    );
+    // Register file_write tool
+    #[cfg(feature = "file-write")]
+    {
+        let file_write_tool = journaled(tools::file_write::FileWrite, &journal);
+        registry.register_tool_with_result(&file_write_tool);
+        dispatcher.register("file_write", move |params: tools::file_write::Params| {
+            let file_write_tool = file_write_tool.clone();
+            async move { file_write_tool.execute(params).await }
+        });
+    }
 
-    // Register file_find tool
-    let file_find_tool = tools::file_find::FileFind;
-    registry.register::<tools::file_find::Params>("file_find", "Find files matching a pattern");
-    dispatcher.register(
-        "file_find",
-        move |params: tools::file_find::Params| async move { file_find_tool.execute(params).await },
-    );
+    // Register file_patch tool
+    #[cfg(feature = "file-patch")]
+    {
+        let file_patch_tool = journaled(tools::file_patch::FilePatch, &journal);
+        registry.register_tool_with_result(&file_patch_tool);
+        dispatcher.register("file_patch", move |params: tools::file_patch::Params| {
+            let file_patch_tool = file_patch_tool.clone();
+            async move { file_patch_tool.execute(params).await }
+        });
+    }
 
-    // Register file_grep tool
-    let file_grep_tool = tools::file_grep::FileGrep;
-    registry.register::<tools::file_grep::Params>("file_grep", "Search file contents for a pattern");
-    dispatcher.register(
-        "file_grep",
-        move |params: tools::file_grep::Params| async move { file_grep_tool.execute(params).await },
-    );
+    // Register file_edit tool
+    #[cfg(feature = "file-edit")]
+    {
+        let file_edit_tool = journaled(tools::file_edit::FileEdit, &journal);
+        registry.register_tool_with_result(&file_edit_tool);
+        dispatcher.register("file_edit", move |params: tools::file_edit::Params| {
+            let file_edit_tool = file_edit_tool.clone();
+            async move { file_edit_tool.execute(params).await }
+        });
+    }
 
-    // Register file_diff tool
-    let file_diff_tool = tools::file_diff::FileDiff;
-    registry.register::<tools::file_diff::Params>("file_diff", "Compare two files");
-    dispatcher.register(
-        "file_diff",
-        move |params: tools::file_diff::Params| async move { file_diff_tool.execute(params).await },
-    );
+    // Register text_insert tool
+    #[cfg(feature = "text-insert")]
+    {
+        let text_insert_tool = journaled(tools::text_insert::TextInsert, &journal);
+        registry.register_tool_with_result(&text_insert_tool);
+        dispatcher.register("text_insert", move |params: tools::text_insert::Params| {
+            let text_insert_tool = text_insert_tool.clone();
+            async move { text_insert_tool.execute(params).await }
+        });
+    }
 
-    // Register shell tool
-    let shell_tool = tools::shell::Shell;
-    registry.register::<tools::shell::Params>("shell", "Execute a shell command");
-    dispatcher.register("shell", move |params: tools::shell::Params| async move {
-        shell_tool.execute(params).await
-    });
+    // Register file_edit_lines tool
+    #[cfg(feature = "file-edit-lines")]
+    {
+        let file_edit_lines_tool = journaled(tools::file_edit_lines::FileEditLines, &journal);
+        registry.register_tool_with_result(&file_edit_lines_tool);
+        dispatcher.register(
+            "file_edit_lines",
+            move |params: tools::file_edit_lines::Params| {
+                let file_edit_lines_tool = file_edit_lines_tool.clone();
+                async move { file_edit_lines_tool.execute(params).await }
+            },
+        );
+    }
 
-    dispatcher
-}
+    // Register network_check tool
+    #[cfg(feature = "network-check")]
+    {
+        let network_check_tool = tools::network_check::NetworkCheck;
+        registry.register_tool_with_result(&network_check_tool);
+        dispatcher.register(
+            "network_check",
+            move |params: tools::network_check::Params| async move {
+                network_check_tool.execute(params).await
+            },
+        );
+    }
 
-/// Factory function to create a dispatcher with a custom transformer
-pub fn create_dispatcher_with_transformer(
-    transformer: transform::FormatTransformer,
-) -> jsonrpc::Dispatcher {
-    use tools::Tool;
+    // Register patch_create tool
+    #[cfg(feature = "patch-create")]
+    {
+        let patch_create_tool = tools::patch_create::PatchCreate;
+        registry.register_tool_with_result(&patch_create_tool);
+        dispatcher.register(
+            "patch_create",
+            move |params: tools::patch_create::Params| async move {
+                patch_create_tool.execute(params).await
+            },
+        );
+    }
 
-    let mut dispatcher = jsonrpc::Dispatcher::with_transformer(Arc::new(transformer));
+    // Register code_outline tool
+    #[cfg(feature = "code-outline")]
+    {
+        let code_outline_tool = tools::code_outline::CodeOutline;
+        registry.register_tool_with_result(&code_outline_tool);
+        dispatcher.register(
+            "code_outline",
+            move |params: tools::code_outline::Params| async move {
+                code_outline_tool.execute(params).await
+            },
+        );
+    }
 
-    // Register directory_list tool
-    let dir_list_tool = tools::directory_list::DirectoryList;
-    dispatcher.register(
-        "directory_list",
-        move |params: tools::directory_list::Params| async move {
-            dir_list_tool.execute(params).await
-        },
-    );
+    // Register directory_make tool
+    #[cfg(feature = "directory-make")]
+    {
+        let dir_make_tool = tools::directory_make::DirectoryMake;
+        registry.register_tool_with_result(&dir_make_tool);
+        dispatcher.register(
+            "directory_make",
+            move |params: tools::directory_make::Params| async move {
+                dir_make_tool.execute(params).await
+            },
+        );
+    }
 
-    // Register file_read tool
-    let file_read_tool = tools::file_read::FileRead;
-    dispatcher.register(
-        "file_read",
-        move |params: tools::file_read::Params| async move { file_read_tool.execute(params).await },
-    );
+    // Register directory_sync tool
+    #[cfg(feature = "directory-sync")]
+    {
+        let dir_sync_tool = journaled(tools::directory_sync::DirectorySync, &journal);
+        registry.register_tool_with_result(&dir_sync_tool);
+        dispatcher.register(
+            "directory_sync",
+            move |params: tools::directory_sync::Params| {
+                let dir_sync_tool = dir_sync_tool.clone();
+                async move { dir_sync_tool.execute(params).await }
+            },
+        );
+    }
 
-    // Register file_write tool
-    let file_write_tool = tools::file_write::FileWrite;
-    dispatcher.register(
-        "file_write",
-        move |params: tools::file_write::Params| async move {
-            file_write_tool.execute(params).await
-        },
-    );
+    // Register file_compare tool
+    #[cfg(feature = "file-compare")]
+    {
+        let file_compare_tool = tools::file_compare::FileCompare;
+        registry.register_tool_with_result(&file_compare_tool);
+        dispatcher.register(
+            "file_compare",
+            move |params: tools::file_compare::Params| async move {
+                file_compare_tool.execute(params).await
+            },
+        );
+    }
 
-    // Register file_patch tool
-    let file_patch_tool = tools::file_patch::FilePatch;
-    dispatcher.register(
-        "file_patch",
-        move |params: tools::file_patch::Params| async move {
-            file_patch_tool.execute(params).await
-        },
-    );
+    // Register path_info tool
+    #[cfg(feature = "path-info")]
+    {
+        let path_info_tool = tools::path_info::PathInfo;
+        registry.register_tool_with_result(&path_info_tool);
+        dispatcher.register("path_info", move |params: tools::path_info::Params| async move {
+            path_info_tool.execute(params).await
+        });
+    }
 
-    // Register directory_make tool
-    let dir_make_tool = tools::directory_make::DirectoryMake;
-    dispatcher.register(
-        "directory_make",
-        move |params: tools::directory_make::Params| async move {
-            dir_make_tool.execute(params).await
-        },
-    );
+    // Register recent_files tool
+    #[cfg(feature = "recent-files")]
+    {
+        let recent_files_tool = tools::recent_files::RecentFiles;
+        registry.register_tool_with_result(&recent_files_tool);
+        dispatcher.register(
+            "recent_files",
+            move |params: tools::recent_files::Params| async move {
+                recent_files_tool.execute(params).await
+            },
+        );
+    }
+
+    // Register file_metadata_set tool
+    #[cfg(feature = "file-metadata-set")]
+    {
+        let metadata_set_tool = tools::file_metadata_set::FileMetadataSet;
+        registry.register_tool_with_result(&metadata_set_tool);
+        dispatcher.register(
+            "file_metadata_set",
+            move |params: tools::file_metadata_set::Params| async move {
+                metadata_set_tool.execute(params).await
+            },
+        );
+    }
 
     // Register file_move tool
-    let file_move_tool = tools::file_move::FileMove;
-    dispatcher.register(
-        "file_move",
-        move |params: tools::file_move::Params| async move { file_move_tool.execute(params).await },
-    );
+    #[cfg(feature = "file-move")]
+    {
+        let file_move_tool = journaled(tools::file_move::FileMove, &journal);
+        registry.register_tool_with_result(&file_move_tool);
+        dispatcher.register("file_move", move |params: tools::file_move::Params| {
+            let file_move_tool = file_move_tool.clone();
+            async move { file_move_tool.execute(params).await }
+        });
+    }
 
     // Register file_find tool
-    let file_find_tool = tools::file_find::FileFind;
-    dispatcher.register(
-        "file_find",
-        move |params: tools::file_find::Params| async move { file_find_tool.execute(params).await },
-    );
+    #[cfg(feature = "file-find")]
+    {
+        let file_find_tool = tools::file_find::FileFind;
+        registry.register_tool_with_result(&file_find_tool);
+        dispatcher.register(
+            "file_find",
+            move |params: tools::file_find::Params| async move { file_find_tool.execute(params).await },
+        );
+    }
+
+    // Register fuzzy_find tool
+    #[cfg(feature = "fuzzy-find")]
+    {
+        let fuzzy_find_tool = tools::fuzzy_find::FuzzyFind;
+        registry.register_tool_with_result(&fuzzy_find_tool);
+        dispatcher.register(
+            "fuzzy_find",
+            move |params: tools::fuzzy_find::Params| async move { fuzzy_find_tool.execute(params).await },
+        );
+    }
+
+    // Register generate_id tool
+    #[cfg(feature = "generate-id")]
+    {
+        let generate_id_tool = tools::generate_id::GenerateId;
+        registry.register_tool_with_result(&generate_id_tool);
+        dispatcher.register(
+            "generate_id",
+            move |params: tools::generate_id::Params| async move { generate_id_tool.execute(params).await },
+        );
+    }
 
     // Register file_grep tool
-    let file_grep_tool = tools::file_grep::FileGrep;
-    dispatcher.register(
-        "file_grep",
-        move |params: tools::file_grep::Params| async move { file_grep_tool.execute(params).await },
-    );
+    #[cfg(feature = "file-grep")]
+    {
+        let file_grep_tool = tools::file_grep::FileGrep;
+        registry.register_tool_with_result(&file_grep_tool);
+        dispatcher.register(
+            "file_grep",
+            move |params: tools::file_grep::Params| async move { file_grep_tool.execute(params).await },
+        );
+    }
 
     // Register file_diff tool
-    let file_diff_tool = tools::file_diff::FileDiff;
-    dispatcher.register(
-        "file_diff",
-        move |params: tools::file_diff::Params| async move { file_diff_tool.execute(params).await },
-    );
+    #[cfg(feature = "file-diff")]
+    {
+        let file_diff_tool = tools::file_diff::FileDiff;
+        registry.register_tool_with_result(&file_diff_tool);
+        dispatcher.register(
+            "file_diff",
+            move |params: tools::file_diff::Params| async move { file_diff_tool.execute(params).await },
+        );
+    }
 
     // Register shell tool
-    let shell_tool = tools::shell::Shell;
-    dispatcher.register("shell", move |params: tools::shell::Params| async move {
-        shell_tool.execute(params).await
-    });
+    #[cfg(feature = "shell")]
+    let shell_tool = shell.unwrap_or_default();
+    #[cfg(not(feature = "shell"))]
+    let _ = shell;
+    #[cfg(feature = "shell")]
+    {
+        registry.register_tool_with_result(&shell_tool);
+        let shell_handler = shell_tool.clone();
+        dispatcher.register("shell", move |params: tools::shell::Params| {
+            let shell_tool = shell_handler.clone();
+            async move { shell_tool.execute(params).await }
+        });
+    }
 
-    dispatcher
+    // Register background job tools, sharing one set of jobs
+    #[cfg(feature = "job")]
+    {
+        let jobs = tools::job::JobManager::new();
+
+        let shell_spawn_tool = tools::job::ShellSpawn::new(shell_tool.clone(), jobs.clone());
+        registry.register_tool_with_result(&shell_spawn_tool);
+        dispatcher.register("shell_spawn", move |params: tools::shell::Params| {
+            let shell_spawn_tool = shell_spawn_tool.clone();
+            async move { shell_spawn_tool.execute(params).await }
+        });
+
+        let job_status_tool = tools::job::JobStatus::new(jobs.clone());
+        registry.register_tool_with_result(&job_status_tool);
+        dispatcher.register("job_status", move |params: tools::job::StatusParams| {
+            let job_status_tool = job_status_tool.clone();
+            async move { job_status_tool.execute(params).await }
+        });
+
+        let job_output_tool = tools::job::JobOutput::new(jobs.clone());
+        registry.register_tool_with_result(&job_output_tool);
+        dispatcher.register("job_output", move |params: tools::job::OutputParams| {
+            let job_output_tool = job_output_tool.clone();
+            async move { job_output_tool.execute(params).await }
+        });
+
+        let shutdown_jobs = jobs.clone();
+        dispatcher.on_shutdown(move || {
+            shutdown_jobs.kill_all();
+        });
+
+        let job_kill_tool = tools::job::JobKill::new(jobs);
+        registry.register_tool_with_result(&job_kill_tool);
+        dispatcher.register("job_kill", move |params: tools::job::KillParams| {
+            let job_kill_tool = job_kill_tool.clone();
+            async move { job_kill_tool.execute(params).await }
+        });
+    }
+
+    // Register undo tools when changes are being journaled
+    #[cfg(feature = "undo")]
+    if let Some(journal) = journal {
+        let undo_list_tool = tools::undo::UndoList::new(journal.clone());
+        registry.register_tool_with_result(&undo_list_tool);
+        dispatcher.register("undo_list", move |params: tools::undo::ListParams| {
+            let undo_list_tool = undo_list_tool.clone();
+            async move { undo_list_tool.execute(params).await }
+        });
+
+        let undo_apply_tool = tools::undo::UndoApply::new(journal);
+        registry.register_tool_with_result(&undo_apply_tool);
+        dispatcher.register("undo_apply", move |params: tools::undo::ApplyParams| {
+            let undo_apply_tool = undo_apply_tool.clone();
+            async move { undo_apply_tool.execute(params).await }
+        });
+    }
+    #[cfg(not(feature = "undo"))]
+    let _ = journal;
+
+    // The cancel and pipeline methods are built into the dispatcher; only their schemas are registered
+    registry.register::<cancel::Params>(cancel::METHOD, "Cancel a running request by its JSONRPC id");
+    registry.register::<pipeline::Params>(pipeline::METHOD, "Run several tool calls in order, passing results along with ${id.path} references");
+
+    dispatcher.with_tool_list(registry)
+}
+
+/// Factory function to create a dispatcher with a custom transformer
+pub fn create_dispatcher_with_transformer(
+    transformer: transform::FormatTransformer,
+) -> jsonrpc::Dispatcher {
+    let mut registry = schema::ToolSchemaRegistry::new();
+    create_dispatcher_with_transformer_and_registry(transformer, &mut registry, None, None)
 }
@@ -0,0 +1,205 @@
+//! Filesystem limits
+//!
+//! One request against a huge tree, such as a search of `/`, shouldn't keep a
+//! server busy for minutes. [`FsLimits`] caps how many files the tools of a
+//! dispatcher have open at once, and how many entries and how much time a
+//! single directory walk may take. Walks over a limit fail fast: `file_find`,
+//! `file_grep`, `fuzzy_find`, `recent_files`, `directory_sync` and directory
+//! `file_diff` report a `too_large` error once a walk has visited too many
+//! entries, and a `timeout` error once it has run too long, naming the limit.
+//!
+//! Dispatchers apply [`FsLimits::default`] unless given others with
+//! [`Dispatcher::with_fs_limits`].
+//!
+//! [`Dispatcher::with_fs_limits`]: crate::jsonrpc::Dispatcher::with_fs_limits
+
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits on the filesystem work of a dispatcher's tools; `None` means
+/// unlimited
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FsLimits {
+    /// Files the tools may have open at once; further opens wait their turn
+    pub max_open_files: usize,
+    /// Entries one directory walk may visit
+    pub max_walk_entries: Option<usize>,
+    /// Time one directory walk may take
+    pub max_walk_duration: Option<Duration>,
+}
+
+impl Default for FsLimits {
+    fn default() -> Self {
+        Self {
+            max_open_files: 64,
+            max_walk_entries: Some(1_000_000),
+            max_walk_duration: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// The limits of a dispatcher, with the open files counted against them
+#[derive(Debug)]
+pub(crate) struct Limiter {
+    limits: FsLimits,
+    open_files: Arc<Semaphore>,
+}
+
+impl Limiter {
+    pub(crate) fn new(limits: FsLimits) -> Self {
+        Self {
+            open_files: Arc::new(Semaphore::new(limits.max_open_files.max(1))),
+            limits,
+        }
+    }
+
+    pub(crate) fn limits(&self) -> FsLimits {
+        self.limits
+    }
+}
+
+/// Limits of calls made outside a dispatcher with its own
+static DEFAULT: LazyLock<Arc<Limiter>> =
+    LazyLock::new(|| Arc::new(Limiter::new(FsLimits::default())));
+
+tokio::task_local! {
+    static CURRENT: Arc<Limiter>;
+}
+
+/// Run a future under `limiter`
+pub(crate) fn scope<F: Future>(limiter: Arc<Limiter>, future: F) -> impl Future<Output = F::Output> {
+    CURRENT.scope(limiter, future)
+}
+
+fn current() -> Arc<Limiter> {
+    CURRENT
+        .try_with(Arc::clone)
+        .unwrap_or_else(|_| DEFAULT.clone())
+}
+
+/// Wait until another file may be opened; the file counts as open until the
+/// permit is dropped
+pub(crate) async fn open_file() -> OwnedSemaphorePermit {
+    current()
+        .open_files
+        .clone()
+        .acquire_owned()
+        .await
+        .expect("the open file semaphore is never closed")
+}
+
+/// The entries and time of one directory walk, counted against the limits.
+/// Create it on the task making the call; it can then move to a blocking
+/// thread with the walk.
+#[derive(Debug)]
+pub(crate) struct WalkBudget {
+    limits: FsLimits,
+    root: String,
+    started: Instant,
+    entries: usize,
+}
+
+impl WalkBudget {
+    pub(crate) fn new(root: &Path) -> Self {
+        Self {
+            limits: current().limits,
+            root: crate::paths::to_string(root),
+            started: Instant::now(),
+            entries: 0,
+        }
+    }
+
+    /// Count an entry, failing if the walk is over a limit
+    pub(crate) fn visit(&mut self) -> io::Result<()> {
+        self.entries += 1;
+        if let Some(max) = self.limits.max_walk_entries
+            && self.entries > max
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!(
+                    "Walk of {} stopped after {} entries (max_walk_entries); search a narrower directory",
+                    self.root, max
+                ),
+            ));
+        }
+        if let Some(max) = self.limits.max_walk_duration
+            && self.started.elapsed() >= max
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "Walk of {} stopped after {} ms (max_walk_duration); search a narrower directory",
+                    self.root,
+                    max.as_millis()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{Filesystem, MemoryFs, WalkOptions};
+
+    #[tokio::test]
+    async fn test_walk_limits() -> crate::Result<()> {
+        let fs = MemoryFs::new()
+            .with_file("/src/a.rs", "")
+            .with_file("/src/b.rs", "")
+            .with_file("/src/c.rs", "");
+        let options = WalkOptions {
+            max_depth: usize::MAX,
+            follow_links: false,
+        };
+        assert_eq!(fs.walk(Path::new("/"), options).await?.len(), 4);
+
+        let limited = |limits| Arc::new(Limiter::new(limits));
+        let err = scope(
+            limited(FsLimits {
+                max_walk_entries: Some(2),
+                ..FsLimits::default()
+            }),
+            fs.walk(Path::new("/"), options),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::FileTooLarge);
+        assert!(err.to_string().contains("max_walk_entries"));
+        assert_eq!(crate::Error::Io(err).code(), "too_large");
+
+        let err = scope(
+            limited(FsLimits {
+                max_walk_duration: Some(Duration::ZERO),
+                ..FsLimits::default()
+            }),
+            fs.walk(Path::new("/"), options),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_open_files() {
+        let limiter = Arc::new(Limiter::new(FsLimits {
+            max_open_files: 1,
+            ..FsLimits::default()
+        }));
+        scope(limiter.clone(), async {
+            let permit = open_file().await;
+            assert_eq!(limiter.open_files.available_permits(), 0);
+            drop(permit);
+            assert_eq!(limiter.open_files.available_permits(), 1);
+        })
+        .await;
+    }
+}
@@ -2,6 +2,25 @@
 //!
 //! This module provides logging initialization and convenient re-exports
 //! of the log crate macros for consistent logging across the library.
+//!
+//! Every dispatched call gets a correlation id. The dispatcher logs each call
+//! under the `gamecode_tools::call` target with `correlation_id`, `method`,
+//! `duration_ms` and `outcome` key-values, and records logged while a tool runs
+//! carry the same `correlation_id`, so they can be matched with the response
+//! (see [`Dispatcher::with_correlation_ids`](crate::jsonrpc::Dispatcher::with_correlation_ids)).
+//!
+//! A [`RequestLogger`], set with
+//! [`Dispatcher::with_request_logging`](crate::jsonrpc::Dispatcher::with_request_logging),
+//! also logs the full request and response of each call at debug level under
+//! the `gamecode_tools::traffic` target, with file contents, environment values
+//! and other bulky or sensitive fields redacted.
+
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::Result;
 
 /// Initialize logging with the specified level
 pub fn init(_level: log::LevelFilter) {
@@ -12,3 +31,274 @@ pub fn init(_level: log::LevelFilter) {
 
 // Re-export the log crate and its macros
 pub use log::{LevelFilter, debug, error, info, trace, warn};
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+/// Correlation id of the call running on this task, if any
+pub fn correlation_id() -> Option<String> {
+    CORRELATION_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Create a new correlation id
+pub(crate) fn new_correlation_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+/// Run a future with `id` as the correlation id
+pub(crate) fn scope<F: Future>(id: String, future: F) -> impl Future<Output = F::Output> {
+    CORRELATION_ID.scope(id, future)
+}
+
+/// Log a record tagged with the correlation id of the current call, as the
+/// `correlation_id` key-value and as a prefix for loggers that drop those
+#[cfg(any(
+    feature = "directory-make",
+    feature = "file-grep",
+    feature = "file-patch",
+    feature = "file-write",
+))]
+macro_rules! call_log {
+    ($level:expr, $($arg:tt)+) => {{
+        let correlation_id = $crate::logging::correlation_id();
+        let correlation_id = correlation_id.as_deref().unwrap_or("-");
+        log::log!(
+            $level,
+            correlation_id = correlation_id;
+            "[{}] {}",
+            correlation_id,
+            format_args!($($arg)+)
+        );
+    }};
+}
+#[cfg(any(
+    feature = "directory-make",
+    feature = "file-grep",
+    feature = "file-patch",
+    feature = "file-write",
+))]
+pub(crate) use call_log;
+
+/// Log the outcome of a dispatched call
+pub(crate) fn log_call<T>(
+    correlation_id: &str,
+    method: &str,
+    elapsed: Duration,
+    result: &Result<T>,
+) {
+    let duration_ms = elapsed.as_millis() as u64;
+    match result {
+        Ok(_) => log::info!(
+            target: "gamecode_tools::call",
+            correlation_id = correlation_id,
+            method = method,
+            duration_ms = duration_ms,
+            outcome = "ok";
+            "[{}] {} succeeded in {} ms",
+            correlation_id,
+            method,
+            duration_ms
+        ),
+        Err(e) => {
+            let error = e.to_string();
+            log::warn!(
+                target: "gamecode_tools::call",
+                correlation_id = correlation_id,
+                method = method,
+                duration_ms = duration_ms,
+                outcome = "error",
+                error = error.as_str();
+                "[{}] {} failed in {} ms: {}",
+                correlation_id,
+                method,
+                duration_ms,
+                error
+            )
+        }
+    }
+}
+
+/// Fields whose values [`RequestLogger`] redacts unless told otherwise
+pub const DEFAULT_REDACTED_FIELDS: &[&str] = &[
+    "content",
+    "content1",
+    "content2",
+    "old_string",
+    "new_string",
+    "patch",
+    "env",
+    "stdin",
+    "stdout",
+    "stderr",
+];
+
+/// Logs the request and response of each dispatched call at debug level,
+/// redacting the values of the named fields wherever they appear
+#[derive(Debug, Clone)]
+pub struct RequestLogger {
+    redacted: BTreeSet<String>,
+}
+
+impl Default for RequestLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestLogger {
+    /// Redact the [`DEFAULT_REDACTED_FIELDS`]
+    pub fn new() -> Self {
+        Self {
+            redacted: DEFAULT_REDACTED_FIELDS
+                .iter()
+                .map(|field| field.to_string())
+                .collect(),
+        }
+    }
+
+    /// Redact nothing
+    pub fn unredacted() -> Self {
+        Self {
+            redacted: BTreeSet::new(),
+        }
+    }
+
+    /// Also redact the values of `field`
+    pub fn redact(mut self, field: impl Into<String>) -> Self {
+        self.redacted.insert(field.into());
+        self
+    }
+
+    /// Log the values of `field` after all
+    pub fn reveal(mut self, field: &str) -> Self {
+        self.redacted.remove(field);
+        self
+    }
+
+    /// A copy of `value` with the redacted fields hidden. Strings become a
+    /// note of their length; objects keep their keys, so it still shows
+    /// which environment variables were set.
+    pub fn redacted(&self, value: &Value) -> Value {
+        match value {
+            Value::Object(map) => map
+                .iter()
+                .map(|(key, value)| {
+                    let value = if self.redacted.contains(key) {
+                        hide(value)
+                    } else {
+                        self.redacted(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+            Value::Array(items) => items.iter().map(|item| self.redacted(item)).collect(),
+            _ => value.clone(),
+        }
+    }
+
+    /// Log a request as received
+    pub(crate) fn log_request(&self, correlation_id: &str, request: &str) {
+        if !log::log_enabled!(target: "gamecode_tools::traffic", log::Level::Debug) {
+            return;
+        }
+        let request = match serde_json::from_str::<Value>(request) {
+            Ok(request) => self.redacted(&request).to_string(),
+            Err(_) => format!("<{} bytes that aren't JSON>", request.len()),
+        };
+        log::debug!(
+            target: "gamecode_tools::traffic",
+            correlation_id = correlation_id;
+            "[{}] request: {}",
+            correlation_id,
+            request
+        );
+    }
+
+    /// Log a response as sent
+    pub(crate) fn log_response<T: serde::Serialize>(&self, correlation_id: &str, response: &T) {
+        if !log::log_enabled!(target: "gamecode_tools::traffic", log::Level::Debug) {
+            return;
+        }
+        let response = match serde_json::to_value(response) {
+            Ok(response) => self.redacted(&response).to_string(),
+            Err(e) => format!("<unserializable: {}>", e),
+        };
+        log::debug!(
+            target: "gamecode_tools::traffic",
+            correlation_id = correlation_id;
+            "[{}] response: {}",
+            correlation_id,
+            response
+        );
+    }
+}
+
+/// Hide a redacted value, keeping the keys of objects
+fn hide(value: &Value) -> Value {
+    match value {
+        Value::Null => Value::Null,
+        Value::String(s) => Value::String(format!("<redacted {} bytes>", s.len())),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| (key.clone(), hide(value)))
+            .collect(),
+        Value::Array(items) => items.iter().map(hide).collect(),
+        _ => Value::String("<redacted>".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_correlation_id() -> Result<()> {
+        assert_eq!(correlation_id(), None);
+        let id = new_correlation_id();
+        assert_eq!(id.len(), 16);
+        assert_eq!(
+            scope(id.clone(), async { correlation_id() }).await,
+            Some(id)
+        );
+
+        // Responses only carry the id when asked to
+        let request = r#"{"jsonrpc": "2.0", "method": "session_info", "params": {}, "id": 1}"#;
+        let dispatcher = crate::create_default_dispatcher();
+        let response: Value = serde_json::from_str(&dispatcher.dispatch(request).await?)?;
+        assert!(response.get("correlation_id").is_none());
+
+        let dispatcher = crate::create_default_dispatcher().with_correlation_ids();
+        let first: Value = serde_json::from_str(&dispatcher.dispatch(request).await?)?;
+        let second: Value = serde_json::from_str(&dispatcher.dispatch(request).await?)?;
+        assert_eq!(first["error"]["code"], -32601);
+        assert!(first["correlation_id"].is_string());
+        assert_ne!(first["correlation_id"], second["correlation_id"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_redaction() {
+        let request = serde_json::json!({
+            "method": "shell",
+            "params": {"command": "deploy", "env": {"TOKEN": "s3cret"}, "stdin": null},
+            "result": {"files": [{"path": "a.txt", "content": "hello"}]},
+        });
+        let logger = RequestLogger::new();
+        let redacted = logger.redacted(&request);
+        assert_eq!(redacted["params"]["command"], "deploy");
+        assert_eq!(redacted["params"]["env"]["TOKEN"], "<redacted 6 bytes>");
+        assert_eq!(redacted["params"]["stdin"], Value::Null);
+        assert_eq!(redacted["result"]["files"][0]["path"], "a.txt");
+        assert_eq!(
+            redacted["result"]["files"][0]["content"],
+            "<redacted 5 bytes>"
+        );
+
+        let redacted = logger.redact("command").reveal("env").redacted(&request);
+        assert_eq!(redacted["params"]["command"], "<redacted 6 bytes>");
+        assert_eq!(redacted["params"]["env"]["TOKEN"], "s3cret");
+        assert_eq!(RequestLogger::unredacted().redacted(&request), request);
+    }
+}
@@ -0,0 +1,319 @@
+//! Path handling that behaves the same on every platform
+//!
+//! On Windows, `canonicalize` returns verbatim paths such as
+//! `\\?\C:\src\lib.rs`, which callers don't expect back and glob patterns
+//! don't match, while paths of `MAX_PATH` (260) characters or more only work
+//! in that verbatim form. The [`RealFs`](crate::vfs::RealFs) filesystem and the
+//! tools go through these helpers: [`long`] before handing a path to the OS,
+//! [`canonicalize`] and [`simplify`] for paths coming back from it, and
+//! [`to_string`] for paths reported to callers. On other platforms only
+//! [`to_string`] and [`canonicalize`] do anything, and those just what `std`
+//! does.
+//!
+//! Tools check the paths they are given with the crate-internal
+//! `require_file`, `require_dir` and `canonical_dir`, so a missing path is
+//! `not_found`, one of the wrong type `invalid_param` and an unreadable one
+//! `permission_denied` whichever tool was called. `check_within` keeps a
+//! resolved path below a root, failing with `outside_sandbox`.
+
+use std::borrow::Cow;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(any(
+    test,
+    feature = "code-outline",
+    feature = "file-compare",
+    feature = "file-diff",
+    feature = "file-edit",
+    feature = "file-find",
+    feature = "file-grep",
+    feature = "file-read",
+    feature = "fuzzy-find",
+    feature = "recent-files",
+))]
+use crate::vfs::{Filesystem, Metadata};
+#[cfg(any(
+    test,
+    feature = "code-outline",
+    feature = "file-compare",
+    feature = "file-diff",
+    feature = "file-edit",
+    feature = "file-find",
+    feature = "file-grep",
+    feature = "file-read",
+    feature = "fuzzy-find",
+    feature = "recent-files",
+    feature = "shell",
+    feature = "workspace-snapshot",
+))]
+use crate::{Error, Result};
+
+/// Length from which Windows needs the verbatim form
+const MAX_PATH: usize = 260;
+
+const VERBATIM: &str = r"\\?\";
+const VERBATIM_UNC: &str = r"\\?\UNC\";
+
+/// `path` without the verbatim prefix, if it has one with a plain equivalent
+pub fn simplify(path: &Path) -> PathBuf {
+    if !cfg!(windows) {
+        return path.to_path_buf();
+    }
+    path.to_str()
+        .and_then(strip_verbatim)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// `path` in a form the OS accepts at any length. On Windows, paths of
+/// `MAX_PATH` characters or more are made absolute and verbatim.
+pub fn long(path: &Path) -> Cow<'_, Path> {
+    if !cfg!(windows) {
+        return Cow::Borrowed(path);
+    }
+    let Ok(absolute) = std::path::absolute(path) else {
+        return Cow::Borrowed(path);
+    };
+    match absolute.to_str().and_then(extend) {
+        Some(extended) => Cow::Owned(PathBuf::from(extended)),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// Canonicalize `path`, of any length, without the verbatim prefix
+pub fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    std::fs::canonicalize(long(path)).map(|path| simplify(&path))
+}
+
+/// `path` as reported to callers: without the verbatim prefix and, on
+/// Windows, with `/` separators so it reads the same as on other platforms
+pub fn to_string(path: &Path) -> String {
+    let path = simplify(path);
+    let path = path.to_string_lossy();
+    if cfg!(windows) {
+        path.replace('\\', "/")
+    } else {
+        path.into_owned()
+    }
+}
+
+/// Metadata of `path`, which a tool needs to be a regular file
+#[cfg(any(
+    test,
+    feature = "code-outline",
+    feature = "file-compare",
+    feature = "file-diff",
+    feature = "file-edit",
+    feature = "file-grep",
+    feature = "file-read",
+))]
+pub(crate) async fn require_file(fs: &dyn Filesystem, path: &Path) -> Result<Metadata> {
+    let metadata = metadata(fs, path, "File").await?;
+    if !metadata.is_file() {
+        return Err(Error::InvalidParam(format!(
+            "Path is not a file: {}",
+            path.display()
+        )));
+    }
+    Ok(metadata)
+}
+
+/// Metadata of `path`, which a tool needs to be a directory
+#[cfg(any(
+    test,
+    feature = "file-find",
+    feature = "file-grep",
+    feature = "fuzzy-find",
+    feature = "recent-files",
+))]
+pub(crate) async fn require_dir(fs: &dyn Filesystem, path: &Path) -> Result<Metadata> {
+    let metadata = metadata(fs, path, "Directory").await?;
+    if !metadata.is_dir() {
+        return Err(Error::InvalidParam(format!(
+            "Path is not a directory: {}",
+            path.display()
+        )));
+    }
+    Ok(metadata)
+}
+
+/// The canonical form of `path`, which a tool needs to be a directory
+#[cfg(any(
+    test,
+    feature = "file-find",
+    feature = "file-grep",
+    feature = "fuzzy-find",
+    feature = "recent-files",
+))]
+pub(crate) async fn canonical_dir(fs: &dyn Filesystem, path: &Path) -> Result<PathBuf> {
+    require_dir(fs, path).await?;
+    Ok(fs.canonicalize(path).await?)
+}
+
+/// Fail unless `path` is `root` or below it; both should be canonical
+#[cfg(any(test, feature = "shell", feature = "workspace-snapshot"))]
+pub(crate) fn check_within(path: &Path, root: &Path) -> Result<()> {
+    if path.starts_with(root) {
+        Ok(())
+    } else {
+        Err(Error::OutsideSandbox(format!(
+            "{} is outside {}",
+            to_string(path),
+            to_string(root)
+        )))
+    }
+}
+
+/// Metadata of `path`, with the errors of a missing or unreadable `kind`
+#[cfg(any(
+    test,
+    feature = "code-outline",
+    feature = "file-compare",
+    feature = "file-diff",
+    feature = "file-edit",
+    feature = "file-find",
+    feature = "file-grep",
+    feature = "file-read",
+    feature = "fuzzy-find",
+    feature = "recent-files",
+))]
+async fn metadata(fs: &dyn Filesystem, path: &Path, kind: &str) -> Result<Metadata> {
+    fs.metadata(path).await.map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => {
+            Error::NotFound(format!("{} not found: {}", kind, path.display()))
+        }
+        io::ErrorKind::PermissionDenied => Error::PermissionDenied(path.display().to_string()),
+        _ => Error::Io(e),
+    })
+}
+
+/// `\\?\C:\dir` as `C:\dir` and `\\?\UNC\server\share` as `\\server\share`
+fn strip_verbatim(path: &str) -> Option<String> {
+    if let Some(rest) = path.strip_prefix(VERBATIM_UNC) {
+        return Some(format!(r"\\{}", rest));
+    }
+    let rest = path.strip_prefix(VERBATIM)?;
+    // Other verbatim paths, such as `\\?\Volume{...}`, have no plain form
+    is_drive(rest).then(|| rest.to_string())
+}
+
+/// The verbatim form of an absolute path that is too long for the plain one.
+/// Verbatim paths are taken literally, so `/` becomes `\` and `.` and `..`
+/// are resolved.
+fn extend(path: &str) -> Option<String> {
+    if path.len() < MAX_PATH || path.starts_with(VERBATIM) {
+        return None;
+    }
+    let (prefix, rest) =
+        if let Some(rest) = path.strip_prefix(r"\\").or_else(|| path.strip_prefix("//")) {
+            (VERBATIM_UNC, rest)
+        } else if is_drive(path) {
+            (VERBATIM, path)
+        } else {
+            return None;
+        };
+
+    let mut parts: Vec<&str> = Vec::new();
+    for part in rest.split(['\\', '/']) {
+        match part {
+            "" | "." => {}
+            // Never pop the drive or the server and share
+            ".." if parts.len() > usize::from(prefix == VERBATIM_UNC) + 1 => {
+                parts.pop();
+            }
+            ".." => {}
+            part => parts.push(part),
+        }
+    }
+    Some(format!("{}{}", prefix, parts.join(r"\")))
+}
+
+/// Whether `path` starts with a drive letter, as in `C:\`
+fn is_drive(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_verbatim() {
+        assert_eq!(
+            strip_verbatim(r"\\?\C:\src\lib.rs").as_deref(),
+            Some(r"C:\src\lib.rs")
+        );
+        assert_eq!(
+            strip_verbatim(r"\\?\UNC\server\share\file").as_deref(),
+            Some(r"\\server\share\file")
+        );
+        assert_eq!(strip_verbatim(r"\\?\Volume{1234}\file"), None);
+        assert_eq!(strip_verbatim(r"C:\src"), None);
+        assert_eq!(strip_verbatim("/home/user"), None);
+    }
+
+    #[test]
+    fn test_extend() {
+        let long_name = "x".repeat(MAX_PATH);
+        assert_eq!(extend(r"C:\short"), None);
+        assert_eq!(
+            extend(&format!(r"C:\dir/./other\..\{}", long_name)),
+            Some(format!(r"\\?\C:\dir\{}", long_name))
+        );
+        assert_eq!(
+            extend(&format!(r"\\server\share\..\{}", long_name)),
+            Some(format!(r"\\?\UNC\server\share\{}", long_name))
+        );
+        let verbatim = format!(r"\\?\C:\{}", long_name);
+        assert_eq!(extend(&verbatim), None);
+        assert_eq!(extend(&format!("/home/{}", long_name)), None);
+    }
+
+    #[tokio::test]
+    async fn test_require() {
+        use crate::vfs::MemoryFs;
+
+        let fs = MemoryFs::new().with_file("/work/src/lib.rs", "");
+        assert!(
+            require_file(&fs, Path::new("/work/src/lib.rs"))
+                .await
+                .is_ok()
+        );
+        assert!(require_dir(&fs, Path::new("/work/src")).await.is_ok());
+        assert_eq!(
+            canonical_dir(&fs, Path::new("/work/./src/../src"))
+                .await
+                .unwrap(),
+            Path::new("/work/src")
+        );
+
+        let err = require_file(&fs, Path::new("/work/src")).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid parameter: Path is not a file: /work/src"
+        );
+        let err = require_dir(&fs, Path::new("/work/src/lib.rs"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "invalid_param");
+        let err = require_file(&fs, Path::new("/work/missing.rs"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "File not found: /work/missing.rs");
+        let err = canonical_dir(&fs, Path::new("/missing")).await.unwrap_err();
+        assert_eq!(err.to_string(), "Directory not found: /missing");
+
+        assert!(check_within(Path::new("/work/src"), Path::new("/work")).is_ok());
+        let err = check_within(Path::new("/etc"), Path::new("/work")).unwrap_err();
+        assert_eq!(err.code(), "outside_sandbox");
+    }
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(to_string(Path::new("dir/file.txt")), "dir/file.txt");
+        let canonical = canonicalize(Path::new(".")).unwrap();
+        assert!(!to_string(&canonical).starts_with(VERBATIM));
+    }
+}
@@ -0,0 +1,301 @@
+//! The `pipeline` method: several tool calls in one request.
+//!
+//! Steps run in order, and stop at the first one that fails. A string in a
+//! step's parameters can refer to the results of earlier steps with
+//! `${id.field[0].other}`, where `id` is the step's `id` (or its index when it
+//! has none). A string that is exactly one reference is replaced by the
+//! referenced value, whatever its type; references inside longer strings are
+//! replaced by their text.
+//!
+//! ```json
+//! {"steps": [
+//!   {"id": "find", "method": "file_find", "params": {"directory": "src", "pattern": "main.rs", "mode": "name"}},
+//!   {"method": "file_read", "params": {"path": "${find.entries[0].path}"}}
+//! ]}
+//! ```
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::{Error, Result};
+
+/// Name of the method
+pub const METHOD: &str = "pipeline";
+
+/// Maximum number of steps in a pipeline
+pub(crate) const MAX_STEPS: usize = 100;
+
+/// Parameters for the pipeline method
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Params {
+    /// Tool calls to run in order
+    pub steps: Vec<Step>,
+}
+
+/// One tool call of a pipeline
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Step {
+    /// Name later steps use to refer to this one's result (defaults to its index)
+    #[serde(default)]
+    pub id: Option<String>,
+
+    /// Tool to call
+    pub method: String,
+
+    /// Parameters of the call, which may contain `${id.path}` references
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// Result of one step
+#[derive(Debug, Serialize)]
+pub struct StepResult {
+    /// Id of the step
+    pub id: String,
+
+    /// Tool that was called
+    pub method: String,
+
+    /// What the tool returned
+    pub result: Value,
+}
+
+/// Output of the pipeline method
+#[derive(Debug, Serialize)]
+pub struct Output {
+    /// Results of every step, in order
+    pub steps: Vec<StepResult>,
+}
+
+/// Results of the steps run so far
+#[derive(Debug, Default)]
+pub(crate) struct Run {
+    results: HashMap<String, Value>,
+    steps: Vec<StepResult>,
+}
+
+impl Run {
+    /// Check a pipeline before running any of it
+    pub(crate) fn validate(params: &Params) -> Result<()> {
+        if params.steps.is_empty() {
+            return Err(Error::InvalidParam("Pipeline has no steps".to_string()));
+        }
+        if params.steps.len() > MAX_STEPS {
+            return Err(Error::TooLarge(format!(
+                "Pipeline has {} steps, at most {} are allowed",
+                params.steps.len(),
+                MAX_STEPS
+            )));
+        }
+
+        let mut seen = Vec::with_capacity(params.steps.len());
+        for (index, step) in params.steps.iter().enumerate() {
+            let id = step_id(step, index);
+            if seen.contains(&id) {
+                return Err(Error::InvalidParam(format!("Duplicate step id: {}", id)));
+            }
+            if step.method == METHOD {
+                return Err(Error::InvalidParam(
+                    "Pipelines cannot be nested".to_string(),
+                ));
+            }
+            seen.push(id);
+        }
+        Ok(())
+    }
+
+    /// Parameters of a step with its references filled in
+    pub(crate) fn render(&self, step: &Step) -> Result<Value> {
+        render(&step.params, &self.results)
+    }
+
+    /// Keep the result of a step
+    pub(crate) fn record(&mut self, step: &Step, index: usize, result: Value) {
+        let id = step_id(step, index);
+        self.results.insert(id.clone(), result.clone());
+        self.steps.push(StepResult {
+            id,
+            method: step.method.clone(),
+            result,
+        });
+    }
+
+    pub(crate) fn finish(self) -> Output {
+        Output { steps: self.steps }
+    }
+}
+
+fn step_id(step: &Step, index: usize) -> String {
+    step.id.clone().unwrap_or_else(|| index.to_string())
+}
+
+/// Say which step an error came from, keeping its kind
+pub(crate) fn step_error(step: &Step, index: usize, err: Error) -> Error {
+    let context = format!(
+        "Pipeline step {} ({}) failed",
+        step_id(step, index),
+        step.method
+    );
+    err.with_context(&context)
+}
+
+/// Replace the references in a value
+fn render(value: &Value, results: &HashMap<String, Value>) -> Result<Value> {
+    match value {
+        Value::String(text) => render_string(text, results),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| render(item, results))
+            .collect::<Result<Vec<_>>>()
+            .map(Value::Array),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, item)| Ok((key.clone(), render(item, results)?)))
+            .collect::<Result<serde_json::Map<_, _>>>()
+            .map(Value::Object),
+        _ => Ok(value.clone()),
+    }
+}
+
+fn render_string(text: &str, results: &HashMap<String, Value>) -> Result<Value> {
+    // A lone reference keeps the type of what it refers to
+    if let Some(expr) = text
+        .strip_prefix("${")
+        .and_then(|rest| rest.strip_suffix('}'))
+        && !expr.contains('}')
+    {
+        return lookup(expr, results).cloned();
+    }
+
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        match lookup(&rest[start + 2..start + len], results)? {
+            Value::String(s) => rendered.push_str(s),
+            other => rendered.push_str(&other.to_string()),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    rendered.push_str(rest);
+
+    Ok(Value::String(rendered))
+}
+
+/// Find the value a reference such as `find.entries[0].path` points to
+fn lookup<'a>(expr: &str, results: &'a HashMap<String, Value>) -> Result<&'a Value> {
+    let not_found = || Error::InvalidParam(format!("Reference ${{{}}} not found", expr));
+
+    let id_end = expr.find(['.', '[']).unwrap_or(expr.len());
+    let mut value = results.get(&expr[..id_end]).ok_or_else(not_found)?;
+
+    let mut rest = &expr[id_end..];
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            value = value.get(&after[..end]).ok_or_else(not_found)?;
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(not_found)?;
+            let index: usize = after[..end].trim().parse().map_err(|_| not_found())?;
+            value = value.get(index).ok_or_else(not_found)?;
+            rest = &after[end + 1..];
+        } else {
+            return Err(not_found());
+        }
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let mut results = HashMap::new();
+        results.insert(
+            "find".to_string(),
+            json!({"entries": [{"path": "/src/main.rs", "size": 42}], "total": 1}),
+        );
+
+        let params = json!({
+            "path": "${find.entries[0].path}",
+            "size": "${find.entries[0].size}",
+            "label": "${find.total} file(s), first is ${find.entries[0].path}",
+            "list": ["${find.total}", "plain"],
+        });
+        assert_eq!(
+            render(&params, &results)?,
+            json!({
+                "path": "/src/main.rs",
+                "size": 42,
+                "label": "1 file(s), first is /src/main.rs",
+                "list": [1, "plain"],
+            })
+        );
+
+        assert!(render(&json!("${find.entries[3].path}"), &results).is_err());
+        assert!(render(&json!("${missing}"), &results).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_pipeline() -> Result<()> {
+        let test_file = std::env::temp_dir().join(format!(
+            "pipeline_test_{}_{}.txt",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        let dispatcher = crate::create_default_dispatcher();
+
+        let request = json!({
+            "jsonrpc": "2.0",
+            "method": "pipeline",
+            "params": {"steps": [
+                {"id": "write", "method": "file_write", "params": {"path": test_file, "content": "hello\n"}},
+                {"method": "file_read", "params": {"path": "${write.path}"}},
+            ]},
+            "id": 1,
+        });
+        let response: Value =
+            serde_json::from_str(&dispatcher.dispatch(&request.to_string()).await?)?;
+        assert_eq!(response["result"]["steps"][1]["id"], "1");
+        assert_eq!(
+            response["result"]["steps"][1]["result"]["content"],
+            "hello\n"
+        );
+
+        // The first failure stops the pipeline
+        let request = json!({
+            "jsonrpc": "2.0",
+            "method": "pipeline",
+            "params": {"steps": [
+                {"method": "file_read", "params": {"path": "/definitely/missing.txt"}},
+                {"method": "file_write", "params": {"path": test_file, "content": "changed\n"}},
+            ]},
+            "id": 2,
+        });
+        let response: Value =
+            serde_json::from_str(&dispatcher.dispatch(&request.to_string()).await?)?;
+        assert_eq!(response["error"]["data"]["code"], "not_found");
+        let message = response["error"]["message"].as_str().unwrap();
+        assert!(
+            message.contains("Pipeline step 0 (file_read) failed"),
+            "{}",
+            message
+        );
+        assert_eq!(std::fs::read_to_string(&test_file)?, "hello\n");
+
+        std::fs::remove_file(&test_file)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+//! Per-session quotas
+//!
+//! A dispatcher built with [`Dispatcher::with_quotas`] meters what each
+//! session does: bytes written and files created through the
+//! [`vfs`](crate::vfs) layer and by `batch`, and time spent running `shell`
+//! commands. Calls without a session share one meter. A write that would go
+//! over a limit fails with the `quota_exceeded` error code, and `shell`
+//! commands are stopped when the session's shell time runs out, as if they had
+//! timed out. Background jobs started with `shell_spawn` are not metered.
+//!
+//! The `quota/status` method reports the limits and usage of the calling
+//! session.
+//!
+//! [`Dispatcher::with_quotas`]: crate::jsonrpc::Dispatcher::with_quotas
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::vfs::Filesystem;
+#[cfg(any(test, feature = "shell"))]
+use crate::{Error, Result};
+
+/// Name of the method reporting quota usage
+pub const METHOD: &str = "quota/status";
+
+/// Limits for each session; `None` means unlimited
+#[derive(Clone, Debug, Default)]
+pub struct QuotaConfig {
+    /// Total bytes written to files
+    pub max_bytes_written: Option<u64>,
+    /// Number of files created
+    pub max_files_created: Option<u64>,
+    /// Time spent running shell commands
+    pub max_shell_time: Option<Duration>,
+}
+
+/// What a session has used so far
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Total bytes written to files
+    pub bytes_written: u64,
+    /// Number of files created
+    pub files_created: u64,
+    /// Time spent running shell commands
+    pub shell_time: Duration,
+}
+
+/// Usage of one session against the limits
+#[derive(Debug)]
+pub(crate) struct Meter {
+    config: Arc<QuotaConfig>,
+    usage: Mutex<Usage>,
+}
+
+impl Meter {
+    fn new(config: Arc<QuotaConfig>) -> Self {
+        Self {
+            config,
+            usage: Mutex::new(Usage::default()),
+        }
+    }
+
+    /// Count a write, unless it would go over a limit
+    fn charge_write(&self, bytes: u64, created: bool) -> std::result::Result<(), String> {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(max) = self.config.max_bytes_written
+            && usage.bytes_written + bytes > max
+        {
+            return Err(format!(
+                "Writing {} bytes would exceed the quota of {} bytes ({} used)",
+                bytes, max, usage.bytes_written
+            ));
+        }
+        if let Some(max) = self.config.max_files_created
+            && created
+            && usage.files_created >= max
+        {
+            return Err(format!(
+                "Creating another file would exceed the quota of {} files",
+                max
+            ));
+        }
+        usage.bytes_written += bytes;
+        usage.files_created += u64::from(created);
+        Ok(())
+    }
+
+    #[cfg(any(test, feature = "shell"))]
+    fn shell_time_left(&self) -> Option<Duration> {
+        let max = self.config.max_shell_time?;
+        Some(max.saturating_sub(self.usage.lock().unwrap().shell_time))
+    }
+}
+
+/// Quota meters of a dispatcher, by session id
+#[derive(Debug)]
+pub struct Quotas {
+    config: Arc<QuotaConfig>,
+    meters: Mutex<HashMap<String, Arc<Meter>>>,
+}
+
+impl Quotas {
+    /// Create meters that apply `config` to every session
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            meters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The limits
+    pub fn config(&self) -> &QuotaConfig {
+        &self.config
+    }
+
+    /// What a session (or the calls without one, for `None`) has used
+    pub fn usage(&self, session_id: Option<&str>) -> Usage {
+        self.meters
+            .lock()
+            .unwrap()
+            .get(session_id.unwrap_or_default())
+            .map(|meter| *meter.usage.lock().unwrap())
+            .unwrap_or_default()
+    }
+
+    /// Forget the usage of a session
+    pub fn reset(&self, session_id: Option<&str>) {
+        self.meters
+            .lock()
+            .unwrap()
+            .remove(session_id.unwrap_or_default());
+    }
+
+    pub(crate) fn meter(&self, session_id: Option<&str>) -> Arc<Meter> {
+        self.meters
+            .lock()
+            .unwrap()
+            .entry(session_id.unwrap_or_default().to_string())
+            .or_insert_with(|| Arc::new(Meter::new(self.config.clone())))
+            .clone()
+    }
+
+    /// Limits and usage of a session for the quota/status method
+    pub(crate) fn status(&self, session_id: Option<&str>) -> Status {
+        let usage = self.usage(session_id);
+        Status {
+            limits: Amounts {
+                bytes_written: self.config.max_bytes_written,
+                files_created: self.config.max_files_created,
+                shell_secs: self.config.max_shell_time.map(|t| t.as_secs_f64()),
+            },
+            used: Amounts {
+                bytes_written: Some(usage.bytes_written),
+                files_created: Some(usage.files_created),
+                shell_secs: Some(usage.shell_time.as_secs_f64()),
+            },
+        }
+    }
+}
+
+/// Output of the quota/status method
+#[derive(Debug, Serialize)]
+pub struct Status {
+    /// Limits of the session (null when unlimited)
+    pub limits: Amounts,
+    /// What the session has used
+    pub used: Amounts,
+}
+
+/// Amounts counted by the quotas
+#[derive(Debug, Serialize)]
+pub struct Amounts {
+    /// Bytes written to files
+    pub bytes_written: Option<u64>,
+    /// Files created
+    pub files_created: Option<u64>,
+    /// Seconds spent running shell commands
+    pub shell_secs: Option<f64>,
+}
+
+tokio::task_local! {
+    static CURRENT: Arc<Meter>;
+}
+
+/// Run a future with `meter` counting its usage
+pub(crate) fn scope<F: Future>(meter: Arc<Meter>, future: F) -> impl Future<Output = F::Output> {
+    CURRENT.scope(meter, future)
+}
+
+/// Count a write of `bytes` to `path` against the current meter, failing with
+/// [`io::ErrorKind::QuotaExceeded`] if that would go over a limit
+pub(crate) async fn charge_write(fs: &dyn Filesystem, path: &Path, bytes: usize) -> io::Result<()> {
+    let Ok(meter) = CURRENT.try_with(|meter| meter.clone()) else {
+        return Ok(());
+    };
+    let created = !fs.exists(path).await;
+    meter
+        .charge_write(bytes as u64, created)
+        .map_err(|msg| io::Error::new(io::ErrorKind::QuotaExceeded, msg))
+}
+
+#[cfg(any(test, feature = "shell"))]
+/// Shell time the current session has left, if it is limited
+pub(crate) fn shell_time_left() -> Result<Option<Duration>> {
+    let left = CURRENT
+        .try_with(|meter| meter.shell_time_left())
+        .ok()
+        .flatten();
+    if left == Some(Duration::ZERO) {
+        return Err(Error::QuotaExceeded(
+            "The shell time quota is used up".to_string(),
+        ));
+    }
+    Ok(left)
+}
+
+#[cfg(any(test, feature = "shell"))]
+/// Count time spent running a shell command
+pub(crate) fn charge_shell_time(elapsed: Duration) {
+    let _ = CURRENT.try_with(|meter| meter.usage.lock().unwrap().shell_time += elapsed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[tokio::test]
+    async fn test_charge_write() -> Result<()> {
+        let quotas = Quotas::new(QuotaConfig {
+            max_bytes_written: Some(10),
+            max_files_created: Some(1),
+            max_shell_time: Some(Duration::from_secs(1)),
+        });
+        let fs = MemoryFs::new().with_file("/old.txt", "x");
+        let path = Path::new;
+
+        // Nothing is counted outside a scope
+        charge_write(&fs, path("/new.txt"), 100).await?;
+        assert!(shell_time_left()?.is_none());
+
+        scope(quotas.meter(Some("a")), async {
+            charge_write(&fs, path("/new.txt"), 4).await?;
+            charge_write(&fs, path("/old.txt"), 4).await?;
+
+            let err = charge_write(&fs, path("/other.txt"), 1).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::QuotaExceeded);
+            let err = charge_write(&fs, path("/old.txt"), 3).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::QuotaExceeded);
+
+            charge_shell_time(Duration::from_millis(400));
+            assert_eq!(shell_time_left()?, Some(Duration::from_millis(600)));
+            charge_shell_time(Duration::from_secs(1));
+            assert!(matches!(shell_time_left(), Err(Error::QuotaExceeded(_))));
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+        let usage = quotas.usage(Some("a"));
+        assert_eq!(usage.bytes_written, 8);
+        assert_eq!(usage.files_created, 1);
+        assert_eq!(quotas.usage(Some("b")), Usage::default());
+
+        quotas.reset(Some("a"));
+        assert_eq!(quotas.usage(Some("a")), Usage::default());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dispatcher_quotas() -> Result<()> {
+        use crate::Session;
+        use serde_json::{Value, json};
+
+        let dispatcher = crate::create_default_dispatcher()
+            .with_filesystem(Arc::new(MemoryFs::new()))
+            .with_quotas(QuotaConfig {
+                max_bytes_written: Some(10),
+                ..QuotaConfig::default()
+            });
+        let first = Arc::new(Session::new(std::env::temp_dir())?);
+        let second = Arc::new(Session::new(std::env::temp_dir())?);
+
+        let call = async |session: &Arc<Session>, method: &str, params: Value| -> Result<Value> {
+            let request = json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1});
+            let response = dispatcher
+                .dispatch_in_session(&request.to_string(), session.clone())
+                .await?;
+            Ok(serde_json::from_str(&response)?)
+        };
+        let write = |path: &str| json!({"path": path, "content": "123456", "create_dirs": true});
+
+        let response = call(&first, "file_write", write("/a.txt")).await?;
+        assert_eq!(response["result"]["created"], true);
+        let response = call(&first, "file_write", write("/b.txt")).await?;
+        assert_eq!(response["error"]["code"], -32003);
+        assert_eq!(response["error"]["data"]["code"], "quota_exceeded");
+
+        // Each session has its own quota
+        let response = call(&second, "file_write", write("/b.txt")).await?;
+        assert_eq!(response["result"]["created"], true);
+
+        let response = call(&first, METHOD, json!({})).await?;
+        let result = &response["result"];
+        assert_eq!(result["limits"]["bytes_written"], 10);
+        assert_eq!(result["limits"]["files_created"], Value::Null);
+        assert_eq!(result["used"]["bytes_written"], 6);
+        assert_eq!(result["used"]["files_created"], 1);
+
+        // Without quotas there is no quota/status method
+        let dispatcher = crate::create_default_dispatcher();
+        let request = json!({"jsonrpc": "2.0", "method": METHOD, "params": {}, "id": 1});
+        let response: Value =
+            serde_json::from_str(&dispatcher.dispatch(&request.to_string()).await?)?;
+        assert_eq!(response["error"]["code"], -32601);
+
+        Ok(())
+    }
+}
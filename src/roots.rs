@@ -0,0 +1,211 @@
+//! Named workspace roots
+//!
+//! A dispatcher can name the directories it works in with
+//! [`Dispatcher::with_root`], e.g. `src`, `docs` and `scratch`. Path parameters
+//! of the tools then accept `name:relative/path` references, which resolve
+//! below the root of that name wherever the server runs. A reference can't
+//! climb out of its root with `..`. Anything else, including a prefix that
+//! names no root such as a Windows drive letter, is taken as a plain path.
+//!
+//! The roots are listed by `server/info` and `tools/list`.
+//!
+//! [`Dispatcher::with_root`]: crate::jsonrpc::Dispatcher::with_root
+
+#[cfg(any(
+    feature = "code-outline",
+    feature = "directory-list",
+    feature = "directory-make",
+    feature = "file-compare",
+    feature = "file-diff",
+    feature = "file-edit",
+    feature = "file-find",
+    feature = "file-grep",
+    feature = "file-metadata-set",
+    feature = "file-move",
+    feature = "file-patch",
+    feature = "file-read",
+    feature = "file-write",
+    feature = "files-read",
+    feature = "fuzzy-find",
+    feature = "path-info",
+    feature = "recent-files",
+    feature = "shell",
+))]
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{Error, Result, paths};
+
+/// Named roots, by name
+pub type Roots = BTreeMap<String, PathBuf>;
+
+tokio::task_local! {
+    static ROOTS: Arc<Roots>;
+}
+
+/// Run `future` with `roots` as the named roots
+pub fn scope<F: Future>(roots: Arc<Roots>, future: F) -> impl Future<Output = F::Output> {
+    ROOTS.scope(roots, future)
+}
+
+/// Whether `path` is a `name:relative` reference to a configured root
+pub(crate) fn is_reference(path: &str) -> bool {
+    path.split_once(':').is_some_and(|(name, _)| {
+        ROOTS
+            .try_with(|roots| roots.contains_key(name))
+            .unwrap_or(false)
+    })
+}
+
+/// `path` with a `name:relative` reference to a configured root resolved.
+/// Other paths come back unchanged.
+pub fn resolve(path: &str) -> Result<String> {
+    let Some((name, relative)) = path.split_once(':') else {
+        return Ok(path.to_string());
+    };
+    let Some(root) = ROOTS
+        .try_with(|roots| roots.get(name).cloned())
+        .ok()
+        .flatten()
+    else {
+        return Ok(path.to_string());
+    };
+
+    let relative = Path::new(relative);
+    let mut depth = 0usize;
+    for component in relative.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => {
+                return Err(Error::OutsideSandbox(format!(
+                    "{} is not a path below the {} root",
+                    path, name
+                )));
+            }
+        }
+    }
+    Ok(paths::to_string(&root.join(relative)))
+}
+
+/// Deserialize a path parameter, resolving root references
+#[cfg(any(
+    feature = "code-outline",
+    feature = "directory-list",
+    feature = "directory-make",
+    feature = "file-compare",
+    feature = "file-diff",
+    feature = "file-edit",
+    feature = "file-find",
+    feature = "file-grep",
+    feature = "file-metadata-set",
+    feature = "file-move",
+    feature = "file-read",
+    feature = "file-write",
+    feature = "fuzzy-find",
+    feature = "path-info",
+    feature = "recent-files",
+))]
+pub(crate) fn path<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<String, D::Error> {
+    let path = String::deserialize(deserializer)?;
+    resolve(&path).map_err(serde::de::Error::custom)
+}
+
+/// Deserialize an optional path parameter, resolving root references
+#[cfg(any(feature = "file-patch", feature = "path-info", feature = "shell"))]
+pub(crate) fn optional_path<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<String>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|path| resolve(&path).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Deserialize a list of path parameters, resolving root references
+#[cfg(any(feature = "file-grep", feature = "files-read"))]
+pub(crate) fn path_list<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<String>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|path| resolve(path).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve() {
+        let roots = Arc::new(Roots::from([
+            ("src".to_string(), PathBuf::from("/work/repo/src")),
+            ("scratch".to_string(), PathBuf::from("/tmp/scratch")),
+        ]));
+        scope(roots, async {
+            assert_eq!(resolve("src:lib.rs").unwrap(), "/work/repo/src/lib.rs");
+            assert_eq!(
+                resolve("scratch:a/../b.txt").unwrap(),
+                "/tmp/scratch/a/../b.txt"
+            );
+            assert_eq!(resolve("src:").unwrap(), "/work/repo/src/");
+
+            assert!(is_reference("src:lib.rs"));
+
+            // Not a root reference
+            assert!(!is_reference("docs:intro.md"));
+            assert_eq!(resolve("docs:intro.md").unwrap(), "docs:intro.md");
+            assert_eq!(resolve("/etc/hosts").unwrap(), "/etc/hosts");
+
+            // References stay below their root
+            assert!(matches!(
+                resolve("src:../secrets"),
+                Err(Error::OutsideSandbox(_))
+            ));
+            assert!(matches!(
+                resolve("src:/etc/hosts"),
+                Err(Error::OutsideSandbox(_))
+            ));
+        })
+        .await;
+
+        // No roots configured
+        assert_eq!(resolve("src:lib.rs").unwrap(), "src:lib.rs");
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_with_roots() -> Result<()> {
+        let dispatcher = crate::create_default_dispatcher().with_root("repo", ".");
+
+        let read = r#"{"jsonrpc": "2.0", "method": "file_read", "id": 1, "params": {"path": "repo:Cargo.toml"}}"#;
+        let response: serde_json::Value = serde_json::from_str(&dispatcher.dispatch(read).await?)?;
+        assert!(
+            response["result"]["content"]
+                .as_str()
+                .unwrap()
+                .contains("gamecode-tools")
+        );
+
+        let escape = r#"{"jsonrpc": "2.0", "method": "file_read", "id": 2, "params": {"path": "repo:../x"}}"#;
+        let response: serde_json::Value =
+            serde_json::from_str(&dispatcher.dispatch(escape).await?)?;
+        assert!(response["error"].is_object());
+
+        // Sessions resolve relative paths, but not root references
+        let session = Arc::new(crate::session::Session::new(std::env::temp_dir())?);
+        let response: serde_json::Value =
+            serde_json::from_str(&dispatcher.dispatch_in_session(read, session).await?)?;
+        assert!(response["result"]["content"].is_string());
+
+        let info = r#"{"jsonrpc": "2.0", "method": "server/info", "id": 3, "params": {}}"#;
+        let response: serde_json::Value = serde_json::from_str(&dispatcher.dispatch(info).await?)?;
+        assert_eq!(response["result"]["roots"]["repo"], ".");
+        Ok(())
+    }
+}
@@ -1,13 +1,18 @@
 //! JSON Schema generation for tools
 //!
 //! This module provides functionality to generate JSON schemas for tool parameters
-//! and generate tool specifications for various platforms (AWS Bedrock, OpenAI, etc.)
+//! and generate tool specifications for various platforms (AWS Bedrock, OpenAI, MCP, etc.)
 
 use schemars::{schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 
+use crate::tools::{Annotations, Tool};
+
+/// Name of the method listing the tools in MCP format
+pub const LIST_METHOD: &str = "tools/list";
+
 /// Tool schema information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolSchema {
@@ -17,6 +22,12 @@ pub struct ToolSchema {
     pub description: String,
     /// JSON schema for parameters
     pub parameters_schema: Value,
+    /// Hints about the tool's effects
+    #[serde(default)]
+    pub annotations: Annotations,
+    /// JSON schema of the result, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_schema: Option<Value>,
 }
 
 /// AWS Bedrock tool specification
@@ -44,6 +55,8 @@ pub fn generate_tool_schema<T: JsonSchema>(name: &str, description: &str) -> Too
         name: name.to_string(),
         description: description.to_string(),
         parameters_schema: serde_json::to_value(&schema).unwrap_or(Value::Null),
+        annotations: Annotations::default(),
+        result_schema: None,
     }
 }
 
@@ -67,6 +80,36 @@ pub fn to_openai_function(schema: &ToolSchema) -> Value {
     })
 }
 
+/// Convert a tool schema to the JSON form of a LangChain `StructuredTool`
+pub fn to_langchain_tool(schema: &ToolSchema) -> Value {
+    serde_json::json!({
+        "name": schema.name,
+        "description": schema.description,
+        "args_schema": schema.parameters_schema,
+        "return_direct": false
+    })
+}
+
+/// Convert a tool schema to the JSON form of a LlamaIndex `ToolMetadata`
+pub fn to_llamaindex_tool(schema: &ToolSchema) -> Value {
+    serde_json::json!({
+        "name": schema.name,
+        "description": schema.description,
+        "fn_schema": schema.parameters_schema,
+        "return_direct": false
+    })
+}
+
+/// Convert a tool schema to an MCP tool definition, as listed by `tools/list`
+pub fn to_mcp_tool(schema: &ToolSchema) -> Value {
+    serde_json::json!({
+        "name": schema.name,
+        "description": schema.description,
+        "inputSchema": schema.parameters_schema,
+        "annotations": schema.annotations
+    })
+}
+
 /// Registry for all tool schemas
 #[derive(Debug, Default)]
 pub struct ToolSchemaRegistry {
@@ -85,6 +128,37 @@ impl ToolSchemaRegistry {
         self.schemas.insert(name.to_string(), schema);
     }
 
+    /// Register the schema of a tool, with its own name, description and
+    /// annotations
+    pub fn register_tool<T>(&mut self, tool: &T)
+    where
+        T: Tool,
+        T::Params: JsonSchema,
+    {
+        let mut schema = generate_tool_schema::<T::Params>(tool.name(), tool.description());
+        schema.annotations = tool.annotations();
+        self.register_schema(schema);
+    }
+
+    /// Register the schema of a tool like [`register_tool`](Self::register_tool),
+    /// along with the schema of its result
+    pub fn register_tool_with_result<T>(&mut self, tool: &T)
+    where
+        T: Tool,
+        T::Params: JsonSchema,
+        T::Output: JsonSchema,
+    {
+        self.register_tool(tool);
+        if let Some(schema) = self.schemas.get_mut(tool.name()) {
+            schema.result_schema = serde_json::to_value(schema_for!(T::Output)).ok();
+        }
+    }
+
+    /// Register a schema that was not generated from a type
+    pub fn register_schema(&mut self, schema: ToolSchema) {
+        self.schemas.insert(schema.name.clone(), schema);
+    }
+
     /// Get a tool schema by name
     pub fn get(&self, name: &str) -> Option<&ToolSchema> {
         self.schemas.get(name)
@@ -110,12 +184,218 @@ impl ToolSchemaRegistry {
         self.schemas.values().map(to_openai_function).collect()
     }
 
+    /// Convert all schemas to LangChain tool definitions, sorted by name
+    pub fn to_langchain_tools(&self) -> Vec<Value> {
+        self.sorted().into_iter().map(to_langchain_tool).collect()
+    }
+
+    /// Convert all schemas to LlamaIndex tool definitions, sorted by name
+    pub fn to_llamaindex_tools(&self) -> Vec<Value> {
+        self.sorted().into_iter().map(to_llamaindex_tool).collect()
+    }
+
+    /// Convert all schemas to MCP tool definitions, sorted by name
+    pub fn to_mcp_tools(&self) -> Vec<Value> {
+        self.sorted().into_iter().map(to_mcp_tool).collect()
+    }
+
+    /// Render the catalog as Markdown, for example to put in a system prompt:
+    /// each tool with its description, hints, parameters (with types and
+    /// defaults) and result fields, sorted by name
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# Tools\n");
+        for schema in self.sorted() {
+            out.push_str(&format!("\n## `{}`\n\n", schema.name));
+            if !schema.description.is_empty() {
+                out.push_str(&format!("{}\n\n", schema.description));
+            }
+            out.push_str(&format!("Hints: {}\n", hints(&schema.annotations)));
+            out.push_str("\n### Parameters\n\n");
+            out.push_str(&fields_markdown(&schema.parameters_schema, true));
+            if let Some(result) = &schema.result_schema {
+                out.push_str("\n### Result\n\n");
+                out.push_str(&fields_markdown(result, false));
+            }
+        }
+        out
+    }
+
+    /// The catalog as a server manifest: the name and version of this crate,
+    /// and each tool in MCP format with its result schema as `outputSchema`,
+    /// sorted by name
+    pub fn to_manifest_json(&self) -> Value {
+        let tools: Vec<Value> = self
+            .sorted()
+            .into_iter()
+            .map(|schema| {
+                let mut tool = to_mcp_tool(schema);
+                if let Some(result) = &schema.result_schema {
+                    tool["outputSchema"] = result.clone();
+                }
+                tool
+            })
+            .collect();
+        serde_json::json!({
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "tools": tools
+        })
+    }
+
+    /// The schemas sorted by tool name
+    fn sorted(&self) -> Vec<&ToolSchema> {
+        let mut schemas: Vec<&ToolSchema> = self.schemas.values().collect();
+        schemas.sort_by(|a, b| a.name.cmp(&b.name));
+        schemas
+    }
+
     /// Get schemas as JSON
     pub fn to_json(&self) -> Value {
         serde_json::to_value(&self.schemas).unwrap_or(Value::Null)
     }
 }
 
+/// The annotations in words, e.g. "read-only, idempotent"
+fn hints(annotations: &Annotations) -> String {
+    let mut hints = vec![if annotations.read_only {
+        "read-only"
+    } else if annotations.destructive {
+        "destructive"
+    } else {
+        "writes"
+    }];
+    if annotations.idempotent {
+        hints.push("idempotent");
+    }
+    if annotations.open_world {
+        hints.push("open world");
+    }
+    hints.join(", ")
+}
+
+/// Tables of the properties of an object schema and of the objects it refers to
+fn fields_markdown(schema: &Value, parameters: bool) -> String {
+    let defs = schema.get("$defs");
+    let mut out = field_table(schema, defs, parameters);
+    if let Some(Value::Object(definitions)) = defs {
+        for (name, definition) in definitions {
+            if definition.get("properties").is_some() {
+                out.push_str(&format!("\n`{}`:\n\n", name));
+                out.push_str(&field_table(definition, defs, parameters));
+            }
+        }
+    }
+    out
+}
+
+/// A table of the properties of an object schema; parameters also get
+/// whether they are required and their default
+fn field_table(schema: &Value, defs: Option<&Value>, parameters: bool) -> String {
+    let properties = match schema.get("properties").and_then(Value::as_object) {
+        Some(properties) if !properties.is_empty() => properties,
+        _ => return "None\n".to_string(),
+    };
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    let mut out = if parameters {
+        "| Name | Type | Required | Default | Description |\n|---|---|---|---|---|\n"
+    } else {
+        "| Name | Type | Description |\n|---|---|---|\n"
+    }
+    .to_string();
+    for (name, property) in properties {
+        let kind = type_name(property, defs);
+        let description = cell(
+            property
+                .get("description")
+                .and_then(Value::as_str)
+                .unwrap_or(""),
+        );
+        if parameters {
+            let required = if required.contains(&name.as_str()) {
+                "yes"
+            } else {
+                "no"
+            };
+            let default = property
+                .get("default")
+                .map(|default| format!("`{}`", cell(&default.to_string())))
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "| `{}` | {} | {} | {} | {} |\n",
+                name, kind, required, default, description
+            ));
+        } else {
+            out.push_str(&format!("| `{}` | {} | {} |\n", name, kind, description));
+        }
+    }
+    out
+}
+
+/// Short type of a property, e.g. `string`, `integer[]`, `Entry` or the
+/// values of an enum
+fn type_name(schema: &Value, defs: Option<&Value>) -> String {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference.rsplit('/').next().unwrap_or(reference);
+        // Objects have their own table; enums are spelled out
+        return match defs.and_then(|defs| defs.get(name)) {
+            Some(definition) if definition.get("properties").is_none() => {
+                type_name(definition, defs)
+            }
+            _ => format!("`{}`", name),
+        };
+    }
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        let values: Vec<String> = values
+            .iter()
+            .filter(|value| !value.is_null())
+            .map(|value| format!("`{}`", value))
+            .collect();
+        return values.join(" \\| ");
+    }
+    if let Some(value) = schema.get("const") {
+        return format!("`{}`", value);
+    }
+    for key in ["anyOf", "oneOf"] {
+        if let Some(variants) = schema.get(key).and_then(Value::as_array) {
+            let mut names: Vec<String> = Vec::new();
+            for variant in variants {
+                let name = type_name(variant, defs);
+                if name != "null" && !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            return names.join(" \\| ");
+        }
+    }
+
+    let mut types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(kind)) => vec![kind.as_str()],
+        Some(Value::Array(kinds)) => kinds.iter().filter_map(Value::as_str).collect(),
+        _ => return "any".to_string(),
+    };
+    if types.len() > 1 {
+        types.retain(|kind| *kind != "null");
+    }
+    let types: Vec<String> = types
+        .into_iter()
+        .map(|kind| match (kind, schema.get("items")) {
+            ("array", Some(items)) => format!("{}[]", type_name(items, defs)),
+            _ => kind.to_string(),
+        })
+        .collect();
+    types.join(" \\| ")
+}
+
+/// Text made safe for a Markdown table cell
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -150,5 +430,96 @@ mod tests {
         let bedrock_specs = registry.to_bedrock_specs();
         assert_eq!(bedrock_specs.len(), 1);
         assert_eq!(bedrock_specs[0].name, "test_tool");
+
+        let langchain = registry.to_langchain_tools();
+        assert_eq!(langchain[0]["name"], "test_tool");
+        assert_eq!(langchain[0]["description"], "Test description");
+        assert_eq!(langchain[0]["args_schema"]["required"][0], "path");
+
+        let llamaindex = registry.to_llamaindex_tools();
+        assert_eq!(llamaindex[0]["name"], "test_tool");
+        assert!(llamaindex[0]["fn_schema"]["properties"]["recursive"].is_object());
+    }
+
+    #[test]
+    fn test_framework_export() {
+        let mut registry = ToolSchemaRegistry::new();
+        registry.register::<TestParams>("zeta", "Last tool");
+        registry.register::<TestParams>("alpha", "First tool");
+        let parameters = registry.get("alpha").unwrap().parameters_schema.clone();
+
+        // Both come out sorted by name, whatever the registration order
+        let langchain = registry.to_langchain_tools();
+        assert_eq!(langchain.len(), 2);
+        assert_eq!(
+            langchain[0],
+            serde_json::json!({
+                "name": "alpha",
+                "description": "First tool",
+                "args_schema": parameters,
+                "return_direct": false
+            })
+        );
+        assert_eq!(langchain[1]["name"], "zeta");
+
+        let llamaindex = registry.to_llamaindex_tools();
+        assert_eq!(
+            llamaindex[0],
+            serde_json::json!({
+                "name": "alpha",
+                "description": "First tool",
+                "fn_schema": parameters,
+                "return_direct": false
+            })
+        );
+        assert_eq!(llamaindex[1]["description"], "Last tool");
+    }
+
+    #[test]
+    #[cfg(all(feature = "file-read", feature = "file-write"))]
+    fn test_register_tool() {
+        let mut registry = ToolSchemaRegistry::new();
+        registry.register_tool(&crate::tools::file_read::FileRead);
+        registry.register_tool(&crate::tools::file_write::FileWrite);
+
+        let schema = registry.get("file_read").unwrap();
+        assert_eq!(schema.description, "Read a file from the filesystem");
+        assert_eq!(schema.annotations, Annotations::READ_ONLY);
+
+        let tools = registry.to_mcp_tools();
+        assert_eq!(tools[0]["name"], "file_read");
+        assert_eq!(tools[0]["annotations"]["readOnlyHint"], true);
+        assert!(tools[0]["inputSchema"].is_object());
+        assert_eq!(tools[1]["name"], "file_write");
+        assert_eq!(tools[1]["annotations"]["readOnlyHint"], false);
+        assert_eq!(tools[1]["annotations"]["destructiveHint"], true);
+        assert_eq!(tools[1]["annotations"]["idempotentHint"], true);
+    }
+
+    #[test]
+    #[cfg(feature = "file-read")]
+    fn test_catalog_export() {
+        let mut registry = ToolSchemaRegistry::new();
+        registry.register_tool_with_result(&crate::tools::file_read::FileRead);
+        registry.register::<TestParams>("test_tool", "Test description");
+
+        let markdown = registry.to_markdown();
+        assert!(markdown.starts_with("# Tools\n"));
+        assert!(
+            markdown.find("## `file_read`").unwrap() < markdown.find("## `test_tool`").unwrap()
+        );
+        assert!(markdown.contains("Hints: read-only, idempotent\n"));
+        assert!(markdown.contains("| `path` | string | yes |  | "));
+        assert!(markdown.contains("| `recursive` | boolean | no |"));
+        assert!(markdown.contains("### Result"));
+        assert!(markdown.contains("| `content` | string |"));
+        // Only tools registered with their result have one
+        assert_eq!(markdown.matches("### Result").count(), 1);
+
+        let manifest = registry.to_manifest_json();
+        assert_eq!(manifest["name"], "gamecode-tools");
+        assert_eq!(manifest["tools"][0]["name"], "file_read");
+        assert!(manifest["tools"][0]["outputSchema"]["properties"]["content"].is_object());
+        assert!(manifest["tools"][1].get("outputSchema").is_none());
     }
 }
@@ -0,0 +1,404 @@
+//! Per-client sessions with their own working directory.
+//!
+//! A multi-tenant server keeps one [`Session`] per client and dispatches that
+//! client's requests with [`Dispatcher::dispatch_in_session`]. Relative paths in
+//! tool parameters are then resolved against the session's working directory
+//! instead of the process one, and the `session_cd` and `session_info` methods
+//! become available.
+//!
+//! [`Dispatcher::dispatch_in_session`]: crate::jsonrpc::Dispatcher::dispatch_in_session
+
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::transform::{deserialize, serialize};
+use crate::{Error, Result, paths, roots, vfs};
+
+/// Parameter keys holding a path or a list of paths
+const PATH_KEYS: &[&str] = &[
+    "path",
+    "paths",
+    "file1",
+    "file2",
+    "source",
+    "destination",
+    "new_path",
+    "base_dir",
+    "directory",
+    "cwd",
+    "files",
+    "relative_to",
+];
+
+/// Parameter keys naming a working directory, which default to the session's
+/// own
+const WORKING_DIR_KEYS: &[&str] = &["directory", "cwd"];
+
+/// A client session: an id, a working directory and scratch space
+#[derive(Debug)]
+pub struct Session {
+    id: String,
+    cwd: Mutex<PathBuf>,
+    scratch_dir: PathBuf,
+}
+
+/// Parameters for the session_cd method
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CdParams {
+    /// Directory to change to, relative to the current one or absolute
+    pub path: String,
+}
+
+/// Output of the session_cd and session_info methods
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    /// Id of the session
+    pub session_id: String,
+
+    /// Current working directory of the session
+    pub cwd: String,
+
+    /// Directory for temporary files, removed when the session ends
+    pub scratch_dir: String,
+}
+
+impl Session {
+    /// Start a session with a random id in the directory `cwd`
+    pub fn new(cwd: impl AsRef<Path>) -> Result<Self> {
+        Self::with_id(format!("{:016x}", rand::random::<u64>()), cwd)
+    }
+
+    /// Start a session with the given id (letters, digits, `-` and `_`) in the
+    /// directory `cwd`
+    pub fn with_id(id: impl Into<String>, cwd: impl AsRef<Path>) -> Result<Self> {
+        let id = id.into();
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(Error::InvalidParam(format!("Invalid session id: {:?}", id)));
+        }
+
+        let cwd = canonical_dir(cwd.as_ref())?;
+        let scratch_dir = std::env::temp_dir().join(format!("gamecode-session-{}", id));
+
+        Ok(Self {
+            id,
+            cwd: Mutex::new(cwd),
+            scratch_dir,
+        })
+    }
+
+    /// Id of the session
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Current working directory of the session
+    pub fn cwd(&self) -> PathBuf {
+        self.cwd.lock().unwrap().clone()
+    }
+
+    /// Resolve a path against the session's working directory
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.cwd().join(path)
+    }
+
+    /// Change the working directory, relative to the current one.
+    /// Returns the new working directory.
+    pub fn cd(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        let mut cwd = self.cwd.lock().unwrap();
+        let new_cwd = canonical_dir(&cwd.join(path))?;
+        *cwd = new_cwd.clone();
+        Ok(new_cwd)
+    }
+
+    /// Scratch directory of the session, created on first use
+    pub fn scratch_dir(&self) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.scratch_dir)?;
+        Ok(self.scratch_dir.clone())
+    }
+
+    /// Describe the session
+    pub fn info(&self) -> SessionInfo {
+        SessionInfo {
+            session_id: self.id.clone(),
+            cwd: self.cwd().to_string_lossy().to_string(),
+            scratch_dir: self.scratch_dir.to_string_lossy().to_string(),
+        }
+    }
+
+    /// Run one of the session methods. Returns `None` for any other method.
+    pub(crate) fn handle(&self, method: &str, params: Value) -> Option<Result<Value>> {
+        let result = match method {
+            "session_info" => Ok(self.info()),
+            "session_cd" => deserialize::<CdParams>(params)
+                .and_then(|params| self.cd(&params.path))
+                .map(|_| self.info()),
+            _ => return None,
+        };
+
+        Some(result.and_then(serialize))
+    }
+
+    /// Make the paths in tool parameters absolute against the session's
+    /// working directory, and point working directories that were left out
+    /// at it
+    pub(crate) fn resolve_params(&self, params: &mut Value) {
+        let Value::Object(map) = params else {
+            return;
+        };
+
+        let cwd = self.cwd();
+        for key in WORKING_DIR_KEYS {
+            let missing = match map.get(*key) {
+                None | Some(Value::Null) => true,
+                Some(Value::String(s)) => s.is_empty(),
+                _ => false,
+            };
+            if missing {
+                map.insert(
+                    key.to_string(),
+                    Value::String(cwd.to_string_lossy().to_string()),
+                );
+            }
+        }
+
+        resolve_keys(params, &cwd);
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.scratch_dir);
+    }
+}
+
+/// Canonicalize a path that must be an existing directory
+fn canonical_dir(path: &Path) -> Result<PathBuf> {
+    let dir = paths::canonicalize(path)
+        .map_err(|_| Error::NotFound(format!("Directory not found: {}", path.display())))?;
+    if !dir.is_dir() {
+        return Err(Error::InvalidParam(format!(
+            "Path is not a directory: {}",
+            path.display()
+        )));
+    }
+    Ok(dir)
+}
+
+/// Directory the `files` of an object are relative to: its `directory`, or
+/// `cwd` without one. `None` when the directory is a root reference, which the
+/// tool resolves them against itself.
+fn files_base(map: &Map<String, Value>, cwd: &Path) -> Option<PathBuf> {
+    match map.get("directory").and_then(Value::as_str) {
+        None | Some("") => Some(cwd.to_path_buf()),
+        Some(directory) if roots::is_reference(directory) => None,
+        Some(directory) => Some(cwd.join(directory)),
+    }
+}
+
+/// Resolve the path keys of an object, and of the objects in its arrays
+/// (such as batch operations)
+fn resolve_keys(value: &mut Value, cwd: &Path) {
+    let Value::Object(map) = value else {
+        return;
+    };
+
+    let files_base = files_base(map, cwd);
+    for (key, value) in map.iter_mut() {
+        if key == "files" {
+            if let Some(base) = &files_base {
+                resolve_path_value(value, base);
+            }
+        } else if PATH_KEYS.contains(&key.as_str()) {
+            resolve_path_value(value, cwd);
+        } else if let Value::Array(items) = value {
+            for item in items {
+                resolve_keys(item, cwd);
+            }
+        }
+    }
+}
+
+/// Paths named in tool parameters, including in the objects of their arrays
+pub(crate) fn param_paths(params: &Value) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    collect_paths(params, &mut paths);
+    paths
+}
+
+fn collect_paths(value: &Value, paths: &mut Vec<PathBuf>) {
+    let Value::Object(map) = value else {
+        return;
+    };
+
+    for (key, value) in map {
+        match value {
+            Value::Array(files) if key == "files" => {
+                let base = files_base(map, Path::new("")).unwrap_or_default();
+                paths.extend(
+                    files
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .filter(|file| !file.is_empty())
+                        .map(|file| base.join(file)),
+                );
+            }
+            Value::String(path) if PATH_KEYS.contains(&key.as_str()) && !path.is_empty() => {
+                paths.push(PathBuf::from(path));
+            }
+            Value::Array(items) if PATH_KEYS.contains(&key.as_str()) => {
+                paths.extend(
+                    items
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .filter(|path| !path.is_empty())
+                        .map(PathBuf::from),
+                );
+            }
+            Value::Array(items) => {
+                for item in items {
+                    collect_paths(item, paths);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Make a path, or each path of a list, absolute
+fn resolve_path_value(value: &mut Value, cwd: &Path) {
+    match value {
+        Value::String(path)
+            if !path.is_empty()
+                && Path::new(path.as_str()).is_relative()
+                && vfs::remote_scheme(Path::new(path.as_str())).is_none()
+                && !roots::is_reference(path) =>
+        {
+            *path = cwd.join(path.as_str()).to_string_lossy().to_string();
+        }
+        Value::Array(items) => {
+            for item in items {
+                resolve_path_value(item, cwd);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "session_test_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::canonicalize(dir).unwrap()
+    }
+
+    #[test]
+    fn test_session_cd() -> Result<()> {
+        let dir = test_dir();
+        let session = Session::with_id("client-1", &dir)?;
+
+        assert_eq!(session.cwd(), dir);
+        assert_eq!(session.cd("sub")?, dir.join("sub"));
+        assert_eq!(session.resolve("file.txt"), dir.join("sub/file.txt"));
+        assert_eq!(session.cd("..")?, dir);
+        assert!(matches!(session.cd("missing"), Err(Error::NotFound(_))));
+        assert_eq!(session.cwd(), dir);
+
+        assert!(Session::with_id("../escape", &dir).is_err());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_in_session() -> Result<()> {
+        let dir = test_dir();
+        std::fs::write(dir.join("sub/hello.txt"), "hello\n")?;
+        let dispatcher = crate::create_default_dispatcher();
+        let session = std::sync::Arc::new(Session::new(&dir)?);
+
+        let response = dispatcher
+            .dispatch_in_session(
+                r#"{"jsonrpc": "2.0", "method": "session_cd", "params": {"path": "sub"}, "id": 1}"#,
+                session.clone(),
+            )
+            .await?;
+        let response: Value = serde_json::from_str(&response)?;
+        assert_eq!(
+            response["result"]["cwd"],
+            dir.join("sub").to_string_lossy().as_ref()
+        );
+
+        let response = dispatcher
+            .dispatch_in_session(
+                r#"{"jsonrpc": "2.0", "method": "file_read", "params": {"path": "hello.txt"}, "id": 2}"#,
+                session.clone(),
+            )
+            .await?;
+        let response: Value = serde_json::from_str(&response)?;
+        assert_eq!(response["result"]["content"], "hello\n");
+
+        // Without a session there are no session methods
+        let response = dispatcher
+            .dispatch(r#"{"jsonrpc": "2.0", "method": "session_info", "params": {}, "id": 3}"#)
+            .await?;
+        let response: Value = serde_json::from_str(&response)?;
+        assert_eq!(response["error"]["code"], -32601);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_params() -> Result<()> {
+        let dir = test_dir();
+        let session = Session::new(&dir)?;
+        let abs = |p: &str| dir.join(p).to_string_lossy().to_string();
+
+        let mut params = json!({
+            "path": "notes.txt",
+            "relative_to": "docs",
+            "paths": ["a.txt", "/etc/hosts", "s3://bucket/key"],
+            "content": "not/a/path",
+            "operations": [{"op": "move", "source": "x", "destination": "y"}],
+            "files": ["src/lib.rs"],
+        });
+        session.resolve_params(&mut params);
+
+        assert_eq!(params["path"], abs("notes.txt"));
+        assert_eq!(params["relative_to"], abs("docs"));
+        assert_eq!(
+            params["paths"],
+            json!([abs("a.txt"), "/etc/hosts", "s3://bucket/key"])
+        );
+        assert_eq!(params["content"], "not/a/path");
+        assert_eq!(params["operations"][0]["source"], abs("x"));
+        assert_eq!(params["operations"][0]["destination"], abs("y"));
+        assert_eq!(params["cwd"], dir.to_string_lossy().as_ref());
+        assert_eq!(params["directory"], dir.to_string_lossy().as_ref());
+        assert_eq!(params["files"], json!([abs("src/lib.rs")]));
+
+        // Files are relative to the directory they are searched in
+        let mut params = json!({"directory": "sub", "files": ["a.txt"]});
+        session.resolve_params(&mut params);
+        assert_eq!(params["directory"], abs("sub"));
+        assert_eq!(params["files"], json!([abs("sub/a.txt")]));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
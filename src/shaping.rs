@@ -0,0 +1,306 @@
+//! Token-budget output shaping
+//!
+//! Results meant for a model are only useful if they fit in its context. A
+//! dispatcher built with
+//! [`Dispatcher::with_token_budget`](crate::jsonrpc::Dispatcher::with_token_budget)
+//! estimates the tokens of each tool result and, when it is over budget, trims
+//! it in order of importance until it fits:
+//!
+//! 1. fields that only add detail, such as the context lines of `file_grep`
+//!    matches, are removed everywhere in the result;
+//! 2. the longest lists are cut to their first half, again and again;
+//! 3. the longest strings are cut to their first half, again and again.
+//!
+//! What was dropped is noted in a `shaped` object added to the result, which
+//! also says `truncated: true`:
+//!
+//! ```json
+//! {"matches": [...], "total_matches": 310,
+//!  "shaped": {"max_tokens": 2000, "estimated_tokens": 9120, "truncated": true,
+//!             "dropped": ["before_context (310 removed)", "/matches: kept 40 of 310"]}}
+//! ```
+
+use serde_json::Value;
+
+/// Fields [`TokenBudget`] removes first, in this order
+pub const DEFAULT_DROPPABLE_FIELDS: &[&str] = &[
+    "before_context",
+    "after_context",
+    "matched_patterns",
+    "byte_start",
+    "byte_end",
+];
+
+/// Strings shorter than this are not cut
+const MIN_STRING_BYTES: usize = 64;
+
+/// A token budget for tool results
+#[derive(Debug, Clone)]
+pub struct TokenBudget {
+    max_tokens: usize,
+    bytes_per_token: usize,
+    droppable: Vec<String>,
+}
+
+impl TokenBudget {
+    /// Fit results in about `max_tokens` tokens, estimating four bytes of JSON
+    /// per token and dropping the [`DEFAULT_DROPPABLE_FIELDS`] first
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens,
+            bytes_per_token: 4,
+            droppable: DEFAULT_DROPPABLE_FIELDS
+                .iter()
+                .map(|field| field.to_string())
+                .collect(),
+        }
+    }
+
+    /// Estimate tokens as `bytes` bytes of JSON each, for models whose
+    /// tokenizers differ from the default of four
+    pub fn with_bytes_per_token(mut self, bytes: usize) -> Self {
+        self.bytes_per_token = bytes.max(1);
+        self
+    }
+
+    /// Also remove `field` before cutting lists, after the fields already named
+    pub fn with_droppable_field(mut self, field: impl Into<String>) -> Self {
+        self.droppable.push(field.into());
+        self
+    }
+
+    /// Estimated tokens of a value as JSON
+    pub fn estimate_tokens(&self, value: &Value) -> usize {
+        size(value).div_ceil(self.bytes_per_token)
+    }
+
+    /// Trim a result to fit the budget, noting what was dropped; results that
+    /// fit are returned as they are
+    pub fn shape(&self, mut result: Value) -> Value {
+        let estimated_tokens = self.estimate_tokens(&result);
+        if estimated_tokens <= self.max_tokens {
+            return result;
+        }
+
+        let mut dropped = Vec::new();
+        for field in &self.droppable {
+            let removed = remove_field(&mut result, field);
+            if removed > 0 {
+                dropped.push(format!("{} ({} removed)", field, removed));
+                if self.fits(&result) {
+                    return self.annotate(result, estimated_tokens, dropped);
+                }
+            }
+        }
+
+        // Cut lists, remembering how long each was to begin with
+        let mut cut: Vec<(String, usize, usize)> = Vec::new();
+        while !self.fits(&result) {
+            let Some((pointer, len)) = largest(&result, &|value| match value {
+                Value::Array(items) if items.len() > 1 => Some(items.len()),
+                _ => None,
+            }) else {
+                break;
+            };
+            if let Some(Value::Array(items)) = result.pointer_mut(&pointer) {
+                items.truncate(len / 2);
+            }
+            match cut
+                .iter_mut()
+                .find(|(cut_pointer, ..)| *cut_pointer == pointer)
+            {
+                Some(entry) => entry.2 = len / 2,
+                None => cut.push((pointer, len, len / 2)),
+            }
+        }
+        for (pointer, len, kept) in cut {
+            dropped.push(format!("{}: kept {} of {}", pointer, kept, len));
+        }
+
+        let mut cut: Vec<(String, usize, usize)> = Vec::new();
+        while !self.fits(&result) {
+            let Some((pointer, len)) = largest(&result, &|value| match value {
+                Value::String(s) if s.len() >= MIN_STRING_BYTES => Some(s.len()),
+                _ => None,
+            }) else {
+                break;
+            };
+            if let Some(Value::String(s)) = result.pointer_mut(&pointer) {
+                let mut end = len / 2;
+                while !s.is_char_boundary(end) {
+                    end -= 1;
+                }
+                s.truncate(end);
+                match cut
+                    .iter_mut()
+                    .find(|(cut_pointer, ..)| *cut_pointer == pointer)
+                {
+                    Some(entry) => entry.2 = end,
+                    None => cut.push((pointer, len, end)),
+                }
+            }
+        }
+        for (pointer, len, kept) in cut {
+            dropped.push(format!("{}: kept {} of {} bytes", pointer, kept, len));
+        }
+
+        self.annotate(result, estimated_tokens, dropped)
+    }
+
+    fn fits(&self, value: &Value) -> bool {
+        self.estimate_tokens(value) <= self.max_tokens
+    }
+
+    /// Note what was dropped in an object result
+    fn annotate(&self, mut result: Value, estimated_tokens: usize, dropped: Vec<String>) -> Value {
+        if let Value::Object(map) = &mut result {
+            map.insert(
+                "shaped".to_string(),
+                serde_json::json!({
+                    "max_tokens": self.max_tokens,
+                    "estimated_tokens": estimated_tokens,
+                    "truncated": true,
+                    "dropped": dropped,
+                }),
+            );
+        }
+        result
+    }
+}
+
+/// Bytes of a value as JSON
+fn size(value: &Value) -> usize {
+    serde_json::to_vec(value)
+        .map(|bytes| bytes.len())
+        .unwrap_or(0)
+}
+
+/// Remove a field from every object in a value, returning how many were removed
+fn remove_field(value: &mut Value, field: &str) -> usize {
+    match value {
+        Value::Object(map) => {
+            let removed = usize::from(map.remove(field).is_some());
+            removed
+                + map
+                    .values_mut()
+                    .map(|v| remove_field(v, field))
+                    .sum::<usize>()
+        }
+        Value::Array(items) => items.iter_mut().map(|v| remove_field(v, field)).sum(),
+        _ => 0,
+    }
+}
+
+/// JSON pointer to the largest value (as JSON) that `length` gives a length
+/// for, with that length
+fn largest(value: &Value, length: &dyn Fn(&Value) -> Option<usize>) -> Option<(String, usize)> {
+    fn walk(
+        value: &Value,
+        pointer: &mut String,
+        length: &dyn Fn(&Value) -> Option<usize>,
+        best: &mut Option<(String, usize, usize)>,
+    ) {
+        if let Some(len) = length(value) {
+            let bytes = size(value);
+            if best
+                .as_ref()
+                .is_none_or(|(_, _, best_bytes)| bytes > *best_bytes)
+            {
+                *best = Some((pointer.clone(), len, bytes));
+            }
+        }
+        let start = pointer.len();
+        match value {
+            Value::Object(map) => {
+                for (key, child) in map {
+                    pointer.push('/');
+                    pointer.push_str(&key.replace('~', "~0").replace('/', "~1"));
+                    walk(child, pointer, length, best);
+                    pointer.truncate(start);
+                }
+            }
+            Value::Array(items) => {
+                for (index, child) in items.iter().enumerate() {
+                    pointer.push_str(&format!("/{}", index));
+                    walk(child, pointer, length, best);
+                    pointer.truncate(start);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut best = None;
+    walk(value, &mut String::new(), length, &mut best);
+    best.map(|(pointer, len, _)| (pointer, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn grep_result(matches: usize) -> Value {
+        let matches: Vec<Value> = (0..matches)
+            .map(|n| {
+                json!({
+                    "line_number": n,
+                    "line": format!("let value_{} = compute();", n),
+                    "before_context": ["// a comment above the match", "fn surrounding() {"],
+                    "after_context": ["    other();", "}"],
+                })
+            })
+            .collect();
+        json!({"path": "src/lib.rs", "matches": matches, "total_matches": matches.len()})
+    }
+
+    #[test]
+    fn test_shape_within_budget() {
+        let result = grep_result(3);
+        assert_eq!(TokenBudget::new(10_000).shape(result.clone()), result);
+    }
+
+    #[test]
+    fn test_shape_drops_context_then_items() {
+        let budget = TokenBudget::new(1000);
+        let result = grep_result(100);
+        assert!(budget.estimate_tokens(&result) > 1000);
+
+        let shaped = budget.shape(result);
+        let matches = shaped["matches"].as_array().unwrap();
+        assert!(matches[0].get("before_context").is_none());
+        assert!(matches.len() < 100);
+        assert_eq!(matches[0]["line_number"], 0);
+        assert_eq!(shaped["total_matches"], 100);
+        assert_eq!(shaped["shaped"]["truncated"], true);
+        let dropped: Vec<&str> = shaped["shaped"]["dropped"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(Value::as_str)
+            .collect();
+        assert_eq!(dropped[0], "before_context (100 removed)");
+        assert_eq!(dropped[1], "after_context (100 removed)");
+        assert_eq!(dropped[2], "/matches: kept 50 of 100");
+        // The note may push it slightly over; the content fits
+        let mut content = shaped.clone();
+        content.as_object_mut().unwrap().remove("shaped");
+        assert!(budget.estimate_tokens(&content) <= 1000);
+    }
+
+    #[test]
+    fn test_shape_cuts_long_strings() {
+        let budget = TokenBudget::new(100);
+        let content = "é".repeat(1000);
+        let shaped = budget.shape(json!({"path": "a.txt", "content": content.clone()}));
+        let kept = shaped["content"].as_str().unwrap();
+        assert!(kept.len() < 400);
+        assert!(content.starts_with(kept));
+        assert!(
+            shaped["shaped"]["dropped"][0]
+                .as_str()
+                .unwrap()
+                .starts_with("/content: kept ")
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Filesystem and blocking-task shims for WASM
+//!
+//! tokio's `fs` feature and its blocking thread pool aren't available on
+//! `wasm32-wasi`, which has no threads. The crate goes through [`fs`] and
+//! [`spawn_blocking`] from here instead: on other targets they are tokio's, and
+//! on WASM they run the `std` call in place against WASI's filesystem. The
+//! in-place versions are also built for tests so they can be checked natively.
+
+#[cfg(not(target_family = "wasm"))]
+pub(crate) use tokio::{fs, task::spawn_blocking};
+
+#[cfg(target_family = "wasm")]
+pub(crate) use {run_in_place as spawn_blocking, std_fs as fs};
+
+/// Run `f` in place; there is no thread to hand it to
+#[cfg(any(test, target_family = "wasm"))]
+pub(crate) async fn run_in_place<F, R>(f: F) -> Result<R, tokio::task::JoinError>
+where
+    F: FnOnce() -> R,
+{
+    Ok(f())
+}
+
+/// The subset of `tokio::fs` the crate uses, on top of `std::fs`
+#[cfg(any(test, target_family = "wasm"))]
+#[allow(dead_code)]
+pub(crate) mod std_fs {
+    use std::fs::{Metadata, Permissions};
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    pub(crate) async fn metadata(path: impl AsRef<Path>) -> io::Result<Metadata> {
+        std::fs::metadata(path)
+    }
+
+    pub(crate) async fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    pub(crate) async fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+
+    pub(crate) async fn write(
+        path: impl AsRef<Path>,
+        contents: impl AsRef<[u8]>,
+    ) -> io::Result<()> {
+        std::fs::write(path, contents)
+    }
+
+    pub(crate) async fn create_dir(path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::create_dir(path)
+    }
+
+    pub(crate) async fn create_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::create_dir_all(path)
+    }
+
+    pub(crate) async fn remove_file(path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    pub(crate) async fn remove_dir(path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::remove_dir(path)
+    }
+
+    pub(crate) async fn remove_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::remove_dir_all(path)
+    }
+
+    pub(crate) async fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    pub(crate) async fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<u64> {
+        std::fs::copy(from, to)
+    }
+
+    pub(crate) async fn canonicalize(path: impl AsRef<Path>) -> io::Result<PathBuf> {
+        std::fs::canonicalize(path)
+    }
+
+    pub(crate) async fn set_permissions(
+        path: impl AsRef<Path>,
+        perm: Permissions,
+    ) -> io::Result<()> {
+        std::fs::set_permissions(path, perm)
+    }
+
+    pub(crate) async fn read_dir(path: impl AsRef<Path>) -> io::Result<ReadDir> {
+        std::fs::read_dir(path).map(ReadDir)
+    }
+
+    /// Entries of a directory, like `tokio::fs::ReadDir`
+    pub(crate) struct ReadDir(std::fs::ReadDir);
+
+    impl ReadDir {
+        pub(crate) async fn next_entry(&mut self) -> io::Result<Option<DirEntry>> {
+            self.0.next().transpose().map(|entry| entry.map(DirEntry))
+        }
+    }
+
+    /// An entry of a directory, like `tokio::fs::DirEntry`
+    pub(crate) struct DirEntry(std::fs::DirEntry);
+
+    impl DirEntry {
+        pub(crate) fn path(&self) -> PathBuf {
+            self.0.path()
+        }
+
+        pub(crate) fn file_name(&self) -> std::ffi::OsString {
+            self.0.file_name()
+        }
+
+        pub(crate) async fn metadata(&self) -> io::Result<Metadata> {
+            self.0.metadata()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_std_fs() -> std::io::Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "test_sys_std_fs_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        std_fs::create_dir_all(dir.join("sub")).await?;
+        std_fs::write(dir.join("a.txt"), "alpha").await?;
+        std_fs::rename(dir.join("a.txt"), dir.join("sub/b.txt")).await?;
+        std_fs::copy(dir.join("sub/b.txt"), dir.join("c.txt")).await?;
+
+        assert_eq!(std_fs::read_to_string(dir.join("c.txt")).await?, "alpha");
+        assert_eq!(std_fs::metadata(dir.join("sub/b.txt")).await?.len(), 5);
+        assert!(std_fs::metadata(dir.join("a.txt")).await.is_err());
+
+        let mut names = Vec::new();
+        let mut entries = std_fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            assert_eq!(entry.path(), dir.join(entry.file_name()));
+            names.push((entry.file_name(), entry.metadata().await?.is_dir()));
+        }
+        names.sort();
+        assert_eq!(names, [("c.txt".into(), false), ("sub".into(), true)]);
+
+        std_fs::remove_file(dir.join("c.txt")).await?;
+        std_fs::remove_dir_all(&dir).await?;
+        assert!(std_fs::metadata(&dir).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_in_place() {
+        let thread = std::thread::current().id();
+        let ran_on = run_in_place(|| std::thread::current().id()).await.unwrap();
+        assert_eq!(ran_on, thread);
+    }
+}
@@ -0,0 +1,158 @@
+//! OpenTelemetry metrics and spans for tool calls
+//!
+//! With the `telemetry` feature every tool call dispatched gets a span named
+//! after the tool, and these metrics are recorded:
+//!
+//! - `gamecode.dispatch.duration` (s): latency of each call, by `tool` and `outcome`
+//! - `gamecode.tool.calls`: calls by `tool` and `outcome`, with `error.type` on
+//!   failures, from which error rates follow
+//! - `gamecode.fs.bytes_read` and `gamecode.fs.bytes_written` (By): file
+//!   content moved through the [`vfs`](crate::vfs) layer
+//!
+//! They go to the global meter and tracer providers, so install those (for
+//! example with `opentelemetry-otlp`) before dispatching the first request.
+//! Without the feature all of this compiles to nothing.
+
+use std::future::Future;
+
+use crate::Result;
+
+/// Name of the instrumentation scope
+#[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+const SCOPE: &str = "gamecode-tools";
+
+#[cfg(feature = "telemetry")]
+mod instruments {
+    use opentelemetry::global;
+    use opentelemetry::metrics::{Counter, Histogram};
+    use std::sync::OnceLock;
+
+    use super::SCOPE;
+
+    pub(super) struct Instruments {
+        pub duration: Histogram<f64>,
+        pub calls: Counter<u64>,
+        pub bytes_read: Counter<u64>,
+        pub bytes_written: Counter<u64>,
+    }
+
+    /// Instruments of the global meter provider, created on first use
+    pub(super) fn get() -> &'static Instruments {
+        static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+        INSTRUMENTS.get_or_init(|| {
+            let meter = global::meter(SCOPE);
+            Instruments {
+                duration: meter
+                    .f64_histogram("gamecode.dispatch.duration")
+                    .with_unit("s")
+                    .with_description("Duration of tool calls")
+                    .build(),
+                calls: meter
+                    .u64_counter("gamecode.tool.calls")
+                    .with_description("Tool calls by outcome")
+                    .build(),
+                bytes_read: meter
+                    .u64_counter("gamecode.fs.bytes_read")
+                    .with_unit("By")
+                    .with_description("Bytes read from files")
+                    .build(),
+                bytes_written: meter
+                    .u64_counter("gamecode.fs.bytes_written")
+                    .with_unit("By")
+                    .with_description("Bytes written to files")
+                    .build(),
+            }
+        })
+    }
+}
+
+/// Run a tool call in a span, recording its latency and outcome
+#[cfg(feature = "telemetry")]
+pub(crate) async fn instrument<T, F>(tool: &str, future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    use opentelemetry::KeyValue;
+    use opentelemetry::global;
+    use opentelemetry::trace::{Span, Status, Tracer};
+    use std::time::Instant;
+
+    let mut span = global::tracer(SCOPE).start(tool.to_string());
+    span.set_attribute(KeyValue::new("tool", tool.to_string()));
+    if let Some(id) = crate::logging::correlation_id() {
+        span.set_attribute(KeyValue::new("correlation_id", id));
+    }
+
+    let start = Instant::now();
+    let result = future.await;
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let mut attributes = vec![KeyValue::new("tool", tool.to_string())];
+    match &result {
+        Ok(_) => attributes.push(KeyValue::new("outcome", "ok")),
+        Err(e) => {
+            attributes.push(KeyValue::new("outcome", "error"));
+            span.set_status(Status::error(e.to_string()));
+            span.set_attribute(KeyValue::new("error.type", e.code()));
+        }
+    }
+
+    let instruments = instruments::get();
+    instruments.duration.record(elapsed, &attributes);
+    if let Err(e) = &result {
+        attributes.push(KeyValue::new("error.type", e.code()));
+    }
+    instruments.calls.add(1, &attributes);
+    span.end();
+
+    result
+}
+
+/// Without the `telemetry` feature calls are not instrumented
+#[cfg(not(feature = "telemetry"))]
+pub(crate) async fn instrument<T, F>(_tool: &str, future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    future.await
+}
+
+/// Count bytes read from a file
+pub(crate) fn record_read(bytes: usize) {
+    crate::execution::record_read(bytes);
+    #[cfg(feature = "telemetry")]
+    instruments::get().bytes_read.add(bytes as u64, &[]);
+    #[cfg(not(feature = "telemetry"))]
+    let _ = bytes;
+}
+
+/// Count bytes written to a file
+pub(crate) fn record_written(bytes: usize) {
+    crate::execution::record_written(bytes);
+    #[cfg(feature = "telemetry")]
+    instruments::get().bytes_written.add(bytes as u64, &[]);
+    #[cfg(not(feature = "telemetry"))]
+    let _ = bytes;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[tokio::test]
+    async fn test_instrument_passes_results_through() {
+        let ok = instrument("file_read", async { Ok(42) }).await;
+        assert_eq!(ok.unwrap(), 42);
+
+        let err = instrument("file_read", async {
+            Err::<(), _>(Error::InvalidParam("bad".to_string()))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), "invalid_param");
+
+        record_read(10);
+        record_written(10);
+    }
+}
@@ -0,0 +1,472 @@
+//! Batch filesystem operations tool implementation
+//!
+//! Runs an ordered list of write/move/mkdir/delete operations as a unit. Every
+//! completed step is journaled; if a later step fails, the completed steps are
+//! undone in reverse order and the tree is left as it was.
+
+use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::{Annotations, Tool};
+use super::file_move::move_path;
+use super::file_write::ContentType;
+use super::journal::{Journal, Undo};
+use crate::vfs;
+use crate::{Error, Result};
+
+/// Batch filesystem operations tool
+#[derive(Clone, Copy)]
+pub struct Batch;
+
+/// A single filesystem operation
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    /// Write a file, creating parent directories and replacing any existing file
+    Write {
+        /// Path of the file
+        #[serde(deserialize_with = "crate::roots::path")]
+        path: String,
+        /// Content to write
+        content: String,
+        /// Whether the content is text or base64 encoded binary
+        #[serde(default)]
+        content_type: ContentType,
+    },
+    /// Move a file or directory; the destination must not exist
+    Move {
+        /// Source path
+        #[serde(deserialize_with = "crate::roots::path")]
+        source: String,
+        /// Destination path
+        #[serde(deserialize_with = "crate::roots::path")]
+        destination: String,
+    },
+    /// Create a directory and any missing parents
+    Mkdir {
+        /// Path of the directory
+        #[serde(deserialize_with = "crate::roots::path")]
+        path: String,
+    },
+    /// Delete a file, or a directory with `recursive`
+    Delete {
+        /// Path to delete
+        #[serde(deserialize_with = "crate::roots::path")]
+        path: String,
+        /// Allow deleting a non-empty directory
+        #[serde(default)]
+        recursive: bool,
+        /// Move the path to the trash instead of removing it for good
+        #[serde(default)]
+        use_trash: bool,
+    },
+}
+
+impl Operation {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Write { .. } => "write",
+            Operation::Move { .. } => "move",
+            Operation::Mkdir { .. } => "mkdir",
+            Operation::Delete { .. } => "delete",
+        }
+    }
+
+    fn path(&self) -> &str {
+        match self {
+            Operation::Write { path, .. } => path,
+            Operation::Move { source, .. } => source,
+            Operation::Mkdir { path } => path,
+            Operation::Delete { path, .. } => path,
+        }
+    }
+}
+
+/// Parameters for the batch tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Operations to run, in order
+    pub operations: Vec<Operation>,
+}
+
+impl Params {
+    /// Run `operations` in order
+    pub fn new(operations: Vec<Operation>) -> Self {
+        Self {
+            operations,
+        }
+    }
+}
+
+/// Result of a single operation
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct OperationResult {
+    /// Index of the operation in the request
+    pub index: usize,
+
+    /// Kind of operation
+    pub op: String,
+
+    /// Path the operation acted on (the source, for moves)
+    pub path: String,
+}
+
+/// Output of the batch tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Operations that were applied, in order
+    pub operations: Vec<OperationResult>,
+}
+
+/// Run one operation, recording how to undo it
+async fn apply(operation: &Operation, data: Option<&[u8]>, journal: &mut Journal) -> Result<()> {
+    let fs = Arc::clone(&journal.fs);
+    let fs = fs.as_ref();
+    match operation {
+        Operation::Write { path, .. } => {
+            let path = PathBuf::from(path);
+            let existing = fs.metadata(&path).await.ok();
+            if existing.as_ref().is_some_and(|m| m.is_dir()) {
+                return Err(Error::InvalidParam(format!(
+                    "Path is a directory: {}",
+                    path.display()
+                )));
+            }
+            if let Some(parent) = path.parent() {
+                journal.create_dirs(parent).await?;
+            }
+
+            journal.write(&path, data.unwrap_or_default()).await?;
+        }
+        Operation::Move {
+            source,
+            destination,
+        } => {
+            let (source, destination) = (PathBuf::from(source), PathBuf::from(destination));
+            if !fs.exists(&source).await {
+                return Err(Error::NotFound(format!(
+                    "Source does not exist: {}",
+                    source.display()
+                )));
+            }
+            if fs.exists(&destination).await {
+                return Err(Error::AlreadyExists(format!(
+                    "Destination already exists: {}",
+                    destination.display()
+                )));
+            }
+            if let Some(parent) = destination.parent() {
+                journal.create_dirs(parent).await?;
+            }
+
+            move_path(fs, &source, &destination).await?;
+            journal.undo.push(Undo::MoveBack {
+                from: destination,
+                to: source,
+            });
+        }
+        Operation::Mkdir { path } => {
+            let path = PathBuf::from(path);
+            if fs.metadata(&path).await.is_ok_and(|m| !m.is_dir()) {
+                return Err(Error::InvalidParam(format!(
+                    "Path exists but is not a directory: {}",
+                    path.display()
+                )));
+            }
+            journal.create_dirs(&path).await?;
+        }
+        Operation::Delete {
+            path,
+            recursive,
+            use_trash,
+        } => {
+            let path = PathBuf::from(path);
+            let Ok(metadata) = fs.metadata(&path).await else {
+                return Err(Error::NotFound(format!(
+                    "Path does not exist: {}",
+                    path.display()
+                )));
+            };
+            if metadata.is_dir() && !recursive && !fs.read_dir(&path).await?.is_empty() {
+                return Err(Error::InvalidParam(format!(
+                    "Directory is not empty (set recursive to delete it): {}",
+                    path.display()
+                )));
+            }
+
+            // Parked next to itself so it can be restored on rollback
+            journal.delete(&path, *use_trash).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl Tool for Batch {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "batch"
+    }
+
+    fn description(&self) -> &str {
+        "Run write/move/mkdir/delete operations atomically"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::DESTRUCTIVE
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        if params.operations.is_empty() {
+            return Err(Error::InvalidParam("No operations provided".to_string()));
+        }
+
+        // Decode all content up front so bad input fails before anything is touched
+        let mut data = Vec::with_capacity(params.operations.len());
+        for (index, operation) in params.operations.iter().enumerate() {
+            if operation.path().is_empty() {
+                return Err(Error::InvalidParam(format!(
+                    "Operation #{}: path must not be empty",
+                    index
+                )));
+            }
+            data.push(match operation {
+                Operation::Write {
+                    content,
+                    content_type: ContentType::Binary,
+                    ..
+                } => Some(general_purpose::STANDARD.decode(content).map_err(|e| {
+                    Error::InvalidParam(format!(
+                        "Operation #{}: invalid base64 content: {}",
+                        index, e
+                    ))
+                })?),
+                Operation::Write { content, .. } => Some(content.as_bytes().to_vec()),
+                _ => None,
+            });
+        }
+
+        let mut journal = Journal::new(vfs::current());
+        let mut results = Vec::with_capacity(params.operations.len());
+
+        for (index, operation) in params.operations.iter().enumerate() {
+            if let Err(e) = apply(operation, data[index].as_deref(), &mut journal).await {
+                journal.rollback().await;
+                return Err(e.with_context(&format!(
+                    "Operation #{} ({} {}) failed, batch rolled back",
+                    index,
+                    operation.name(),
+                    operation.path()
+                )));
+            }
+            results.push(OperationResult {
+                index,
+                op: operation.name().to_string(),
+                path: operation.path().to_string(),
+            });
+        }
+
+        journal.commit().await;
+
+        Ok(Output {
+            operations: results,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::trash;
+    use crate::vfs::Filesystem;
+    use crate::sys::fs;
+
+    async fn test_dir(name: &str) -> Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!(
+            "batch_test_{}_{}_{}",
+            name,
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        fs::create_dir_all(&dir).await?;
+        Ok(dir)
+    }
+
+    fn path(dir: &Path, name: &str) -> String {
+        dir.join(name).to_string_lossy().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_batch_success() -> Result<()> {
+        let dir = test_dir("ok").await?;
+        fs::write(dir.join("old.txt"), "old").await?;
+        fs::write(dir.join("gone.txt"), "gone").await?;
+
+        let result = Batch
+            .execute(Params {
+                operations: vec![
+                    Operation::Mkdir {
+                        path: path(&dir, "module/sub"),
+                    },
+                    Operation::Write {
+                        path: path(&dir, "module/mod.rs"),
+                        content: "pub mod sub;\n".to_string(),
+                        content_type: ContentType::Text,
+                    },
+                    Operation::Move {
+                        source: path(&dir, "old.txt"),
+                        destination: path(&dir, "module/sub/old.txt"),
+                    },
+                    Operation::Delete {
+                        path: path(&dir, "gone.txt"),
+                        recursive: false,
+                        use_trash: false,
+                    },
+                ],
+            })
+            .await?;
+
+        assert_eq!(result.operations.len(), 4);
+        assert_eq!(
+            fs::read_to_string(dir.join("module/mod.rs")).await?,
+            "pub mod sub;\n"
+        );
+        assert!(dir.join("module/sub/old.txt").exists());
+        assert!(!dir.join("old.txt").exists());
+        assert!(!dir.join("gone.txt").exists());
+
+        // No trash is left behind
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            assert!(!entry.file_name().to_string_lossy().starts_with('.'));
+        }
+
+        fs::remove_dir_all(&dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_batch_delete_to_trash() -> Result<()> {
+        let dir = test_dir("trash").await?;
+        fs::create_dir_all(dir.join("build/out")).await?;
+        fs::write(dir.join("build/out/app"), "binary").await?;
+        let trash_dir = dir.join("trash");
+
+        let params = Params::new(vec![Operation::Delete {
+            path: path(&dir, "build"),
+            recursive: true,
+            use_trash: true,
+        }]);
+        trash::scope(trash_dir.clone(), Batch.execute(params)).await?;
+
+        // The directory went whole, under its own name
+        assert!(!dir.join("build").exists());
+        let mut entries = fs::read_dir(&trash_dir).await?;
+        let entry = entries.next_entry().await?.expect("trashed directory");
+        assert!(entry.file_name().to_string_lossy().starts_with("build."));
+        assert_eq!(
+            fs::read_to_string(entry.path().join("out/app")).await?,
+            "binary"
+        );
+
+        fs::remove_dir_all(&dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_batch_rollback() -> Result<()> {
+        let dir = test_dir("rollback").await?;
+        fs::write(dir.join("keep.txt"), "original").await?;
+        fs::write(dir.join("delete.txt"), "still here").await?;
+
+        let result = Batch
+            .execute(Params {
+                operations: vec![
+                    Operation::Write {
+                        path: path(&dir, "keep.txt"),
+                        content: "changed".to_string(),
+                        content_type: ContentType::Text,
+                    },
+                    Operation::Write {
+                        path: path(&dir, "new/deep/file.txt"),
+                        content: "new".to_string(),
+                        content_type: ContentType::Text,
+                    },
+                    Operation::Delete {
+                        path: path(&dir, "delete.txt"),
+                        recursive: false,
+                        use_trash: false,
+                    },
+                    Operation::Move {
+                        source: path(&dir, "missing.txt"),
+                        destination: path(&dir, "elsewhere.txt"),
+                    },
+                ],
+            })
+            .await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+        assert_eq!(fs::read_to_string(dir.join("keep.txt")).await?, "original");
+        assert_eq!(
+            fs::read_to_string(dir.join("delete.txt")).await?,
+            "still here"
+        );
+        assert!(!dir.join("new").exists());
+
+        fs::remove_dir_all(&dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_batch_memory_fs() -> Result<()> {
+        let memory = Arc::new(
+            vfs::MemoryFs::new()
+                .with_file("/repo/a.txt", "a")
+                .with_file("/repo/build/out/app", "binary"),
+        );
+        let run = |operations| vfs::scope(memory.clone(), Batch.execute(Params::new(operations)));
+
+        run(vec![
+            Operation::Move {
+                source: "/repo/a.txt".to_string(),
+                destination: "/repo/docs/a.txt".to_string(),
+            },
+            Operation::Delete {
+                path: "/repo/build".to_string(),
+                recursive: true,
+                use_trash: false,
+            },
+        ])
+        .await?;
+        assert_eq!(memory.read(Path::new("/repo/docs/a.txt")).await?, b"a");
+        assert!(!memory.exists(Path::new("/repo/build")).await);
+
+        // A failure rolls back the steps before it in memory too
+        let result = run(vec![
+            Operation::Write {
+                path: "/repo/docs/a.txt".to_string(),
+                content: "changed".to_string(),
+                content_type: ContentType::Text,
+            },
+            Operation::Mkdir {
+                path: "/repo/docs/a.txt".to_string(),
+            },
+        ])
+        .await;
+        assert!(matches!(result, Err(Error::InvalidParam(_))));
+        assert_eq!(memory.read(Path::new("/repo/docs/a.txt")).await?, b"a");
+
+        Ok(())
+    }
+}
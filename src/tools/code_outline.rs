@@ -0,0 +1,459 @@
+//! Code outline tool implementation
+//!
+//! Lists the definitions in a source file (functions, types, modules and so
+//! on) with their line numbers, so a large file can be navigated without
+//! reading it whole. Definitions are found line by line with per-language
+//! regexes rather than a parser: they are found wherever they are declared
+//! in the usual way, but a definition split over several lines or hidden in
+//! a multi-line string may be missed or reported wrongly.
+
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::path::{Path, PathBuf};
+
+use super::{Annotations, Tool};
+use crate::{Error, Result, paths, vfs};
+
+/// Longest signature reported, in characters
+const MAX_SIGNATURE: usize = 200;
+
+/// Code outline tool
+#[derive(Clone, Copy)]
+pub struct CodeOutline;
+
+/// Languages the outline understands
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    /// `.rs`
+    Rust,
+    /// `.py`, `.pyi`
+    Python,
+    /// `.js`, `.mjs`, `.cjs`, `.jsx`
+    JavaScript,
+    /// `.ts`, `.mts`, `.cts`, `.tsx`
+    TypeScript,
+    /// `.go`
+    Go,
+    /// `.java`
+    Java,
+    /// `.kt`, `.kts`
+    Kotlin,
+    /// `.cs`
+    CSharp,
+    /// `.c`, `.h`
+    C,
+    /// `.cpp`, `.cc`, `.cxx`, `.hpp`, `.hh`, `.hxx`
+    Cpp,
+}
+
+impl Language {
+    /// The language of a file, from its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Some(match extension.as_str() {
+            "rs" => Self::Rust,
+            "py" | "pyi" => Self::Python,
+            "js" | "mjs" | "cjs" | "jsx" => Self::JavaScript,
+            "ts" | "mts" | "cts" | "tsx" => Self::TypeScript,
+            "go" => Self::Go,
+            "java" => Self::Java,
+            "kt" | "kts" => Self::Kotlin,
+            "cs" => Self::CSharp,
+            "c" | "h" => Self::C,
+            "cpp" | "cc" | "cxx" | "hpp" | "hh" | "hxx" => Self::Cpp,
+            _ => return None,
+        })
+    }
+
+    /// Prefixes of lines that are only a comment
+    fn comments(self) -> &'static [&'static str] {
+        match self {
+            Self::Python => &["#"],
+            _ => &["//", "/*", "*"],
+        }
+    }
+
+    /// What each kind of definition looks like; the `name` group is reported
+    fn rules(self) -> Vec<(SymbolKind, String)> {
+        use SymbolKind::*;
+        // Visibility and other modifiers in front of a Rust item
+        const RUST: &str = r"^\s*(?:pub(?:\([^)]*\))?\s+)?";
+        // Modifiers in front of a Java, Kotlin or C# declaration
+        const JVM: &str = r"^\s*(?:@\w+(?:\([^)]*\))?\s+)*(?:(?:public|private|protected|internal|static|final|abstract|sealed|partial|open|data|override|virtual|readonly|async|synchronized|suspend|inline|operator|extern|unsafe|new)\s+)*";
+        let rust = |rule: &str| format!("{}{}", RUST, rule);
+        let jvm = |rule: &str| format!("{}{}", JVM, rule);
+
+        match self {
+            Self::Rust => vec![
+                (Function, rust(r#"(?:(?:const|async|unsafe|extern(?:\s+"[^"]*")?)\s+)*fn\s+(?P<name>\w+)"#)),
+                (Struct, rust(r"struct\s+(?P<name>\w+)")),
+                (Enum, rust(r"enum\s+(?P<name>\w+)")),
+                (Trait, rust(r"(?:unsafe\s+)?trait\s+(?P<name>\w+)")),
+                (Impl, r"^\s*(?:unsafe\s+)?impl(?:<[^{]*?>)?\s+(?P<name>[^{]+?)\s*(?:\{.*|where.*)?$".into()),
+                (Module, rust(r"mod\s+(?P<name>\w+)")),
+                (Type, rust(r"type\s+(?P<name>\w+)(?:<[^>]*>)?\s*=")),
+                (Macro, r"^\s*(?:#\[macro_export\]\s*)?macro_rules!\s*(?P<name>\w+)".into()),
+            ],
+            Self::Python => vec![
+                (Function, r"^\s*(?:async\s+)?def\s+(?P<name>\w+)".into()),
+                (Class, r"^\s*class\s+(?P<name>\w+)".into()),
+            ],
+            Self::JavaScript | Self::TypeScript => vec![
+                (Function, r"^\s*(?:export\s+)?(?:default\s+)?(?:async\s+)?function\s*\*?\s*(?P<name>[\w$]+)".into()),
+                (Function, r"^\s*(?:export\s+)?(?:const|let|var)\s+(?P<name>[\w$]+)\s*(?::[^=]+)?=\s*(?:async\s+)?(?:function\b|(?:\([^)]*\)|[\w$]+)\s*(?::[^=]+)?=>)".into()),
+                (Class, r"^\s*(?:export\s+)?(?:default\s+)?(?:abstract\s+)?class\s+(?P<name>[\w$]+)".into()),
+                (Interface, r"^\s*(?:export\s+)?(?:declare\s+)?interface\s+(?P<name>[\w$]+)".into()),
+                (Type, r"^\s*(?:export\s+)?(?:declare\s+)?type\s+(?P<name>[\w$]+)(?:<[^>]*>)?\s*=".into()),
+                (Enum, r"^\s*(?:export\s+)?(?:declare\s+)?(?:const\s+)?enum\s+(?P<name>[\w$]+)".into()),
+            ],
+            Self::Go => vec![
+                (Function, r"^func\s+(?:\([^)]*\)\s*)?(?P<name>\w+)".into()),
+                (Struct, r"^(?:type\s+|\s+)(?P<name>\w+)(?:\[[^\]]*\])?\s+struct\b".into()),
+                (Interface, r"^(?:type\s+|\s+)(?P<name>\w+)(?:\[[^\]]*\])?\s+interface\b".into()),
+            ],
+            Self::Java | Self::Kotlin | Self::CSharp => vec![
+                (Class, jvm(r"(?:class|record|object)\s+(?P<name>\w+)")),
+                (Interface, jvm(r"(?:interface|@interface)\s+(?P<name>\w+)")),
+                (Enum, jvm(r"enum(?:\s+class)?\s+(?P<name>\w+)")),
+                (Function, jvm(r"fun\s+(?:<[^>]*>\s*)?(?:[\w.]+\.)?(?P<name>\w+)\s*\(")),
+                // A return type, then the name and an opening parenthesis
+                (Function, r"^\s*(?:@\w+(?:\([^)]*\))?\s+)*(?:(?:public|private|protected|internal|static|final|abstract|override|virtual|async|synchronized)\s+)+(?:<[^>]*>\s+)?[\w.<>\[\],?]+\s+(?P<name>\w+)\s*\(".into()),
+            ],
+            Self::C | Self::Cpp => vec![
+                (Struct, r"^\s*(?:typedef\s+)?(?:struct|union)\s+(?P<name>\w+)\s*(?:\{.*)?$".into()),
+                (Class, r"^\s*(?:template\s*<[^>]*>\s*)?class\s+(?P<name>\w+)\s*(?:final\s*)?(?:[:{].*)?$".into()),
+                (Enum, r"^\s*(?:typedef\s+)?enum\s+(?:class\s+)?(?P<name>\w+)\s*(?:[:{].*)?$".into()),
+                (Module, r"^\s*namespace\s+(?P<name>[\w:]+)".into()),
+                // A definition at the start of a line: type, name, parameters, no `;`
+                (Function, r"^(?:[\w:*&<>,]+\s+)+[*&]*(?P<name>[\w:~]+)\s*\([^;]*$".into()),
+            ],
+        }
+    }
+}
+
+/// Kind of definition
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    /// A function or method
+    Function,
+    /// A struct or union
+    Struct,
+    /// A class, record or object
+    Class,
+    /// An enum
+    Enum,
+    /// A Rust trait
+    Trait,
+    /// An interface
+    Interface,
+    /// A Rust `impl` block
+    Impl,
+    /// A module or namespace
+    Module,
+    /// A type alias
+    Type,
+    /// A macro
+    Macro,
+}
+
+/// Parameters for the code outline tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Path of the source file
+    #[serde(deserialize_with = "crate::roots::path")]
+    pub path: String,
+
+    /// Language of the file, when its extension doesn't tell
+    #[serde(default)]
+    pub language: Option<Language>,
+
+    /// Only report these kinds of definitions (all if empty)
+    #[serde(default)]
+    pub kinds: Vec<SymbolKind>,
+}
+
+impl Params {
+    /// Outline the file at `path`
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            language: None,
+            kinds: Vec::new(),
+        }
+    }
+}
+
+/// A definition found in the file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct Symbol {
+    /// Kind of definition
+    pub kind: SymbolKind,
+
+    /// Name of the definition (for `impl`, the implemented type and trait)
+    pub name: String,
+
+    /// Line number (1-based)
+    pub line: usize,
+
+    /// Indentation of the line, as a hint of nesting
+    pub indent: usize,
+
+    /// The line the definition starts on, trimmed
+    pub signature: String,
+}
+
+/// Output of the code outline tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Path of the source file
+    pub path: String,
+
+    /// Language the file was read as
+    pub language: Language,
+
+    /// Definitions in the order they appear
+    pub symbols: Vec<Symbol>,
+
+    /// Number of lines in the file
+    pub total_lines: usize,
+}
+
+/// Words that look like a C function name but are statements
+const C_KEYWORDS: &[&str] = &["if", "for", "while", "switch", "return", "sizeof", "else"];
+
+/// Definitions in `source`, one per line at most
+pub fn outline(source: &str, language: Language) -> Vec<Symbol> {
+    let rules: Vec<(SymbolKind, Regex)> = language
+        .rules()
+        .into_iter()
+        .map(|(kind, rule)| {
+            (
+                kind,
+                Regex::new(&rule).expect("outline rules are valid regexes"),
+            )
+        })
+        .collect();
+    let comments = language.comments();
+
+    let mut symbols = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || comments.iter().any(|prefix| trimmed.starts_with(prefix)) {
+            continue;
+        }
+        let found = rules.iter().find_map(|(kind, regex)| {
+            let name = regex.captures(line)?.name("name")?.as_str().trim();
+            if matches!(language, Language::C | Language::Cpp) && C_KEYWORDS.contains(&name) {
+                return None;
+            }
+            Some((*kind, name.to_string()))
+        });
+        if let Some((kind, name)) = found {
+            let indent = line
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .map(|c| if c == '\t' { 4 } else { 1 })
+                .sum();
+            symbols.push(Symbol {
+                kind,
+                name,
+                line: i + 1,
+                indent,
+                signature: trimmed.chars().take(MAX_SIGNATURE).collect(),
+            });
+        }
+    }
+    symbols
+}
+
+#[async_trait]
+impl Tool for CodeOutline {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "code_outline"
+    }
+
+    fn description(&self) -> &str {
+        "List the functions, types and other definitions in a source file with their line numbers"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let path = PathBuf::from(&params.path);
+        let language = params
+            .language
+            .or_else(|| Language::from_path(&path))
+            .ok_or_else(|| {
+                Error::InvalidParam(format!(
+                    "Cannot tell the language of {}; set `language`",
+                    params.path
+                ))
+            })?;
+
+        let fs = vfs::current();
+        paths::require_file(fs.as_ref(), &path).await?;
+        let bytes = fs.read(&path).await?;
+        let source = String::from_utf8_lossy(&bytes);
+
+        let mut symbols = outline(&source, language);
+        if !params.kinds.is_empty() {
+            symbols.retain(|symbol| params.kinds.contains(&symbol.kind));
+        }
+
+        Ok(Output {
+            path: params.path,
+            language,
+            symbols,
+            total_lines: source.lines().count(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+    use std::sync::Arc;
+
+    fn names(source: &str, language: Language) -> Vec<(SymbolKind, String, usize)> {
+        outline(source, language)
+            .into_iter()
+            .map(|symbol| (symbol.kind, symbol.name, symbol.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_outline_rust() {
+        let source = r#"//! Docs
+use std::fmt;
+
+/// A point
+pub struct Point {
+    x: i32,
+}
+
+pub(crate) enum Shape {}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // fn not_this()
+        write!(f, "{}", self.x)
+    }
+}
+
+pub async fn run() {}
+
+mod tests {}
+"#;
+        let symbols = names(source, Language::Rust);
+        assert_eq!(
+            symbols,
+            vec![
+                (SymbolKind::Struct, "Point".to_string(), 5),
+                (SymbolKind::Enum, "Shape".to_string(), 9),
+                (SymbolKind::Impl, "fmt::Display for Point".to_string(), 11),
+                (SymbolKind::Function, "fmt".to_string(), 12),
+                (SymbolKind::Function, "run".to_string(), 18),
+                (SymbolKind::Module, "tests".to_string(), 20),
+            ]
+        );
+        assert_eq!(outline(source, Language::Rust)[3].indent, 4);
+    }
+
+    #[test]
+    fn test_outline_other_languages() {
+        let python = "class Tool:\n    async def run(self):\n        pass\n\ndef main():\n    # def not_this():\n    pass\n";
+        assert_eq!(
+            names(python, Language::Python),
+            vec![
+                (SymbolKind::Class, "Tool".to_string(), 1),
+                (SymbolKind::Function, "run".to_string(), 2),
+                (SymbolKind::Function, "main".to_string(), 5),
+            ]
+        );
+
+        let typescript = "export interface Props {}\nexport const handler = async (e: Event) => {};\nexport default class App {}\nfunction helper() {}\n";
+        assert_eq!(
+            names(typescript, Language::TypeScript),
+            vec![
+                (SymbolKind::Interface, "Props".to_string(), 1),
+                (SymbolKind::Function, "handler".to_string(), 2),
+                (SymbolKind::Class, "App".to_string(), 3),
+                (SymbolKind::Function, "helper".to_string(), 4),
+            ]
+        );
+
+        let go = "type Server struct {\n}\n\nfunc (s *Server) Start() error {\n\treturn nil\n}\n";
+        assert_eq!(
+            names(go, Language::Go),
+            vec![
+                (SymbolKind::Struct, "Server".to_string(), 1),
+                (SymbolKind::Function, "Start".to_string(), 4),
+            ]
+        );
+
+        let java = "public class Main {\n    public static void main(String[] args) {\n        if (x) {}\n    }\n}\n";
+        assert_eq!(
+            names(java, Language::Java),
+            vec![
+                (SymbolKind::Class, "Main".to_string(), 1),
+                (SymbolKind::Function, "main".to_string(), 2),
+            ]
+        );
+
+        let c =
+            "struct node {\n};\n\nstatic int count(struct node *n)\n{\n    if (n) return 1;\n}\n";
+        assert_eq!(
+            names(c, Language::C),
+            vec![
+                (SymbolKind::Struct, "node".to_string(), 1),
+                (SymbolKind::Function, "count".to_string(), 4),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_code_outline() -> Result<()> {
+        let fs = Arc::new(
+            MemoryFs::new()
+                .with_file("/src/lib.rs", "pub struct A;\n\nfn b() {}\n")
+                .with_file("/notes.txt", "fn looks_like_code() {}\n"),
+        );
+        vfs::scope(fs, async {
+            let tool = CodeOutline;
+
+            let result = tool.execute(Params::new("/src/lib.rs")).await?;
+            assert_eq!(result.language, Language::Rust);
+            assert_eq!(result.symbols.len(), 2);
+            assert_eq!(result.total_lines, 3);
+
+            let functions = Params {
+                kinds: vec![SymbolKind::Function],
+                ..Params::new("/src/lib.rs")
+            };
+            let result = tool.execute(functions).await?;
+            assert_eq!(result.symbols.len(), 1);
+            assert_eq!(result.symbols[0].name, "b");
+
+            // Unknown extensions need an explicit language
+            let result = tool.execute(Params::new("/notes.txt")).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+            let explicit = Params {
+                language: Some(Language::Rust),
+                ..Params::new("/notes.txt")
+            };
+            assert_eq!(tool.execute(explicit).await?.symbols.len(), 1);
+            Ok(())
+        })
+        .await
+    }
+}
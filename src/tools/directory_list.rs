@@ -2,22 +2,23 @@
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use glob::Pattern;
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
 use std::path::PathBuf;
-use tokio::fs;
 
-use super::Tool;
-use crate::{Error, Result};
+use super::{Annotations, Tool};
+use crate::{Error, Result, paths, vfs};
 
 /// Directory list tool
 #[derive(Clone, Copy)]
 pub struct DirectoryList;
 
 /// Parameters for the directory list tool
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Params {
     /// Path to the directory to list
+    #[serde(deserialize_with = "crate::roots::path")]
     pub path: String,
 
     /// Optional glob pattern to filter results
@@ -37,8 +38,21 @@ pub struct Params {
     pub files_only: bool,
 }
 
+impl Params {
+    /// List the directory at `path`
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            pattern: None,
+            include_hidden: false,
+            directories_only: false,
+            files_only: false,
+        }
+    }
+}
+
 /// File or directory entry information
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Entry {
     /// Name of the file or directory
     pub name: String,
@@ -58,7 +72,7 @@ pub struct Entry {
 }
 
 /// Output of the directory list tool
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Output {
     /// List of entries in the directory
     pub entries: Vec<Entry>,
@@ -76,11 +90,20 @@ impl Tool for DirectoryList {
         "directory_list"
     }
 
+    fn description(&self) -> &str {
+        "List contents of a directory"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
     async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
         let path = PathBuf::from(&params.path);
+        let fs = vfs::current();
 
         // Check if the path exists and is a directory
-        let metadata = fs::metadata(&path).await?;
+        let metadata = fs.metadata(&path).await?;
 
         if !metadata.is_dir() {
             return Err(Error::InvalidParam(format!(
@@ -89,24 +112,27 @@ impl Tool for DirectoryList {
             )));
         }
 
+        // Compile the filter pattern once up front
+        let pattern = params
+            .pattern
+            .as_deref()
+            .map(|p| {
+                Pattern::new(p)
+                    .map_err(|e| Error::InvalidParam(format!("Invalid pattern '{}': {}", p, e)))
+            })
+            .transpose()?;
+
         // Read directory entries
         let mut entries = Vec::new();
-        let mut dir = fs::read_dir(&path).await?;
-
-        while let Some(entry) = dir.next_entry().await? {
-            let file_name = entry.file_name();
-            let file_name_str = file_name.to_string_lossy().to_string();
+        for entry in fs.read_dir(&path).await? {
+            let file_name_str = entry.file_name();
 
             // Skip hidden files if include_hidden is false
             if !params.include_hidden && file_name_str.starts_with('.') {
                 continue;
             }
 
-            // Get file metadata
-            let metadata = match entry.metadata().await {
-                Ok(meta) => meta,
-                Err(_) => continue, // Skip if we can't get metadata
-            };
+            let metadata = &entry.metadata;
 
             let is_directory = metadata.is_dir();
 
@@ -116,11 +142,10 @@ impl Tool for DirectoryList {
             }
 
             // Apply pattern filtering if provided
-            if let Some(pattern) = &params.pattern {
-                // Simple glob-like pattern matching
-                if !matches_pattern(&file_name_str, pattern) {
-                    continue;
-                }
+            if let Some(pattern) = &pattern
+                && !pattern.matches(&file_name_str)
+            {
+                continue;
             }
 
             // Get modification time
@@ -142,7 +167,7 @@ impl Tool for DirectoryList {
 
             entries.push(Entry {
                 name: file_name_str,
-                path: entry.path().to_string_lossy().to_string(),
+                path: paths::to_string(entry.path()),
                 is_directory,
                 size: if is_directory { 0 } else { metadata.len() },
                 modified,
@@ -156,50 +181,11 @@ impl Tool for DirectoryList {
     }
 }
 
-/// Simple pattern matching function
-fn matches_pattern(name: &str, pattern: &str) -> bool {
-    if pattern.contains('*') {
-        let parts: Vec<&str> = pattern.split('*').collect();
-
-        if parts.is_empty() {
-            return true;
-        }
-
-        if pattern.starts_with('*') {
-            if pattern.ends_with('*') {
-                // *text*
-                if parts.len() > 1 {
-                    return name.contains(parts[1]);
-                }
-            } else {
-                // *text
-                return name.ends_with(parts[1]);
-            }
-        } else if pattern.ends_with('*') {
-            // text*
-            return name.starts_with(parts[0]);
-        } else {
-            // text*text
-            if !name.starts_with(parts[0]) {
-                return false;
-            }
-
-            if parts.len() > 1 && !name.ends_with(parts[parts.len() - 1]) {
-                return false;
-            }
-
-            return true;
-        }
-    }
-
-    // Exact match
-    name == pattern
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::io;
+    use tokio::fs;
     use tokio::fs::File;
     use tokio::fs::create_dir_all;
     use tokio::io::AsyncWriteExt;
@@ -232,13 +218,8 @@ mod tests {
         let tool = DirectoryList;
 
         // Test basic listing
-        let params = Params {
-            path: test_dir.to_string_lossy().to_string(),
-            pattern: None,
-            include_hidden: false,
-            directories_only: false,
-            files_only: false,
-        };
+        let path = test_dir.to_string_lossy();
+        let params = Params::new(path.clone());
 
         let result = tool.execute(params).await?;
 
@@ -247,11 +228,8 @@ mod tests {
 
         // Test with pattern
         let params = Params {
-            path: test_dir.to_string_lossy().to_string(),
             pattern: Some("*.txt".to_string()),
-            include_hidden: false,
-            directories_only: false,
-            files_only: false,
+            ..Params::new(path.clone())
         };
 
         let result = tool.execute(params).await?;
@@ -262,11 +240,8 @@ mod tests {
 
         // Test directories only
         let params = Params {
-            path: test_dir.to_string_lossy().to_string(),
-            pattern: None,
-            include_hidden: false,
             directories_only: true,
-            files_only: false,
+            ..Params::new(path.clone())
         };
 
         let result = tool.execute(params).await?;
@@ -277,11 +252,8 @@ mod tests {
 
         // Test including hidden files
         let params = Params {
-            path: test_dir.to_string_lossy().to_string(),
-            pattern: None,
             include_hidden: true,
-            directories_only: false,
-            files_only: false,
+            ..Params::new(path.clone())
         };
 
         let result = tool.execute(params).await?;
@@ -295,6 +267,10 @@ mod tests {
         Ok(())
     }
 
+    fn matches_pattern(name: &str, pattern: &str) -> bool {
+        Pattern::new(pattern).unwrap().matches(name)
+    }
+
     #[test]
     fn test_pattern_matching() {
         // Exact match
@@ -317,4 +293,45 @@ mod tests {
         assert!(matches_pattern("contains_text_inside", "*text*"));
         assert!(!matches_pattern("does_not_match", "*text*"));
     }
+
+    #[tokio::test]
+    async fn test_directory_list_patterns() -> Result<()> {
+        let memory = std::sync::Arc::new(
+            vfs::MemoryFs::new()
+                .with_file("/dir/my_test_data_file.rs", "")
+                .with_file("/dir/my_file_test.rs", "")
+                .with_file("/dir/log1.txt", "")
+                .with_file("/dir/log12.txt", "")
+                .with_file("/dir/b.rs", "")
+                .with_file("/dir/d.rs", ""),
+        );
+        vfs::scope(memory, async {
+            let list = async |pattern: &str| -> Result<Vec<String>> {
+                let params = Params {
+                    pattern: Some(pattern.to_string()),
+                    ..Params::new("/dir")
+                };
+                let mut names: Vec<String> = DirectoryList
+                    .execute(params)
+                    .await?
+                    .entries
+                    .into_iter()
+                    .map(|entry| entry.name)
+                    .collect();
+                names.sort();
+                Ok(names)
+            };
+
+            // Multiple wildcards must match in order
+            assert_eq!(list("*test*file*").await?, ["my_test_data_file.rs"]);
+            // Single-character wildcard and character classes
+            assert_eq!(list("log?.txt").await?, ["log1.txt"]);
+            assert_eq!(list("[abc].rs").await?, ["b.rs"]);
+
+            let result = list("[abc").await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+            Ok(())
+        })
+        .await
+    }
 }
@@ -4,19 +4,21 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
 use std::path::PathBuf;
-use tokio::fs;
 
-use super::Tool;
-use crate::{Error, Result};
+use super::mode::parse_mode;
+use super::{Annotations, Tool};
+use crate::vfs::{self, Filesystem};
+use crate::{Error, Result, logging};
 
 /// Directory make tool
 #[derive(Clone, Copy)]
 pub struct DirectoryMake;
 
 /// Parameters for the directory make tool
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Params {
     /// Path of the directory to create
+    #[serde(deserialize_with = "crate::roots::path")]
     pub path: String,
 
     /// Whether to create parent directories if they don't exist
@@ -26,16 +28,75 @@ pub struct Params {
     /// Don't throw an error if the directory already exists
     #[serde(default)]
     pub exist_ok: bool,
+
+    /// Permissions for the created directories, including any parents, as an
+    /// octal string, e.g. "2775" (Unix only; the umask doesn't apply)
+    #[serde(default)]
+    pub mode: Option<String>,
+
+    /// Set the setgid bit on the created directories, so files created in them
+    /// belong to the directory's group, as shared directories need (Unix only)
+    #[serde(default)]
+    pub setgid: bool,
+}
+
+impl Params {
+    /// Create the directory at `path`
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            parents: false,
+            exist_ok: false,
+            mode: None,
+            setgid: false,
+        }
+    }
 }
 
 /// Output of the directory make tool
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Output {
     /// Path of the created directory
     pub path: String,
 
     /// Whether the directory was created (true) or already existed (false)
     pub created: bool,
+
+    /// Permissions of the created directory as an octal string (Unix only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+/// Apply the requested permissions to the created directories, parents first,
+/// and return the resulting mode of the last one
+async fn apply_mode(
+    fs: &dyn Filesystem,
+    created: &[PathBuf],
+    mode: Option<u32>,
+    setgid: bool,
+) -> Result<Option<String>> {
+    let mut last = None;
+    for dir in created.iter().rev() {
+        let Some(current_mode) = fs.metadata(dir).await?.mode else {
+            if mode.is_some() || setgid {
+                logging::call_log!(
+                    log::Level::Warn,
+                    "Directory permissions are not supported here, ignoring mode"
+                );
+            }
+            return Ok(None);
+        };
+        let mut new_mode = mode.unwrap_or(current_mode & 0o7777);
+        if setgid {
+            new_mode |= 0o2000;
+        }
+
+        if mode.is_some() || setgid {
+            fs.set_mode(dir, new_mode).await?;
+        }
+        last = Some(format!("{:o}", new_mode));
+    }
+    Ok(last)
 }
 
 #[async_trait]
@@ -47,14 +108,27 @@ impl Tool for DirectoryMake {
         "directory_make"
     }
 
+    fn description(&self) -> &str {
+        "Create a directory"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations {
+            destructive: false,
+            ..Annotations::DESTRUCTIVE
+        }
+    }
+
     async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
         let path = PathBuf::from(&params.path);
+        let fs = vfs::current();
 
-        // Check if the directory already exists
-        let already_exists = path.exists();
+        // Validate the mode before creating anything
+        let mode = params.mode.as_deref().map(parse_mode).transpose()?;
 
-        if already_exists {
-            if !path.is_dir() {
+        // Check if the directory already exists
+        if let Ok(metadata) = fs.metadata(&path).await {
+            if !metadata.is_dir() {
                 return Err(Error::InvalidParam(format!(
                     "Path exists but is not a directory: {}",
                     params.path
@@ -62,7 +136,7 @@ impl Tool for DirectoryMake {
             }
 
             if !params.exist_ok {
-                return Err(Error::InvalidParam(format!(
+                return Err(Error::AlreadyExists(format!(
                     "Directory already exists: {}",
                     params.path
                 )));
@@ -72,24 +146,34 @@ impl Tool for DirectoryMake {
             return Ok(Output {
                 path: params.path,
                 created: false,
+                mode: None,
             });
         }
 
+        // Note the parents that are missing too, as they get the mode as well
+        let mut missing = vec![path.clone()];
+        if params.parents {
+            let mut ancestor = path.parent();
+            while let Some(dir) = ancestor
+                && !dir.as_os_str().is_empty()
+                && !fs.exists(dir).await
+            {
+                missing.push(dir.to_path_buf());
+                ancestor = dir.parent();
+            }
+        }
+
         // Create the directory
         let result = if params.parents {
-            fs::create_dir_all(&path).await
+            fs.create_dir_all(&path).await
         } else {
-            fs::create_dir(&path).await
+            fs.create_dir(&path).await
         };
 
         // Handle creation errors
-        match result {
-            Ok(_) => Ok(Output {
-                path: params.path,
-                created: true,
-            }),
-            Err(e) => match e.kind() {
-                std::io::ErrorKind::NotFound => Err(Error::InvalidParam(format!(
+        if let Err(e) = result {
+            return match e.kind() {
+                std::io::ErrorKind::NotFound => Err(Error::NotFound(format!(
                     "Parent directory does not exist: {}",
                     path.parent().unwrap_or(&path).display()
                 ))),
@@ -98,8 +182,16 @@ impl Tool for DirectoryMake {
                     params.path
                 ))),
                 _ => Err(Error::Io(e)),
-            },
+            };
         }
+
+        let mode = apply_mode(fs.as_ref(), &missing, mode, params.setgid).await?;
+
+        Ok(Output {
+            path: params.path,
+            created: true,
+            mode,
+        })
     }
 }
 
@@ -161,6 +253,8 @@ mod tests {
             path: path_str.clone(),
             parents: false,
             exist_ok: false,
+            mode: None,
+            setgid: false,
         };
 
         let result = tool.execute(params).await?;
@@ -203,6 +297,8 @@ mod tests {
             path: nested_path.clone(),
             parents: true,
             exist_ok: false,
+            mode: None,
+            setgid: false,
         };
 
         let result = tool.execute(params).await?;
@@ -231,6 +327,8 @@ mod tests {
             path: nested_dir.to_string_lossy().to_string(),
             parents: false,
             exist_ok: false,
+            mode: None,
+            setgid: false,
         };
 
         let result = tool.execute(params).await;
@@ -271,6 +369,8 @@ mod tests {
             path: test_dir.to_string_lossy().to_string(),
             parents: false,
             exist_ok: false,
+            mode: None,
+            setgid: false,
         };
 
         let result = tool.execute(params).await;
@@ -286,6 +386,8 @@ mod tests {
             path: test_dir.to_string_lossy().to_string(),
             parents: false,
             exist_ok: true,
+            mode: None,
+            setgid: false,
         };
 
         let result = tool.execute(params).await?;
@@ -300,4 +402,39 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_directory_make_mode() -> Result<()> {
+        let fs = std::sync::Arc::new(vfs::MemoryFs::new());
+        fs.create_dir(Path::new("/srv")).await?;
+        let srv_mode = fs.metadata(Path::new("/srv")).await?.mode;
+
+        vfs::scope(fs.clone(), async {
+            let params = Params {
+                parents: true,
+                mode: Some("770".to_string()),
+                setgid: true,
+                ..Params::new("/srv/shared/team")
+            };
+            let result = DirectoryMake.execute(params).await?;
+            assert_eq!(result.mode.as_deref(), Some("2770"));
+
+            // Created parents get the mode too, existing ones are left alone
+            for dir in ["/srv/shared", "/srv/shared/team"] {
+                let mode = fs.metadata(Path::new(dir)).await?.mode.unwrap();
+                assert_eq!(mode & 0o7777, 0o2770, "{}", dir);
+            }
+            assert_eq!(fs.metadata(Path::new("/srv")).await?.mode, srv_mode);
+
+            let params = Params {
+                mode: Some("rwx".to_string()),
+                ..Params::new("/srv/other")
+            };
+            let result = DirectoryMake.execute(params).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+            assert!(!fs.exists(Path::new("/srv/other")).await);
+            Ok(())
+        })
+        .await
+    }
 }
@@ -0,0 +1,557 @@
+//! Directory sync tool implementation
+//!
+//! Makes a destination tree mirror a source tree in one direction, like
+//! `rsync -r`. Files missing from the destination are copied, files that
+//! differ are replaced, and with `delete` anything the source doesn't have is
+//! removed. A file counts as changed when its size or modification time
+//! differs, or with `compare: "content"` when its bytes do. Copies keep the
+//! source's modification time, so an unchanged tree is left alone next time.
+//!
+//! With `dry_run` the planned actions are reported without touching anything,
+//! and with `use_trash` deleted and replaced files go to the
+//! [trash](crate::trash) instead.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use super::file_compare;
+use super::{Annotations, Tool};
+use crate::vfs::{FileKind, Filesystem, Metadata, WalkOptions};
+use crate::{Error, Result, cancel, paths, trash, vfs};
+
+/// Directory sync tool
+#[derive(Clone, Copy)]
+pub struct DirectorySync;
+
+/// How to tell whether a file changed
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Compare {
+    /// Size and modification time
+    #[default]
+    Metadata,
+    /// Size and content, for trees whose modification times can't be trusted
+    Content,
+}
+
+/// Parameters for the directory sync tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Directory to copy from
+    #[serde(deserialize_with = "crate::roots::path")]
+    pub source: String,
+
+    /// Directory to make a mirror of `source`; created if missing
+    #[serde(deserialize_with = "crate::roots::path")]
+    pub destination: String,
+
+    /// How to tell whether a file changed
+    #[serde(default)]
+    pub compare: Compare,
+
+    /// Remove files and directories that aren't in `source`
+    #[serde(default)]
+    pub delete: bool,
+
+    /// Only report the actions that would be taken
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Move deleted and replaced files to the trash instead of removing them
+    /// for good
+    #[serde(default)]
+    pub use_trash: bool,
+}
+
+impl Params {
+    /// Mirror `source` into `destination`
+    pub fn new(source: impl Into<String>, destination: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            destination: destination.into(),
+            compare: Compare::default(),
+            delete: false,
+            dry_run: false,
+            use_trash: false,
+        }
+    }
+}
+
+/// What was done to a path
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    /// A directory was created
+    CreateDir,
+    /// A file that was missing was copied
+    Copy,
+    /// A file that differed was replaced
+    Update,
+    /// A file or directory not in the source was removed
+    Delete,
+}
+
+/// An action taken, or planned with `dry_run`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct Action {
+    /// What was done
+    pub action: ActionKind,
+
+    /// Path relative to the source and destination
+    pub path: String,
+}
+
+/// Output of the directory sync tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Directory copied from
+    pub source: String,
+
+    /// Directory copied to
+    pub destination: String,
+
+    /// Whether the actions were only planned
+    pub dry_run: bool,
+
+    /// Actions in the order they were (or would be) taken
+    pub actions: Vec<Action>,
+
+    /// Number of files copied or replaced
+    pub files_copied: usize,
+
+    /// Number of files and directories removed
+    pub deleted: usize,
+
+    /// Number of files that were already up to date
+    pub unchanged: usize,
+}
+
+/// Entries below `root`, keyed by their path relative to it
+async fn entries(fs: &dyn Filesystem, root: &Path) -> Result<BTreeMap<PathBuf, Metadata>> {
+    let options = WalkOptions {
+        max_depth: usize::MAX,
+        follow_links: false,
+    };
+    let entries = fs.walk(root, options).await?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| {
+            // The last `depth` components, whatever form the walk returned
+            // the root in
+            let components: Vec<_> = entry.path.components().collect();
+            let relative: PathBuf = components[components.len().saturating_sub(entry.depth)..]
+                .iter()
+                .collect();
+            (relative, entry.metadata)
+        })
+        .collect())
+}
+
+/// `path`, canonicalized as far as it exists, for checking that two trees
+/// don't overlap
+async fn resolve(fs: &dyn Filesystem, path: &Path) -> PathBuf {
+    if let Ok(canonical) = fs.canonicalize(path).await {
+        return canonical;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            match fs.canonicalize(parent).await {
+                Ok(parent) => parent.join(name),
+                Err(_) => path.to_path_buf(),
+            }
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Whether the destination file needs to be replaced by the source file
+async fn changed(
+    fs: &dyn Filesystem,
+    compare: Compare,
+    (source, source_meta): (&Path, &Metadata),
+    (destination, destination_meta): (&Path, &Metadata),
+) -> Result<bool> {
+    if source_meta.len() != destination_meta.len() {
+        return Ok(true);
+    }
+    match compare {
+        Compare::Metadata => {
+            Ok(source_meta.modified.is_none() || source_meta.modified != destination_meta.modified)
+        }
+        Compare::Content => Ok(file_compare::difference(fs, source, destination)
+            .await?
+            .is_some()),
+    }
+}
+
+#[async_trait]
+impl Tool for DirectorySync {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "directory_sync"
+    }
+
+    fn description(&self) -> &str {
+        "Make a destination directory mirror a source directory"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations {
+            idempotent: true,
+            ..Annotations::DESTRUCTIVE
+        }
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let fs = vfs::current();
+        let fs = fs.as_ref();
+        let source = Path::new(&params.source);
+        let destination = Path::new(&params.destination);
+
+        match fs.metadata(source).await {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) => {
+                return Err(Error::InvalidParam(format!(
+                    "Source is not a directory: {}",
+                    params.source
+                )));
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(Error::NotFound(format!(
+                    "Source directory not found: {}",
+                    params.source
+                )));
+            }
+            Err(e) => return Err(Error::Io(e)),
+        }
+        let destination_exists = match fs.metadata(destination).await {
+            Ok(metadata) if metadata.is_dir() => true,
+            Ok(_) => {
+                return Err(Error::InvalidParam(format!(
+                    "Destination is not a directory: {}",
+                    params.destination
+                )));
+            }
+            Err(_) => false,
+        };
+
+        // Copying a tree into itself would never settle
+        let (resolved_source, resolved_destination) =
+            (resolve(fs, source).await, resolve(fs, destination).await);
+        if resolved_destination.starts_with(&resolved_source)
+            || resolved_source.starts_with(&resolved_destination)
+        {
+            return Err(Error::InvalidParam(format!(
+                "Source and destination overlap: {} and {}",
+                params.source, params.destination
+            )));
+        }
+
+        let source_entries = entries(fs, source).await?;
+        let destination_entries = if destination_exists {
+            entries(fs, destination).await?
+        } else {
+            BTreeMap::new()
+        };
+
+        // Plan: creations and copies parents first, then deletions children first
+        let mut planned = Vec::new();
+        let mut unchanged = 0;
+        if !destination_exists {
+            planned.push((ActionKind::CreateDir, PathBuf::new()));
+        }
+        for (relative, source_meta) in &source_entries {
+            cancel::check()?;
+            let existing = destination_entries.get(relative);
+            match source_meta.kind {
+                FileKind::Dir => match existing {
+                    Some(meta) if meta.is_dir() => {}
+                    Some(_) => {
+                        planned.push((ActionKind::Delete, relative.clone()));
+                        planned.push((ActionKind::CreateDir, relative.clone()));
+                    }
+                    None => planned.push((ActionKind::CreateDir, relative.clone())),
+                },
+                FileKind::File => match existing {
+                    None => planned.push((ActionKind::Copy, relative.clone())),
+                    // Copying onto a link would write through it, so the link goes first
+                    Some(meta) if meta.kind == FileKind::Symlink => {
+                        planned.push((ActionKind::Delete, relative.clone()));
+                        planned.push((ActionKind::Copy, relative.clone()));
+                    }
+                    Some(meta) if meta.is_dir() => {
+                        return Err(Error::Conflict(format!(
+                            "Destination has a directory where the source has a file: {}",
+                            relative.display()
+                        )));
+                    }
+                    Some(meta) => {
+                        let from = source.join(relative);
+                        let to = destination.join(relative);
+                        if changed(fs, params.compare, (&from, source_meta), (&to, meta)).await? {
+                            planned.push((ActionKind::Update, relative.clone()));
+                        } else {
+                            unchanged += 1;
+                        }
+                    }
+                },
+                // Links are neither followed nor copied
+                FileKind::Symlink => {}
+            }
+        }
+        if params.delete {
+            for relative in destination_entries.keys().rev() {
+                if !source_entries.contains_key(relative) {
+                    planned.push((ActionKind::Delete, relative.clone()));
+                }
+            }
+        }
+
+        if !params.dry_run {
+            for (action, relative) in &planned {
+                cancel::check()?;
+                let from = source.join(relative);
+                let to = destination.join(relative);
+                match action {
+                    ActionKind::CreateDir => fs.create_dir_all(&to).await?,
+                    ActionKind::Copy | ActionKind::Update => {
+                        if *action == ActionKind::Update && params.use_trash {
+                            trash::discard(fs, &to).await?;
+                        }
+                        fs.copy(&from, &to).await?;
+                        // Without the time the file would look changed next time
+                        let modified = source_entries[relative].modified;
+                        match fs.set_times(&to, None, modified).await {
+                            Err(e) if e.kind() != ErrorKind::Unsupported => return Err(e.into()),
+                            _ => {}
+                        }
+                    }
+                    ActionKind::Delete => {
+                        // Directories are empty by now, their content having
+                        // been deleted first
+                        if destination_entries[relative].is_dir() {
+                            fs.remove_dir(&to).await?;
+                        } else if params.use_trash {
+                            trash::discard(fs, &to).await?;
+                        } else {
+                            fs.remove_file(&to).await?;
+                        }
+                    }
+                }
+            }
+        }
+
+        let count = |kinds: &[ActionKind]| {
+            planned
+                .iter()
+                .filter(|(action, _)| kinds.contains(action))
+                .count()
+        };
+        let files_copied = count(&[ActionKind::Copy, ActionKind::Update]);
+        let deleted = count(&[ActionKind::Delete]);
+        let actions = planned
+            .iter()
+            .map(|(action, relative)| Action {
+                action: *action,
+                path: if relative.as_os_str().is_empty() {
+                    ".".to_string()
+                } else {
+                    paths::to_string(relative)
+                },
+            })
+            .collect();
+
+        Ok(Output {
+            source: params.source,
+            destination: params.destination,
+            dry_run: params.dry_run,
+            actions,
+            files_copied,
+            deleted,
+            unchanged,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+    use std::sync::Arc;
+
+    fn action(action: ActionKind, path: &str) -> Action {
+        Action {
+            action,
+            path: path.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_directory_sync() -> Result<()> {
+        let fs = Arc::new(
+            MemoryFs::new()
+                .with_file("/src/a.txt", "new a")
+                .with_file("/src/sub/b.txt", "b")
+                .with_file("/dst/a.txt", "old a")
+                .with_file("/dst/extra.txt", "extra")
+                .with_file("/dst/old/c.txt", "c"),
+        );
+        vfs::scope(fs.clone(), async {
+            let tool = DirectorySync;
+            let params = || Params {
+                delete: true,
+                ..Params::new("/src", "/dst")
+            };
+            let expected = vec![
+                action(ActionKind::Update, "a.txt"),
+                action(ActionKind::CreateDir, "sub"),
+                action(ActionKind::Copy, "sub/b.txt"),
+                action(ActionKind::Delete, "old/c.txt"),
+                action(ActionKind::Delete, "old"),
+                action(ActionKind::Delete, "extra.txt"),
+            ];
+
+            // A dry run only plans
+            let result = tool
+                .execute(Params {
+                    dry_run: true,
+                    ..params()
+                })
+                .await?;
+            assert_eq!(result.actions, expected);
+            assert_eq!(fs.read_to_string(Path::new("/dst/a.txt")).await?, "old a");
+
+            let result = tool.execute(params()).await?;
+            assert_eq!(result.actions, expected);
+            assert_eq!((result.files_copied, result.deleted), (2, 3));
+            assert_eq!(fs.read_to_string(Path::new("/dst/a.txt")).await?, "new a");
+            assert_eq!(fs.read_to_string(Path::new("/dst/sub/b.txt")).await?, "b");
+            assert!(!fs.exists(Path::new("/dst/extra.txt")).await);
+            assert!(!fs.exists(Path::new("/dst/old")).await);
+
+            // Copies keep their modification time, so nothing is left to do
+            let result = tool.execute(params()).await?;
+            assert!(result.actions.is_empty());
+            assert_eq!(result.unchanged, 2);
+
+            let result = tool
+                .execute(Params {
+                    compare: Compare::Content,
+                    ..params()
+                })
+                .await?;
+            assert!(result.actions.is_empty());
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_directory_sync_to_trash() -> Result<()> {
+        let fs = Arc::new(
+            MemoryFs::new()
+                .with_file("/src/a.txt", "new a")
+                .with_file("/dst/a.txt", "old a")
+                .with_file("/dst/old/c.txt", "c"),
+        );
+        let run = vfs::scope(fs.clone(), async {
+            let params = Params {
+                delete: true,
+                use_trash: true,
+                ..Params::new("/src", "/dst")
+            };
+            DirectorySync.execute(params).await
+        });
+        trash::scope(PathBuf::from("/trash"), run).await?;
+
+        assert_eq!(fs.read_to_string(Path::new("/dst/a.txt")).await?, "new a");
+        assert!(!fs.exists(Path::new("/dst/old")).await);
+        let mut trashed = Vec::new();
+        for entry in fs.read_dir(Path::new("/trash")).await? {
+            trashed.push(fs.read_to_string(&entry.path).await?);
+        }
+        trashed.sort();
+        assert_eq!(trashed, ["c", "old a"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_directory_sync_new_destination() -> Result<()> {
+        let fs = Arc::new(MemoryFs::new().with_file("/src/a.txt", "a"));
+        vfs::scope(fs.clone(), async {
+            let result = DirectorySync
+                .execute(Params::new("/src", "/backup/src"))
+                .await?;
+            assert_eq!(
+                result.actions,
+                vec![
+                    action(ActionKind::CreateDir, "."),
+                    action(ActionKind::Copy, "a.txt"),
+                ]
+            );
+            assert_eq!(
+                fs.read_to_string(Path::new("/backup/src/a.txt")).await?,
+                "a"
+            );
+
+            // Without delete, extra files stay
+            fs.write(Path::new("/backup/src/extra.txt"), b"extra")
+                .await?;
+            let result = DirectorySync
+                .execute(Params::new("/src", "/backup/src"))
+                .await?;
+            assert!(result.actions.is_empty());
+            assert!(fs.exists(Path::new("/backup/src/extra.txt")).await);
+
+            let result = DirectorySync
+                .execute(Params::new("/src", "/src/copy"))
+                .await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+            Ok(())
+        })
+        .await
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_directory_sync_replaces_links() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "directory_sync_links_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        std::fs::create_dir_all(dir.join("src"))?;
+        std::fs::create_dir_all(dir.join("dst"))?;
+        std::fs::write(dir.join("src/a.txt"), "new")?;
+        std::fs::write(dir.join("outside.txt"), "keep")?;
+        std::os::unix::fs::symlink(dir.join("outside.txt"), dir.join("dst/a.txt"))?;
+
+        let result = DirectorySync
+            .execute(Params::new(
+                dir.join("src").to_string_lossy(),
+                dir.join("dst").to_string_lossy(),
+            ))
+            .await?;
+        assert_eq!(
+            result.actions,
+            vec![
+                action(ActionKind::Delete, "a.txt"),
+                action(ActionKind::Copy, "a.txt"),
+            ]
+        );
+
+        // The link was replaced, not written through
+        assert!(!std::fs::symlink_metadata(dir.join("dst/a.txt"))?.is_symlink());
+        assert_eq!(std::fs::read_to_string(dir.join("dst/a.txt"))?, "new");
+        assert_eq!(std::fs::read_to_string(dir.join("outside.txt"))?, "keep");
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
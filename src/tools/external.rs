@@ -0,0 +1,320 @@
+//! External tools implemented by other programs
+//!
+//! A tool can be declared in a JSON config instead of written in Rust:
+//!
+//! ```json
+//! {
+//!   "tools": [{
+//!     "name": "lint",
+//!     "description": "Lint a file",
+//!     "command": "/usr/local/bin/lint-tool",
+//!     "args": ["--json"],
+//!     "timeout_ms": 10000,
+//!     "parameters": {"type": "object", "properties": {"path": {"type": "string"}}}
+//!   }]
+//! }
+//! ```
+//!
+//! For every call the program is started, gets the parameters as JSON on
+//! stdin, and must print its result as JSON on stdout and exit with status 0.
+//! A non-zero exit status fails the call with the program's stderr as the
+//! message. `parameters` is the JSON schema advertised for the tool. The
+//! program gets the call's correlation id in `GAMECODE_CORRELATION_ID`.
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time;
+
+use super::{Annotations, Tool};
+use crate::jsonrpc::Dispatcher;
+use crate::logging;
+use crate::schema::{ToolSchema, ToolSchemaRegistry};
+use crate::{Error, Result};
+
+/// A set of external tools
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PluginConfig {
+    /// The tools to register
+    #[serde(default)]
+    pub tools: Vec<ExternalToolConfig>,
+}
+
+/// Declaration of an external tool
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExternalToolConfig {
+    /// Method name of the tool
+    pub name: String,
+
+    /// Description advertised to the model
+    #[serde(default)]
+    pub description: String,
+
+    /// Program to run
+    pub command: String,
+
+    /// Arguments for the program
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// Extra environment variables for the program
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+
+    /// Working directory of the program (defaults to the server's)
+    #[serde(default)]
+    pub cwd: Option<String>,
+
+    /// Timeout in milliseconds (0 for no timeout)
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+
+    /// JSON schema of the parameters
+    #[serde(default = "default_parameters")]
+    pub parameters: Value,
+
+    /// Hints about the tool's effects, as MCP annotations (`readOnlyHint`,
+    /// `destructiveHint`, `idempotentHint`, `openWorldHint`)
+    #[serde(default)]
+    pub annotations: Annotations,
+}
+
+fn default_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_parameters() -> Value {
+    serde_json::json!({"type": "object"})
+}
+
+impl PluginConfig {
+    /// Load a config from a JSON file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::InvalidParam(format!("Cannot read {}: {}", path.display(), e)))?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Register the tools with a dispatcher and their schemas with a registry.
+    /// A tool may not replace a method that is already registered.
+    pub fn register(
+        &self,
+        dispatcher: &mut Dispatcher,
+        registry: &mut ToolSchemaRegistry,
+    ) -> Result<()> {
+        for config in &self.tools {
+            if config.name.is_empty() || config.command.is_empty() {
+                return Err(Error::InvalidParam(
+                    "External tools need a name and a command".to_string(),
+                ));
+            }
+            if dispatcher.has_method(&config.name) {
+                return Err(Error::AlreadyExists(format!(
+                    "A tool named {} is already registered",
+                    config.name
+                )));
+            }
+
+            let schema = ToolSchema {
+                name: config.name.clone(),
+                description: config.description.clone(),
+                parameters_schema: config.parameters.clone(),
+                annotations: config.annotations,
+                result_schema: None,
+            };
+            dispatcher.list_tool(&schema);
+            registry.register_schema(schema);
+
+            let tool = ExternalTool::new(config.clone());
+            dispatcher.register(&config.name, move |params: Value| {
+                let tool = tool.clone();
+                async move { tool.execute(params).await }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A tool run as an external program
+#[derive(Debug, Clone)]
+pub struct ExternalTool {
+    config: Arc<ExternalToolConfig>,
+}
+
+impl ExternalTool {
+    /// Create a tool from its declaration
+    pub fn new(config: ExternalToolConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for ExternalTool {
+    type Params = Value;
+    type Output = Value;
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn description(&self) -> &str {
+        &self.config.description
+    }
+
+    fn annotations(&self) -> Annotations {
+        self.config.annotations
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let config = &self.config;
+
+        let mut cmd = Command::new(&config.command);
+        cmd.args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(cwd) = &config.cwd {
+            cmd.current_dir(cwd);
+        }
+        if let Some(id) = logging::correlation_id() {
+            cmd.env("GAMECODE_CORRELATION_ID", id);
+        }
+
+        let mut child = cmd.spawn().map_err(|e| {
+            Error::Other(format!(
+                "Failed to start {} for tool {}: {}",
+                config.command, config.name, e
+            ))
+        })?;
+
+        // Feed the parameters while the output is collected, so a program that
+        // writes before reading everything can't deadlock
+        let input = serde_json::to_vec(&params)?;
+        if let Some(mut stdin) = child.stdin.take() {
+            tokio::spawn(async move {
+                let _ = stdin.write_all(&input).await;
+            });
+        }
+
+        // Dropping the child on timeout kills it
+        let output = if config.timeout_ms > 0 {
+            time::timeout(
+                Duration::from_millis(config.timeout_ms),
+                child.wait_with_output(),
+            )
+            .await
+            .map_err(|_| {
+                Error::Timeout(format!(
+                    "Tool {} timed out after {} ms",
+                    config.name, config.timeout_ms
+                ))
+            })??
+        } else {
+            child.wait_with_output().await?
+        };
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::Other(format!(
+                "Tool {} failed ({}): {}",
+                config.name,
+                output.status,
+                stderr.trim()
+            )));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&stdout)
+            .map_err(|e| Error::Other(format!("Tool {} printed invalid JSON: {}", config.name, e)))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sh(name: &str, script: &str, timeout_ms: u64) -> ExternalToolConfig {
+        serde_json::from_value(json!({
+            "name": name,
+            "command": "sh",
+            "args": ["-c", script],
+            "timeout_ms": timeout_ms,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_external_tool() -> Result<()> {
+        let echo = ExternalTool::new(sh("echo", "cat", 5000));
+        let result = echo.execute(json!({"path": "a.txt", "n": 2})).await?;
+        assert_eq!(result, json!({"path": "a.txt", "n": 2}));
+
+        let failing = ExternalTool::new(sh("fail", "echo boom >&2; exit 3", 5000));
+        let err = failing.execute(json!({})).await.unwrap_err().to_string();
+        assert!(err.contains("boom"), "{}", err);
+
+        let garbage = ExternalTool::new(sh("garbage", "echo not json", 5000));
+        assert!(garbage.execute(json!({})).await.is_err());
+
+        let slow = ExternalTool::new(sh("slow", "sleep 5", 100));
+        let err = slow.execute(json!({})).await.unwrap_err().to_string();
+        assert!(err.contains("timed out"), "{}", err);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_register_plugins() -> Result<()> {
+        let (mut dispatcher, mut registry) =
+            crate::create_dispatcher_with_schema_registry(crate::FormatTransformer::standard());
+        let config = PluginConfig {
+            tools: vec![sh("echo", "cat", 5000)],
+        };
+        config.register(&mut dispatcher, &mut registry)?;
+        assert!(registry.get("echo").is_some());
+
+        let response = dispatcher
+            .dispatch(r#"{"jsonrpc": "2.0", "method": "echo", "params": {"x": 1}, "id": 1}"#)
+            .await?;
+        let response: Value = serde_json::from_str(&response)?;
+        assert_eq!(response["result"], json!({"x": 1}));
+
+        // Plugins show up in tools/list, with the most cautious annotations
+        let response = dispatcher
+            .dispatch(r#"{"jsonrpc": "2.0", "method": "tools/list", "params": {}, "id": 2}"#)
+            .await?;
+        let response: Value = serde_json::from_str(&response)?;
+        let tools = response["result"]["tools"].as_array().unwrap();
+        let echo = tools.iter().find(|tool| tool["name"] == "echo").unwrap();
+        assert_eq!(echo["annotations"]["openWorldHint"], true);
+        let read = tools
+            .iter()
+            .find(|tool| tool["name"] == "file_read")
+            .unwrap();
+        assert_eq!(read["annotations"]["readOnlyHint"], true);
+
+        // Built-in tools can't be replaced
+        let clash = PluginConfig {
+            tools: vec![sh("file_read", "cat", 5000)],
+        };
+        assert!(clash.register(&mut dispatcher, &mut registry).is_err());
+
+        Ok(())
+    }
+}
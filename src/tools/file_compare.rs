@@ -0,0 +1,249 @@
+//! File compare tool implementation
+//!
+//! Tells whether two files have the same content without producing a diff.
+//! Files of different sizes are reported as different from their metadata
+//! alone; otherwise both are read side by side in chunks, stopping at the
+//! first difference.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{Annotations, Tool};
+use crate::vfs::Filesystem;
+use crate::{Result, cancel, paths, vfs};
+
+/// Bytes read from each file at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// File compare tool
+#[derive(Clone, Copy)]
+pub struct FileCompare;
+
+/// Parameters for the file compare tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Path to the first file
+    #[serde(deserialize_with = "crate::roots::path")]
+    pub file1: String,
+
+    /// Path to the second file
+    #[serde(deserialize_with = "crate::roots::path")]
+    pub file2: String,
+}
+
+impl Params {
+    /// Compare `file1` with `file2`
+    pub fn new(file1: impl Into<String>, file2: impl Into<String>) -> Self {
+        Self {
+            file1: file1.into(),
+            file2: file2.into(),
+        }
+    }
+}
+
+/// Output of the file compare tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Path to the first file
+    pub file1: String,
+
+    /// Path to the second file
+    pub file2: String,
+
+    /// Whether both files have exactly the same content
+    pub identical: bool,
+
+    /// Size of the first file in bytes
+    pub size1: u64,
+
+    /// Size of the second file in bytes
+    pub size2: u64,
+
+    /// Byte offset of the first difference, when the sizes are equal but the
+    /// content isn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_difference: Option<u64>,
+}
+
+/// Offset of the first difference between two files of the same size, if any
+pub(crate) async fn difference(
+    fs: &dyn Filesystem,
+    path1: &Path,
+    path2: &Path,
+) -> Result<Option<u64>> {
+    let mut reader1 = fs.open(path1).await?;
+    let mut reader2 = fs.open(path2).await?;
+    first_difference(reader1.as_mut(), reader2.as_mut()).await
+}
+
+/// Read into `buf` until it is full or the reader is exhausted
+async fn fill(reader: &mut (dyn AsyncRead + Send + Unpin), buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader.read(&mut buf[filled..]).await?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+/// Offset of the first byte where the two readers differ, if any
+async fn first_difference(
+    reader1: &mut (dyn AsyncRead + Send + Unpin),
+    reader2: &mut (dyn AsyncRead + Send + Unpin),
+) -> Result<Option<u64>> {
+    let mut buf1 = vec![0; CHUNK_SIZE];
+    let mut buf2 = vec![0; CHUNK_SIZE];
+    let mut offset = 0u64;
+
+    loop {
+        cancel::check()?;
+        let n1 = fill(reader1, &mut buf1).await?;
+        let n2 = fill(reader2, &mut buf2).await?;
+
+        if let Some(i) = buf1[..n1.min(n2)]
+            .iter()
+            .zip(&buf2[..n1.min(n2)])
+            .position(|(a, b)| a != b)
+        {
+            return Ok(Some(offset + i as u64));
+        }
+        // One file changed size since its metadata was read
+        if n1 != n2 {
+            return Ok(Some(offset + n1.min(n2) as u64));
+        }
+        if n1 == 0 {
+            return Ok(None);
+        }
+        offset += n1 as u64;
+    }
+}
+
+#[async_trait]
+impl Tool for FileCompare {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "file_compare"
+    }
+
+    fn description(&self) -> &str {
+        "Check whether two files have identical content, without computing a diff"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let fs = vfs::current();
+        let size1 = paths::require_file(fs.as_ref(), Path::new(&params.file1))
+            .await?
+            .len();
+        let size2 = paths::require_file(fs.as_ref(), Path::new(&params.file2))
+            .await?
+            .len();
+
+        let first_difference = if size1 == size2 {
+            let (path1, path2) = (Path::new(&params.file1), Path::new(&params.file2));
+            difference(fs.as_ref(), path1, path2).await?
+        } else {
+            None
+        };
+
+        Ok(Output {
+            file1: params.file1,
+            file2: params.file2,
+            identical: size1 == size2 && first_difference.is_none(),
+            size1,
+            size2,
+            first_difference,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use crate::vfs::MemoryFs;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_file_compare() -> Result<()> {
+        // Differences past the first chunk are found too
+        let large = vec![b'a'; CHUNK_SIZE * 2 + 10];
+        let mut changed = large.clone();
+        changed[CHUNK_SIZE + 5] = b'b';
+
+        let fs = Arc::new(
+            MemoryFs::new()
+                .with_file("/a.txt", "same content")
+                .with_file("/b.txt", "same content")
+                .with_file("/c.txt", "same contenT")
+                .with_file("/d.txt", "shorter")
+                .with_file("/large1.bin", large)
+                .with_file("/large2.bin", changed),
+        );
+        vfs::scope(fs, async {
+            let tool = FileCompare;
+
+            let result = tool.execute(Params::new("/a.txt", "/b.txt")).await?;
+            assert!(result.identical);
+            assert_eq!(result.first_difference, None);
+
+            let result = tool.execute(Params::new("/a.txt", "/c.txt")).await?;
+            assert!(!result.identical);
+            assert_eq!(result.first_difference, Some(11));
+
+            let result = tool.execute(Params::new("/a.txt", "/d.txt")).await?;
+            assert!(!result.identical);
+            assert_eq!((result.size1, result.size2), (12, 7));
+            assert_eq!(result.first_difference, None);
+
+            let result = tool
+                .execute(Params::new("/large1.bin", "/large2.bin"))
+                .await?;
+            assert!(!result.identical);
+            assert_eq!(result.first_difference, Some(CHUNK_SIZE as u64 + 5));
+
+            let result = tool.execute(Params::new("/a.txt", "/missing.txt")).await;
+            assert!(matches!(result, Err(Error::NotFound(_))));
+            let result = tool.execute(Params::new("/a.txt", "/")).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_file_compare_real_files() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "compare_test_{}",
+            chrono::Utc::now().timestamp_millis()
+        ));
+        std::fs::create_dir_all(&dir)?;
+        let file1 = dir.join("one.txt");
+        let file2 = dir.join("two.txt");
+        std::fs::write(&file1, "identical\n")?;
+        std::fs::write(&file2, "identical\n")?;
+
+        let result = FileCompare
+            .execute(Params::new(
+                file1.to_string_lossy(),
+                file2.to_string_lossy(),
+            ))
+            .await?;
+        assert!(result.identical);
+        assert_eq!(result.size1, 10);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -3,22 +3,26 @@
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
+use serde_json::Value;
 use similar::{ChangeTag, TextDiff};
+use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
-use tokio::fs;
 
-use super::Tool;
-use crate::{Error, Result};
+use super::{Annotations, Tool};
+use crate::cancel;
+use crate::vfs::{self, Filesystem, WalkOptions};
+use crate::{Error, Result, paths};
 
 /// File diff tool
 #[derive(Clone, Copy)]
 pub struct FileDiff;
 
 /// Type of diff
-#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum DiffType {
     /// Unified diff format (most common)
+    #[default]
     Unified,
     /// Side-by-side diff format
     SideBySide,
@@ -26,25 +30,46 @@ pub enum DiffType {
     Line,
     /// Word-by-word diff
     Word,
-    /// Character-by-character diff
+    /// Character-by-character diff, where a character is what a reader sees
+    /// as one (a grapheme cluster), such as an emoji with its skin tone
     Character,
 }
 
-impl Default for DiffType {
-    fn default() -> Self {
-        Self::Unified
-    }
+/// Format of the diff text
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// Human-readable text for the chosen `diff_type`
+    #[default]
+    Text,
+    /// Git-style patch with `diff --git` headers that `git apply` accepts
+    GitPatch,
+    /// RFC 6902 JSON Patch (both sides must be JSON)
+    JsonPatch,
 }
 
 /// Parameters for the file diff tool
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Params {
-    /// Path to the first file
+    /// Path to the first file, or the first directory to compare recursively
+    /// (only a label when `content1` is given)
+    #[serde(default, deserialize_with = "crate::roots::path")]
     pub file1: String,
 
-    /// Path to the second file
+    /// Path to the second file, or the second directory
+    /// (only a label when `content2` is given)
+    #[serde(default, deserialize_with = "crate::roots::path")]
     pub file2: String,
 
+    /// Inline content to use as the first side instead of reading `file1`
+    #[serde(default)]
+    pub content1: Option<String>,
+
+    /// Inline content to use as the second side instead of reading `file2`,
+    /// e.g. a proposed new version of `file1`
+    #[serde(default)]
+    pub content2: Option<String>,
+
     /// Type of diff to generate
     #[serde(default)]
     pub diff_type: DiffType,
@@ -60,6 +85,44 @@ pub struct Params {
     /// Ignore case changes
     #[serde(default)]
     pub ignore_case: bool,
+
+    /// Ignore lines that are empty or only whitespace
+    /// (line numbers then count only the remaining lines)
+    #[serde(default)]
+    pub ignore_blank_lines: bool,
+
+    /// Ignore whitespace at the end of lines
+    #[serde(default)]
+    pub ignore_trailing_whitespace: bool,
+
+    /// When comparing directories, include hunks for modified text files
+    #[serde(default)]
+    pub content_diffs: bool,
+
+    /// Format of `diff_text`. A git patch always carries the exact file
+    /// contents so it applies cleanly; the ignore options only decide which
+    /// files count as changed.
+    #[serde(default)]
+    pub output_format: OutputFormat,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            file1: String::new(),
+            file2: String::new(),
+            content1: None,
+            content2: None,
+            diff_type: DiffType::default(),
+            context_lines: default_context(),
+            ignore_whitespace: false,
+            ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: false,
+            output_format: OutputFormat::default(),
+        }
+    }
 }
 
 fn default_context() -> usize {
@@ -67,14 +130,14 @@ fn default_context() -> usize {
 }
 
 /// A line in the diff
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DiffLine {
     /// Line number in file1 (if applicable)
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub line1: Option<usize>,
 
     /// Line number in file2 (if applicable)
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub line2: Option<usize>,
 
     /// Type of change
@@ -82,10 +145,24 @@ pub struct DiffLine {
 
     /// Line content
     pub content: String,
+
+    /// Changed spans within the line (word and character diffs only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ranges: Vec<InlineRange>,
+}
+
+/// A changed span within a line, used to highlight intra-line edits
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct InlineRange {
+    /// Byte offset where the span starts in the line content
+    pub start: usize,
+
+    /// Byte offset where the span ends in the line content (exclusive)
+    pub end: usize,
 }
 
 /// A hunk of the diff (group of changes)
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DiffHunk {
     /// Start line in file1
     pub start1: usize,
@@ -103,8 +180,60 @@ pub struct DiffHunk {
     pub lines: Vec<DiffLine>,
 }
 
+/// How a file differs between two directories
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    /// Only in the second directory
+    Added,
+    /// Only in the first directory
+    Removed,
+    /// In both, with different content
+    Modified,
+}
+
+/// A file that differs between two directories
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FileChange {
+    /// Path relative to the compared directories
+    pub path: String,
+
+    /// How the file differs
+    pub status: FileStatus,
+
+    /// Hunks of changes (modified text files, with `content_diffs`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// Kind of JSON Patch operation
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonPatchOpKind {
+    /// Add a value or insert it into an array
+    Add,
+    /// Remove a value
+    Remove,
+    /// Replace a value
+    Replace,
+}
+
+/// One RFC 6902 JSON Patch operation
+#[derive(Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct JsonPatchOp {
+    /// Operation to perform
+    pub op: JsonPatchOpKind,
+
+    /// JSON Pointer to the target location
+    pub path: String,
+
+    /// Value to add or replace with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+}
+
 /// Output of the file diff tool
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Output {
     /// Path to the first file
     pub file1: String,
@@ -123,27 +252,15 @@ pub struct Output {
 
     /// Text representation of the diff
     pub diff_text: String,
-}
-
-/// Check if a path exists and is a file
-async fn check_file_path(path: impl AsRef<Path>) -> Result<PathBuf> {
-    let path = path.as_ref();
 
-    if !path.exists() {
-        return Err(Error::InvalidParam(format!(
-            "File not found: {}",
-            path.display()
-        )));
-    }
-
-    if !path.is_file() {
-        return Err(Error::InvalidParam(format!(
-            "Path is not a file: {}",
-            path.display()
-        )));
-    }
+    /// Files that differ (directory comparisons only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileChange>,
 
-    Ok(path.to_path_buf())
+    /// Operations turning the first JSON document into the second
+    /// (`json_patch` output only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_patch: Option<Vec<JsonPatchOp>>,
 }
 
 /// Generate a unified diff
@@ -152,13 +269,11 @@ fn generate_unified_diff(
     content2: &str,
     file1: &str,
     file2: &str,
-    _context_lines: usize,
-    ignore_whitespace: bool,
-    ignore_case: bool,
+    preprocess: &Preprocess,
 ) -> (Vec<DiffHunk>, String) {
     // Preprocess content if needed
-    let processed1 = preprocess_content(content1, ignore_whitespace, ignore_case);
-    let processed2 = preprocess_content(content2, ignore_whitespace, ignore_case);
+    let processed1 = preprocess_content(content1, preprocess);
+    let processed2 = preprocess_content(content2, preprocess);
 
     // Build the diff using the new API
     let diff = TextDiff::from_lines(&processed1, &processed2);
@@ -196,6 +311,7 @@ fn generate_unified_diff(
                         line2: Some(line2),
                         change_type: "equal".to_string(),
                         content: change.value().to_string(),
+                        ranges: Vec::new(),
                     });
 
                     hunk_text.push_str(&format!(" {}", change.value()));
@@ -210,6 +326,7 @@ fn generate_unified_diff(
                         line2: None,
                         change_type: "delete".to_string(),
                         content: change.value().to_string(),
+                        ranges: Vec::new(),
                     });
 
                     hunk_text.push_str(&format!("-{}", change.value()));
@@ -222,6 +339,7 @@ fn generate_unified_diff(
                         line2: Some(line2),
                         change_type: "insert".to_string(),
                         content: change.value().to_string(),
+                        ranges: Vec::new(),
                     });
 
                     hunk_text.push_str(&format!("+{}", change.value()));
@@ -249,20 +367,167 @@ fn generate_unified_diff(
     (hunks, diff_text)
 }
 
+/// Compute intra-line changed spans between an old and a new line
+fn inline_ranges(
+    old: &str,
+    new: &str,
+    diff_type: DiffType,
+) -> (Vec<InlineRange>, Vec<InlineRange>) {
+    let diff = match diff_type {
+        DiffType::Character => TextDiff::from_graphemes(old, new),
+        _ => TextDiff::from_words(old, new),
+    };
+
+    let mut old_ranges = Vec::new();
+    let mut new_ranges = Vec::new();
+    let mut old_pos = 0;
+    let mut new_pos = 0;
+
+    for change in diff.iter_all_changes() {
+        let len = change.value().len();
+        match change.tag() {
+            ChangeTag::Equal => {
+                old_pos += len;
+                new_pos += len;
+            }
+            ChangeTag::Delete => {
+                push_range(&mut old_ranges, old_pos, old_pos + len);
+                old_pos += len;
+            }
+            ChangeTag::Insert => {
+                push_range(&mut new_ranges, new_pos, new_pos + len);
+                new_pos += len;
+            }
+        }
+    }
+
+    (old_ranges, new_ranges)
+}
+
+/// Append a range, merging it with the previous one if they touch
+fn push_range(ranges: &mut Vec<InlineRange>, start: usize, end: usize) {
+    match ranges.last_mut() {
+        Some(last) if last.end == start => last.end = end,
+        _ => ranges.push(InlineRange { start, end }),
+    }
+}
+
+/// Pair deleted and inserted lines within each hunk and record their intra-line changes
+fn annotate_inline_changes(hunks: &mut [DiffHunk], diff_type: DiffType) {
+    for hunk in hunks.iter_mut() {
+        let deletes: Vec<usize> = hunk
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.change_type == "delete")
+            .map(|(i, _)| i)
+            .collect();
+        let inserts: Vec<usize> = hunk
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.change_type == "insert")
+            .map(|(i, _)| i)
+            .collect();
+
+        // Lines are paired in order; unpaired lines are wholly added or removed
+        for (&d, &i) in deletes.iter().zip(inserts.iter()) {
+            let (old_ranges, new_ranges) = inline_ranges(
+                hunk.lines[d].content.trim_end_matches('\n'),
+                hunk.lines[i].content.trim_end_matches('\n'),
+                diff_type,
+            );
+            hunk.lines[d].ranges = old_ranges;
+            hunk.lines[i].ranges = new_ranges;
+        }
+    }
+}
+
+/// Wrap the given ranges of a line in open/close markers
+fn mark_ranges(content: &str, ranges: &[InlineRange], open: &str, close: &str) -> String {
+    let mut marked = String::with_capacity(content.len());
+    let mut pos = 0;
+    for range in ranges {
+        marked.push_str(&content[pos..range.start]);
+        marked.push_str(open);
+        marked.push_str(&content[range.start..range.end]);
+        marked.push_str(close);
+        pos = range.end;
+    }
+    marked.push_str(&content[pos..]);
+    marked
+}
+
+/// Render hunks as text, marking intra-line changes wdiff-style
+fn render_inline_diff(hunks: &[DiffHunk], file1: &str, file2: &str) -> String {
+    let mut diff_text = String::new();
+    diff_text.push_str(&format!("--- {}\n", file1));
+    diff_text.push_str(&format!("+++ {}\n", file2));
+
+    for hunk in hunks {
+        diff_text.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk.start1,
+            hunk.end1 - hunk.start1 + 1,
+            hunk.start2,
+            hunk.end2 - hunk.start2 + 1
+        ));
+
+        for line in &hunk.lines {
+            let content = line.content.trim_end_matches('\n');
+            let rendered = match line.change_type.as_str() {
+                "delete" => format!("-{}", mark_ranges(content, &line.ranges, "[-", "-]")),
+                "insert" => format!("+{}", mark_ranges(content, &line.ranges, "{+", "+}")),
+                _ => format!(" {}", content),
+            };
+            diff_text.push_str(&rendered);
+            diff_text.push('\n');
+        }
+    }
+
+    diff_text
+}
+
+/// Which differences to ignore when comparing
+#[derive(Debug, Clone, Copy, Default)]
+struct Preprocess {
+    ignore_whitespace: bool,
+    ignore_case: bool,
+    ignore_blank_lines: bool,
+    ignore_trailing_whitespace: bool,
+}
+
+impl Preprocess {
+    fn from_params(params: &Params) -> Self {
+        Self {
+            ignore_whitespace: params.ignore_whitespace,
+            ignore_case: params.ignore_case,
+            ignore_blank_lines: params.ignore_blank_lines,
+            ignore_trailing_whitespace: params.ignore_trailing_whitespace,
+        }
+    }
+}
+
 /// Preprocess content based on diff options
-fn preprocess_content(content: &str, ignore_whitespace: bool, ignore_case: bool) -> String {
+fn preprocess_content(content: &str, options: &Preprocess) -> String {
     let lines: Vec<&str> = content.lines().collect();
     let mut processed_lines = Vec::new();
 
     for line in lines {
+        if options.ignore_blank_lines && line.trim().is_empty() {
+            continue;
+        }
+
         let mut processed = line.to_string();
 
-        if ignore_whitespace {
+        if options.ignore_whitespace {
             // Replace multiple whitespaces with a single space
             processed = processed.split_whitespace().collect::<Vec<_>>().join(" ");
+        } else if options.ignore_trailing_whitespace {
+            processed.truncate(processed.trim_end().len());
         }
 
-        if ignore_case {
+        if options.ignore_case {
             processed = processed.to_lowercase();
         }
 
@@ -272,6 +537,305 @@ fn preprocess_content(content: &str, ignore_whitespace: bool, ignore_case: bool)
     processed_lines.join("\n")
 }
 
+/// Diff two texts according to the requested options.
+/// Returns whether they are identical, the hunks and the rendered diff.
+fn diff_contents(
+    content1: &str,
+    content2: &str,
+    label1: &str,
+    label2: &str,
+    params: &Params,
+) -> (bool, Vec<DiffHunk>, String) {
+    // Check if files are identical (after preprocessing)
+    let preprocess = Preprocess::from_params(params);
+    let processed1 = preprocess_content(content1, &preprocess);
+    let processed2 = preprocess_content(content2, &preprocess);
+
+    // Compare processed content for equality
+    let identical = processed1 == processed2;
+
+    // Generate diff based on type
+    let (hunks, diff_text) = match params.diff_type {
+        DiffType::Unified => generate_unified_diff(content1, content2, label1, label2, &preprocess),
+        DiffType::Word | DiffType::Character => {
+            let (mut hunks, _) =
+                generate_unified_diff(content1, content2, label1, label2, &preprocess);
+            annotate_inline_changes(&mut hunks, params.diff_type);
+            let diff_text = render_inline_diff(&hunks, label1, label2);
+            (hunks, diff_text)
+        }
+        // For now, fallback to unified diff for the remaining formats
+        _ => generate_unified_diff(content1, content2, label1, label2, &preprocess),
+    };
+
+    (identical, hunks, diff_text)
+}
+
+/// Path to use in git patch headers: as given, without a leading `./` or `/`
+fn patch_path(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+/// Start a git patch section: the `diff --git` line, a mode line for added
+/// and removed files, and the old and new names for the `---`/`+++` lines
+fn git_patch_header(path: &str, status: FileStatus) -> (String, String, String) {
+    let path = patch_path(path);
+    let mut header = format!("diff --git a/{0} b/{0}\n", path);
+    let (mut old, mut new) = (format!("a/{}", path), format!("b/{}", path));
+    match status {
+        FileStatus::Added => {
+            header.push_str("new file mode 100644\n");
+            old = "/dev/null".to_string();
+        }
+        FileStatus::Removed => {
+            header.push_str("deleted file mode 100644\n");
+            new = "/dev/null".to_string();
+        }
+        FileStatus::Modified => {}
+    }
+    (header, old, new)
+}
+
+/// Render one text file's changes as a git patch section. `None` means the
+/// file doesn't exist on that side.
+fn git_patch(path: &str, old: Option<&str>, new: Option<&str>, context_lines: usize) -> String {
+    let status = match (old, new) {
+        (None, _) => FileStatus::Added,
+        (_, None) => FileStatus::Removed,
+        _ => FileStatus::Modified,
+    };
+    let (mut patch, old_name, new_name) = git_patch_header(path, status);
+    let diff = TextDiff::from_lines(old.unwrap_or(""), new.unwrap_or(""));
+    patch.push_str(
+        &diff
+            .unified_diff()
+            .context_radius(context_lines)
+            .header(&old_name, &new_name)
+            .to_string(),
+    );
+    patch
+}
+
+/// Git patch section for a binary file, which only says that it differs
+fn git_binary_patch(path: &str, status: FileStatus) -> String {
+    let (mut patch, old_name, new_name) = git_patch_header(path, status);
+    patch.push_str(&format!(
+        "Binary files {} and {} differ\n",
+        old_name, new_name
+    ));
+    patch
+}
+
+/// Escape a key for use in a JSON Pointer (RFC 6901)
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Append the operations that turn `old` into `new` at `path`
+fn json_patch_ops(old: &Value, new: &Value, path: &str, ops: &mut Vec<JsonPatchOp>) {
+    match (old, new) {
+        _ if old == new => {}
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let child = format!("{}/{}", path, escape_pointer(key));
+                match new_map.get(key) {
+                    Some(new_value) => json_patch_ops(old_value, new_value, &child, ops),
+                    None => ops.push(JsonPatchOp {
+                        op: JsonPatchOpKind::Remove,
+                        path: child,
+                        value: None,
+                    }),
+                }
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    ops.push(JsonPatchOp {
+                        op: JsonPatchOpKind::Add,
+                        path: format!("{}/{}", path, escape_pointer(key)),
+                        value: Some(new_value.clone()),
+                    });
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            for (i, (old_item, new_item)) in old_items.iter().zip(new_items).enumerate() {
+                json_patch_ops(old_item, new_item, &format!("{}/{}", path, i), ops);
+            }
+            // Remove surplus items from the end so earlier indices stay valid
+            for i in (new_items.len()..old_items.len()).rev() {
+                ops.push(JsonPatchOp {
+                    op: JsonPatchOpKind::Remove,
+                    path: format!("{}/{}", path, i),
+                    value: None,
+                });
+            }
+            for (i, new_item) in new_items.iter().enumerate().skip(old_items.len()) {
+                ops.push(JsonPatchOp {
+                    op: JsonPatchOpKind::Add,
+                    path: format!("{}/{}", path, i),
+                    value: Some(new_item.clone()),
+                });
+            }
+        }
+        _ => ops.push(JsonPatchOp {
+            op: JsonPatchOpKind::Replace,
+            path: path.to_string(),
+            value: Some(new.clone()),
+        }),
+    }
+}
+
+/// Build a JSON Patch between two JSON documents
+fn json_patch(content1: &str, content2: &str, params: &Params) -> Result<Vec<JsonPatchOp>> {
+    let parse = |content: &str, label: &str| {
+        serde_json::from_str::<Value>(content).map_err(|e| {
+            Error::InvalidParam(format!(
+                "json_patch output needs both sides to be JSON, {} is not: {}",
+                label, e
+            ))
+        })
+    };
+    let old = parse(content1, &params.file1)?;
+    let new = parse(content2, &params.file2)?;
+
+    let mut ops = Vec::new();
+    json_patch_ops(&old, &new, "", &mut ops);
+    Ok(ops)
+}
+
+/// List the files under a directory, relative to it
+async fn list_files(fs: &dyn Filesystem, root: &Path) -> Result<BTreeSet<PathBuf>> {
+    let options = WalkOptions {
+        max_depth: usize::MAX,
+        follow_links: false,
+    };
+    Ok(fs
+        .walk(root, options)
+        .await?
+        .into_iter()
+        .filter(|entry| entry.metadata.is_file())
+        .filter_map(|entry| entry.path.strip_prefix(root).ok().map(|p| p.to_path_buf()))
+        .collect())
+}
+
+/// Compare two directory trees file by file
+async fn diff_directories(
+    fs: &dyn Filesystem,
+    dir1: &Path,
+    dir2: &Path,
+    params: &Params,
+) -> Result<Output> {
+    let files1 = list_files(fs, dir1).await?;
+    let files2 = list_files(fs, dir2).await?;
+    cancel::check()?;
+
+    let git = params.output_format == OutputFormat::GitPatch;
+    let mut files = Vec::new();
+    let mut diff_text = String::new();
+    let mut patch = String::new();
+
+    for relative in files1.union(&files2) {
+        cancel::check()?;
+        let name = relative.to_string_lossy().to_string();
+        let status = match (files1.contains(relative), files2.contains(relative)) {
+            (true, false) => FileStatus::Removed,
+            (false, true) => FileStatus::Added,
+            _ => {
+                let bytes1 = fs.read(&dir1.join(relative)).await?;
+                let bytes2 = fs.read(&dir2.join(relative)).await?;
+                if bytes1 == bytes2 {
+                    continue;
+                }
+                let texts = match (String::from_utf8(bytes1), String::from_utf8(bytes2)) {
+                    (Ok(text1), Ok(text2)) => Some((text1, text2)),
+                    _ => None,
+                };
+
+                // Text files can get a content diff; binary ones are just reported
+                let mut hunks = Vec::new();
+                let mut file_text = String::new();
+                if let Some((text1, text2)) = &texts
+                    && (params.content_diffs || git)
+                {
+                    let label1 = dir1.join(relative).to_string_lossy().to_string();
+                    let label2 = dir2.join(relative).to_string_lossy().to_string();
+                    let (identical, file_hunks, text) =
+                        diff_contents(text1, text2, &label1, &label2, params);
+                    if identical {
+                        // Only whitespace or case differences, which were asked to be ignored
+                        continue;
+                    }
+                    if params.content_diffs {
+                        hunks = file_hunks;
+                        file_text = text;
+                    }
+                }
+                diff_text.push_str(&format!("M {}\n", name));
+                diff_text.push_str(&file_text);
+
+                if git {
+                    patch.push_str(&match &texts {
+                        Some((text1, text2)) => git_patch(
+                            &name,
+                            Some(text1.as_str()),
+                            Some(text2.as_str()),
+                            params.context_lines,
+                        ),
+                        None => git_binary_patch(&name, FileStatus::Modified),
+                    });
+                }
+
+                files.push(FileChange {
+                    path: name,
+                    status: FileStatus::Modified,
+                    hunks,
+                });
+                continue;
+            }
+        };
+
+        let marker = if status == FileStatus::Added {
+            "A"
+        } else {
+            "D"
+        };
+        diff_text.push_str(&format!("{} {}\n", marker, name));
+
+        if git {
+            let dir = if status == FileStatus::Added {
+                dir2
+            } else {
+                dir1
+            };
+            let bytes = fs.read(&dir.join(relative)).await?;
+            patch.push_str(&match String::from_utf8(bytes) {
+                Ok(text) if status == FileStatus::Added => {
+                    git_patch(&name, None, Some(text.as_str()), params.context_lines)
+                }
+                Ok(text) => git_patch(&name, Some(text.as_str()), None, params.context_lines),
+                Err(_) => git_binary_patch(&name, status),
+            });
+        }
+
+        files.push(FileChange {
+            path: name,
+            status,
+            hunks: Vec::new(),
+        });
+    }
+
+    Ok(Output {
+        file1: params.file1.clone(),
+        file2: params.file2.clone(),
+        diff_type: params.diff_type,
+        identical: files.is_empty(),
+        hunks: Vec::new(),
+        diff_text: if git { patch } else { diff_text },
+        files,
+        json_patch: None,
+    })
+}
+
 #[async_trait]
 impl Tool for FileDiff {
     type Params = Params;
@@ -281,48 +845,86 @@ impl Tool for FileDiff {
         "file_diff"
     }
 
-    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
-        // Validate file paths
-        let file1_path = check_file_path(&params.file1).await?;
-        let file2_path = check_file_path(&params.file2).await?;
-
-        // Read file contents
-        let content1 = fs::read_to_string(&file1_path).await?;
-        let content2 = fs::read_to_string(&file2_path).await?;
+    fn description(&self) -> &str {
+        "Compare two files"
+    }
 
-        // Check if files are identical (after preprocessing)
-        let processed1 =
-            preprocess_content(&content1, params.ignore_whitespace, params.ignore_case);
-        let processed2 =
-            preprocess_content(&content2, params.ignore_whitespace, params.ignore_case);
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
 
-        // Compare processed content for equality
-        let identical = processed1 == processed2;
+    async fn execute(&self, mut params: Self::Params) -> Result<Self::Output> {
+        // Two directories are compared as trees
+        let fs = vfs::current();
+        let (dir1, dir2) = (Path::new(&params.file1), Path::new(&params.file2));
+        if params.content1.is_none()
+            && params.content2.is_none()
+            && fs.metadata(dir1).await.is_ok_and(|m| m.is_dir())
+            && fs.metadata(dir2).await.is_ok_and(|m| m.is_dir())
+        {
+            if params.output_format == OutputFormat::JsonPatch {
+                return Err(Error::InvalidParam(
+                    "json_patch output is only available when comparing two files".to_string(),
+                ));
+            }
+            return diff_directories(fs.as_ref(), dir1, dir2, &params).await;
+        }
 
-        // Generate diff based on type
-        let (hunks, diff_text) = match params.diff_type {
-            DiffType::Unified => generate_unified_diff(
-                &content1,
-                &content2,
-                &params.file1,
-                &params.file2,
-                params.context_lines,
-                params.ignore_whitespace,
-                params.ignore_case,
-            ),
-            // For now, fallback to unified diff for other formats
-            // In a real implementation, we would add specialized diff generators for each format
-            _ => generate_unified_diff(
-                &content1,
-                &content2,
-                &params.file1,
-                &params.file2,
-                params.context_lines,
-                params.ignore_whitespace,
-                params.ignore_case,
-            ),
+        // Each side is either inline content or a file to read
+        let content1 = match params.content1.take() {
+            Some(content) => content,
+            None => {
+                let path = Path::new(&params.file1);
+                paths::require_file(fs.as_ref(), path).await?;
+                fs.read_to_string(path).await?
+            }
+        };
+        let content2 = match params.content2.take() {
+            Some(content) => content,
+            None => {
+                let path = Path::new(&params.file2);
+                paths::require_file(fs.as_ref(), path).await?;
+                fs.read_to_string(path).await?
+            }
         };
 
+        let (identical, hunks, mut diff_text) =
+            diff_contents(&content1, &content2, &params.file1, &params.file2, &params);
+
+        let mut patch_ops = None;
+        match params.output_format {
+            OutputFormat::Text => {}
+            OutputFormat::GitPatch => {
+                // The patch applies to the first file, or names the second
+                // when the first side is inline content
+                let path = if params.file1.is_empty() {
+                    &params.file2
+                } else {
+                    &params.file1
+                };
+                if path.is_empty() {
+                    return Err(Error::InvalidParam(
+                        "git_patch output needs file1 or file2 as the path to patch".to_string(),
+                    ));
+                }
+                diff_text = if identical {
+                    String::new()
+                } else {
+                    git_patch(
+                        path,
+                        Some(content1.as_str()),
+                        Some(content2.as_str()),
+                        params.context_lines,
+                    )
+                };
+            }
+            OutputFormat::JsonPatch => {
+                let ops = json_patch(&content1, &content2, &params)?;
+                diff_text = serde_json::to_string_pretty(&ops)?;
+                patch_ops = Some(ops);
+            }
+        }
+
         Ok(Output {
             file1: params.file1,
             file2: params.file2,
@@ -330,6 +932,8 @@ impl Tool for FileDiff {
             identical,
             hunks,
             diff_text,
+            files: Vec::new(),
+            json_patch: patch_ops,
         })
     }
 }
@@ -338,6 +942,7 @@ impl Tool for FileDiff {
 mod tests {
     use super::*;
     use log::{debug, trace};
+    use tokio::fs;
     use tokio::fs::File;
     use tokio::io::AsyncWriteExt;
 
@@ -399,10 +1004,16 @@ mod tests {
         let params = Params {
             file1: file1.to_string_lossy().to_string(),
             file2: file2.to_string_lossy().to_string(),
+            content1: None,
+            content2: None,
             diff_type: DiffType::Unified,
             context_lines: 3,
             ignore_whitespace: false,
             ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: false,
+            output_format: OutputFormat::Text,
         };
 
         let result = tool.execute(params).await?;
@@ -412,11 +1023,11 @@ mod tests {
         trace!("File 2: '{}'", content2);
         trace!(
             "Processed1: '{}'",
-            preprocess_content(&content1, false, false)
+            preprocess_content(&content1, &Preprocess::default())
         );
         trace!(
             "Processed2: '{}'",
-            preprocess_content(&content2, false, false)
+            preprocess_content(&content2, &Preprocess::default())
         );
         debug!("Identical: {}", result.identical);
 
@@ -446,10 +1057,16 @@ mod tests {
         let params = Params {
             file1: file1.to_string_lossy().to_string(),
             file2: file2.to_string_lossy().to_string(),
+            content1: None,
+            content2: None,
             diff_type: DiffType::Unified,
             context_lines: 3,
             ignore_whitespace: false,
             ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: false,
+            output_format: OutputFormat::Text,
         };
 
         let result = tool.execute(params).await?;
@@ -495,10 +1112,16 @@ mod tests {
         let params = Params {
             file1: file1.to_string_lossy().to_string(),
             file2: file2.to_string_lossy().to_string(),
+            content1: None,
+            content2: None,
             diff_type: DiffType::Unified,
             context_lines: 3,
             ignore_whitespace: false,
             ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: false,
+            output_format: OutputFormat::Text,
         };
 
         let result = tool.execute(params).await?;
@@ -509,11 +1132,11 @@ mod tests {
         trace!("File 2: '{}'", content2);
         trace!(
             "Processed1: '{}'",
-            preprocess_content(&content1, false, false)
+            preprocess_content(&content1, &Preprocess::default())
         );
         trace!(
             "Processed2: '{}'",
-            preprocess_content(&content2, false, false)
+            preprocess_content(&content2, &Preprocess::default())
         );
 
         assert!(
@@ -525,10 +1148,16 @@ mod tests {
         let params = Params {
             file1: file1.to_string_lossy().to_string(),
             file2: file2.to_string_lossy().to_string(),
+            content1: None,
+            content2: None,
             diff_type: DiffType::Unified,
             context_lines: 3,
             ignore_whitespace: true,
             ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: false,
+            output_format: OutputFormat::Text,
         };
 
         let result = tool.execute(params).await?;
@@ -537,11 +1166,23 @@ mod tests {
         debug!("With ignore_whitespace=true:");
         trace!(
             "Processed1: '{}'",
-            preprocess_content(&content1, true, false)
+            preprocess_content(
+                &content1,
+                &Preprocess {
+                    ignore_whitespace: true,
+                    ..Default::default()
+                }
+            )
         );
         trace!(
             "Processed2: '{}'",
-            preprocess_content(&content2, true, false)
+            preprocess_content(
+                &content2,
+                &Preprocess {
+                    ignore_whitespace: true,
+                    ..Default::default()
+                }
+            )
         );
 
         assert!(
@@ -587,10 +1228,16 @@ mod tests {
         let params = Params {
             file1: file1.to_string_lossy().to_string(),
             file2: file2.to_string_lossy().to_string(),
+            content1: None,
+            content2: None,
             diff_type: DiffType::Unified,
             context_lines: 3,
             ignore_whitespace: false,
             ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: false,
+            output_format: OutputFormat::Text,
         };
 
         let result = tool.execute(params).await?;
@@ -601,11 +1248,11 @@ mod tests {
         trace!("File 2: '{}'", content2);
         trace!(
             "Processed1: '{}'",
-            preprocess_content(&content1, false, false)
+            preprocess_content(&content1, &Preprocess::default())
         );
         trace!(
             "Processed2: '{}'",
-            preprocess_content(&content2, false, false)
+            preprocess_content(&content2, &Preprocess::default())
         );
 
         assert!(
@@ -617,10 +1264,16 @@ mod tests {
         let params = Params {
             file1: file1.to_string_lossy().to_string(),
             file2: file2.to_string_lossy().to_string(),
+            content1: None,
+            content2: None,
             diff_type: DiffType::Unified,
             context_lines: 3,
             ignore_whitespace: false,
             ignore_case: true,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: false,
+            output_format: OutputFormat::Text,
         };
 
         let result = tool.execute(params).await?;
@@ -629,11 +1282,23 @@ mod tests {
         debug!("With ignore_case=true:");
         trace!(
             "Processed1: '{}'",
-            preprocess_content(&content1, false, true)
+            preprocess_content(
+                &content1,
+                &Preprocess {
+                    ignore_case: true,
+                    ..Default::default()
+                }
+            )
         );
         trace!(
             "Processed2: '{}'",
-            preprocess_content(&content2, false, true)
+            preprocess_content(
+                &content2,
+                &Preprocess {
+                    ignore_case: true,
+                    ..Default::default()
+                }
+            )
         );
 
         assert!(
@@ -646,4 +1311,338 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_diff_ignore_blank_lines_and_trailing_whitespace() -> Result<()> {
+        let tool = FileDiff;
+        let params = |blank: bool, trailing: bool| Params {
+            file1: String::new(),
+            file2: String::new(),
+            content1: Some("one\ntwo\n\nthree\n".to_string()),
+            content2: Some("one  \n\n  \ntwo\nthree\t\n".to_string()),
+            diff_type: DiffType::Unified,
+            context_lines: 3,
+            ignore_whitespace: false,
+            ignore_case: false,
+            ignore_blank_lines: blank,
+            ignore_trailing_whitespace: trailing,
+            content_diffs: false,
+            output_format: OutputFormat::Text,
+        };
+
+        assert!(!tool.execute(params(false, false)).await?.identical);
+        assert!(!tool.execute(params(true, false)).await?.identical);
+        assert!(!tool.execute(params(false, true)).await?.identical);
+        assert!(tool.execute(params(true, true)).await?.identical);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_diff_git_patch() -> Result<()> {
+        let result = FileDiff
+            .execute(Params {
+                file1: "./src/notes.txt".to_string(),
+                file2: String::new(),
+                content1: Some("one\ntwo\nthree\n".to_string()),
+                content2: Some("one\n2\nthree".to_string()),
+                diff_type: DiffType::Unified,
+                context_lines: 3,
+                ignore_whitespace: false,
+                ignore_case: false,
+                ignore_blank_lines: false,
+                ignore_trailing_whitespace: false,
+                content_diffs: false,
+                output_format: OutputFormat::GitPatch,
+            })
+            .await?;
+
+        assert!(
+            result
+                .diff_text
+                .starts_with("diff --git a/src/notes.txt b/src/notes.txt\n--- a/src/notes.txt\n+++ b/src/notes.txt\n@@ -1,3 +1,3 @@\n")
+        );
+        // The last line differs only by its missing newline, so it changes too
+        assert!(
+            result
+                .diff_text
+                .ends_with(" one\n-two\n-three\n+2\n+three\n\\ No newline at end of file\n")
+        );
+
+        let added = git_patch("new.txt", None, Some("hello\n"), 3);
+        assert_eq!(
+            added,
+            "diff --git a/new.txt b/new.txt\nnew file mode 100644\n--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1 @@\n+hello\n"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_diff_json_patch() -> Result<()> {
+        let params = |content1: &str, content2: &str| Params {
+            file1: "old.json".to_string(),
+            file2: "new.json".to_string(),
+            content1: Some(content1.to_string()),
+            content2: Some(content2.to_string()),
+            diff_type: DiffType::Unified,
+            context_lines: 3,
+            ignore_whitespace: false,
+            ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: false,
+            output_format: OutputFormat::JsonPatch,
+        };
+
+        let result = FileDiff
+            .execute(params(
+                r#"{"name": "a", "tags": ["x", "y", "z"], "a/b": 1, "old": true}"#,
+                r#"{"name": "b", "tags": ["x"], "a/b": 2, "new": null}"#,
+            ))
+            .await?;
+
+        let ops = result.json_patch.expect("json patch should be returned");
+        let expected = vec![
+            ("replace", "/name", Some(serde_json::json!("b"))),
+            ("remove", "/tags/2", None),
+            ("remove", "/tags/1", None),
+            ("replace", "/a~1b", Some(serde_json::json!(2))),
+            ("remove", "/old", None),
+            ("add", "/new", Some(Value::Null)),
+        ];
+        let mut actual: Vec<_> = ops
+            .iter()
+            .map(|op| {
+                (
+                    serde_json::to_value(op.op)
+                        .unwrap()
+                        .as_str()
+                        .unwrap()
+                        .to_string(),
+                    op.path.clone(),
+                    op.value.clone(),
+                )
+            })
+            .collect();
+        let mut expected: Vec<_> = expected
+            .into_iter()
+            .map(|(op, path, value)| (op.to_string(), path.to_string(), value))
+            .collect();
+        // Object keys may come out in any order
+        actual.sort_by(|a, b| a.1.cmp(&b.1));
+        expected.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(actual, expected);
+
+        let err = FileDiff
+            .execute(params("{}", "not json"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidParam(_)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_diff_word_ranges() -> Result<()> {
+        let test_dir = get_test_dir().join("word");
+        let file1 = test_dir.join("file1.txt");
+        let file2 = test_dir.join("file2.txt");
+
+        fs::create_dir_all(&test_dir).await?;
+        create_test_file(&file1, "Line 1\nLine 2\nLine 3\n").await?;
+        create_test_file(&file2, "Line 1\nLine two\nLine 3\n").await?;
+
+        let tool = FileDiff;
+
+        let params = Params {
+            file1: file1.to_string_lossy().to_string(),
+            file2: file2.to_string_lossy().to_string(),
+            content1: None,
+            content2: None,
+            diff_type: DiffType::Word,
+            context_lines: 3,
+            ignore_whitespace: false,
+            ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: false,
+            output_format: OutputFormat::Text,
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert!(!result.identical);
+
+        let lines: Vec<&DiffLine> = result.hunks.iter().flat_map(|h| h.lines.iter()).collect();
+        let deleted = lines.iter().find(|l| l.change_type == "delete").unwrap();
+        let inserted = lines.iter().find(|l| l.change_type == "insert").unwrap();
+
+        assert_eq!(deleted.ranges, vec![InlineRange { start: 5, end: 6 }]);
+        assert_eq!(inserted.ranges, vec![InlineRange { start: 5, end: 8 }]);
+        assert!(result.diff_text.contains("-Line [-2-]"));
+        assert!(result.diff_text.contains("+Line {+two+}"));
+
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_diff_character_ranges() -> Result<()> {
+        let test_dir = get_test_dir().join("char");
+        let file1 = test_dir.join("file1.txt");
+        let file2 = test_dir.join("file2.txt");
+
+        fs::create_dir_all(&test_dir).await?;
+        create_test_file(&file1, "Line 1\nLine 2\nLine 3\n").await?;
+        create_test_file(&file2, "Line 1\nLine 22\nLine 3\n").await?;
+
+        let tool = FileDiff;
+
+        let params = Params {
+            file1: file1.to_string_lossy().to_string(),
+            file2: file2.to_string_lossy().to_string(),
+            content1: None,
+            content2: None,
+            diff_type: DiffType::Character,
+            context_lines: 3,
+            ignore_whitespace: false,
+            ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: false,
+            output_format: OutputFormat::Text,
+        };
+
+        let result = tool.execute(params).await?;
+
+        let lines: Vec<&DiffLine> = result.hunks.iter().flat_map(|h| h.lines.iter()).collect();
+        let deleted = lines.iter().find(|l| l.change_type == "delete").unwrap();
+        let inserted = lines.iter().find(|l| l.change_type == "insert").unwrap();
+
+        // Only the added character is highlighted
+        assert!(deleted.ranges.is_empty());
+        assert_eq!(inserted.ranges.len(), 1);
+        assert_eq!(inserted.ranges[0].end - inserted.ranges[0].start, 1);
+
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_ranges_graphemes() {
+        // Changing only the skin tone still highlights the whole emoji
+        let (old, new) = inline_ranges(
+            "ok \u{1f44d}\u{1f3fd}",
+            "ok \u{1f44d}\u{1f3ff}",
+            DiffType::Character,
+        );
+        assert_eq!(old, vec![InlineRange { start: 3, end: 11 }]);
+        assert_eq!(new, vec![InlineRange { start: 3, end: 11 }]);
+    }
+
+    #[tokio::test]
+    async fn test_diff_directories() -> Result<()> {
+        let test_dir = std::env::temp_dir().join(format!(
+            "diff_test_dirs_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        let (dir1, dir2) = (test_dir.join("a"), test_dir.join("b"));
+
+        create_test_file(&dir1.join("same.txt"), "same\n").await?;
+        create_test_file(&dir2.join("same.txt"), "same\n").await?;
+        create_test_file(&dir1.join("src/lib.rs"), "one\ntwo\n").await?;
+        create_test_file(&dir2.join("src/lib.rs"), "one\n2\n").await?;
+        create_test_file(&dir1.join("old.txt"), "old\n").await?;
+        create_test_file(&dir2.join("new/added.txt"), "new\n").await?;
+
+        let tool = FileDiff;
+        let params = Params {
+            file1: dir1.to_string_lossy().to_string(),
+            file2: dir2.to_string_lossy().to_string(),
+            content1: None,
+            content2: None,
+            diff_type: DiffType::Unified,
+            context_lines: 3,
+            ignore_whitespace: false,
+            ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: true,
+            output_format: OutputFormat::Text,
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert!(!result.identical);
+        let statuses: Vec<(String, FileStatus)> = result
+            .files
+            .iter()
+            .map(|f| (f.path.replace('\\', "/"), f.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("new/added.txt".to_string(), FileStatus::Added),
+                ("old.txt".to_string(), FileStatus::Removed),
+                ("src/lib.rs".to_string(), FileStatus::Modified),
+            ]
+        );
+
+        // The modified file carries its hunks
+        let modified = &result.files[2];
+        assert!(
+            modified
+                .hunks
+                .iter()
+                .flat_map(|h| &h.lines)
+                .any(|l| l.change_type == "insert" && l.content.starts_with('2'))
+        );
+        assert!(result.diff_text.contains("M src"));
+
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_diff_inline_content() -> Result<()> {
+        let test_dir = std::env::temp_dir().join(format!(
+            "diff_test_inline_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        let file1 = test_dir.join("current.txt");
+        create_test_file(&file1, "keep\nold\n").await?;
+
+        let tool = FileDiff;
+        let params = Params {
+            file1: file1.to_string_lossy().to_string(),
+            file2: "proposed".to_string(),
+            content1: None,
+            content2: Some("keep\nnew\n".to_string()),
+            diff_type: DiffType::Unified,
+            context_lines: 3,
+            ignore_whitespace: false,
+            ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_trailing_whitespace: false,
+            content_diffs: false,
+            output_format: OutputFormat::Text,
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert!(!result.identical);
+        assert!(result.diff_text.contains("+++ proposed"));
+        assert!(result.diff_text.contains("-old"));
+        assert!(result.diff_text.contains("+new"));
+
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,373 @@
+//! File edit tool implementation
+//!
+//! Applies exact-match string replacements to a file. Every anchor must be
+//! found the expected number of times, otherwise nothing is written.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::path::PathBuf;
+
+use super::{Annotations, Tool};
+use super::precondition::check_unchanged;
+use crate::{Error, Result, paths, vfs};
+
+/// File edit tool
+#[derive(Clone, Copy)]
+pub struct FileEdit;
+
+/// A single replacement
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Edit {
+    /// Exact text to find
+    pub old_string: String,
+
+    /// Text to replace it with
+    pub new_string: String,
+
+    /// How many times `old_string` must occur (defaults to 1); every
+    /// occurrence is replaced
+    #[serde(default)]
+    pub expected_occurrences: Option<usize>,
+}
+
+/// Parameters for the file edit tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Path of the file to edit
+    #[serde(deserialize_with = "crate::roots::path")]
+    pub path: String,
+
+    /// Replacements to apply, in order; each sees the result of the previous ones
+    pub edits: Vec<Edit>,
+
+    /// Only edit if the file still has this SHA-256 digest (hex)
+    #[serde(default)]
+    pub expected_sha256: Option<String>,
+
+    /// Only edit if the file still has this modification time
+    /// (RFC 3339, compared to the second)
+    #[serde(default)]
+    pub expected_mtime: Option<String>,
+}
+
+impl Params {
+    /// Apply `edits` to the file at `path`
+    pub fn new(path: impl Into<String>, edits: Vec<Edit>) -> Self {
+        Self {
+            path: path.into(),
+            edits,
+            expected_sha256: None,
+            expected_mtime: None,
+        }
+    }
+}
+
+/// Result of a single replacement
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct EditResult {
+    /// Index of the edit in the request
+    pub edit: usize,
+
+    /// Number of occurrences replaced
+    pub replacements: usize,
+}
+
+/// Output of the file edit tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Path of the edited file
+    pub path: String,
+
+    /// Original size of the file in bytes
+    pub original_size: u64,
+
+    /// New size of the file in bytes
+    pub new_size: u64,
+
+    /// What each edit did
+    pub edits: Vec<EditResult>,
+}
+
+/// Content with its line endings taken out, so that anchors written with plain
+/// newlines match lines whatever their ending
+struct Normalized {
+    text: String,
+    /// Whether each newline in `text` stands for a CRLF
+    crlf: Vec<bool>,
+}
+
+impl Normalized {
+    fn new(content: &str) -> Self {
+        let mut text = String::with_capacity(content.len());
+        let mut crlf = Vec::new();
+        for piece in content.split_inclusive('\n') {
+            match split_ending(piece) {
+                (line, Some(is_crlf)) => {
+                    text.push_str(line);
+                    text.push('\n');
+                    crlf.push(is_crlf);
+                }
+                (line, None) => text.push_str(line),
+            }
+        }
+        Self { text, crlf }
+    }
+
+    /// Replace every occurrence of `old` with `new`, putting the line endings
+    /// back. Each newline of `new` takes the ending of the matched line at the
+    /// same position (or of the last one), so a file mixing endings keeps them.
+    fn replace(&self, old: &str, new: &str) -> String {
+        let old_newlines = old.matches('\n').count();
+        let mut out = String::with_capacity(self.text.len());
+        let mut newline = 0;
+        let mut last = 0;
+
+        for (start, _) in self.text.match_indices(old) {
+            newline += self.restore(&self.text[last..start], newline, &mut out);
+
+            let endings = &self.crlf[newline..newline + old_newlines];
+            let fallback = endings
+                .last()
+                .or(self.crlf.get(newline))
+                .or(self.crlf.last())
+                .copied()
+                .unwrap_or(false);
+            for (index, piece) in new.split_inclusive('\n').enumerate() {
+                let (line, ending) = split_ending(piece);
+                out.push_str(line);
+                if ending.is_some() {
+                    out.push_str(line_ending(endings.get(index).copied().unwrap_or(fallback)));
+                }
+            }
+
+            newline += old_newlines;
+            last = start + old.len();
+        }
+
+        self.restore(&self.text[last..], newline, &mut out);
+        out
+    }
+
+    /// Append `text`, whose first newline is newline number `first`, with its
+    /// original endings. Returns the number of newlines in it.
+    fn restore(&self, text: &str, first: usize, out: &mut String) -> usize {
+        let mut count = 0;
+        for piece in text.split_inclusive('\n') {
+            let (line, ending) = split_ending(piece);
+            out.push_str(line);
+            if ending.is_some() {
+                out.push_str(line_ending(self.crlf[first + count]));
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+/// Split a line from its ending, noting whether that is a CRLF (if it has one)
+fn split_ending(piece: &str) -> (&str, Option<bool>) {
+    match piece.strip_suffix('\n') {
+        Some(line) => match line.strip_suffix('\r') {
+            Some(line) => (line, Some(true)),
+            None => (line, Some(false)),
+        },
+        None => (piece, None),
+    }
+}
+
+fn line_ending(crlf: bool) -> &'static str {
+    if crlf { "\r\n" } else { "\n" }
+}
+
+/// Apply the edits to the content in memory, failing on the first anchor that
+/// is missing or found an unexpected number of times
+fn apply_edits(mut content: String, edits: &[Edit]) -> Result<(String, Vec<EditResult>)> {
+    let mut results = Vec::with_capacity(edits.len());
+
+    for (index, edit) in edits.iter().enumerate() {
+        if edit.old_string.is_empty() {
+            return Err(Error::InvalidParam(format!(
+                "Edit #{}: old_string must not be empty",
+                index
+            )));
+        }
+
+        // Anchors written with plain newlines match lines with either ending;
+        // ones spelling out a CR only match exactly
+        let normalized = (!edit.old_string.contains('\r')).then(|| Normalized::new(&content));
+
+        let expected = edit.expected_occurrences.unwrap_or(1);
+        let found = match &normalized {
+            Some(normalized) => normalized.text.matches(edit.old_string.as_str()).count(),
+            None => content.matches(edit.old_string.as_str()).count(),
+        };
+
+        if found == 0 {
+            return Err(Error::InvalidParam(format!(
+                "Edit #{}: old_string not found",
+                index
+            )));
+        }
+        if found != expected {
+            return Err(Error::InvalidParam(format!(
+                "Edit #{}: old_string found {} times, expected {}",
+                index, found, expected
+            )));
+        }
+
+        content = match &normalized {
+            Some(normalized) => normalized.replace(&edit.old_string, &edit.new_string),
+            None => content.replace(edit.old_string.as_str(), &edit.new_string),
+        };
+        results.push(EditResult {
+            edit: index,
+            replacements: found,
+        });
+    }
+
+    Ok((content, results))
+}
+
+#[async_trait]
+impl Tool for FileEdit {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "file_edit"
+    }
+
+    fn description(&self) -> &str {
+        "Replace exact text in a file"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::DESTRUCTIVE
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let path = PathBuf::from(&params.path);
+        let fs = vfs::current();
+
+        // Check that the path is an existing file
+        paths::require_file(fs.as_ref(), &path).await?;
+
+        if params.edits.is_empty() {
+            return Err(Error::InvalidParam("No edits provided".to_string()));
+        }
+
+        // Make sure nobody changed the file since the caller last read it
+        check_unchanged(
+            &path,
+            params.expected_sha256.as_deref(),
+            params.expected_mtime.as_deref(),
+        )
+        .await?;
+
+        let content = fs.read_to_string(&path).await?;
+        let original_size = content.len() as u64;
+
+        // Everything is applied in memory first, so a bad anchor leaves the file untouched
+        let (content, edits) = apply_edits(content, &params.edits)?;
+        let new_size = content.len() as u64;
+
+        fs.write(&path, content.as_bytes()).await?;
+
+        Ok(Output {
+            path: params.path,
+            original_size,
+            new_size,
+            edits,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::fs;
+
+    fn edit(old: &str, new: &str, expected: Option<usize>) -> Edit {
+        Edit {
+            old_string: old.to_string(),
+            new_string: new.to_string(),
+            expected_occurrences: expected,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_edit() -> Result<()> {
+        let test_file = std::env::temp_dir().join(format!(
+            "test_file_edit_{}_{}.rs",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        fs::write(&test_file, "fn a() {}\nfn b() {}\nlet x = 1;\nlet x = 1;\n").await?;
+
+        let tool = FileEdit;
+        let params = Params {
+            path: test_file.to_string_lossy().to_string(),
+            edits: vec![
+                edit("fn a() {}", "fn a() { b() }", None),
+                edit("let x = 1;", "let x = 2;", Some(2)),
+            ],
+            expected_sha256: None,
+            expected_mtime: None,
+        };
+
+        let result = tool.execute(params).await?;
+        assert_eq!(result.edits.len(), 2);
+        assert_eq!(result.edits[1].replacements, 2);
+        assert_eq!(
+            fs::read_to_string(&test_file).await?,
+            "fn a() { b() }\nfn b() {}\nlet x = 2;\nlet x = 2;\n"
+        );
+
+        // An ambiguous anchor fails and nothing is written, even for earlier edits
+        let params = Params {
+            path: test_file.to_string_lossy().to_string(),
+            edits: vec![
+                edit("fn b() {}", "fn c() {}", None),
+                edit("let x = 2;", "let y = 2;", None),
+            ],
+            expected_sha256: None,
+            expected_mtime: None,
+        };
+
+        assert!(tool.execute(params).await.is_err());
+        assert_eq!(
+            fs::read_to_string(&test_file).await?,
+            "fn a() { b() }\nfn b() {}\nlet x = 2;\nlet x = 2;\n"
+        );
+
+        fs::remove_file(&test_file).await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_edits_crlf_and_missing() {
+        let content = "one\r\ntwo\r\nthree\r\n".to_string();
+
+        let (edited, _) = apply_edits(content.clone(), &[edit("one\ntwo", "1\n2", None)]).unwrap();
+        assert_eq!(edited, "1\r\n2\r\nthree\r\n");
+
+        let result = apply_edits(content, &[edit("four", "4", None)]);
+        assert!(matches!(result, Err(Error::InvalidParam(_))));
+    }
+
+    #[test]
+    fn test_apply_edits_mixed_endings() {
+        // Each matched line keeps its own ending; added lines take the last one
+        let content = "one\r\ntwo\nthree\r\nfour\n".to_string();
+
+        let (edited, _) =
+            apply_edits(content.clone(), &[edit("one\ntwo\nthree", "1\n2\n3\n3.5", None)]).unwrap();
+        assert_eq!(edited, "1\r\n2\n3\n3.5\r\nfour\n");
+
+        // A single-line anchor's new lines take the ending of its line
+        let (edited, _) = apply_edits(content, &[edit("two", "2\n2.5", None)]).unwrap();
+        assert_eq!(edited, "one\r\n2\n2.5\nthree\r\nfour\n");
+    }
+}
@@ -0,0 +1,266 @@
+//! Line-range edit tool implementation
+//!
+//! Replaces, inserts or deletes an explicit range of lines. The replaced range
+//! can be guarded by a hash of its current text, so edits based on a stale view
+//! of the file are rejected.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::path::PathBuf;
+
+use super::{Annotations, Tool};
+use super::file_patch::detect_line_ending;
+use super::precondition::sha256_hex;
+use crate::{Error, Result, paths, vfs};
+
+/// Line-range edit tool
+#[derive(Clone, Copy)]
+pub struct FileEditLines;
+
+/// What to do with the line range
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LineOperation {
+    /// Replace lines `start_line..=end_line` with `content`
+    #[default]
+    Replace,
+    /// Insert `content` before `start_line` (one past the last line appends)
+    Insert,
+    /// Delete lines `start_line..=end_line`
+    Delete,
+}
+
+/// Parameters for the line-range edit tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Path of the file to edit
+    #[serde(deserialize_with = "crate::roots::path")]
+    pub path: String,
+
+    /// First line of the range (1-based)
+    pub start_line: usize,
+
+    /// Last line of the range (inclusive, defaults to `start_line`; ignored for inserts)
+    #[serde(default)]
+    pub end_line: Option<usize>,
+
+    /// What to do with the range
+    #[serde(default)]
+    pub operation: LineOperation,
+
+    /// New lines for replace and insert
+    #[serde(default)]
+    pub content: String,
+
+    /// SHA-256 (hex) of the current text of the range, each line ending in `\n`.
+    /// The edit is rejected with a conflict if the range no longer matches.
+    #[serde(default)]
+    pub expected_hash: Option<String>,
+}
+
+impl Params {
+    /// Edit the file at `path` starting at `start_line`
+    pub fn new(path: impl Into<String>, start_line: usize) -> Self {
+        Self {
+            path: path.into(),
+            start_line,
+            end_line: None,
+            operation: LineOperation::default(),
+            content: String::new(),
+            expected_hash: None,
+        }
+    }
+}
+
+/// Output of the line-range edit tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Path of the edited file
+    pub path: String,
+
+    /// Operation that was applied
+    pub operation: LineOperation,
+
+    /// Number of lines removed
+    pub lines_removed: usize,
+
+    /// Number of lines inserted
+    pub lines_inserted: usize,
+
+    /// SHA-256 of the text that was replaced or deleted, in `expected_hash` form
+    pub range_hash: String,
+
+    /// Number of lines in the file after the edit
+    pub total_lines: usize,
+}
+
+/// Hash a range of lines the way `expected_hash` is specified
+fn hash_lines(lines: &[String]) -> String {
+    let text: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+    sha256_hex(text.as_bytes())
+}
+
+#[async_trait]
+impl Tool for FileEditLines {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "file_edit_lines"
+    }
+
+    fn description(&self) -> &str {
+        "Replace, insert or delete a range of lines in a file"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::DESTRUCTIVE
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let path = PathBuf::from(&params.path);
+        let fs = vfs::current();
+
+        // Check that the path is an existing file
+        paths::require_file(fs.as_ref(), &path).await?;
+
+        let content = fs.read_to_string(&path).await?;
+        let line_ending = detect_line_ending(&content);
+        let trailing_newline = content.is_empty() || content.ends_with('\n');
+        let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
+
+        if params.start_line == 0 {
+            return Err(Error::InvalidParam(
+                "start_line is 1-based and must be at least 1".to_string(),
+            ));
+        }
+        let start = params.start_line - 1;
+
+        // Work out the affected range as 0-based indices
+        let range = match params.operation {
+            LineOperation::Insert => {
+                if start > lines.len() {
+                    return Err(Error::InvalidParam(format!(
+                        "Cannot insert before line {}: the file has {} lines",
+                        params.start_line,
+                        lines.len()
+                    )));
+                }
+                start..start
+            }
+            LineOperation::Replace | LineOperation::Delete => {
+                let end_line = params.end_line.unwrap_or(params.start_line);
+                if end_line < params.start_line || end_line > lines.len() {
+                    return Err(Error::InvalidParam(format!(
+                        "Invalid line range {}-{}: the file has {} lines",
+                        params.start_line,
+                        end_line,
+                        lines.len()
+                    )));
+                }
+                start..end_line
+            }
+        };
+
+        // Refuse to edit a range that changed since the caller looked at it
+        let range_hash = hash_lines(&lines[range.clone()]);
+        if let Some(expected) = &params.expected_hash
+            && !expected.trim().eq_ignore_ascii_case(&range_hash)
+        {
+            return Err(Error::Conflict(format!(
+                "Lines {}-{} of {} have changed since they were read",
+                params.start_line, range.end, params.path
+            )));
+        }
+
+        let new_lines: Vec<String> = match params.operation {
+            LineOperation::Delete => Vec::new(),
+            _ => params.content.lines().map(|s| s.to_string()).collect(),
+        };
+
+        let lines_removed = range.len();
+        let lines_inserted = new_lines.len();
+        let tail = lines.split_off(range.end);
+        lines.truncate(range.start);
+        lines.extend(new_lines);
+        lines.extend(tail);
+
+        let mut updated = lines.join(line_ending);
+        if trailing_newline && !lines.is_empty() {
+            updated.push_str(line_ending);
+        }
+        fs.write(&path, updated.as_bytes()).await?;
+
+        Ok(Output {
+            path: params.path,
+            operation: params.operation,
+            lines_removed,
+            lines_inserted,
+            range_hash,
+            total_lines: lines.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::fs;
+
+    fn params(path: &str, operation: LineOperation, start: usize, end: Option<usize>) -> Params {
+        Params {
+            path: path.to_string(),
+            start_line: start,
+            end_line: end,
+            operation,
+            content: String::new(),
+            expected_hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_edit_lines() -> Result<()> {
+        let test_file = std::env::temp_dir().join(format!(
+            "test_edit_lines_{}_{}.txt",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        fs::write(&test_file, "a\nb\nc\nd\n").await?;
+        let path = test_file.to_string_lossy().to_string();
+        let tool = FileEditLines;
+
+        // Replace b..c, guarded by the hash of the current range
+        let mut replace = params(&path, LineOperation::Replace, 2, Some(3));
+        replace.content = "B\nC\nC2\n".to_string();
+        replace.expected_hash = Some(sha256_hex(b"b\nc\n"));
+        let result = tool.execute(replace).await?;
+        assert_eq!(result.lines_removed, 2);
+        assert_eq!(result.lines_inserted, 3);
+        assert_eq!(fs::read_to_string(&test_file).await?, "a\nB\nC\nC2\nd\n");
+
+        // Insert at the end and delete the first line
+        let mut insert = params(&path, LineOperation::Insert, 6, None);
+        insert.content = "e".to_string();
+        tool.execute(insert).await?;
+        tool.execute(params(&path, LineOperation::Delete, 1, None))
+            .await?;
+        assert_eq!(fs::read_to_string(&test_file).await?, "B\nC\nC2\nd\ne\n");
+
+        // A stale hash is a conflict
+        let mut stale = params(&path, LineOperation::Delete, 1, Some(2));
+        stale.expected_hash = Some(sha256_hex(b"b\nc\n"));
+        let result = tool.execute(stale).await;
+        assert!(matches!(result, Err(Error::Conflict(_))));
+
+        // Out-of-range edits are rejected
+        let result = tool
+            .execute(params(&path, LineOperation::Delete, 4, Some(9)))
+            .await;
+        assert!(matches!(result, Err(Error::InvalidParam(_))));
+
+        fs::remove_file(&test_file).await?;
+
+        Ok(())
+    }
+}
@@ -1,19 +1,25 @@
 //! File find tool implementation
 
 use async_trait::async_trait;
+use chrono::DateTime;
 use glob::Pattern;
+use regex::Regex;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 use std::path::{Path, PathBuf};
-use tokio::fs;
-use tokio::task;
-use walkdir::{DirEntry, WalkDir};
+use std::time::{Duration, SystemTime};
 
-use super::Tool;
+use super::{Annotations, Tool};
+use crate::cancel;
+use crate::paths;
+use crate::vfs::{self, DirEntry, FileKind, Filesystem, Metadata, WalkOptions};
+use crate::warnings::Skipped;
 use crate::{Error, Result};
 
 /// File type for filtering search results
-#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum FileType {
     /// Only find files
@@ -21,31 +27,48 @@ pub enum FileType {
     /// Only find directories
     Directory,
     /// Find both files and directories
+    #[default]
     All,
 }
 
-impl Default for FileType {
-    fn default() -> Self {
-        Self::All
-    }
-}
-
 /// Find mode for determining how to match paths
-#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum FindMode {
     /// Match exact filename or directory
+    #[default]
     Name,
     /// Match a glob pattern
     Pattern,
     /// Match anywhere in the path
     Path,
+    /// Match a regular expression against the file name, or against the full
+    /// path if the expression contains a `/`
+    Regex,
 }
 
-impl Default for FindMode {
-    fn default() -> Self {
-        Self::Name
-    }
+/// Key to sort results by
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    /// Sort by full path
+    #[default]
+    Path,
+    /// Sort by size (directories have no size and sort first)
+    Size,
+    /// Sort by last modified time
+    Mtime,
+}
+
+/// Sort direction
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Smallest, oldest or alphabetically first entries first
+    #[default]
+    Asc,
+    /// Largest, newest or alphabetically last entries first
+    Desc,
 }
 
 /// File find tool
@@ -53,9 +76,10 @@ impl Default for FindMode {
 pub struct FileFind;
 
 /// Parameters for the file find tool
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Params {
     /// Directory to search in
+    #[serde(deserialize_with = "crate::roots::path")]
     pub directory: String,
 
     /// Pattern to search for
@@ -77,7 +101,7 @@ pub struct Params {
     #[serde(default)]
     pub max_depth: usize,
 
-    /// Maximum number of results to return (0 means no limit)
+    /// Maximum number of results to return per page (0 means no limit)
     #[serde(default)]
     pub limit: usize,
 
@@ -88,6 +112,64 @@ pub struct Params {
     /// Patterns to ignore
     #[serde(default)]
     pub ignore: Vec<String>,
+
+    /// Only find files of at least this many bytes (excludes directories)
+    #[serde(default)]
+    pub min_size: Option<u64>,
+
+    /// Only find files of at most this many bytes (excludes directories)
+    #[serde(default)]
+    pub max_size: Option<u64>,
+
+    /// Only find entries modified after this time: an RFC 3339 timestamp or an
+    /// age such as "30m", "1h" or "7d"
+    #[serde(default)]
+    pub modified_after: Option<String>,
+
+    /// Only find entries modified before this time (same formats as `modified_after`)
+    #[serde(default)]
+    pub modified_before: Option<String>,
+
+    /// Only find empty files and directories with no entries
+    #[serde(default)]
+    pub empty_only: bool,
+
+    /// Key to sort results by (ties are broken by path)
+    #[serde(default)]
+    pub sort_by: SortBy,
+
+    /// Sort direction
+    #[serde(default)]
+    pub order: SortOrder,
+
+    /// Cursor from a previous response's `next_cursor`, to fetch the next page
+    #[serde(default)]
+    pub cursor: Option<String>,
+}
+
+impl Params {
+    /// Find entries matching `pattern` under `directory`
+    pub fn new(directory: impl Into<String>, pattern: impl Into<String>) -> Self {
+        Self {
+            directory: directory.into(),
+            pattern: pattern.into(),
+            mode: FindMode::default(),
+            file_type: FileType::default(),
+            recursive: default_recursive(),
+            max_depth: 0,
+            limit: 0,
+            follow_links: false,
+            ignore: Vec::new(),
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            empty_only: false,
+            sort_by: SortBy::default(),
+            order: SortOrder::default(),
+            cursor: None,
+        }
+    }
 }
 
 fn default_recursive() -> bool {
@@ -95,7 +177,7 @@ fn default_recursive() -> bool {
 }
 
 /// File entry in results
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct FileEntry {
     /// Full path
     pub path: String,
@@ -116,7 +198,7 @@ pub struct FileEntry {
 }
 
 /// Output of the file find tool
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Output {
     /// Directory that was searched
     pub directory: String,
@@ -130,32 +212,39 @@ pub struct Output {
     /// Total number of matches found
     pub total: usize,
 
-    /// Whether the results were limited
-    pub limited: bool,
-}
+    /// Number of entries the walk visited, matching or not
+    pub scanned_entries: usize,
 
-/// Check if a path is a valid directory and canonicalize it
-async fn prepare_directory(dir_path: impl AsRef<Path>) -> Result<PathBuf> {
-    let path = dir_path.as_ref();
+    /// Directories the walk couldn't read, by reason
+    #[serde(default)]
+    pub skipped: Skipped,
 
-    if !path.exists() {
-        return Err(Error::InvalidParam(format!(
-            "Directory not found: {}",
-            path.display()
-        )));
-    }
+    /// Whether the results were limited
+    pub limited: bool,
 
-    if !path.is_dir() {
-        return Err(Error::InvalidParam(format!(
-            "Path is not a directory: {}",
-            path.display()
-        )));
-    }
+    /// Cursor for the next page, if more results remain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
 
-    // Canonicalize the path
-    let canonical = path.canonicalize().map_err(Error::Io)?;
+/// Identify the sort a cursor belongs to, so it isn't reused with another one
+fn cursor_key(sort_by: SortBy, order: SortOrder) -> String {
+    format!("{:?}:{:?}", sort_by, order).to_lowercase()
+}
 
-    Ok(canonical)
+/// Turn a cursor back into the offset of the next page
+fn parse_cursor(cursor: &str, sort_by: SortBy, order: SortOrder) -> Result<usize> {
+    cursor
+        .rsplit_once(':')
+        .filter(|(key, _)| *key == cursor_key(sort_by, order))
+        .and_then(|(_, offset)| offset.parse().ok())
+        .ok_or_else(|| {
+            Error::InvalidParam(format!(
+                "Invalid cursor '{}' for sort_by/order {}",
+                cursor,
+                cursor_key(sort_by, order)
+            ))
+        })
 }
 
 /// Search configuration parameters
@@ -167,6 +256,99 @@ struct SearchConfig {
     max_depth: usize,
     limit: usize,
     follow_links: bool,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<SystemTime>,
+    modified_before: Option<SystemTime>,
+    empty_only: bool,
+}
+
+/// Parse an RFC 3339 timestamp, or an age ("90s", "15m", "1h", "2d", "1w")
+/// counted back from now
+fn parse_time(value: &str) -> Result<SystemTime> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.into());
+    }
+
+    let invalid = || {
+        Error::InvalidParam(format!(
+            "Invalid time '{}': expected RFC 3339 or an age like 30m, 1h, 7d",
+            value
+        ))
+    };
+
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+
+    amount
+        .checked_mul(seconds)
+        .and_then(|secs| SystemTime::now().checked_sub(Duration::from_secs(secs)))
+        .ok_or_else(invalid)
+}
+
+/// Check an entry against the size, modification time and emptiness filters
+async fn matches_metadata(fs: &dyn Filesystem, entry: &DirEntry, config: &SearchConfig) -> bool {
+    let size_filter = config.min_size.is_some() || config.max_size.is_some();
+    let time_filter = config.modified_after.is_some() || config.modified_before.is_some();
+    if !size_filter && !time_filter && !config.empty_only {
+        return true;
+    }
+
+    let metadata = &entry.metadata;
+
+    if config.empty_only {
+        let empty = if metadata.is_dir() {
+            fs.read_dir(entry.path())
+                .await
+                .map(|entries| entries.is_empty())
+                .unwrap_or(false)
+        } else {
+            metadata.is_empty()
+        };
+        if !empty {
+            return false;
+        }
+    }
+
+    if size_filter {
+        // Sizes only make sense for files
+        if metadata.is_dir() {
+            return false;
+        }
+        let size = metadata.len();
+        if config.min_size.is_some_and(|min| size < min)
+            || config.max_size.is_some_and(|max| size > max)
+        {
+            return false;
+        }
+    }
+
+    if time_filter {
+        let Ok(modified) = metadata.modified() else {
+            return false;
+        };
+        if config.modified_after.is_some_and(|after| modified < after)
+            || config
+                .modified_before
+                .is_some_and(|before| modified > before)
+        {
+            return false;
+        }
+    }
+
+    true
 }
 
 /// Check if an entry should be included in results
@@ -179,7 +361,15 @@ fn should_include_entry(
     file_type: FileType,
     ignore_patterns: &[Pattern],
 ) -> bool {
-    should_include_entry_with_config(entry, pattern, &None, mode, file_type, ignore_patterns)
+    should_include_entry_with_config(
+        entry,
+        pattern,
+        &None,
+        &None,
+        mode,
+        file_type,
+        ignore_patterns,
+    )
 }
 
 /// Check if an entry should be included in results using the compiled pattern
@@ -187,12 +377,13 @@ fn should_include_entry_with_config(
     entry: &DirEntry,
     pattern_str: &str,
     compiled_pattern: &Option<Pattern>,
+    compiled_regex: &Option<Regex>,
     mode: FindMode,
     file_type: FileType,
     ignore_patterns: &[Pattern],
 ) -> bool {
     // Check file type
-    let is_dir = entry.file_type().is_dir();
+    let is_dir = entry.metadata.is_dir();
     match file_type {
         FileType::File if is_dir => return false,
         FileType::Directory if !is_dir => return false,
@@ -200,7 +391,7 @@ fn should_include_entry_with_config(
     }
 
     // Check ignore patterns
-    let path_str = entry.path().to_string_lossy();
+    let path_str = paths::to_string(entry.path());
     if ignore_patterns.iter().any(|p| p.matches(&path_str)) {
         return false;
     }
@@ -209,21 +400,18 @@ fn should_include_entry_with_config(
     match mode {
         FindMode::Name => {
             // Match against just the filename
-            if let Some(file_name) = entry.file_name().to_str() {
-                file_name == pattern_str
-                    || (pattern_str.contains('*') && {
-                        // Use the compiled pattern if provided, otherwise create one
-                        if let Some(pattern) = compiled_pattern {
-                            pattern.matches(file_name)
-                        } else {
-                            Pattern::new(pattern_str)
-                                .map(|p| p.matches(file_name))
-                                .unwrap_or(false)
-                        }
-                    })
-            } else {
-                false
-            }
+            let file_name = entry.file_name();
+            file_name == pattern_str
+                || (pattern_str.contains('*') && {
+                    // Use the compiled pattern if provided, otherwise create one
+                    if let Some(pattern) = compiled_pattern {
+                        pattern.matches(&file_name)
+                    } else {
+                        Pattern::new(pattern_str)
+                            .map(|p| p.matches(&file_name))
+                            .unwrap_or(false)
+                    }
+                })
         }
         FindMode::Pattern => {
             // Match using glob pattern
@@ -239,26 +427,155 @@ fn should_include_entry_with_config(
             // Match anywhere in the path
             path_str.contains(pattern_str)
         }
+        FindMode::Regex => {
+            let Some(regex) = compiled_regex else {
+                return false;
+            };
+            if pattern_str.contains('/') {
+                regex.is_match(&path_str)
+            } else {
+                regex.is_match(&entry.file_name())
+            }
+        }
     }
 }
 
-/// Get file metadata
-async fn get_file_metadata(path: &Path) -> std::io::Result<Option<(u64, i64)>> {
-    let metadata = fs::metadata(path).await?;
+/// A match, with what it is sorted by; only the returned page becomes
+/// [`FileEntry`]s
+struct Found {
+    path: PathBuf,
+    reported: String,
+    is_dir: bool,
+    size: Option<u64>,
+    modified: Option<i64>,
+}
 
-    let size = if metadata.is_file() {
-        Some(metadata.len())
-    } else {
-        None
-    };
+impl Found {
+    /// A match described by the metadata the walk found, or by that of what
+    /// it links to; `None` for a link that doesn't resolve
+    fn new(path: PathBuf, metadata: Option<&Metadata>) -> Self {
+        let reported = paths::to_string(&path);
+        let Some(metadata) = metadata else {
+            return Self {
+                path,
+                reported,
+                is_dir: false,
+                size: None,
+                modified: None,
+            };
+        };
+
+        let is_dir = metadata.is_dir();
+        let size = if is_dir {
+            None
+        } else if metadata.is_file() {
+            Some(metadata.len())
+        } else {
+            Some(0)
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs() as i64);
+
+        Self {
+            path,
+            reported,
+            is_dir,
+            size,
+            modified: Some(modified.unwrap_or(0)),
+        }
+    }
+
+    fn into_entry(self) -> FileEntry {
+        let name = self
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        FileEntry {
+            path: self.reported,
+            name,
+            is_dir: self.is_dir,
+            size: self.size,
+            modified: self.modified,
+        }
+    }
+}
+
+/// A match ranked by its path alone, in the requested order
+struct ByPath(Found, SortOrder);
+
+impl Ord for ByPath {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let ordering = self.0.reported.cmp(&other.0.reported);
+        match self.1 {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    }
+}
+
+impl PartialOrd for ByPath {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ByPath {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
 
-    let modified = metadata
-        .modified()
-        .ok()
-        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
-        .map(|duration| duration.as_secs() as i64);
+impl Eq for ByPath {}
 
-    Ok(Some((size.unwrap_or(0), modified.unwrap_or(0))))
+/// The first `keep` matches by path, with the last of them on top of the heap
+/// so that it is the one pushed out by an earlier match
+struct FirstByPath {
+    heap: BinaryHeap<ByPath>,
+    keep: usize,
+    order: SortOrder,
+}
+
+impl FirstByPath {
+    fn new(keep: usize, order: SortOrder) -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            keep,
+            order,
+        }
+    }
+
+    /// Whether a match at `reported` would make the cut
+    fn admits(&self, reported: &str) -> bool {
+        if self.heap.len() < self.keep {
+            return true;
+        }
+        self.heap.peek().is_some_and(|last| {
+            let ordering = reported.cmp(&last.0.reported);
+            match self.order {
+                SortOrder::Asc => ordering.is_lt(),
+                SortOrder::Desc => ordering.is_gt(),
+            }
+        })
+    }
+
+    fn push(&mut self, found: Found) {
+        self.heap.push(ByPath(found, self.order));
+        if self.heap.len() > self.keep {
+            self.heap.pop();
+        }
+    }
+
+    fn into_sorted(self) -> Vec<Found> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|ranked| ranked.0)
+            .collect()
+    }
 }
 
 #[async_trait]
@@ -270,10 +587,19 @@ impl Tool for FileFind {
         "file_find"
     }
 
+    fn description(&self) -> &str {
+        "Find files matching a pattern"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
     async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
         // Validate and canonicalize the directory
-        let directory = prepare_directory(&params.directory).await?;
-        let dir_string = directory.to_string_lossy().to_string();
+        let fs = vfs::for_path(Path::new(&params.directory))?;
+        let directory = paths::canonical_dir(fs.as_ref(), Path::new(&params.directory)).await?;
+        let dir_string = paths::to_string(&directory);
 
         // Save the pattern string for the result
         let pattern_for_result = params.pattern.clone();
@@ -286,17 +612,52 @@ impl Tool for FileFind {
             max_depth: params.max_depth,
             limit: params.limit,
             follow_links: params.follow_links,
+            min_size: params.min_size,
+            max_size: params.max_size,
+            modified_after: params
+                .modified_after
+                .as_deref()
+                .map(parse_time)
+                .transpose()?,
+            modified_before: params
+                .modified_before
+                .as_deref()
+                .map(parse_time)
+                .transpose()?,
+            empty_only: params.empty_only,
+        };
+
+        if let (Some(min), Some(max)) = (search_config.min_size, search_config.max_size)
+            && min > max
+        {
+            return Err(Error::InvalidParam(format!(
+                "min_size ({}) is larger than max_size ({})",
+                min, max
+            )));
+        }
+
+        // Resolve the cursor before doing any work
+        let offset = match &params.cursor {
+            Some(cursor) => parse_cursor(cursor, params.sort_by, params.order)?,
+            None => 0,
         };
 
-        // Prepare patterns before moving them into the blocking task
+        // Prepare patterns before walking
         let pattern = Pattern::new(&params.pattern).ok();
+        let regex = if params.mode == FindMode::Regex {
+            Some(Regex::new(&params.pattern).map_err(|e| {
+                Error::InvalidParam(format!("Invalid regex '{}': {}", params.pattern, e))
+            })?)
+        } else {
+            None
+        };
         let ignore_patterns: Vec<Pattern> = params
             .ignore
             .iter()
             .filter_map(|pattern| Pattern::new(pattern).ok())
             .collect();
 
-        // Set up the walkdir with proper configuration
+        // Set up the walk with proper configuration
         let max_depth = if search_config.recursive {
             if search_config.max_depth > 0 {
                 search_config.max_depth
@@ -307,88 +668,111 @@ impl Tool for FileFind {
             1
         };
 
-        // Perform the search operation in a blocking task to avoid async overhead
-        let search_result = task::spawn_blocking(move || {
-            let mut entries = Vec::new();
-            let mut total = 0;
-            let mut limited = false;
-
-            let walker = WalkDir::new(&directory)
-                .max_depth(max_depth)
-                .follow_links(search_config.follow_links);
-
-            for entry in walker {
-                match entry {
-                    Ok(entry) => {
-                        // Skip the root directory itself
-                        if entry.path() == directory {
-                            continue;
-                        }
-
-                        if should_include_entry_with_config(
-                            &entry,
-                            &params.pattern,
-                            &pattern,
-                            search_config.mode,
-                            search_config.file_type,
-                            &ignore_patterns,
-                        ) {
-                            total += 1;
-
-                            // Check limit
-                            if search_config.limit > 0 && entries.len() >= search_config.limit {
-                                limited = true;
-                                continue;
-                            }
-
-                            // Add to results
-                            entries.push(entry.path().to_path_buf());
-                        }
-                    }
-                    Err(_) => continue,
+        let options = WalkOptions {
+            max_depth,
+            follow_links: search_config.follow_links,
+        };
+        // The walk already has the metadata of every entry, so matches are
+        // only looked up again when they are links
+        let mut found = Vec::new();
+        let mut total: usize = 0;
+        let mut scanned_entries = 0;
+        // Sorted by path, a page only needs the matches up to its end, so
+        // only those are held and later ones are just counted
+        let mut first = (params.sort_by == SortBy::Path && search_config.limit > 0)
+            .then(|| FirstByPath::new(offset.saturating_add(search_config.limit), params.order));
+        let (walked, skipped) = fs.walk_skipping(&directory, options).await?;
+        for entry in walked {
+            cancel::check()?;
+            scanned_entries += 1;
+            if should_include_entry_with_config(
+                &entry,
+                &params.pattern,
+                &pattern,
+                &regex,
+                search_config.mode,
+                search_config.file_type,
+                &ignore_patterns,
+            ) && matches_metadata(fs.as_ref(), &entry, &search_config).await
+            {
+                total += 1;
+                if let Some(first) = &first
+                    && !first.admits(&paths::to_string(&entry.path))
+                {
+                    continue;
                 }
-            }
-
-            (entries, total, limited)
-        })
-        .await
-        .map_err(|e| Error::Other(format!("Join error: {}", e)))?;
-
-        let (path_entries, total, limited) = search_result;
 
-        // Map paths to file entries with metadata
-        let mut entries = Vec::with_capacity(path_entries.len());
-        for path in path_entries {
-            let name = path
-                .file_name()
-                .map(|n| n.to_string_lossy().to_string())
-                .unwrap_or_default();
-
-            let is_dir = path.is_dir();
-
-            let (size, modified) = match get_file_metadata(&path).await {
-                Ok(Some((s, m))) => (if is_dir { None } else { Some(s) }, Some(m)),
-                _ => (None, None),
-            };
+                // Describe the match; limits apply after sorting
+                let metadata = if entry.metadata.kind == FileKind::Symlink {
+                    fs.metadata(&entry.path).await.ok()
+                } else {
+                    Some(entry.metadata)
+                };
+                let entry = Found::new(entry.path, metadata.as_ref());
+                match &mut first {
+                    Some(first) => first.push(entry),
+                    None => found.push(entry),
+                }
+            }
+        }
 
-            entries.push(FileEntry {
-                path: path.to_string_lossy().to_string(),
-                name,
-                is_dir,
-                size,
-                modified,
+        // The held matches come out in order; the others are sorted, falling
+        // back to the path so the order (and paging) is deterministic
+        if let Some(first) = first {
+            found = first.into_sorted();
+        } else {
+            found.sort_by(|a, b| {
+                let ordering = match params.sort_by {
+                    SortBy::Path => a.reported.cmp(&b.reported),
+                    SortBy::Size => a
+                        .size
+                        .cmp(&b.size)
+                        .then_with(|| a.reported.cmp(&b.reported)),
+                    SortBy::Mtime => a
+                        .modified
+                        .cmp(&b.modified)
+                        .then_with(|| a.reported.cmp(&b.reported)),
+                };
+                match params.order {
+                    SortOrder::Asc => ordering,
+                    SortOrder::Desc => ordering.reverse(),
+                }
             });
         }
 
-        // Sort by path for consistency
-        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        // Cut out the requested page, and only describe what is on it
+        let remaining = total.saturating_sub(offset);
+        let limited = search_config.limit > 0 && remaining > search_config.limit;
+        let page = if search_config.limit > 0 {
+            search_config.limit
+        } else {
+            remaining
+        };
+        let entries: Vec<FileEntry> = found
+            .into_iter()
+            .skip(offset)
+            .take(page)
+            .map(Found::into_entry)
+            .collect();
+        let next_cursor = if limited {
+            Some(format!(
+                "{}:{}",
+                cursor_key(params.sort_by, params.order),
+                offset + search_config.limit
+            ))
+        } else {
+            None
+        };
 
         Ok(Output {
             directory: dir_string,
             pattern: pattern_for_result,
             entries,
             total,
+            scanned_entries,
+            skipped,
             limited,
+            next_cursor,
         })
     }
 }
@@ -397,6 +781,7 @@ impl Tool for FileFind {
 mod tests {
     use super::*;
     use log::debug;
+    use tokio::fs;
     use tokio::fs::File;
     use tokio::io::AsyncWriteExt;
 
@@ -425,10 +810,10 @@ mod tests {
         }
 
         // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent).await?;
-            }
+        if let Some(parent) = path.parent()
+            && !parent.exists()
+        {
+            fs::create_dir_all(parent).await?;
         }
 
         // Create and write to the file
@@ -520,6 +905,14 @@ mod tests {
             limit: 0,
             follow_links: false,
             ignore: vec![],
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            empty_only: false,
+            sort_by: SortBy::Path,
+            order: SortOrder::Asc,
+            cursor: None,
         };
 
         let result = tool.execute(params).await?;
@@ -554,6 +947,14 @@ mod tests {
             limit: 0,
             follow_links: false,
             ignore: vec![],
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            empty_only: false,
+            sort_by: SortBy::Path,
+            order: SortOrder::Asc,
+            cursor: None,
         };
 
         let result = tool.execute(params).await?;
@@ -583,6 +984,14 @@ mod tests {
             limit: 0,
             follow_links: false,
             ignore: vec![],
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            empty_only: false,
+            sort_by: SortBy::Path,
+            order: SortOrder::Asc,
+            cursor: None,
         };
 
         let result = tool.execute(params).await?;
@@ -621,6 +1030,14 @@ mod tests {
             limit: 2,
             follow_links: false,
             ignore: vec![],
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            empty_only: false,
+            sort_by: SortBy::Path,
+            order: SortOrder::Asc,
+            cursor: None,
         };
 
         let result = tool.execute(params).await?;
@@ -653,6 +1070,14 @@ mod tests {
             limit: 0,
             follow_links: false,
             ignore: vec![],
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            empty_only: false,
+            sort_by: SortBy::Path,
+            order: SortOrder::Asc,
+            cursor: None,
         };
 
         let result = tool.execute(params).await?;
@@ -687,6 +1112,14 @@ mod tests {
             limit: 0,
             follow_links: false,
             ignore: vec!["*.log".to_string()],
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            empty_only: false,
+            sort_by: SortBy::Path,
+            order: SortOrder::Asc,
+            cursor: None,
         };
 
         let result = tool.execute(params).await?;
@@ -699,4 +1132,248 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_find_with_size_and_time() -> Result<()> {
+        let test_dir = setup_test_directory().await?;
+        create_test_file(&test_dir.join("big.bin"), &"x".repeat(2048)).await?;
+        let tool = FileFind;
+
+        let params =
+            |min_size, modified_after: Option<&str>, modified_before: Option<&str>| Params {
+                directory: test_dir.to_string_lossy().to_string(),
+                pattern: "*".to_string(),
+                mode: FindMode::Pattern,
+                file_type: FileType::All,
+                recursive: true,
+                max_depth: 0,
+                limit: 0,
+                follow_links: false,
+                ignore: vec![],
+                min_size,
+                max_size: None,
+                modified_after: modified_after.map(|s| s.to_string()),
+                modified_before: modified_before.map(|s| s.to_string()),
+                empty_only: false,
+                sort_by: SortBy::Path,
+                order: SortOrder::Asc,
+                cursor: None,
+            };
+
+        // Only the large file passes the size filter, directories never do
+        let result = tool.execute(params(Some(1024), None, None)).await?;
+        assert_eq!(result.entries.len(), 1);
+        assert_eq!(result.entries[0].name, "big.bin");
+
+        // Everything was just created
+        let result = tool.execute(params(None, Some("1h"), None)).await?;
+        assert_eq!(result.entries.len(), 9);
+        let result = tool
+            .execute(params(None, None, Some("2000-01-01T00:00:00Z")))
+            .await?;
+        assert!(result.entries.is_empty());
+
+        // Bad times are rejected
+        assert!(matches!(
+            tool.execute(params(None, Some("yesterday"), None)).await,
+            Err(Error::InvalidParam(_))
+        ));
+
+        // Clean up
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_by_regex() -> Result<()> {
+        let test_dir = setup_test_directory().await?;
+        create_test_file(&test_dir.join("test_parse_v2.rs"), "").await?;
+        create_test_file(&test_dir.join("test_parse_vx.rs"), "").await?;
+        let tool = FileFind;
+
+        let params = |pattern: &str| Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            pattern: pattern.to_string(),
+            mode: FindMode::Regex,
+            file_type: FileType::File,
+            recursive: true,
+            max_depth: 0,
+            limit: 0,
+            follow_links: false,
+            ignore: vec![],
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            empty_only: false,
+            sort_by: SortBy::Path,
+            order: SortOrder::Asc,
+            cursor: None,
+        };
+
+        // Without a slash the expression is matched against the file name
+        let result = tool.execute(params(r"^test_.*_v\d+\.rs$")).await?;
+        assert_eq!(result.entries.len(), 1);
+        assert_eq!(result.entries[0].name, "test_parse_v2.rs");
+
+        // With a slash it is matched against the full path
+        let result = tool.execute(params(r"dir2/.*\.(txt|log)$")).await?;
+        assert_eq!(result.entries.len(), 2);
+
+        // Invalid expressions are rejected
+        assert!(matches!(
+            tool.execute(params("(")).await,
+            Err(Error::InvalidParam(_))
+        ));
+
+        // Clean up
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_empty_only() -> Result<()> {
+        let test_dir = setup_test_directory().await?;
+        create_test_file(&test_dir.join("dir1/empty.txt"), "").await?;
+        fs::create_dir(test_dir.join("dir1/empty_dir")).await?;
+        let tool = FileFind;
+
+        let params = Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            pattern: "*".to_string(),
+            mode: FindMode::Pattern,
+            file_type: FileType::All,
+            recursive: true,
+            max_depth: 0,
+            limit: 0,
+            follow_links: false,
+            ignore: vec![],
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            empty_only: true,
+            sort_by: SortBy::Path,
+            order: SortOrder::Asc,
+            cursor: None,
+        };
+
+        let result = tool.execute(params).await?;
+
+        let names: Vec<&str> = result.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["empty.txt", "empty_dir"]);
+
+        // Clean up
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_sorted_pages() -> Result<()> {
+        let test_dir = setup_test_directory().await?;
+        create_test_file(&test_dir.join("dir1/big.txt"), &"x".repeat(100)).await?;
+        let tool = FileFind;
+
+        let params = |cursor: Option<String>| Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            pattern: "*".to_string(),
+            mode: FindMode::Pattern,
+            file_type: FileType::File,
+            recursive: true,
+            max_depth: 0,
+            limit: 4,
+            follow_links: false,
+            ignore: vec![],
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            empty_only: false,
+            sort_by: SortBy::Size,
+            order: SortOrder::Desc,
+            cursor,
+        };
+
+        // The largest file comes first and the first page is full
+        let first = tool.execute(params(None)).await?;
+        assert_eq!(first.total, 6);
+        // The three directories were walked too, but don't match
+        assert_eq!(first.scanned_entries, 9);
+        assert_eq!(first.skipped, Skipped::default());
+        assert_eq!(first.entries.len(), 4);
+        assert_eq!(first.entries[0].name, "big.txt");
+        assert!(first.limited);
+
+        // The cursor picks up where the first page stopped
+        let second = tool.execute(params(first.next_cursor.clone())).await?;
+        assert_eq!(second.entries.len(), 2);
+        assert_eq!(second.total, 6);
+        assert!(!second.limited);
+        assert!(second.next_cursor.is_none());
+        for entry in &second.entries {
+            assert!(first.entries.iter().all(|e| e.path != entry.path));
+        }
+
+        // A cursor from a different sort is rejected
+        let mut bad = params(first.next_cursor);
+        bad.sort_by = SortBy::Path;
+        assert!(matches!(
+            tool.execute(bad).await,
+            Err(Error::InvalidParam(_))
+        ));
+
+        // Clean up
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_path_pages() -> Result<()> {
+        let test_dir = setup_test_directory().await?;
+        let tool = FileFind;
+
+        for order in [SortOrder::Asc, SortOrder::Desc] {
+            let params = |limit: usize, cursor: Option<String>| Params {
+                file_type: FileType::File,
+                limit,
+                sort_by: SortBy::Path,
+                order,
+                cursor,
+                ..Params::new(test_dir.to_string_lossy(), "*")
+            };
+            let all: Vec<String> = tool
+                .execute(params(0, None))
+                .await?
+                .entries
+                .into_iter()
+                .map(|e| e.path)
+                .collect();
+            assert_eq!(all.len(), 5);
+
+            // Paging through in twos gives the full listing, and every page
+            // still counts every match
+            let mut paged = Vec::new();
+            let mut cursor = None;
+            loop {
+                let page = tool.execute(params(2, cursor)).await?;
+                assert_eq!(page.total, 5);
+                assert!(page.entries.len() <= 2);
+                paged.extend(page.entries.into_iter().map(|e| e.path));
+                cursor = page.next_cursor;
+                if cursor.is_none() {
+                    break;
+                }
+            }
+            assert_eq!(paged, all);
+        }
+
+        // Clean up
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
 }
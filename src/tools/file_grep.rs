@@ -1,16 +1,23 @@
 //! File grep tool implementation
+//!
+//! Searches text files line by line, or whole files with `multiline`. With
+//! `binary_mode` it searches raw bytes instead, for binary assets and core
+//! dumps, reporting byte offsets and a hex dump around each match.
 
+use aho_corasick::AhoCorasick;
 use async_trait::async_trait;
 use glob::Pattern;
-use regex::Regex;
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
 use std::path::{Path, PathBuf};
-use tokio::fs;
-use tokio::task;
-use walkdir::{DirEntry, WalkDir};
 
-use super::Tool;
+use super::{Annotations, Tool};
+use crate::cancel;
+use crate::logging;
+use crate::paths;
+use crate::vfs::{self, DirEntry, Filesystem, WalkOptions};
+use crate::warnings::{self, Skipped};
 use crate::{Error, Result};
 
 /// File grep tool
@@ -18,14 +25,25 @@ use crate::{Error, Result};
 pub struct FileGrep;
 
 /// Parameters for the file grep tool
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Params {
-    /// Directory to search in
+    /// Directory to search in (with `files`, only used to resolve relative paths)
+    #[serde(default, deserialize_with = "crate::roots::path")]
     pub directory: String,
 
+    /// Search exactly these files instead of walking `directory`
+    #[serde(default, deserialize_with = "crate::roots::path_list")]
+    pub files: Vec<String>,
+
     /// Pattern to search for in file contents
+    #[serde(default)]
     pub pattern: String,
 
+    /// Further patterns to search for at the same time; a line matches if any
+    /// pattern does, and each match lists the patterns it contains
+    #[serde(default)]
+    pub patterns: Vec<String>,
+
     /// Whether the pattern is a regular expression
     #[serde(default)]
     pub regex: bool,
@@ -34,6 +52,10 @@ pub struct Params {
     #[serde(default)]
     pub case_insensitive: bool,
 
+    /// Log an invalid `pattern` regex and report no matches instead of failing
+    #[serde(default)]
+    pub ignore_invalid_regex: bool,
+
     /// Whether to search recursively
     #[serde(default = "default_recursive")]
     pub recursive: bool,
@@ -50,14 +72,19 @@ pub struct Params {
     #[serde(default)]
     pub follow_links: bool,
 
-    /// Glob pattern to include files
+    /// Glob pattern to include files, matched against paths relative to
+    /// `directory` with `/` separators, such as `src/**/*.rs`
     #[serde(default)]
     pub include: Option<String>,
 
-    /// Glob patterns to exclude files
+    /// Glob patterns to exclude files, matched like `include`
     #[serde(default)]
     pub exclude: Vec<String>,
 
+    /// Match `include` and `exclude` against absolute paths instead
+    #[serde(default)]
+    pub absolute_globs: bool,
+
     /// Whether to include line numbers in the output
     #[serde(default)]
     pub line_numbers: bool,
@@ -73,14 +100,81 @@ pub struct Params {
     /// Whether to only return file names, not content
     #[serde(default)]
     pub file_names_only: bool,
+
+    /// Report the lines that do NOT match (like `grep -v`)
+    #[serde(default)]
+    pub invert: bool,
+
+    /// Only return the number of matching lines per file (like `grep -c`)
+    #[serde(default)]
+    pub count_only: bool,
+
+    /// Search the whole file instead of line by line, so matches can span
+    /// lines; `.` matches newlines and `^`/`$` match at line boundaries
+    #[serde(default)]
+    pub multiline: bool,
+
+    /// Search raw bytes, reading the patterns as hex or as escaped text;
+    /// matches are listed under `binary_matches`
+    #[serde(default)]
+    pub binary_mode: Option<BinaryMode>,
+
+    /// Bytes to show before and after each match in binary mode, rounded out
+    /// to whole 16-byte lines of the hex dump
+    #[serde(default = "default_binary_context")]
+    pub binary_context: usize,
+}
+
+/// How patterns are read in binary mode
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BinaryMode {
+    /// Hex digits, two per byte, with optional spaces: "7f 45 4c 46"
+    Hex,
+    /// Text with `\xNN`, `\0`, `\n`, `\r`, `\t` and `\\` escapes: "\x7fELF"
+    Escaped,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            directory: String::new(),
+            files: Vec::new(),
+            pattern: String::new(),
+            patterns: Vec::new(),
+            regex: false,
+            case_insensitive: false,
+            ignore_invalid_regex: false,
+            recursive: default_recursive(),
+            max_depth: 0,
+            limit: 0,
+            follow_links: false,
+            include: None,
+            exclude: Vec::new(),
+            absolute_globs: false,
+            line_numbers: false,
+            before_context: 0,
+            after_context: 0,
+            file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: default_binary_context(),
+        }
+    }
 }
 
 fn default_recursive() -> bool {
     true
 }
 
+fn default_binary_context() -> usize {
+    16
+}
+
 /// Match in a file
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Match {
     /// Line number (1-based)
     pub line_number: usize,
@@ -89,16 +183,69 @@ pub struct Match {
     pub line: String,
 
     /// Context lines before the match
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub before_context: Vec<String>,
 
     /// Context lines after the match
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub after_context: Vec<String>,
+
+    /// Last line of the match (1-based, multiline only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line_number: Option<usize>,
+
+    /// Byte offset where the match starts (multiline only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_start: Option<usize>,
+
+    /// Byte offset just past the end of the match (multiline only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_end: Option<usize>,
+
+    /// Patterns found in the line (when searching for several patterns)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matched_patterns: Vec<String>,
+
+    /// Where each match sits within the line (line mode, not inverted)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<MatchSpan>,
+}
+
+/// Position of a match within its line
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct MatchSpan {
+    /// Byte offset where the match starts
+    pub start: usize,
+
+    /// Byte offset just past the end of the match
+    pub end: usize,
+
+    /// Character column where the match starts (0-based)
+    pub column_start: usize,
+
+    /// Character column just past the end of the match (0-based)
+    pub column_end: usize,
+}
+
+/// Match in a file searched in binary mode
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BinaryMatch {
+    /// Byte offset where the match starts
+    pub offset: usize,
+
+    /// Length of the match in bytes
+    pub length: usize,
+
+    /// Pattern found (when searching for several patterns)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+
+    /// Bytes around the match, 16 to a line as `offset: hex |ascii|`
+    pub hex_dump: Vec<String>,
 }
 
 /// File with matches
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct FileMatch {
     /// Path to the file
     pub path: String,
@@ -107,12 +254,28 @@ pub struct FileMatch {
     pub size: u64,
 
     /// List of matches in the file
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub matches: Vec<Match>,
+
+    /// List of matches in the file (binary mode)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub binary_matches: Vec<BinaryMatch>,
+
+    /// Number of matching lines (only with `count_only`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+}
+
+impl FileMatch {
+    /// Number of matches in the file, whether or not they were listed
+    fn match_count(&self) -> usize {
+        self.count
+            .unwrap_or(self.matches.len() + self.binary_matches.len())
+    }
 }
 
 /// Output of the file grep tool
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Output {
     /// Directory that was searched
     pub directory: String,
@@ -132,47 +295,60 @@ pub struct Output {
     /// Total number of matches found
     pub total_matches: usize,
 
+    /// Files and directories that weren't searched, by reason
+    #[serde(default)]
+    pub skipped: Skipped,
+
     /// Whether the results were limited
     pub limited: bool,
 }
 
-/// Check if a path is a valid directory and canonicalize it
-async fn prepare_directory(dir_path: impl AsRef<Path>) -> Result<PathBuf> {
-    let path = dir_path.as_ref();
-
-    if !path.exists() {
-        return Err(Error::InvalidParam(format!(
-            "Directory not found: {}",
-            path.display()
-        )));
-    }
-
-    if !path.is_dir() {
-        return Err(Error::InvalidParam(format!(
-            "Path is not a directory: {}",
-            path.display()
-        )));
+/// Resolve an explicit list of files, relative to `base` if given
+async fn resolve_files(
+    fs: &dyn Filesystem,
+    files: &[String],
+    base: Option<&Path>,
+) -> Result<Vec<PathBuf>> {
+    let mut resolved = Vec::with_capacity(files.len());
+    for file in files {
+        let path = match base {
+            Some(base) => base.join(file),
+            None => PathBuf::from(file),
+        };
+        paths::require_file(fs, &path).await?;
+        resolved.push(path);
     }
+    Ok(resolved)
+}
 
-    // Canonicalize the path
-    let canonical = path.canonicalize().map_err(Error::Io)?;
-
-    Ok(canonical)
+/// A glob as given, without a leading `./`, or `None` if it is invalid
+fn glob_pattern(pattern: &str) -> Option<Pattern> {
+    let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+    Pattern::new(pattern).ok()
 }
 
-/// Check if a file should be included in the search
+/// Check if a file should be included in the search. Globs are matched
+/// against the path relative to `root`, the walked directory, unless
+/// `absolute` is set.
 fn should_include_file(
     entry: &DirEntry,
+    root: &Path,
+    absolute: bool,
     include_pattern: &Option<Pattern>,
     exclude_patterns: &[Pattern],
 ) -> bool {
     // Check if it's a file
-    if !entry.file_type().is_file() {
+    if !entry.metadata.is_file() {
         return false;
     }
 
-    // Get path as string
-    let path_str = entry.path().to_string_lossy();
+    // Get path as string, with `/` separators on every platform
+    let path = entry.path();
+    let path_str = if absolute {
+        paths::to_string(path)
+    } else {
+        paths::to_string(path.strip_prefix(root).unwrap_or(path))
+    };
 
     // Check exclude patterns
     if exclude_patterns.iter().any(|p| p.matches(&path_str)) {
@@ -180,32 +356,439 @@ fn should_include_file(
     }
 
     // Check include pattern if specified
-    if let Some(include) = include_pattern {
-        if !include.matches(&path_str) {
-            return false;
-        }
+    if let Some(include) = include_pattern
+        && !include.matches(&path_str)
+    {
+        return false;
     }
 
     true
 }
 
-/// Search a file for the pattern
-async fn search_file(
-    path: &Path,
-    pattern: &str,
+/// Several patterns searched for in a single pass
+enum PatternSet {
+    /// Literal strings, matched with Aho-Corasick
+    Literals(AhoCorasick, Vec<String>),
+    /// Regular expressions, with each one compiled separately to locate matches
+    Regexes(RegexSet, Vec<Regex>),
+}
+
+impl PatternSet {
+    /// Compile the patterns; case-insensitive literals only fold ASCII
+    fn new(patterns: Vec<String>, regex: bool, case_insensitive: bool) -> Result<Self> {
+        if regex {
+            let invalid =
+                |e: regex::Error| Error::InvalidParam(format!("Invalid regex pattern: {}", e));
+            let set = RegexSetBuilder::new(&patterns)
+                .case_insensitive(case_insensitive)
+                .build()
+                .map_err(invalid)?;
+            let regexes = patterns
+                .iter()
+                .map(|p| {
+                    RegexBuilder::new(p)
+                        .case_insensitive(case_insensitive)
+                        .build()
+                        .map_err(invalid)
+                })
+                .collect::<Result<_>>()?;
+            Ok(PatternSet::Regexes(set, regexes))
+        } else {
+            let automaton = AhoCorasick::builder()
+                .ascii_case_insensitive(case_insensitive)
+                .build(&patterns)
+                .map_err(|e| Error::InvalidParam(format!("Invalid patterns: {}", e)))?;
+            Ok(PatternSet::Literals(automaton, patterns))
+        }
+    }
+
+    /// The patterns found in `text`, in the order they were given, and the
+    /// byte ranges of all their matches
+    fn find(&self, text: &str) -> (Vec<String>, Vec<(usize, usize)>) {
+        let mut found = Vec::new();
+        let mut ranges = Vec::new();
+
+        let names: Vec<&str> = match self {
+            PatternSet::Literals(automaton, patterns) => {
+                for m in automaton.find_overlapping_iter(text) {
+                    found.push(m.pattern().as_usize());
+                    ranges.push((m.start(), m.end()));
+                }
+                patterns.iter().map(String::as_str).collect()
+            }
+            PatternSet::Regexes(set, regexes) => {
+                for i in &set.matches(text) {
+                    found.push(i);
+                    ranges.extend(regexes[i].find_iter(text).map(|m| (m.start(), m.end())));
+                }
+                regexes.iter().map(Regex::as_str).collect()
+            }
+        };
+
+        found.sort_unstable();
+        found.dedup();
+        ranges.sort_unstable();
+        let found = found.into_iter().map(|i| names[i].to_string()).collect();
+        (found, ranges)
+    }
+}
+
+/// Turn byte ranges within a line into spans with character columns
+fn match_spans(line: &str, ranges: Vec<(usize, usize)>) -> Vec<MatchSpan> {
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let column_start = line[..start].chars().count();
+            MatchSpan {
+                start,
+                end,
+                column_start,
+                column_end: column_start + line[start..end].chars().count(),
+            }
+        })
+        .collect()
+}
+
+/// How each file is searched
+#[derive(Clone, Copy)]
+struct SearchOptions<'a> {
+    pattern: &'a str,
     regex: bool,
     case_insensitive: bool,
     before_context: usize,
     after_context: usize,
     file_names_only: bool,
+    invert: bool,
+    count_only: bool,
+    /// Compiled pattern for a multiline search
+    multiline: Option<&'a Regex>,
+    /// Patterns to search for instead of `pattern`
+    patterns: Option<&'a PatternSet>,
+    /// Byte patterns for a binary search
+    binary: Option<&'a BinarySearch>,
+}
+
+/// Byte patterns searched for in binary mode
+struct BinarySearch {
+    automaton: AhoCorasick,
+    /// The patterns as given, to name them when there are several
+    patterns: Vec<String>,
+    /// Bytes shown around each match
+    context: usize,
+}
+
+impl BinarySearch {
+    fn new(
+        patterns: Vec<String>,
+        mode: BinaryMode,
+        case_insensitive: bool,
+        context: usize,
+    ) -> Result<Self> {
+        let bytes = patterns
+            .iter()
+            .map(|pattern| match mode {
+                BinaryMode::Hex => parse_hex(pattern),
+                BinaryMode::Escaped => parse_escaped(pattern),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let automaton = AhoCorasick::builder()
+            .ascii_case_insensitive(case_insensitive)
+            .build(&bytes)
+            .map_err(|e| Error::InvalidParam(format!("Invalid patterns: {}", e)))?;
+        Ok(Self {
+            automaton,
+            patterns,
+            context,
+        })
+    }
+
+    /// Every match in `content`, with the number of matches
+    fn search(&self, content: &[u8], list: bool) -> (Vec<BinaryMatch>, usize) {
+        let mut matches = Vec::new();
+        let mut count = 0;
+        for m in self.automaton.find_iter(content) {
+            count += 1;
+            if !list {
+                continue;
+            }
+            matches.push(BinaryMatch {
+                offset: m.start(),
+                length: m.len(),
+                pattern: (self.patterns.len() > 1)
+                    .then(|| self.patterns[m.pattern().as_usize()].clone()),
+                hex_dump: hex_dump(
+                    content,
+                    m.start().saturating_sub(self.context),
+                    (m.end() + self.context).min(content.len()),
+                ),
+            });
+        }
+        (matches, count)
+    }
+}
+
+/// Bytes of a hex pattern such as "7f 45 4c 46"
+fn parse_hex(pattern: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = pattern
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    let invalid = || Error::InvalidParam(format!("Invalid hex pattern: {:?}", pattern));
+    if digits.is_empty()
+        || !digits.len().is_multiple_of(2)
+        || !digits.iter().all(u8::is_ascii_hexdigit)
+    {
+        return Err(invalid());
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+/// Bytes of text with `\xNN`, `\0`, `\n`, `\r`, `\t` and `\\` escapes
+fn parse_escaped(pattern: &str) -> Result<Vec<u8>> {
+    let invalid =
+        |why: &str| Error::InvalidParam(format!("Invalid escaped pattern {:?}: {}", pattern, why));
+    let mut bytes = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 2 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+                    .ok_or_else(|| invalid("\\x needs two hex digits"))?;
+                bytes.push(byte);
+            }
+            Some('0') => bytes.push(0),
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('\\') => bytes.push(b'\\'),
+            Some(other) => return Err(invalid(&format!("unknown escape \\{}", other))),
+            None => return Err(invalid("trailing backslash")),
+        }
+    }
+    if bytes.is_empty() {
+        return Err(invalid("empty pattern"));
+    }
+    Ok(bytes)
+}
+
+/// Hex dump of the 16-byte lines covering `start..end`, like `xxd`
+fn hex_dump(content: &[u8], start: usize, end: usize) -> Vec<String> {
+    let first = start / 16 * 16;
+    let last = end.div_ceil(16).max(first / 16 + 1) * 16;
+    content[first..last.min(content.len())]
+        .chunks(16)
+        .enumerate()
+        .map(|(index, line)| {
+            let hex: Vec<String> = (0..16)
+                .map(|i| match line.get(i) {
+                    Some(byte) => format!("{:02x}", byte),
+                    None => "  ".to_string(),
+                })
+                .collect();
+            let ascii: String = line
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!(
+                "{:08x}: {}  {}  |{}|",
+                first + index * 16,
+                hex[..8].join(" "),
+                hex[8..].join(" "),
+                ascii
+            )
+        })
+        .collect()
+}
+
+/// Build the regex for a multiline search
+fn multiline_regex(pattern: &str, regex: bool, case_insensitive: bool) -> Result<Regex> {
+    let pattern = if regex {
+        pattern.to_string()
+    } else {
+        regex::escape(pattern)
+    };
+    let flags = if case_insensitive { "(?ism)" } else { "(?sm)" };
+    Regex::new(&format!("{}{}", flags, pattern))
+        .map_err(|e| Error::InvalidParam(format!("Invalid regex pattern '{}': {}", pattern, e)))
+}
+
+/// Search the whole content with a multiline regex, reporting the line and
+/// byte range of each match
+fn search_multiline(content: &str, re: &Regex, options: SearchOptions<'_>) -> (Vec<Match>, usize) {
+    // Byte offset at which each line starts
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset);
+    let lines: Vec<&str> = content.lines().collect();
+
+    let mut matches = Vec::new();
+    let mut count = 0;
+
+    for m in re.find_iter(content) {
+        count += 1;
+        if options.count_only || options.file_names_only {
+            continue;
+        }
+
+        // 1-based lines of the first and last byte of the match
+        let start_line = line_of(m.start());
+        let end_line = line_of(m.end().saturating_sub(1).max(m.start()));
+
+        let before_start = (start_line - 1).saturating_sub(options.before_context);
+        let before = lines[before_start..(start_line - 1).min(lines.len())]
+            .iter()
+            .enumerate()
+            .map(|(idx, &l)| format!("{}:{}", before_start + idx + 1, l))
+            .collect();
+        let after_end = (end_line + options.after_context).min(lines.len());
+        let after = lines[end_line.min(after_end)..after_end]
+            .iter()
+            .enumerate()
+            .map(|(idx, &l)| format!("{}:{}", end_line + idx + 1, l))
+            .collect();
+
+        matches.push(Match {
+            line_number: start_line,
+            line: m.as_str().to_string(),
+            before_context: before,
+            after_context: after,
+            end_line_number: Some(end_line),
+            byte_start: Some(m.start()),
+            byte_end: Some(m.end()),
+            matched_patterns: Vec::new(),
+            spans: Vec::new(),
+        });
+    }
+
+    (matches, count)
+}
+
+/// Why a file couldn't be searched as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NotText {
+    /// It has NUL bytes
+    Binary,
+    /// It isn't valid UTF-8
+    NonUtf8,
+}
+
+impl NotText {
+    /// The reason behind an error from [`read_text`], if it is one
+    fn of(error: &std::io::Error) -> Option<Self> {
+        error.get_ref()?.downcast_ref::<Self>().copied()
+    }
+}
+
+impl std::fmt::Display for NotText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Binary => write!(f, "file is binary"),
+            Self::NonUtf8 => write!(f, "file is not valid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for NotText {}
+
+/// Leading bytes checked for NUL to tell binary files from text
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// Read a file as UTF-8 text, failing with `InvalidData` and a [`NotText`]
+/// reason if it isn't
+async fn read_text(fs: &dyn Filesystem, path: &Path) -> std::io::Result<String> {
+    let bytes = fs.read(path).await?;
+    // NUL is valid UTF-8, so binary files are recognised before decoding
+    if bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            NotText::Binary,
+        ));
+    }
+    String::from_utf8(bytes)
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, NotText::NonUtf8))
+}
+
+/// Search a file for the pattern
+async fn search_file(
+    fs: &dyn Filesystem,
+    path: &Path,
+    options: SearchOptions<'_>,
 ) -> std::io::Result<Option<FileMatch>> {
+    let SearchOptions {
+        pattern,
+        regex,
+        case_insensitive,
+        before_context,
+        after_context,
+        file_names_only,
+        invert,
+        count_only,
+        multiline,
+        patterns,
+        binary,
+    } = options;
+
     // Get file metadata
-    let metadata = fs::metadata(path).await?;
+    let metadata = fs.metadata(path).await?;
     let size = metadata.len();
 
+    if let Some(binary) = binary {
+        let content = fs.read(path).await?;
+        let (binary_matches, count) = binary.search(&content, !count_only && !file_names_only);
+        if count == 0 {
+            return Ok(None);
+        }
+        return Ok(Some(FileMatch {
+            path: paths::to_string(path),
+            size,
+            matches: Vec::new(),
+            binary_matches,
+            count: if count_only { Some(count) } else { None },
+        }));
+    }
+
+    if let Some(re) = multiline {
+        let content = read_text(fs, path).await?;
+        let (matches, count) = search_multiline(&content, re, options);
+        if count == 0 {
+            return Ok(None);
+        }
+        return Ok(Some(FileMatch {
+            path: paths::to_string(path),
+            size,
+            matches,
+            binary_matches: Vec::new(),
+            count: if count_only { Some(count) } else { None },
+        }));
+    }
+
     // If only file names are needed, we can check for matches more efficiently
-    if file_names_only {
-        let content = fs::read_to_string(path).await?;
+    // (an inverted search still has to look at every line)
+    if file_names_only && !invert && patterns.is_none() {
+        let content = read_text(fs, path).await?;
 
         // Check if there's a match without line-by-line processing
         let has_match = if regex {
@@ -215,7 +798,12 @@ async fn search_file(
                 Ok(re) => re.is_match(&content),
                 Err(e) => {
                     // Log error for debugging but don't fail the whole operation
-                    log::warn!("Invalid regex pattern '{}': {}", pattern, e);
+                    logging::call_log!(
+                        log::Level::Warn,
+                        "Invalid regex pattern '{}': {}",
+                        pattern,
+                        e
+                    );
                     false
                 }
             }
@@ -227,9 +815,11 @@ async fn search_file(
 
         if has_match {
             return Ok(Some(FileMatch {
-                path: path.to_string_lossy().to_string(),
+                path: paths::to_string(path),
                 size,
                 matches: vec![],
+                binary_matches: Vec::new(),
+                count: None,
             }));
         } else {
             return Ok(None);
@@ -237,18 +827,23 @@ async fn search_file(
     }
 
     // Read the file content
-    let content = fs::read_to_string(path).await?;
+    let content = read_text(fs, path).await?;
     let lines: Vec<&str> = content.lines().collect();
 
     // Prepare regex if needed
-    let re = if regex {
+    let re = if regex && patterns.is_none() {
         let regex_flags = if case_insensitive { "(?i)" } else { "" };
         let pattern = format!("{}{}", regex_flags, pattern);
         match Regex::new(&pattern) {
             Ok(re) => Some(re),
             Err(e) => {
                 // Log error for debugging but don't fail the whole operation
-                log::warn!("Invalid regex pattern '{}': {}", pattern, e);
+                logging::call_log!(
+                    log::Level::Warn,
+                    "Invalid regex pattern '{}': {}",
+                    pattern,
+                    e
+                );
                 return Ok(None);
             }
         }
@@ -256,13 +851,28 @@ async fn search_file(
         None
     };
 
+    // A literal pattern is also compiled as a regex to locate its matches
+    let literal_re = if !regex && patterns.is_none() {
+        let regex_flags = if case_insensitive { "(?i)" } else { "" };
+        Regex::new(&format!("{}{}", regex_flags, regex::escape(pattern))).ok()
+    } else {
+        None
+    };
+    let span_re = re.as_ref().or(literal_re.as_ref());
+
     let mut matches = Vec::new();
+    let mut count = 0;
 
     // Search for matches line by line
     for (i, line) in lines.iter().enumerate() {
         let line_num = i + 1; // 1-based line number
 
-        let is_match = if let Some(re) = &re {
+        let mut matched_patterns = Vec::new();
+        let mut ranges = Vec::new();
+        let is_match = if let Some(set) = patterns {
+            (matched_patterns, ranges) = set.find(line);
+            !matched_patterns.is_empty()
+        } else if let Some(re) = &re {
             re.is_match(line)
         } else if case_insensitive {
             line.to_lowercase().contains(&pattern.to_lowercase())
@@ -270,7 +880,20 @@ async fn search_file(
             line.contains(pattern)
         };
 
-        if is_match {
+        // With invert, the lines that don't match are the ones reported
+        if is_match == invert {
+            continue;
+        }
+        count += 1;
+
+        if !count_only && !file_names_only {
+            if !invert && let Some(span_re) = span_re {
+                ranges = span_re
+                    .find_iter(line)
+                    .map(|m| (m.start(), m.end()))
+                    .collect();
+            }
+
             // Add context lines
             let before = if before_context > 0 {
                 let start = i.saturating_sub(before_context);
@@ -298,17 +921,24 @@ async fn search_file(
                 line: line.to_string(),
                 before_context: before,
                 after_context: after,
+                end_line_number: None,
+                byte_start: None,
+                byte_end: None,
+                matched_patterns,
+                spans: match_spans(line, ranges),
             });
         }
     }
 
-    if matches.is_empty() {
+    if count == 0 {
         Ok(None)
     } else {
         Ok(Some(FileMatch {
-            path: path.to_string_lossy().to_string(),
+            path: paths::to_string(path),
             size,
             matches,
+            binary_matches: Vec::new(),
+            count: if count_only { Some(count) } else { None },
         }))
     }
 }
@@ -322,10 +952,27 @@ impl Tool for FileGrep {
         "file_grep"
     }
 
+    fn description(&self) -> &str {
+        "Search file contents for a pattern"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
     async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
-        // Validate and canonicalize the directory
-        let directory = prepare_directory(&params.directory).await?;
-        let dir_string = directory.to_string_lossy().to_string();
+        let fs = vfs::current();
+
+        // Validate and canonicalize the directory, which is optional with a file list
+        let directory = if params.files.is_empty() || !params.directory.is_empty() {
+            Some(paths::canonical_dir(fs.as_ref(), Path::new(&params.directory)).await?)
+        } else {
+            None
+        };
+        let dir_string = directory
+            .as_ref()
+            .map(|d| paths::to_string(d))
+            .unwrap_or_default();
 
         // Clone or extract parameters we'll need in the blocking task
         let pattern = params.pattern.clone();
@@ -338,21 +985,95 @@ impl Tool for FileGrep {
         let before_context = params.before_context;
         let after_context = params.after_context;
         let file_names_only = params.file_names_only;
+        let invert = params.invert;
+        let count_only = params.count_only;
+
+        // A binary search reads its patterns as bytes, so has no regex
+        let binary_search = match params.binary_mode {
+            Some(mode) => {
+                if regex || params.multiline || invert {
+                    return Err(Error::InvalidParam(
+                        "binary_mode cannot be combined with regex, multiline or invert"
+                            .to_string(),
+                    ));
+                }
+                let all: Vec<String> = std::iter::once(pattern.clone())
+                    .filter(|p| !p.is_empty())
+                    .chain(params.patterns.iter().cloned())
+                    .collect();
+                if all.is_empty() {
+                    return Err(Error::InvalidParam(
+                        "Either pattern or patterns must be provided".to_string(),
+                    ));
+                }
+                Some(BinarySearch::new(
+                    all,
+                    mode,
+                    case_insensitive,
+                    params.binary_context,
+                )?)
+            }
+            None => None,
+        };
+
+        // Reject an invalid regex now rather than quietly matching nothing
+        if regex && !params.ignore_invalid_regex && !pattern.is_empty() {
+            let regex_flags = if case_insensitive { "(?i)" } else { "" };
+            if let Err(e) = Regex::new(&format!("{}{}", regex_flags, pattern)) {
+                return Err(Error::InvalidParam(format!(
+                    "Invalid regex pattern '{}': {}",
+                    pattern, e
+                )));
+            }
+        }
+
+        // Several patterns are compiled once, up front
+        let pattern_set = if binary_search.is_some() {
+            None
+        } else if params.patterns.is_empty() {
+            if pattern.is_empty() {
+                return Err(Error::InvalidParam(
+                    "Either pattern or patterns must be provided".to_string(),
+                ));
+            }
+            None
+        } else {
+            if params.multiline {
+                return Err(Error::InvalidParam(
+                    "patterns cannot be combined with multiline".to_string(),
+                ));
+            }
+            let all = std::iter::once(pattern.clone())
+                .filter(|p| !p.is_empty())
+                .chain(params.patterns)
+                .collect();
+            Some(PatternSet::new(all, regex, case_insensitive)?)
+        };
+
+        // A multiline search compiles its pattern once, up front
+        let multiline = if params.multiline {
+            if invert {
+                return Err(Error::InvalidParam(
+                    "invert cannot be combined with multiline".to_string(),
+                ));
+            }
+            Some(multiline_regex(&pattern, regex, case_insensitive)?)
+        } else {
+            None
+        };
 
         // Prepare include pattern
-        let include_pattern = params
-            .include
-            .as_ref()
-            .and_then(|pattern| Pattern::new(pattern).ok());
+        let include_pattern = params.include.as_deref().and_then(glob_pattern);
 
         // Prepare exclude patterns
         let exclude_patterns: Vec<Pattern> = params
             .exclude
             .iter()
-            .filter_map(|pattern| Pattern::new(pattern).ok())
+            .filter_map(|pattern| glob_pattern(pattern))
             .collect();
+        let absolute_globs = params.absolute_globs;
 
-        // Set up the walkdir with proper configuration
+        // How deep the walk goes
         let max_depth = if recursive {
             if max_depth_param > 0 {
                 max_depth_param
@@ -363,26 +1084,36 @@ impl Tool for FileGrep {
             1
         };
 
-        // Get all file paths to search in a blocking task
-        let file_paths = task::spawn_blocking(move || {
-            let mut paths = Vec::new();
-
-            let walker = WalkDir::new(&directory)
-                .max_depth(max_depth)
-                .follow_links(follow_links);
-
-            walker.into_iter().for_each(|entry| {
-                if let Ok(entry) = entry {
-                    if should_include_file(&entry, &include_pattern, &exclude_patterns) {
-                        paths.push(entry.path().to_path_buf());
-                    }
-                }
-            });
-
-            paths
-        })
-        .await
-        .map_err(|e| Error::Other(format!("Join error: {}", e)))?;
+        let (file_paths, mut skipped) = match directory {
+            // Get all file paths to search
+            Some(directory) if params.files.is_empty() => {
+                let options = WalkOptions {
+                    max_depth,
+                    follow_links,
+                };
+                let (entries, skipped) = fs.walk_skipping(&directory, options).await?;
+                cancel::check()?;
+                let paths = entries
+                    .iter()
+                    .filter(|entry| {
+                        should_include_file(
+                            entry,
+                            &directory,
+                            absolute_globs,
+                            &include_pattern,
+                            &exclude_patterns,
+                        )
+                    })
+                    .map(|entry| entry.path.clone())
+                    .collect();
+                (paths, skipped)
+            }
+            // Or search the given files as they are
+            directory => (
+                resolve_files(fs.as_ref(), &params.files, directory.as_deref()).await?,
+                Skipped::default(),
+            ),
+        };
 
         let files_to_search = file_paths.len();
 
@@ -391,33 +1122,61 @@ impl Tool for FileGrep {
         let mut files_matched = 0;
         let mut total_matches = 0;
         let mut limited = false;
+        let mut unreadable = Vec::new();
 
         for path in file_paths {
+            cancel::check()?;
             if limit > 0 && files.len() >= limit {
                 limited = true;
                 break;
             }
 
-            match search_file(
-                &path,
-                &pattern,
+            let options = SearchOptions {
+                pattern: &pattern,
                 regex,
                 case_insensitive,
                 before_context,
                 after_context,
                 file_names_only,
-            )
-            .await
-            {
+                invert,
+                count_only,
+                multiline: multiline.as_ref(),
+                patterns: pattern_set.as_ref(),
+                binary: binary_search.as_ref(),
+            };
+
+            match search_file(fs.as_ref(), &path, options).await {
                 Ok(Some(file_match)) => {
                     files_matched += 1;
-                    total_matches += file_match.matches.len();
+                    total_matches += file_match.match_count();
                     files.push(file_match);
                 }
                 Ok(None) => {}
-                Err(_) => continue,
+                // Files that aren't text are counted, but aren't worth a warning
+                Err(e) => match NotText::of(&e) {
+                    Some(NotText::Binary) => skipped.binary += 1,
+                    Some(NotText::NonUtf8) => skipped.non_utf8 += 1,
+                    None => {
+                        skipped.record(&e);
+                        unreadable.push((path, e));
+                    }
+                },
             }
         }
+        match unreadable.as_slice() {
+            [] => {}
+            [(path, e)] => warnings::warn(format!(
+                "Skipped unreadable file {}: {}",
+                paths::to_string(path),
+                e
+            )),
+            [(path, e), ..] => warnings::warn(format!(
+                "Skipped {} unreadable files, e.g. {}: {}",
+                unreadable.len(),
+                paths::to_string(path),
+                e
+            )),
+        }
 
         // Sort by path for consistency
         files.sort_by(|a, b| a.path.cmp(&b.path));
@@ -429,6 +1188,7 @@ impl Tool for FileGrep {
             files_searched: files_to_search,
             files_matched,
             total_matches,
+            skipped,
             limited,
         })
     }
@@ -437,6 +1197,7 @@ impl Tool for FileGrep {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sys::fs;
     use log::debug;
     use std::sync::Mutex;
     use tokio::fs::File;
@@ -528,19 +1289,28 @@ mod tests {
         // Basic grep for "find"
         let params = Params {
             directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
             pattern: "find".to_string(),
+            patterns: vec![],
             regex: false,
             case_insensitive: false,
+            ignore_invalid_regex: false,
             recursive: true,
             max_depth: 0,
             limit: 0,
             follow_links: false,
             include: None,
             exclude: vec![],
+            absolute_globs: false,
             line_numbers: true,
             before_context: 0,
             after_context: 0,
             file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
         };
 
         let result = tool.execute(params).await?;
@@ -563,19 +1333,28 @@ mod tests {
         // Case insensitive grep for "find"
         let params = Params {
             directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
             pattern: "find".to_string(),
+            patterns: vec![],
             regex: false,
             case_insensitive: true,
+            ignore_invalid_regex: false,
             recursive: true,
             max_depth: 0,
             limit: 0,
             follow_links: false,
             include: None,
             exclude: vec![],
+            absolute_globs: false,
             line_numbers: true,
             before_context: 0,
             after_context: 0,
             file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
         };
 
         let result = tool.execute(params).await?;
@@ -653,19 +1432,28 @@ mod tests {
         // Use a simpler, more explicit regex that should reliably match "find"
         let params = Params {
             directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
             pattern: "f\\w+d".to_string(), // This will match "find" on all platforms
+            patterns: vec![],
             regex: true,
             case_insensitive: false,
+            ignore_invalid_regex: false,
             recursive: true,
             max_depth: 0,
             limit: 0,
             follow_links: false,
             include: None,
             exclude: vec![],
+            absolute_globs: false,
             line_numbers: true,
             before_context: 0,
             after_context: 0,
             file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
         };
 
         // Execute the search
@@ -807,19 +1595,28 @@ mod tests {
         // Grep for "find" but only in .txt files
         let params = Params {
             directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
             pattern: "find".to_string(),
+            patterns: vec![],
             regex: false,
             case_insensitive: false,
+            ignore_invalid_regex: false,
             recursive: true,
             max_depth: 0,
             limit: 0,
             follow_links: false,
             include: Some("*.txt".to_string()),
             exclude: vec![],
+            absolute_globs: false,
             line_numbers: true,
             before_context: 0,
             after_context: 0,
             file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
         };
 
         let result = tool.execute(params).await?;
@@ -887,19 +1684,28 @@ mod tests {
         // Grep for "find" with context
         let params = Params {
             directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
             pattern: "find".to_string(),
+            patterns: vec![],
             regex: false,
             case_insensitive: false,
+            ignore_invalid_regex: false,
             recursive: true,
             max_depth: 0,
             limit: 0,
             follow_links: false,
             include: None, // Allow all files to be searched
             exclude: vec![],
+            absolute_globs: false,
             line_numbers: true,
             before_context: 1,
             after_context: 1,
             file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
         };
 
         let result = tool.execute(params).await?;
@@ -1022,19 +1828,28 @@ mod tests {
         // Grep for "find" but only report file names
         let params = Params {
             directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
             pattern: "find".to_string(),
+            patterns: vec![],
             regex: false,
             case_insensitive: false,
+            ignore_invalid_regex: false,
             recursive: true,
             max_depth: 0,
             limit: 0,
             follow_links: false,
             include: None,
             exclude: vec![],
+            absolute_globs: false,
             line_numbers: true,
             before_context: 0,
             after_context: 0,
             file_names_only: true,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
         };
 
         let result = tool.execute(params).await?;
@@ -1084,19 +1899,28 @@ mod tests {
         // Grep for "find" but limit to 1 file
         let params = Params {
             directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
             pattern: "find".to_string(),
+            patterns: vec![],
             regex: false,
             case_insensitive: false,
+            ignore_invalid_regex: false,
             recursive: true,
             max_depth: 0,
             limit: 1,
             follow_links: false,
             include: None,
             exclude: vec![],
+            absolute_globs: false,
             line_numbers: true,
             before_context: 0,
             after_context: 0,
             file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
         };
 
         let result = tool.execute(params).await?;
@@ -1109,4 +1933,560 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_grep_invert_and_count() -> Result<()> {
+        let test_dir = setup_test_directory().await?;
+        let tool = FileGrep;
+
+        let params = |invert, count_only| Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
+            pattern: "find".to_string(),
+            patterns: vec![],
+            regex: false,
+            case_insensitive: false,
+            ignore_invalid_regex: false,
+            recursive: true,
+            max_depth: 0,
+            limit: 0,
+            follow_links: false,
+            include: Some("*file5.txt".to_string()),
+            exclude: vec![],
+            absolute_globs: false,
+            line_numbers: true,
+            before_context: 0,
+            after_context: 0,
+            file_names_only: false,
+            invert,
+            count_only,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
+        };
+
+        // Both lines of file5 contain "find"
+        let result = tool.execute(params(false, true)).await?;
+        assert_eq!(result.files.len(), 1);
+        assert_eq!(result.files[0].count, Some(2));
+        assert!(result.files[0].matches.is_empty());
+        assert_eq!(result.total_matches, 2);
+
+        // So no line is left when inverting
+        let result = tool.execute(params(true, false)).await?;
+        assert!(result.files.is_empty());
+
+        // Inverted over the whole tree, only the lines without "find" are reported
+        let mut all = params(true, false);
+        all.include = None;
+        let result = tool.execute(all).await?;
+        assert_eq!(result.files_matched, 2);
+        assert!(
+            result
+                .files
+                .iter()
+                .flat_map(|f| &f.matches)
+                .all(|m| !m.line.contains("find"))
+        );
+
+        // Cleanup
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_grep_multiline() -> Result<()> {
+        let test_dir = get_test_dir();
+        create_test_file(
+            &test_dir.join("lib.rs"),
+            "use std::fmt;\n\npub fn render(\n    value: &str,\n) -> String {\n    value.to_string()\n}\n",
+        )
+        .await?;
+        let tool = FileGrep;
+
+        let params = Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
+            pattern: r"pub fn \w+\([^)]*\) -> \w+".to_string(),
+            patterns: vec![],
+            regex: true,
+            case_insensitive: false,
+            ignore_invalid_regex: false,
+            recursive: true,
+            max_depth: 0,
+            limit: 0,
+            follow_links: false,
+            include: None,
+            exclude: vec![],
+            absolute_globs: false,
+            line_numbers: true,
+            before_context: 1,
+            after_context: 1,
+            file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: true,
+            binary_mode: None,
+            binary_context: 16,
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert_eq!(result.total_matches, 1);
+        let m = &result.files[0].matches[0];
+        assert_eq!(m.line_number, 3);
+        assert_eq!(m.end_line_number, Some(5));
+        assert_eq!(m.line, "pub fn render(\n    value: &str,\n) -> String");
+        assert_eq!(m.byte_start, Some(15));
+        assert_eq!(m.byte_end, Some(15 + m.line.len()));
+        assert_eq!(m.before_context, vec!["2:".to_string()]);
+        assert_eq!(m.after_context, vec!["6:    value.to_string()".to_string()]);
+
+        // Cleanup
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_grep_multiple_patterns() -> Result<()> {
+        let test_dir = setup_test_directory().await?;
+        let tool = FileGrep;
+
+        let params = |regex, patterns: &[&str]| Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
+            pattern: String::new(),
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            regex,
+            case_insensitive: false,
+            ignore_invalid_regex: false,
+            recursive: true,
+            max_depth: 0,
+            limit: 0,
+            follow_links: false,
+            include: None,
+            exclude: vec![],
+            absolute_globs: false,
+            line_numbers: true,
+            before_context: 0,
+            after_context: 0,
+            file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
+        };
+
+        // Literal patterns report which of them each line contains
+        let result = tool
+            .execute(params(false, &["find", "here", "FIND"]))
+            .await?;
+        assert_eq!(result.files_matched, 4);
+        let file1 = result
+            .files
+            .iter()
+            .find(|f| f.path.ends_with("file1.txt"))
+            .unwrap();
+        assert_eq!(file1.matches[0].matched_patterns, vec!["find", "here"]);
+        let file4 = result
+            .files
+            .iter()
+            .find(|f| f.path.ends_with("file4.log"))
+            .unwrap();
+        assert_eq!(file4.matches[0].matched_patterns, vec!["here", "FIND"]);
+
+        // Regex patterns work the same way
+        let result = tool
+            .execute(params(true, &[r"^No\b", r"easily\.$"]))
+            .await?;
+        assert_eq!(result.total_matches, 2);
+
+        // Invalid regexes are rejected up front
+        assert!(matches!(
+            tool.execute(params(true, &["("])).await,
+            Err(Error::InvalidParam(_))
+        ));
+
+        // Cleanup
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_spans() {
+        let set = PatternSet::new(vec!["é".to_string(), "b".to_string()], false, false).unwrap();
+        let (names, ranges) = set.find("aéb é");
+        assert_eq!(names, vec!["é", "b"]);
+        assert_eq!(
+            match_spans("aéb é", ranges),
+            vec![
+                MatchSpan {
+                    start: 1,
+                    end: 3,
+                    column_start: 1,
+                    column_end: 2
+                },
+                MatchSpan {
+                    start: 3,
+                    end: 4,
+                    column_start: 2,
+                    column_end: 3
+                },
+                MatchSpan {
+                    start: 5,
+                    end: 7,
+                    column_start: 4,
+                    column_end: 5
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_grep_match_spans() -> Result<()> {
+        let test_dir = setup_test_directory().await?;
+        let tool = FileGrep;
+
+        let params = Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
+            pattern: "find".to_string(),
+            patterns: vec![],
+            regex: false,
+            case_insensitive: true,
+            ignore_invalid_regex: false,
+            recursive: true,
+            max_depth: 0,
+            limit: 0,
+            follow_links: false,
+            include: Some("*file4.log".to_string()),
+            exclude: vec![],
+            absolute_globs: false,
+            line_numbers: true,
+            before_context: 0,
+            after_context: 0,
+            file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
+        };
+
+        let result = tool.execute(params).await?;
+
+        let spans = &result.files[0].matches[0].spans;
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].start, spans[0].end), (0, 4));
+        assert_eq!((spans[0].column_start, spans[0].column_end), (0, 4));
+
+        // Cleanup
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_grep_file_list() -> Result<()> {
+        let test_dir = setup_test_directory().await?;
+        let tool = FileGrep;
+
+        let params = |directory: String, files: Vec<String>| Params {
+            directory,
+            files,
+            pattern: "find".to_string(),
+            patterns: vec![],
+            regex: false,
+            case_insensitive: false,
+            ignore_invalid_regex: false,
+            recursive: true,
+            max_depth: 0,
+            limit: 0,
+            follow_links: false,
+            include: None,
+            exclude: vec![],
+            absolute_globs: false,
+            line_numbers: true,
+            before_context: 0,
+            after_context: 0,
+            file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
+        };
+
+        // Absolute paths need no directory; only the listed files are searched
+        let file5 = test_dir.join("dir2/subdir/file5.txt");
+        let result = tool
+            .execute(params(
+                String::new(),
+                vec![file5.to_string_lossy().to_string()],
+            ))
+            .await?;
+        assert_eq!(result.files_searched, 1);
+        assert_eq!(result.total_matches, 2);
+
+        // Relative paths are resolved against the directory
+        let result = tool
+            .execute(params(
+                test_dir.to_string_lossy().to_string(),
+                vec!["file1.txt".to_string(), "dir1/file3.txt".to_string()],
+            ))
+            .await?;
+        assert_eq!(result.files_searched, 2);
+        assert_eq!(result.files_matched, 1);
+
+        // Missing files are reported
+        assert!(matches!(
+            tool.execute(params(
+                test_dir.to_string_lossy().to_string(),
+                vec!["missing.txt".to_string()],
+            ))
+            .await,
+            Err(Error::NotFound(_))
+        ));
+
+        // Cleanup
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_grep_invalid_regex() -> Result<()> {
+        let test_dir = setup_test_directory().await?;
+        let tool = FileGrep;
+
+        let params = |ignore_invalid_regex| Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            files: vec![],
+            pattern: "find(".to_string(),
+            patterns: vec![],
+            regex: true,
+            case_insensitive: false,
+            ignore_invalid_regex,
+            recursive: true,
+            max_depth: 0,
+            limit: 0,
+            follow_links: false,
+            include: None,
+            exclude: vec![],
+            absolute_globs: false,
+            line_numbers: true,
+            before_context: 0,
+            after_context: 0,
+            file_names_only: false,
+            invert: false,
+            count_only: false,
+            multiline: false,
+            binary_mode: None,
+            binary_context: 16,
+        };
+
+        // The regex error is reported to the caller
+        match tool.execute(params(false)).await {
+            Err(Error::InvalidParam(msg)) => assert!(msg.contains("find(")),
+            other => panic!("expected an invalid regex error, got {:?}", other),
+        }
+
+        // Unless the old lenient behaviour is asked for
+        let result = tool.execute(params(true)).await?;
+        assert_eq!(result.files_matched, 0);
+
+        // Cleanup
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_patterns() {
+        assert_eq!(parse_hex("7f 45 4C46").unwrap(), b"\x7fELF");
+        assert!(parse_hex("7f4").is_err());
+        assert!(parse_hex("+f").is_err());
+        assert_eq!(parse_escaped("\\x7fELF\\0\\\\").unwrap(), b"\x7fELF\0\\");
+        assert!(parse_escaped("\\x7").is_err());
+        assert!(parse_escaped("\\q").is_err());
+
+        let dump = hex_dump(b"0123456789abcdefGHI\x00", 17, 18);
+        assert_eq!(
+            dump,
+            vec!["00000010: 47 48 49 00                                       |GHI.|"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_grep_binary_mode() -> Result<()> {
+        let test_dir = get_test_dir();
+        fs::create_dir_all(&test_dir).await?;
+        let mut content = vec![0u8; 40];
+        content.extend_from_slice(b"\x7fELF\x02\x01");
+        content.extend(vec![0xffu8; 40]);
+        content.extend_from_slice(b"\x7fELF");
+        fs::write(test_dir.join("core.bin"), &content).await?;
+
+        let params = Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            pattern: "7f454c46".to_string(),
+            binary_mode: Some(BinaryMode::Hex),
+            binary_context: 4,
+            ..Default::default()
+        };
+        let output = FileGrep.execute(params).await?;
+        assert_eq!(output.total_matches, 2);
+        let matches = &output.files[0].binary_matches;
+        assert_eq!(matches[0].offset, 40);
+        assert_eq!(matches[0].length, 4);
+        assert_eq!(matches[1].offset, 86);
+        assert!(matches[0].hex_dump[0].starts_with("00000020: 00 00"));
+        assert!(matches[0].hex_dump[0].ends_with("|.........ELF....|"));
+
+        let params = Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            pattern: "\\x7fELF\\x02".to_string(),
+            binary_mode: Some(BinaryMode::Escaped),
+            count_only: true,
+            ..Default::default()
+        };
+        let output = FileGrep.execute(params).await?;
+        assert_eq!(output.files[0].count, Some(1));
+
+        let params = Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            pattern: "zz".to_string(),
+            binary_mode: Some(BinaryMode::Hex),
+            ..Default::default()
+        };
+        assert!(matches!(
+            FileGrep.execute(params).await,
+            Err(Error::InvalidParam(_))
+        ));
+
+        cleanup(&test_dir).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_grep_relative_globs() -> Result<()> {
+        let test_dir = get_test_dir();
+        fs::create_dir_all(test_dir.join("src/tools")).await?;
+        fs::create_dir_all(test_dir.join("target")).await?;
+        fs::write(test_dir.join("src/lib.rs"), "find").await?;
+        fs::write(test_dir.join("src/tools/grep.rs"), "find").await?;
+        fs::write(test_dir.join("target/gen.rs"), "find").await?;
+        fs::write(test_dir.join("notes.txt"), "find").await?;
+
+        let search = |include: Option<&str>, exclude: &[&str], absolute_globs| {
+            let params = Params {
+                directory: test_dir.to_string_lossy().to_string(),
+                pattern: "find".to_string(),
+                include: include.map(str::to_string),
+                exclude: exclude.iter().map(|glob| glob.to_string()).collect(),
+                absolute_globs,
+                file_names_only: true,
+                ..Default::default()
+            };
+            async move {
+                let output = FileGrep.execute(params).await?;
+                let mut names: Vec<String> = output
+                    .files
+                    .iter()
+                    .map(|file| file.path.rsplit('/').next().unwrap().to_string())
+                    .collect();
+                names.sort();
+                Ok::<_, Error>(names)
+            }
+        };
+
+        assert_eq!(
+            search(Some("src/**/*.rs"), &[], false).await?,
+            ["grep.rs", "lib.rs"]
+        );
+        assert_eq!(
+            search(Some("./*.rs"), &["target/*"], false).await?,
+            ["grep.rs", "lib.rs"]
+        );
+        assert_eq!(search(Some("*.txt"), &[], false).await?, ["notes.txt"]);
+        // Relative globs don't match absolute paths, and the other way round
+        assert!(search(Some("src/**/*.rs"), &[], true).await?.is_empty());
+        assert_eq!(
+            search(Some("*/src/*.rs"), &[], true).await?,
+            ["grep.rs", "lib.rs"]
+        );
+
+        cleanup(&test_dir).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_grep_skipped() -> Result<()> {
+        let test_dir = get_test_dir();
+        fs::create_dir_all(&test_dir).await?;
+        fs::write(test_dir.join("text.txt"), "find").await?;
+        fs::write(test_dir.join("data.bin"), b"find\0\x01").await?;
+        fs::write(test_dir.join("latin1.txt"), b"find caf\xe9").await?;
+
+        let params = Params {
+            directory: test_dir.to_string_lossy().to_string(),
+            pattern: "find".to_string(),
+            ..Default::default()
+        };
+        let (output, warnings) = warnings::capture(FileGrep.execute(params)).await;
+        let output = output?;
+
+        // Files that aren't text are counted, but only unreadable ones warned about
+        assert_eq!(output.files_matched, 1);
+        assert_eq!(
+            output.skipped,
+            Skipped {
+                binary: 1,
+                non_utf8: 1,
+                ..Skipped::default()
+            }
+        );
+        assert_eq!(output.skipped.total(), 2);
+        assert!(warnings.is_empty());
+
+        cleanup(&test_dir).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_grep_memory_fs() -> Result<()> {
+        let fs = std::sync::Arc::new(
+            vfs::MemoryFs::new()
+                .with_file("/repo/src/lib.rs", "fn find() {}\n")
+                .with_file("/repo/src/main.rs", "fn main() {}\n")
+                .with_file("/repo/notes.txt", "find me\n"),
+        );
+        vfs::scope(fs, async {
+            let params = Params {
+                directory: "/repo".to_string(),
+                pattern: "find".to_string(),
+                include: Some("src/*.rs".to_string()),
+                ..Default::default()
+            };
+            let output = FileGrep.execute(params).await?;
+            assert_eq!(output.files_searched, 2);
+            assert_eq!(output.files.len(), 1);
+            assert_eq!(output.files[0].path, "/repo/src/lib.rs");
+            assert_eq!(output.files[0].matches[0].line_number, 1);
+
+            let params = Params {
+                pattern: "find".to_string(),
+                files: vec!["/repo/notes.txt".to_string()],
+                ..Default::default()
+            };
+            let output = FileGrep.execute(params).await?;
+            assert_eq!(output.files_matched, 1);
+            Ok(())
+        })
+        .await
+    }
 }
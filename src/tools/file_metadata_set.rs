@@ -0,0 +1,222 @@
+//! File metadata set tool implementation
+//!
+//! Sets the modification and access times of a file or directory, e.g. to make
+//! a build system or cache see a file as stale or fresh.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use super::{Annotations, Tool};
+use crate::{Error, Result, vfs};
+
+/// File metadata set tool
+#[derive(Clone, Copy)]
+pub struct FileMetadataSet;
+
+/// Parameters for the file metadata set tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Path of the file or directory
+    #[serde(deserialize_with = "crate::roots::path")]
+    pub path: String,
+
+    /// New modification time: an RFC 3339 timestamp such as
+    /// "2024-01-31T12:00:00Z", or "now". Unchanged if not provided.
+    #[serde(default)]
+    pub modified: Option<String>,
+
+    /// New access time, in the same format as `modified`. Unchanged if not
+    /// provided.
+    #[serde(default)]
+    pub accessed: Option<String>,
+}
+
+impl Params {
+    /// Set the modification time of `path` to `modified` ("now" or RFC 3339)
+    pub fn new(path: impl Into<String>, modified: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            modified: Some(modified.into()),
+            accessed: None,
+        }
+    }
+}
+
+/// Output of the file metadata set tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Path of the file or directory
+    pub path: String,
+
+    /// Modification time after the change (RFC 3339), if the filesystem reports it
+    pub modified: Option<String>,
+
+    /// Access time that was set (RFC 3339), if it was changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accessed: Option<String>,
+}
+
+/// Parse "now" or an RFC 3339 timestamp
+fn parse_time(name: &str, value: &str, now: SystemTime) -> Result<SystemTime> {
+    if value.eq_ignore_ascii_case("now") {
+        return Ok(now);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(SystemTime::from)
+        .map_err(|e| {
+            Error::InvalidParam(format!(
+                "{} must be \"now\" or an RFC 3339 timestamp, got '{}': {}",
+                name, value, e
+            ))
+        })
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339()
+}
+
+#[async_trait]
+impl Tool for FileMetadataSet {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "file_metadata_set"
+    }
+
+    fn description(&self) -> &str {
+        "Set the modification and access times of a file"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations {
+            destructive: false,
+            ..Annotations::DESTRUCTIVE
+        }
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        if params.modified.is_none() && params.accessed.is_none() {
+            return Err(Error::InvalidParam(
+                "At least one of modified or accessed is required".to_string(),
+            ));
+        }
+
+        // "now" means the same instant for both times
+        let now = SystemTime::now();
+        let modified = params
+            .modified
+            .as_deref()
+            .map(|value| parse_time("modified", value, now))
+            .transpose()?;
+        let accessed = params
+            .accessed
+            .as_deref()
+            .map(|value| parse_time("accessed", value, now))
+            .transpose()?;
+
+        let path = PathBuf::from(&params.path);
+        let fs = vfs::current();
+        fs.set_times(&path, accessed, modified)
+            .await
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => {
+                    Error::NotFound(format!("File not found: {}", params.path))
+                }
+                std::io::ErrorKind::PermissionDenied => {
+                    Error::PermissionDenied(format!("Permission denied: {}", params.path))
+                }
+                _ => Error::Io(e),
+            })?;
+
+        let metadata = fs.metadata(&path).await?;
+        Ok(Output {
+            path: params.path,
+            modified: metadata.modified.map(format_time),
+            accessed: accessed.map(format_time),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_file_metadata_set() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "metadata_set_test_{}",
+            Utc::now().timestamp_millis()
+        ));
+        std::fs::create_dir_all(&dir)?;
+        let file = dir.join("stale.txt");
+        std::fs::write(&file, "content")?;
+
+        let tool = FileMetadataSet;
+        let params = Params {
+            accessed: Some("2020-01-01T00:00:00Z".to_string()),
+            ..Params::new(file.to_string_lossy(), "2021-06-15T12:30:00+02:00")
+        };
+        let result = tool.execute(params).await?;
+        assert_eq!(
+            result.modified.as_deref(),
+            Some("2021-06-15T10:30:00+00:00")
+        );
+        assert_eq!(
+            result.accessed.as_deref(),
+            Some("2020-01-01T00:00:00+00:00")
+        );
+
+        let metadata = std::fs::metadata(&file)?;
+        let expected =
+            SystemTime::from(DateTime::parse_from_rfc3339("2021-06-15T10:30:00Z").unwrap());
+        assert_eq!(metadata.modified()?, expected);
+
+        // "now" moves it forward again
+        let result = tool
+            .execute(Params::new(file.to_string_lossy(), "now"))
+            .await?;
+        assert!(std::fs::metadata(&file)?.modified()? > expected);
+        assert!(result.accessed.is_none());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_metadata_set_errors() {
+        let fs = Arc::new(MemoryFs::new().with_file("/a.txt", "a"));
+        vfs::scope(fs, async {
+            let tool = FileMetadataSet;
+
+            let neither = Params {
+                modified: None,
+                ..Params::new("/a.txt", "now")
+            };
+            let result = tool.execute(neither).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+
+            let result = tool.execute(Params::new("/a.txt", "yesterday")).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+
+            let result = tool.execute(Params::new("/missing.txt", "now")).await;
+            assert!(matches!(result, Err(Error::NotFound(_))));
+
+            let result = tool
+                .execute(Params::new("/a.txt", "2000-01-01T00:00:00Z"))
+                .await
+                .unwrap();
+            assert_eq!(
+                result.modified.as_deref(),
+                Some("2000-01-01T00:00:00+00:00")
+            );
+        })
+        .await;
+    }
+}
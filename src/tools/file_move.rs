@@ -3,23 +3,26 @@
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
-use std::path::PathBuf;
-use tokio::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 
-use super::Tool;
-use crate::{Error, Result};
+use super::{Annotations, Tool};
+use crate::vfs::{Filesystem, Metadata};
+use crate::{Error, Result, paths, trash, vfs};
 
 /// File move tool
 #[derive(Clone, Copy)]
 pub struct FileMove;
 
 /// Parameters for the file move tool
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Params {
     /// Source path
+    #[serde(deserialize_with = "crate::roots::path")]
     pub source: String,
 
     /// Destination path
+    #[serde(deserialize_with = "crate::roots::path")]
     pub destination: String,
 
     /// Whether to overwrite the destination if it exists
@@ -29,10 +32,39 @@ pub struct Params {
     /// Whether to create parent directories of the destination if they don't exist
     #[serde(default)]
     pub create_dirs: bool,
+
+    /// When moving a directory onto an existing directory, merge the source
+    /// contents into it (conflicting files require `overwrite`)
+    #[serde(default)]
+    pub merge: bool,
+
+    /// When the destination is an existing directory, remove it entirely first
+    #[serde(default)]
+    pub replace: bool,
+
+    /// Move a destination that is overwritten or replaced to the trash instead
+    /// of removing it for good
+    #[serde(default)]
+    pub use_trash: bool,
+}
+
+impl Params {
+    /// Move `source` to `destination`
+    pub fn new(source: impl Into<String>, destination: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            destination: destination.into(),
+            overwrite: false,
+            create_dirs: false,
+            merge: false,
+            replace: false,
+            use_trash: false,
+        }
+    }
 }
 
 /// Output of the file move tool
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Output {
     /// Source path
     pub source: String,
@@ -42,6 +74,141 @@ pub struct Output {
 
     /// Whether the destination was overwritten
     pub overwritten: bool,
+
+    /// Whether the move fell back to copy and delete because the source and
+    /// destination are on different filesystems
+    pub copied: bool,
+
+    /// Whether a source directory was merged into an existing destination directory
+    pub merged: bool,
+
+    /// Where the old destination went, when it was moved to a trash directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trashed: Option<String>,
+}
+
+/// Copy a file, verify the copy and then remove the original
+async fn copy_verify_remove(fs: &dyn Filesystem, source: &Path, destination: &Path) -> Result<()> {
+    let expected = fs.metadata(source).await?.len();
+    let copied = fs.copy(source, destination).await?;
+
+    // Make sure the whole file arrived before deleting the original
+    let actual = fs.metadata(destination).await?.len();
+    if copied != expected || actual != expected {
+        let _ = fs.remove_file(destination).await;
+        return Err(Error::Other(format!(
+            "Copy verification failed: expected {} bytes, found {}",
+            expected, actual
+        )));
+    }
+
+    fs.remove_file(source).await?;
+    Ok(())
+}
+
+/// Recursively copy a directory
+pub(crate) async fn copy_dir_all(
+    fs: &dyn Filesystem,
+    source: &Path,
+    destination: &Path,
+) -> std::io::Result<()> {
+    let mut pending = vec![(source.to_path_buf(), destination.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        fs.create_dir_all(&to).await?;
+        for entry in fs.read_dir(&from).await? {
+            let target = to.join(entry.file_name());
+            if entry.metadata.is_dir() {
+                pending.push((entry.path, target));
+            } else {
+                fs.copy(&entry.path, &target).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rename a path, copying and removing it when crossing filesystems.
+/// Returns whether the fallback was used.
+pub(crate) async fn move_path(
+    fs: &dyn Filesystem,
+    source: &Path,
+    destination: &Path,
+) -> std::io::Result<bool> {
+    match fs.rename(source, destination).await {
+        Ok(()) => Ok(false),
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            if fs.metadata(source).await?.is_dir() {
+                copy_dir_all(fs, source, destination).await?;
+                fs.remove_dir_all(source).await?;
+            } else {
+                fs.copy(source, destination).await?;
+                fs.remove_file(source).await?;
+            }
+            Ok(true)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Collect destination paths that would be overwritten by merging `source` into `destination`
+async fn find_merge_conflicts(
+    fs: &dyn Filesystem,
+    source: &Path,
+    destination: &Path,
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut conflicts = Vec::new();
+    let mut pending = vec![(source.to_path_buf(), destination.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        for entry in fs.read_dir(&from).await? {
+            let target = to.join(entry.file_name());
+            match fs.metadata(&target).await {
+                Ok(existing) if existing.is_dir() && entry.metadata.is_dir() => {
+                    pending.push((entry.path, target));
+                }
+                Ok(_) => conflicts.push(target),
+                Err(_) => {}
+            }
+        }
+    }
+    Ok(conflicts)
+}
+
+/// Move the contents of `source` into `destination`, recursing into directories
+/// that exist on both sides, then remove the emptied source directories.
+/// Returns whether any entry had to be copied across filesystems.
+async fn merge_directories(
+    fs: &dyn Filesystem,
+    source: &Path,
+    destination: &Path,
+) -> std::io::Result<bool> {
+    let mut copied = false;
+    let mut emptied = Vec::new();
+    let mut pending = vec![(source.to_path_buf(), destination.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        for entry in fs.read_dir(&from).await? {
+            let target = to.join(entry.file_name());
+            let existing = fs.metadata(&target).await.ok();
+            if entry.metadata.is_dir() && existing.as_ref().is_some_and(Metadata::is_dir) {
+                pending.push((entry.path, target));
+                continue;
+            }
+
+            // Conflicts have been checked by the caller, so anything here is replaced
+            match existing {
+                Some(existing) if existing.is_dir() => fs.remove_dir_all(&target).await?,
+                Some(_) => fs.remove_file(&target).await?,
+                None => {}
+            }
+            copied |= move_path(fs, &entry.path, &target).await?;
+        }
+        emptied.push(from);
+    }
+
+    // Subdirectories were emptied after their parents, so go back to front
+    for dir in emptied.iter().rev() {
+        fs.remove_dir(dir).await?;
+    }
+    Ok(copied)
 }
 
 #[async_trait]
@@ -53,57 +220,141 @@ impl Tool for FileMove {
         "file_move"
     }
 
+    fn description(&self) -> &str {
+        "Move or rename a file"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::DESTRUCTIVE
+    }
+
     async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let fs = vfs::current();
+        let fs = fs.as_ref();
         let source = PathBuf::from(&params.source);
         let destination = PathBuf::from(&params.destination);
 
         // Check if the source exists
-        if !source.exists() {
-            return Err(Error::InvalidParam(format!(
+        let Ok(source_metadata) = fs.metadata(&source).await else {
+            return Err(Error::NotFound(format!(
                 "Source not found: {}",
                 params.source
             )));
-        }
+        };
 
         // Create parent directories if requested
         if params.create_dirs {
-            if let Some(parent) = destination.parent() {
-                if !parent.as_os_str().is_empty() && !parent.exists() {
-                    fs::create_dir_all(parent).await?;
-                }
+            if let Some(parent) = destination.parent()
+                && !parent.as_os_str().is_empty()
+                && !fs.exists(parent).await
+            {
+                fs.create_dir_all(parent).await?;
             }
-        } else if let Some(parent) = destination.parent() {
-            if !parent.as_os_str().is_empty() && !parent.exists() {
+        } else if let Some(parent) = destination.parent()
+            && !parent.as_os_str().is_empty()
+            && !fs.exists(parent).await
+        {
+            return Err(Error::NotFound(format!(
+                "Destination parent directory does not exist: {}",
+                parent.display()
+            )));
+        }
+
+        // Check if the destination exists
+        let dest_metadata = fs.metadata(&destination).await.ok();
+        let dest_exists = dest_metadata.is_some();
+        let dest_is_dir = dest_metadata.as_ref().is_some_and(Metadata::is_dir);
+
+        // Merge a directory into an existing directory
+        if params.merge && source_metadata.is_dir() && dest_is_dir {
+            let conflicts = find_merge_conflicts(fs, &source, &destination).await?;
+
+            if !conflicts.is_empty() && !params.overwrite {
                 return Err(Error::InvalidParam(format!(
-                    "Destination parent directory does not exist: {}",
-                    parent.display()
+                    "Merge would overwrite {} existing path(s), starting with {}",
+                    conflicts.len(),
+                    conflicts[0].display()
                 )));
             }
-        }
+            if !conflicts.is_empty() && params.use_trash {
+                return Err(Error::InvalidParam(
+                    "use_trash is not supported when a merge overwrites existing paths".to_string(),
+                ));
+            }
 
-        // Check if the destination exists
-        let dest_exists = destination.exists();
+            let copied = merge_directories(fs, &source, &destination).await?;
 
-        if dest_exists && !params.overwrite {
-            return Err(Error::InvalidParam(format!(
-                "Destination already exists: {}",
-                params.destination
-            )));
+            return Ok(Output {
+                source: params.source,
+                destination: params.destination,
+                overwritten: !conflicts.is_empty(),
+                copied,
+                merged: true,
+                trashed: None,
+            });
         }
 
-        // For overwrite operations, we need to remove the destination first
-        // because rename can fail on some platforms when destination exists
-        if dest_exists && params.overwrite {
-            fs::remove_file(&destination).await?;
+        let mut trashed = None;
+        if dest_is_dir {
+            // Replace an existing directory only when explicitly asked to
+            if !params.replace {
+                return Err(Error::AlreadyExists(format!(
+                    "Destination directory already exists: {} (use merge or replace)",
+                    params.destination
+                )));
+            }
+            trashed = if params.use_trash {
+                trash::discard(fs, &destination).await?
+            } else {
+                fs.remove_dir_all(&destination).await?;
+                None
+            };
+        } else if dest_exists {
+            if !params.overwrite {
+                return Err(Error::AlreadyExists(format!(
+                    "Destination already exists: {}",
+                    params.destination
+                )));
+            }
+
+            // Remove the destination first because rename can fail on some
+            // platforms when the destination exists
+            trashed = if params.use_trash {
+                trash::discard(fs, &destination).await?
+            } else {
+                fs.remove_file(&destination).await?;
+                None
+            };
         }
 
-        // Perform the move operation
-        fs::rename(&source, &destination).await?;
+        // Perform the move operation, falling back to copy and delete when
+        // rename cannot cross filesystems
+        let copied = match fs.rename(&source, &destination).await {
+            Ok(()) => false,
+            Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+                log::debug!(
+                    "Cross-device move from {} to {}, copying instead",
+                    params.source,
+                    params.destination
+                );
+                if source_metadata.is_dir() {
+                    copy_dir_all(fs, &source, &destination).await?;
+                    fs.remove_dir_all(&source).await?;
+                } else {
+                    copy_verify_remove(fs, &source, &destination).await?;
+                }
+                true
+            }
+            Err(e) => return Err(Error::Io(e)),
+        };
 
         Ok(Output {
             source: params.source,
             destination: params.destination,
             overwritten: dest_exists,
+            copied,
+            merged: false,
+            trashed: trashed.as_deref().map(paths::to_string),
         })
     }
 }
@@ -111,6 +362,7 @@ impl Tool for FileMove {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sys::fs;
     use std::path::Path;
     use tokio::fs::File;
     use tokio::io::AsyncWriteExt;
@@ -182,6 +434,9 @@ mod tests {
             destination: dest_file.to_string_lossy().to_string(),
             overwrite: false,
             create_dirs: false,
+            merge: false,
+            replace: false,
+            use_trash: false,
         };
 
         let result = tool.execute(params).await?;
@@ -204,6 +459,32 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_file_move_overwrite_to_trash() -> Result<()> {
+        let test_dir = get_test_dir().with_extension("trash");
+        fs::create_dir_all(&test_dir).await?;
+        let source_file = test_dir.join("new.txt");
+        let dest_file = test_dir.join("config.txt");
+        create_test_file(&source_file, "new").await?;
+        create_test_file(&dest_file, "old").await?;
+
+        let params = Params {
+            overwrite: true,
+            use_trash: true,
+            ..Params::new(source_file.to_string_lossy(), dest_file.to_string_lossy())
+        };
+        let result = trash::scope(test_dir.join("trash"), FileMove.execute(params)).await?;
+
+        assert!(result.overwritten);
+        assert_eq!(fs::read_to_string(&dest_file).await?, "new");
+        let trashed = result.trashed.expect("old destination in the trash");
+        assert!(trashed.contains("config.txt."));
+        assert_eq!(fs::read_to_string(&trashed).await?, "old");
+
+        fs::remove_dir_all(&test_dir).await?;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_file_move_overwrite() -> Result<()> {
         let test_dir = get_test_dir();
@@ -250,6 +531,9 @@ mod tests {
             destination: dest_file.to_string_lossy().to_string(),
             overwrite: false,
             create_dirs: false,
+            merge: false,
+            replace: false,
+            use_trash: false,
         };
 
         let result = tool.execute(params).await;
@@ -275,6 +559,9 @@ mod tests {
             destination: dest_file.to_string_lossy().to_string(),
             overwrite: true,
             create_dirs: false,
+            merge: false,
+            replace: false,
+            use_trash: false,
         };
 
         let result = tool.execute(params).await?;
@@ -330,6 +617,9 @@ mod tests {
             destination: dest_file.to_string_lossy().to_string(),
             overwrite: false,
             create_dirs: true,
+            merge: false,
+            replace: false,
+            use_trash: false,
         };
 
         let result = tool.execute(params).await?;
@@ -365,6 +655,9 @@ mod tests {
             destination: dest_file.to_string_lossy().to_string(),
             overwrite: false,
             create_dirs: false,
+            merge: false,
+            replace: false,
+            use_trash: false,
         };
 
         let result = tool.execute(params).await;
@@ -377,4 +670,81 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_copy_verify_remove() -> Result<()> {
+        let test_dir = get_test_dir().join("copy_fallback");
+        fs::create_dir_all(&test_dir).await?;
+
+        let source_file = test_dir.join("source.txt");
+        let dest_file = test_dir.join("destination.txt");
+        create_test_file(&source_file, "Copied content").await?;
+
+        // Exercise the cross-device fallback directly
+        copy_verify_remove(&vfs::RealFs, &source_file, &dest_file).await?;
+
+        assert!(!source_file.exists());
+        assert_eq!(fs::read_to_string(&dest_file).await?, "Copied content");
+
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_directory_move_merge_and_replace() -> Result<()> {
+        let test_dir = get_test_dir().join("dir_merge");
+        let source_dir = test_dir.join("source");
+        let dest_dir = test_dir.join("destination");
+
+        fs::create_dir_all(source_dir.join("nested")).await?;
+        fs::create_dir_all(dest_dir.join("nested")).await?;
+        create_test_file(&source_dir.join("nested/new.txt"), "new").await?;
+        create_test_file(&source_dir.join("shared.txt"), "from source").await?;
+        create_test_file(&dest_dir.join("nested/kept.txt"), "kept").await?;
+        create_test_file(&dest_dir.join("shared.txt"), "from destination").await?;
+
+        let tool = FileMove;
+        let params = |merge: bool, overwrite: bool, replace: bool| Params {
+            source: source_dir.to_string_lossy().to_string(),
+            destination: dest_dir.to_string_lossy().to_string(),
+            overwrite,
+            create_dirs: false,
+            merge,
+            replace,
+            use_trash: false,
+        };
+
+        // Moving onto an existing directory needs merge or replace
+        assert!(tool.execute(params(false, false, false)).await.is_err());
+
+        // Merging with a conflicting file needs overwrite
+        assert!(tool.execute(params(true, false, false)).await.is_err());
+        assert!(source_dir.join("shared.txt").exists());
+
+        let result = tool.execute(params(true, true, false)).await?;
+        assert!(result.merged);
+        assert!(result.overwritten);
+        assert!(!source_dir.exists());
+        assert!(dest_dir.join("nested/kept.txt").exists());
+        assert!(dest_dir.join("nested/new.txt").exists());
+        assert_eq!(
+            fs::read_to_string(dest_dir.join("shared.txt")).await?,
+            "from source"
+        );
+
+        // Replace removes the existing directory entirely
+        fs::create_dir_all(&source_dir).await?;
+        create_test_file(&source_dir.join("only.txt"), "only").await?;
+
+        let result = tool.execute(params(false, false, true)).await?;
+        assert!(!result.merged);
+        assert!(result.overwritten);
+        assert!(dest_dir.join("only.txt").exists());
+        assert!(!dest_dir.join("nested").exists());
+
+        cleanup(&test_dir).await;
+
+        Ok(())
+    }
 }
@@ -4,41 +4,48 @@ use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine as _};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tokio::fs;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 
-use super::Tool;
-use crate::{Error, Result};
+use super::{Annotations, Tool};
+use super::file_diff::DiffHunk;
+use super::journal::Journal;
+use super::precondition::check_unchanged;
+use crate::vfs::{self, Filesystem};
+use crate::{Error, Result, paths};
 
 /// Patch type for the file patch tool
-#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum PatchType {
     /// Unified diff format
+    #[default]
     Unified,
     /// Binary patch as base64 encoded data
     Binary,
 }
 
-impl Default for PatchType {
-    fn default() -> Self {
-        Self::Unified
-    }
-}
-
 /// File patch tool
 #[derive(Clone, Copy)]
 pub struct FilePatch;
 
 /// Parameters for the file patch tool
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Default)]
 pub struct Params {
-    /// Path of the file to patch
+    /// Path of the file to patch (may be omitted when `base_dir` is given)
+    #[serde(default, deserialize_with = "crate::roots::path")]
     pub path: String,
 
-    /// The patch to apply
+    /// The patch to apply (may be omitted when `hunks` is given)
+    #[serde(default)]
     pub patch: String,
 
+    /// Structured hunks as produced by file_diff, applied instead of `patch`
+    /// (unified patches only)
+    #[serde(default)]
+    pub hunks: Vec<DiffHunk>,
+
     /// Type of patch
     #[serde(default)]
     pub patch_type: PatchType,
@@ -46,10 +53,35 @@ pub struct Params {
     /// Create a backup of the original file
     #[serde(default)]
     pub create_backup: bool,
+
+    /// Maximum number of context lines that may be ignored at each end of a hunk
+    /// when it does not match exactly (like the fuzz factor of GNU patch)
+    #[serde(default)]
+    pub fuzz: usize,
+
+    /// Only check whether the patch applies cleanly, without modifying the file
+    #[serde(default)]
+    pub check_only: bool,
+
+    /// Apply a multi-file (git-style) patch, resolving the paths in its
+    /// `---`/`+++` headers and `rename from`/`rename to` lines relative to
+    /// this directory. An I/O error part way through undoes the files already
+    /// changed.
+    #[serde(default, deserialize_with = "crate::roots::optional_path")]
+    pub base_dir: Option<String>,
+
+    /// Only patch if the file still has this SHA-256 digest (hex, single-file patches)
+    #[serde(default)]
+    pub expected_sha256: Option<String>,
+
+    /// Only patch if the file still has this modification time
+    /// (RFC 3339, compared to the second, single-file patches)
+    #[serde(default)]
+    pub expected_mtime: Option<String>,
 }
 
 /// Output of the file patch tool
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Output {
     /// Path of the patched file
     pub path: String,
@@ -57,7 +89,7 @@ pub struct Output {
     /// Original size of the file in bytes
     pub original_size: u64,
 
-    /// New size of the file in bytes
+    /// New size of the file in bytes (the size it would have when only checking)
     pub new_size: u64,
 
     /// Type of patch that was applied
@@ -66,163 +98,509 @@ pub struct Output {
     /// Path of the backup file (if backup was created)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub backup_path: Option<String>,
+
+    /// Where each hunk was applied (unified patches only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hunks: Vec<HunkResult>,
+
+    /// Whether the patch was only checked and the file left untouched
+    pub check_only: bool,
+
+    /// Whether every hunk of the patch applied cleanly
+    pub clean: bool,
+
+    /// Per-file results (multi-file patches only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileResult>,
 }
 
-/// Apply a unified diff patch to text content
-fn apply_unified_patch(content: String, patch_text: &str) -> Result<String> {
-    // Convert the content to lines for patching
-    let lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
+/// What a multi-file patch does to a single file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FileAction {
+    /// An existing file is modified
+    Modified,
+    /// A new file is created
+    Created,
+    /// An existing file is deleted
+    Deleted,
+    /// An existing file is moved, possibly with changes
+    Renamed,
+}
 
-    // Parse and apply the patch manually
-    let mut patched_lines = lines.clone();
-    let mut in_hunk = false;
-    let mut hunk_old_start = 0;
-    let mut line_offset = 0;
-    let mut removed_lines = 0;
+/// Result of patching a single file of a multi-file patch
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FileResult {
+    /// Path of the file, relative to the base directory
+    pub path: String,
 
-    // Track additions and removals for this hunk
-    let mut to_remove = Vec::new();
-    let mut to_add = Vec::new();
+    /// Where a renamed file came from, relative to the base directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<String>,
 
-    for line in patch_text.lines() {
-        if line.starts_with("---") || line.starts_with("+++") {
-            // Header lines, skip
-            continue;
-        } else if line.starts_with("@@") {
-            // Start of a hunk
-            if in_hunk {
-                // Apply previous hunk before starting a new one
-                apply_hunk(&mut patched_lines, &to_remove, &to_add)?;
-                to_remove.clear();
-                to_add.clear();
-                line_offset = 0;
-                removed_lines = 0;
-            }
+    /// What the patch does to the file
+    pub action: FileAction,
 
-            in_hunk = true;
-            // Parse the hunk header (e.g. @@ -1,5 +1,6 @@)
-            let parts: Vec<&str> = line.split(" ").collect();
-            if parts.len() < 3 {
-                return Err(Error::InvalidParam(format!(
-                    "Invalid hunk header: {}",
-                    line
-                )));
-            }
+    /// Original size of the file in bytes
+    pub original_size: u64,
 
-            let old_range = parts[1].trim_start_matches("-");
+    /// New size of the file in bytes
+    pub new_size: u64,
 
-            let old_parts: Vec<&str> = old_range.split(",").collect();
+    /// Path of the backup file (if backup was created)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup_path: Option<String>,
 
-            hunk_old_start = old_parts[0].parse::<usize>().map_err(|_| {
-                Error::InvalidParam(format!("Invalid line number in hunk header: {}", line))
-            })?;
-        } else if in_hunk {
-            if let Some(_stripped) = line.strip_prefix(" ") {
-                // Context line
-                let text = line[1..].to_string();
-                let line_num = hunk_old_start + line_offset - 1;
+    /// Where each hunk was applied
+    pub hunks: Vec<HunkResult>,
 
-                if line_num < patched_lines.len() && patched_lines[line_num] == text {
-                    // Context line matches, move on
-                    line_offset += 1;
-                } else {
-                    return Err(Error::InvalidParam(format!(
-                        "Context line mismatch at line {}: expected '{}', found '{}'",
-                        line_num + 1,
-                        text,
-                        if line_num < patched_lines.len() {
-                            &patched_lines[line_num]
-                        } else {
-                            ""
-                        }
-                    )));
-                }
-            } else if let Some(_stripped) = line.strip_prefix("-") {
-                // Remove line
-                let text = line[1..].to_string();
-                let line_num = hunk_old_start + line_offset - 1 - removed_lines;
-
-                if line_num < patched_lines.len() && patched_lines[line_num] == text {
-                    // Line to remove matches
-                    to_remove.push(line_num);
-                    line_offset += 1;
-                    removed_lines += 1;
-                } else {
-                    return Err(Error::InvalidParam(format!(
-                        "Remove line mismatch at line {}: expected '{}', found '{}'",
-                        line_num + 1,
-                        text,
-                        if line_num < patched_lines.len() {
-                            &patched_lines[line_num]
-                        } else {
-                            ""
-                        }
-                    )));
-                }
-            } else if let Some(_stripped) = line.strip_prefix("+") {
-                // Add line
-                let text = line[1..].to_string();
-                let line_num = hunk_old_start + line_offset - 1;
+    /// Whether every hunk for this file applied cleanly
+    pub clean: bool,
+}
 
-                to_add.push((line_num, text));
-            }
+/// A single line within a hunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HunkLine {
+    /// Line present in both the original and patched file
+    Context(String),
+    /// Line removed from the original file
+    Remove(String),
+    /// Line added to the patched file
+    Add(String),
+}
+
+/// A parsed hunk of a unified diff
+#[derive(Debug, Clone)]
+struct Hunk {
+    /// Start line in the original file as given by the hunk header
+    old_start: usize,
+    /// Number of original lines covered by the hunk as given by the hunk header
+    old_count: usize,
+    /// Lines of the hunk body
+    lines: Vec<HunkLine>,
+    /// Whether the original side ends without a newline ("\\ No newline at end of file")
+    old_no_newline: bool,
+    /// Whether the patched side ends without a newline
+    new_no_newline: bool,
+}
+
+impl Hunk {
+    /// Whether the patched file should end with a newline, if the hunk says so explicitly
+    fn newline_at_end(&self) -> Option<bool> {
+        if self.new_no_newline {
+            Some(false)
+        } else if self.old_no_newline {
+            Some(true)
+        } else {
+            None
         }
     }
 
-    // Apply the last hunk if we were in one
-    if in_hunk {
-        apply_hunk(&mut patched_lines, &to_remove, &to_add)?;
+    /// Build the original and replacement lines, ignoring up to `fuzz` context
+    /// lines at each end of the hunk. Also returns how many leading lines were dropped.
+    fn trimmed(&self, fuzz: usize) -> (Vec<&str>, Vec<&str>, usize) {
+        let leading = self
+            .lines
+            .iter()
+            .take_while(|l| matches!(l, HunkLine::Context(_)))
+            .count();
+        let trailing = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|l| matches!(l, HunkLine::Context(_)))
+            .count();
+
+        let lead = fuzz.min(leading);
+        let trail = fuzz.min(trailing).min(self.lines.len() - lead);
+
+        let mut old = Vec::new();
+        let mut new = Vec::new();
+        for line in &self.lines[lead..self.lines.len() - trail] {
+            match line {
+                HunkLine::Context(text) => {
+                    old.push(text.as_str());
+                    new.push(text.as_str());
+                }
+                HunkLine::Remove(text) => old.push(text.as_str()),
+                HunkLine::Add(text) => new.push(text.as_str()),
+            }
+        }
+
+        (old, new, lead)
     }
+}
 
-    // Join lines to create patched content
-    Ok(patched_lines.join("\n"))
+/// Result of applying a single hunk
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct HunkResult {
+    /// Index of the hunk in the patch (1-based)
+    pub hunk: usize,
+
+    /// Whether the hunk could be applied
+    pub applied: bool,
+
+    /// Line in the patched file where the hunk was applied, or where it was
+    /// expected when it could not be applied (1-based)
+    pub line: usize,
+
+    /// Number of lines the hunk was shifted from the position given in its header
+    pub offset: isize,
+
+    /// Number of context lines ignored at each end of the hunk to make it apply
+    pub fuzz: usize,
 }
 
-/// Apply a single hunk's changes to the patched lines
-fn apply_hunk(
-    patched_lines: &mut Vec<String>,
-    to_remove: &[usize],
-    to_add: &[(usize, String)],
-) -> Result<()> {
-    // First, mark all the lines to remove
-    let to_remove_indexes = to_remove.to_vec();
+/// Parse a hunk header (e.g. @@ -1,5 +1,6 @@) into the original start line
+/// and count, and the patched count
+fn parse_hunk_header(line: &str) -> Result<(usize, usize, usize)> {
+    let invalid = || Error::InvalidParam(format!("Invalid hunk header: {}", line));
+
+    let mut ranges = line.split_whitespace().skip(1);
+    let old_range = ranges
+        .next()
+        .and_then(|range| range.strip_prefix('-'))
+        .ok_or_else(invalid)?;
+    let new_range = ranges
+        .next()
+        .and_then(|range| range.strip_prefix('+'))
+        .ok_or_else(invalid)?;
+
+    let mut old_parts = old_range.split(',');
+    let old_start = old_parts
+        .next()
+        .and_then(|start| start.parse::<usize>().ok())
+        .ok_or_else(|| {
+            Error::InvalidParam(format!("Invalid line number in hunk header: {}", line))
+        })?;
+    let old_count = match old_parts.next() {
+        Some(count) => count.parse::<usize>().map_err(|_| invalid())?,
+        None => 1,
+    };
+    let new_count = match new_range.split_once(',') {
+        Some((_, count)) => count.parse::<usize>().map_err(|_| invalid())?,
+        None => 1,
+    };
+
+    Ok((old_start, old_count, new_count))
+}
 
-    // Sort the lines to add by position
-    let mut sorted_adds = to_add.to_vec();
-    sorted_adds.sort_by(|a, b| a.0.cmp(&b.0));
+/// Mark which lines of a patch belong to a hunk body, going by the line
+/// counts of each `@@` header, so that a removed `-- x` or added `++ y` line
+/// isn't taken for a file header, nor text after a hunk for part of it. A
+/// body is cut short if another header or a line that can't be part of a
+/// hunk comes first.
+fn hunk_body_lines(lines: &[&str]) -> Vec<bool> {
+    let mut body = vec![false; lines.len()];
+    let (mut old_left, mut new_left) = (0, 0);
+    // Whether the previous line ended a hunk, which a "\ No newline at end
+    // of file" marker may still follow
+    let mut just_ended = false;
+
+    for (index, line) in lines.iter().enumerate() {
+        if line.starts_with("@@") {
+            (old_left, new_left) = match parse_hunk_header(line) {
+                Ok((_, old_count, new_count)) => (old_count, new_count),
+                Err(_) => (0, 0),
+            };
+            just_ended = false;
+            continue;
+        }
 
-    // Create a new vec with the patched content
-    let mut new_patched_lines = Vec::with_capacity(patched_lines.len());
+        let in_hunk = old_left > 0 || new_left > 0;
+        if line.starts_with('\\') && (in_hunk || just_ended) {
+            body[index] = true;
+            continue;
+        }
+        just_ended = false;
+        if !in_hunk {
+            continue;
+        }
 
-    for (idx, line) in patched_lines.iter().enumerate() {
-        // Skip lines marked for removal
-        if to_remove_indexes.contains(&idx) {
+        if line.is_empty() || line.starts_with(' ') {
+            old_left -= old_left.min(1);
+            new_left -= new_left.min(1);
+        } else if line.starts_with('-') {
+            old_left -= old_left.min(1);
+        } else if line.starts_with('+') {
+            new_left -= new_left.min(1);
+        } else {
+            (old_left, new_left) = (0, 0);
             continue;
         }
+        body[index] = true;
+        just_ended = old_left == 0 && new_left == 0;
+    }
 
-        // Add any new lines that should come before this position
-        while let Some((add_idx, text)) = sorted_adds.first() {
-            if *add_idx <= idx {
-                new_patched_lines.push(text.clone());
-                sorted_adds.remove(0);
-            } else {
+    body
+}
+
+/// Check whether the line at `index` belongs to a `---`/`+++` file header pair
+fn is_file_header(lines: &[&str], index: usize) -> bool {
+    let line = lines[index];
+    if line.starts_with("--- ") {
+        lines
+            .get(index + 1)
+            .is_some_and(|next| next.starts_with("+++ "))
+    } else if line.starts_with("+++ ") {
+        index > 0 && lines[index - 1].starts_with("--- ")
+    } else {
+        false
+    }
+}
+
+/// Parse the hunks of a unified diff. Each hunk's body is as long as its
+/// header's line counts say; anything between hunks, such as file headers,
+/// is skipped.
+fn parse_unified_patch(patch_text: &str) -> Result<Vec<Hunk>> {
+    let patch_lines: Vec<&str> = patch_text.lines().collect();
+    let body = hunk_body_lines(&patch_lines);
+    let mut hunks = Vec::new();
+    let mut current: Option<Hunk> = None;
+
+    for (index, line) in patch_lines.iter().enumerate() {
+        if line.starts_with("@@") {
+            // Start of a hunk
+            if let Some(hunk) = current.take() {
+                hunks.push(hunk);
+            }
+
+            let (old_start, old_count, _) = parse_hunk_header(line)?;
+            current = Some(Hunk {
+                old_start,
+                old_count,
+                lines: Vec::new(),
+                old_no_newline: false,
+                new_no_newline: false,
+            });
+        } else if !body[index] {
+            continue;
+        } else if let Some(hunk) = current.as_mut() {
+            if let Some(text) = line.strip_prefix(' ') {
+                hunk.lines.push(HunkLine::Context(text.to_string()));
+            } else if let Some(text) = line.strip_prefix('-') {
+                hunk.lines.push(HunkLine::Remove(text.to_string()));
+            } else if let Some(text) = line.strip_prefix('+') {
+                hunk.lines.push(HunkLine::Add(text.to_string()));
+            } else if line.starts_with('\\') {
+                // "\ No newline at end of file" applies to the preceding line
+                match hunk.lines.last() {
+                    Some(HunkLine::Remove(_)) => hunk.old_no_newline = true,
+                    Some(HunkLine::Add(_)) => hunk.new_no_newline = true,
+                    Some(HunkLine::Context(_)) => {
+                        hunk.old_no_newline = true;
+                        hunk.new_no_newline = true;
+                    }
+                    None => {}
+                }
+            } else if line.is_empty() {
+                // Blank context lines often lose their leading space in transit
+                hunk.lines.push(HunkLine::Context(String::new()));
+            }
+        }
+    }
+
+    if let Some(hunk) = current {
+        hunks.push(hunk);
+    }
+
+    if hunks.is_empty() {
+        return Err(Error::InvalidParam(
+            "Patch does not contain any hunks".to_string(),
+        ));
+    }
+
+    Ok(hunks)
+}
+
+/// Find the position of `old` in `lines`, searching outward from `start`
+/// but never before `min_pos`
+fn search_lines(lines: &[String], old: &[&str], start: isize, min_pos: usize) -> Option<usize> {
+    let max_pos = lines.len().checked_sub(old.len())?;
+    if min_pos > max_pos {
+        return None;
+    }
+
+    let start = start.clamp(min_pos as isize, max_pos as isize) as usize;
+    let matches_at = |pos: usize| {
+        lines[pos..pos + old.len()]
+            .iter()
+            .zip(old)
+            .all(|(line, expected)| line == expected)
+    };
+
+    let span = (start - min_pos).max(max_pos - start);
+    for distance in 0..=span {
+        if start + distance <= max_pos && matches_at(start + distance) {
+            return Some(start + distance);
+        }
+        if distance > 0 && start >= min_pos + distance && matches_at(start - distance) {
+            return Some(start - distance);
+        }
+    }
+
+    None
+}
+
+/// Apply parsed hunks to lines in order, allowing each hunk to shift from its
+/// stated position and to ignore up to `fuzz` context lines at each end.
+/// Hunks that cannot be applied are skipped and reported as failed.
+fn apply_hunks(lines: &mut Vec<String>, hunks: &[Hunk], fuzz: usize) -> Vec<HunkResult> {
+    let mut results = Vec::with_capacity(hunks.len());
+    let mut delta: isize = 0;
+    let mut last_offset: isize = 0;
+    let mut min_pos = 0;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        // A hunk covering no original lines inserts after its start line
+        let stated = if hunk.old_count == 0 {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let expected = stated as isize + delta;
+
+        let mut applied = None;
+        let mut previous_len = None;
+        for level in 0..=fuzz {
+            let (old, new, lead) = hunk.trimmed(level);
+
+            // Once there is no more context to drop, higher levels change nothing
+            if previous_len == Some(old.len()) {
+                break;
+            }
+            previous_len = Some(old.len());
+
+            let start = expected + last_offset + lead as isize;
+            if let Some(pos) = search_lines(lines, &old, start, min_pos) {
+                let new_lines: Vec<String> = new.iter().map(|s| s.to_string()).collect();
+                applied = Some((pos, lead, level, old.len(), new_lines));
                 break;
             }
         }
 
-        // Add the current line
-        new_patched_lines.push(line.clone());
+        // Record a failed hunk and carry on so every hunk gets checked
+        let Some((pos, lead, level, old_len, new_lines)) = applied else {
+            results.push(HunkResult {
+                hunk: index + 1,
+                applied: false,
+                line: expected.max(0) as usize + 1,
+                offset: 0,
+                fuzz: 0,
+            });
+            continue;
+        };
+
+        let offset = pos as isize - lead as isize - expected;
+        let new_len = new_lines.len();
+        let tail = lines.split_off(pos + old_len);
+        lines.truncate(pos);
+        lines.extend(new_lines);
+        lines.extend(tail);
+
+        results.push(HunkResult {
+            hunk: index + 1,
+            applied: true,
+            line: pos.saturating_sub(lead) + 1,
+            offset,
+            fuzz: level,
+        });
+
+        delta += new_len as isize - old_len as isize;
+        last_offset = offset;
+        min_pos = pos + new_len;
     }
 
-    // Add any remaining lines
-    for (_, text) in sorted_adds {
-        new_patched_lines.push(text);
+    results
+}
+
+/// Detect the dominant line ending of text content
+pub(crate) fn detect_line_ending(content: &str) -> &'static str {
+    let crlf = content.matches("\r\n").count();
+    let lf = content.matches('\n').count() - crlf;
+    if crlf > lf { "\r\n" } else { "\n" }
+}
+
+/// Apply a unified diff patch to text content, preserving its line endings
+/// and whether it ends with a newline
+pub(crate) fn apply_unified_patch(
+    content: String,
+    patch_text: &str,
+    fuzz: usize,
+) -> Result<(String, Vec<HunkResult>)> {
+    let hunks = parse_unified_patch(patch_text)?;
+    Ok(apply_parsed_hunks(content, &hunks, fuzz))
+}
+
+/// Convert structured file_diff hunks into patch hunks
+fn convert_diff_hunks(diff_hunks: &[DiffHunk]) -> Result<Vec<Hunk>> {
+    diff_hunks
+        .iter()
+        .map(|diff_hunk| {
+            let mut lines = Vec::with_capacity(diff_hunk.lines.len());
+            for line in &diff_hunk.lines {
+                let text = line.content.trim_end_matches(['\n', '\r']).to_string();
+                lines.push(match line.change_type.as_str() {
+                    "equal" => HunkLine::Context(text),
+                    "delete" => HunkLine::Remove(text),
+                    "insert" => HunkLine::Add(text),
+                    other => {
+                        return Err(Error::InvalidParam(format!(
+                            "Unknown change_type in hunk: {}",
+                            other
+                        )));
+                    }
+                });
+            }
+
+            let old_count = lines
+                .iter()
+                .filter(|line| !matches!(line, HunkLine::Add(_)))
+                .count();
+
+            // A pure insertion goes after the line before its position
+            let old_start = if old_count == 0 {
+                diff_hunk.start1.saturating_sub(1)
+            } else {
+                diff_hunk.start1
+            };
+
+            // file_diff doesn't record missing trailing newlines, so leave that alone
+            Ok(Hunk {
+                old_start,
+                old_count,
+                lines,
+                old_no_newline: false,
+                new_no_newline: false,
+            })
+        })
+        .collect()
+}
+
+/// Apply parsed hunks to text content, preserving its line endings and whether
+/// it ends with a newline
+fn apply_parsed_hunks(content: String, hunks: &[Hunk], fuzz: usize) -> (String, Vec<HunkResult>) {
+    let line_ending = detect_line_ending(&content);
+    let mut trailing_newline = content.is_empty() || content.ends_with('\n');
+
+    // Convert the content to lines for patching
+    let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
+    let results = apply_hunks(&mut lines, hunks, fuzz);
+
+    // An explicit "No newline at end of file" marker overrides the original state
+    for (hunk, result) in hunks.iter().zip(&results) {
+        if let Some(newline) = hunk.newline_at_end().filter(|_| result.applied) {
+            trailing_newline = newline;
+        }
     }
 
-    // Replace the old lines with the new ones
-    *patched_lines = new_patched_lines;
+    // Join lines to create patched content
+    let mut patched = lines.join(line_ending);
+    if trailing_newline && !lines.is_empty() {
+        patched.push_str(line_ending);
+    }
 
-    Ok(())
+    (patched, results)
 }
 
 /// Apply a binary patch (a simple approach using base64)
@@ -268,6 +646,369 @@ fn apply_binary_patch(original: Vec<u8>, patch_text: &str) -> Result<Vec<u8>> {
     Ok(patched)
 }
 
+/// A section of a multi-file patch that targets a single file
+#[derive(Debug)]
+struct FileSection {
+    /// Path from the `---` header (None for /dev/null)
+    old_path: Option<String>,
+    /// Path from the `+++` header (None for /dev/null)
+    new_path: Option<String>,
+    /// Whether the section has a `---`/`+++` header
+    headed: bool,
+    /// Hunks of the section as unified diff text
+    body: String,
+}
+
+/// Extract the path from a `---` or `+++` header line, dropping timestamps
+/// and git's `a/` and `b/` prefixes
+fn parse_header_path(line: &str) -> Option<String> {
+    let path = line[4..].split('\t').next().unwrap_or("").trim();
+    if path == "/dev/null" {
+        return None;
+    }
+
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+
+    Some(path.to_string())
+}
+
+/// Split a multi-file patch into per-file sections. A section starts at a
+/// `diff --git` line or, without one, at its `---` header; git's `rename from`
+/// and `rename to` lines name the paths of renames without changes.
+fn split_multi_file_patch(patch_text: &str) -> Vec<FileSection> {
+    let patch_lines: Vec<&str> = patch_text.lines().collect();
+    let body = hunk_body_lines(&patch_lines);
+    let mut sections: Vec<FileSection> = Vec::new();
+    // Whether the last section started at a `diff --git` line and has not
+    // reached its `---` header yet
+    let mut in_git_header = false;
+
+    for (index, line) in patch_lines.iter().enumerate() {
+        let git_header = sections.last_mut().filter(|_| in_git_header);
+        if line.starts_with("diff --git ") {
+            sections.push(FileSection {
+                old_path: None,
+                new_path: None,
+                headed: false,
+                body: String::new(),
+            });
+            in_git_header = true;
+        } else if !body[index] && is_file_header(&patch_lines, index) {
+            if line.starts_with("--- ") {
+                let old_path = parse_header_path(line);
+                match git_header {
+                    Some(section) => {
+                        section.old_path = old_path;
+                        section.headed = true;
+                    }
+                    None => sections.push(FileSection {
+                        old_path,
+                        new_path: None,
+                        headed: true,
+                        body: String::new(),
+                    }),
+                }
+                in_git_header = false;
+            } else if let Some(section) = sections.last_mut() {
+                section.new_path = parse_header_path(line);
+            }
+        } else if let Some(section) = git_header
+            && (line.starts_with("rename from ") || line.starts_with("rename to "))
+        {
+            if let Some(path) = line.strip_prefix("rename from ") {
+                section.old_path = Some(path.to_string());
+            } else if let Some(path) = line.strip_prefix("rename to ") {
+                section.new_path = Some(path.to_string());
+            }
+        } else if let Some(section) = sections.last_mut() {
+            section.body.push_str(line);
+            section.body.push('\n');
+        }
+    }
+
+    // Git sections that only change a file's mode have nothing to apply
+    sections.retain(|section| {
+        section.headed || section.old_path.is_some() || section.new_path.is_some()
+    });
+    sections
+}
+
+/// Resolve a patch path against the base directory, refusing paths that escape it
+fn resolve_patch_path(base_dir: &Path, relative: &str) -> Result<PathBuf> {
+    let path = Path::new(relative);
+    if path.is_absolute() || path.components().any(|c| matches!(c, Component::ParentDir)) {
+        return Err(Error::OutsideSandbox(format!(
+            "Patch path escapes the base directory: {}",
+            relative
+        )));
+    }
+
+    Ok(base_dir.join(path))
+}
+
+/// Paths a multi-file patch may touch, resolved against the base directory
+/// (paths that escape it are left out, the patch will refuse them anyway)
+#[cfg(feature = "undo")]
+pub(crate) fn multi_file_patch_paths(patch_text: &str, base_dir: &Path) -> Vec<PathBuf> {
+    split_multi_file_patch(patch_text)
+        .into_iter()
+        .flat_map(|section| [section.old_path, section.new_path])
+        .flatten()
+        .filter_map(|relative| resolve_patch_path(base_dir, &relative).ok())
+        .collect()
+}
+
+/// A change to the filesystem planned by a multi-file patch
+enum PlannedChange {
+    /// Write new content to a file
+    Write(PathBuf, String),
+    /// Remove a file
+    Delete(PathBuf),
+    /// Move a file, writing new content at its new path
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+        content: String,
+    },
+}
+
+impl PlannedChange {
+    /// The file the change starts from
+    fn source(&self) -> &Path {
+        match self {
+            PlannedChange::Write(path, _) | PlannedChange::Delete(path) => path,
+            PlannedChange::Rename { from, .. } => from,
+        }
+    }
+}
+
+/// Apply a multi-file patch relative to a base directory. All files are patched
+/// in memory first so nothing is written unless every hunk applies, and the
+/// changes are journaled so an I/O error part way through undoes them.
+async fn apply_multi_file_patch(
+    fs: Arc<dyn Filesystem>,
+    params: &Params,
+    base_dir: &str,
+) -> Result<Output> {
+    if params.patch_type != PatchType::Unified {
+        return Err(Error::InvalidParam(
+            "Multi-file patches must be unified diffs".to_string(),
+        ));
+    }
+
+    let base = PathBuf::from(base_dir);
+    if !fs.metadata(&base).await.is_ok_and(|m| m.is_dir()) {
+        return Err(Error::NotFound(format!(
+            "Base directory not found: {}",
+            base_dir
+        )));
+    }
+
+    let sections = split_multi_file_patch(&params.patch);
+    if sections.is_empty() {
+        return Err(Error::InvalidParam(
+            "Patch does not contain any file headers".to_string(),
+        ));
+    }
+
+    let mut files = Vec::with_capacity(sections.len());
+    let mut changes = Vec::with_capacity(sections.len());
+    // What earlier sections left at the paths they changed (None once a file
+    // is gone), so that sections for the same file build on each other
+    let mut working: HashMap<PathBuf, Option<String>> = HashMap::new();
+
+    for section in sections {
+        let (relative, old_relative, action) = match (section.old_path, section.new_path) {
+            (None, Some(new_path)) => (new_path, None, FileAction::Created),
+            (Some(old_path), None) => (old_path, None, FileAction::Deleted),
+            (Some(old_path), Some(new_path)) if old_path != new_path => {
+                (new_path, Some(old_path), FileAction::Renamed)
+            }
+            (Some(_), Some(new_path)) => (new_path, None, FileAction::Modified),
+            (None, None) => {
+                return Err(Error::InvalidParam(
+                    "Patch section has no file path".to_string(),
+                ));
+            }
+        };
+        let path = resolve_patch_path(&base, &relative)?;
+        let old_path = match &old_relative {
+            Some(old_relative) => Some(resolve_patch_path(&base, old_relative)?),
+            None => None,
+        };
+
+        // The file whose content is patched
+        let source = old_path.as_ref().unwrap_or(&path);
+        let content = match action {
+            FileAction::Created => String::new(),
+            FileAction::Modified | FileAction::Deleted | FileAction::Renamed => {
+                let not_found = || {
+                    let relative = old_relative.as_ref().unwrap_or(&relative);
+                    Error::NotFound(format!("File not found: {}", relative))
+                };
+                match working.get(source) {
+                    Some(content) => content.clone().ok_or_else(not_found)?,
+                    None if fs.metadata(source).await.is_ok_and(|m| m.is_file()) => {
+                        fs.read_to_string(source).await?
+                    }
+                    None => return Err(not_found()),
+                }
+            }
+        };
+        let exists = match working.get(&path) {
+            Some(content) => content.is_some(),
+            None => fs.exists(&path).await,
+        };
+        if matches!(action, FileAction::Created | FileAction::Renamed) && exists {
+            return Err(Error::AlreadyExists(format!(
+                "File to create already exists: {}",
+                relative
+            )));
+        }
+
+        let original_size = content.len() as u64;
+        // A rename without changes has no hunks
+        let (patched_content, hunks) =
+            if action == FileAction::Renamed && !section.body.lines().any(|l| l.starts_with("@@")) {
+                (content, Vec::new())
+            } else {
+                apply_unified_patch(content, &section.body, params.fuzz)?
+            };
+        let clean = hunks.iter().all(|h| h.applied);
+
+        let new_size = match action {
+            FileAction::Deleted => 0,
+            _ => patched_content.len() as u64,
+        };
+
+        changes.push(match (action, old_path) {
+            (FileAction::Deleted, _) => {
+                working.insert(path.clone(), None);
+                PlannedChange::Delete(path)
+            }
+            (FileAction::Renamed, Some(from)) => {
+                working.insert(from.clone(), None);
+                working.insert(path.clone(), Some(patched_content.clone()));
+                PlannedChange::Rename {
+                    from,
+                    to: path,
+                    content: patched_content,
+                }
+            }
+            _ => {
+                working.insert(path.clone(), Some(patched_content.clone()));
+                PlannedChange::Write(path, patched_content)
+            }
+        });
+
+        files.push(FileResult {
+            path: relative,
+            old_path: old_relative,
+            action,
+            original_size,
+            new_size,
+            backup_path: None,
+            hunks,
+            clean,
+        });
+    }
+
+    // Refuse to write anything unless every file applies cleanly
+    if !params.check_only {
+        for file in &files {
+            if let Some(failed) = file.hunks.iter().find(|h| !h.applied) {
+                return Err(Error::InvalidParam(format!(
+                    "Hunk #{} failed to apply to {} at line {}",
+                    failed.hunk, file.path, failed.line
+                )));
+            }
+        }
+
+        let mut journal = Journal::new(fs);
+        // Only the first change to a file backs up what it held before the patch
+        let mut backed_up = HashSet::new();
+        for (change, file) in changes.into_iter().zip(files.iter_mut()) {
+            let create_backup =
+                params.create_backup && backed_up.insert(change.source().to_path_buf());
+            if let Err(e) = apply_change(&mut journal, change, file, create_backup).await {
+                journal.rollback().await;
+                return Err(e.with_context(&format!(
+                    "Patching {} failed, patch rolled back",
+                    file.path
+                )));
+            }
+        }
+        journal.commit().await;
+    }
+
+    Ok(Output {
+        path: base_dir.to_string(),
+        original_size: files.iter().map(|f| f.original_size).sum(),
+        new_size: files.iter().map(|f| f.new_size).sum(),
+        patch_type: params.patch_type,
+        backup_path: None,
+        hunks: Vec::new(),
+        check_only: params.check_only,
+        clean: files.iter().all(|f| f.clean),
+        files,
+    })
+}
+
+/// Make one planned change of a multi-file patch, recording it in `journal`
+async fn apply_change(
+    journal: &mut Journal,
+    change: PlannedChange,
+    file: &mut FileResult,
+    create_backup: bool,
+) -> Result<()> {
+    match change {
+        PlannedChange::Write(path, content) => {
+            if file.action == FileAction::Modified && create_backup {
+                file.backup_path = Some(journaled_backup(journal, &path).await?);
+            }
+            if let Some(parent) = path.parent() {
+                journal.create_dirs(parent).await?;
+            }
+            journal.write(&path, content.as_bytes()).await?;
+        }
+        PlannedChange::Delete(path) => {
+            if create_backup {
+                file.backup_path = Some(journaled_backup(journal, &path).await?);
+            }
+            journal.delete(&path, false).await?;
+        }
+        PlannedChange::Rename { from, to, content } => {
+            if create_backup {
+                file.backup_path = Some(journaled_backup(journal, &from).await?);
+            }
+            if let Some(parent) = to.parent() {
+                journal.create_dirs(parent).await?;
+            }
+            journal.write(&to, content.as_bytes()).await?;
+            journal.delete(&from, false).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy a file to `<path>.bak` through `journal` and return the backup path
+async fn journaled_backup(journal: &mut Journal, path: &Path) -> Result<String> {
+    let backup_path_str = format!("{}.bak", path.display());
+    let content = journal.fs.read(path).await?;
+    journal.write(Path::new(&backup_path_str), &content).await?;
+    Ok(backup_path_str)
+}
+
+/// Copy a file to `<path>.bak` and return the backup path
+async fn backup_file(fs: &dyn Filesystem, path: &Path) -> Result<String> {
+    let backup_path_str = format!("{}.bak", path.display());
+    fs.copy(path, Path::new(&backup_path_str)).await?;
+    Ok(backup_path_str)
+}
+
 #[async_trait]
 impl Tool for FilePatch {
     type Params = Params;
@@ -277,66 +1018,104 @@ impl Tool for FilePatch {
         "file_patch"
     }
 
+    fn description(&self) -> &str {
+        "Apply a patch to a file"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::DESTRUCTIVE
+    }
+
     async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
-        let path = PathBuf::from(&params.path);
+        if !params.hunks.is_empty() && (!params.patch.is_empty() || params.base_dir.is_some()) {
+            return Err(Error::InvalidParam(
+                "Structured hunks cannot be combined with patch text or base_dir".to_string(),
+            ));
+        }
+        if !params.hunks.is_empty() && params.patch_type == PatchType::Binary {
+            return Err(Error::InvalidParam(
+                "Structured hunks only apply to unified patches".to_string(),
+            ));
+        }
 
-        // Check if the file exists
-        if !path.exists() {
-            return Err(Error::InvalidParam(format!(
-                "File not found: {}",
-                params.path
-            )));
+        let fs = vfs::current();
+        if let Some(base_dir) = &params.base_dir {
+            return apply_multi_file_patch(fs, &params, base_dir).await;
         }
 
-        // Check if it's a file (not a directory)
-        let metadata = fs::metadata(&path).await?;
-        if !metadata.is_file() {
-            return Err(Error::InvalidParam(format!(
-                "Path is not a file: {}",
-                params.path
-            )));
+        if params.path.is_empty() {
+            return Err(Error::InvalidParam(
+                "Either path or base_dir must be provided".to_string(),
+            ));
         }
 
+        let path = PathBuf::from(&params.path);
+
+        // Check that the path is an existing file
+        let metadata = paths::require_file(fs.as_ref(), &path).await?;
+
+        // Make sure nobody changed the file since the caller last read it
+        check_unchanged(
+            &path,
+            params.expected_sha256.as_deref(),
+            params.expected_mtime.as_deref(),
+        )
+        .await?;
+
         // Get original file size
         let original_size = metadata.len();
 
-        // Create backup if requested
-        let backup_path = if params.create_backup {
-            let backup_path_str = format!("{}.bak", params.path);
-            let backup_path = PathBuf::from(&backup_path_str);
-            fs::copy(&path, &backup_path).await?;
-            Some(backup_path_str)
-        } else {
-            None
-        };
-
-        // Apply the patch based on patch type
-        match params.patch_type {
+        // Apply the patch in memory based on patch type
+        let (patched_content, hunks) = match params.patch_type {
             PatchType::Unified => {
                 // Read the file as text
-                let content = fs::read_to_string(&path).await.map_err(Error::Io)?;
+                let content = fs.read_to_string(&path).await.map_err(Error::Io)?;
 
-                // Apply the patch
-                let patched_content = apply_unified_patch(content, &params.patch)?;
+                // Apply the patch, from structured hunks if given
+                let (patched_content, hunks) = if params.hunks.is_empty() {
+                    apply_unified_patch(content, &params.patch, params.fuzz)?
+                } else {
+                    let hunks = convert_diff_hunks(&params.hunks)?;
+                    apply_parsed_hunks(content, &hunks, params.fuzz)
+                };
 
-                // Write the patched content back to the file
-                fs::write(&path, patched_content).await?;
+                (patched_content.into_bytes(), hunks)
             }
             PatchType::Binary => {
                 // Read the file as binary
-                let content = fs::read(&path).await?;
+                let content = fs.read(&path).await?;
 
                 // Apply the binary patch
-                let patched_content = apply_binary_patch(content, &params.patch)?;
-
-                // Write the patched content back to the file
-                fs::write(&path, patched_content).await?;
+                (apply_binary_patch(content, &params.patch)?, Vec::new())
             }
+        };
+
+        // Refuse to write a partially applied patch
+        if let Some(failed) = hunks.iter().find(|h| !h.applied && !params.check_only) {
+            return Err(Error::InvalidParam(format!(
+                "Hunk #{} failed to apply at line {}",
+                failed.hunk, failed.line
+            )));
         }
 
-        // Get the new file size
-        let new_metadata = fs::metadata(&path).await?;
-        let new_size = new_metadata.len();
+        let clean = hunks.iter().all(|h| h.applied);
+
+        let new_size = patched_content.len() as u64;
+
+        // Create backup and write the patched content back to the file
+        let backup_path = if params.check_only {
+            None
+        } else {
+            let backup_path = if params.create_backup {
+                Some(backup_file(fs.as_ref(), &path).await?)
+            } else {
+                None
+            };
+
+            fs.write(&path, &patched_content).await?;
+
+            backup_path
+        };
 
         Ok(Output {
             path: params.path,
@@ -344,6 +1123,10 @@ impl Tool for FilePatch {
             new_size,
             patch_type: params.patch_type,
             backup_path,
+            hunks,
+            check_only: params.check_only,
+            clean,
+            files: Vec::new(),
         })
     }
 }
@@ -352,6 +1135,7 @@ impl Tool for FilePatch {
 mod tests {
     use super::*;
     use log::{debug, warn};
+    use tokio::fs;
     use tokio::fs::File;
     use tokio::io::AsyncWriteExt;
 
@@ -420,8 +1204,8 @@ mod tests {
         let params = Params {
             path: test_file.to_string_lossy().to_string(),
             patch: patch.to_string(),
-            patch_type: PatchType::Unified,
             create_backup: true,
+            ..Params::default()
         };
 
         let result = tool.execute(params).await?;
@@ -476,7 +1260,7 @@ mod tests {
             path: test_file.to_string_lossy().to_string(),
             patch: patch.to_string(),
             patch_type: PatchType::Binary,
-            create_backup: false,
+            ..Params::default()
         };
 
         let result = tool.execute(params).await?;
@@ -519,8 +1303,7 @@ mod tests {
         let params = Params {
             path: test_file.to_string_lossy().to_string(),
             patch: patch.to_string(),
-            patch_type: PatchType::Unified,
-            create_backup: false,
+            ..Params::default()
         };
 
         let result = tool.execute(params).await;
@@ -533,4 +1316,440 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_unified_patch_with_offset() -> Result<()> {
+        let content = "A\nB\nC\nD\nE".to_string();
+
+        // The header claims the hunk starts at line 1, but it really starts at line 3
+        let patch = "@@ -1,3 +1,3 @@\n C\n-D\n+X\n E";
+
+        let (patched, hunks) = apply_unified_patch(content, patch, 0)?;
+
+        assert_eq!(patched, "A\nB\nC\nX\nE");
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].offset, 2);
+        assert_eq!(hunks[0].line, 3);
+        assert_eq!(hunks[0].fuzz, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unified_patch_with_fuzz() -> Result<()> {
+        let content = "A\nB\nC\nD\nE".to_string();
+
+        // The leading context line does not match the file
+        let patch = "@@ -2,3 +2,3 @@\n Q\n-C\n+X\n D";
+
+        let (_, hunks) = apply_unified_patch(content.clone(), patch, 0)?;
+        assert!(!hunks[0].applied);
+
+        let (patched, hunks) = apply_unified_patch(content, patch, 1)?;
+
+        assert_eq!(patched, "A\nB\nX\nD\nE");
+        assert_eq!(hunks[0].fuzz, 1);
+        assert_eq!(hunks[0].offset, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_patch_check_only() -> Result<()> {
+        let test_file = std::env::temp_dir().join(format!(
+            "test_file_for_patch_check_{}.txt",
+            chrono::Utc::now().timestamp_millis()
+        ));
+        let content = "Line 1\nLine 2\nLine 3\nLine 4\nLine 5";
+        fs::write(&test_file, content).await?;
+
+        let tool = FilePatch;
+
+        // The first hunk applies, the second does not
+        let patch = r#"@@ -1,2 +1,2 @@
+-Line 1
++First line
+ Line 2
+@@ -4,2 +4,2 @@
+-Line 9
++Ninth line
+ Line 5"#;
+
+        let params = Params {
+            path: test_file.to_string_lossy().to_string(),
+            patch: patch.to_string(),
+            create_backup: true,
+            check_only: true,
+            ..Params::default()
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert!(result.check_only);
+        assert!(!result.clean);
+        assert!(result.backup_path.is_none());
+        assert_eq!(result.hunks.len(), 2);
+        assert!(result.hunks[0].applied);
+        assert!(!result.hunks[1].applied);
+
+        // The file must not have been touched
+        assert_eq!(fs::read_to_string(&test_file).await?, content);
+
+        fs::remove_file(&test_file).await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unified_patch_preserves_crlf() -> Result<()> {
+        let content = "A\r\nB\r\nC\r\n".to_string();
+        let patch = "@@ -1,3 +1,3 @@\n A\n-B\n+X\n C";
+
+        let (patched, _) = apply_unified_patch(content, patch, 0)?;
+
+        assert_eq!(patched, "A\r\nX\r\nC\r\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unified_patch_trailing_newline() -> Result<()> {
+        let patch = "@@ -1,3 +1,3 @@\n A\n-B\n+X\n C";
+
+        // The final newline is kept when present and not added when absent
+        let (patched, _) = apply_unified_patch("A\nB\nC\n".to_string(), patch, 0)?;
+        assert_eq!(patched, "A\nX\nC\n");
+
+        let (patched, _) = apply_unified_patch("A\nB\nC".to_string(), patch, 0)?;
+        assert_eq!(patched, "A\nX\nC");
+
+        // An explicit marker removes the final newline
+        let patch = "@@ -2,2 +2,2 @@\n B\n-C\n+Y\n\\ No newline at end of file";
+        let (patched, _) = apply_unified_patch("A\nB\nC\n".to_string(), patch, 0)?;
+        assert_eq!(patched, "A\nB\nY");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_patch_multi_file() -> Result<()> {
+        let base_dir = std::env::temp_dir().join(format!(
+            "patch_multi_test_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        fs::create_dir_all(base_dir.join("src")).await?;
+        fs::write(base_dir.join("src/lib.txt"), "one\ntwo\nthree\n").await?;
+        fs::write(base_dir.join("old.txt"), "gone\n").await?;
+
+        let tool = FilePatch;
+
+        let patch = r#"diff --git a/src/lib.txt b/src/lib.txt
+--- a/src/lib.txt
++++ b/src/lib.txt
+@@ -1,3 +1,3 @@
+ one
+-two
++TWO
+ three
+diff --git a/new.txt b/new.txt
+new file mode 100644
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1,2 @@
++hello
++world
+diff --git a/old.txt b/old.txt
+deleted file mode 100644
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+"#;
+
+        let params = Params {
+            patch: patch.to_string(),
+            base_dir: Some(base_dir.to_string_lossy().to_string()),
+            ..Params::default()
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert!(result.clean);
+        assert_eq!(result.files.len(), 3);
+        assert_eq!(result.files[0].action, FileAction::Modified);
+        assert_eq!(result.files[1].action, FileAction::Created);
+        assert_eq!(result.files[2].action, FileAction::Deleted);
+
+        assert_eq!(
+            fs::read_to_string(base_dir.join("src/lib.txt")).await?,
+            "one\nTWO\nthree\n"
+        );
+        assert_eq!(
+            fs::read_to_string(base_dir.join("new.txt")).await?,
+            "hello\nworld\n"
+        );
+        assert!(!base_dir.join("old.txt").exists());
+
+        // Paths outside the base directory are rejected
+        let params = Params {
+            patch: "--- a/../escape.txt\n+++ b/../escape.txt\n@@ -1 +1 @@\n-a\n+b\n".to_string(),
+            base_dir: Some(base_dir.to_string_lossy().to_string()),
+            ..Params::default()
+        };
+
+        assert!(matches!(
+            tool.execute(params).await,
+            Err(Error::OutsideSandbox(_))
+        ));
+
+        fs::remove_dir_all(&base_dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_patch_multi_file_renames() -> Result<()> {
+        let memory = Arc::new(
+            vfs::MemoryFs::new()
+                .with_file("/repo/a.txt", "one\ntwo\n")
+                .with_file("/repo/old.txt", "keep\n")
+                .with_file("/repo/moved.txt", "x\ny\n")
+                .with_file("/repo/blocker", "file\n"),
+        );
+        let params = |patch: &str| Params {
+            patch: patch.to_string(),
+            base_dir: Some("/repo".to_string()),
+            ..Params::default()
+        };
+        let names = async || -> Result<Vec<String>> {
+            let mut names: Vec<String> = memory
+                .read_dir(Path::new("/repo"))
+                .await?
+                .into_iter()
+                .map(|entry| entry.path.file_name().unwrap().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            Ok(names)
+        };
+
+        vfs::scope(memory.clone(), async {
+            // A pure rename has no hunks; the other one also changes the file
+            let patch = r#"diff --git a/old.txt b/new.txt
+similarity index 100%
+rename from old.txt
+rename to new.txt
+diff --git a/moved.txt b/sub/moved.txt
+similarity index 50%
+rename from moved.txt
+rename to sub/moved.txt
+--- a/moved.txt
++++ b/sub/moved.txt
+@@ -1,2 +1,2 @@
+ x
+-y
++Y
+"#;
+            let result = FilePatch.execute(params(patch)).await?;
+            assert_eq!(result.files.len(), 2);
+            assert_eq!(result.files[0].action, FileAction::Renamed);
+            assert_eq!(result.files[0].path, "new.txt");
+            assert_eq!(result.files[0].old_path.as_deref(), Some("old.txt"));
+            assert!(result.files[0].hunks.is_empty());
+            assert_eq!(result.files[1].path, "sub/moved.txt");
+            assert_eq!(memory.read(Path::new("/repo/new.txt")).await?, b"keep\n");
+            assert_eq!(memory.read(Path::new("/repo/sub/moved.txt")).await?, b"x\nY\n");
+            assert_eq!(names().await?, ["a.txt", "blocker", "new.txt", "sub"]);
+
+            // A write that fails part way undoes the files already patched
+            let patch = r#"--- a/a.txt
++++ b/a.txt
+@@ -1,2 +1,2 @@
+ one
+-two
++TWO
+--- a/new.txt
++++ b/renamed.txt
+--- /dev/null
++++ b/blocker/new.txt
+@@ -0,0 +1 @@
++new
+"#;
+            let result = FilePatch.execute(params(patch)).await;
+            assert!(matches!(result, Err(Error::Io(_))), "{:?}", result);
+            assert_eq!(memory.read(Path::new("/repo/a.txt")).await?, b"one\ntwo\n");
+            assert_eq!(names().await?, ["a.txt", "blocker", "new.txt", "sub"]);
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_file_patch_multi_file_same_file() -> Result<()> {
+        let memory = Arc::new(vfs::MemoryFs::new().with_file("/repo/a.txt", "a\nb\nc\nd\ne\n"));
+        vfs::scope(memory.clone(), async {
+            // The second section for a.txt applies on top of the first one
+            let patch = r#"--- a/a.txt
++++ b/a.txt
+@@ -1,3 +1,3 @@
+ a
+-b
++B
+ c
+--- a/a.txt
++++ b/a.txt
+@@ -1,4 +1,5 @@
+ a
+-B
++BB
+ c
+-d
++D
++d2
+"#;
+            let params = Params {
+                patch: patch.to_string(),
+                create_backup: true,
+                base_dir: Some("/repo".to_string()),
+                ..Params::default()
+            };
+            let result = FilePatch.execute(params).await?;
+            assert!(result.clean);
+            assert_eq!(result.files.len(), 2);
+            assert_eq!(
+                memory.read(Path::new("/repo/a.txt")).await?,
+                b"a\nBB\nc\nD\nd2\ne\n"
+            );
+            // The backup holds the file from before the patch
+            assert_eq!(
+                memory.read(Path::new("/repo/a.txt.bak")).await?,
+                b"a\nb\nc\nd\ne\n"
+            );
+
+            // A file deleted by an earlier section is gone for later ones
+            let patch = r#"--- a/a.txt
++++ /dev/null
+@@ -1,6 +0,0 @@
+-a
+-BB
+-c
+-D
+-d2
+-e
+--- a/a.txt
++++ b/a.txt
+@@ -1 +1 @@
+-a
++z
+"#;
+            let params = Params {
+                patch: patch.to_string(),
+                base_dir: Some("/repo".to_string()),
+                ..Params::default()
+            };
+            let result = FilePatch.execute(params).await;
+            assert!(matches!(result, Err(Error::NotFound(_))), "{:?}", result);
+            assert!(memory.exists(Path::new("/repo/a.txt")).await);
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_file_patch_hunk_counts() -> Result<()> {
+        // Lines that look like file headers inside a hunk are removed and
+        // added lines, and the blank line after the last hunk isn't context
+        let patch = "@@ -1,3 +1,3 @@\n a\n--- x\n+++ y\n c\n\n";
+        let (patched, results) = apply_unified_patch("a\n-- x\nc\n".to_string(), patch, 0)?;
+        assert_eq!(patched, "a\n++ y\nc\n");
+        assert_eq!(results[0].offset, 0);
+
+        // The same goes for the file sections of a multi-file patch
+        let memory = Arc::new(
+            vfs::MemoryFs::new()
+                .with_file("/repo/a.txt", "a\n-- x\nc\n")
+                .with_file("/repo/b.txt", "b\n"),
+        );
+        vfs::scope(memory.clone(), async {
+            let patch = "--- a/a.txt\n+++ b/a.txt\n@@ -1,3 +1,3 @@\n a\n--- x\n+++ y\n c\n\n--- a/b.txt\n+++ b/b.txt\n@@ -1 +1 @@\n-b\n+B\n";
+            let params = Params {
+                patch: patch.to_string(),
+                base_dir: Some("/repo".to_string()),
+                ..Params::default()
+            };
+            let result = FilePatch.execute(params).await?;
+            assert_eq!(result.files.len(), 2);
+            assert_eq!(memory.read(Path::new("/repo/a.txt")).await?, b"a\n++ y\nc\n");
+            assert_eq!(memory.read(Path::new("/repo/b.txt")).await?, b"B\n");
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_patch_structured_hunks() -> Result<()> {
+        use crate::tools::file_diff::{self, DiffType, FileDiff};
+
+        let test_dir = std::env::temp_dir().join(format!(
+            "patch_hunks_test_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        fs::create_dir_all(&test_dir).await?;
+        let original = test_dir.join("original.txt");
+        let modified = test_dir.join("modified.txt");
+        fs::write(&original, "one\ntwo\nthree\nfour\n").await?;
+        fs::write(&modified, "zero\none\n2\nthree\nfour\nfive\n").await?;
+
+        let diff = FileDiff
+            .execute(file_diff::Params {
+                file1: original.to_string_lossy().to_string(),
+                file2: modified.to_string_lossy().to_string(),
+                content1: None,
+                content2: None,
+                diff_type: DiffType::Unified,
+                context_lines: 3,
+                ignore_whitespace: false,
+                ignore_case: false,
+                ignore_blank_lines: false,
+                ignore_trailing_whitespace: false,
+                content_diffs: false,
+                output_format: file_diff::OutputFormat::Text,
+            })
+            .await?;
+
+        // Send the hunks through JSON, the way a client would
+        let json = serde_json::to_value(&diff.hunks)?;
+        let hunks: Vec<DiffHunk> = serde_json::from_value(json.clone())?;
+
+        // Binary patches have no line hunks
+        let result = FilePatch
+            .execute(Params {
+                path: original.to_string_lossy().to_string(),
+                patch_type: PatchType::Binary,
+                hunks: serde_json::from_value(json)?,
+                ..Params::default()
+            })
+            .await;
+        assert!(matches!(result, Err(Error::InvalidParam(_))));
+        assert_eq!(fs::read_to_string(&original).await?, "one\ntwo\nthree\nfour\n");
+
+        let result = FilePatch
+            .execute(Params {
+                path: original.to_string_lossy().to_string(),
+                hunks,
+                ..Params::default()
+            })
+            .await?;
+
+        assert!(result.clean);
+        assert_eq!(
+            fs::read_to_string(&original).await?,
+            "zero\none\n2\nthree\nfour\nfive\n"
+        );
+
+        fs::remove_dir_all(&test_dir).await?;
+
+        Ok(())
+    }
 }
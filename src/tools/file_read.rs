@@ -2,16 +2,23 @@
 
 use async_trait::async_trait;
 use base64::{Engine as _, engine::general_purpose};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
+use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
-use tokio::fs;
+use std::time::SystemTime;
+use tokio::io::AsyncReadExt;
 
-use super::Tool;
-use crate::{Error, Result};
+use super::{Annotations, Tool};
+use super::image_data;
+use super::precondition::sha256_hex;
+use crate::{Error, Result, paths, vfs};
 
 /// Content type for file reading
-#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum ContentType {
     /// Return file as text (UTF-8)
@@ -19,23 +26,19 @@ pub enum ContentType {
     /// Return file as base64 encoded binary
     Binary,
     /// Auto-detect based on file extension
+    #[default]
     Auto,
 }
 
-impl Default for ContentType {
-    fn default() -> Self {
-        Self::Auto
-    }
-}
-
 /// File read tool
 #[derive(Clone, Copy)]
 pub struct FileRead;
 
 /// Parameters for the file read tool
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Params {
     /// Path of the file to read
+    #[serde(deserialize_with = "crate::roots::path")]
     pub path: String,
 
     /// How to interpret the file content
@@ -53,12 +56,69 @@ pub struct Params {
     /// Whether to include line numbers in the output (only applies to text)
     #[serde(default)]
     pub line_numbers: bool,
+
+    /// Character encoding of the file (e.g. "utf-16le", "latin1"); detected when omitted
+    #[serde(default)]
+    pub encoding: Option<String>,
+
+    /// Only return lines matching this regular expression (only applies to text)
+    #[serde(default)]
+    pub filter_pattern: Option<String>,
+
+    /// Lines of context to include around each filter match
+    #[serde(default)]
+    pub filter_context: usize,
+
+    /// Downscale images so neither side exceeds this many pixels
+    /// (needs the `image-resize` feature; ignored otherwise)
+    #[serde(default)]
+    pub max_dimension: Option<u32>,
+
+    /// Include the SHA-256 of the whole file, for use as `expected_sha256`
+    /// when writing it back
+    #[serde(default)]
+    pub include_hash: bool,
+
+    /// Return the text as `lines`, each with its line number, instead of as
+    /// `content` (only applies to text)
+    #[serde(default)]
+    pub as_lines: bool,
+
+    /// Binary files larger than this many bytes come back as a `chunked`
+    /// handle for `file_read_chunk` instead of as content (default 16 MiB)
+    #[serde(default)]
+    pub chunk_threshold: Option<u64>,
+
+    /// Bytes in each chunk of a chunked file (default 4 MiB)
+    #[serde(default)]
+    pub chunk_size: Option<usize>,
+}
+
+impl Params {
+    /// Read the file at `path`
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            content_type: ContentType::default(),
+            offset: None,
+            limit: None,
+            line_numbers: false,
+            encoding: None,
+            filter_pattern: None,
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
+        }
+    }
 }
 
 /// Output of the file read tool
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Output {
-    /// Content of the file (text or base64 encoded)
+    /// Content of the file (text or base64 encoded; empty if `chunked`)
     pub content: String,
 
     /// Size of the file in bytes
@@ -70,9 +130,124 @@ pub struct Output {
     /// Type of content returned (text or binary)
     pub content_type: ContentType,
 
-    /// Total number of lines in the file (if text and line numbers were requested)
+    /// Total number of lines in the file (if text and line numbers or
+    /// `as_lines` were requested)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub line_count: Option<usize>,
+
+    /// The lines returned, with their numbers (if `as_lines` was set; `content`
+    /// is then empty)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<Vec<Line>>,
+
+    /// Character encoding the text was decoded from (text only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+
+    /// Number of lines that matched `filter_pattern` (if a filter was given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched_lines: Option<usize>,
+
+    /// SHA-256 of the whole file, even when only part of it was returned (if
+    /// `include_hash` was set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
+    /// Dimensions of the image (if the file is an image read as binary)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<ImageInfo>,
+
+    /// How to fetch the file with `file_read_chunk` (if it is binary and over
+    /// `chunk_threshold`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunked: Option<Chunked>,
+}
+
+/// A line of a text file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Line {
+    /// Line number, starting at 1
+    pub number: usize,
+
+    /// Text of the line, without its line ending
+    pub text: String,
+}
+
+/// Details of an image read as binary
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ImageInfo {
+    /// Width in pixels of the returned image
+    pub width: u32,
+
+    /// Height in pixels of the returned image
+    pub height: u32,
+
+    /// Whether the image was downscaled to fit `max_dimension`
+    pub resized: bool,
+}
+
+impl Output {
+    /// Build a vision-model image content block from an image read as binary:
+    /// `{"type": "image", "source": {"type": "base64", "media_type": ..., "data": ...}}`
+    pub fn image_block(&self) -> Option<serde_json::Value> {
+        if self.image.is_none() || self.content_type != ContentType::Binary {
+            return None;
+        }
+
+        Some(serde_json::json!({
+            "type": "image",
+            "source": {
+                "type": "base64",
+                "media_type": self.mime_type,
+                "data": self.content,
+            }
+        }))
+    }
+}
+
+/// Pick the lines in `window` that match `filter`, plus `context` lines around
+/// each match. Returns the selected line indices and the number of matches.
+fn filter_lines(
+    lines: &[&str],
+    window: std::ops::Range<usize>,
+    filter: &Regex,
+    context: usize,
+) -> (Vec<usize>, usize) {
+    let mut selected: Vec<usize> = Vec::new();
+    let mut matches = 0;
+
+    for i in window.clone() {
+        if !filter.is_match(lines[i]) {
+            continue;
+        }
+        matches += 1;
+
+        let from = i.saturating_sub(context).max(window.start);
+        let to = (i + context + 1).min(window.end);
+        let from = match selected.last() {
+            Some(&last) => from.max(last + 1),
+            None => from,
+        };
+        selected.extend(from..to);
+    }
+
+    (selected, matches)
+}
+
+/// Join the selected lines, separating non-adjacent groups with `--` like grep
+fn format_selected(lines: &[&str], selected: &[usize], line_numbers: bool) -> String {
+    let mut output = Vec::with_capacity(selected.len());
+    for (n, &i) in selected.iter().enumerate() {
+        if n > 0 && selected[n - 1] + 1 != i {
+            output.push("--".to_string());
+        }
+        if line_numbers {
+            output.push(format!("{:>6}  {}", i + 1, lines[i]));
+        } else {
+            output.push(lines[i].to_string());
+        }
+    }
+    output.join("\n")
 }
 
 /// Guess the MIME type from a file extension
@@ -105,6 +280,116 @@ fn guess_mime_type(path: &Path) -> String {
     }
 }
 
+/// Number of leading bytes inspected when sniffing content
+const SNIFF_LEN: usize = 8192;
+
+/// Size above which binary files are handed out in chunks
+const DEFAULT_CHUNK_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Bytes in each chunk of a chunked file
+const DEFAULT_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// How `file_read` hands out a file in chunks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Chunked {
+    /// Pass to `file_read_chunk` along with each chunk's index
+    pub handle: String,
+
+    /// Bytes in each chunk; the last one may be shorter
+    pub chunk_size: usize,
+
+    /// Number of chunks
+    pub chunks: u64,
+}
+
+/// What a handle stands for: a file, the version of it that was read and how
+/// it is cut up
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Handle {
+    pub(crate) path: String,
+    pub(crate) size: u64,
+    pub(crate) modified: Option<SystemTime>,
+    pub(crate) chunk_size: usize,
+}
+
+impl Chunked {
+    /// Chunks of `chunk_size` bytes of the file at `path` as it is now
+    pub(crate) fn new(path: &Path, metadata: &vfs::Metadata, chunk_size: usize) -> Self {
+        let handle = Handle {
+            path: paths::to_string(path),
+            size: metadata.len(),
+            modified: metadata.modified,
+            chunk_size,
+        };
+        let json = serde_json::to_vec(&handle).expect("a handle always serializes");
+        Self {
+            handle: general_purpose::URL_SAFE_NO_PAD.encode(json),
+            chunk_size,
+            chunks: metadata.len().div_ceil(chunk_size as u64),
+        }
+    }
+}
+
+#[cfg(feature = "file-read-chunk")]
+impl Handle {
+    pub(crate) fn decode(handle: &str) -> Result<Self> {
+        general_purpose::URL_SAFE_NO_PAD
+            .decode(handle.trim())
+            .ok()
+            .and_then(|json| serde_json::from_slice::<Self>(&json).ok())
+            .filter(|handle| handle.chunk_size > 0)
+            .ok_or_else(|| {
+                Error::InvalidParam(
+                    "Invalid handle; use the chunked.handle returned by file_read".to_string(),
+                )
+            })
+    }
+
+    pub(crate) fn changed(&self) -> Error {
+        Error::Conflict(format!(
+            "File {} has changed since it was read; read it again with file_read",
+            self.path
+        ))
+    }
+}
+
+/// Check whether content looks like text: a BOM, or valid UTF-8 without NUL bytes
+fn looks_like_text(bytes: &[u8]) -> bool {
+    if Encoding::for_bom(bytes).is_some() {
+        return true;
+    }
+
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    if sample.contains(&0) {
+        return false;
+    }
+
+    match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        // A multi-byte sequence cut off by the sample boundary is still fine
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// Detect the MIME type from file content, using the extension as a tiebreaker
+fn detect_mime_type(path: &Path, bytes: &[u8]) -> String {
+    // Magic numbers identify most binary formats reliably
+    if let Some(kind) =
+        infer::get(bytes).filter(|kind| kind.matcher_type() != infer::MatcherType::Text)
+    {
+        return kind.mime_type().to_string();
+    }
+
+    let by_extension = guess_mime_type(path);
+    let extension_is_text = is_text_mime_type(&by_extension) || by_extension == "image/svg+xml";
+
+    match (looks_like_text(bytes), extension_is_text) {
+        (true, true) | (false, false) => by_extension,
+        (true, false) => "text/plain".to_string(),
+        (false, true) => "application/octet-stream".to_string(),
+    }
+}
+
 /// Determine if a file should be read as text based on MIME type
 fn is_text_mime_type(mime_type: &str) -> bool {
     mime_type.starts_with("text/")
@@ -113,6 +398,81 @@ fn is_text_mime_type(mime_type: &str) -> bool {
         || mime_type == "application/javascript"
 }
 
+/// Guess UTF-16 without a BOM from the share of zero bytes in even or odd positions
+fn detect_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    if bytes.len() < 4 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let pairs = bytes.len() / 2;
+    let even_zeros = bytes.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_zeros = bytes.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+
+    // Mostly-ASCII UTF-16 text has a zero in every other byte
+    if odd_zeros * 10 >= pairs * 9 && even_zeros == 0 {
+        Some(UTF_16LE)
+    } else if even_zeros * 10 >= pairs * 9 && odd_zeros == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Detect the encoding of raw bytes from a BOM, UTF-8 validity and content heuristics
+fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+
+    if let Some(encoding) = detect_utf16(bytes) {
+        return encoding;
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+/// Decode raw bytes to UTF-8 text, using the given encoding label or detecting it.
+/// Returns the text and the name of the encoding that was used.
+fn decode_text(bytes: &[u8], label: Option<&str>) -> Result<(String, &'static str)> {
+    let encoding = match label {
+        Some(label) => Encoding::for_label(label.trim().as_bytes())
+            .ok_or_else(|| Error::InvalidParam(format!("Unknown encoding: {}", label)))?,
+        None => detect_encoding(bytes),
+    };
+
+    // decode() strips a BOM and switches encoding if one is present
+    let (text, used, had_errors) = encoding.decode(bytes);
+    if had_errors {
+        log::warn!(
+            "File is not valid {}, malformed sequences were replaced",
+            used.name()
+        );
+    }
+
+    Ok((text.into_owned(), used.name()))
+}
+
+/// SHA-256 of a file, read a piece at a time
+async fn sha256_stream(fs: &dyn vfs::Filesystem, path: &Path) -> Result<String> {
+    let mut reader = fs.open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
 #[async_trait]
 impl Tool for FileRead {
     type Params = Params;
@@ -122,31 +482,79 @@ impl Tool for FileRead {
         "file_read"
     }
 
+    fn description(&self) -> &str {
+        "Read a file from the filesystem"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
     async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
         let path = PathBuf::from(&params.path);
+        let fs = vfs::for_path(&path)?;
 
-        // Check if the file exists
-        if !path.exists() {
-            return Err(Error::InvalidParam(format!(
-                "File not found: {}",
-                params.path
-            )));
-        }
-
-        // Check if it's a file (not a directory)
-        let metadata = fs::metadata(&path).await?;
-        if !metadata.is_file() {
-            return Err(Error::InvalidParam(format!(
-                "Path is not a file: {}",
-                params.path
-            )));
-        }
+        // Check that the path is an existing file
+        let metadata = paths::require_file(fs.as_ref(), &path).await?;
 
         // Get file size
         let size = metadata.len();
 
-        // Determine MIME type
-        let mime_type = guess_mime_type(&path);
+        if params.max_dimension == Some(0) {
+            return Err(Error::InvalidParam(
+                "max_dimension must be at least 1".to_string(),
+            ));
+        }
+        if params.chunk_size == Some(0) {
+            return Err(Error::InvalidParam(
+                "chunk_size must be at least 1".to_string(),
+            ));
+        }
+
+        // Compile the filter before doing any work
+        let filter = params
+            .filter_pattern
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| {
+                    Error::InvalidParam(format!("Invalid filter pattern '{}': {}", pattern, e))
+                })
+            })
+            .transpose()?;
+
+        // Hand large binary files out in chunks instead of reading them whole
+        if size > params.chunk_threshold.unwrap_or(DEFAULT_CHUNK_THRESHOLD)
+            && params.content_type != ContentType::Text
+        {
+            let head = fs.read_range(&path, 0, SNIFF_LEN).await?;
+            let mime_type = detect_mime_type(&path, &head);
+            if params.content_type == ContentType::Binary || !is_text_mime_type(&mime_type) {
+                let sha256 = if params.include_hash {
+                    Some(sha256_stream(fs.as_ref(), &path).await?)
+                } else {
+                    None
+                };
+                let chunk_size = params.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+                return Ok(Output {
+                    content: String::new(),
+                    size,
+                    mime_type,
+                    content_type: ContentType::Binary,
+                    line_count: None,
+                    lines: None,
+                    encoding: None,
+                    matched_lines: None,
+                    sha256,
+                    image: None,
+                    chunked: Some(Chunked::new(&path, &metadata, chunk_size)),
+                });
+            }
+        }
+
+        // Read the file and determine its MIME type from the content
+        let bytes = fs.read(&path).await?;
+        let mut mime_type = detect_mime_type(&path, &bytes);
+        let sha256 = params.include_hash.then(|| sha256_hex(&bytes));
 
         // Determine content type based on params and MIME type
         let effective_content_type = match params.content_type {
@@ -163,19 +571,70 @@ impl Tool for FileRead {
 
         match effective_content_type {
             ContentType::Text => {
-                // Read the file as text
-                let content = fs::read_to_string(&path).await.map_err(Error::Io)?;
+                // Decode the file as text
+                let (content, encoding) = decode_text(&bytes, params.encoding.as_deref())?;
 
                 // Process line numbers if requested
                 let lines: Vec<&str> = content.lines().collect();
-                let line_count = if params.line_numbers {
+                let line_count = if params.line_numbers || params.as_lines {
                     Some(lines.len())
                 } else {
                     None
                 };
 
-                // Apply offset and limit if specified
-                let processed_content = if params.offset.is_some() || params.limit.is_some() {
+                // Apply the filter within the offset/limit window, if one was given
+                let mut matched_lines = None;
+
+                // Numbered lines leave no doubt where each line starts and ends
+                if params.as_lines {
+                    let start = params.offset.unwrap_or(0).min(lines.len());
+                    let end = match params.limit {
+                        Some(limit) => (start + limit).min(lines.len()),
+                        None => lines.len(),
+                    };
+                    let selected: Vec<usize> = match &filter {
+                        Some(filter) => {
+                            let (selected, matches) =
+                                filter_lines(&lines, start..end, filter, params.filter_context);
+                            matched_lines = Some(matches);
+                            selected
+                        }
+                        None => (start..end).collect(),
+                    };
+                    let numbered = selected
+                        .into_iter()
+                        .map(|i| Line {
+                            number: i + 1,
+                            text: lines[i].to_string(),
+                        })
+                        .collect();
+
+                    return Ok(Output {
+                        content: String::new(),
+                        size,
+                        mime_type,
+                        content_type: ContentType::Text,
+                        line_count,
+                        lines: Some(numbered),
+                        encoding: Some(encoding.to_string()),
+                        matched_lines,
+                        sha256,
+                        image: None,
+                        chunked: None,
+                    });
+                }
+                let processed_content = if let Some(filter) = &filter {
+                    let start = params.offset.unwrap_or(0).min(lines.len());
+                    let end = match params.limit {
+                        Some(limit) => (start + limit).min(lines.len()),
+                        None => lines.len(),
+                    };
+                    let (selected, matches) =
+                        filter_lines(&lines, start..end, filter, params.filter_context);
+                    matched_lines = Some(matches);
+                    format_selected(&lines, &selected, params.line_numbers)
+                } else if params.offset.is_some() || params.limit.is_some() {
+                    // Apply offset and limit if specified
                     let offset = params.offset.unwrap_or(0);
                     let limit = params.limit.unwrap_or(lines.len().saturating_sub(offset));
 
@@ -216,14 +675,45 @@ impl Tool for FileRead {
                     mime_type,
                     content_type: ContentType::Text,
                     line_count,
+                    lines: None,
+                    encoding: Some(encoding.to_string()),
+                    matched_lines,
+                    sha256,
+                    image: None,
+                    chunked: None,
                 })
             }
             ContentType::Binary => {
-                // Read the file as binary
-                let content = fs::read(&path).await?;
+                let mut bytes = bytes;
+
+                // Report image dimensions, shrinking the image first if requested
+                let mut image = None;
+                if mime_type.starts_with("image/")
+                    && mime_type != "image/svg+xml"
+                    && let Some((width, height)) = image_data::dimensions(&bytes)
+                {
+                    let mut info = ImageInfo {
+                        width,
+                        height,
+                        resized: false,
+                    };
+                    if let Some(max) = params.max_dimension
+                        && (width > max || height > max)
+                        && let Some(small) = image_data::downscale(&bytes, max)?
+                    {
+                        info = ImageInfo {
+                            width: small.width,
+                            height: small.height,
+                            resized: true,
+                        };
+                        mime_type = small.mime_type;
+                        bytes = small.bytes;
+                    }
+                    image = Some(info);
+                }
 
                 // Encode as base64
-                let base64_content = general_purpose::STANDARD.encode(&content);
+                let base64_content = general_purpose::STANDARD.encode(&bytes);
 
                 Ok(Output {
                     content: base64_content,
@@ -231,6 +721,12 @@ impl Tool for FileRead {
                     mime_type,
                     content_type: ContentType::Binary,
                     line_count: None,
+                    lines: None,
+                    encoding: None,
+                    matched_lines: None,
+                    sha256,
+                    image,
+                    chunked: None,
                 })
             }
             ContentType::Auto => {
@@ -285,6 +781,14 @@ mod tests {
             offset: None,
             limit: None,
             line_numbers: false,
+            encoding: None,
+            filter_pattern: None,
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
         };
 
         let result = tool.execute(params).await?;
@@ -292,6 +796,7 @@ mod tests {
         assert_eq!(result.content, "Line 1\nLine 2\nLine 3\nLine 4\nLine 5");
         assert_eq!(result.content_type, ContentType::Text);
         assert!(result.line_count.is_none());
+        assert!(result.sha256.is_none());
 
         // Get the actual size from the file
         let metadata = tokio::fs::metadata(&test_file).await?;
@@ -304,6 +809,14 @@ mod tests {
             offset: None,
             limit: None,
             line_numbers: true,
+            encoding: None,
+            filter_pattern: None,
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
         };
 
         let result = tool.execute(params).await?;
@@ -321,11 +834,24 @@ mod tests {
             offset: Some(1),
             limit: Some(2),
             line_numbers: false,
+            encoding: None,
+            filter_pattern: None,
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: true,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
         };
 
         let result = tool.execute(params).await?;
 
         assert_eq!(result.content, "Line 2\nLine 3");
+        // The hash covers the whole file, not just the lines returned
+        assert_eq!(
+            result.sha256.as_deref(),
+            Some(sha256_hex(b"Line 1\nLine 2\nLine 3\nLine 4\nLine 5").as_str())
+        );
 
         // Clean up
         tokio::fs::remove_file(test_file).await?;
@@ -346,6 +872,14 @@ mod tests {
             offset: None,
             limit: None,
             line_numbers: false,
+            encoding: None,
+            filter_pattern: None,
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
         };
 
         let result = tool.execute(params).await?;
@@ -354,6 +888,7 @@ mod tests {
         assert_eq!(result.content, "AAECAwQFBgcICQ==");
         assert_eq!(result.content_type, ContentType::Binary);
         assert!(result.line_count.is_none());
+        assert!(result.sha256.is_none());
 
         // Get the actual size from the file
         let metadata = tokio::fs::metadata(&test_file).await?;
@@ -366,6 +901,14 @@ mod tests {
             offset: None,
             limit: None,
             line_numbers: false,
+            encoding: None,
+            filter_pattern: None,
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
         };
 
         let result = tool.execute(params).await?;
@@ -389,18 +932,400 @@ mod tests {
             offset: None,
             limit: None,
             line_numbers: false,
+            encoding: None,
+            filter_pattern: None,
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
         };
 
         let result = tool.execute(params).await;
 
         assert!(result.is_err());
 
-        if let Err(Error::InvalidParam(msg)) = result {
+        if let Err(Error::NotFound(msg)) = result {
             assert!(msg.contains("File not found"));
         } else {
-            panic!("Expected InvalidParam error");
+            panic!("Expected NotFound error");
         }
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_file_read_encodings() -> Result<()> {
+        let test_dir = std::env::temp_dir().join(format!(
+            "read_encoding_test_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        tokio::fs::create_dir_all(&test_dir).await?;
+
+        let tool = FileRead;
+
+        // UTF-16LE with a byte order mark is detected and transcoded
+        let utf16_file = test_dir.join("utf16.txt");
+        tokio::fs::write(&utf16_file, [0xFF, 0xFE, b'h', 0, b'i', 0]).await?;
+
+        let params = Params {
+            path: utf16_file.to_string_lossy().to_string(),
+            content_type: ContentType::Text,
+            offset: None,
+            limit: None,
+            line_numbers: false,
+            encoding: None,
+            filter_pattern: None,
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert_eq!(result.content, "hi");
+        assert_eq!(result.encoding.as_deref(), Some("UTF-16LE"));
+
+        // Latin-1 content can be decoded with an explicit encoding
+        let latin1_file = test_dir.join("latin1.txt");
+        tokio::fs::write(&latin1_file, b"caf\xe9").await?;
+
+        let params = Params {
+            path: latin1_file.to_string_lossy().to_string(),
+            content_type: ContentType::Text,
+            offset: None,
+            limit: None,
+            line_numbers: false,
+            encoding: Some("latin1".to_string()),
+            filter_pattern: None,
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert_eq!(result.content, "caf\u{e9}");
+
+        // Unknown encodings are rejected
+        let params = Params {
+            path: latin1_file.to_string_lossy().to_string(),
+            content_type: ContentType::Text,
+            offset: None,
+            limit: None,
+            line_numbers: false,
+            encoding: Some("not-an-encoding".to_string()),
+            filter_pattern: None,
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
+        };
+
+        assert!(tool.execute(params).await.is_err());
+
+        tokio::fs::remove_dir_all(&test_dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_read_filter() -> Result<()> {
+        let test_file = std::env::temp_dir().join(format!(
+            "test_file_read_filter_{}_{}.log",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        tokio::fs::write(
+            &test_file,
+            "start\nok 1\nERROR one\nok 2\nok 3\nok 4\nok 5\nERROR two\nend\n",
+        )
+        .await?;
+
+        let tool = FileRead;
+        let params = Params {
+            path: test_file.to_string_lossy().to_string(),
+            content_type: ContentType::Text,
+            offset: None,
+            limit: None,
+            line_numbers: true,
+            encoding: None,
+            filter_pattern: Some("^ERROR".to_string()),
+            filter_context: 1,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert_eq!(result.matched_lines, Some(2));
+        assert_eq!(
+            result.content,
+            "     2  ok 1\n     3  ERROR one\n     4  ok 2\n--\n     7  ok 5\n     8  ERROR two\n     9  end"
+        );
+
+        // The filter only looks inside the offset/limit window
+        let params = Params {
+            path: test_file.to_string_lossy().to_string(),
+            content_type: ContentType::Text,
+            offset: Some(4),
+            limit: None,
+            line_numbers: false,
+            encoding: None,
+            filter_pattern: Some("ERROR".to_string()),
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert_eq!(result.matched_lines, Some(1));
+        assert_eq!(result.content, "ERROR two");
+
+        // Lines come back numbered, with gaps where the filter skipped some
+        let params = Params {
+            filter_pattern: Some("^ERROR".to_string()),
+            filter_context: 1,
+            as_lines: true,
+            ..Params::new(test_file.to_string_lossy())
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert!(result.content.is_empty());
+        assert_eq!(result.line_count, Some(9));
+        let lines = result.lines.unwrap();
+        let numbers: Vec<usize> = lines.iter().map(|line| line.number).collect();
+        assert_eq!(numbers, [2, 3, 4, 7, 8, 9]);
+        assert_eq!(
+            lines[1],
+            Line {
+                number: 3,
+                text: "ERROR one".to_string()
+            }
+        );
+
+        let params = Params {
+            offset: Some(7),
+            as_lines: true,
+            ..Params::new(test_file.to_string_lossy())
+        };
+        let lines = tool.execute(params).await?.lines.unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].number, 8);
+        assert_eq!(lines[1].text, "end");
+
+        // Invalid patterns are rejected
+        let params = Params {
+            path: test_file.to_string_lossy().to_string(),
+            content_type: ContentType::Text,
+            offset: None,
+            limit: None,
+            line_numbers: false,
+            encoding: None,
+            filter_pattern: Some("(".to_string()),
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
+        };
+
+        assert!(matches!(
+            tool.execute(params).await,
+            Err(Error::InvalidParam(_))
+        ));
+
+        tokio::fs::remove_file(&test_file).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_read_include_hash() -> Result<()> {
+        let blob: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+        let fs = std::sync::Arc::new(
+            vfs::MemoryFs::new()
+                .with_file("/notes.txt", "one\ntwo\nthree\n")
+                .with_file("/blob.bin", blob.clone()),
+        );
+        vfs::scope(fs, async {
+            // No hash unless asked for
+            let result = FileRead.execute(Params::new("/notes.txt")).await?;
+            assert!(result.sha256.is_none());
+
+            // A single line still hashes the whole file
+            let params = Params {
+                offset: Some(2),
+                limit: Some(1),
+                include_hash: true,
+                ..Params::new("/notes.txt")
+            };
+            let result = FileRead.execute(params).await?;
+            assert_eq!(result.content, "three");
+            assert_eq!(
+                result.sha256.as_deref(),
+                Some(sha256_hex(b"one\ntwo\nthree\n").as_str())
+            );
+
+            // Chunked binary files are hashed without being returned
+            let params = Params {
+                include_hash: true,
+                chunk_threshold: Some(1024),
+                ..Params::new("/blob.bin")
+            };
+            let result = FileRead.execute(params).await?;
+            assert!(result.chunked.is_some());
+            assert_eq!(result.sha256, Some(sha256_hex(&blob)));
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_file_read_as_lines() -> Result<()> {
+        let fs = std::sync::Arc::new(
+            vfs::MemoryFs::new().with_file("/code.rs", "fn main() {\n\n    let x = 1;\t\n}\n"),
+        );
+        vfs::scope(fs, async {
+            let params = Params {
+                as_lines: true,
+                ..Params::new("/code.rs")
+            };
+            let result = FileRead.execute(params).await?;
+            assert!(result.content.is_empty());
+            assert_eq!(result.line_count, Some(4));
+            // Blank lines and surrounding whitespace come back as they are
+            let texts: Vec<&str> = result
+                .lines
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|line| line.text.as_str())
+                .collect();
+            assert_eq!(texts, ["fn main() {", "", "    let x = 1;\t", "}"]);
+
+            // Numbers count from the start of the file, not of the window
+            let params = Params {
+                offset: Some(1),
+                limit: Some(2),
+                as_lines: true,
+                ..Params::new("/code.rs")
+            };
+            let lines = FileRead.execute(params).await?.lines.unwrap();
+            assert_eq!(
+                lines,
+                [
+                    Line {
+                        number: 2,
+                        text: String::new()
+                    },
+                    Line {
+                        number: 3,
+                        text: "    let x = 1;\t".to_string()
+                    }
+                ]
+            );
+
+            // Without the option the content is a single string as before
+            let result = FileRead.execute(Params::new("/code.rs")).await?;
+            assert!(result.lines.is_none());
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_file_read_image() -> Result<()> {
+        let test_file = std::env::temp_dir().join(format!(
+            "test_file_read_image_{}_{}.bin",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+
+        // A PNG header is enough for the type and dimensions to be sniffed
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        png.extend_from_slice(&13u32.to_be_bytes());
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&320u32.to_be_bytes());
+        png.extend_from_slice(&200u32.to_be_bytes());
+        png.extend_from_slice(&[8, 6, 0, 0, 0]);
+        tokio::fs::write(&test_file, &png).await?;
+
+        let params = Params {
+            path: test_file.to_string_lossy().to_string(),
+            content_type: ContentType::Auto,
+            offset: None,
+            limit: None,
+            line_numbers: false,
+            encoding: None,
+            filter_pattern: None,
+            filter_context: 0,
+            max_dimension: None,
+            include_hash: false,
+            as_lines: false,
+            chunk_threshold: None,
+            chunk_size: None,
+        };
+
+        let result = FileRead.execute(params).await?;
+
+        assert_eq!(result.mime_type, "image/png");
+        assert_eq!(result.content_type, ContentType::Binary);
+        let image = result.image.as_ref().expect("image info");
+        assert_eq!((image.width, image.height), (320, 200));
+        assert!(!image.resized);
+
+        let block = result.image_block().expect("image block");
+        assert_eq!(block["type"], "image");
+        assert_eq!(block["source"]["media_type"], "image/png");
+        assert_eq!(block["source"]["data"], result.content.as_str());
+
+        tokio::fs::remove_file(&test_file).await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_mime_type() {
+        // Extensionless scripts are recognised as text
+        assert_eq!(
+            detect_mime_type(Path::new("run"), b"#!/bin/sh\necho hi\n"),
+            "text/plain"
+        );
+
+        // Magic numbers win over the extension
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0];
+        assert_eq!(detect_mime_type(Path::new("image.txt"), &png), "image/png");
+
+        // The extension refines text content
+        assert_eq!(
+            detect_mime_type(Path::new("icon.svg"), b"<svg></svg>"),
+            "image/svg+xml"
+        );
+
+        // Binary content is never reported as text
+        assert_eq!(
+            detect_mime_type(Path::new("data.txt"), &[0, 1, 2, 3]),
+            "application/octet-stream"
+        );
+    }
 }
@@ -0,0 +1,168 @@
+//! File read chunk tool implementation
+//!
+//! `file_read` doesn't return binary files over its `chunk_threshold` whole.
+//! It returns a `chunked` handle instead, and this tool fetches the file one
+//! base64 chunk at a time, so large artifacts can be transferred without
+//! holding the whole file, or one giant response, in memory. Each call reads
+//! only its own range of the file.
+//!
+//! The handle records the file's size and modification time; a chunk of a file
+//! that has changed since fails with a conflict, so the pieces of a transfer
+//! always come from the same version of the file.
+
+use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::path::Path;
+
+use super::file_read::Handle;
+use super::{Annotations, Tool};
+use crate::{Error, Result, paths, vfs};
+
+/// File read chunk tool
+#[derive(Clone, Copy)]
+pub struct FileReadChunk;
+
+/// Parameters for the file read chunk tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// The `chunked.handle` returned by `file_read`
+    pub handle: String,
+
+    /// Index of the chunk to read, starting at 0
+    #[serde(default)]
+    pub index: u64,
+}
+
+impl Params {
+    /// Read chunk `index` of the file behind `handle`
+    pub fn new(handle: impl Into<String>, index: u64) -> Self {
+        Self {
+            handle: handle.into(),
+            index,
+        }
+    }
+}
+
+/// Output of the file read chunk tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Base64 encoded bytes of the chunk
+    pub content: String,
+
+    /// Index of the chunk
+    pub index: u64,
+
+    /// Position of the chunk's first byte in the file
+    pub offset: u64,
+
+    /// Number of bytes in the chunk
+    pub length: usize,
+
+    /// Whether this is the last chunk
+    pub eof: bool,
+}
+
+#[async_trait]
+impl Tool for FileReadChunk {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "file_read_chunk"
+    }
+
+    fn description(&self) -> &str {
+        "Read one base64 chunk of a large file returned by file_read as a chunked handle"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let handle = Handle::decode(&params.handle)?;
+        let path = Path::new(&handle.path);
+        let fs = vfs::for_path(path)?;
+
+        let chunks = handle.size.div_ceil(handle.chunk_size as u64);
+        if params.index >= chunks {
+            return Err(Error::InvalidParam(format!(
+                "Chunk index {} is out of range; {} has {} chunks",
+                params.index, handle.path, chunks
+            )));
+        }
+
+        let metadata = paths::require_file(fs.as_ref(), path).await?;
+        if metadata.len() != handle.size || metadata.modified != handle.modified {
+            return Err(handle.changed());
+        }
+
+        let offset = params.index * handle.chunk_size as u64;
+        let expected = (handle.size - offset).min(handle.chunk_size as u64) as usize;
+        let bytes = fs.read_range(path, offset, handle.chunk_size).await?;
+        if bytes.len() != expected {
+            return Err(handle.changed());
+        }
+
+        Ok(Output {
+            content: general_purpose::STANDARD.encode(&bytes),
+            index: params.index,
+            offset,
+            length: bytes.len(),
+            eof: params.index + 1 == chunks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::file_read::{self, ContentType, FileRead};
+    use crate::vfs::{Filesystem, MemoryFs};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_file_read_chunk() -> Result<()> {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let fs = Arc::new(MemoryFs::new().with_file("/big.bin", data.clone()));
+        vfs::scope(fs.clone(), async {
+            let params = file_read::Params {
+                chunk_threshold: Some(512),
+                chunk_size: Some(300),
+                ..file_read::Params::new("/big.bin")
+            };
+            let output = FileRead.execute(params).await?;
+            assert_eq!(output.content_type, ContentType::Binary);
+            assert!(output.content.is_empty());
+            let chunked = output.chunked.expect("file is over the threshold");
+            assert_eq!(chunked.chunk_size, 300);
+            assert_eq!(chunked.chunks, 4);
+
+            // The chunks put back together are the file
+            let mut joined = Vec::new();
+            for index in 0..chunked.chunks {
+                let chunk = FileReadChunk
+                    .execute(Params::new(&chunked.handle, index))
+                    .await?;
+                assert_eq!(chunk.offset, index * 300);
+                assert_eq!(chunk.eof, index == 3);
+                joined.extend(general_purpose::STANDARD.decode(&chunk.content).unwrap());
+            }
+            assert_eq!(joined, data);
+
+            let result = FileReadChunk.execute(Params::new(&chunked.handle, 4)).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+            let result = FileReadChunk.execute(Params::new("not a handle", 0)).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+
+            // A changed file can't be read with the old handle
+            fs.write(Path::new("/big.bin"), &data[..900]).await?;
+            let result = FileReadChunk.execute(Params::new(&chunked.handle, 0)).await;
+            assert!(matches!(result, Err(Error::Conflict(_))));
+            Ok(())
+        })
+        .await
+    }
+}
@@ -4,36 +4,35 @@ use async_trait::async_trait;
 use base64::{Engine as _, engine::general_purpose};
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
-use std::path::PathBuf;
-use tokio::fs;
+use std::path::{Path, PathBuf};
 
-use super::Tool;
+use super::{Annotations, Tool};
+use super::mode::parse_mode;
+use super::precondition::{check_unchanged, sha256_hex};
+use crate::logging;
+use crate::vfs::{self, Filesystem};
 use crate::{Error, Result};
 
 /// Content type for file writing
-#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum ContentType {
     /// Content is provided as text (UTF-8)
+    #[default]
     Text,
     /// Content is provided as base64 encoded binary
     Binary,
 }
 
-impl Default for ContentType {
-    fn default() -> Self {
-        Self::Text
-    }
-}
-
 /// File write tool
 #[derive(Clone, Copy)]
 pub struct FileWrite;
 
 /// Parameters for the file write tool
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Params {
     /// Path of the file to write
+    #[serde(deserialize_with = "crate::roots::path")]
     pub path: String,
 
     /// Content to write to the file
@@ -46,10 +45,51 @@ pub struct Params {
     /// Whether to create parent directories if they don't exist
     #[serde(default)]
     pub create_dirs: bool,
+
+    /// Permissions to set on the file as an octal string, e.g. "644" (Unix only)
+    #[serde(default)]
+    pub mode: Option<String>,
+
+    /// Make the file executable wherever it is readable, like `chmod +x` (Unix only)
+    #[serde(default)]
+    pub set_executable: bool,
+
+    /// Only write if the existing file still has this SHA-256 digest (hex)
+    #[serde(default)]
+    pub expected_sha256: Option<String>,
+
+    /// Only write if the existing file still has this modification time
+    /// (RFC 3339, compared to the second)
+    #[serde(default)]
+    pub expected_mtime: Option<String>,
+
+    /// SHA-256 digest (hex) the content must have once decoded; it is checked
+    /// before writing and again on a flushed copy next to the file, which
+    /// only then replaces it, to catch payloads that were cut off or corrupted
+    /// on the way
+    #[serde(default)]
+    pub content_sha256: Option<String>,
+}
+
+impl Params {
+    /// Write `content` to the file at `path`
+    pub fn new(path: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            content: content.into(),
+            content_type: ContentType::default(),
+            create_dirs: false,
+            mode: None,
+            set_executable: false,
+            expected_sha256: None,
+            expected_mtime: None,
+            content_sha256: None,
+        }
+    }
 }
 
 /// Output of the file write tool
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Output {
     /// Path of the written file
     pub path: String,
@@ -62,6 +102,89 @@ pub struct Output {
 
     /// Whether the file was created (true) or modified (false)
     pub created: bool,
+
+    /// Permissions of the written file as an octal string (Unix only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+/// The digest of `data`, if it isn't the one the caller gave
+fn sha256_mismatch(data: &[u8], expected: &str) -> Option<String> {
+    let actual = sha256_hex(data);
+    (!actual.eq_ignore_ascii_case(expected.trim())).then_some(actual)
+}
+
+/// Where `path`'s new content waits until it is known to be intact
+fn staged_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.file-write-{}", name, rand::random::<u32>()))
+}
+
+/// Write `data` next to `path`, flush it and check that it reads back as
+/// `expected` before putting it in place. A bad write leaves `path` alone.
+async fn write_verified(
+    fs: &dyn Filesystem,
+    path: &Path,
+    data: &[u8],
+    expected: &str,
+) -> Result<()> {
+    let staged = staged_path(path);
+    fs.write(&staged, data).await?;
+    let result = async {
+        fs.sync(&staged).await?;
+        if let Some(actual) = sha256_mismatch(&fs.read(&staged).await?, expected) {
+            return Err(Error::Other(format!(
+                "Written file {} does not match content_sha256: expected {}, found {}",
+                path.display(),
+                expected,
+                actual
+            )));
+        }
+        // The replaced file's permissions carry over
+        if let Ok(Some(mode)) = fs.metadata(path).await.map(|m| m.mode) {
+            fs.set_mode(&staged, mode & 0o7777).await?;
+        }
+        fs.replace(&staged, path).await?;
+        Ok(())
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs.remove_file(&staged).await;
+    }
+    result
+}
+
+/// Apply the requested permissions and return the resulting mode
+async fn apply_permissions(
+    fs: &dyn Filesystem,
+    path: &Path,
+    mode: Option<u32>,
+    set_executable: bool,
+) -> Result<Option<String>> {
+    let Some(current_mode) = fs.metadata(path).await?.mode else {
+        if mode.is_some() || set_executable {
+            logging::call_log!(
+                log::Level::Warn,
+                "File permissions are not supported here, ignoring mode"
+            );
+        }
+        return Ok(None);
+    };
+    let mut new_mode = mode.unwrap_or(current_mode & 0o7777);
+
+    if set_executable {
+        // Grant execute wherever read is granted
+        new_mode |= (new_mode & 0o444) >> 2;
+    }
+
+    if mode.is_some() || set_executable {
+        fs.set_mode(path, new_mode).await?;
+    }
+
+    Ok(Some(format!("{:o}", new_mode)))
 }
 
 #[async_trait]
@@ -73,45 +196,82 @@ impl Tool for FileWrite {
         "file_write"
     }
 
+    fn description(&self) -> &str {
+        "Write content to a file"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations {
+            idempotent: true,
+            ..Annotations::DESTRUCTIVE
+        }
+    }
+
     async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
         let path = PathBuf::from(&params.path);
+        let fs = vfs::for_path(&path)?;
 
         // Handle parent directories
-        if let Some(parent) = path.parent() {
-            if !parent.as_os_str().is_empty() && !parent.exists() {
-                if params.create_dirs {
-                    fs::create_dir_all(parent).await?;
-                } else {
-                    return Err(Error::InvalidParam(format!(
-                        "Parent directory does not exist: {}",
-                        parent.display()
-                    )));
-                }
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+            && !fs.exists(parent).await
+        {
+            if params.create_dirs {
+                fs.create_dir_all(parent).await?;
+            } else {
+                return Err(Error::NotFound(format!(
+                    "Parent directory does not exist: {}",
+                    parent.display()
+                )));
             }
         }
 
+        // Validate the mode before touching the file
+        let mode = params.mode.as_deref().map(parse_mode).transpose()?;
+
+        // Make sure nobody changed the file since the caller last read it
+        check_unchanged(
+            &path,
+            params.expected_sha256.as_deref(),
+            params.expected_mtime.as_deref(),
+        )
+        .await?;
+
         // Check if the file already exists
-        let created = !path.exists();
+        let created = !fs.exists(&path).await;
 
-        // Write the content based on the content type
-        match params.content_type {
-            ContentType::Text => {
-                // Write the content as text
-                fs::write(&path, &params.content).await?;
-            }
+        // Get the bytes to write based on the content type
+        let binary_data;
+        let data = match params.content_type {
+            ContentType::Text => params.content.as_bytes(),
             ContentType::Binary => {
                 // Decode the base64 content
-                let binary_data = general_purpose::STANDARD
+                binary_data = general_purpose::STANDARD
                     .decode(&params.content)
                     .map_err(|e| Error::InvalidParam(format!("Invalid base64 content: {}", e)))?;
+                &binary_data
+            }
+        };
 
-                // Write the binary data
-                fs::write(&path, binary_data).await?;
+        // Refuse content that didn't arrive intact, then check what landed
+        match &params.content_sha256 {
+            Some(expected) => {
+                if let Some(actual) = sha256_mismatch(data, expected) {
+                    return Err(Error::InvalidParam(format!(
+                        "Content does not match content_sha256: expected {}, found {}",
+                        expected, actual
+                    )));
+                }
+                write_verified(fs.as_ref(), &path, data, expected).await?;
             }
+            None => fs.write(&path, data).await?,
         }
 
+        // Set permissions if requested
+        let mode = apply_permissions(fs.as_ref(), &path, mode, params.set_executable).await?;
+
         // Get the file metadata
-        let metadata = fs::metadata(&path).await?;
+        let metadata = fs.metadata(&path).await?;
         let size = metadata.len();
 
         Ok(Output {
@@ -119,6 +279,7 @@ impl Tool for FileWrite {
             size,
             content_type: params.content_type,
             created,
+            mode,
         })
     }
 }
@@ -142,6 +303,11 @@ mod tests {
             content: test_content.to_string(),
             content_type: ContentType::Text,
             create_dirs: false,
+            mode: None,
+            set_executable: false,
+            expected_sha256: None,
+            expected_mtime: None,
+            content_sha256: None,
         };
 
         let result = tool.execute(params).await?;
@@ -178,6 +344,11 @@ mod tests {
             content: base64_content,
             content_type: ContentType::Binary,
             create_dirs: false,
+            mode: None,
+            set_executable: false,
+            expected_sha256: None,
+            expected_mtime: None,
+            content_sha256: None,
         };
 
         let result = tool.execute(params).await?;
@@ -220,6 +391,11 @@ mod tests {
             content: test_content.to_string(),
             content_type: ContentType::Text,
             create_dirs: true,
+            mode: None,
+            set_executable: false,
+            expected_sha256: None,
+            expected_mtime: None,
+            content_sha256: None,
         };
 
         let result = tool.execute(params).await?;
@@ -250,18 +426,199 @@ mod tests {
             content: test_content.to_string(),
             content_type: ContentType::Text,
             create_dirs: false,
+            mode: None,
+            set_executable: false,
+            expected_sha256: None,
+            expected_mtime: None,
+            content_sha256: None,
         };
 
         let result = tool.execute(params).await;
 
         assert!(result.is_err());
 
-        if let Err(Error::InvalidParam(msg)) = result {
+        if let Err(Error::NotFound(msg)) = result {
             assert!(msg.contains("Parent directory does not exist"));
         } else {
-            panic!("Expected InvalidParam error");
+            panic!("Expected NotFound error");
         }
 
         Ok(())
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_file_write_mode() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let test_file = std::env::temp_dir().join(format!(
+            "test_write_mode_{}.sh",
+            chrono::Utc::now().timestamp_millis()
+        ));
+
+        let tool = FileWrite;
+
+        // Explicit mode plus set_executable
+        let params = Params {
+            path: test_file.to_string_lossy().to_string(),
+            content: "#!/bin/sh\necho hi\n".to_string(),
+            content_type: ContentType::Text,
+            create_dirs: false,
+            mode: Some("640".to_string()),
+            set_executable: true,
+            expected_sha256: None,
+            expected_mtime: None,
+            content_sha256: None,
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert_eq!(result.mode.as_deref(), Some("750"));
+        let metadata = tokio::fs::metadata(&test_file).await?;
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o750);
+
+        // Invalid modes are rejected
+        let params = Params {
+            path: test_file.to_string_lossy().to_string(),
+            content: String::new(),
+            content_type: ContentType::Text,
+            create_dirs: false,
+            mode: Some("rwx".to_string()),
+            set_executable: false,
+            expected_sha256: None,
+            expected_mtime: None,
+            content_sha256: None,
+        };
+
+        assert!(tool.execute(params).await.is_err());
+
+        tokio::fs::remove_file(&test_file).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_write_expected_sha256() -> Result<()> {
+        use crate::tools::precondition::sha256_hex;
+
+        let test_file = std::env::temp_dir().join(format!(
+            "test_write_precondition_{}.txt",
+            chrono::Utc::now().timestamp_millis()
+        ));
+        tokio::fs::write(&test_file, "original").await?;
+
+        let tool = FileWrite;
+        let params = |expected: String| Params {
+            path: test_file.to_string_lossy().to_string(),
+            content: "updated".to_string(),
+            content_type: ContentType::Text,
+            create_dirs: false,
+            mode: None,
+            set_executable: false,
+            expected_sha256: Some(expected),
+            expected_mtime: None,
+            content_sha256: None,
+        };
+
+        // A stale hash is a conflict and leaves the file alone
+        let result = tool.execute(params(sha256_hex(b"stale"))).await;
+        assert!(matches!(result, Err(Error::Conflict(_))));
+        assert_eq!(tokio::fs::read_to_string(&test_file).await?, "original");
+
+        // The current hash allows the write
+        tool.execute(params(sha256_hex(b"original"))).await?;
+        assert_eq!(tokio::fs::read_to_string(&test_file).await?, "updated");
+
+        tokio::fs::remove_file(&test_file).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_write_content_sha256() -> Result<()> {
+        let fs = std::sync::Arc::new(vfs::MemoryFs::new());
+        vfs::scope(fs.clone(), async {
+            let params = |expected: String| Params {
+                content_type: ContentType::Binary,
+                content_sha256: Some(expected),
+                ..Params::new("/blob.bin", general_purpose::STANDARD.encode(b"payload"))
+            };
+
+            // A payload that doesn't hash as promised is never written
+            let result = FileWrite.execute(params(sha256_hex(b"payl"))).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+            assert!(!fs.exists(Path::new("/blob.bin")).await);
+
+            let output = FileWrite
+                .execute(params(sha256_hex(b"payload").to_uppercase()))
+                .await?;
+            assert_eq!(output.size, 7);
+            assert_eq!(fs.read(Path::new("/blob.bin")).await?, b"payload");
+            Ok(())
+        })
+        .await
+    }
+
+    /// A filesystem whose writes lose their last byte
+    struct Truncating(vfs::MemoryFs);
+
+    #[async_trait]
+    impl Filesystem for Truncating {
+        async fn metadata(&self, path: &Path) -> std::io::Result<vfs::Metadata> {
+            self.0.metadata(path).await
+        }
+        async fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+            self.0.read(path).await
+        }
+        async fn write(&self, path: &Path, contents: &[u8]) -> std::io::Result<()> {
+            let len = contents.len().saturating_sub(1);
+            self.0.write(path, &contents[..len]).await
+        }
+        async fn read_dir(&self, path: &Path) -> std::io::Result<Vec<vfs::DirEntry>> {
+            self.0.read_dir(path).await
+        }
+        async fn create_dir(&self, path: &Path) -> std::io::Result<()> {
+            self.0.create_dir(path).await
+        }
+        async fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+            self.0.create_dir_all(path).await
+        }
+        async fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+            self.0.remove_file(path).await
+        }
+        async fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+            self.0.remove_dir(path).await
+        }
+        async fn set_mode(&self, path: &Path, mode: u32) -> std::io::Result<()> {
+            self.0.set_mode(path, mode).await
+        }
+        async fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+            self.0.canonicalize(path).await
+        }
+        async fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+            self.0.rename(from, to).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_write_content_sha256_bad_write() -> Result<()> {
+        let fs = std::sync::Arc::new(Truncating(
+            vfs::MemoryFs::new().with_file("/blob.bin", "original"),
+        ));
+        vfs::scope(fs.clone(), async {
+            let params = Params {
+                content_sha256: Some(sha256_hex(b"payload")),
+                ..Params::new("/blob.bin", "payload")
+            };
+
+            // What landed is checked before it replaces the file
+            let result = FileWrite.execute(params).await;
+            assert!(matches!(result, Err(Error::Other(msg)) if msg.contains("does not match")));
+            assert_eq!(fs.read(Path::new("/blob.bin")).await?, b"original");
+            // The staged copy is gone
+            assert_eq!(fs.read_dir(Path::new("/")).await?.len(), 1);
+            Ok(())
+        })
+        .await
+    }
 }
@@ -0,0 +1,268 @@
+//! Multi-file read tool implementation
+//!
+//! Reads several files in one call. Each file is read like `file_read` would;
+//! a file that can't be read gets an error entry instead of failing the call.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+use super::{Annotations, Tool};
+use super::file_read::{self, ContentType, FileRead};
+use crate::{Error, Result};
+
+/// Default cap on the content returned per file
+const DEFAULT_MAX_BYTES_PER_FILE: usize = 256 * 1024;
+
+/// Multi-file read tool
+#[derive(Clone, Copy)]
+pub struct FilesRead;
+
+/// Parameters for the multi-file read tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Paths of the files to read
+    #[serde(deserialize_with = "crate::roots::path_list")]
+    pub paths: Vec<String>,
+
+    /// How to interpret the file contents
+    #[serde(default)]
+    pub content_type: ContentType,
+
+    /// Whether to include line numbers in the output (only applies to text)
+    #[serde(default)]
+    pub line_numbers: bool,
+
+    /// Maximum bytes of content to return per file (defaults to 256 KiB)
+    #[serde(default)]
+    pub max_bytes_per_file: Option<usize>,
+}
+
+impl Params {
+    /// Read the files at `paths`
+    pub fn new(paths: Vec<String>) -> Self {
+        Self {
+            paths,
+            content_type: ContentType::default(),
+            line_numbers: false,
+            max_bytes_per_file: None,
+        }
+    }
+}
+
+/// One file in the output
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FileContent {
+    /// Path of the file
+    pub path: String,
+
+    /// Content of the file (text or base64 encoded), absent if it couldn't be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+
+    /// Size of the file in bytes
+    pub size: u64,
+
+    /// MIME type of the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+
+    /// Type of content returned (text or binary)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<ContentType>,
+
+    /// Whether the content was cut to `max_bytes_per_file`
+    pub truncated: bool,
+
+    /// Why the file couldn't be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Output of the multi-file read tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// The files, in the order they were requested
+    pub files: Vec<FileContent>,
+
+    /// Number of files that were read successfully
+    pub read: usize,
+
+    /// Number of files that could not be read
+    pub failed: usize,
+}
+
+/// Cut content down to `max_bytes`, keeping text on a character boundary and
+/// base64 on a whole 4-character group. Returns whether anything was cut.
+fn truncate_content(content: &mut String, content_type: ContentType, max_bytes: usize) -> bool {
+    if content.len() <= max_bytes {
+        return false;
+    }
+
+    let mut end = match content_type {
+        ContentType::Binary => max_bytes - max_bytes % 4,
+        _ => max_bytes,
+    };
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    content.truncate(end);
+    true
+}
+
+#[async_trait]
+impl Tool for FilesRead {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "files_read"
+    }
+
+    fn description(&self) -> &str {
+        "Read several files at once"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        if params.paths.is_empty() {
+            return Err(Error::InvalidParam("No paths provided".to_string()));
+        }
+
+        let max_bytes = params
+            .max_bytes_per_file
+            .unwrap_or(DEFAULT_MAX_BYTES_PER_FILE);
+        let mut files = Vec::with_capacity(params.paths.len());
+
+        for path in params.paths {
+            let result = FileRead
+                .execute(file_read::Params {
+                    path: path.clone(),
+                    content_type: params.content_type,
+                    offset: None,
+                    limit: None,
+                    line_numbers: params.line_numbers,
+                    encoding: None,
+                    filter_pattern: None,
+                    filter_context: 0,
+                    max_dimension: None,
+                    include_hash: false,
+                    as_lines: false,
+                    // Content is cut to max_bytes_per_file rather than chunked
+                    chunk_threshold: Some(u64::MAX),
+                    chunk_size: None,
+                })
+                .await;
+
+            files.push(match result {
+                Ok(mut output) => {
+                    let truncated =
+                        truncate_content(&mut output.content, output.content_type, max_bytes);
+                    FileContent {
+                        path,
+                        content: Some(output.content),
+                        size: output.size,
+                        mime_type: Some(output.mime_type),
+                        content_type: Some(output.content_type),
+                        truncated,
+                        error: None,
+                    }
+                }
+                Err(e) => FileContent {
+                    path,
+                    content: None,
+                    size: 0,
+                    mime_type: None,
+                    content_type: None,
+                    truncated: false,
+                    error: Some(e.to_string()),
+                },
+            });
+        }
+
+        let failed = files.iter().filter(|f| f.error.is_some()).count();
+
+        Ok(Output {
+            read: files.len() - failed,
+            failed,
+            files,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::fs;
+
+    #[tokio::test]
+    async fn test_files_read() -> Result<()> {
+        let test_dir = std::env::temp_dir().join(format!(
+            "files_read_test_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        fs::create_dir_all(&test_dir).await?;
+        let small = test_dir.join("small.txt");
+        let large = test_dir.join("large.txt");
+        fs::write(&small, "hello\n").await?;
+        fs::write(&large, "x".repeat(100)).await?;
+
+        let result = FilesRead
+            .execute(Params {
+                paths: vec![
+                    small.to_string_lossy().to_string(),
+                    large.to_string_lossy().to_string(),
+                    test_dir.join("missing.txt").to_string_lossy().to_string(),
+                ],
+                content_type: ContentType::Text,
+                line_numbers: false,
+                max_bytes_per_file: Some(10),
+            })
+            .await?;
+
+        assert_eq!(result.read, 2);
+        assert_eq!(result.failed, 1);
+        assert_eq!(result.files[0].content.as_deref(), Some("hello\n"));
+        assert!(!result.files[0].truncated);
+        assert_eq!(result.files[1].content.as_deref(), Some("xxxxxxxxxx"));
+        assert_eq!(result.files[1].size, 100);
+        assert!(result.files[1].truncated);
+        assert!(result.files[2].error.is_some());
+
+        fs::remove_dir_all(&test_dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_files_read_memory_fs() -> Result<()> {
+        let memory = std::sync::Arc::new(
+            crate::vfs::MemoryFs::new()
+                .with_file("/repo/a.txt", "a\n")
+                .with_file("/repo/b.txt", "b\n"),
+        );
+        let params = Params::new(vec!["/repo/a.txt".to_string(), "/repo/c.txt".to_string()]);
+        let result = crate::vfs::scope(memory, FilesRead.execute(params)).await?;
+
+        assert_eq!(result.read, 1);
+        assert_eq!(result.files[0].content.as_deref(), Some("a\n"));
+        assert!(result.files[1].error.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_content() {
+        let mut text = "héllo".to_string();
+        assert!(truncate_content(&mut text, ContentType::Text, 2));
+        assert_eq!(text, "h");
+
+        let mut base64 = "QUJDREVGRw==".to_string();
+        assert!(truncate_content(&mut base64, ContentType::Binary, 6));
+        assert_eq!(base64, "QUJD");
+    }
+}
@@ -0,0 +1,360 @@
+//! Fuzzy find tool implementation
+//!
+//! Finds files whose path contains the query as a subsequence, the way fzf
+//! does, and returns the best scored ones. Matches at the start of a path
+//! component or word, runs of consecutive characters and matches in the file
+//! name score higher; gaps between matched characters cost a little. The
+//! query is case-insensitive unless it contains an uppercase letter.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::path::Path;
+
+use super::{Annotations, Tool};
+use crate::vfs::WalkOptions;
+use crate::{Error, Result, cancel, paths, vfs};
+
+/// Score of each matched character
+const SCORE_MATCH: i64 = 16;
+/// Bonus for a match right after the previous one
+const BONUS_CONSECUTIVE: i64 = 15;
+/// Bonus for a match at the start of a path component
+const BONUS_COMPONENT: i64 = 12;
+/// Bonus for a match at the start of a word, after `_`, `-`, `.` or a space,
+/// or at a camelCase hump
+const BONUS_WORD: i64 = 8;
+/// Bonus for a match in the file name rather than a parent directory
+const BONUS_FILE_NAME: i64 = 4;
+/// Penalty for each character skipped between two matches
+const PENALTY_GAP: i64 = 1;
+
+/// Fuzzy find tool
+#[derive(Clone, Copy)]
+pub struct FuzzyFind;
+
+/// Parameters for the fuzzy find tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Characters to look for, in order, in each path
+    pub query: String,
+
+    /// Directory to search in
+    #[serde(default = "default_directory", deserialize_with = "crate::roots::path")]
+    pub directory: String,
+
+    /// Maximum number of results (0 means no limit)
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+
+    /// Match directories as well as files
+    #[serde(default)]
+    pub include_dirs: bool,
+
+    /// Match paths below hidden directories and hidden files, like `.git`
+    #[serde(default)]
+    pub include_hidden: bool,
+}
+
+fn default_directory() -> String {
+    ".".to_string()
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+impl Params {
+    /// Search `directory` for paths matching `query`
+    pub fn new(query: impl Into<String>, directory: impl Into<String>) -> Self {
+        Self {
+            query: query.into(),
+            directory: directory.into(),
+            limit: default_limit(),
+            include_dirs: false,
+            include_hidden: false,
+        }
+    }
+}
+
+/// A path matching the query
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Match {
+    /// Full path of the entry
+    pub path: String,
+
+    /// Path relative to the searched directory, which the query was matched
+    /// against
+    pub relative: String,
+
+    /// Score of the match; higher is better
+    pub score: i64,
+
+    /// Character positions in `relative` that matched the query
+    pub positions: Vec<usize>,
+}
+
+/// Output of the fuzzy find tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// The query that was searched for
+    pub query: String,
+
+    /// Best matches, highest score first
+    pub matches: Vec<Match>,
+
+    /// Number of paths that matched before the limit was applied
+    pub total_matched: usize,
+}
+
+/// Bonus for matching the character at `index`
+fn boundary_bonus(chars: &[char], index: usize) -> i64 {
+    let Some(&prev) = index.checked_sub(1).and_then(|i| chars.get(i)) else {
+        return BONUS_COMPONENT;
+    };
+    let current = chars[index];
+    match prev {
+        '/' | '\\' => BONUS_COMPONENT,
+        '_' | '-' | '.' | ' ' => BONUS_WORD,
+        _ if prev.is_lowercase() && current.is_uppercase() => BONUS_WORD,
+        _ if !prev.is_ascii_digit() && current.is_ascii_digit() => BONUS_WORD,
+        _ => 0,
+    }
+}
+
+/// Score `query` against `candidate`, returning the score and the matched
+/// character positions, or `None` when the query isn't a subsequence.
+///
+/// Like fzf's fast path, this finds the first window that contains the
+/// query, shrinks it from the right end back to the latest possible start
+/// and scores the leftmost match inside it.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(fold)
+        .collect();
+    if query.is_empty() {
+        return None;
+    }
+    let chars: Vec<char> = candidate.chars().collect();
+    let folded: Vec<char> = chars.iter().copied().map(fold).collect();
+
+    // Forward: the first index where the whole query has been seen
+    let mut qi = 0;
+    let mut end = None;
+    for (i, &c) in folded.iter().enumerate() {
+        if c == query[qi] {
+            qi += 1;
+            if qi == query.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    // Backward: the latest start that still contains the query
+    let mut qi = query.len();
+    let mut start = end;
+    for (i, &c) in folded[..=end].iter().enumerate().rev() {
+        if c == query[qi - 1] {
+            qi -= 1;
+            if qi == 0 {
+                start = i;
+                break;
+            }
+        }
+    }
+
+    let file_name_start = chars
+        .iter()
+        .rposition(|&c| c == '/' || c == '\\')
+        .map_or(0, |i| i + 1);
+    let mut score = 0;
+    let mut positions = Vec::with_capacity(query.len());
+    let mut qi = 0;
+    for (i, &c) in folded.iter().enumerate().take(end + 1).skip(start) {
+        if qi == query.len() {
+            break;
+        }
+        if c != query[qi] {
+            continue;
+        }
+        score += SCORE_MATCH + boundary_bonus(&chars, i);
+        match positions.last() {
+            Some(&last) if last + 1 == i => score += BONUS_CONSECUTIVE,
+            Some(&last) => score -= PENALTY_GAP * (i - last - 1) as i64,
+            None => {}
+        }
+        if i >= file_name_start {
+            score += BONUS_FILE_NAME;
+        }
+        positions.push(i);
+        qi += 1;
+    }
+    Some((score, positions))
+}
+
+/// Whether any component of a relative path starts with a dot
+fn is_hidden(relative: &Path) -> bool {
+    relative
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+#[async_trait]
+impl Tool for FuzzyFind {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "fuzzy_find"
+    }
+
+    fn description(&self) -> &str {
+        "Find files by fuzzy matching a query against their paths, best matches first"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        if params.query.trim().is_empty() {
+            return Err(Error::InvalidParam("query must not be empty".to_string()));
+        }
+
+        let fs = vfs::current();
+        let directory = paths::canonical_dir(fs.as_ref(), Path::new(&params.directory)).await?;
+        let directory = directory.as_path();
+
+        let options = WalkOptions {
+            max_depth: usize::MAX,
+            follow_links: false,
+        };
+        let mut matches = Vec::new();
+        for entry in fs.walk(directory, options).await? {
+            cancel::check()?;
+            if entry.metadata.is_dir() && !params.include_dirs {
+                continue;
+            }
+            let Ok(relative) = entry.path.strip_prefix(directory) else {
+                continue;
+            };
+            if !params.include_hidden && is_hidden(relative) {
+                continue;
+            }
+            let relative = paths::to_string(relative);
+            if let Some((score, positions)) = fuzzy_match(&params.query, &relative) {
+                matches.push(Match {
+                    path: paths::to_string(&entry.path),
+                    relative,
+                    score,
+                    positions,
+                });
+            }
+        }
+
+        // Best score first; shorter paths win ties, then the path decides
+        matches.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.relative.len().cmp(&b.relative.len()))
+                .then_with(|| a.relative.cmp(&b.relative))
+        });
+        let total_matched = matches.len();
+        if params.limit > 0 {
+            matches.truncate(params.limit);
+        }
+
+        Ok(Output {
+            query: params.query,
+            matches,
+            total_matched,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_fuzzy_match() {
+        let (score, positions) = fuzzy_match("cfg", "src/config.rs").unwrap();
+        assert_eq!(positions, vec![4, 7, 9]);
+        assert!(score > 0);
+
+        // Not a subsequence
+        assert_eq!(fuzzy_match("xyz", "src/config.rs"), None);
+
+        // Smart case: an uppercase letter makes the query case-sensitive
+        assert!(fuzzy_match("readme", "README.md").is_some());
+        assert!(fuzzy_match("ReadMe", "README.md").is_none());
+
+        // Word starts and consecutive runs beat scattered matches
+        let (boundary, _) = fuzzy_match("fm", "file_metadata.rs").unwrap();
+        let (scattered, _) = fuzzy_match("fm", "farm.rs").unwrap();
+        assert!(boundary > scattered);
+        let (run, _) = fuzzy_match("conf", "config.toml").unwrap();
+        let (gaps, _) = fuzzy_match("conf", "cargo_of_nfs.rs").unwrap();
+        assert!(run > gaps);
+
+        // The window shrinks to the latest start, so the later run is scored
+        let (_, positions) = fuzzy_match("lib", "l/src/lib.rs").unwrap();
+        assert_eq!(positions, vec![6, 7, 8]);
+    }
+
+    #[tokio::test]
+    async fn test_fuzzy_find() -> Result<()> {
+        let fs = Arc::new(
+            MemoryFs::new()
+                .with_file("/work/config/settings.toml", "")
+                .with_file("/work/src/config.rs", "")
+                .with_file("/work/src/cache/fingerprint.rs", "")
+                .with_file("/work/docs/contributing.md", "")
+                .with_file("/work/.git/config", ""),
+        );
+        vfs::scope(fs, async {
+            let tool = FuzzyFind;
+
+            let result = tool.execute(Params::new("config", "/work")).await?;
+            let found: Vec<_> = result.matches.iter().map(|m| m.relative.as_str()).collect();
+            assert_eq!(found[0], "src/config.rs");
+            assert!(!found.contains(&".git/config"));
+            assert!(!found.contains(&"config"));
+            assert_eq!(result.matches[0].path, "/work/src/config.rs");
+
+            let params = Params {
+                include_hidden: true,
+                include_dirs: true,
+                limit: 1,
+                ..Params::new("gitcfg", "/work")
+            };
+            let result = tool.execute(params).await?;
+            assert_eq!(result.total_matched, 1);
+            assert_eq!(result.matches[0].relative, ".git/config");
+
+            let result = tool.execute(Params::new("zzz", "/work")).await?;
+            assert!(result.matches.is_empty());
+
+            let result = tool.execute(Params::new(" ", "/work")).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+            let result = tool.execute(Params::new("x", "/missing")).await;
+            assert!(matches!(result, Err(Error::NotFound(_))));
+            Ok(())
+        })
+        .await
+    }
+}
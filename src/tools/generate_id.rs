@@ -0,0 +1,249 @@
+//! Generate id tool implementation
+//!
+//! Produces unique identifiers for file names, fixtures and test data without a
+//! shell: random (v4) and time-ordered (v7) UUIDs, random hex strings, and
+//! random bytes in base64. Everything comes from the thread-local generator of
+//! `rand`, which is cryptographically secure.
+
+use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Annotations, Tool};
+use crate::{Error, Result};
+
+/// Most ids one call generates
+const MAX_COUNT: usize = 1000;
+
+/// Most random bytes in one hex or base64 id
+const MAX_BYTES: usize = 4096;
+
+/// Generate id tool
+#[derive(Clone, Copy)]
+pub struct GenerateId;
+
+/// Kind of id to generate
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IdKind {
+    /// Random UUID, e.g. "9b2c4f3e-8a1d-4c5b-9e7f-0a1b2c3d4e5f"
+    #[default]
+    UuidV4,
+    /// UUID starting with the current time, so later ids sort after earlier ones
+    UuidV7,
+    /// `bytes` random bytes as lowercase hex
+    Hex,
+    /// `bytes` random bytes as standard base64
+    Base64,
+    /// `bytes` random bytes as unpadded URL-safe base64, usable in file names
+    Base64Url,
+}
+
+/// Parameters for the generate id tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Kind of id to generate
+    #[serde(default)]
+    pub kind: IdKind,
+
+    /// Number of ids to generate (at most 1000)
+    #[serde(default = "default_count")]
+    pub count: usize,
+
+    /// Number of random bytes in each hex or base64 id (at most 4096)
+    #[serde(default = "default_bytes")]
+    pub bytes: usize,
+}
+
+fn default_count() -> usize {
+    1
+}
+
+fn default_bytes() -> usize {
+    16
+}
+
+impl Params {
+    /// Generate one id of `kind`
+    pub fn new(kind: IdKind) -> Self {
+        Self {
+            kind,
+            count: default_count(),
+            bytes: default_bytes(),
+        }
+    }
+}
+
+/// Output of the generate id tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Kind of the ids generated
+    pub kind: IdKind,
+
+    /// The ids, in the order generated
+    pub ids: Vec<String>,
+}
+
+fn random_bytes(n: usize) -> Vec<u8> {
+    (0..n).map(|_| rand::random::<u8>()).collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Format 16 bytes as a UUID with the given version and the RFC 9562 variant
+fn uuid(mut bytes: [u8; 16], version: u8) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | (version << 4);
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    format!(
+        "{}-{}-{}-{}-{}",
+        hex(&bytes[..4]),
+        hex(&bytes[4..6]),
+        hex(&bytes[6..8]),
+        hex(&bytes[8..10]),
+        hex(&bytes[10..])
+    )
+}
+
+/// A v7 UUID: 48 bits of Unix time in milliseconds, then random bits
+fn uuid_v7(unix_ms: u64) -> String {
+    let mut bytes = rand::random::<[u8; 16]>();
+    bytes[..6].copy_from_slice(&unix_ms.to_be_bytes()[2..]);
+    uuid(bytes, 7)
+}
+
+#[async_trait]
+impl Tool for GenerateId {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "generate_id"
+    }
+
+    fn description(&self) -> &str {
+        "Generate UUIDs (v4 or time-ordered v7), random hex strings or random bytes in base64"
+    }
+
+    fn annotations(&self) -> Annotations {
+        // Every call gives new ids
+        Annotations {
+            idempotent: false,
+            ..Annotations::READ_ONLY
+        }
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        if params.count == 0 || params.count > MAX_COUNT {
+            return Err(Error::InvalidParam(format!(
+                "count must be between 1 and {}",
+                MAX_COUNT
+            )));
+        }
+        let uses_bytes = matches!(
+            params.kind,
+            IdKind::Hex | IdKind::Base64 | IdKind::Base64Url
+        );
+        if uses_bytes && (params.bytes == 0 || params.bytes > MAX_BYTES) {
+            return Err(Error::InvalidParam(format!(
+                "bytes must be between 1 and {}",
+                MAX_BYTES
+            )));
+        }
+
+        let unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
+        let ids = (0..params.count)
+            .map(|_| match params.kind {
+                IdKind::UuidV4 => uuid(rand::random(), 4),
+                IdKind::UuidV7 => uuid_v7(unix_ms),
+                IdKind::Hex => hex(&random_bytes(params.bytes)),
+                IdKind::Base64 => general_purpose::STANDARD.encode(random_bytes(params.bytes)),
+                IdKind::Base64Url => {
+                    general_purpose::URL_SAFE_NO_PAD.encode(random_bytes(params.bytes))
+                }
+            })
+            .collect();
+
+        Ok(Output {
+            kind: params.kind,
+            ids,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_uuid(id: &str, version: char) -> bool {
+        let groups: Vec<&str> = id.split('-').collect();
+        groups.iter().map(|g| g.len()).eq([8, 4, 4, 4, 12])
+            && id
+                .chars()
+                .all(|c| c == '-' || matches!(c, '0'..='9' | 'a'..='f'))
+            && groups[2].starts_with(version)
+            && groups[3].starts_with(['8', '9', 'a', 'b'])
+    }
+
+    #[tokio::test]
+    async fn test_generate_uuids() -> Result<()> {
+        let mut params = Params::new(IdKind::UuidV4);
+        params.count = 50;
+        let output = GenerateId.execute(params).await?;
+        assert_eq!(output.ids.len(), 50);
+        assert!(output.ids.iter().all(|id| is_uuid(id, '4')));
+        let mut unique = output.ids.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 50);
+
+        let output = GenerateId.execute(Params::new(IdKind::UuidV7)).await?;
+        assert!(is_uuid(&output.ids[0], '7'));
+
+        // v7 ids sort by time
+        assert!(uuid_v7(1_700_000_000_000) < uuid_v7(1_700_000_000_001));
+        assert!(uuid_v7(0x0123_4567_89ab).starts_with("01234567-89ab-7"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_generate_random_strings() -> Result<()> {
+        let mut params = Params::new(IdKind::Hex);
+        params.bytes = 8;
+        let output = GenerateId.execute(params).await?;
+        assert_eq!(output.ids[0].len(), 16);
+        assert!(output.ids[0].chars().all(|c| c.is_ascii_hexdigit()));
+
+        let output = GenerateId.execute(Params::new(IdKind::Base64)).await?;
+        assert_eq!(
+            general_purpose::STANDARD
+                .decode(&output.ids[0])
+                .unwrap()
+                .len(),
+            16
+        );
+
+        let output = GenerateId.execute(Params::new(IdKind::Base64Url)).await?;
+        assert!(!output.ids[0].contains(['+', '/', '=']));
+
+        let mut params = Params::new(IdKind::Hex);
+        params.bytes = 0;
+        assert!(matches!(
+            GenerateId.execute(params).await,
+            Err(Error::InvalidParam(_))
+        ));
+        let mut params = Params::new(IdKind::UuidV4);
+        params.count = 0;
+        assert!(matches!(
+            GenerateId.execute(params).await,
+            Err(Error::InvalidParam(_))
+        ));
+        Ok(())
+    }
+}
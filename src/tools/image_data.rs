@@ -0,0 +1,121 @@
+//! Image helpers for file_read: dimensions and optional downscaling
+//!
+//! Downscaling decodes and re-encodes the image, which needs the `image` crate;
+//! it is only available with the `image-resize` feature.
+
+use crate::Result;
+#[cfg(feature = "image-resize")]
+use crate::Error;
+
+/// A re-encoded, smaller copy of an image
+#[cfg_attr(not(feature = "image-resize"), allow(dead_code))]
+pub(crate) struct Downscaled {
+    /// Encoded image data
+    pub bytes: Vec<u8>,
+    /// MIME type of the encoded data
+    pub mime_type: String,
+    /// New width in pixels
+    pub width: u32,
+    /// New height in pixels
+    pub height: u32,
+}
+
+/// Read the pixel dimensions from an image header without decoding it
+pub(crate) fn dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let size = imagesize::blob_size(bytes).ok()?;
+    Some((
+        u32::try_from(size.width).ok()?,
+        u32::try_from(size.height).ok()?,
+    ))
+}
+
+/// Shrink an image so neither side exceeds `max_dimension`, keeping the aspect
+/// ratio. JPEGs stay JPEG; everything else is re-encoded as PNG. Returns `None`
+/// if the image already fits.
+#[cfg(feature = "image-resize")]
+pub(crate) fn downscale(bytes: &[u8], max_dimension: u32) -> Result<Option<Downscaled>> {
+    use image::{DynamicImage, ImageFormat};
+    use std::io::Cursor;
+
+    let format = image::guess_format(bytes)
+        .map_err(|e| Error::Other(format!("Unsupported image format: {}", e)))?;
+    let img = image::load_from_memory_with_format(bytes, format)
+        .map_err(|e| Error::Other(format!("Failed to decode image: {}", e)))?;
+
+    if img.width() <= max_dimension && img.height() <= max_dimension {
+        return Ok(None);
+    }
+
+    let thumbnail = img.thumbnail(max_dimension, max_dimension);
+    let (thumbnail, format, mime_type) = if format == ImageFormat::Jpeg {
+        // The JPEG encoder has no alpha channel
+        (
+            DynamicImage::ImageRgb8(thumbnail.to_rgb8()),
+            ImageFormat::Jpeg,
+            "image/jpeg",
+        )
+    } else {
+        (thumbnail, ImageFormat::Png, "image/png")
+    };
+
+    let mut encoded = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut encoded), format)
+        .map_err(|e| Error::Other(format!("Failed to encode image: {}", e)))?;
+
+    Ok(Some(Downscaled {
+        bytes: encoded,
+        mime_type: mime_type.to_string(),
+        width: thumbnail.width(),
+        height: thumbnail.height(),
+    }))
+}
+
+/// Without the `image-resize` feature images are returned at full size
+#[cfg(not(feature = "image-resize"))]
+pub(crate) fn downscale(_bytes: &[u8], _max_dimension: u32) -> Result<Option<Downscaled>> {
+    log::warn!("max_dimension needs the image-resize feature, returning the image unscaled");
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Just enough of a PNG for the header to be parsed
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        bytes.extend_from_slice(&13u32.to_be_bytes());
+        bytes.extend_from_slice(b"IHDR");
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes.extend_from_slice(&[8, 6, 0, 0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn test_dimensions() {
+        assert_eq!(dimensions(&png_header(640, 480)), Some((640, 480)));
+        assert_eq!(dimensions(b"not an image"), None);
+    }
+
+    #[cfg(feature = "image-resize")]
+    #[test]
+    fn test_downscale() -> Result<()> {
+        use std::io::Cursor;
+
+        let img = image::DynamicImage::new_rgba8(64, 32);
+        let mut bytes = Vec::new();
+        img.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+
+        let small = downscale(&bytes, 16)?.expect("image should be downscaled");
+        assert_eq!((small.width, small.height), (16, 8));
+        assert_eq!(small.mime_type, "image/png");
+        assert_eq!(dimensions(&small.bytes), Some((16, 8)));
+
+        assert!(downscale(&bytes, 64)?.is_none());
+
+        Ok(())
+    }
+}
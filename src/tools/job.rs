@@ -0,0 +1,685 @@
+//! Background job management for long-running shell commands
+//!
+//! `shell_spawn` starts a command and returns a job id immediately, so long
+//! builds don't block the dispatcher or run into transport timeouts. The job is
+//! then managed with `job_status`, `job_output` (incremental, by byte offset)
+//! and `job_kill`. All four tools share the jobs of one [`JobManager`].
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time;
+
+use super::{Annotations, Tool};
+use super::shell::{self, RunningCommand, Shell};
+use crate::{Error, Result};
+
+/// How many finished jobs to keep around before the oldest are forgotten
+const MAX_FINISHED_JOBS: usize = 64;
+
+/// Default cap on the bytes returned per stream by one `job_output` call
+const DEFAULT_MAX_BYTES: usize = 64 * 1024;
+
+/// Lifecycle state of a background job
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// The command is still running
+    Running,
+    /// The command exited on its own
+    Exited,
+    /// The command was stopped with `job_kill`
+    Killed,
+    /// The command was stopped because it exceeded its timeout
+    TimedOut,
+}
+
+/// How a job ended
+#[derive(Debug, Clone)]
+struct JobExit {
+    state: JobState,
+    status: i32,
+    signal: Option<String>,
+    finished: DateTime<Utc>,
+}
+
+/// A background job and the output collected from it
+struct Job {
+    command: String,
+    args: Vec<String>,
+    started: DateTime<Utc>,
+    stdout: Arc<Mutex<Vec<u8>>>,
+    stderr: Option<Arc<Mutex<Vec<u8>>>>,
+    exit: Arc<Mutex<Option<JobExit>>>,
+    kill: Option<oneshot::Sender<()>>,
+}
+
+impl Job {
+    fn exit(&self) -> Option<JobExit> {
+        self.exit.lock().unwrap().clone()
+    }
+}
+
+/// Registry of background jobs, shared by the job tools
+#[derive(Clone, Default)]
+pub struct JobManager {
+    jobs: Arc<Mutex<HashMap<u64, Job>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl JobManager {
+    /// Create an empty job registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start watching a spawned command and return its job id
+    fn spawn(&self, params: shell::Params, running: RunningCommand) -> u64 {
+        let RunningCommand {
+            mut children,
+            stdout,
+            stderr,
+        } = running;
+
+        let stdout_buffer = stdout
+            .as_ref()
+            .map(|reader| reader.buffer())
+            .unwrap_or_default();
+        let stderr_buffer = stderr.as_ref().map(|reader| reader.buffer());
+        let exit = Arc::new(Mutex::new(None));
+        let (kill_tx, kill_rx) = oneshot::channel::<()>();
+
+        let timeout = if params.timeout_ms > 0 {
+            Some(Duration::from_millis(params.timeout_ms))
+        } else {
+            None
+        };
+        let job_exit = Arc::clone(&exit);
+
+        tokio::spawn(async move {
+            let deadline = async {
+                match timeout {
+                    Some(timeout) => time::sleep(timeout).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            // A dropped sender (the job was forgotten) also stops the command
+            let stopped = tokio::select! {
+                result = shell::wait_children(&mut children) => Ok(result),
+                _ = kill_rx => Err(JobState::Killed),
+                _ = deadline => Err(JobState::TimedOut),
+            };
+
+            let (exit_status, state) = match stopped {
+                Ok(result) => (
+                    result.ok().and_then(|s| s.last().copied()),
+                    JobState::Exited,
+                ),
+                Err(state) => {
+                    let statuses = shell::kill_children(&mut children).await;
+                    (statuses.last().copied().flatten(), state)
+                }
+            };
+
+            let grace = (state != JobState::Exited).then_some(shell::PIPE_DRAIN_GRACE);
+            for mut reader in [stdout, stderr].into_iter().flatten() {
+                reader.wait(grace).await;
+            }
+
+            *job_exit.lock().unwrap() = Some(JobExit {
+                state,
+                status: exit_status.and_then(|s| s.code()).unwrap_or(-1),
+                signal: exit_status.and_then(shell::termination_signal),
+                finished: Utc::now(),
+            });
+        });
+
+        let job_id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.insert(
+            job_id,
+            Job {
+                command: params.reported_command().to_string(),
+                args: params.args,
+                started: Utc::now(),
+                stdout: stdout_buffer,
+                stderr: stderr_buffer,
+                exit,
+                kill: Some(kill_tx),
+            },
+        );
+        forget_old_jobs(&mut jobs);
+
+        job_id
+    }
+
+    /// Stop every running job, returning how many were sent a kill
+    pub fn kill_all(&self) -> usize {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.values_mut()
+            .filter(|job| job.exit().is_none())
+            .filter_map(|job| job.kill.take())
+            .filter_map(|kill| kill.send(()).ok())
+            .count()
+    }
+
+    /// Run `f` against a job, failing if the id is unknown
+    fn with_job<T>(&self, job_id: u64, f: impl FnOnce(&mut Job) -> T) -> Result<T> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs
+            .get_mut(&job_id)
+            .ok_or_else(|| Error::InvalidParam(format!("Unknown job id: {}", job_id)))?;
+        Ok(f(job))
+    }
+}
+
+/// Drop the oldest finished jobs once there are too many of them
+fn forget_old_jobs(jobs: &mut HashMap<u64, Job>) {
+    let mut finished: Vec<u64> = jobs
+        .iter()
+        .filter(|(_, job)| job.exit().is_some())
+        .map(|(id, _)| *id)
+        .collect();
+
+    if finished.len() > MAX_FINISHED_JOBS {
+        finished.sort_unstable();
+        for id in &finished[..finished.len() - MAX_FINISHED_JOBS] {
+            jobs.remove(id);
+        }
+    }
+}
+
+/// Read up to `max_bytes` from `offset`, without splitting a UTF-8 sequence at
+/// the end. Returns the text and the offset to continue from.
+fn read_from(buffer: &Mutex<Vec<u8>>, offset: usize, max_bytes: usize) -> (String, usize) {
+    let buffer = buffer.lock().unwrap();
+    let start = offset.min(buffer.len());
+    let mut end = start.saturating_add(max_bytes).min(buffer.len());
+
+    if let Err(e) = std::str::from_utf8(&buffer[start..end])
+        && e.error_len().is_none()
+        && e.valid_up_to() > 0
+    {
+        end = start + e.valid_up_to();
+    }
+
+    (
+        String::from_utf8_lossy(&buffer[start..end]).to_string(),
+        end,
+    )
+}
+
+/// Background shell command tool
+#[derive(Clone, Default)]
+pub struct ShellSpawn {
+    shell: Shell,
+    jobs: JobManager,
+}
+
+impl ShellSpawn {
+    /// Create a spawn tool that runs commands like `shell` and records them in `jobs`
+    pub fn new(shell: Shell, jobs: JobManager) -> Self {
+        Self { shell, jobs }
+    }
+}
+
+/// Output of the shell_spawn tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SpawnOutput {
+    /// Id used to refer to the job in the other job tools
+    pub job_id: u64,
+
+    /// The command that was started
+    pub command: String,
+
+    /// The arguments that were passed to the command
+    pub args: Vec<String>,
+}
+
+#[async_trait]
+impl Tool for ShellSpawn {
+    type Params = shell::Params;
+    type Output = SpawnOutput;
+
+    fn name(&self) -> &str {
+        "shell_spawn"
+    }
+
+    fn description(&self) -> &str {
+        "Start a shell command in the background"
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        // Jobs keep all their output, to be read back in pieces
+        let running = self.shell.start(&params, None).await?;
+        let command = params.reported_command().to_string();
+        let args = params.args.clone();
+        let job_id = self.jobs.spawn(params, running);
+
+        Ok(SpawnOutput {
+            job_id,
+            command,
+            args,
+        })
+    }
+}
+
+/// Job status tool
+#[derive(Clone, Default)]
+pub struct JobStatus {
+    jobs: JobManager,
+}
+
+impl JobStatus {
+    /// Create a status tool for the jobs in `jobs`
+    pub fn new(jobs: JobManager) -> Self {
+        Self { jobs }
+    }
+}
+
+/// Parameters for the job_status tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct StatusParams {
+    /// Id returned by shell_spawn
+    pub job_id: u64,
+}
+
+/// Output of the job_status tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct StatusOutput {
+    /// Id of the job
+    pub job_id: u64,
+
+    /// The command that was started
+    pub command: String,
+
+    /// The arguments that were passed to the command
+    pub args: Vec<String>,
+
+    /// Current state of the job
+    pub state: JobState,
+
+    /// Exit status code (-1 if it was killed by a signal), once finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<i32>,
+
+    /// The signal that terminated the command, e.g. "SIGKILL" (Unix only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<String>,
+
+    /// When the job was started (RFC 3339)
+    pub started: String,
+
+    /// When the job finished (RFC 3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished: Option<String>,
+
+    /// Bytes of stdout collected so far
+    pub stdout_bytes: usize,
+
+    /// Bytes of stderr collected so far (if captured)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr_bytes: Option<usize>,
+}
+
+#[async_trait]
+impl Tool for JobStatus {
+    type Params = StatusParams;
+    type Output = StatusOutput;
+
+    fn name(&self) -> &str {
+        "job_status"
+    }
+
+    fn description(&self) -> &str {
+        "Get the status of a background job"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        self.jobs.with_job(params.job_id, |job| {
+            let exit = job.exit();
+            StatusOutput {
+                job_id: params.job_id,
+                command: job.command.clone(),
+                args: job.args.clone(),
+                state: exit.as_ref().map_or(JobState::Running, |e| e.state),
+                status: exit.as_ref().map(|e| e.status),
+                signal: exit.as_ref().and_then(|e| e.signal.clone()),
+                started: job.started.to_rfc3339(),
+                finished: exit.as_ref().map(|e| e.finished.to_rfc3339()),
+                stdout_bytes: job.stdout.lock().unwrap().len(),
+                stderr_bytes: job.stderr.as_ref().map(|b| b.lock().unwrap().len()),
+            }
+        })
+    }
+}
+
+/// Job output tool
+#[derive(Clone, Default)]
+pub struct JobOutput {
+    jobs: JobManager,
+}
+
+impl JobOutput {
+    /// Create an output tool for the jobs in `jobs`
+    pub fn new(jobs: JobManager) -> Self {
+        Self { jobs }
+    }
+}
+
+/// Parameters for the job_output tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct OutputParams {
+    /// Id returned by shell_spawn
+    pub job_id: u64,
+
+    /// Byte offset into stdout to read from (the previous call's `stdout_offset`)
+    #[serde(default)]
+    pub stdout_offset: usize,
+
+    /// Byte offset into stderr to read from (the previous call's `stderr_offset`)
+    #[serde(default)]
+    pub stderr_offset: usize,
+
+    /// Maximum bytes to return per stream (defaults to 64 KiB)
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+impl OutputParams {
+    /// Read the output of job `job_id` from the start
+    pub fn new(job_id: u64) -> Self {
+        Self {
+            job_id,
+            stdout_offset: 0,
+            stderr_offset: 0,
+            max_bytes: None,
+        }
+    }
+}
+
+/// Output of the job_output tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct OutputChunk {
+    /// Id of the job
+    pub job_id: u64,
+
+    /// Current state of the job
+    pub state: JobState,
+
+    /// New stdout since `stdout_offset`
+    pub stdout: String,
+
+    /// Offset to pass as `stdout_offset` on the next call
+    pub stdout_offset: usize,
+
+    /// New stderr since `stderr_offset` (if captured)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+
+    /// Offset to pass as `stderr_offset` on the next call
+    pub stderr_offset: usize,
+
+    /// Whether more output is already available beyond this chunk
+    pub more: bool,
+}
+
+#[async_trait]
+impl Tool for JobOutput {
+    type Params = OutputParams;
+    type Output = OutputChunk;
+
+    fn name(&self) -> &str {
+        "job_output"
+    }
+
+    fn description(&self) -> &str {
+        "Read new output from a background job"
+    }
+
+    fn annotations(&self) -> Annotations {
+        // Each call moves the read position on
+        Annotations {
+            idempotent: false,
+            ..Annotations::READ_ONLY
+        }
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let max_bytes = params.max_bytes.unwrap_or(DEFAULT_MAX_BYTES).max(1);
+
+        self.jobs.with_job(params.job_id, |job| {
+            // Check the state first so a finished job never reports output
+            // that arrived after this chunk was taken
+            let state = job.exit().map_or(JobState::Running, |e| e.state);
+
+            let (stdout, stdout_offset) = read_from(&job.stdout, params.stdout_offset, max_bytes);
+            let mut more = stdout_offset < job.stdout.lock().unwrap().len();
+
+            let (stderr, stderr_offset) = match &job.stderr {
+                Some(buffer) => {
+                    let (text, offset) = read_from(buffer, params.stderr_offset, max_bytes);
+                    more |= offset < buffer.lock().unwrap().len();
+                    (Some(text), offset)
+                }
+                None => (None, params.stderr_offset),
+            };
+
+            OutputChunk {
+                job_id: params.job_id,
+                state,
+                stdout,
+                stdout_offset,
+                stderr,
+                stderr_offset,
+                more,
+            }
+        })
+    }
+}
+
+/// Job kill tool
+#[derive(Clone, Default)]
+pub struct JobKill {
+    jobs: JobManager,
+}
+
+impl JobKill {
+    /// Create a kill tool for the jobs in `jobs`
+    pub fn new(jobs: JobManager) -> Self {
+        Self { jobs }
+    }
+}
+
+/// Parameters for the job_kill tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct KillParams {
+    /// Id returned by shell_spawn
+    pub job_id: u64,
+}
+
+/// Output of the job_kill tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct KillOutput {
+    /// Id of the job
+    pub job_id: u64,
+
+    /// Whether a kill was sent (false if the job had already finished)
+    pub killed: bool,
+}
+
+#[async_trait]
+impl Tool for JobKill {
+    type Params = KillParams;
+    type Output = KillOutput;
+
+    fn name(&self) -> &str {
+        "job_kill"
+    }
+
+    fn description(&self) -> &str {
+        "Stop a background job"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations {
+            idempotent: true,
+            ..Annotations::DESTRUCTIVE
+        }
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let killed = self.jobs.with_job(params.job_id, |job| {
+            job.exit().is_none() && job.kill.take().is_some_and(|kill| kill.send(()).is_ok())
+        })?;
+
+        Ok(KillOutput {
+            job_id: params.job_id,
+            killed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::file_write::ContentType;
+
+    fn spawn_params(command: &str, args: &[&str]) -> shell::Params {
+        shell::Params {
+            command: command.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            env: HashMap::new(),
+            cwd: None,
+            capture_stderr: false,
+            timeout_ms: 0,
+            stdin: None,
+            stdin_type: ContentType::Text,
+            use_shell: false,
+            script: None,
+            script_shell: Default::default(),
+            pipeline: vec![],
+            max_output_lines: None,
+            output_from: shell::OutputFrom::Head,
+        }
+    }
+
+    async fn wait_for_exit(status: &JobStatus, job_id: u64) -> Result<StatusOutput> {
+        for _ in 0..100 {
+            let result = status.execute(StatusParams { job_id }).await?;
+            if result.state != JobState::Running {
+                return Ok(result);
+            }
+            time::sleep(Duration::from_millis(50)).await;
+        }
+        Err(Error::Other("Job did not finish".to_string()))
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn test_job_output() -> Result<()> {
+        let jobs = JobManager::new();
+        let spawn = ShellSpawn::new(Shell::new().with_shell_mode(true), jobs.clone());
+        let status = JobStatus::new(jobs.clone());
+        let output = JobOutput::new(jobs.clone());
+
+        let spawned = spawn
+            .execute(spawn_params("sh", &["-c", "echo one; echo two"]))
+            .await?;
+        let finished = wait_for_exit(&status, spawned.job_id).await?;
+        assert_eq!(finished.state, JobState::Exited);
+        assert_eq!(finished.status, Some(0));
+
+        // Read the output in small increments
+        let first = output
+            .execute(OutputParams {
+                job_id: spawned.job_id,
+                stdout_offset: 0,
+                stderr_offset: 0,
+                max_bytes: Some(4),
+            })
+            .await?;
+        assert_eq!(first.stdout, "one\n");
+        assert!(first.more);
+
+        let rest = output
+            .execute(OutputParams {
+                job_id: spawned.job_id,
+                stdout_offset: first.stdout_offset,
+                stderr_offset: 0,
+                max_bytes: None,
+            })
+            .await?;
+        assert_eq!(rest.stdout, "two\n");
+        assert!(!rest.more);
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn test_job_kill() -> Result<()> {
+        let jobs = JobManager::new();
+        let spawn = ShellSpawn::new(Shell::new(), jobs.clone());
+        let status = JobStatus::new(jobs.clone());
+        let kill = JobKill::new(jobs.clone());
+
+        let spawned = spawn.execute(spawn_params("sleep", &["10"])).await?;
+        let running = status
+            .execute(StatusParams {
+                job_id: spawned.job_id,
+            })
+            .await?;
+        assert_eq!(running.state, JobState::Running);
+
+        let result = kill
+            .execute(KillParams {
+                job_id: spawned.job_id,
+            })
+            .await?;
+        assert!(result.killed);
+
+        let finished = wait_for_exit(&status, spawned.job_id).await?;
+        assert_eq!(finished.state, JobState::Killed);
+
+        // kill_all stops whatever is still running
+        let first = spawn.execute(spawn_params("sleep", &["10"])).await?;
+        let second = spawn.execute(spawn_params("sleep", &["10"])).await?;
+        assert_eq!(jobs.kill_all(), 2);
+        for job_id in [first.job_id, second.job_id] {
+            let finished = wait_for_exit(&status, job_id).await?;
+            assert_eq!(finished.state, JobState::Killed);
+        }
+
+        // Unknown ids are rejected
+        let result = status.execute(StatusParams { job_id: 9999 }).await;
+        assert!(matches!(result, Err(Error::InvalidParam(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_from_utf8_boundary() {
+        let buffer = Mutex::new("héllo".as_bytes().to_vec());
+
+        // "é" is two bytes; a cut inside it is moved back
+        let (text, offset) = read_from(&buffer, 0, 2);
+        assert_eq!(text, "h");
+        assert_eq!(offset, 1);
+
+        let (text, offset) = read_from(&buffer, offset, 10);
+        assert_eq!(text, "éllo");
+        assert_eq!(offset, 6);
+    }
+}
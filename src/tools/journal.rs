@@ -0,0 +1,161 @@
+//! Journal of the steps of a change that spans several paths
+//!
+//! Every completed step records how to undo itself; if a later step fails, the
+//! completed steps are undone in reverse order and the tree is left as it was.
+//! Deleted paths are parked next to themselves until the change commits.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[cfg(feature = "batch")]
+use super::file_move::move_path;
+use crate::vfs::Filesystem;
+use crate::{Error, logging, trash};
+
+/// How to undo a completed step
+pub(crate) enum Undo {
+    /// Remove a file that did not exist before
+    RemoveFile(PathBuf),
+    /// Put back the previous content of an overwritten file
+    RestoreFile(PathBuf, Vec<u8>),
+    /// Remove a directory that did not exist before
+    RemoveDir(PathBuf),
+    /// Move a path back to where it came from
+    #[cfg(feature = "batch")]
+    MoveBack { from: PathBuf, to: PathBuf },
+    /// Bring back a deleted path from where it was parked
+    Restore {
+        parked: PathBuf,
+        original: PathBuf,
+        use_trash: bool,
+    },
+}
+
+/// Journal of completed steps on a filesystem
+pub(crate) struct Journal {
+    pub(crate) fs: Arc<dyn Filesystem>,
+    pub(crate) undo: Vec<Undo>,
+}
+
+impl Journal {
+    /// Start an empty journal for `fs`
+    pub(crate) fn new(fs: Arc<dyn Filesystem>) -> Self {
+        Self {
+            fs,
+            undo: Vec::new(),
+        }
+    }
+
+    /// Undo every recorded step, newest first. Failures are logged and skipped
+    /// so that as much as possible is restored.
+    pub(crate) async fn rollback(self) {
+        let fs = self.fs.as_ref();
+        for step in self.undo.iter().rev() {
+            let (result, path) = match step {
+                Undo::RemoveFile(path) => (fs.remove_file(path).await, path),
+                Undo::RestoreFile(path, content) => (fs.write(path, content).await, path),
+                Undo::RemoveDir(path) => (fs.remove_dir(path).await, path),
+                #[cfg(feature = "batch")]
+                Undo::MoveBack { from, to } => (move_path(fs, from, to).await.map(|_| ()), to),
+                Undo::Restore {
+                    parked, original, ..
+                } => (fs.rename(parked, original).await, original),
+            };
+            if let Err(e) = result {
+                logging::call_log!(
+                    log::Level::Warn,
+                    "Failed to roll back {}: {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Make the change permanent by removing the parked paths, or moving them
+    /// to the trash
+    pub(crate) async fn commit(self) {
+        let fs = self.fs.as_ref();
+        for step in self.undo {
+            if let Undo::Restore {
+                parked,
+                original,
+                use_trash,
+            } = step
+            {
+                let result = if use_trash {
+                    trash::discard_as(fs, &parked, &original)
+                        .await
+                        .map(|_| ())
+                } else if fs.metadata(&parked).await.is_ok_and(|m| m.is_dir()) {
+                    fs.remove_dir_all(&parked).await.map_err(Error::Io)
+                } else {
+                    fs.remove_file(&parked).await.map_err(Error::Io)
+                };
+                if let Err(e) = result {
+                    logging::call_log!(
+                        log::Level::Warn,
+                        "Failed to remove {}: {}",
+                        parked.display(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    /// Create `path` and any missing parents, recording each new directory
+    pub(crate) async fn create_dirs(&mut self, path: &Path) -> std::io::Result<()> {
+        let mut missing = Vec::new();
+        let mut current = Some(path);
+        while let Some(dir) = current {
+            if dir.as_os_str().is_empty() || self.fs.exists(dir).await {
+                break;
+            }
+            missing.push(dir.to_path_buf());
+            current = dir.parent();
+        }
+
+        for dir in missing.into_iter().rev() {
+            self.fs.create_dir(&dir).await?;
+            self.undo.push(Undo::RemoveDir(dir));
+        }
+        Ok(())
+    }
+
+    /// Create or replace the file at `path`, keeping what it held before
+    pub(crate) async fn write(&mut self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        let previous = match self.fs.metadata(path).await {
+            Ok(_) => Some(self.fs.read(path).await?),
+            Err(_) => None,
+        };
+        self.fs.write(path, data).await?;
+        self.undo.push(match previous {
+            Some(content) => Undo::RestoreFile(path.to_path_buf(), content),
+            None => Undo::RemoveFile(path.to_path_buf()),
+        });
+        Ok(())
+    }
+
+    /// Delete `path` by parking it until the change commits, when it is
+    /// removed or, with `use_trash`, moved to the trash
+    pub(crate) async fn delete(&mut self, path: &Path, use_trash: bool) -> std::io::Result<()> {
+        let parked = parked_path(path);
+        self.fs.rename(path, &parked).await?;
+        self.undo.push(Undo::Restore {
+            parked,
+            original: path.to_path_buf(),
+            use_trash,
+        });
+        Ok(())
+    }
+}
+
+/// Sibling path a deleted entry is parked at until the change commits
+fn parked_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.deleted-{}", name, rand::random::<u32>()))
+}
@@ -1,9 +1,60 @@
 //! Tools module with all tool implementations.
 
 use async_trait::async_trait;
-use serde::{Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 use crate::Result;
+use crate::cancel::{self, CancellationToken};
+
+/// Hints about what a tool does to its environment, named and defaulted like
+/// the MCP tool annotations. Hosts can use them to decide which calls need the
+/// user's confirmation; they are not enforced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Annotations {
+    /// The tool does not change its environment
+    #[serde(rename = "readOnlyHint")]
+    pub read_only: bool,
+    /// The tool may overwrite or delete data (only meaningful if not read-only)
+    #[serde(rename = "destructiveHint")]
+    pub destructive: bool,
+    /// Repeating a call with the same parameters has no further effect
+    #[serde(rename = "idempotentHint")]
+    pub idempotent: bool,
+    /// The tool reaches beyond the local files, e.g. by running programs
+    #[serde(rename = "openWorldHint")]
+    pub open_world: bool,
+}
+
+impl Annotations {
+    /// A tool that only looks at files
+    pub const READ_ONLY: Self = Self {
+        read_only: true,
+        destructive: false,
+        idempotent: true,
+        open_world: false,
+    };
+
+    /// A tool that changes files and may overwrite or delete data
+    pub const DESTRUCTIVE: Self = Self {
+        read_only: false,
+        destructive: true,
+        idempotent: false,
+        open_world: false,
+    };
+}
+
+impl Default for Annotations {
+    /// The most cautious hints: anything may happen
+    fn default() -> Self {
+        Self {
+            read_only: false,
+            destructive: true,
+            idempotent: false,
+            open_world: true,
+        }
+    }
+}
 
 /// Base trait for all tool implementations
 #[async_trait]
@@ -17,17 +68,243 @@ pub trait Tool {
     /// The name of the tool
     fn name(&self) -> &str;
 
+    /// What the tool does, as advertised to the model
+    fn description(&self) -> &str {
+        ""
+    }
+
+    /// Hints about the tool's effects
+    fn annotations(&self) -> Annotations {
+        Annotations::default()
+    }
+
     /// Execute the tool with the given parameters
     async fn execute(&self, params: Self::Params) -> Result<Self::Output>;
+
+    /// Execute the tool, stopping with [`Error::Cancelled`](crate::Error::Cancelled)
+    /// once `token` is cancelled
+    async fn execute_cancellable(
+        &self,
+        params: Self::Params,
+        token: CancellationToken,
+    ) -> Result<Self::Output>
+    where
+        Self: Sync,
+    {
+        cancel::scope(token, self.execute(params)).await
+    }
+
+    /// Execute the tool like [`execute_cancellable`](Self::execute_cancellable),
+    /// stopping with [`Error::Timeout`](crate::Error::Timeout) once `deadline`
+    /// passes
+    async fn execute_until(
+        &self,
+        params: Self::Params,
+        token: CancellationToken,
+        deadline: tokio::time::Instant,
+    ) -> Result<Self::Output>
+    where
+        Self: Sync,
+    {
+        cancel::scope_until(token, Some(deadline), self.execute(params)).await
+    }
 }
 
+#[cfg(feature = "batch")]
+pub mod batch;
+#[cfg(feature = "code-outline")]
+pub mod code_outline;
+#[cfg(feature = "directory-list")]
 pub mod directory_list;
+#[cfg(feature = "directory-make")]
 pub mod directory_make;
+#[cfg(feature = "directory-sync")]
+pub mod directory_sync;
+#[cfg(not(target_family = "wasm"))]
+pub mod external;
+#[cfg(feature = "file-compare")]
+pub mod file_compare;
+#[cfg(feature = "file-diff")]
 pub mod file_diff;
+#[cfg(feature = "file-edit")]
+pub mod file_edit;
+#[cfg(feature = "file-edit-lines")]
+pub mod file_edit_lines;
+#[cfg(feature = "file-find")]
 pub mod file_find;
+#[cfg(feature = "file-grep")]
 pub mod file_grep;
+#[cfg(feature = "file-metadata-set")]
+pub mod file_metadata_set;
+#[cfg(feature = "file-move")]
 pub mod file_move;
+#[cfg(feature = "file-patch")]
 pub mod file_patch;
+#[cfg(feature = "file-read")]
 pub mod file_read;
+#[cfg(feature = "file-read-chunk")]
+pub mod file_read_chunk;
+#[cfg(feature = "file-write")]
 pub mod file_write;
+#[cfg(feature = "files-read")]
+pub mod files_read;
+#[cfg(feature = "fuzzy-find")]
+pub mod fuzzy_find;
+#[cfg(feature = "generate-id")]
+pub mod generate_id;
+#[cfg(feature = "file-read")]
+mod image_data;
+#[cfg(feature = "job")]
+pub mod job;
+#[cfg(any(feature = "batch", feature = "file-patch"))]
+mod journal;
+#[cfg(any(feature = "directory-make", feature = "file-write"))]
+mod mode;
+#[cfg(feature = "network-check")]
+pub mod network_check;
+#[cfg(feature = "patch-create")]
+pub mod patch_create;
+#[cfg(feature = "path-info")]
+pub mod path_info;
+#[cfg(any(
+    feature = "file-edit",
+    feature = "file-edit-lines",
+    feature = "file-patch",
+    feature = "file-read",
+    feature = "file-write"
+))]
+mod precondition;
+#[cfg(feature = "recent-files")]
+pub mod recent_files;
+#[cfg(feature = "shell")]
 pub mod shell;
+#[cfg(feature = "text-insert")]
+pub mod text_insert;
+#[cfg(feature = "undo")]
+pub mod undo;
+#[cfg(feature = "workspace-snapshot")]
+pub mod workspace_snapshot;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // Unused when no tool features are enabled
+    #[allow(unused_imports)]
+    use serde_json::{Value, json};
+
+    /// Params built in Rust must match what the same JSON deserializes to
+    #[allow(dead_code)]
+    fn assert_same<P: Serialize + DeserializeOwned>(params: P, json: Value) {
+        let parsed: P = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            serde_json::to_value(&params).unwrap(),
+            serde_json::to_value(&parsed).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_params_constructors() {
+        #[cfg(feature = "code-outline")]
+        assert_same(code_outline::Params::new("a.rs"), json!({"path": "a.rs"}));
+        #[cfg(feature = "directory-list")]
+        assert_same(directory_list::Params::new("."), json!({"path": "."}));
+        #[cfg(feature = "directory-make")]
+        assert_same(directory_make::Params::new("d"), json!({"path": "d"}));
+        #[cfg(feature = "file-metadata-set")]
+        assert_same(
+            file_metadata_set::Params::new("a", "now"),
+            json!({"path": "a", "modified": "now"}),
+        );
+        #[cfg(feature = "file-edit")]
+        assert_same(
+            file_edit::Params::new("a", vec![]),
+            json!({"path": "a", "edits": []}),
+        );
+        #[cfg(feature = "file-edit-lines")]
+        assert_same(
+            file_edit_lines::Params::new("a", 3),
+            json!({"path": "a", "start_line": 3}),
+        );
+        #[cfg(feature = "file-move")]
+        assert_same(
+            file_move::Params::new("a", "b"),
+            json!({"source": "a", "destination": "b"}),
+        );
+        #[cfg(feature = "file-read")]
+        assert_same(file_read::Params::new("a"), json!({"path": "a"}));
+        #[cfg(feature = "file-read-chunk")]
+        assert_same(
+            file_read_chunk::Params::new("h", 2),
+            json!({"handle": "h", "index": 2}),
+        );
+        #[cfg(feature = "file-write")]
+        assert_same(
+            file_write::Params::new("a", "x"),
+            json!({"path": "a", "content": "x"}),
+        );
+        #[cfg(feature = "files-read")]
+        assert_same(
+            files_read::Params::new(vec!["a".to_string()]),
+            json!({"paths": ["a"]}),
+        );
+        #[cfg(feature = "network-check")]
+        assert_same(network_check::Params::new("a"), json!({"host": "a"}));
+        #[cfg(feature = "patch-create")]
+        assert_same(patch_create::Params::new("a"), json!({"path": "a"}));
+        #[cfg(feature = "path-info")]
+        assert_same(path_info::Params::new("a"), json!({"path": "a"}));
+        #[cfg(feature = "recent-files")]
+        assert_same(recent_files::Params::new("a"), json!({"directory": "a"}));
+        #[cfg(feature = "batch")]
+        assert_same(batch::Params::new(vec![]), json!({"operations": []}));
+        #[cfg(feature = "file-find")]
+        assert_same(
+            file_find::Params::new(".", "*.rs"),
+            json!({"directory": ".", "pattern": "*.rs"}),
+        );
+        #[cfg(feature = "fuzzy-find")]
+        assert_same(
+            fuzzy_find::Params::new("cfg", "."),
+            json!({"query": "cfg", "directory": "."}),
+        );
+        #[cfg(feature = "generate-id")]
+        assert_same(
+            generate_id::Params::new(generate_id::IdKind::Hex),
+            json!({"kind": "hex"}),
+        );
+        #[cfg(feature = "shell")]
+        assert_same(shell::Params::new("ls"), json!({"command": "ls"}));
+        #[cfg(feature = "text-insert")]
+        assert_same(
+            text_insert::Params::new("a", "x", 1),
+            json!({"path": "a", "content": "x", "line": 1}),
+        );
+        #[cfg(feature = "job")]
+        assert_same(job::OutputParams::new(1), json!({"job_id": 1}));
+
+        #[cfg(feature = "file-diff")]
+        assert_same(file_diff::Params::default(), json!({}));
+        #[cfg(feature = "directory-sync")]
+        assert_same(
+            directory_sync::Params::new("a", "b"),
+            json!({"source": "a", "destination": "b"}),
+        );
+        #[cfg(feature = "file-compare")]
+        assert_same(
+            file_compare::Params::new("a", "b"),
+            json!({"file1": "a", "file2": "b"}),
+        );
+        #[cfg(feature = "file-grep")]
+        assert_same(file_grep::Params::default(), json!({}));
+        #[cfg(feature = "file-patch")]
+        assert_same(file_patch::Params::default(), json!({}));
+        #[cfg(feature = "undo")]
+        assert_same(undo::ListParams::default(), json!({}));
+        #[cfg(feature = "undo")]
+        assert_same(undo::ApplyParams::default(), json!({}));
+        #[cfg(feature = "workspace-snapshot")]
+        assert_same(workspace_snapshot::SnapshotParams::default(), json!({}));
+        #[cfg(feature = "workspace-snapshot")]
+        assert_same(workspace_snapshot::RestoreParams::default(), json!({}));
+    }
+}
@@ -0,0 +1,14 @@
+//! Octal permission strings, as taken by the tools that set modes
+
+use crate::{Error, Result};
+
+/// Parse an octal permission string such as "755", "0644" or "0o600"
+pub(crate) fn parse_mode(mode: &str) -> Result<u32> {
+    let trimmed = mode.trim();
+    let digits = trimmed.strip_prefix("0o").unwrap_or(trimmed);
+
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|m| *m <= 0o7777)
+        .ok_or_else(|| Error::InvalidParam(format!("Invalid octal mode: {}", mode)))
+}
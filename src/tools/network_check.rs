@@ -0,0 +1,376 @@
+//! Network check tool implementation
+//!
+//! Answers "is the dev server up" without handing an agent a shell: resolves a
+//! host name, optionally connects to a TCP port on it, and optionally sends an
+//! HTTP `HEAD` request over that connection. Failures of the checks are part of
+//! the output rather than errors, so one call shows how far it got. Only plain
+//! HTTP is spoken; for an HTTPS server the TCP check is as far as it goes.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, lookup_host};
+use tokio::time::timeout;
+
+use super::{Annotations, Tool};
+use crate::{Error, Result};
+
+/// Network check tool
+#[derive(Clone, Copy)]
+pub struct NetworkCheck;
+
+/// Parameters for the network check tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Host name or IP address to check
+    pub host: String,
+
+    /// TCP port to connect to; 80 if not set and `http_head` is
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    /// Send an HTTP HEAD request once connected and report the status
+    #[serde(default)]
+    pub http_head: bool,
+
+    /// Path to request with `http_head`
+    #[serde(default = "default_http_path")]
+    pub http_path: String,
+
+    /// Time allowed for each step in milliseconds
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_http_path() -> String {
+    "/".to_string()
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+impl Params {
+    /// Resolve `host`
+    pub fn new(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            port: None,
+            http_head: false,
+            http_path: default_http_path(),
+            timeout_ms: default_timeout_ms(),
+        }
+    }
+}
+
+/// Result of the TCP check
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TcpCheck {
+    /// Port connected to
+    pub port: u16,
+
+    /// Whether a connection was made
+    pub connected: bool,
+
+    /// Address the connection was made to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+
+    /// Time taken to connect (or give up) in milliseconds
+    pub elapsed_ms: u64,
+
+    /// Why no connection was made
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Result of the HTTP check
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct HttpCheck {
+    /// Status code of the response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+
+    /// First line of the response, e.g. "HTTP/1.1 200 OK"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_line: Option<String>,
+
+    /// Time from sending the request to the status line in milliseconds
+    pub elapsed_ms: u64,
+
+    /// Why no status was received
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Output of the network check tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Host checked
+    pub host: String,
+
+    /// Addresses the host resolved to
+    pub addresses: Vec<String>,
+
+    /// Time taken to resolve the host in milliseconds
+    pub resolve_ms: u64,
+
+    /// Why the host didn't resolve
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolve_error: Option<String>,
+
+    /// Result of connecting to `port`, if one was checked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<TcpCheck>,
+
+    /// Result of the HEAD request, if one was sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpCheck>,
+
+    /// Whether every check asked for succeeded
+    pub reachable: bool,
+}
+
+fn millis(start: Instant) -> u64 {
+    start.elapsed().as_millis() as u64
+}
+
+/// Run `future` with the time limit, turning a timeout into an I/O error
+async fn limited<T>(
+    limit: Duration,
+    future: impl Future<Output = std::io::Result<T>>,
+) -> std::io::Result<T> {
+    timeout(limit, future).await.unwrap_or_else(|_| {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("timed out after {} ms", limit.as_millis()),
+        ))
+    })
+}
+
+/// Connect to the first address that accepts
+async fn connect(addresses: &[SocketAddr], limit: Duration) -> std::io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in addresses {
+        match limited(limit, TcpStream::connect(address)).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses to connect to")
+    }))
+}
+
+/// Send a HEAD request and read the status line of the response
+async fn head(stream: TcpStream, host: &str, path: &str) -> std::io::Result<String> {
+    let mut stream = BufReader::new(stream);
+    let request = format!(
+        "HEAD {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: {}/{}\r\nConnection: close\r\n\r\n",
+        path,
+        host,
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    stream.get_mut().write_all(request.as_bytes()).await?;
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    Ok(line.trim_end().to_string())
+}
+
+/// Status code of an HTTP status line
+fn parse_status(line: &str) -> Option<u16> {
+    let mut parts = line.split_whitespace();
+    parts
+        .next()
+        .filter(|version| version.starts_with("HTTP/"))?;
+    parts.next()?.parse().ok()
+}
+
+#[async_trait]
+impl Tool for NetworkCheck {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "network_check"
+    }
+
+    fn description(&self) -> &str {
+        "Check whether a host resolves, accepts TCP connections on a port and answers an HTTP HEAD request"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations {
+            open_world: true,
+            ..Annotations::READ_ONLY
+        }
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        if params.host.is_empty() {
+            return Err(Error::InvalidParam("host must not be empty".to_string()));
+        }
+        if params.timeout_ms == 0 {
+            return Err(Error::InvalidParam(
+                "timeout_ms must be at least 1".to_string(),
+            ));
+        }
+        if params.http_head
+            && (!params.http_path.starts_with('/')
+                || params
+                    .http_path
+                    .chars()
+                    .any(|c| c.is_whitespace() || c.is_control()))
+        {
+            return Err(Error::InvalidParam(format!(
+                "http_path must start with '/' and have no spaces: {:?}",
+                params.http_path
+            )));
+        }
+        let limit = Duration::from_millis(params.timeout_ms);
+        let port = params.port.or(params.http_head.then_some(80));
+
+        // An address in brackets is an IPv6 literal
+        let host = params.host.trim_start_matches('[').trim_end_matches(']');
+        let start = Instant::now();
+        let resolved = limited(limit, async {
+            let addresses = lookup_host((host, port.unwrap_or(0))).await?;
+            Ok::<_, std::io::Error>(addresses.collect::<Vec<_>>())
+        })
+        .await;
+        let resolve_ms = millis(start);
+        let (addresses, resolve_error) = match resolved {
+            Ok(addresses) => (addresses, None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+
+        let mut output = Output {
+            host: params.host.clone(),
+            addresses: addresses.iter().map(|a| a.ip().to_string()).collect(),
+            resolve_ms,
+            resolve_error,
+            tcp: None,
+            http: None,
+            reachable: false,
+        };
+        let Some(port) = port else {
+            output.reachable = !output.addresses.is_empty();
+            return Ok(output);
+        };
+        if addresses.is_empty() {
+            return Ok(output);
+        }
+
+        let start = Instant::now();
+        let stream = match connect(&addresses, limit).await {
+            Ok(stream) => {
+                output.tcp = Some(TcpCheck {
+                    port,
+                    connected: true,
+                    address: stream.peer_addr().ok().map(|a| a.to_string()),
+                    elapsed_ms: millis(start),
+                    error: None,
+                });
+                stream
+            }
+            Err(e) => {
+                output.tcp = Some(TcpCheck {
+                    port,
+                    connected: false,
+                    address: None,
+                    elapsed_ms: millis(start),
+                    error: Some(e.to_string()),
+                });
+                return Ok(output);
+            }
+        };
+        if !params.http_head {
+            output.reachable = true;
+            return Ok(output);
+        }
+
+        let start = Instant::now();
+        let http = match limited(limit, head(stream, &params.host, &params.http_path)).await {
+            Ok(line) => match parse_status(&line) {
+                Some(status) => HttpCheck {
+                    status: Some(status),
+                    status_line: Some(line),
+                    elapsed_ms: millis(start),
+                    error: None,
+                },
+                None => HttpCheck {
+                    status: None,
+                    error: Some(format!("Not an HTTP response: {:?}", line)),
+                    status_line: Some(line),
+                    elapsed_ms: millis(start),
+                },
+            },
+            Err(e) => HttpCheck {
+                status: None,
+                status_line: None,
+                elapsed_ms: millis(start),
+                error: Some(e.to_string()),
+            },
+        };
+        output.reachable = http.status.is_some();
+        output.http = Some(http);
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(parse_status("HTTP/1.1 204 No Content"), Some(204));
+        assert_eq!(parse_status("HTTP/2 301"), Some(301));
+        assert_eq!(parse_status("SSH-2.0-OpenSSH_9.6"), None);
+        assert_eq!(parse_status(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_network_check() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await?;
+            let mut request = vec![0; 1024];
+            let n = socket.read(&mut request).await?;
+            assert!(request[..n].starts_with(b"HEAD /health HTTP/1.1\r\n"));
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .await?;
+            std::io::Result::Ok(())
+        });
+
+        let mut params = Params::new("127.0.0.1");
+        params.port = Some(port);
+        params.http_head = true;
+        params.http_path = "/health".to_string();
+        let output = NetworkCheck.execute(params).await?;
+        server.await.unwrap()?;
+        assert_eq!(output.addresses, vec!["127.0.0.1"]);
+        assert!(output.tcp.as_ref().unwrap().connected);
+        assert_eq!(output.http.as_ref().unwrap().status, Some(200));
+        assert!(output.reachable);
+
+        // Nothing listens on the port once the listener is gone
+        let mut params = Params::new("127.0.0.1");
+        params.port = Some(port);
+        let output = NetworkCheck.execute(params).await?;
+        let tcp = output.tcp.unwrap();
+        assert!(!tcp.connected);
+        assert!(tcp.error.is_some());
+        assert!(!output.reachable);
+
+        Ok(())
+    }
+}
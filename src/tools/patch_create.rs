@@ -0,0 +1,229 @@
+//! Patch creation tool implementation
+//!
+//! Produces a unified diff that `file_patch` is guaranteed to apply: every patch
+//! is applied back to the original in memory before it is returned.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use similar::{ChangeTag, TextDiff};
+use std::path::Path;
+
+use super::{Annotations, Tool};
+use super::file_patch::apply_unified_patch;
+use crate::{Error, Result, paths, vfs};
+
+/// Patch creation tool
+#[derive(Clone, Copy)]
+pub struct PatchCreate;
+
+/// Parameters for the patch creation tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Path of the original file
+    #[serde(deserialize_with = "crate::roots::path")]
+    pub path: String,
+
+    /// Proposed new content of the file
+    #[serde(default)]
+    pub new_content: Option<String>,
+
+    /// Path of a file holding the new content (instead of `new_content`)
+    #[serde(default)]
+    pub new_path: Option<String>,
+
+    /// Context lines to include around changes
+    #[serde(default = "default_context")]
+    pub context_lines: usize,
+}
+
+impl Params {
+    /// Create a patch for the file at `path`
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            new_content: None,
+            new_path: None,
+            context_lines: default_context(),
+        }
+    }
+}
+
+fn default_context() -> usize {
+    3
+}
+
+/// Output of the patch creation tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Path of the original file
+    pub path: String,
+
+    /// Unified diff that turns the original into the new content (empty if identical)
+    pub patch: String,
+
+    /// Whether the original and the new content are identical
+    pub identical: bool,
+
+    /// Number of hunks in the patch
+    pub hunks: usize,
+
+    /// Number of added lines
+    pub additions: usize,
+
+    /// Number of removed lines
+    pub deletions: usize,
+}
+
+/// Read a text file, rejecting anything that is not a regular file
+async fn read_text_file(path: &str) -> Result<String> {
+    let fs = vfs::current();
+    paths::require_file(fs.as_ref(), Path::new(path)).await?;
+    Ok(fs.read_to_string(Path::new(path)).await?)
+}
+
+#[async_trait]
+impl Tool for PatchCreate {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "patch_create"
+    }
+
+    fn description(&self) -> &str {
+        "Create a unified diff that file_patch can apply"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let original = read_text_file(&params.path).await?;
+
+        let (new_content, new_label) = match (params.new_content, &params.new_path) {
+            (Some(content), None) => (content, params.path.clone()),
+            (None, Some(new_path)) => (read_text_file(new_path).await?, new_path.clone()),
+            _ => {
+                return Err(Error::InvalidParam(
+                    "Exactly one of new_content or new_path must be provided".to_string(),
+                ));
+            }
+        };
+
+        let diff = TextDiff::from_lines(&original, &new_content);
+
+        let (mut additions, mut deletions) = (0, 0);
+        for change in diff.iter_all_changes() {
+            match change.tag() {
+                ChangeTag::Insert => additions += 1,
+                ChangeTag::Delete => deletions += 1,
+                ChangeTag::Equal => {}
+            }
+        }
+
+        let identical = additions == 0 && deletions == 0;
+        let hunks = diff.grouped_ops(params.context_lines).len();
+
+        let patch = if identical {
+            String::new()
+        } else {
+            diff.unified_diff()
+                .context_radius(params.context_lines)
+                .header(&params.path, &new_label)
+                .to_string()
+        };
+
+        // Make sure the patch reproduces the new content exactly
+        if !identical {
+            let (patched, results) = apply_unified_patch(original, &patch, 0)?;
+            if patched != new_content || results.iter().any(|h| !h.applied) {
+                return Err(Error::Other(
+                    "Generated patch does not round-trip (mixed line endings?)".to_string(),
+                ));
+            }
+        }
+
+        Ok(Output {
+            path: params.path,
+            patch,
+            identical,
+            hunks,
+            additions,
+            deletions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::file_patch::{self, FilePatch, PatchType};
+    use tokio::fs;
+
+    #[tokio::test]
+    async fn test_patch_create_round_trip() -> Result<()> {
+        let test_file = std::env::temp_dir().join(format!(
+            "test_patch_create_{}_{}.txt",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        let original: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&test_file, &original).await?;
+
+        // Change a line near each end and drop the trailing newline
+        let new_content = original
+            .replace("line 2\n", "line two\n")
+            .replace("line 19\n", "")
+            .trim_end()
+            .to_string();
+
+        let result = PatchCreate
+            .execute(Params {
+                path: test_file.to_string_lossy().to_string(),
+                new_content: Some(new_content.clone()),
+                new_path: None,
+                context_lines: 3,
+            })
+            .await?;
+
+        assert!(!result.identical);
+        assert_eq!(result.hunks, 2);
+        assert_eq!(result.additions, 2);
+        assert_eq!(result.deletions, 3);
+
+        // The same artifact applies with file_patch
+        FilePatch
+            .execute(file_patch::Params {
+                path: test_file.to_string_lossy().to_string(),
+                patch: result.patch,
+                patch_type: PatchType::Unified,
+                create_backup: false,
+                fuzz: 0,
+                check_only: false,
+                base_dir: None,
+                expected_sha256: None,
+                expected_mtime: None,
+                hunks: Vec::new(),
+            })
+            .await?;
+        assert_eq!(fs::read_to_string(&test_file).await?, new_content);
+
+        // Identical content produces an empty patch
+        let result = PatchCreate
+            .execute(Params {
+                path: test_file.to_string_lossy().to_string(),
+                new_content: Some(new_content),
+                new_path: None,
+                context_lines: 3,
+            })
+            .await?;
+        assert!(result.identical);
+        assert!(result.patch.is_empty());
+
+        fs::remove_file(&test_file).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+//! Path info tool implementation
+//!
+//! Answers the small questions about a path that would otherwise take a shell
+//! call: its normalized and canonical forms, whether it exists and what it is,
+//! and where it is relative to another directory. Components can be joined
+//! onto the path first; they must stay below it, so a `..` or absolute
+//! component from an untrusted source can't point somewhere else.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::path::{Component, Path, PathBuf};
+
+use super::{Annotations, Tool};
+use crate::vfs::FileKind;
+use crate::{Error, Result, paths, vfs};
+
+/// Path info tool
+#[derive(Clone, Copy)]
+pub struct PathInfo;
+
+/// Parameters for the path info tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// The path to look at
+    #[serde(deserialize_with = "crate::roots::path")]
+    pub path: String,
+
+    /// Components to join onto `path`. Each must be relative and the result
+    /// must stay below `path`.
+    #[serde(default)]
+    pub join: Vec<String>,
+
+    /// Directory to express the path relative to
+    #[serde(default, deserialize_with = "crate::roots::optional_path")]
+    pub relative_to: Option<String>,
+}
+
+impl Params {
+    /// Look at `path`
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            join: Vec::new(),
+            relative_to: None,
+        }
+    }
+}
+
+/// Kind of entry found at a path
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PathKind {
+    /// A regular file
+    File,
+    /// A directory
+    Directory,
+    /// A symbolic link whose target doesn't exist
+    Symlink,
+}
+
+impl From<FileKind> for PathKind {
+    fn from(kind: FileKind) -> Self {
+        match kind {
+            FileKind::File => Self::File,
+            FileKind::Dir => Self::Directory,
+            FileKind::Symlink => Self::Symlink,
+        }
+    }
+}
+
+/// Output of the path info tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// The path with `join` applied and `.` and `..` resolved lexically
+    pub path: String,
+
+    /// Absolute form of `path`, without following links
+    pub absolute: String,
+
+    /// Absolute form with links resolved, if the path exists
+    pub canonical: Option<String>,
+
+    /// Whether anything exists at the path
+    pub exists: bool,
+
+    /// What exists at the path
+    pub kind: Option<PathKind>,
+
+    /// The path relative to `relative_to`, e.g. `../src/lib.rs`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative: Option<String>,
+}
+
+/// Resolve `.` and `..` without touching the filesystem. A `..` at the start
+/// of a relative path is kept, and one above the root is dropped.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        normalized.push(".");
+    }
+    normalized
+}
+
+/// Join `components` onto `base`, rejecting any that would leave it
+fn join_below(base: &Path, components: &[String]) -> Result<PathBuf> {
+    let mut joined = base.to_path_buf();
+    let mut depth = 0usize;
+    for part in components {
+        for component in Path::new(part).components() {
+            match component {
+                Component::Normal(name) => {
+                    joined.push(name);
+                    depth += 1;
+                }
+                Component::CurDir => {}
+                Component::ParentDir if depth > 0 => {
+                    joined.pop();
+                    depth -= 1;
+                }
+                _ => {
+                    return Err(Error::InvalidParam(format!(
+                        "Component '{}' would leave {}",
+                        part,
+                        base.display()
+                    )));
+                }
+            }
+        }
+    }
+    Ok(joined)
+}
+
+/// `path` relative to `base`, both absolute and normalized. `None` when they
+/// share no root, like paths on different Windows drives.
+fn relative(path: &Path, base: &Path) -> Option<PathBuf> {
+    let path: Vec<_> = path.components().collect();
+    let base: Vec<_> = base.components().collect();
+    if path.first() != base.first() {
+        return None;
+    }
+
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for component in &path[common..] {
+        relative.push(component);
+    }
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    Some(relative)
+}
+
+/// Absolute, normalized form of `path`
+fn absolute(path: &Path) -> Result<PathBuf> {
+    Ok(normalize(&std::path::absolute(path)?))
+}
+
+#[async_trait]
+impl Tool for PathInfo {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "path_info"
+    }
+
+    fn description(&self) -> &str {
+        "Normalize a path, check whether it exists and compute it relative to another"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        if params.path.is_empty() {
+            return Err(Error::InvalidParam("path must not be empty".to_string()));
+        }
+        let path = normalize(&join_below(Path::new(&params.path), &params.join)?);
+        let absolute_path = absolute(&path)?;
+
+        let fs = vfs::current();
+        let metadata = fs.metadata(&path).await.ok();
+        let canonical = match metadata {
+            Some(_) => fs.canonicalize(&path).await.ok(),
+            None => None,
+        };
+
+        let relative_path = match &params.relative_to {
+            Some(base) => {
+                let base = absolute(Path::new(base))?;
+                Some(relative(&absolute_path, &base).ok_or_else(|| {
+                    Error::InvalidParam(format!(
+                        "{} and {} have no common root",
+                        absolute_path.display(),
+                        base.display()
+                    ))
+                })?)
+            }
+            None => None,
+        };
+
+        Ok(Output {
+            path: paths::to_string(&path),
+            absolute: paths::to_string(&absolute_path),
+            canonical: canonical.as_deref().map(paths::to_string),
+            exists: metadata.is_some(),
+            kind: metadata.map(|metadata| metadata.kind.into()),
+            relative: relative_path.as_deref().map(paths::to_string),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("a/./b/../c")), Path::new("a/c"));
+        assert_eq!(normalize(Path::new("../a/..")), Path::new(".."));
+        assert_eq!(normalize(Path::new("a/..")), Path::new("."));
+        assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+    }
+
+    #[test]
+    fn test_join_below() {
+        let base = Path::new("/work");
+        assert_eq!(
+            join_below(base, &["src/x".to_string(), "../lib.rs".to_string()]).unwrap(),
+            Path::new("/work/src/lib.rs")
+        );
+        assert!(join_below(base, &["../etc".to_string()]).is_err());
+        assert!(join_below(base, &["a/../../etc".to_string()]).is_err());
+        assert!(join_below(base, &["/etc".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_relative() {
+        assert_eq!(
+            relative(Path::new("/work/src/lib.rs"), Path::new("/work/tests")),
+            Some(PathBuf::from("../src/lib.rs"))
+        );
+        assert_eq!(
+            relative(Path::new("/work"), Path::new("/work")),
+            Some(PathBuf::from("."))
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_path_info() -> Result<()> {
+        let fs = Arc::new(MemoryFs::new().with_file("/work/src/lib.rs", "pub fn f() {}\n"));
+        vfs::scope(fs, async {
+            let tool = PathInfo;
+
+            let result = tool
+                .execute(Params {
+                    join: vec!["src".to_string(), "./lib.rs".to_string()],
+                    relative_to: Some("/work/tests".to_string()),
+                    ..Params::new("/work/docs/..")
+                })
+                .await?;
+            assert_eq!(result.path, "/work/src/lib.rs");
+            assert_eq!(result.absolute, "/work/src/lib.rs");
+            assert_eq!(result.canonical.as_deref(), Some("/work/src/lib.rs"));
+            assert!(result.exists);
+            assert_eq!(result.kind, Some(PathKind::File));
+            assert_eq!(result.relative.as_deref(), Some("../src/lib.rs"));
+
+            let result = tool.execute(Params::new("/work/src")).await?;
+            assert_eq!(result.kind, Some(PathKind::Directory));
+
+            let result = tool.execute(Params::new("/work/missing.rs")).await?;
+            assert!(!result.exists);
+            assert_eq!(result.kind, None);
+            assert_eq!(result.canonical, None);
+
+            let escape = Params {
+                join: vec!["../../etc/passwd".to_string()],
+                ..Params::new("/work")
+            };
+            assert!(matches!(
+                tool.execute(escape).await,
+                Err(Error::InvalidParam(_))
+            ));
+            Ok(())
+        })
+        .await
+    }
+}
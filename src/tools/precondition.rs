@@ -0,0 +1,100 @@
+//! Optimistic concurrency checks shared by the tools that modify files
+
+use sha2::{Digest, Sha256};
+#[cfg(any(feature = "file-edit", feature = "file-patch", feature = "file-write"))]
+use {
+    crate::{Error, Result, vfs},
+    chrono::DateTime,
+    std::path::Path,
+};
+
+/// Compute the lowercase hex SHA-256 digest of some bytes
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Fail with a conflict error if the file no longer matches what the caller
+/// last saw. `expected_mtime` is an RFC 3339 timestamp compared to the second.
+#[cfg(any(feature = "file-edit", feature = "file-patch", feature = "file-write"))]
+pub(crate) async fn check_unchanged(
+    path: &Path,
+    expected_sha256: Option<&str>,
+    expected_mtime: Option<&str>,
+) -> Result<()> {
+    if expected_sha256.is_none() && expected_mtime.is_none() {
+        return Ok(());
+    }
+
+    let fs = vfs::for_path(path)?;
+    if !fs.exists(path).await {
+        return Err(Error::Conflict(format!(
+            "File no longer exists: {}",
+            path.display()
+        )));
+    }
+
+    if let Some(expected) = expected_sha256 {
+        let actual = sha256_hex(&fs.read(path).await?);
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(Error::Conflict(format!(
+                "File {} has changed: expected SHA-256 {}, found {}",
+                path.display(),
+                expected,
+                actual
+            )));
+        }
+    }
+
+    if let Some(expected) = expected_mtime {
+        let expected_secs = DateTime::parse_from_rfc3339(expected)
+            .map_err(|e| Error::InvalidParam(format!("Invalid expected_mtime: {}", e)))?
+            .timestamp();
+        let actual = DateTime::<chrono::Utc>::from(fs.metadata(path).await?.modified()?);
+        if actual.timestamp() != expected_secs {
+            return Err(Error::Conflict(format!(
+                "File {} has changed: expected modification time {}, found {}",
+                path.display(),
+                expected,
+                actual.to_rfc3339()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::fs;
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_unchanged() -> Result<()> {
+        let test_file = std::env::temp_dir().join(format!(
+            "precondition_test_{}.txt",
+            chrono::Utc::now().timestamp_millis()
+        ));
+        fs::write(&test_file, "abc").await?;
+
+        let hash = sha256_hex(b"abc");
+        check_unchanged(&test_file, Some(&hash), None).await?;
+
+        let result = check_unchanged(&test_file, Some(&sha256_hex(b"other")), None).await;
+        assert!(matches!(result, Err(Error::Conflict(_))));
+
+        fs::remove_file(&test_file).await?;
+
+        let result = check_unchanged(&test_file, Some(&hash), None).await;
+        assert!(matches!(result, Err(Error::Conflict(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+//! Recent files tool implementation
+//!
+//! Lists the most recently modified files under a directory, newest first,
+//! which is the quickest way to see what a build, generator or formatter just
+//! touched.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::path::Path;
+use std::time::SystemTime;
+
+use super::{Annotations, Tool};
+use crate::vfs::WalkOptions;
+use crate::{Error, Result, cancel, paths, vfs};
+
+/// Recent files tool
+#[derive(Clone, Copy)]
+pub struct RecentFiles;
+
+/// Parameters for the recent files tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Directory to search in
+    #[serde(default = "default_directory", deserialize_with = "crate::roots::path")]
+    pub directory: String,
+
+    /// Maximum number of files to return (0 means no limit)
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+
+    /// Glob patterns a file's path must match one of, e.g. `*.rs`
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Glob patterns of paths to leave out, e.g. `*/target/*`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// Include hidden files and files below hidden directories, like `.git`
+    #[serde(default)]
+    pub include_hidden: bool,
+}
+
+fn default_directory() -> String {
+    ".".to_string()
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+impl Params {
+    /// List the most recently modified files under `directory`
+    pub fn new(directory: impl Into<String>) -> Self {
+        Self {
+            directory: directory.into(),
+            limit: default_limit(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            include_hidden: false,
+        }
+    }
+}
+
+/// A recently modified file
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct RecentFile {
+    /// Path of the file
+    pub path: String,
+
+    /// Last modification time (RFC 3339)
+    pub modified: String,
+
+    /// Seconds since the last modification
+    pub age_seconds: u64,
+
+    /// Size in bytes
+    pub size: u64,
+}
+
+/// Output of the recent files tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// The directory that was searched
+    pub directory: String,
+
+    /// Files, most recently modified first
+    pub files: Vec<RecentFile>,
+
+    /// Number of files that passed the filters before the limit was applied
+    pub total_files: usize,
+}
+
+/// Compile glob patterns, rejecting invalid ones
+fn compile(patterns: &[String]) -> Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|p| {
+            Pattern::new(p)
+                .map_err(|e| Error::InvalidParam(format!("Invalid pattern '{}': {}", p, e)))
+        })
+        .collect()
+}
+
+/// Whether any component of a relative path starts with a dot
+fn is_hidden(relative: &Path) -> bool {
+    relative
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+#[async_trait]
+impl Tool for RecentFiles {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "recent_files"
+    }
+
+    fn description(&self) -> &str {
+        "List the most recently modified files under a directory, newest first"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let include = compile(&params.include)?;
+        let exclude = compile(&params.exclude)?;
+
+        let fs = vfs::current();
+        let directory = paths::canonical_dir(fs.as_ref(), Path::new(&params.directory)).await?;
+        let directory = directory.as_path();
+
+        let options = WalkOptions {
+            max_depth: usize::MAX,
+            follow_links: false,
+        };
+        let mut files = Vec::new();
+        for entry in fs.walk(directory, options).await? {
+            cancel::check()?;
+            if !entry.metadata.is_file() {
+                continue;
+            }
+            // Files without a modification time can't be ranked
+            let Some(modified) = entry.metadata.modified else {
+                continue;
+            };
+            if !params.include_hidden && entry.path.strip_prefix(directory).is_ok_and(is_hidden) {
+                continue;
+            }
+            let path = paths::to_string(&entry.path);
+            if exclude.iter().any(|p| p.matches(&path))
+                || (!include.is_empty() && !include.iter().any(|p| p.matches(&path)))
+            {
+                continue;
+            }
+            files.push((modified, path, entry.metadata.len()));
+        }
+
+        // Newest first, with the path deciding between equal times
+        files.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        let total_files = files.len();
+        if params.limit > 0 {
+            files.truncate(params.limit);
+        }
+
+        let now = SystemTime::now();
+        let files = files
+            .into_iter()
+            .map(|(modified, path, size)| RecentFile {
+                path,
+                modified: DateTime::<Utc>::from(modified).to_rfc3339(),
+                age_seconds: now.duration_since(modified).map_or(0, |age| age.as_secs()),
+                size,
+            })
+            .collect();
+
+        Ok(Output {
+            directory: paths::to_string(directory),
+            files,
+            total_files,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{Filesystem, MemoryFs};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_recent_files() -> Result<()> {
+        let fs = Arc::new(
+            MemoryFs::new()
+                .with_file("/work/src/lib.rs", "lib")
+                .with_file("/work/src/main.rs", "main")
+                .with_file("/work/README.md", "readme")
+                .with_file("/work/target/out.o", "object")
+                .with_file("/work/.git/index", "index"),
+        );
+        let now = SystemTime::now();
+        for (i, path) in [
+            "/work/README.md",
+            "/work/src/lib.rs",
+            "/work/src/main.rs",
+            "/work/target/out.o",
+            "/work/.git/index",
+        ]
+        .iter()
+        .enumerate()
+        {
+            let modified = now - Duration::from_secs(3600 - 60 * i as u64);
+            fs.set_times(Path::new(path), None, Some(modified)).await?;
+        }
+
+        vfs::scope(fs, async {
+            let tool = RecentFiles;
+
+            let result = tool.execute(Params::new("/work")).await?;
+            let found: Vec<_> = result.files.iter().map(|f| f.path.as_str()).collect();
+            assert_eq!(
+                found,
+                [
+                    "/work/target/out.o",
+                    "/work/src/main.rs",
+                    "/work/src/lib.rs",
+                    "/work/README.md"
+                ]
+            );
+            assert_eq!(result.files[1].size, 4);
+            assert!(result.files[0].age_seconds >= 3600 - 180);
+
+            let params = Params {
+                limit: 1,
+                include: vec!["*.rs".to_string()],
+                exclude: vec!["*/target/*".to_string()],
+                ..Params::new("/work")
+            };
+            let result = tool.execute(params).await?;
+            assert_eq!(result.total_files, 2);
+            assert_eq!(result.files[0].path, "/work/src/main.rs");
+
+            let params = Params {
+                include_hidden: true,
+                ..Params::new("/work")
+            };
+            let result = tool.execute(params).await?;
+            assert_eq!(result.files[0].path, "/work/.git/index");
+
+            let params = Params {
+                include: vec!["[".to_string()],
+                ..Params::new("/work")
+            };
+            let result = tool.execute(params).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+            let result = tool.execute(Params::new("/work/README.md")).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+            Ok(())
+        })
+        .await
+    }
+}
@@ -3,27 +3,270 @@
 //! This tool provides a more structured and secure way to execute shell commands.
 //! Unlike direct shell execution, it separates the command from its arguments,
 //! making it easier for systems to evaluate and potentially sandbox requests.
+//!
+//! Commands that genuinely need a shell (pipes, globs, `&&` chaining) can opt in
+//! with `use_shell`, but only when the embedding application has allowed it via
+//! [`Shell::with_shell_mode`]. Shell mode hands the whole string to `sh -c`
+//! (or `cmd /C`) and bypasses the metacharacter validation, so it is unsafe with
+//! untrusted input. While it is off, shell interpreters (`sh`, `bash`, `cmd`,
+//! `powershell` and the like) can't be run as the command or as a pipeline
+//! stage either, so `sh -c` can't be used to get around it.
+//!
+//! `script` is the portable form of shell mode: the snippet runs under
+//! `script_shell` (`sh` by default, `cmd` on Windows, or PowerShell) with the
+//! quoting each one needs, and `args` are passed to it as its own arguments
+//! rather than spliced into the text. It needs shell mode enabled too.
+//!
+//! A workspace root can be configured with [`Shell::with_root`]. Relative working
+//! directories are then resolved against that root instead of the server's own
+//! working directory, and any `cwd` that escapes the root is rejected.
 
 use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose};
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::time::Duration;
-use tokio::process::Command;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::task::JoinHandle;
 use tokio::time;
 
 use super::Tool;
+use super::file_write::ContentType;
+use crate::{cancel, paths, quota};
 use crate::{Error, Result};
 
 /// Shell command execution tool
-#[derive(Clone, Copy)]
-pub struct Shell;
+#[derive(Clone, Default)]
+pub struct Shell {
+    allow_shell_mode: bool,
+    root: Option<PathBuf>,
+}
+
+impl Shell {
+    /// Create a shell tool with shell mode disabled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow or forbid requests that set `use_shell`
+    pub fn with_shell_mode(mut self, allow: bool) -> Self {
+        self.allow_shell_mode = allow;
+        self
+    }
+
+    /// Confine working directories to `root` and resolve relative ones against it
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Validate and spawn the command (and any pipeline stages), feeding stdin
+    /// and draining output in the background, keeping only `limit` lines of
+    /// each stream if given
+    pub(crate) async fn start(
+        &self,
+        params: &Params,
+        limit: Option<(usize, OutputFrom)>,
+    ) -> Result<RunningCommand> {
+        // Prepare the command
+        let mut first = if let Some(script) = &params.script {
+            if !params.command.is_empty() || params.use_shell {
+                return Err(Error::InvalidParam(
+                    "script cannot be combined with command or use_shell".to_string(),
+                ));
+            }
+            self.check_shell_mode(params, "script")?;
+            // The script's own arguments, quoted for its shell
+            script_command(script, params.script_shell, &params.args)?
+        } else if params.command.is_empty() {
+            return Err(Error::InvalidParam(
+                "Either command or script is required".to_string(),
+            ));
+        } else if params.use_shell {
+            self.check_shell_mode(params, "use_shell")?;
+            shell_command(&params.command)
+        } else {
+            validate_command(&params.command)?;
+            self.check_interpreter(&params.command)?;
+            Command::new(&params.command)
+        };
+
+        // Add arguments
+        if params.script.is_none() && !params.args.is_empty() {
+            first.args(&params.args);
+        }
+
+        // Prepare the remaining pipeline stages
+        let mut commands = vec![first];
+        for stage in &params.pipeline {
+            validate_command(&stage.command)?;
+            self.check_interpreter(&stage.command)?;
+            let mut cmd = Command::new(&stage.command);
+            cmd.args(&stage.args);
+            commands.push(cmd);
+        }
+
+        // Working directory, confined to the workspace root if one is set
+        let cwd = self.resolve_cwd(params.cwd.as_deref()).await?;
+
+        for cmd in &mut commands {
+            // Set environment variables
+            if !params.env.is_empty() {
+                cmd.envs(params.env.iter());
+            }
+
+            if let Some(cwd) = &cwd {
+                cmd.current_dir(cwd);
+            }
+
+            cmd.kill_on_drop(true);
+            cmd.stdout(std::process::Stdio::piped());
+
+            if params.capture_stderr {
+                cmd.stderr(std::process::Stdio::piped());
+            } else {
+                cmd.stderr(std::process::Stdio::null());
+            }
+        }
+
+        // Only the first stage reads the provided stdin
+        let stdin_data = decode_stdin(params)?;
+        if stdin_data.is_some() {
+            commands[0].stdin(std::process::Stdio::piped());
+        } else {
+            commands[0].stdin(std::process::Stdio::null());
+        }
+
+        // Start each stage with its stdin connected to the previous stdout
+        let mut children = Vec::with_capacity(commands.len());
+        let mut stderr_pipes = Vec::new();
+        let mut stdout: Option<ChildStdout> = None;
+
+        for mut cmd in commands {
+            if let Some(previous) = stdout.take() {
+                let stdin: std::process::Stdio = previous.try_into().map_err(Error::Io)?;
+                cmd.stdin(stdin);
+            }
+
+            let mut child = cmd.spawn().map_err(Error::Io)?;
+            stderr_pipes.extend(child.stderr.take());
+            stdout = child.stdout.take();
+            children.push(child);
+        }
+
+        // Feed stdin from a separate task so a chatty child can't deadlock us;
+        // dropping the pipe afterwards signals EOF
+        if let (Some(mut pipe), Some(data)) = (children[0].stdin.take(), stdin_data) {
+            tokio::spawn(async move {
+                // The child may exit without reading everything
+                let _ = pipe.write_all(&data).await;
+            });
+        }
+
+        // Drain stdout and stderr while the command runs, so that output is
+        // kept even if the command has to be killed
+        let stdout = stdout.map(|pipe| PipeReader::spawn(pipe, limit));
+        let stderr =
+            (!stderr_pipes.is_empty()).then(|| PipeReader::spawn_all(stderr_pipes, limit));
+
+        Ok(RunningCommand {
+            children,
+            stdout,
+            stderr,
+        })
+    }
+
+    /// Reject `params` for running through a shell (via `option`) unless the
+    /// server allows it, or if it also asks for a pipeline
+    fn check_shell_mode(&self, params: &Params, option: &str) -> Result<()> {
+        if !self.allow_shell_mode {
+            return Err(Error::PermissionDenied(
+                "Shell mode is disabled for this server".to_string(),
+            ));
+        }
+        if !params.pipeline.is_empty() {
+            return Err(Error::InvalidParam(format!(
+                "pipeline cannot be combined with {}",
+                option
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reject running a shell interpreter directly unless the server allows
+    /// shell mode, since it would run arbitrary shell syntax from its arguments
+    fn check_interpreter(&self, command: &str) -> Result<()> {
+        if self.allow_shell_mode {
+            return Ok(());
+        }
+        let name = Path::new(command)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if SHELL_INTERPRETERS.contains(&name.as_str()) {
+            return Err(Error::PermissionDenied(format!(
+                "Running the shell interpreter '{}' needs shell mode, which is disabled for this server",
+                command
+            )));
+        }
+        Ok(())
+    }
+
+    /// Resolve the requested working directory against the configured root
+    async fn resolve_cwd(&self, cwd: Option<&str>) -> Result<Option<PathBuf>> {
+        let root = match &self.root {
+            Some(root) => fs::canonicalize(root).await.map_err(|e| {
+                Error::Other(format!(
+                    "Workspace root {} is not accessible: {}",
+                    root.display(),
+                    e
+                ))
+            })?,
+            None => {
+                return match cwd {
+                    Some(cwd) if !Path::new(cwd).is_dir() => Err(Error::NotFound(format!(
+                        "Working directory does not exist: {}",
+                        cwd
+                    ))),
+                    _ => Ok(cwd.map(PathBuf::from)),
+                };
+            }
+        };
+
+        let Some(cwd) = cwd else {
+            return Ok(Some(root));
+        };
+
+        let resolved = fs::canonicalize(root.join(cwd))
+            .await
+            .map_err(|_| Error::NotFound(format!("Working directory does not exist: {}", cwd)))?;
+
+        paths::check_within(&resolved, &root)
+            .map_err(|e| e.with_context(&format!("Working directory {}", cwd)))?;
+
+        if !resolved.is_dir() {
+            return Err(Error::InvalidParam(format!(
+                "Working directory is not a directory: {}",
+                cwd
+            )));
+        }
+
+        Ok(Some(resolved))
+    }
+}
 
 /// Parameters for the shell tool
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Params {
-    /// The command to execute (without arguments)
+    /// The command to execute (without arguments), or a full command line
+    /// when `use_shell` is set. Leave empty when `script` is given.
+    #[serde(default)]
     pub command: String,
 
     /// Array of arguments to pass to the command
@@ -35,7 +278,7 @@ pub struct Params {
     pub env: HashMap<String, String>,
 
     /// Working directory for the command
-    #[serde(default)]
+    #[serde(default, deserialize_with = "crate::roots::optional_path")]
     pub cwd: Option<String>,
 
     /// Whether to capture stderr in the output
@@ -45,10 +288,125 @@ pub struct Params {
     /// Timeout in milliseconds (0 for no timeout)
     #[serde(default)]
     pub timeout_ms: u64,
+
+    /// Data to write to the command's stdin (stdin is empty if not provided)
+    #[serde(default)]
+    pub stdin: Option<String>,
+
+    /// How to interpret the provided stdin data
+    #[serde(default)]
+    pub stdin_type: ContentType,
+
+    /// Run `command` through `sh -c` (`cmd /C` on Windows). UNSAFE: allows
+    /// arbitrary shell syntax and is rejected unless enabled by the server
+    #[serde(default)]
+    pub use_shell: bool,
+
+    /// A shell snippet to run instead of `command`, with `args` as its
+    /// arguments (`$1`, `%1` or `$args`). UNSAFE like `use_shell`, and
+    /// likewise rejected unless enabled by the server
+    #[serde(default)]
+    pub script: Option<String>,
+
+    /// The shell that runs `script`: `sh`, or `cmd` by default on Windows
+    #[serde(default)]
+    pub script_shell: ScriptShell,
+
+    /// Further commands to pipe the output through, like `command | a | b`.
+    /// Each stage's stdout feeds the next stage's stdin.
+    #[serde(default)]
+    pub pipeline: Vec<PipelineStage>,
+
+    /// Maximum number of lines to return for stdout and stderr each
+    #[serde(default)]
+    pub max_output_lines: Option<usize>,
+
+    /// Which end of the output to keep when it exceeds `max_output_lines`
+    #[serde(default)]
+    pub output_from: OutputFrom,
+}
+
+impl Params {
+    /// Run `command`
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            cwd: None,
+            capture_stderr: false,
+            timeout_ms: 0,
+            stdin: None,
+            stdin_type: ContentType::default(),
+            use_shell: false,
+            script: None,
+            script_shell: ScriptShell::default(),
+            pipeline: Vec::new(),
+            max_output_lines: None,
+            output_from: OutputFrom::default(),
+        }
+    }
+
+    /// Run the shell snippet `script` with the platform's default shell
+    pub fn script(script: impl Into<String>) -> Self {
+        Self {
+            script: Some(script.into()),
+            ..Self::new("")
+        }
+    }
+
+    /// What was run, as reported back: the script or the command
+    pub(crate) fn reported_command(&self) -> &str {
+        self.script.as_deref().unwrap_or(&self.command)
+    }
+}
+
+/// Shell that runs a `script`
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptShell {
+    /// POSIX `sh -c`
+    Sh,
+    /// `cmd /C` (Windows only)
+    Cmd,
+    /// `powershell -Command` on Windows, `pwsh -Command` elsewhere
+    Powershell,
+}
+
+impl Default for ScriptShell {
+    fn default() -> Self {
+        if cfg!(target_os = "windows") {
+            Self::Cmd
+        } else {
+            Self::Sh
+        }
+    }
+}
+
+/// Which part of long output to keep
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFrom {
+    /// Keep the first lines
+    #[default]
+    Head,
+    /// Keep the last lines
+    Tail,
+}
+
+/// A command in a pipeline
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct PipelineStage {
+    /// The command to execute (without arguments)
+    pub command: String,
+
+    /// Array of arguments to pass to the command
+    #[serde(default)]
+    pub args: Vec<String>,
 }
 
 /// Output of the shell tool
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Output {
     /// The command that was executed
     pub command: String,
@@ -56,9 +414,17 @@ pub struct Output {
     /// The arguments that were passed to the command
     pub args: Vec<String>,
 
-    /// The exit status code of the command
+    /// The exit status code of the command (-1 if it was killed by a signal)
     pub status: i32,
 
+    /// The signal that terminated the command, e.g. "SIGSEGV" (Unix only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<String>,
+
+    /// Exit status of every stage when a pipeline was run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pipeline_status: Vec<i32>,
+
     /// Whether the command was successful (exit code 0)
     pub success: bool,
 
@@ -71,8 +437,17 @@ pub struct Output {
 
     /// Whether the command timed out
     pub timed_out: bool,
+
+    /// Whether stdout or stderr was cut down to `max_output_lines`
+    pub truncated: bool,
 }
 
+/// Programs that run shell syntax given in their arguments, which may only be
+/// run when shell mode is allowed
+const SHELL_INTERPRETERS: &[&str] = &[
+    "sh", "bash", "zsh", "dash", "ksh", "csh", "tcsh", "fish", "cmd", "powershell", "pwsh",
+];
+
 /// Validate the command to ensure it doesn't contain shell metacharacters
 fn validate_command(command: &str) -> Result<()> {
     // Check if the command contains whitespace or shell metacharacters
@@ -87,53 +462,331 @@ fn validate_command(command: &str) -> Result<()> {
     Ok(())
 }
 
-#[async_trait]
-impl Tool for Shell {
-    type Params = Params;
-    type Output = Output;
+/// Build a command that runs a full command line through the platform shell.
+/// Any extra args become the script's positional parameters on Unix.
+fn shell_command(command_line: &str) -> Command {
+    #[cfg(target_os = "windows")]
+    {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command_line);
+        cmd
+    }
 
-    fn name(&self) -> &str {
-        "shell"
+    #[cfg(not(target_os = "windows"))]
+    {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command_line).arg("sh");
+        cmd
     }
+}
 
-    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
-        // Validate the command
-        validate_command(&params.command)?;
+/// Build a command that runs `script` under `shell` with `args` as its
+/// arguments, quoted so the shell sees each one as a single word
+fn script_command(script: &str, shell: ScriptShell, args: &[String]) -> Result<Command> {
+    match shell {
+        ScriptShell::Sh => {
+            // Positional parameters are never re-parsed, so need no quoting
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(script).arg("sh").args(args);
+            Ok(cmd)
+        }
+        ScriptShell::Cmd => cmd_script(script, args),
+        ScriptShell::Powershell => {
+            let program = if cfg!(target_os = "windows") {
+                "powershell"
+            } else {
+                "pwsh"
+            };
+            let mut line = format!("& {{ {} }}", script);
+            for arg in args {
+                line.push_str(&format!(" '{}'", arg.replace('\'', "''")));
+            }
+            let mut cmd = Command::new(program);
+            cmd.args(["-NoProfile", "-NonInteractive", "-Command", &line]);
+            Ok(cmd)
+        }
+    }
+}
 
-        // Prepare the command
-        let mut cmd = Command::new(&params.command);
+/// `cmd /S /C "script args"`, passed verbatim because cmd doesn't parse its
+/// command line the way other programs do. `%` in args is still expanded.
+#[cfg(target_os = "windows")]
+fn cmd_script(script: &str, args: &[String]) -> Result<Command> {
+    let mut line = script.to_string();
+    for arg in args {
+        line.push_str(&format!(" \"{}\"", arg.replace('"', "\"\"")));
+    }
+    let mut cmd = Command::new("cmd");
+    cmd.raw_arg("/D /S /C").raw_arg(format!("\"{}\"", line));
+    Ok(cmd)
+}
 
-        // Add arguments
-        if !params.args.is_empty() {
-            cmd.args(&params.args);
-        }
+#[cfg(not(target_os = "windows"))]
+fn cmd_script(_script: &str, _args: &[String]) -> Result<Command> {
+    Err(Error::InvalidParam(
+        "script_shell 'cmd' is only available on Windows".to_string(),
+    ))
+}
+
+/// Name the signal that terminated a process, if any
+#[cfg(unix)]
+pub(crate) fn termination_signal(status: ExitStatus) -> Option<String> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let signal = status.signal()?;
+    // These numbers are the same on Linux and macOS
+    let name = match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        _ => return Some(format!("signal {}", signal)),
+    };
+    Some(name.to_string())
+}
+
+/// Name the signal that terminated a process, if any
+#[cfg(not(unix))]
+pub(crate) fn termination_signal(_status: ExitStatus) -> Option<String> {
+    None
+}
+
+/// How long to keep reading output after a timed-out command is killed
+pub(crate) const PIPE_DRAIN_GRACE: Duration = Duration::from_millis(100);
+
+/// A spawned command whose output is being collected in the background
+pub(crate) struct RunningCommand {
+    /// One process per pipeline stage, in order
+    pub(crate) children: Vec<Child>,
+    pub(crate) stdout: Option<PipeReader>,
+    pub(crate) stderr: Option<PipeReader>,
+}
+
+/// Wait for every process to exit
+pub(crate) async fn wait_children(children: &mut [Child]) -> std::io::Result<Vec<ExitStatus>> {
+    let mut statuses = Vec::with_capacity(children.len());
+    for child in children {
+        statuses.push(child.wait().await?);
+    }
+    Ok(statuses)
+}
+
+/// Kill any processes still running and collect every exit status
+pub(crate) async fn kill_children(children: &mut [Child]) -> Vec<Option<ExitStatus>> {
+    let mut statuses = Vec::with_capacity(children.len());
+    for child in children {
+        let _ = child.kill().await;
+        statuses.push(child.try_wait().ok().flatten());
+    }
+    statuses
+}
 
-        // Set environment variables
-        if !params.env.is_empty() {
-            cmd.envs(params.env.iter());
+/// Collects what is read from a child's output pipes in the background
+pub(crate) struct PipeReader {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    /// Lines kept instead of `buffer` when the output is limited
+    kept: Option<Arc<Mutex<KeptLines>>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl PipeReader {
+    /// Start reading the pipe until EOF
+    fn spawn<R: AsyncRead + Unpin + Send + 'static>(
+        pipe: R,
+        limit: Option<(usize, OutputFrom)>,
+    ) -> Self {
+        Self::spawn_all(vec![pipe], limit)
+    }
+
+    /// Start reading several pipes into one buffer, interleaved as data
+    /// arrives, keeping only `limit` lines if given
+    fn spawn_all<R: AsyncRead + Unpin + Send + 'static>(
+        pipes: Vec<R>,
+        limit: Option<(usize, OutputFrom)>,
+    ) -> Self {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let kept = limit
+            .map(|(max_lines, from)| Arc::new(Mutex::new(KeptLines::new(max_lines, from))));
+
+        let tasks = pipes
+            .into_iter()
+            .map(|mut pipe| {
+                let sink = Arc::clone(&buffer);
+                let kept = kept.clone();
+                tokio::spawn(async move {
+                    let mut chunk = [0u8; 8192];
+                    while let Ok(n) = pipe.read(&mut chunk).await {
+                        if n == 0 {
+                            break;
+                        }
+                        match &kept {
+                            Some(kept) => kept.lock().unwrap().push(&chunk[..n]),
+                            None => sink.lock().unwrap().extend_from_slice(&chunk[..n]),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            buffer,
+            kept,
+            tasks,
         }
+    }
+
+    /// The bytes read so far, shared with the reading task
+    #[cfg(feature = "job")]
+    pub(crate) fn buffer(&self) -> Arc<Mutex<Vec<u8>>> {
+        Arc::clone(&self.buffer)
+    }
 
-        // Set working directory if provided
-        if let Some(cwd) = &params.cwd {
-            let cwd_path = PathBuf::from(cwd);
-            if !cwd_path.exists() {
-                return Err(Error::InvalidParam(format!(
-                    "Working directory does not exist: {}",
-                    cwd
-                )));
+    /// Wait for EOF at most `grace` (or indefinitely), then stop reading
+    pub(crate) async fn wait(&mut self, grace: Option<Duration>) {
+        for task in &mut self.tasks {
+            match grace {
+                Some(grace) => {
+                    if time::timeout(grace, &mut *task).await.is_err() {
+                        task.abort();
+                    }
+                }
+                None => {
+                    let _ = (&mut *task).await;
+                }
             }
-            cmd.current_dir(cwd_path);
         }
+    }
+
+    /// Return what was read and whether lines were dropped to stay within the
+    /// limit, waiting for EOF at most `grace` (or indefinitely)
+    async fn finish(mut self, grace: Option<Duration>) -> (String, bool) {
+        self.wait(grace).await;
+        match &self.kept {
+            Some(kept) => kept.lock().unwrap().text(),
+            None => (String::from_utf8_lossy(&self.buffer.lock().unwrap()).to_string(), false),
+        }
+    }
+}
 
-        // Configure stdout and stderr
-        cmd.kill_on_drop(true);
+/// At most `max_lines` lines from one end of some output, kept as it is read
+/// so that long output never has to be held in memory
+struct KeptLines {
+    max_lines: usize,
+    from: OutputFrom,
+    /// The lines kept; the last may still be missing its newline
+    lines: VecDeque<Vec<u8>>,
+    /// Whether the last line read has not ended yet
+    open: bool,
+    /// Whether the line being read is kept
+    keeping: bool,
+    /// How many lines were dropped
+    dropped: usize,
+}
 
-        if params.capture_stderr {
-            cmd.stderr(std::process::Stdio::piped());
-        } else {
-            cmd.stderr(std::process::Stdio::null());
+impl KeptLines {
+    fn new(max_lines: usize, from: OutputFrom) -> Self {
+        Self {
+            max_lines,
+            from,
+            lines: VecDeque::new(),
+            open: false,
+            keeping: false,
+            dropped: 0,
+        }
+    }
+
+    /// Add the next bytes read, dropping lines beyond the limit
+    fn push(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let end = data
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(data.len(), |i| i + 1);
+            let (piece, rest) = data.split_at(end);
+            data = rest;
+
+            if !self.open {
+                self.open = true;
+                match self.from {
+                    // Past the first lines, later ones are only counted
+                    OutputFrom::Head if self.lines.len() >= self.max_lines => {
+                        self.keeping = false;
+                        self.dropped += 1;
+                    }
+                    // Otherwise a new line pushes out the oldest in the tail
+                    _ => {
+                        self.keeping = true;
+                        self.lines.push_back(Vec::new());
+                        if self.lines.len() > self.max_lines {
+                            self.lines.pop_front();
+                            self.dropped += 1;
+                        }
+                    }
+                }
+            }
+
+            if self.keeping
+                && let Some(line) = self.lines.back_mut()
+            {
+                line.extend_from_slice(piece);
+            }
+            if piece.ends_with(b"\n") {
+                self.open = false;
+            }
+        }
+    }
+
+    /// The kept text, noting how many lines were dropped, and whether any were
+    fn text(&self) -> (String, bool) {
+        let kept: Vec<u8> = self.lines.iter().flatten().copied().collect();
+        let kept = String::from_utf8_lossy(&kept);
+        if self.dropped == 0 {
+            return (kept.to_string(), false);
         }
 
+        let limited = match self.from {
+            OutputFrom::Head => format!("{}... [{} more lines truncated]\n", kept, self.dropped),
+            OutputFrom::Tail => format!("... [{} earlier lines truncated]\n{}", self.dropped, kept),
+        };
+
+        (limited, true)
+    }
+}
+
+/// Decode the stdin data according to its content type
+fn decode_stdin(params: &Params) -> Result<Option<Vec<u8>>> {
+    match (&params.stdin, params.stdin_type) {
+        (None, _) => Ok(None),
+        (Some(data), ContentType::Text) => Ok(Some(data.clone().into_bytes())),
+        (Some(data), ContentType::Binary) => general_purpose::STANDARD
+            .decode(data)
+            .map(Some)
+            .map_err(|e| Error::InvalidParam(format!("Invalid base64 stdin data: {}", e))),
+    }
+}
+
+#[async_trait]
+impl Tool for Shell {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "shell"
+    }
+
+    fn description(&self) -> &str {
+        "Execute a shell command"
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
         // Set timeout if specified
         let timeout = if params.timeout_ms > 0 {
             Some(Duration::from_millis(params.timeout_ms))
@@ -141,98 +794,100 @@ impl Tool for Shell {
             None
         };
 
-        // Execute the command
-        let execution = match timeout {
-            Some(timeout_duration) => {
-                // With timeout
-                let mut child = cmd
-                    .stdout(std::process::Stdio::piped())
-                    .spawn()
-                    .map_err(Error::Io)?;
-
-                let timed_out = match time::timeout(timeout_duration, child.wait()).await {
-                    Ok(result) => match result {
-                        Ok(_) => false,
-                        Err(e) => return Err(Error::Io(e)),
-                    },
-                    Err(_) => {
-                        // Timeout occurred
-                        // Kill the child process on timeout
-                        let _ = child.kill().await;
-                        true
-                    }
-                };
+        // Never run past the session's remaining shell time
+        let timeout = match (timeout, quota::shell_time_left()?) {
+            (Some(timeout), Some(left)) => Some(timeout.min(left)),
+            (timeout, left) => timeout.or(left),
+        };
 
-                // Capture output
-                let stdout = match child.stdout.take() {
-                    Some(stdout) => {
-                        let mut stdout_bytes = Vec::new();
-                        if let Err(e) = tokio::io::AsyncReadExt::read_to_end(
-                            &mut tokio::io::BufReader::new(stdout),
-                            &mut stdout_bytes,
-                        )
-                        .await
-                        {
-                            return Err(Error::Io(e));
-                        }
-                        String::from_utf8_lossy(&stdout_bytes).to_string()
+        let RunningCommand {
+            mut children,
+            stdout: stdout_reader,
+            stderr: stderr_reader,
+        } = self
+            .start(&params, params.max_output_lines.map(|max| (max, params.output_from)))
+            .await?;
+
+        // Wait for every stage to finish, killing them if the call is cancelled
+        let started = Instant::now();
+        let token = cancel::current();
+        let waited = tokio::select! {
+            waited = async {
+                match timeout {
+                    Some(timeout_duration) => {
+                        time::timeout(timeout_duration, wait_children(&mut children)).await
                     }
-                    None => String::new(),
-                };
+                    None => Ok(wait_children(&mut children).await),
+                }
+            } => waited,
+            _ = token.cancelled() => {
+                kill_children(&mut children).await;
+                quota::charge_shell_time(started.elapsed());
+                return Err(Error::Cancelled);
+            }
+        };
+        quota::charge_shell_time(started.elapsed());
+
+        let (exit_statuses, timed_out) = match waited {
+            Ok(result) => (
+                result.map_err(Error::Io)?.into_iter().map(Some).collect(),
+                false,
+            ),
+            Err(_) => {
+                // Kill the child processes on timeout
+                (kill_children(&mut children).await, true)
+            }
+        };
 
-                let stderr = if params.capture_stderr {
-                    match child.stderr.take() {
-                        Some(stderr) => {
-                            let mut stderr_bytes = Vec::new();
-                            if let Err(e) = tokio::io::AsyncReadExt::read_to_end(
-                                &mut tokio::io::BufReader::new(stderr),
-                                &mut stderr_bytes,
-                            )
-                            .await
-                            {
-                                return Err(Error::Io(e));
-                            }
-                            Some(String::from_utf8_lossy(&stderr_bytes).to_string())
-                        }
-                        None => None,
-                    }
-                } else {
-                    None
-                };
+        // The last stage determines the overall result, like in a shell
+        let exit_status = exit_statuses.last().copied().flatten();
+        let status = exit_status.and_then(|s| s.code()).unwrap_or(-1);
+        let signal = exit_status.and_then(termination_signal);
 
-                let status = match child.try_wait() {
-                    Ok(Some(status)) => status.code().unwrap_or(-1),
-                    _ => -1,
-                };
+        let pipeline_status: Vec<i32> = if exit_statuses.len() > 1 {
+            exit_statuses
+                .iter()
+                .map(|s| s.and_then(|s| s.code()).unwrap_or(-1))
+                .collect()
+        } else {
+            Vec::new()
+        };
 
-                (status, stdout, stderr, timed_out)
+        // After a timeout, processes spawned by the command may still hold the
+        // pipes open, so only wait briefly for the remaining output
+        let grace = timed_out.then_some(PIPE_DRAIN_GRACE);
+
+        // Long output was already cut down to the requested lines while read
+        let mut truncated = false;
+        let stdout = match stdout_reader {
+            Some(reader) => {
+                let (stdout, cut) = reader.finish(grace).await;
+                truncated |= cut;
+                stdout
             }
-            None => {
-                // Without timeout
-                let output = cmd.output().await.map_err(Error::Io)?;
-
-                let status = output.status.code().unwrap_or(-1);
-                let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                let stderr = if params.capture_stderr {
-                    Some(String::from_utf8_lossy(&output.stderr).to_string())
-                } else {
-                    None
-                };
+            None => String::new(),
+        };
 
-                (status, stdout, stderr, false)
+        let stderr = match stderr_reader {
+            Some(reader) if params.capture_stderr => {
+                let (stderr, cut) = reader.finish(grace).await;
+                truncated |= cut;
+                Some(stderr)
             }
+            _ => None,
         };
 
-        let (status, stdout, stderr, timed_out) = execution;
-
         Ok(Output {
-            command: params.command,
+            command: params.reported_command().to_string(),
             args: params.args,
             status,
+            signal,
+            pipeline_status,
             success: status == 0,
             stdout,
             stderr,
             timed_out,
+            truncated,
         })
     }
 }
@@ -243,16 +898,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_shell_echo() -> Result<()> {
-        let tool = Shell;
+        let tool = Shell::new();
 
         // Test simple echo command
         let params = Params {
-            command: "echo".to_string(),
             args: vec!["hello".to_string(), "world".to_string()],
-            env: HashMap::new(),
-            cwd: None,
-            capture_stderr: false,
-            timeout_ms: 0,
+            ..Params::new("echo")
         };
 
         let result = tool.execute(params).await?;
@@ -270,66 +921,40 @@ mod tests {
 
     #[tokio::test]
     async fn test_shell_with_env() -> Result<()> {
-        let tool = Shell;
+        let tool = Shell::new().with_shell_mode(true);
 
         // Test with environment variables
         let mut env = HashMap::new();
         env.insert("TEST_VAR".to_string(), "test_value".to_string());
 
+        // The variable is expanded by the script's shell on every platform
         #[cfg(target_os = "windows")]
-        let params = Params {
-            command: "cmd".to_string(),
-            args: vec!["/c".to_string(), "echo %TEST_VAR%".to_string()],
-            env,
-            cwd: None,
-            capture_stderr: false,
-            timeout_ms: 0,
-        };
-
+        let script = "echo %TEST_VAR%";
         #[cfg(not(target_os = "windows"))]
+        let script = "echo $TEST_VAR";
         let params = Params {
-            command: "echo".to_string(),
-            args: vec!["$TEST_VAR".to_string()],
             env,
-            cwd: None,
-            capture_stderr: false,
-            timeout_ms: 0,
+            ..Params::script(script)
         };
 
         let result = tool.execute(params).await?;
 
         assert_eq!(result.status, 0);
 
-        // On different shells or platforms, the actual output might vary
-        // We just check that the command completed successfully
         assert!(result.success);
+        assert_eq!(result.stdout.trim(), "test_value");
 
         Ok(())
     }
 
     #[tokio::test]
     async fn test_shell_timeout() -> Result<()> {
-        let tool = Shell;
-
-        // Test command timeout
-        #[cfg(target_os = "windows")]
-        let params = Params {
-            command: "timeout".to_string(),
-            args: vec!["2".to_string()],
-            env: HashMap::new(),
-            cwd: None,
-            capture_stderr: false,
-            timeout_ms: 500, // 500ms timeout
-        };
+        let tool = Shell::new().with_shell_mode(true);
 
-        #[cfg(not(target_os = "windows"))]
+        // Test command timeout; cmd has no sleep, so it falls back to ping
         let params = Params {
-            command: "sleep".to_string(),
-            args: vec!["2".to_string()],
-            env: HashMap::new(),
-            cwd: None,
-            capture_stderr: false,
             timeout_ms: 500, // 500ms timeout
+            ..Params::script("sleep 2 || ping -n 3 127.0.0.1")
         };
 
         let result = tool.execute(params).await?;
@@ -340,19 +965,36 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shell_cancel() -> Result<()> {
+        let tool = Shell::new();
+        let params = Params {
+            args: vec!["5".to_string()],
+            ..Params::new("sleep")
+        };
+
+        let token = cancel::CancellationToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            time::sleep(Duration::from_millis(100)).await;
+            canceller.cancel();
+        });
+
+        let start = std::time::Instant::now();
+        let result = tool.execute_cancellable(params, token).await;
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(start.elapsed() < Duration::from_secs(4));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_shell_invalid_command() -> Result<()> {
-        let tool = Shell;
+        let tool = Shell::new();
 
         // Test invalid command with whitespace
-        let params = Params {
-            command: "echo hello".to_string(),
-            args: vec![],
-            env: HashMap::new(),
-            cwd: None,
-            capture_stderr: false,
-            timeout_ms: 0,
-        };
+        let params = Params::new("echo hello");
 
         let result = tool.execute(params).await;
 
@@ -364,27 +1006,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_shell_capture_stderr() -> Result<()> {
-        let tool = Shell;
+        let tool = Shell::new().with_shell_mode(true);
 
         // Test stderr capture
-        #[cfg(target_os = "windows")]
-        let params = Params {
-            command: "cmd".to_string(),
-            args: vec!["/c".to_string(), "echo error 1>&2".to_string()],
-            env: HashMap::new(),
-            cwd: None,
-            capture_stderr: true,
-            timeout_ms: 0,
-        };
-
-        #[cfg(not(target_os = "windows"))]
         let params = Params {
-            command: "sh".to_string(),
-            args: vec!["-c".to_string(), "echo error 1>&2".to_string()],
-            env: HashMap::new(),
-            cwd: None,
             capture_stderr: true,
-            timeout_ms: 0,
+            ..Params::script("echo error 1>&2")
         };
 
         let result = tool.execute(params).await?;
@@ -398,4 +1025,313 @@ mod tests {
 
         Ok(())
     }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn test_shell_stdin() -> Result<()> {
+        let tool = Shell::new();
+
+        // Text stdin
+        let params = Params {
+            stdin: Some("hello from stdin\n".to_string()),
+            ..Params::new("cat")
+        };
+
+        let result = tool.execute(params).await?;
+        assert!(result.success);
+        assert_eq!(result.stdout, "hello from stdin\n");
+
+        // Binary stdin, with a timeout
+        let params = Params {
+            args: vec!["-c".to_string()],
+            timeout_ms: 5000,
+            stdin: Some(general_purpose::STANDARD.encode([0u8, 159, 146, 150])),
+            stdin_type: ContentType::Binary,
+            ..Params::new("wc")
+        };
+
+        let result = tool.execute(params).await?;
+        assert!(result.success);
+        assert_eq!(result.stdout.trim(), "4");
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn test_shell_timeout_partial_output() -> Result<()> {
+        let tool = Shell::new().with_shell_mode(true);
+
+        let params = Params {
+            capture_stderr: true,
+            timeout_ms: 500,
+            ..Params::script("echo partial; echo oops 1>&2; sleep 5")
+        };
+
+        let result = tool.execute(params).await?;
+
+        // Output printed before the kill is still returned
+        assert!(result.timed_out);
+        assert_eq!(result.signal.as_deref(), Some("SIGKILL"));
+        assert_eq!(result.stdout.trim(), "partial");
+        assert_eq!(result.stderr.unwrap().trim(), "oops");
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn test_shell_mode() -> Result<()> {
+        let params = || Params {
+            use_shell: true,
+            ..Params::new("echo shell mode | tr a-z A-Z")
+        };
+
+        // Rejected unless the server allows it
+        let result = Shell::new().execute(params()).await;
+        assert!(matches!(result, Err(Error::PermissionDenied(_))));
+
+        // Running a shell directly doesn't get around that, by any path or as
+        // any pipeline stage
+        let direct = |command: &str| Params {
+            args: vec!["-c".to_string(), "echo hi".to_string()],
+            ..Params::new(command)
+        };
+        for params in [
+            direct("sh"),
+            direct("/bin/bash"),
+            Params {
+                pipeline: vec![PipelineStage {
+                    command: "dash".to_string(),
+                    args: vec![],
+                }],
+                ..Params::new("echo")
+            },
+        ] {
+            let result = Shell::new().execute(params).await;
+            assert!(matches!(result, Err(Error::PermissionDenied(_))));
+        }
+        let result = Shell::new().with_shell_mode(true).execute(direct("sh")).await?;
+        assert_eq!(result.stdout.trim(), "hi");
+
+        let result = Shell::new().with_shell_mode(true).execute(params()).await?;
+        assert!(result.success);
+        assert_eq!(result.stdout.trim(), "SHELL MODE");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shell_script() -> Result<()> {
+        // Arguments reach the script as single words, whatever they contain
+        #[cfg(target_os = "windows")]
+        let script = "echo %~1";
+        #[cfg(not(target_os = "windows"))]
+        let script = "echo \"$1\"";
+        let params = || Params {
+            args: vec!["it's a \"test\"".to_string()],
+            ..Params::script(script)
+        };
+
+        // Rejected unless the server allows it
+        let result = Shell::new().execute(params()).await;
+        assert!(matches!(result, Err(Error::PermissionDenied(_))));
+
+        let shell = Shell::new().with_shell_mode(true);
+        let result = shell.execute(params()).await?;
+        assert!(result.success);
+        assert_eq!(result.command, script);
+        #[cfg(not(target_os = "windows"))]
+        assert_eq!(result.stdout.trim(), "it's a \"test\"");
+
+        // Only one of command and script
+        let both = Params {
+            command: "echo".to_string(),
+            ..params()
+        };
+        let result = shell.execute(both).await;
+        assert!(matches!(result, Err(Error::InvalidParam(_))));
+        let neither = Params::new("");
+        let result = shell.execute(neither).await;
+        assert!(matches!(result, Err(Error::InvalidParam(_))));
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shell_signal() -> Result<()> {
+        let tool = Shell::new().with_shell_mode(true);
+
+        let params = Params::script("kill -SEGV $$");
+
+        let result = tool.execute(params).await?;
+
+        assert!(!result.success);
+        assert_eq!(result.status, -1);
+        assert_eq!(result.signal.as_deref(), Some("SIGSEGV"));
+
+        // A normal exit reports no signal
+        let params = Params::new("false");
+
+        let result = tool.execute(params).await?;
+        assert_eq!(result.status, 1);
+        assert!(result.signal.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shell_cwd_root() -> Result<()> {
+        let root = std::env::temp_dir().join(format!(
+            "shell_root_test_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        fs::create_dir_all(root.join("subcrate")).await?;
+        let tool = Shell::new().with_root(&root);
+
+        // Relative cwds resolve against the root, not the server's cwd
+        let resolved = tool.resolve_cwd(Some("subcrate")).await?;
+        assert_eq!(
+            resolved,
+            Some(fs::canonicalize(root.join("subcrate")).await?)
+        );
+
+        // No cwd means the root itself
+        let resolved = tool.resolve_cwd(None).await?;
+        assert_eq!(resolved, Some(fs::canonicalize(&root).await?));
+
+        // Escaping the root is refused
+        let result = tool.resolve_cwd(Some("..")).await;
+        assert!(matches!(result, Err(Error::OutsideSandbox(_))));
+
+        let result = tool.resolve_cwd(Some("missing")).await;
+        assert!(matches!(result, Err(Error::NotFound(_))));
+
+        fs::remove_dir_all(&root).await?;
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn test_shell_dispatcher() -> Result<()> {
+        let root = std::env::temp_dir().join(format!(
+            "shell_dispatcher_test_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ));
+        fs::create_dir_all(root.join("sub")).await?;
+        let (dispatcher, _) = crate::create_dispatcher_with_shell(
+            crate::transform::standard_transformer(),
+            Shell::new().with_shell_mode(true).with_root(&root),
+        );
+        let call = async |params: serde_json::Value| -> Result<serde_json::Value> {
+            let request = serde_json::json!({
+                "jsonrpc": "2.0", "method": "shell", "params": params, "id": 1
+            });
+            Ok(serde_json::from_str(&dispatcher.dispatch(&request.to_string()).await?)?)
+        };
+
+        // Shell mode and scripts reach the tool through the dispatcher
+        let response = call(serde_json::json!({"command": "echo a | tr a b", "use_shell": true})).await?;
+        assert_eq!(response["result"]["stdout"], "b\n");
+
+        // Relative working directories resolve against the root
+        let response = call(serde_json::json!({"script": "pwd", "cwd": "sub"})).await?;
+        let expected = fs::canonicalize(root.join("sub")).await?;
+        assert_eq!(
+            response["result"]["stdout"].as_str().map(str::trim),
+            Some(expected.to_string_lossy().as_ref())
+        );
+        let response = call(serde_json::json!({"command": "pwd", "cwd": ".."})).await?;
+        assert_eq!(response["error"]["data"]["code"], "outside_sandbox");
+
+        // The default dispatcher keeps shell mode off
+        let request = r#"{"jsonrpc": "2.0", "method": "shell", "params": {"script": "true"}, "id": 2}"#;
+        let response: serde_json::Value =
+            serde_json::from_str(&crate::create_default_dispatcher().dispatch(request).await?)?;
+        assert_eq!(response["error"]["data"]["code"], "permission_denied");
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shell_pipeline() -> Result<()> {
+        let tool = Shell::new();
+
+        // printf 'b\na\nb\n' | sort | uniq -c
+        let params = Params {
+            args: vec!["b\\na\\nb\\n".to_string()],
+            capture_stderr: true,
+            timeout_ms: 5000,
+            pipeline: vec![
+                PipelineStage {
+                    command: "sort".to_string(),
+                    args: vec![],
+                },
+                PipelineStage {
+                    command: "uniq".to_string(),
+                    args: vec!["-c".to_string()],
+                },
+            ],
+            ..Params::new("printf")
+        };
+
+        let result = tool.execute(params).await?;
+
+        assert!(result.success);
+        assert_eq!(result.pipeline_status, vec![0, 0, 0]);
+        let lines: Vec<String> = result
+            .stdout
+            .lines()
+            .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect();
+        assert_eq!(lines, vec!["1 a", "2 b"]);
+
+        // Stages are validated like the main command
+        let params = Params {
+            pipeline: vec![PipelineStage {
+                command: "grep x".to_string(),
+                args: vec![],
+            }],
+            ..Params::new("echo")
+        };
+        assert!(tool.execute(params).await.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kept_lines() {
+        let text = b"1\n2\n3\n4\n5\n";
+        let kept = |max_lines, from| {
+            let mut kept = KeptLines::new(max_lines, from);
+            // Split lines across reads
+            for chunk in text.chunks(3) {
+                kept.push(chunk);
+            }
+            kept.text()
+        };
+
+        let (head, truncated) = kept(2, OutputFrom::Head);
+        assert!(truncated);
+        assert_eq!(head, "1\n2\n... [3 more lines truncated]\n");
+
+        let (tail, truncated) = kept(2, OutputFrom::Tail);
+        assert!(truncated);
+        assert_eq!(tail, "... [3 earlier lines truncated]\n4\n5\n");
+
+        let (all, truncated) = kept(5, OutputFrom::Tail);
+        assert!(!truncated);
+        assert_eq!(all, "1\n2\n3\n4\n5\n");
+
+        // A last line without a newline still counts
+        let mut partial = KeptLines::new(1, OutputFrom::Tail);
+        partial.push(b"a\nb");
+        partial.push(b"c");
+        assert_eq!(partial.text(), ("... [1 earlier lines truncated]\nbc".to_string(), true));
+    }
 }
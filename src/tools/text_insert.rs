@@ -0,0 +1,262 @@
+//! Text insert tool implementation
+//!
+//! Inserts lines before or after a line picked by number or by an anchor
+//! regex. The anchor must match exactly one line, so an insert never lands
+//! next to the wrong occurrence.
+
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::path::PathBuf;
+
+use super::file_patch::detect_line_ending;
+use super::{Annotations, Tool};
+use crate::{Error, Result, paths, vfs};
+
+/// Text insert tool
+#[derive(Clone, Copy)]
+pub struct TextInsert;
+
+/// Where to insert relative to the target line
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Position {
+    /// Insert before the target line
+    Before,
+    /// Insert after the target line
+    #[default]
+    After,
+}
+
+/// Parameters for the text insert tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Params {
+    /// Path of the file to edit
+    #[serde(deserialize_with = "crate::roots::path")]
+    pub path: String,
+
+    /// Lines to insert
+    pub content: String,
+
+    /// Target line number (1-based). Line 0 with `after` inserts at the start.
+    #[serde(default)]
+    pub line: Option<usize>,
+
+    /// Regex that must match exactly one line, used instead of `line`
+    #[serde(default)]
+    pub anchor: Option<String>,
+
+    /// Whether to insert before or after the target line
+    #[serde(default)]
+    pub position: Position,
+}
+
+impl Params {
+    /// Insert `content` after line `line` of `path`
+    pub fn new(path: impl Into<String>, content: impl Into<String>, line: usize) -> Self {
+        Self {
+            path: path.into(),
+            content: content.into(),
+            line: Some(line),
+            anchor: None,
+            position: Position::default(),
+        }
+    }
+
+    /// Insert `content` after the line of `path` that matches `anchor`
+    pub fn with_anchor(
+        path: impl Into<String>,
+        content: impl Into<String>,
+        anchor: impl Into<String>,
+    ) -> Self {
+        Self {
+            line: None,
+            anchor: Some(anchor.into()),
+            ..Self::new(path, content, 0)
+        }
+    }
+}
+
+/// Output of the text insert tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Output {
+    /// Path of the edited file
+    pub path: String,
+
+    /// Line number (1-based) of the first inserted line
+    pub line: usize,
+
+    /// Number of lines inserted
+    pub lines_inserted: usize,
+
+    /// Number of lines in the file after the edit
+    pub total_lines: usize,
+}
+
+/// 1-based number of the one line matching `anchor`
+fn find_anchor(lines: &[String], anchor: &str) -> Result<usize> {
+    let regex = Regex::new(anchor)
+        .map_err(|e| Error::InvalidParam(format!("Invalid anchor regex: {}", e)))?;
+    let mut matches = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| regex.is_match(line))
+        .map(|(i, _)| i + 1);
+
+    match (matches.next(), matches.next()) {
+        (Some(line), None) => Ok(line),
+        (None, _) => Err(Error::NotFound(format!(
+            "No line matches the anchor '{}'",
+            anchor
+        ))),
+        (Some(first), Some(second)) => Err(Error::InvalidParam(format!(
+            "The anchor '{}' is ambiguous: it matches lines {}, {}{}",
+            anchor,
+            first,
+            second,
+            if matches.next().is_some() {
+                " and more"
+            } else {
+                ""
+            }
+        ))),
+    }
+}
+
+#[async_trait]
+impl Tool for TextInsert {
+    type Params = Params;
+    type Output = Output;
+
+    fn name(&self) -> &str {
+        "text_insert"
+    }
+
+    fn description(&self) -> &str {
+        "Insert lines before or after a line number or the line matching an anchor regex"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations {
+            destructive: false,
+            ..Annotations::DESTRUCTIVE
+        }
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let path = PathBuf::from(&params.path);
+        let fs = vfs::current();
+
+        // Check that the path is an existing file
+        paths::require_file(fs.as_ref(), &path).await?;
+
+        let content = fs.read_to_string(&path).await?;
+        let line_ending = detect_line_ending(&content);
+        let trailing_newline = content.is_empty() || content.ends_with('\n');
+        let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
+
+        let target = match (&params.anchor, params.line) {
+            (Some(anchor), None) => find_anchor(&lines, anchor)?,
+            (None, Some(line)) => {
+                let first = match params.position {
+                    Position::Before => 1,
+                    Position::After => 0,
+                };
+                if line < first || line > lines.len() {
+                    return Err(Error::InvalidParam(format!(
+                        "Cannot insert {} line {}: the file has {} lines",
+                        match params.position {
+                            Position::Before => "before",
+                            Position::After => "after",
+                        },
+                        line,
+                        lines.len()
+                    )));
+                }
+                line
+            }
+            _ => {
+                return Err(Error::InvalidParam(
+                    "Exactly one of line or anchor is required".to_string(),
+                ));
+            }
+        };
+
+        // 0-based index the first new line goes to
+        let index = match params.position {
+            Position::Before => target - 1,
+            Position::After => target,
+        };
+        let new_lines: Vec<String> = params.content.lines().map(|s| s.to_string()).collect();
+        let lines_inserted = new_lines.len();
+        lines.splice(index..index, new_lines);
+
+        let mut updated = lines.join(line_ending);
+        if trailing_newline && !lines.is_empty() {
+            updated.push_str(line_ending);
+        }
+        fs.write(&path, updated.as_bytes()).await?;
+
+        Ok(Output {
+            path: params.path,
+            line: index + 1,
+            lines_inserted,
+            total_lines: lines.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{Filesystem, MemoryFs};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_text_insert() -> Result<()> {
+        let fs =
+            Arc::new(MemoryFs::new().with_file("/lib.rs", "use a;\r\n\r\nfn main() {\r\n}\r\n"));
+        vfs::scope(fs.clone(), async {
+            let tool = TextInsert;
+
+            // After the anchor, keeping the file's line endings
+            let result = tool
+                .execute(Params::with_anchor("/lib.rs", "use b;\nuse c;", r"^use a;"))
+                .await?;
+            assert_eq!((result.line, result.lines_inserted), (2, 2));
+            assert_eq!(
+                fs.read_to_string(Path::new("/lib.rs")).await?,
+                "use a;\r\nuse b;\r\nuse c;\r\n\r\nfn main() {\r\n}\r\n"
+            );
+
+            // Before a line number, and at the very start
+            let before = Params {
+                position: Position::Before,
+                ..Params::new("/lib.rs", "// main", 5)
+            };
+            tool.execute(before).await?;
+            tool.execute(Params::new("/lib.rs", "// top", 0)).await?;
+            assert_eq!(
+                fs.read_to_string(Path::new("/lib.rs")).await?,
+                "// top\r\nuse a;\r\nuse b;\r\nuse c;\r\n\r\n// main\r\nfn main() {\r\n}\r\n"
+            );
+
+            // Absent and ambiguous anchors are refused
+            let result = tool
+                .execute(Params::with_anchor("/lib.rs", "x", "^struct"))
+                .await;
+            assert!(matches!(result, Err(Error::NotFound(_))));
+            let result = tool
+                .execute(Params::with_anchor("/lib.rs", "x", "^use"))
+                .await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+
+            let result = tool.execute(Params::new("/lib.rs", "x", 99)).await;
+            assert!(matches!(result, Err(Error::InvalidParam(_))));
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,792 @@
+//! Undo journal for filesystem changes
+//!
+//! The journal is opt-in. When a dispatcher is created with one, the mutating
+//! tools are wrapped in [`Journaled`], which copies every path a call may touch
+//! into a session-scoped undo directory before the call runs. `undo_list` shows
+//! the recorded operations and `undo_apply` rolls back the last N of them,
+//! newest first.
+//!
+//! Paths are read and restored through the [filesystem](crate::vfs) the call
+//! ran on, while the copies always live on the real disk.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::file_move;
+use super::{Annotations, Tool};
+#[cfg(feature = "batch")]
+use super::batch;
+#[cfg(feature = "directory-sync")]
+use super::directory_sync;
+#[cfg(feature = "file-edit")]
+use super::file_edit;
+#[cfg(feature = "file-edit-lines")]
+use super::file_edit_lines;
+#[cfg(feature = "file-patch")]
+use super::file_patch;
+#[cfg(feature = "file-write")]
+use super::file_write;
+#[cfg(feature = "text-insert")]
+use super::text_insert;
+use crate::sys;
+use crate::vfs::{self, Filesystem};
+use crate::{Error, Result};
+
+/// How many operations to keep before the oldest are forgotten
+const MAX_ENTRIES: usize = 100;
+
+/// What was at a path before an operation
+enum Saved {
+    /// Nothing; undoing removes whatever the operation created
+    Missing,
+    /// A file, copied into the undo directory, and its permission bits
+    File(PathBuf, Option<u32>),
+    /// A directory tree, copied into the undo directory
+    Dir(PathBuf),
+}
+
+/// The state of one path before an operation
+struct Snapshot {
+    path: PathBuf,
+    saved: Saved,
+}
+
+/// One journaled operation
+struct Entry {
+    id: u64,
+    tool: String,
+    timestamp: DateTime<Utc>,
+    /// Filesystem the operation ran on
+    fs: Arc<dyn Filesystem>,
+    snapshots: Vec<Snapshot>,
+}
+
+impl Entry {
+    fn info(&self) -> EntryInfo {
+        EntryInfo {
+            id: self.id,
+            tool: self.tool.clone(),
+            timestamp: self.timestamp.to_rfc3339(),
+            paths: self
+                .snapshots
+                .iter()
+                .map(|s| s.path.to_string_lossy().to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Snapshots and entries of one journal; the undo directory goes away with it
+struct JournalState {
+    dir: PathBuf,
+    entries: Mutex<Vec<Entry>>,
+    next_id: AtomicU64,
+}
+
+impl Drop for JournalState {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Journal of the state of files before they were changed, shared by the
+/// journaled tools and the undo tools
+#[derive(Clone)]
+pub struct UndoJournal {
+    state: Arc<JournalState>,
+}
+
+impl Default for UndoJournal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoJournal {
+    /// Create a journal keeping its snapshots in a fresh directory under the
+    /// system temp directory
+    pub fn new() -> Self {
+        Self::with_dir(std::env::temp_dir().join(format!(
+            "gamecode-undo-{}-{}",
+            std::process::id(),
+            rand::random::<u32>()
+        )))
+    }
+
+    /// Create a journal keeping its snapshots in `dir`, which is removed when
+    /// the last handle to the journal is dropped
+    pub fn with_dir(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            state: Arc::new(JournalState {
+                dir: dir.into(),
+                entries: Mutex::new(Vec::new()),
+                next_id: AtomicU64::new(1),
+            }),
+        }
+    }
+
+    /// Save the current state of `paths` before `tool` changes them.
+    /// Returns the id of the new entry.
+    async fn record(&self, tool: &str, paths: Vec<PathBuf>) -> Result<u64> {
+        let id = self.state.next_id.fetch_add(1, Ordering::SeqCst);
+        let entry_dir = self.state.dir.join(id.to_string());
+        let fs = vfs::current();
+        let snapshots = match save_paths(fs.as_ref(), &entry_dir, paths).await {
+            Ok(snapshots) => snapshots,
+            Err(e) => {
+                let _ = sys::fs::remove_dir_all(&entry_dir).await;
+                return Err(Error::Other(format!(
+                    "Failed to save undo snapshot: {}",
+                    e
+                )));
+            }
+        };
+
+        let forgotten = {
+            let mut entries = self.state.entries.lock().unwrap();
+            entries.push(Entry {
+                id,
+                tool: tool.to_string(),
+                timestamp: Utc::now(),
+                fs,
+                snapshots,
+            });
+            let excess = entries.len().saturating_sub(MAX_ENTRIES);
+            entries.drain(..excess).map(|e| e.id).collect::<Vec<_>>()
+        };
+        for old in forgotten {
+            let _ = sys::fs::remove_dir_all(self.state.dir.join(old.to_string())).await;
+        }
+
+        Ok(id)
+    }
+
+    /// Forget an entry, e.g. because its operation failed
+    fn discard(&self, id: u64) {
+        self.state.entries.lock().unwrap().retain(|e| e.id != id);
+        let _ = fs::remove_dir_all(self.state.dir.join(id.to_string()));
+    }
+
+    /// The recorded operations, newest first
+    pub fn entries(&self) -> Vec<EntryInfo> {
+        let entries = self.state.entries.lock().unwrap();
+        entries.iter().rev().map(Entry::info).collect()
+    }
+
+    /// Roll back the last `count` operations, newest first. Returns the
+    /// operations that were undone.
+    pub async fn undo(&self, count: usize) -> Result<Vec<EntryInfo>> {
+        let undone = {
+            let mut entries = self.state.entries.lock().unwrap();
+            let start = entries.len().saturating_sub(count);
+            entries.split_off(start)
+        };
+
+        let mut infos = Vec::with_capacity(undone.len());
+        for entry in undone.into_iter().rev() {
+            restore_entry(&entry).await.map_err(|e| {
+                Error::Other(format!(
+                    "Failed to undo operation {} ({}): {}",
+                    entry.id, entry.tool, e
+                ))
+            })?;
+            let _ = sys::fs::remove_dir_all(self.state.dir.join(entry.id.to_string())).await;
+            infos.push(entry.info());
+        }
+        Ok(infos)
+    }
+}
+
+/// Copy whatever is at each path on `fs` into `entry_dir`. Only the first
+/// occurrence of a path is saved, since that is the state to go back to.
+async fn save_paths(
+    fs: &dyn Filesystem,
+    entry_dir: &Path,
+    paths: Vec<PathBuf>,
+) -> std::io::Result<Vec<Snapshot>> {
+    let mut snapshots: Vec<Snapshot> = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        if snapshots.iter().any(|s| s.path == path) {
+            continue;
+        }
+
+        let copy = entry_dir.join(snapshots.len().to_string());
+        let saved = match fs.metadata(&path).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => Saved::Missing,
+            Err(e) => return Err(e),
+            Ok(metadata) if metadata.is_dir() => {
+                save_dir(fs, &path, &copy).await?;
+                Saved::Dir(copy)
+            }
+            Ok(metadata) => {
+                sys::fs::create_dir_all(entry_dir).await?;
+                sys::fs::write(&copy, fs.read(&path).await?).await?;
+                Saved::File(copy, metadata.mode)
+            }
+        };
+
+        snapshots.push(Snapshot { path, saved });
+    }
+
+    Ok(snapshots)
+}
+
+/// Copy a directory tree on `fs` to `copy` in the undo directory
+async fn save_dir(fs: &dyn Filesystem, dir: &Path, copy: &Path) -> std::io::Result<()> {
+    sys::fs::create_dir_all(copy).await?;
+    let mut pending = vec![(dir.to_path_buf(), copy.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        for entry in fs.read_dir(&from).await? {
+            let target = to.join(entry.file_name());
+            if entry.metadata.is_dir() {
+                sys::fs::create_dir(&target).await?;
+                pending.push((entry.path, target));
+            } else {
+                sys::fs::write(&target, fs.read(&entry.path).await?).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy a directory tree saved at `copy` back to `dir` on `fs`
+async fn restore_dir(fs: &dyn Filesystem, copy: &Path, dir: &Path) -> std::io::Result<()> {
+    let mut pending = vec![(copy.to_path_buf(), dir.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        fs.create_dir_all(&to).await?;
+        let mut entries = sys::fs::read_dir(&from).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let target = to.join(entry.file_name());
+            if entry.metadata().await?.is_dir() {
+                pending.push((entry.path(), target));
+            } else {
+                fs.write(&target, &sys::fs::read(entry.path()).await?).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove whatever is at a path on `fs` now. A link is removed itself rather
+/// than what it points to.
+async fn remove_existing(fs: &dyn Filesystem, path: &Path) -> std::io::Result<()> {
+    match fs.remove_file(path).await {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(_) if fs.metadata(path).await.is_ok_and(|m| m.is_dir()) => {
+            fs.remove_dir_all(path).await
+        }
+        result => result,
+    }
+}
+
+/// Put every path of an entry back the way it was, in reverse order so that
+/// paths inside created directories go before the directories themselves
+async fn restore_entry(entry: &Entry) -> std::io::Result<()> {
+    let fs = entry.fs.as_ref();
+    for snapshot in entry.snapshots.iter().rev() {
+        remove_existing(fs, &snapshot.path).await?;
+        match &snapshot.saved {
+            Saved::Missing => {}
+            Saved::File(copy, mode) => {
+                if let Some(parent) = snapshot.path.parent()
+                    && !parent.as_os_str().is_empty()
+                {
+                    fs.create_dir_all(parent).await?;
+                }
+                fs.write(&snapshot.path, &sys::fs::read(copy).await?).await?;
+                if let Some(mode) = mode {
+                    match fs.set_mode(&snapshot.path, *mode).await {
+                        Err(e) if e.kind() != ErrorKind::Unsupported => return Err(e),
+                        _ => {}
+                    }
+                }
+            }
+            Saved::Dir(copy) => restore_dir(fs, copy, &snapshot.path).await?,
+        }
+    }
+    Ok(())
+}
+
+/// Tools whose changes can be recorded in an undo journal
+pub trait JournalPaths: Tool {
+    /// Paths the call may create, change or remove
+    fn journal_paths(params: &Self::Params) -> Vec<PathBuf>;
+}
+
+#[cfg(feature = "file-write")]
+impl JournalPaths for file_write::FileWrite {
+    fn journal_paths(params: &Self::Params) -> Vec<PathBuf> {
+        vec![PathBuf::from(&params.path)]
+    }
+}
+
+#[cfg(feature = "file-edit")]
+impl JournalPaths for file_edit::FileEdit {
+    fn journal_paths(params: &Self::Params) -> Vec<PathBuf> {
+        vec![PathBuf::from(&params.path)]
+    }
+}
+
+#[cfg(feature = "file-edit-lines")]
+impl JournalPaths for file_edit_lines::FileEditLines {
+    fn journal_paths(params: &Self::Params) -> Vec<PathBuf> {
+        vec![PathBuf::from(&params.path)]
+    }
+}
+
+#[cfg(feature = "text-insert")]
+impl JournalPaths for text_insert::TextInsert {
+    fn journal_paths(params: &Self::Params) -> Vec<PathBuf> {
+        vec![PathBuf::from(&params.path)]
+    }
+}
+
+#[cfg(feature = "file-patch")]
+impl JournalPaths for file_patch::FilePatch {
+    fn journal_paths(params: &Self::Params) -> Vec<PathBuf> {
+        if params.check_only {
+            return Vec::new();
+        }
+        match &params.base_dir {
+            Some(base_dir) => {
+                file_patch::multi_file_patch_paths(&params.patch, Path::new(base_dir))
+            }
+            None => vec![PathBuf::from(&params.path)],
+        }
+    }
+}
+
+impl JournalPaths for file_move::FileMove {
+    fn journal_paths(params: &Self::Params) -> Vec<PathBuf> {
+        vec![
+            PathBuf::from(&params.source),
+            PathBuf::from(&params.destination),
+        ]
+    }
+}
+
+#[cfg(feature = "batch")]
+impl JournalPaths for batch::Batch {
+    fn journal_paths(params: &Self::Params) -> Vec<PathBuf> {
+        params
+            .operations
+            .iter()
+            .flat_map(|op| match op {
+                batch::Operation::Move {
+                    source,
+                    destination,
+                } => vec![PathBuf::from(source), PathBuf::from(destination)],
+                batch::Operation::Write { path, .. }
+                | batch::Operation::Mkdir { path }
+                | batch::Operation::Delete { path, .. } => vec![PathBuf::from(path)],
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "directory-sync")]
+impl JournalPaths for directory_sync::DirectorySync {
+    fn journal_paths(params: &Self::Params) -> Vec<PathBuf> {
+        if params.dry_run {
+            return Vec::new();
+        }
+        vec![PathBuf::from(&params.destination)]
+    }
+}
+
+/// A tool whose changes are recorded in an undo journal before it runs.
+/// Without a journal the tool runs as is.
+#[derive(Clone)]
+pub struct Journaled<T> {
+    tool: T,
+    journal: Option<UndoJournal>,
+}
+
+impl<T> Journaled<T> {
+    /// Wrap `tool`, recording its changes in `journal` if there is one
+    pub fn new(tool: T, journal: Option<UndoJournal>) -> Self {
+        Self { tool, journal }
+    }
+}
+
+#[async_trait]
+impl<T> Tool for Journaled<T>
+where
+    T: JournalPaths + Send + Sync,
+{
+    type Params = T::Params;
+    type Output = T::Output;
+
+    fn name(&self) -> &str {
+        self.tool.name()
+    }
+
+    fn description(&self) -> &str {
+        self.tool.description()
+    }
+
+    fn annotations(&self) -> Annotations {
+        self.tool.annotations()
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let Some(journal) = &self.journal else {
+            return self.tool.execute(params).await;
+        };
+
+        // Objects in remote stores are not journaled
+        let paths: Vec<PathBuf> = T::journal_paths(&params)
+            .into_iter()
+            .filter(|path| vfs::remote_scheme(path).is_none())
+            .collect();
+        if paths.is_empty() {
+            return self.tool.execute(params).await;
+        }
+
+        let id = journal.record(self.tool.name(), paths).await?;
+        let result = self.tool.execute(params).await;
+        if result.is_err() {
+            // A failed operation leaves nothing behind worth undoing
+            journal.discard(id);
+        }
+        result
+    }
+}
+
+/// A journaled operation
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct EntryInfo {
+    /// Id of the operation
+    pub id: u64,
+
+    /// Tool that performed the operation
+    pub tool: String,
+
+    /// When the operation ran (RFC 3339)
+    pub timestamp: String,
+
+    /// Paths that were saved before the operation
+    pub paths: Vec<String>,
+}
+
+/// Undo list tool
+#[derive(Clone, Default)]
+pub struct UndoList {
+    journal: UndoJournal,
+}
+
+impl UndoList {
+    /// Create a list tool for the operations in `journal`
+    pub fn new(journal: UndoJournal) -> Self {
+        Self { journal }
+    }
+}
+
+/// Parameters for the undo_list tool
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ListParams {
+    /// Maximum number of operations to return (newest first, all by default)
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Output of the undo_list tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ListOutput {
+    /// Journaled operations, newest first
+    pub entries: Vec<EntryInfo>,
+
+    /// Total number of operations that can be undone
+    pub total: usize,
+}
+
+#[async_trait]
+impl Tool for UndoList {
+    type Params = ListParams;
+    type Output = ListOutput;
+
+    fn name(&self) -> &str {
+        "undo_list"
+    }
+
+    fn description(&self) -> &str {
+        "List operations that can be undone"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let mut entries = self.journal.entries();
+        let total = entries.len();
+        if let Some(limit) = params.limit {
+            entries.truncate(limit);
+        }
+
+        Ok(ListOutput { entries, total })
+    }
+}
+
+/// Undo apply tool
+#[derive(Clone, Default)]
+pub struct UndoApply {
+    journal: UndoJournal,
+}
+
+impl UndoApply {
+    /// Create an apply tool for the operations in `journal`
+    pub fn new(journal: UndoJournal) -> Self {
+        Self { journal }
+    }
+}
+
+/// Parameters for the undo_apply tool
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ApplyParams {
+    /// Number of operations to roll back, newest first
+    #[serde(default = "default_count")]
+    pub count: usize,
+}
+
+impl Default for ApplyParams {
+    fn default() -> Self {
+        Self {
+            count: default_count(),
+        }
+    }
+}
+
+fn default_count() -> usize {
+    1
+}
+
+/// Output of the undo_apply tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ApplyOutput {
+    /// Operations that were rolled back, in the order they were undone
+    pub undone: Vec<EntryInfo>,
+
+    /// Number of operations left in the journal
+    pub remaining: usize,
+}
+
+#[async_trait]
+impl Tool for UndoApply {
+    type Params = ApplyParams;
+    type Output = ApplyOutput;
+
+    fn name(&self) -> &str {
+        "undo_apply"
+    }
+
+    fn description(&self) -> &str {
+        "Roll back the last operations"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::DESTRUCTIVE
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        if params.count == 0 {
+            return Err(Error::InvalidParam("count must be at least 1".to_string()));
+        }
+
+        let undone = self.journal.undo(params.count).await?;
+
+        Ok(ApplyOutput {
+            undone,
+            remaining: self.journal.entries().len(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "batch", feature = "file-write"))]
+mod tests {
+    use super::*;
+    use tokio::fs;
+
+    fn test_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "undo_test_{}_{}_{}",
+            name,
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_undo_write_and_move() -> Result<()> {
+        let dir = test_dir("write_move");
+        fs::create_dir_all(&dir).await?;
+        let journal = UndoJournal::with_dir(dir.join(".undo"));
+        let existing = dir.join("existing.txt");
+        let created = dir.join("created.txt");
+        let moved = dir.join("moved.txt");
+        fs::write(&existing, "original\n").await?;
+
+        let write = Journaled::new(file_write::FileWrite, Some(journal.clone()));
+        for (path, content) in [(&existing, "changed\n"), (&created, "new\n")] {
+            let params: file_write::Params = serde_json::from_value(serde_json::json!({
+                "path": path.to_string_lossy(),
+                "content": content,
+            }))?;
+            write.execute(params).await?;
+        }
+
+        Journaled::new(file_move::FileMove, Some(journal.clone()))
+            .execute(file_move::Params {
+                source: created.to_string_lossy().to_string(),
+                destination: moved.to_string_lossy().to_string(),
+                overwrite: false,
+                create_dirs: false,
+                merge: false,
+                replace: false,
+                use_trash: false,
+            })
+            .await?;
+
+        let list = UndoList::new(journal.clone())
+            .execute(ListParams { limit: None })
+            .await?;
+        assert_eq!(list.total, 3);
+        assert_eq!(list.entries[0].tool, "file_move");
+
+        // Undo the move, then the two writes
+        let apply = UndoApply::new(journal.clone());
+        let result = apply.execute(ApplyParams { count: 1 }).await?;
+        assert_eq!(result.undone.len(), 1);
+        assert_eq!(result.remaining, 2);
+        assert!(!moved.exists());
+        assert_eq!(fs::read_to_string(&created).await?, "new\n");
+
+        let result = apply.execute(ApplyParams { count: 5 }).await?;
+        assert_eq!(result.undone.len(), 2);
+        assert_eq!(result.remaining, 0);
+        assert!(!created.exists());
+        assert_eq!(fs::read_to_string(&existing).await?, "original\n");
+
+        fs::remove_dir_all(&dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_undo_batch_delete() -> Result<()> {
+        let dir = test_dir("batch");
+        fs::create_dir_all(dir.join("tree/sub")).await?;
+        fs::write(dir.join("tree/sub/file.txt"), "keep me\n").await?;
+        let journal = UndoJournal::with_dir(dir.join(".undo"));
+
+        let params: batch::Params = serde_json::from_value(serde_json::json!({
+            "operations": [
+                {"op": "delete", "path": dir.join("tree").to_string_lossy(), "recursive": true},
+                {"op": "mkdir", "path": dir.join("fresh").to_string_lossy()},
+            ]
+        }))?;
+        Journaled::new(batch::Batch, Some(journal.clone()))
+            .execute(params)
+            .await?;
+        assert!(!dir.join("tree").exists());
+        assert!(dir.join("fresh").is_dir());
+
+        journal.undo(1).await?;
+        assert_eq!(
+            fs::read_to_string(dir.join("tree/sub/file.txt")).await?,
+            "keep me\n"
+        );
+        assert!(!dir.join("fresh").exists());
+
+        fs::remove_dir_all(&dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_operation_is_not_journaled() -> Result<()> {
+        let dir = test_dir("failed");
+        let journal = UndoJournal::with_dir(dir.join(".undo"));
+
+        let result = Journaled::new(file_move::FileMove, Some(journal.clone()))
+            .execute(file_move::Params {
+                source: dir.join("missing").to_string_lossy().to_string(),
+                destination: dir.join("elsewhere").to_string_lossy().to_string(),
+                overwrite: false,
+                create_dirs: false,
+                merge: false,
+                replace: false,
+                use_trash: false,
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert!(journal.entries().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_undo_memory_fs() -> Result<()> {
+        let dir = test_dir("memory");
+        let journal = UndoJournal::with_dir(dir.join(".undo"));
+        let memory = Arc::new(vfs::MemoryFs::new().with_file("/repo/a.txt", "original\n"));
+
+        let write = Journaled::new(file_write::FileWrite, Some(journal.clone()));
+        for (path, content) in [("/repo/a.txt", "changed\n"), ("/repo/b.txt", "new\n")] {
+            let params: file_write::Params = serde_json::from_value(serde_json::json!({
+                "path": path,
+                "content": content,
+            }))?;
+            vfs::scope(memory.clone(), write.execute(params)).await?;
+        }
+        assert_eq!(memory.read(Path::new("/repo/a.txt")).await?, b"changed\n");
+
+        // The snapshots are restored into the filesystem the writes went to
+        journal.undo(2).await?;
+        assert_eq!(memory.read(Path::new("/repo/a.txt")).await?, b"original\n");
+        assert!(!memory.exists(Path::new("/repo/b.txt")).await);
+        assert!(!Path::new("/repo/a.txt").exists());
+
+        let _ = fs::remove_dir_all(&dir).await;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "directory-sync")]
+    #[tokio::test]
+    async fn test_undo_directory_sync() -> Result<()> {
+        let dir = test_dir("sync");
+        let journal = UndoJournal::with_dir(dir.join(".undo"));
+        let memory = Arc::new(
+            vfs::MemoryFs::new()
+                .with_file("/src/a.txt", "new")
+                .with_file("/dst/a.txt", "old")
+                .with_file("/dst/extra.txt", "extra"),
+        );
+
+        let sync = Journaled::new(directory_sync::DirectorySync, Some(journal.clone()));
+        let params = directory_sync::Params {
+            delete: true,
+            ..directory_sync::Params::new("/src", "/dst")
+        };
+        vfs::scope(memory.clone(), sync.execute(params)).await?;
+        assert!(!memory.exists(Path::new("/dst/extra.txt")).await);
+
+        journal.undo(1).await?;
+        assert_eq!(memory.read(Path::new("/dst/a.txt")).await?, b"old");
+        assert_eq!(memory.read(Path::new("/dst/extra.txt")).await?, b"extra");
+
+        let _ = fs::remove_dir_all(&dir).await;
+
+        Ok(())
+    }
+}
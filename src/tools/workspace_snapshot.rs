@@ -0,0 +1,912 @@
+//! Workspace snapshots
+//!
+//! A [`SnapshotStore`] keeps checkpoints of a set of root directories, to go
+//! back to after a multi-file refactor goes wrong. `workspace_snapshot` records
+//! every file and directory under the roots, `workspace_restore` puts the roots
+//! back the way a snapshot found them (removing what was added since), and
+//! `workspace_snapshot_list` shows the snapshots taken. The tools are added to
+//! a dispatcher with [`SnapshotStore::register`].
+//!
+//! Contents are stored once per distinct content under their SHA-256, so
+//! another snapshot of a mostly unchanged tree costs little. The store copies
+//! files in by default; with [`SnapshotStore::with_hard_links`] it links them
+//! instead, which is only safe when files are replaced rather than rewritten in
+//! place. Restore checks every object it uses and refuses to run if one
+//! changed.
+//!
+//! Snapshots cover the real filesystem. Symbolic links aren't recorded, and are
+//! only removed where a restore puts back a file or directory, so it never
+//! writes through them. Excluded paths are left alone by both tools.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use walkdir::WalkDir;
+
+use super::{Annotations, Tool};
+use crate::cancel::CancellationToken;
+use crate::jsonrpc::Dispatcher;
+use crate::sys::spawn_blocking;
+use crate::{Error, Result, ToolSchemaRegistry, cancel, paths};
+
+/// Snapshot store
+///
+/// Holds the configuration shared by the snapshot tools: where snapshots are
+/// kept, which roots they cover and which paths they skip.
+#[derive(Clone)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+    roots: Vec<PathBuf>,
+    exclude: Vec<Pattern>,
+    hard_links: bool,
+    /// Keeps a snapshot from reading a tree that a restore is rewriting
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl SnapshotStore {
+    /// Create a store keeping its snapshots in `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            roots: Vec::new(),
+            exclude: Vec::new(),
+            hard_links: false,
+            lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// Add a directory for the snapshots to cover
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.roots.push(root.into());
+        self
+    }
+
+    /// Skip paths matching `pattern`, a glob matched against the path relative
+    /// to its root such as `target` or `*.log`. An excluded directory is
+    /// skipped with everything below it.
+    pub fn with_exclude(mut self, pattern: &str) -> Result<Self> {
+        let pattern = Pattern::new(pattern).map_err(|e| {
+            Error::InvalidParam(format!("Invalid exclude pattern '{}': {}", pattern, e))
+        })?;
+        self.exclude.push(pattern);
+        Ok(self)
+    }
+
+    /// Hard link files into the store instead of copying them
+    pub fn with_hard_links(mut self, hard_links: bool) -> Self {
+        self.hard_links = hard_links;
+        self
+    }
+
+    /// Add `workspace_snapshot`, `workspace_restore` and
+    /// `workspace_snapshot_list` for this store to a dispatcher
+    pub fn register(&self, dispatcher: &mut Dispatcher, registry: &mut ToolSchemaRegistry) {
+        let snapshot_tool = WorkspaceSnapshot::new(self.clone());
+        list_tool(dispatcher, registry, &snapshot_tool);
+        dispatcher.register("workspace_snapshot", move |params: SnapshotParams| {
+            let snapshot_tool = snapshot_tool.clone();
+            async move { snapshot_tool.execute(params).await }
+        });
+
+        let restore_tool = WorkspaceRestore::new(self.clone());
+        list_tool(dispatcher, registry, &restore_tool);
+        dispatcher.register("workspace_restore", move |params: RestoreParams| {
+            let restore_tool = restore_tool.clone();
+            async move { restore_tool.execute(params).await }
+        });
+
+        let list = WorkspaceSnapshotList::new(self.clone());
+        list_tool(dispatcher, registry, &list);
+        dispatcher.register("workspace_snapshot_list", move |params: ListParams| {
+            let list = list.clone();
+            async move { list.execute(params).await }
+        });
+    }
+
+    fn objects_dir(&self) -> PathBuf {
+        self.dir.join("objects")
+    }
+
+    fn snapshots_dir(&self) -> PathBuf {
+        self.dir.join("snapshots")
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        self.objects_dir().join(&hash[..2]).join(hash)
+    }
+
+    /// Whether a path below a root is left out of snapshots and restores
+    fn is_excluded(&self, store_dir: &Path, path: &Path, relative: &str) -> bool {
+        path.starts_with(store_dir) || self.exclude.iter().any(|p| p.matches(relative))
+    }
+
+    /// Entries below `root` that snapshots cover, keyed by their relative path
+    fn walk(
+        &self,
+        root: &Path,
+        store_dir: &Path,
+        token: &CancellationToken,
+    ) -> Result<BTreeMap<String, (PathBuf, EntryKind)>> {
+        let mut entries = BTreeMap::new();
+        if !root.exists() {
+            return Ok(entries);
+        }
+        let walker = WalkDir::new(root)
+            .min_depth(1)
+            .follow_links(false)
+            .into_iter()
+            .filter_entry(|entry| {
+                let relative = relative_path(root, entry.path());
+                !self.is_excluded(store_dir, entry.path(), &relative)
+            });
+        for entry in walker {
+            if token.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            let entry = entry.map_err(io::Error::from)?;
+            let kind = if entry.file_type().is_dir() {
+                EntryKind::Dir
+            } else if entry.file_type().is_file() {
+                EntryKind::File
+            } else {
+                continue;
+            };
+            entries.insert(
+                relative_path(root, entry.path()),
+                (entry.path().to_path_buf(), kind),
+            );
+        }
+        Ok(entries)
+    }
+
+    /// Put a file's content in the store, returning its hash
+    fn store_file(&self, path: &Path, stats: &mut SnapshotOutput) -> Result<String> {
+        let hash = hash_file(path)?;
+        if self.object_path(&hash).exists() {
+            return Ok(hash);
+        }
+
+        // Hash what actually landed in the store, in case the file changed
+        let temp = self
+            .objects_dir()
+            .join(format!("tmp-{:08x}", rand::random::<u32>()));
+        if !(self.hard_links && fs::hard_link(path, &temp).is_ok()) {
+            fs::copy(path, &temp)?;
+        }
+        let hash = hash_file(&temp)?;
+        let object = self.object_path(&hash);
+        if object.exists() {
+            fs::remove_file(&temp)?;
+        } else {
+            fs::create_dir_all(object.parent().expect("objects have a parent"))?;
+            fs::rename(&temp, &object)?;
+            stats.new_objects += 1;
+            stats.stored_bytes += fs::metadata(&object)?.len();
+        }
+        Ok(hash)
+    }
+
+    /// Record every root in a new snapshot
+    fn take(&self, label: Option<String>, token: &CancellationToken) -> Result<SnapshotOutput> {
+        if self.roots.is_empty() {
+            return Err(Error::InvalidParam(
+                "The snapshot store has no roots configured".to_string(),
+            ));
+        }
+        fs::create_dir_all(self.objects_dir())?;
+        fs::create_dir_all(self.snapshots_dir())?;
+        let store_dir = std::path::absolute(&self.dir)?;
+
+        let created = Utc::now();
+        let id = format!(
+            "{}-{:04x}",
+            created.format("%Y%m%dT%H%M%S"),
+            rand::random::<u16>()
+        );
+        let mut output = SnapshotOutput {
+            id: id.clone(),
+            created: created.to_rfc3339(),
+            label: label.clone(),
+            files: 0,
+            directories: 0,
+            bytes: 0,
+            new_objects: 0,
+            stored_bytes: 0,
+        };
+
+        let mut roots = Vec::with_capacity(self.roots.len());
+        for root in &self.roots {
+            let root = std::path::absolute(root)?;
+            if !root.is_dir() {
+                return Err(Error::NotFound(format!(
+                    "Snapshot root is not a directory: {}",
+                    root.display()
+                )));
+            }
+
+            let mut entries = Vec::new();
+            for (relative, (path, kind)) in self.walk(&root, &store_dir, token)? {
+                let metadata = fs::metadata(&path)?;
+                let hash = match kind {
+                    EntryKind::Dir => {
+                        output.directories += 1;
+                        None
+                    }
+                    EntryKind::File => {
+                        output.files += 1;
+                        output.bytes += metadata.len();
+                        Some(self.store_file(&path, &mut output)?)
+                    }
+                };
+                entries.push(ManifestEntry {
+                    path: relative,
+                    kind,
+                    hash,
+                    mode: mode(&metadata),
+                });
+            }
+            roots.push(RootManifest { root, entries });
+        }
+
+        let manifest = Manifest {
+            id: id.clone(),
+            created: output.created.clone(),
+            label,
+            roots,
+        };
+        let json = serde_json::to_vec_pretty(&manifest)?;
+        fs::write(self.snapshots_dir().join(format!("{}.json", id)), json)?;
+        Ok(output)
+    }
+
+    /// Every snapshot in the store, oldest first
+    fn manifests(&self) -> Result<Vec<Manifest>> {
+        let entries = match fs::read_dir(self.snapshots_dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut manifests = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                manifests.push(serde_json::from_slice::<Manifest>(&fs::read(&path)?)?);
+            }
+        }
+        manifests.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.id.cmp(&b.id)));
+        Ok(manifests)
+    }
+
+    /// Put the roots back the way snapshot `id` (the latest by default) found
+    /// them
+    fn restore(
+        &self,
+        id: Option<&str>,
+        dry_run: bool,
+        token: &CancellationToken,
+    ) -> Result<RestoreOutput> {
+        let mut manifests = self.manifests()?;
+        let manifest = match id {
+            Some(id) => manifests
+                .into_iter()
+                .find(|m| m.id == id)
+                .ok_or_else(|| Error::NotFound(format!("No snapshot with id {}", id)))?,
+            None => manifests
+                .pop()
+                .ok_or_else(|| Error::NotFound("No snapshots have been taken".to_string()))?,
+        };
+
+        // Check the objects before touching anything
+        for root in &manifest.roots {
+            for hash in root.entries.iter().filter_map(|e| e.hash.as_deref()) {
+                if token.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+                let object = self.object_path(hash);
+                let intact = match hash_file(&object) {
+                    Ok(actual) => actual == hash,
+                    Err(e) if e.kind() == ErrorKind::NotFound => false,
+                    Err(e) => return Err(e.into()),
+                };
+                if !intact {
+                    return Err(Error::Conflict(format!(
+                        "Snapshot {} is damaged: the object {} is missing or changed",
+                        manifest.id, hash
+                    )));
+                }
+            }
+        }
+
+        let store_dir = std::path::absolute(&self.dir)?;
+        let mut output = RestoreOutput {
+            id: manifest.id.clone(),
+            dry_run,
+            restored: Vec::new(),
+            deleted: Vec::new(),
+            unchanged: 0,
+        };
+        for root in &manifest.roots {
+            let canonical_root = if dry_run {
+                None
+            } else {
+                fs::create_dir_all(&root.root)?;
+                Some(fs::canonicalize(&root.root)?)
+            };
+            let wanted: BTreeMap<&str, &ManifestEntry> = root
+                .entries
+                .iter()
+                .map(|entry| (entry.path.as_str(), entry))
+                .collect();
+            let current = self.walk(&root.root, &store_dir, token)?;
+
+            // Remove what the snapshot doesn't have, deepest first
+            for (relative, (path, kind)) in current.iter().rev() {
+                if wanted
+                    .get(relative.as_str())
+                    .is_some_and(|entry| entry.kind == *kind)
+                {
+                    continue;
+                }
+                output.deleted.push(paths::to_string(path));
+                if !dry_run {
+                    match kind {
+                        EntryKind::Dir => fs::remove_dir_all(path)?,
+                        EntryKind::File => fs::remove_file(path)?,
+                    }
+                }
+            }
+
+            for entry in &root.entries {
+                if token.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+                let path = root.root.join(&entry.path);
+                if let Some(canonical_root) = &canonical_root {
+                    // Parents come first, so a link in place of one is gone by now
+                    remove_link(&path)?;
+                    let parent = path.parent().expect("entries are below their root");
+                    paths::check_within(&fs::canonicalize(parent)?, canonical_root)?;
+                }
+                let Some(hash) = &entry.hash else {
+                    if !dry_run {
+                        fs::create_dir_all(&path)?;
+                        set_mode(&path, entry.mode)?;
+                    }
+                    continue;
+                };
+                let same = matches!(current.get(&entry.path), Some((_, EntryKind::File)))
+                    && hash_file(&path)? == *hash;
+                if same {
+                    output.unchanged += 1;
+                    continue;
+                }
+                output.restored.push(paths::to_string(&path));
+                if !dry_run {
+                    if path.exists() {
+                        fs::remove_file(&path)?;
+                    }
+                    fs::copy(self.object_path(hash), &path)?;
+                    set_mode(&path, entry.mode)?;
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// Register the schema of a snapshot tool and list it on the dispatcher
+fn list_tool<T>(dispatcher: &mut Dispatcher, registry: &mut ToolSchemaRegistry, tool: &T)
+where
+    T: Tool,
+    T::Params: JsonSchema,
+    T::Output: JsonSchema,
+{
+    registry.register_tool_with_result(tool);
+    if let Some(schema) = registry.get(tool.name()) {
+        dispatcher.list_tool(schema);
+    }
+}
+
+/// `path` relative to `root`, with `/` separators
+fn relative_path(root: &Path, path: &Path) -> String {
+    paths::to_string(path.strip_prefix(root).unwrap_or(path))
+}
+
+/// Remove a symbolic link at `path`, leaving anything else
+fn remove_link(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        // Windows links to directories go like directories
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            fs::remove_file(path).or_else(|_| fs::remove_dir(path))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// SHA-256 of a file's content, in hex
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    match mode {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+/// Kind of entry recorded in a snapshot
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum EntryKind {
+    File,
+    Dir,
+}
+
+/// A file or directory recorded in a snapshot
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestEntry {
+    /// Path relative to the root, with `/` separators
+    path: String,
+    kind: EntryKind,
+    /// SHA-256 of a file's content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    /// Unix permission bits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+}
+
+/// The entries recorded below one root
+#[derive(Debug, Serialize, Deserialize)]
+struct RootManifest {
+    root: PathBuf,
+    entries: Vec<ManifestEntry>,
+}
+
+/// A snapshot as saved in the store
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    id: String,
+    created: String,
+    label: Option<String>,
+    roots: Vec<RootManifest>,
+}
+
+/// Workspace snapshot tool
+#[derive(Clone)]
+pub struct WorkspaceSnapshot {
+    store: SnapshotStore,
+}
+
+impl WorkspaceSnapshot {
+    /// Create a snapshot tool for `store`
+    pub fn new(store: SnapshotStore) -> Self {
+        Self { store }
+    }
+}
+
+/// Parameters for the workspace_snapshot tool
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct SnapshotParams {
+    /// Note to recognize the snapshot by, e.g. "before renaming the parser"
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// Output of the workspace_snapshot tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotOutput {
+    /// Id to restore the snapshot with
+    pub id: String,
+
+    /// When the snapshot was taken (RFC 3339)
+    pub created: String,
+
+    /// The snapshot's label
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    /// Number of files recorded
+    pub files: usize,
+
+    /// Number of directories recorded
+    pub directories: usize,
+
+    /// Total size of the files recorded
+    pub bytes: u64,
+
+    /// Number of contents the store didn't have yet
+    pub new_objects: usize,
+
+    /// Bytes added to the store
+    pub stored_bytes: u64,
+}
+
+#[async_trait]
+impl Tool for WorkspaceSnapshot {
+    type Params = SnapshotParams;
+    type Output = SnapshotOutput;
+
+    fn name(&self) -> &str {
+        "workspace_snapshot"
+    }
+
+    fn description(&self) -> &str {
+        "Record the files under the workspace roots as a checkpoint that workspace_restore can go back to"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations {
+            destructive: false,
+            ..Annotations::DESTRUCTIVE
+        }
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let _guard = self.store.lock.lock().await;
+        let store = self.store.clone();
+        let token = cancel::current();
+        spawn_blocking(move || store.take(params.label, &token))
+            .await
+            .map_err(|e| Error::Other(format!("Join error: {}", e)))?
+    }
+}
+
+/// Workspace restore tool
+#[derive(Clone)]
+pub struct WorkspaceRestore {
+    store: SnapshotStore,
+}
+
+impl WorkspaceRestore {
+    /// Create a restore tool for `store`
+    pub fn new(store: SnapshotStore) -> Self {
+        Self { store }
+    }
+}
+
+/// Parameters for the workspace_restore tool
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct RestoreParams {
+    /// Id of the snapshot to restore; the latest if not set
+    #[serde(default)]
+    pub id: Option<String>,
+
+    /// Only report what would be restored and deleted
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Output of the workspace_restore tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct RestoreOutput {
+    /// Id of the snapshot restored
+    pub id: String,
+
+    /// Whether the changes were only planned
+    pub dry_run: bool,
+
+    /// Files written back from the snapshot
+    pub restored: Vec<String>,
+
+    /// Files and directories removed because the snapshot doesn't have them
+    pub deleted: Vec<String>,
+
+    /// Number of files that already matched the snapshot
+    pub unchanged: usize,
+}
+
+#[async_trait]
+impl Tool for WorkspaceRestore {
+    type Params = RestoreParams;
+    type Output = RestoreOutput;
+
+    fn name(&self) -> &str {
+        "workspace_restore"
+    }
+
+    fn description(&self) -> &str {
+        "Put the workspace roots back the way a snapshot found them, removing files added since"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations {
+            idempotent: true,
+            ..Annotations::DESTRUCTIVE
+        }
+    }
+
+    async fn execute(&self, params: Self::Params) -> Result<Self::Output> {
+        let _guard = self.store.lock.lock().await;
+        let store = self.store.clone();
+        let token = cancel::current();
+        spawn_blocking(move || store.restore(params.id.as_deref(), params.dry_run, &token))
+            .await
+            .map_err(|e| Error::Other(format!("Join error: {}", e)))?
+    }
+}
+
+/// Workspace snapshot list tool
+#[derive(Clone)]
+pub struct WorkspaceSnapshotList {
+    store: SnapshotStore,
+}
+
+impl WorkspaceSnapshotList {
+    /// Create a list tool for `store`
+    pub fn new(store: SnapshotStore) -> Self {
+        Self { store }
+    }
+}
+
+/// Parameters for the workspace_snapshot_list tool
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ListParams {}
+
+/// A snapshot in the store
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotInfo {
+    /// Id of the snapshot
+    pub id: String,
+
+    /// When the snapshot was taken (RFC 3339)
+    pub created: String,
+
+    /// The snapshot's label
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    /// Roots the snapshot covers
+    pub roots: Vec<String>,
+
+    /// Number of files recorded
+    pub files: usize,
+}
+
+/// Output of the workspace_snapshot_list tool
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ListOutput {
+    /// Snapshots, newest first
+    pub snapshots: Vec<SnapshotInfo>,
+}
+
+#[async_trait]
+impl Tool for WorkspaceSnapshotList {
+    type Params = ListParams;
+    type Output = ListOutput;
+
+    fn name(&self) -> &str {
+        "workspace_snapshot_list"
+    }
+
+    fn description(&self) -> &str {
+        "List the workspace snapshots that can be restored"
+    }
+
+    fn annotations(&self) -> Annotations {
+        Annotations::READ_ONLY
+    }
+
+    async fn execute(&self, _params: Self::Params) -> Result<Self::Output> {
+        let store = self.store.clone();
+        let manifests = spawn_blocking(move || store.manifests())
+            .await
+            .map_err(|e| Error::Other(format!("Join error: {}", e)))??;
+        let snapshots = manifests
+            .into_iter()
+            .rev()
+            .map(|manifest| SnapshotInfo {
+                files: manifest
+                    .roots
+                    .iter()
+                    .flat_map(|root| &root.entries)
+                    .filter(|entry| entry.kind == EntryKind::File)
+                    .count(),
+                roots: manifest
+                    .roots
+                    .iter()
+                    .map(|root| paths::to_string(&root.root))
+                    .collect(),
+                id: manifest.id,
+                created: manifest.created,
+                label: manifest.label,
+            })
+            .collect();
+        Ok(ListOutput { snapshots })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "snapshot_test_{}_{}_{}",
+            name,
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u16>()
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_and_restore() -> Result<()> {
+        let dir = test_dir("restore");
+        let root = dir.join("work");
+        fs::create_dir_all(root.join("src"))?;
+        fs::create_dir_all(root.join("target"))?;
+        fs::write(root.join("src/lib.rs"), "pub fn a() {}\n")?;
+        fs::write(root.join("src/copy.rs"), "pub fn a() {}\n")?;
+        fs::write(root.join("README.md"), "# Work\n")?;
+        fs::write(root.join("target/out"), "build output")?;
+
+        // The store lives inside the root and is skipped on its own
+        let store = SnapshotStore::new(root.join(".snapshots"))
+            .with_root(&root)
+            .with_exclude("target")?;
+        let snapshot = WorkspaceSnapshot::new(store.clone());
+        let restore = WorkspaceRestore::new(store.clone());
+
+        let taken = snapshot
+            .execute(SnapshotParams {
+                label: Some("before refactor".to_string()),
+            })
+            .await?;
+        assert_eq!((taken.files, taken.directories), (3, 1));
+        // Identical files share one object
+        assert_eq!(taken.new_objects, 2);
+
+        // A refactor gone wrong
+        fs::write(root.join("src/lib.rs"), "broken")?;
+        fs::remove_file(root.join("README.md"))?;
+        fs::create_dir_all(root.join("src/new"))?;
+        fs::write(root.join("src/new/mod.rs"), "added")?;
+        fs::write(root.join("target/out"), "newer build output")?;
+
+        let planned = restore
+            .execute(RestoreParams {
+                id: None,
+                dry_run: true,
+            })
+            .await?;
+        assert_eq!(planned.restored.len(), 2);
+        assert_eq!(planned.deleted.len(), 2);
+        assert_eq!(fs::read_to_string(root.join("src/lib.rs"))?, "broken");
+
+        let restored = restore
+            .execute(RestoreParams {
+                id: Some(taken.id.clone()),
+                dry_run: false,
+            })
+            .await?;
+        assert_eq!(restored.unchanged, 1);
+        assert_eq!(
+            fs::read_to_string(root.join("src/lib.rs"))?,
+            "pub fn a() {}\n"
+        );
+        assert_eq!(fs::read_to_string(root.join("README.md"))?, "# Work\n");
+        assert!(!root.join("src/new").exists());
+        // Excluded paths are left alone
+        assert_eq!(
+            fs::read_to_string(root.join("target/out"))?,
+            "newer build output"
+        );
+
+        // Nothing left to do the second time
+        let again = restore.execute(RestoreParams::default()).await?;
+        assert!(again.restored.is_empty() && again.deleted.is_empty());
+
+        let list = WorkspaceSnapshotList::new(store)
+            .execute(ListParams::default())
+            .await?;
+        assert_eq!(list.snapshots.len(), 1);
+        assert_eq!(list.snapshots[0].label.as_deref(), Some("before refactor"));
+        assert_eq!(list.snapshots[0].files, 3);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_refuses_damaged_snapshot() -> Result<()> {
+        let dir = test_dir("damaged");
+        let root = dir.join("work");
+        fs::create_dir_all(&root)?;
+        fs::write(root.join("a.txt"), "a")?;
+
+        let store = SnapshotStore::new(dir.join("store"))
+            .with_root(&root)
+            .with_hard_links(true);
+        WorkspaceSnapshot::new(store.clone())
+            .execute(SnapshotParams::default())
+            .await?;
+
+        // Rewriting a hard linked file in place changes the stored object too
+        fs::write(root.join("a.txt"), "changed in place")?;
+        let result = WorkspaceRestore::new(store.clone())
+            .execute(RestoreParams::default())
+            .await;
+        assert!(matches!(result, Err(Error::Conflict(_))));
+
+        let result = WorkspaceRestore::new(store)
+            .execute(RestoreParams {
+                id: Some("missing".to_string()),
+                dry_run: false,
+            })
+            .await;
+        assert!(matches!(result, Err(Error::NotFound(_))));
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_restore_replaces_links() -> Result<()> {
+        let dir = test_dir("links");
+        let root = dir.join("work");
+        let outside = dir.join("outside");
+        fs::create_dir_all(root.join("src"))?;
+        fs::create_dir_all(&outside)?;
+        fs::write(root.join("src/lib.rs"), "pub fn a() {}\n")?;
+
+        let store = SnapshotStore::new(dir.join("store")).with_root(&root);
+        WorkspaceSnapshot::new(store.clone())
+            .execute(SnapshotParams::default())
+            .await?;
+
+        // The directory is swapped for a link to somewhere else
+        fs::remove_dir_all(root.join("src"))?;
+        std::os::unix::fs::symlink(&outside, root.join("src"))?;
+
+        WorkspaceRestore::new(store)
+            .execute(RestoreParams::default())
+            .await?;
+        assert!(!fs::symlink_metadata(root.join("src"))?.is_symlink());
+        assert_eq!(
+            fs::read_to_string(root.join("src/lib.rs"))?,
+            "pub fn a() {}\n"
+        );
+        assert!(!outside.join("lib.rs").exists());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -6,7 +6,7 @@
 use serde::{Serialize, de::DeserializeOwned};
 use serde_json::Value;
 
-use crate::Result;
+use crate::{Error, Result};
 
 /// Input format for parameters
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -133,7 +133,7 @@ fn from_bedrock_format(value: &Value) -> Value {
             Value::Array(new_arr)
         }
         // For other values, return as is
-        _ => value.clone(),
+        _ => value.clone()
     }
 }
 
@@ -187,8 +187,10 @@ pub fn serialize<T: Serialize>(value: T) -> Result<Value> {
     Ok(serde_json::to_value(value)?)
 }
 
+/// Parameters that don't fit the type are invalid params rather than
+/// malformed JSON
 pub fn deserialize<T: DeserializeOwned>(value: Value) -> Result<T> {
-    Ok(serde_json::from_value(value)?)
+    serde_json::from_value(value).map_err(|e| Error::InvalidParam(e.to_string()))
 }
 
 /// Create a standard format transformer
@@ -0,0 +1,133 @@
+//! Recoverable deletion
+//!
+//! Tools that delete or replace paths take a `use_trash` option. With it the
+//! old entry is moved to a trash instead of being removed: the directory set
+//! with [`Dispatcher::with_trash_dir`] if there is one, otherwise the
+//! operating system's trash with the `os-trash` feature, otherwise `.trash` in
+//! the working directory. Entries in a trash directory are named after the
+//! original with the time they were trashed appended, e.g.
+//! `notes.txt.20240501T101500123`.
+//!
+//! Moving into a trash directory is a rename, so it must be on the same
+//! filesystem as the paths being deleted.
+//!
+//! [`Dispatcher::with_trash_dir`]: crate::jsonrpc::Dispatcher::with_trash_dir
+
+use std::future::Future;
+use std::path::PathBuf;
+#[cfg(any(
+    test,
+    feature = "directory-sync",
+    feature = "file-move",
+    feature = "file-patch",
+))]
+use {
+    crate::Result,
+    crate::vfs::Filesystem,
+    std::io::ErrorKind,
+    std::path::Path,
+};
+
+/// Trash directory used when none is configured and there is no system trash
+pub const DEFAULT_DIRECTORY: &str = ".trash";
+
+tokio::task_local! {
+    static DIRECTORY: PathBuf;
+}
+
+/// Run `future` with `directory` as the trash
+pub fn scope<F: Future>(directory: PathBuf, future: F) -> impl Future<Output = F::Output> {
+    DIRECTORY.scope(directory, future)
+}
+
+/// Move `path` to the trash. Returns where it went, or `None` when it went to
+/// the system trash.
+#[cfg(any(test, feature = "directory-sync", feature = "file-move"))]
+pub(crate) async fn discard(fs: &dyn Filesystem, path: &Path) -> Result<Option<PathBuf>> {
+    discard_as(fs, path, path).await
+}
+
+/// Move `path`, which was parked away from `original`, to the trash under the
+/// name of `original`. The system trash keeps the parked name.
+#[cfg(any(
+    test,
+    feature = "directory-sync",
+    feature = "file-move",
+    feature = "file-patch",
+))]
+pub(crate) async fn discard_as(
+    fs: &dyn Filesystem,
+    path: &Path,
+    original: &Path,
+) -> Result<Option<PathBuf>> {
+    let directory = match DIRECTORY.try_with(PathBuf::clone) {
+        Ok(directory) => directory,
+        Err(_) => match fs.trash(path).await {
+            Ok(()) => return Ok(None),
+            Err(e) if e.kind() == ErrorKind::Unsupported => PathBuf::from(DEFAULT_DIRECTORY),
+            Err(e) => return Err(e.into()),
+        },
+    };
+
+    fs.create_dir_all(&directory).await?;
+    let name = original
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "root".to_string());
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%3f");
+    let mut target = directory.join(format!("{}.{}", name, stamp));
+    let mut n = 1;
+    while fs.exists(&target).await {
+        n += 1;
+        target = directory.join(format!("{}.{}-{}", name, stamp, n));
+    }
+    fs.rename(path, &target).await?;
+    Ok(Some(target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[tokio::test]
+    async fn test_discard() -> Result<()> {
+        let fs = MemoryFs::new()
+            .with_file("/work/notes.txt", "first")
+            .with_file("/work/old/a.txt", "a");
+
+        scope(PathBuf::from("/trash"), async {
+            let trashed = discard(&fs, Path::new("/work/notes.txt")).await?.unwrap();
+            assert!(trashed.starts_with("/trash"));
+            assert!(trashed.to_string_lossy().contains("notes.txt."));
+            assert_eq!(fs.read_to_string(&trashed).await?, "first");
+            assert!(!fs.exists(Path::new("/work/notes.txt")).await);
+
+            // Same name again in the same instant gets its own entry
+            fs.write(Path::new("/work/notes.txt"), b"second").await?;
+            let again = discard(&fs, Path::new("/work/notes.txt")).await?.unwrap();
+            assert_ne!(again, trashed);
+
+            // Directories go whole, under their original name when parked
+            fs.rename(Path::new("/work/old"), Path::new("/work/.old.parked"))
+                .await?;
+            let dir = discard_as(&fs, Path::new("/work/.old.parked"), Path::new("/work/old"))
+                .await?
+                .unwrap();
+            assert!(dir.file_name().unwrap().to_string_lossy().starts_with("old."));
+            assert_eq!(fs.read_to_string(&dir.join("a.txt")).await?, "a");
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_discard_default_directory() -> Result<()> {
+        // MemoryFs has no system trash, so the default directory is used
+        let fs = MemoryFs::new().with_file("/a.txt", "a");
+        let trashed = discard(&fs, Path::new("/a.txt")).await?.unwrap();
+        assert!(trashed.starts_with(DEFAULT_DIRECTORY));
+        assert_eq!(fs.read_to_string(&trashed).await?, "a");
+        Ok(())
+    }
+}
@@ -0,0 +1,1108 @@
+//! Filesystem abstraction used by the tools.
+//!
+//! Tools reach the filesystem through [`current`], which is the real one unless
+//! the task runs inside [`scope`] with another [`Filesystem`], such as a
+//! [`MemoryFs`] for hermetic tests. A dispatcher can be given a filesystem for
+//! all of its tools with [`Dispatcher::with_filesystem`].
+//!
+//! The shell always uses the real filesystem.
+//!
+//! With the `object-store` feature, `file_read`, `file_write` and `file_find`
+//! also take `s3://` and `gs://` URIs, which [`for_path`] maps to an
+//! `ObjectStoreFs` for the bucket.
+//!
+//! [`Dispatcher::with_filesystem`]: crate::jsonrpc::Dispatcher::with_filesystem
+
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io::{self, Cursor, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use walkdir::WalkDir;
+
+use crate::Result;
+use crate::warnings::{self, Skipped, Warnings};
+use crate::{limits, paths, quota, sys, telemetry};
+
+#[cfg(feature = "object-store")]
+mod remote;
+#[cfg(feature = "object-store")]
+pub use remote::{ObjectStoreFs, register_object_store};
+
+/// URI schemes served by an object store
+const REMOTE_SCHEMES: &[&str] = &["s3", "gs", "gcs"];
+
+tokio::task_local! {
+    static CURRENT: Arc<dyn Filesystem>;
+}
+
+/// The filesystem for the current task: the one set with [`scope`], or the
+/// real filesystem
+pub fn current() -> Arc<dyn Filesystem> {
+    CURRENT
+        .try_with(Arc::clone)
+        .unwrap_or_else(|_| Arc::new(RealFs))
+}
+
+/// Run `future` with `fs` as the filesystem seen by the tools
+pub fn scope<F: Future>(fs: Arc<dyn Filesystem>, future: F) -> impl Future<Output = F::Output> {
+    CURRENT.scope(fs, future)
+}
+
+/// Scheme of an object store URI such as `s3://bucket/key`
+pub(crate) fn remote_scheme(path: &Path) -> Option<&str> {
+    let (scheme, _) = path.to_str()?.split_once("://")?;
+    REMOTE_SCHEMES.contains(&scheme).then_some(scheme)
+}
+
+/// The filesystem holding `path`: the bucket's object store for `s3://` and
+/// `gs://` URIs, otherwise [`current`]
+pub fn for_path(path: &Path) -> Result<Arc<dyn Filesystem>> {
+    match remote_scheme(path) {
+        None => Ok(current()),
+        #[cfg(feature = "object-store")]
+        Some(_) => Ok(remote::for_url(path)?),
+        #[cfg(not(feature = "object-store"))]
+        Some(scheme) => Err(crate::Error::InvalidParam(format!(
+            "{}:// paths need the object-store feature",
+            scheme
+        ))),
+    }
+}
+
+/// Kind of filesystem entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// A regular file
+    File,
+    /// A directory
+    Dir,
+    /// A symbolic link that was not followed
+    Symlink,
+}
+
+/// Metadata of a filesystem entry
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    /// Kind of entry
+    pub kind: FileKind,
+    /// Size in bytes
+    pub len: u64,
+    /// Last modification time, if known
+    pub modified: Option<SystemTime>,
+    /// Unix permission bits, if the filesystem has them
+    pub mode: Option<u32>,
+}
+
+impl Metadata {
+    /// Whether the entry is a regular file
+    pub fn is_file(&self) -> bool {
+        self.kind == FileKind::File
+    }
+
+    /// Whether the entry is a directory
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileKind::Dir
+    }
+
+    /// Size in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the entry has no content
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Last modification time, failing like `std::fs::Metadata::modified` when unknown
+    pub fn modified(&self) -> io::Result<SystemTime> {
+        self.modified.ok_or_else(|| {
+            io::Error::new(ErrorKind::Unsupported, "modification time not available")
+        })
+    }
+}
+
+impl From<std::fs::Metadata> for Metadata {
+    fn from(metadata: std::fs::Metadata) -> Self {
+        let kind = if metadata.is_dir() {
+            FileKind::Dir
+        } else if metadata.file_type().is_symlink() {
+            FileKind::Symlink
+        } else {
+            FileKind::File
+        };
+
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode())
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+
+        Self {
+            kind,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            mode,
+        }
+    }
+}
+
+/// An entry found by [`Filesystem::read_dir`] or [`Filesystem::walk`]
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    /// Full path of the entry
+    pub path: PathBuf,
+    /// Metadata of the entry (of the link itself, unless links are followed)
+    pub metadata: Metadata,
+    /// Depth below the directory that was read or walked (children are 1)
+    pub depth: usize,
+}
+
+impl DirEntry {
+    /// Full path of the entry
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Last component of the path
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// How to walk a directory tree
+#[derive(Debug, Clone, Copy)]
+pub struct WalkOptions {
+    /// How deep to descend (1 lists only the directory's children)
+    pub max_depth: usize,
+    /// Whether to descend into symbolic links to directories
+    pub follow_links: bool,
+}
+
+/// Operations the tools need from a filesystem
+#[async_trait]
+pub trait Filesystem: Send + Sync {
+    /// Metadata of a path, following symbolic links
+    async fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Read a whole file
+    async fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Create or replace a file; its parent directory must exist
+    async fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// Entries of a directory, in no particular order
+    async fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+
+    /// Create a directory; its parent must exist
+    async fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Create a directory and any missing parents
+    async fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Remove a file
+    async fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Remove an empty directory
+    async fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Remove a directory and everything below it
+    async fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        let options = WalkOptions {
+            max_depth: usize::MAX,
+            follow_links: false,
+        };
+        // Children sort after their parents, so are removed first
+        for entry in self.walk(path, options).await?.iter().rev() {
+            if entry.metadata.is_dir() {
+                self.remove_dir(&entry.path).await?;
+            } else {
+                self.remove_file(&entry.path).await?;
+            }
+        }
+        self.remove_dir(path).await
+    }
+
+    /// Set the Unix permission bits of a path
+    async fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()>;
+
+    /// Absolute form of an existing path, with links and `..` resolved
+    async fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Set the access and modification times of a path, leaving a time that
+    /// is `None` unchanged
+    async fn set_times(
+        &self,
+        _path: &Path,
+        _accessed: Option<SystemTime>,
+        _modified: Option<SystemTime>,
+    ) -> io::Result<()> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "setting file times is not supported by this filesystem",
+        ))
+    }
+
+    /// Rename a file or directory; the destination must not exist
+    async fn rename(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "renaming is not supported by this filesystem",
+        ))
+    }
+
+    /// Put the file at `from` in place of the one at `to`, in one step where
+    /// the filesystem can
+    async fn replace(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if self.metadata(to).await.is_ok_and(|m| m.is_file()) {
+            self.remove_file(to).await?;
+        }
+        match self.rename(from, to).await {
+            Err(e) if e.kind() == ErrorKind::Unsupported => {
+                self.copy(from, to).await?;
+                self.remove_file(from).await
+            }
+            result => result,
+        }
+    }
+
+    /// Flush a file's content to durable storage; a no-op where writes are
+    /// durable once they return
+    async fn sync(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Move a file or directory to the operating system's trash
+    async fn trash(&self, _path: &Path) -> io::Result<()> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "there is no system trash for this filesystem",
+        ))
+    }
+
+    /// Open a file for reading
+    async fn open(&self, path: &Path) -> io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        Ok(Box::new(Cursor::new(self.read(path).await?)))
+    }
+
+    /// Read up to `len` bytes of a file, starting `offset` bytes in; fewer
+    /// come back at the end of the file
+    async fn read_range(&self, path: &Path, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut reader = self.open(path).await?;
+        tokio::io::copy(&mut (&mut reader).take(offset), &mut tokio::io::sink()).await?;
+        let mut data = Vec::new();
+        reader.take(len as u64).read_to_end(&mut data).await?;
+        Ok(data)
+    }
+
+    /// Read a whole file as UTF-8 text
+    async fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read(path).await?).map_err(|_| {
+            io::Error::new(ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+        })
+    }
+
+    /// Copy a file's content to another path
+    async fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        let contents = self.read(from).await?;
+        self.write(to, &contents).await?;
+        Ok(contents.len() as u64)
+    }
+
+    /// Whether anything exists at a path
+    async fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).await.is_ok()
+    }
+
+    /// All entries below a directory, parents before their children.
+    /// Entries that can't be read are skipped; walks over the
+    /// [`FsLimits`](crate::limits::FsLimits) fail.
+    async fn walk(&self, root: &Path, options: WalkOptions) -> io::Result<Vec<DirEntry>> {
+        Ok(self.walk_skipping(root, options).await?.0)
+    }
+
+    /// Like [`walk`](Self::walk), also counting the entries that were skipped
+    /// because they couldn't be read
+    async fn walk_skipping(
+        &self,
+        root: &Path,
+        options: WalkOptions,
+    ) -> io::Result<(Vec<DirEntry>, Skipped)> {
+        let mut budget = limits::WalkBudget::new(root);
+        let mut found = Vec::new();
+        let mut skipped = Skipped::default();
+        let mut pending = vec![(root.to_path_buf(), 0)];
+
+        while let Some((dir, depth)) = pending.pop() {
+            if depth >= options.max_depth {
+                continue;
+            }
+            let entries = match self.read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) => {
+                    warnings::warn(format!("Skipped {}: {}", paths::to_string(&dir), e));
+                    skipped.record(&e);
+                    continue;
+                }
+            };
+
+            for mut entry in entries {
+                budget.visit()?;
+                entry.depth = depth + 1;
+                if entry.metadata.kind == FileKind::Symlink
+                    && options.follow_links
+                    && let Ok(target) = self.metadata(&entry.path).await
+                {
+                    entry.metadata = target;
+                }
+                if entry.metadata.is_dir() {
+                    pending.push((entry.path.clone(), entry.depth));
+                }
+                found.push(entry);
+            }
+        }
+
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok((found, skipped))
+    }
+}
+
+/// The real filesystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+#[async_trait]
+impl Filesystem for RealFs {
+    async fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        Ok(sys::fs::metadata(paths::long(path)).await?.into())
+    }
+
+    async fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let _open = limits::open_file().await;
+        let data = sys::fs::read(paths::long(path)).await?;
+        telemetry::record_read(data.len());
+        Ok(data)
+    }
+
+    async fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        quota::charge_write(self, path, contents.len()).await?;
+        sys::fs::write(paths::long(path), contents).await?;
+        telemetry::record_written(contents.len());
+        Ok(())
+    }
+
+    // WASM has no async file handles and keeps reading the whole file
+    #[cfg(not(target_family = "wasm"))]
+    async fn open(&self, path: &Path) -> io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        let open = limits::open_file().await;
+        let file = sys::fs::File::open(paths::long(path)).await?;
+        Ok(Box::new(Counted(file, open)))
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    async fn read_range(&self, path: &Path, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        use tokio::io::AsyncSeekExt;
+
+        let _open = limits::open_file().await;
+        let mut file = sys::fs::File::open(paths::long(path)).await?;
+        file.seek(io::SeekFrom::Start(offset)).await?;
+        let mut data = Vec::new();
+        file.take(len as u64).read_to_end(&mut data).await?;
+        telemetry::record_read(data.len());
+        Ok(data)
+    }
+
+    async fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut dir = sys::fs::read_dir(paths::long(path)).await?;
+        let mut entries = Vec::new();
+        while let Some(entry) = dir.next_entry().await? {
+            // Skip entries that vanished or can't be inspected
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            entries.push(DirEntry {
+                path: paths::simplify(&entry.path()),
+                metadata: metadata.into(),
+                depth: 1,
+            });
+        }
+        Ok(entries)
+    }
+
+    async fn create_dir(&self, path: &Path) -> io::Result<()> {
+        sys::fs::create_dir(paths::long(path)).await
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        sys::fs::create_dir_all(paths::long(path)).await
+    }
+
+    async fn remove_file(&self, path: &Path) -> io::Result<()> {
+        sys::fs::remove_file(paths::long(path)).await
+    }
+
+    async fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        sys::fs::remove_dir(paths::long(path)).await
+    }
+
+    async fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        sys::fs::remove_dir_all(paths::long(path)).await
+    }
+
+    #[cfg(unix)]
+    async fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        sys::fs::set_permissions(paths::long(path), std::fs::Permissions::from_mode(mode)).await
+    }
+
+    #[cfg(not(unix))]
+    async fn set_mode(&self, _path: &Path, _mode: u32) -> io::Result<()> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "file permissions are only supported on Unix",
+        ))
+    }
+
+    async fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        Ok(paths::simplify(
+            &sys::fs::canonicalize(paths::long(path)).await?,
+        ))
+    }
+
+    async fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> io::Result<()> {
+        let path = paths::long(path).into_owned();
+        sys::spawn_blocking(move || {
+            // Windows needs write access to change times, and Unix only
+            // ownership, which also covers read-only files and directories
+            let file = if cfg!(windows) {
+                std::fs::File::options().write(true).open(&path)?
+            } else {
+                std::fs::File::open(&path)?
+            };
+            let mut times = std::fs::FileTimes::new();
+            if let Some(accessed) = accessed {
+                times = times.set_accessed(accessed);
+            }
+            if let Some(modified) = modified {
+                times = times.set_modified(modified);
+            }
+            file.set_times(times)
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        sys::fs::rename(paths::long(from), paths::long(to)).await
+    }
+
+    async fn replace(&self, from: &Path, to: &Path) -> io::Result<()> {
+        // Renaming over a file replaces it atomically
+        sys::fs::rename(paths::long(from), paths::long(to)).await
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    async fn sync(&self, path: &Path) -> io::Result<()> {
+        let _open = limits::open_file().await;
+        // Windows only flushes files opened for writing
+        let file = sys::fs::OpenOptions::new()
+            .write(true)
+            .open(paths::long(path))
+            .await?;
+        file.sync_all().await
+    }
+
+    #[cfg(all(feature = "os-trash", not(target_family = "wasm")))]
+    async fn trash(&self, path: &Path) -> io::Result<()> {
+        let path = path.to_path_buf();
+        sys::spawn_blocking(move || ::trash::delete(&path).map_err(io::Error::other))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        let (from, to) = (paths::long(from), paths::long(to));
+        let len = sys::fs::metadata(&from).await?.len();
+        quota::charge_write(self, &to, len as usize).await?;
+        sys::fs::copy(from, to).await
+    }
+
+    async fn walk_skipping(
+        &self,
+        root: &Path,
+        options: WalkOptions,
+    ) -> io::Result<(Vec<DirEntry>, Skipped)> {
+        let mut budget = limits::WalkBudget::new(root);
+        let root = paths::long(root).into_owned();
+        // Stop early when the call is cancelled; callers check for that themselves
+        let token = crate::cancel::current();
+        let warnings = Warnings::current();
+        sys::spawn_blocking(move || {
+            let mut found = Vec::new();
+            let mut skipped = Skipped::default();
+            let entries = WalkDir::new(&root)
+                .min_depth(1)
+                .max_depth(options.max_depth)
+                .follow_links(options.follow_links)
+                .into_iter()
+                .take_while(|_| !token.is_cancelled());
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        warnings.warn(walk_warning(&e));
+                        skipped.record_walk(&e);
+                        continue;
+                    }
+                };
+                budget.visit()?;
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                found.push(DirEntry {
+                    path: paths::simplify(entry.path()),
+                    metadata: metadata.into(),
+                    depth: entry.depth(),
+                });
+            }
+            Ok((found, skipped))
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+}
+
+/// Warning for an entry a walk had to skip
+pub(crate) fn walk_warning(error: &walkdir::Error) -> String {
+    let path = error.path().map(paths::to_string).unwrap_or_default();
+    match error.io_error() {
+        Some(e) => format!("Skipped {}: {}", path, e),
+        None => format!("Skipped {}: {}", path, error),
+    }
+}
+
+/// A reader that counts what is read through it as bytes read from a file,
+/// holding its file's place among the open files until dropped
+#[cfg(not(target_family = "wasm"))]
+struct Counted<R>(R, #[allow(dead_code)] tokio::sync::OwnedSemaphorePermit);
+
+#[cfg(not(target_family = "wasm"))]
+impl<R: AsyncRead + Unpin> AsyncRead for Counted<R> {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = std::pin::Pin::new(&mut self.0).poll_read(cx, buf);
+        if let std::task::Poll::Ready(Ok(())) = poll {
+            telemetry::record_read(buf.filled().len() - before);
+        }
+        poll
+    }
+}
+
+/// A node of the in-memory filesystem
+#[derive(Debug, Clone)]
+enum Node {
+    File {
+        data: Vec<u8>,
+        modified: SystemTime,
+        mode: u32,
+    },
+    Dir {
+        modified: SystemTime,
+        mode: u32,
+    },
+}
+
+impl Node {
+    fn metadata(&self) -> Metadata {
+        match self {
+            Node::File {
+                data,
+                modified,
+                mode,
+            } => Metadata {
+                kind: FileKind::File,
+                len: data.len() as u64,
+                modified: Some(*modified),
+                mode: Some(*mode),
+            },
+            Node::Dir { modified, mode } => Metadata {
+                kind: FileKind::Dir,
+                len: 0,
+                modified: Some(*modified),
+                mode: Some(*mode),
+            },
+        }
+    }
+
+    fn new_dir() -> Self {
+        Node::Dir {
+            modified: SystemTime::now(),
+            mode: 0o40755,
+        }
+    }
+}
+
+/// An in-memory filesystem, mainly for hermetic tests. Relative paths are
+/// taken relative to `/`, which always exists.
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    nodes: Mutex<BTreeMap<PathBuf, Node>>,
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        ErrorKind::NotFound,
+        format!("No such file or directory: {}", path.display()),
+    )
+}
+
+/// Make a path absolute and drop `.` and `..` components
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(part) => normalized.push(part),
+            _ => {}
+        }
+    }
+    normalized
+}
+
+impl MemoryFs {
+    /// Create an empty filesystem
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, creating its parent directories
+    pub fn with_file(self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) -> Self {
+        {
+            let path = normalize(path.as_ref());
+            let mut nodes = self.nodes.lock().unwrap();
+            for ancestor in path.ancestors().skip(1) {
+                if ancestor != Path::new("/") {
+                    nodes
+                        .entry(ancestor.to_path_buf())
+                        .or_insert_with(Node::new_dir);
+                }
+            }
+            nodes.insert(
+                path,
+                Node::File {
+                    data: contents.into(),
+                    modified: SystemTime::now(),
+                    mode: 0o100644,
+                },
+            );
+        }
+        self
+    }
+
+    fn lookup(&self, path: &Path) -> io::Result<Node> {
+        let path = normalize(path);
+        if path == Path::new("/") {
+            return Ok(Node::new_dir());
+        }
+        self.nodes
+            .lock()
+            .unwrap()
+            .get(&path)
+            .cloned()
+            .ok_or_else(|| not_found(&path))
+    }
+
+    /// Check that the parent of a path is an existing directory
+    fn check_parent(&self, path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(parent) if !self.lookup(parent)?.metadata().is_dir() => Err(io::Error::new(
+                ErrorKind::NotADirectory,
+                format!("Not a directory: {}", parent.display()),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Filesystem for MemoryFs {
+    async fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        Ok(self.lookup(path)?.metadata())
+    }
+
+    async fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.lookup(path)? {
+            Node::File { data, .. } => {
+                telemetry::record_read(data.len());
+                Ok(data)
+            }
+            Node::Dir { .. } => Err(io::Error::new(
+                ErrorKind::IsADirectory,
+                format!("Is a directory: {}", path.display()),
+            )),
+        }
+    }
+
+    async fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let path = normalize(path);
+        self.check_parent(&path)?;
+        quota::charge_write(self, &path, contents.len()).await?;
+
+        let mut nodes = self.nodes.lock().unwrap();
+        let mode = match nodes.get(&path) {
+            Some(Node::Dir { .. }) => {
+                return Err(io::Error::new(
+                    ErrorKind::IsADirectory,
+                    format!("Is a directory: {}", path.display()),
+                ));
+            }
+            Some(Node::File { mode, .. }) => *mode,
+            None => 0o100644,
+        };
+        nodes.insert(
+            path,
+            Node::File {
+                data: contents.to_vec(),
+                modified: SystemTime::now(),
+                mode,
+            },
+        );
+        telemetry::record_written(contents.len());
+        Ok(())
+    }
+
+    async fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let normalized = normalize(path);
+        if !self.lookup(&normalized)?.metadata().is_dir() {
+            return Err(io::Error::new(
+                ErrorKind::NotADirectory,
+                format!("Not a directory: {}", path.display()),
+            ));
+        }
+
+        // Entries are named after the path as given, like those of std::fs::read_dir
+        let nodes = self.nodes.lock().unwrap();
+        Ok(nodes
+            .iter()
+            .filter(|(child, _)| child.parent() == Some(normalized.as_path()))
+            .filter_map(|(child, node)| {
+                Some(DirEntry {
+                    path: path.join(child.file_name()?),
+                    metadata: node.metadata(),
+                    depth: 1,
+                })
+            })
+            .collect())
+    }
+
+    async fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        if self.lookup(&path).is_ok() {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("File exists: {}", path.display()),
+            ));
+        }
+        self.check_parent(&path)?;
+        self.nodes.lock().unwrap().insert(path, Node::new_dir());
+        Ok(())
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut ancestors: Vec<&Path> = path.ancestors().collect();
+        ancestors.reverse();
+
+        let mut nodes = self.nodes.lock().unwrap();
+        for ancestor in ancestors.into_iter().skip(1) {
+            match nodes.get(ancestor) {
+                Some(Node::Dir { .. }) => {}
+                Some(Node::File { .. }) => {
+                    return Err(io::Error::new(
+                        ErrorKind::NotADirectory,
+                        format!("Not a directory: {}", ancestor.display()),
+                    ));
+                }
+                None => {
+                    nodes.insert(ancestor.to_path_buf(), Node::new_dir());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get(&path) {
+            Some(Node::File { .. }) => {
+                nodes.remove(&path);
+                Ok(())
+            }
+            Some(Node::Dir { .. }) => Err(io::Error::new(
+                ErrorKind::IsADirectory,
+                format!("Is a directory: {}", path.display()),
+            )),
+            None => Err(not_found(&path)),
+        }
+    }
+
+    async fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get(&path) {
+            Some(Node::Dir { .. }) => {
+                if nodes
+                    .keys()
+                    .any(|other| other.parent() == Some(path.as_path()))
+                {
+                    return Err(io::Error::new(
+                        ErrorKind::DirectoryNotEmpty,
+                        format!("Directory not empty: {}", path.display()),
+                    ));
+                }
+                nodes.remove(&path);
+                Ok(())
+            }
+            Some(Node::File { .. }) => Err(io::Error::new(
+                ErrorKind::NotADirectory,
+                format!("Not a directory: {}", path.display()),
+            )),
+            None => Err(not_found(&path)),
+        }
+    }
+
+    async fn set_mode(&self, path: &Path, new_mode: u32) -> io::Result<()> {
+        let path = normalize(path);
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get_mut(&path) {
+            Some(Node::File { mode, .. } | Node::Dir { mode, .. }) => {
+                // Keep the file type bits
+                *mode = (*mode & !0o7777) | (new_mode & 0o7777);
+                Ok(())
+            }
+            None => Err(not_found(&path)),
+        }
+    }
+
+    async fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = normalize(path);
+        self.lookup(&path)?;
+        Ok(path)
+    }
+
+    /// Access times aren't tracked, so only `modified` has an effect
+    async fn set_times(
+        &self,
+        path: &Path,
+        _accessed: Option<SystemTime>,
+        new_modified: Option<SystemTime>,
+    ) -> io::Result<()> {
+        let path = normalize(path);
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get_mut(&path) {
+            Some(Node::File { modified, .. } | Node::Dir { modified, .. }) => {
+                if let Some(new_modified) = new_modified {
+                    *modified = new_modified;
+                }
+                Ok(())
+            }
+            None => Err(not_found(&path)),
+        }
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (normalize(from), normalize(to));
+        self.lookup(&from)?;
+        if self.lookup(&to).is_ok() {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("File exists: {}", to.display()),
+            ));
+        }
+        if to.starts_with(&from) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Cannot move {} into itself", from.display()),
+            ));
+        }
+        self.check_parent(&to)?;
+
+        // Move the entry along with everything below it
+        let mut nodes = self.nodes.lock().unwrap();
+        let moved: Vec<PathBuf> = nodes
+            .keys()
+            .filter(|path| path.starts_with(&from))
+            .cloned()
+            .collect();
+        for path in moved {
+            let node = nodes.remove(&path).expect("key was just listed");
+            let relative = path.strip_prefix(&from).expect("key starts with from");
+            let target = if relative.as_os_str().is_empty() {
+                to.clone()
+            } else {
+                to.join(relative)
+            };
+            nodes.insert(target, node);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_fs() -> io::Result<()> {
+        let fs = MemoryFs::new().with_file("/project/src/main.rs", "fn main() {}\n");
+
+        assert!(fs.metadata(Path::new("/project/src")).await?.is_dir());
+        assert_eq!(
+            fs.read_to_string(Path::new("/project/src/../src/main.rs"))
+                .await?,
+            "fn main() {}\n"
+        );
+        let main = Path::new("/project/src/main.rs");
+        assert_eq!(fs.read_range(main, 3, 4).await?, b"main");
+        assert_eq!(fs.read_range(main, 11, 100).await?, b"}\n");
+
+        let err = fs
+            .write(Path::new("/missing/file.txt"), b"x")
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        fs.create_dir_all(Path::new("/project/docs/api")).await?;
+        fs.write(Path::new("/project/docs/api/index.md"), b"# API\n")
+            .await?;
+
+        let walked: Vec<String> = fs
+            .walk(
+                Path::new("/project"),
+                WalkOptions {
+                    max_depth: usize::MAX,
+                    follow_links: false,
+                },
+            )
+            .await?
+            .iter()
+            .map(|entry| format!("{}:{}", entry.depth, entry.path.display()))
+            .collect();
+        assert_eq!(
+            walked,
+            vec![
+                "1:/project/docs",
+                "2:/project/docs/api",
+                "3:/project/docs/api/index.md",
+                "1:/project/src",
+                "2:/project/src/main.rs",
+            ]
+        );
+
+        fs.rename(Path::new("/project/docs"), Path::new("/project/manual"))
+            .await?;
+        assert_eq!(
+            fs.read_to_string(Path::new("/project/manual/api/index.md"))
+                .await?,
+            "# API\n"
+        );
+        assert!(!fs.exists(Path::new("/project/docs")).await);
+        let err = fs
+            .rename(Path::new("/project/manual"), Path::new("/project/src"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        fs.remove_file(Path::new("/project/src/main.rs")).await?;
+        assert!(!fs.exists(Path::new("/project/src/main.rs")).await);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scope() {
+        let fs: Arc<dyn Filesystem> = Arc::new(MemoryFs::new().with_file("/a.txt", "in memory"));
+
+        let content = scope(fs, async {
+            current().read_to_string(Path::new("/a.txt")).await
+        })
+        .await
+        .unwrap();
+        assert_eq!(content, "in memory");
+
+        // Outside the scope the real filesystem is used again
+        assert!(!current().exists(Path::new("/a.txt")).await);
+    }
+
+    #[test]
+    fn test_remote_scheme() {
+        assert_eq!(remote_scheme(Path::new("s3://bucket/key")), Some("s3"));
+        assert_eq!(remote_scheme(Path::new("gs://bucket")), Some("gs"));
+        assert_eq!(remote_scheme(Path::new("/tmp/s3://x")), None);
+        assert_eq!(remote_scheme(Path::new("file.txt")), None);
+
+        #[cfg(not(feature = "object-store"))]
+        assert!(matches!(
+            for_path(Path::new("s3://bucket/key")),
+            Err(crate::Error::InvalidParam(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dispatcher_with_memory_fs() -> crate::Result<()> {
+        let fs = Arc::new(MemoryFs::new().with_file("/repo/README.md", "# Repo\n"));
+        let dispatcher = crate::create_default_dispatcher().with_filesystem(fs.clone());
+
+        let call = async |method: &str,
+                          params: serde_json::Value|
+               -> crate::Result<serde_json::Value> {
+            let request =
+                serde_json::json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1});
+            let response = dispatcher.dispatch(&request.to_string()).await?;
+            Ok(serde_json::from_str::<serde_json::Value>(&response)?["result"].clone())
+        };
+
+        call(
+            "file_write",
+            serde_json::json!({"path": "/repo/src/lib.rs", "content": "pub fn f() {}\n", "create_dirs": true}),
+        )
+        .await?;
+        let result = call("file_read", serde_json::json!({"path": "/repo/src/lib.rs"})).await?;
+        assert_eq!(result["content"], "pub fn f() {}\n");
+
+        let result = call(
+            "file_find",
+            serde_json::json!({"directory": "/repo", "pattern": "*.rs", "mode": "name"}),
+        )
+        .await?;
+        assert_eq!(result["total"], 1);
+        assert_eq!(result["entries"][0]["path"], "/repo/src/lib.rs");
+
+        // Nothing reached the real filesystem
+        assert!(!Path::new("/repo/src/lib.rs").exists());
+        assert!(fs.exists(Path::new("/repo/src")).await);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+//! Object store backend for `s3://` and `gs://` URIs
+//!
+//! Objects are addressed as `<scheme>://<bucket>/<key>`. Directories are
+//! implicit: a path is a directory when some object key starts with it, and
+//! creating one does nothing.
+
+use async_trait::async_trait;
+use object_store::aws::AmazonS3Builder;
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectMeta, ObjectStore, PutPayload};
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use super::{DirEntry, FileKind, Filesystem, Metadata};
+use crate::{quota, telemetry};
+
+/// Object stores by `<scheme>://<bucket>`, built on first use
+fn stores() -> &'static Mutex<HashMap<String, Arc<ObjectStoreFs>>> {
+    static STORES: OnceLock<Mutex<HashMap<String, Arc<ObjectStoreFs>>>> = OnceLock::new();
+    STORES.get_or_init(Default::default)
+}
+
+/// Split a URI into its `<scheme>://<bucket>` prefix, scheme and bucket
+fn split_url(url: &str) -> io::Result<(String, &str, &str)> {
+    let invalid = || {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid object store URI: {}", url),
+        )
+    };
+    let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+    let bucket = rest.split('/').next().unwrap_or_default();
+    if bucket.is_empty() {
+        return Err(invalid());
+    }
+    Ok((format!("{}://{}", scheme, bucket), scheme, bucket))
+}
+
+/// Use `store` for every path under `url` (`<scheme>://<bucket>`), instead of
+/// a client configured from the environment
+pub fn register_object_store(url: &str, store: Arc<dyn ObjectStore>) -> io::Result<()> {
+    let (prefix, _, _) = split_url(url)?;
+    let fs = Arc::new(ObjectStoreFs::new(store, &prefix));
+    stores().lock().unwrap().insert(prefix, fs);
+    Ok(())
+}
+
+/// The object store holding `path`, with credentials taken from the
+/// environment (`AWS_*` or `GOOGLE_*` variables) unless one was registered
+pub(super) fn for_url(path: &Path) -> io::Result<Arc<ObjectStoreFs>> {
+    let url = path.to_string_lossy();
+    let (prefix, scheme, bucket) = split_url(&url)?;
+
+    if let Some(fs) = stores().lock().unwrap().get(&prefix) {
+        return Ok(fs.clone());
+    }
+
+    let store: Arc<dyn ObjectStore> = match scheme {
+        "s3" => Arc::new(
+            AmazonS3Builder::from_env()
+                .with_bucket_name(bucket)
+                .build()
+                .map_err(to_io)?,
+        ),
+        "gs" | "gcs" => Arc::new(
+            GoogleCloudStorageBuilder::from_env()
+                .with_bucket_name(bucket)
+                .build()
+                .map_err(to_io)?,
+        ),
+        _ => {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                format!("Unsupported object store scheme: {}://", scheme),
+            ));
+        }
+    };
+
+    let fs = Arc::new(ObjectStoreFs::new(store, &prefix));
+    Ok(stores().lock().unwrap().entry(prefix).or_insert(fs).clone())
+}
+
+fn to_io(err: object_store::Error) -> io::Error {
+    let kind = match &err {
+        object_store::Error::NotFound { .. } => ErrorKind::NotFound,
+        object_store::Error::AlreadyExists { .. } => ErrorKind::AlreadyExists,
+        object_store::Error::NotImplemented => ErrorKind::Unsupported,
+        _ => ErrorKind::Other,
+    };
+    io::Error::new(kind, err)
+}
+
+/// A bucket of an object store, seen as a filesystem
+#[derive(Debug)]
+pub struct ObjectStoreFs {
+    store: Arc<dyn ObjectStore>,
+    /// `<scheme>://<bucket>`, which paths must start with
+    prefix: String,
+}
+
+impl ObjectStoreFs {
+    /// Serve paths under `prefix` (`<scheme>://<bucket>`) from `store`
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: &str) -> Self {
+        Self {
+            store,
+            prefix: prefix.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Object key of a path; the bucket itself has an empty key
+    fn key(&self, path: &Path) -> io::Result<ObjectPath> {
+        let path = path.to_string_lossy();
+        let key = path
+            .strip_prefix(&self.prefix)
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+            .ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} is not in {}", path, self.prefix),
+                )
+            })?;
+        ObjectPath::parse(key.trim_matches('/'))
+            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))
+    }
+
+    /// Path of an object key
+    fn url(&self, key: &ObjectPath) -> PathBuf {
+        if key.as_ref().is_empty() {
+            PathBuf::from(&self.prefix)
+        } else {
+            PathBuf::from(format!("{}/{}", self.prefix, key))
+        }
+    }
+}
+
+fn file_metadata(meta: &ObjectMeta) -> Metadata {
+    Metadata {
+        kind: FileKind::File,
+        len: meta.size,
+        modified: Some(meta.last_modified.into()),
+        mode: None,
+    }
+}
+
+fn dir_metadata() -> Metadata {
+    Metadata {
+        kind: FileKind::Dir,
+        len: 0,
+        modified: None,
+        mode: None,
+    }
+}
+
+#[async_trait]
+impl Filesystem for ObjectStoreFs {
+    async fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let key = self.key(path)?;
+        if key.as_ref().is_empty() {
+            return Ok(dir_metadata());
+        }
+
+        match self.store.head(&key).await {
+            Ok(meta) => Ok(file_metadata(&meta)),
+            Err(object_store::Error::NotFound { .. }) => {
+                // A prefix of other keys is a directory
+                let listing = self
+                    .store
+                    .list_with_delimiter(Some(&key))
+                    .await
+                    .map_err(to_io)?;
+                if listing.objects.is_empty() && listing.common_prefixes.is_empty() {
+                    Err(io::Error::new(
+                        ErrorKind::NotFound,
+                        format!("No such object: {}", path.display()),
+                    ))
+                } else {
+                    Ok(dir_metadata())
+                }
+            }
+            Err(e) => Err(to_io(e)),
+        }
+    }
+
+    async fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let key = self.key(path)?;
+        let result = self.store.get(&key).await.map_err(to_io)?;
+        let data = result.bytes().await.map_err(to_io)?.to_vec();
+        telemetry::record_read(data.len());
+        Ok(data)
+    }
+
+    async fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let key = self.key(path)?;
+        quota::charge_write(self, path, contents.len()).await?;
+        self.store
+            .put(&key, PutPayload::from(contents.to_vec()))
+            .await
+            .map_err(to_io)?;
+        telemetry::record_written(contents.len());
+        Ok(())
+    }
+
+    async fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let key = self.key(path)?;
+        let prefix = (!key.as_ref().is_empty()).then_some(&key);
+        let listing = self
+            .store
+            .list_with_delimiter(prefix)
+            .await
+            .map_err(to_io)?;
+
+        let dirs = listing.common_prefixes.iter().map(|dir| DirEntry {
+            path: self.url(dir),
+            metadata: dir_metadata(),
+            depth: 1,
+        });
+        let files = listing.objects.iter().map(|meta| DirEntry {
+            path: self.url(&meta.location),
+            metadata: file_metadata(meta),
+            depth: 1,
+        });
+        Ok(dirs.chain(files).collect())
+    }
+
+    async fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.key(path)?;
+        Ok(())
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.key(path)?;
+        Ok(())
+    }
+
+    async fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let key = self.key(path)?;
+        self.store.delete(&key).await.map_err(to_io)
+    }
+
+    async fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        // Directories are prefixes of keys and vanish with their last object
+        self.key(path)?;
+        Ok(())
+    }
+
+    async fn set_mode(&self, _path: &Path, _mode: u32) -> io::Result<()> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "object stores have no file permissions",
+        ))
+    }
+
+    async fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.metadata(path).await?;
+        Ok(self.url(&self.key(path)?))
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        let (from, to) = (self.key(from)?, self.key(to)?);
+        self.store.copy(&from, &to).await.map_err(to_io)?;
+        Ok(self.store.head(&to).await.map_err(to_io)?.size)
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "file-find",
+    feature = "file-read",
+    feature = "file-write"
+))]
+mod tests {
+    use super::*;
+    use crate::tools::Tool;
+    use crate::tools::{file_find, file_read, file_write};
+    use object_store::memory::InMemory;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_object_store_fs() -> io::Result<()> {
+        let fs = ObjectStoreFs::new(Arc::new(InMemory::new()), "s3://bucket");
+
+        fs.write(Path::new("s3://bucket/logs/2024/a.log"), b"a\n")
+            .await?;
+        fs.write(Path::new("s3://bucket/logs/b.log"), b"bb\n")
+            .await?;
+
+        assert!(fs.metadata(Path::new("s3://bucket/logs")).await?.is_dir());
+        assert_eq!(
+            fs.metadata(Path::new("s3://bucket/logs/b.log"))
+                .await?
+                .len(),
+            3
+        );
+        assert_eq!(
+            fs.metadata(Path::new("s3://bucket/missing"))
+                .await
+                .unwrap_err()
+                .kind(),
+            ErrorKind::NotFound
+        );
+        assert!(fs.key(Path::new("s3://other/key")).is_err());
+
+        let mut listed: Vec<String> = fs
+            .read_dir(Path::new("s3://bucket/logs"))
+            .await?
+            .iter()
+            .map(|entry| entry.path.to_string_lossy().to_string())
+            .collect();
+        listed.sort();
+        assert_eq!(
+            listed,
+            vec!["s3://bucket/logs/2024", "s3://bucket/logs/b.log"]
+        );
+
+        fs.remove_file(Path::new("s3://bucket/logs/b.log")).await?;
+        assert!(!fs.exists(Path::new("s3://bucket/logs/b.log")).await);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tools_on_object_store() -> crate::Result<()> {
+        register_object_store("s3://tools-test", Arc::new(InMemory::new()))?;
+
+        let params = serde_json::from_value(json!({
+            "path": "s3://tools-test/reports/summary.txt",
+            "content": "all good\n",
+            "create_dirs": true,
+        }))?;
+        file_write::FileWrite.execute(params).await?;
+
+        let params =
+            serde_json::from_value(json!({"path": "s3://tools-test/reports/summary.txt"}))?;
+        let read = file_read::FileRead.execute(params).await?;
+        assert_eq!(read.content, "all good\n");
+
+        let params = serde_json::from_value(json!({
+            "directory": "s3://tools-test",
+            "pattern": "*.txt",
+            "mode": "name",
+        }))?;
+        let found = file_find::FileFind.execute(params).await?;
+        assert_eq!(found.total, 1);
+        assert_eq!(found.entries[0].path, "s3://tools-test/reports/summary.txt");
+
+        Ok(())
+    }
+}
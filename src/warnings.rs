@@ -0,0 +1,187 @@
+//! Warnings about what a call skipped
+//!
+//! Tools step over what they can't read rather than fail the whole call: a
+//! directory walk goes on past directories it may not list, and `file_grep`
+//! past files it can't open. They note each case as a warning, and the
+//! dispatcher adds the warnings of a tool call to its result as a `warnings`
+//! array of strings, after any the result already has, so callers can tell an
+//! empty answer from an incomplete one:
+//!
+//! ```json
+//! {"jsonrpc": "2.0", "result": {"entries": [...], "warnings": ["Skipped /srv/private: Permission denied (os error 13)"]}, "id": 1}
+//! ```
+//!
+//! Results without warnings have no `warnings` member. Calling a tool directly
+//! inside [`capture`] collects them the same way.
+//!
+//! The searches also count what they skipped, by reason, in a [`Skipped`] in
+//! their output, so "no matches" can be told apart from "couldn't read half
+//! the tree" without reading the warnings.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// Warnings kept per call; further ones are only counted
+const MAX_WARNINGS: usize = 100;
+
+/// The warnings noted by one call
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Warnings(Arc<Mutex<Noted>>);
+
+#[derive(Debug, Default)]
+struct Noted {
+    messages: Vec<String>,
+    dropped: usize,
+}
+
+impl Warnings {
+    /// The warnings of the current call. Take them on the task making the call
+    /// to note warnings from a blocking thread; outside a call, what is noted
+    /// is dropped.
+    pub(crate) fn current() -> Self {
+        CURRENT.try_with(Clone::clone).unwrap_or_default()
+    }
+
+    /// Note a warning
+    pub(crate) fn warn(&self, message: impl Into<String>) {
+        let mut noted = self.0.lock().unwrap();
+        if noted.messages.len() < MAX_WARNINGS {
+            noted.messages.push(message.into());
+        } else {
+            noted.dropped += 1;
+        }
+    }
+
+    /// The warnings noted so far, leaving none
+    fn take(&self) -> Vec<String> {
+        let mut noted = self.0.lock().unwrap();
+        let mut messages = std::mem::take(&mut noted.messages);
+        if noted.dropped > 0 {
+            messages.push(format!("{} more warnings not shown", noted.dropped));
+            noted.dropped = 0;
+        }
+        messages
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: Warnings;
+}
+
+/// Entries a search skipped, by reason
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Skipped {
+    /// Files and directories that couldn't be read for lack of permission
+    pub permission_denied: usize,
+
+    /// Files and directories that couldn't be read for other I/O errors
+    pub io_errors: usize,
+
+    /// Files passed over because they are binary
+    pub binary: usize,
+
+    /// Files passed over because their text isn't UTF-8
+    pub non_utf8: usize,
+}
+
+impl Skipped {
+    /// Number of entries skipped for any reason
+    pub fn total(&self) -> usize {
+        self.permission_denied + self.io_errors + self.binary + self.non_utf8
+    }
+
+    /// Count an entry that couldn't be read
+    pub(crate) fn record(&mut self, error: &io::Error) {
+        if error.kind() == io::ErrorKind::PermissionDenied {
+            self.permission_denied += 1;
+        } else {
+            self.io_errors += 1;
+        }
+    }
+
+    /// Count an entry a directory walk couldn't read
+    pub(crate) fn record_walk(&mut self, error: &walkdir::Error) {
+        match error.io_error() {
+            Some(e) => self.record(e),
+            None => self.io_errors += 1,
+        }
+    }
+}
+
+/// Note a warning for the current call
+pub(crate) fn warn(message: impl Into<String>) {
+    let _ = CURRENT.try_with(|warnings| warnings.warn(message));
+}
+
+/// Run `future`, returning its output with the warnings it noted
+pub async fn capture<F: Future>(future: F) -> (F::Output, Vec<String>) {
+    let warnings = Warnings::default();
+    let output = CURRENT.scope(warnings.clone(), future).await;
+    (output, warnings.take())
+}
+
+/// Add warnings to a tool result, after those it already has; results that
+/// aren't objects are left as they are
+pub(crate) fn attach(mut result: Value, warnings: Vec<String>) -> Value {
+    if warnings.is_empty() {
+        return result;
+    }
+    if let Value::Object(map) = &mut result
+        && let Value::Array(list) = map
+            .entry("warnings")
+            .or_insert_with(|| Value::Array(Vec::new()))
+    {
+        list.extend(warnings.into_iter().map(Value::String));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_capture() {
+        let ((), warnings) = capture(async {
+            warn("first");
+            // Blocking threads note warnings through a handle
+            let handle = Warnings::current();
+            std::thread::spawn(move || {
+                for i in 0..MAX_WARNINGS + 2 {
+                    handle.warn(format!("warning {}", i));
+                }
+            })
+            .join()
+            .unwrap();
+        })
+        .await;
+        assert_eq!(warnings.len(), MAX_WARNINGS + 1);
+        assert_eq!(warnings[0], "first");
+        assert_eq!(warnings[MAX_WARNINGS], "3 more warnings not shown");
+
+        // Outside a call, warnings go nowhere
+        warn("dropped");
+        Warnings::current().warn("dropped");
+    }
+
+    #[test]
+    fn test_attach() {
+        let warnings = || vec!["skipped".to_string()];
+        assert_eq!(attach(json!({"a": 1}), vec![]), json!({"a": 1}));
+        assert_eq!(
+            attach(json!({"a": 1}), warnings()),
+            json!({"a": 1, "warnings": ["skipped"]})
+        );
+        assert_eq!(
+            attach(json!({"warnings": ["own"]}), warnings()),
+            json!({"warnings": ["own", "skipped"]})
+        );
+        assert_eq!(attach(json!([1]), warnings()), json!([1]));
+    }
+}
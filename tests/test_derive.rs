@@ -0,0 +1,89 @@
+//! Tools defined with the `#[tool]` attribute
+#![cfg(feature = "derive")]
+
+use gamecode_tools::tools::{Annotations, Tool};
+use gamecode_tools::{Error, Result, ToolSchemaRegistry, tool};
+use serde_json::Value;
+
+/// Count the words in a text
+#[tool(read_only)]
+async fn word_count(
+    /// Text to count the words of
+    text: String,
+    /// Count only words at least this long
+    #[serde(default)]
+    min_length: usize,
+) -> Result<usize> {
+    Ok(text
+        .split_whitespace()
+        .filter(|word| word.len() >= min_length)
+        .count())
+}
+
+#[tool(name = "shout", description = "Upper-case a text")]
+async fn make_loud(text: String) -> Result<String> {
+    if text.is_empty() {
+        return Err(Error::InvalidParam("Nothing to shout".to_string()));
+    }
+    Ok(text.to_uppercase())
+}
+
+#[tokio::test]
+async fn test_tool_attribute() -> Result<()> {
+    assert_eq!(WordCount.name(), "word_count");
+    assert_eq!(WordCount.description(), "Count the words in a text");
+    assert_eq!(
+        WordCount.annotations(),
+        Annotations {
+            read_only: true,
+            destructive: false,
+            idempotent: false,
+            open_world: false,
+        }
+    );
+    assert_eq!(MakeLoud.name(), "shout");
+    assert_eq!(MakeLoud.annotations(), Annotations::default());
+
+    let params = WordCountParams {
+        text: "a bb ccc".to_string(),
+        min_length: 2,
+    };
+    assert_eq!(WordCount.execute(params).await?, 2);
+
+    let (mut dispatcher, mut registry) = gamecode_tools::create_dispatcher_with_schema_registry(
+        gamecode_tools::FormatTransformer::standard(),
+    );
+    register_word_count(&mut dispatcher, &mut registry);
+    register_make_loud(&mut dispatcher, &mut registry);
+
+    let schema = &registry.get("word_count").unwrap().parameters_schema;
+    assert_eq!(
+        schema["properties"]["text"]["description"],
+        "Text to count the words of"
+    );
+    assert_eq!(schema["required"], serde_json::json!(["text"]));
+
+    let call = async |request: &str| -> Result<Value> {
+        let response = dispatcher.dispatch(request).await?;
+        Ok(serde_json::from_str(&response)?)
+    };
+    let response = call(
+        r#"{"jsonrpc": "2.0", "method": "word_count", "params": {"text": "one two"}, "id": 1}"#,
+    )
+    .await?;
+    assert_eq!(response["result"], 2);
+    let response =
+        call(r#"{"jsonrpc": "2.0", "method": "shout", "params": {"text": ""}, "id": 2}"#).await?;
+    assert_eq!(response["error"]["data"]["code"], "invalid_param");
+
+    let response =
+        call(r#"{"jsonrpc": "2.0", "method": "tools/list", "params": {}, "id": 3}"#).await?;
+    let tools = response["result"]["tools"].as_array().unwrap();
+    assert!(tools.iter().any(|tool| tool["name"] == "shout"));
+
+    let mut fresh = ToolSchemaRegistry::new();
+    fresh.register_tool(&MakeLoud);
+    assert_eq!(fresh.get("shout").unwrap().description, "Upper-case a text");
+
+    Ok(())
+}